@@ -18,8 +18,12 @@ pub fn probe_lua() {
         .lua52compat(cfg!(feature = "luajit52"))
         .build();
 
+    #[cfg(feature = "luau")]
+    set_wasi_sysroot_flags();
+
     #[cfg(feature = "luau")]
     let artifacts = luau0_src::Build::new()
+        .use_longjmp(is_wasm_without_exceptions())
         .enable_codegen(cfg!(feature = "luau-codegen"))
         .set_max_cstack_size(1000000)
         .set_vector_size(if cfg!(feature = "luau-vector4") { 4 } else { 3 })
@@ -27,3 +31,41 @@ pub fn probe_lua() {
 
     artifacts.print_cargo_metadata();
 }
+
+// Non-emscripten wasm targets (`wasm32-unknown-unknown`, `wasm32-wasi*`) have no support
+// for C++ exceptions, so Luau must be built to use `setjmp`/`longjmp` for error handling.
+fn is_wasm_without_exceptions() -> bool {
+    let target_family = std::env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let is_wasm = target_family.split(',').any(|f| f == "wasm");
+    if is_wasm && cfg!(feature = "luau-codegen") {
+        panic!("Luau codegen (jit) is not supported on wasm targets");
+    }
+    is_wasm && target_os != "emscripten"
+}
+
+// `wasm32-unknown-unknown` has no C library of its own, so Luau is compiled against the headers
+// of a WASI sysroot (eg. from wasi-sdk) pointed to by `WASI_SYSROOT`.
+// Flags explicitly set by the user for the target take precedence.
+fn set_wasi_sysroot_flags() {
+    println!("cargo:rerun-if-env-changed=WASI_SYSROOT");
+    if std::env::var("TARGET").as_deref() != Ok("wasm32-unknown-unknown") {
+        return;
+    }
+    let Ok(sysroot) = std::env::var("WASI_SYSROOT") else {
+        return;
+    };
+
+    let include = format!("{sysroot}/include/wasm32-wasip1");
+    let cflags = format!("--sysroot={sysroot} -isystem {include}");
+    let cxxflags = format!("{cflags} -isystem {include}/c++/v1");
+    for (var, flags) in [
+        ("CFLAGS_wasm32_unknown_unknown", cflags),
+        ("CXXFLAGS_wasm32_unknown_unknown", cxxflags),
+    ] {
+        if std::env::var_os(var).is_none() {
+            std::env::set_var(var, flags);
+        }
+    }
+    println!("cargo:rustc-link-search=native={sysroot}/lib/wasm32-wasip1");
+}
@@ -37,6 +37,8 @@ extern "C-unwind" {
     pub fn luaopen_jit(L: *mut lua_State) -> c_int;
     #[cfg(feature = "luajit")]
     pub fn luaopen_ffi(L: *mut lua_State) -> c_int;
+    #[cfg(feature = "luajit")]
+    pub fn luaopen_string_buffer(L: *mut lua_State) -> c_int;
 
     // open all builtin libraries
     pub fn luaL_openlibs(L: *mut lua_State);
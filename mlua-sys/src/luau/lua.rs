@@ -16,6 +16,9 @@ const LUA_UTAG_LIMIT: c_int = 128;
 // Number of valid Lua lightuserdata tags
 const LUA_LUTAG_LIMIT: c_int = 128;
 
+// Number of valid memory categories
+pub const LUA_MEMORY_CATEGORIES: c_int = 256;
+
 //
 // Pseudo-indices
 //
@@ -13,3 +13,6 @@ pub mod lua;
 pub mod luacode;
 pub mod luacodegen;
 pub mod lualib;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod wasm;
@@ -0,0 +1,78 @@
+//! C allocation functions for `wasm32-unknown-unknown`.
+//!
+//! The target has no libc, so the allocations made by Luau outside of the Lua allocator (e.g. by
+//! the compiler) are served by the Rust global allocator.
+
+use std::alloc::{self, Layout};
+use std::os::raw::c_void;
+use std::ptr;
+
+// Each allocation is prefixed with its size. The header size keeps the returned pointers aligned
+// as required by `malloc` (to `max_align_t`).
+const HEADER_SIZE: usize = 16;
+
+#[inline]
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER_SIZE)?, HEADER_SIZE).ok()
+}
+
+#[inline]
+unsafe fn allocate(size: usize, alloc: unsafe fn(Layout) -> *mut u8) -> *mut c_void {
+    let base = match layout(size) {
+        Some(layout) => alloc(layout),
+        None => return ptr::null_mut(),
+    };
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    *(base as *mut usize) = size;
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+// Returns the start and the layout of the allocation of `ptr`
+#[inline]
+unsafe fn allocation(ptr: *mut c_void) -> (*mut u8, Layout) {
+    let base = (ptr as *mut u8).sub(HEADER_SIZE);
+    let size = *(base as *const usize);
+    let layout = Layout::from_size_align_unchecked(size + HEADER_SIZE, HEADER_SIZE);
+    (base, layout)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    allocate(size, alloc::alloc)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(size) => allocate(size, alloc::alloc_zeroed),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    let new_layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    let (base, layout) = allocation(ptr);
+    let base = alloc::realloc(base, layout, new_layout.size());
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    *(base as *mut usize) = size;
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if !ptr.is_null() {
+        let (base, layout) = allocation(ptr);
+        alloc::dealloc(base, layout);
+    }
+}
@@ -0,0 +1,29 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{meta, parse_macro_input, ItemFn, LitStr};
+
+pub fn lua_export(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let ident = &func.sig.ident;
+
+    let mut name = ident.to_string();
+    let parser = meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else {
+            Err(meta.error("unsupported lua_export attribute, expected `name`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let gen = quote! {
+        #func
+
+        ::mlua::__inventory::submit! {
+            ::mlua::LuaExport::new(#name, |lua| lua.create_named_function(#name, #ident))
+        }
+    };
+
+    gen.into()
+}
@@ -0,0 +1,141 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, ExprPath, Fields, Generics,
+    LitStr, Token,
+};
+
+// How a field is filled when there are no values left
+enum Missing {
+    Nil,
+    Default,
+    DefaultWith(ExprPath),
+}
+
+struct FieldAttrs {
+    missing: Missing,
+    rest: bool,
+}
+
+fn parse_attrs(attrs: &[Attribute], container: bool) -> syn::Result<FieldAttrs> {
+    let mut result = FieldAttrs {
+        missing: Missing::Nil,
+        rest: false,
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("lua")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") && !meta.input.peek(Token![=]) {
+                result.missing = Missing::Default;
+                Ok(())
+            } else if meta.path.is_ident("default") && !container {
+                let path = meta.value()?.parse::<LitStr>()?;
+                result.missing = Missing::DefaultWith(path.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("rest") && !container {
+                result.rest = true;
+                Ok(())
+            } else if container {
+                Err(meta.error("unsupported lua attribute, expected `default`"))
+            } else {
+                Err(meta.error("unsupported lua attribute, expected `default` or `rest`"))
+            }
+        })?;
+    }
+    Ok(result)
+}
+
+pub fn from_lua_multi(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+
+    let fields = if let Data::Struct(data_struct) = input.data {
+        data_struct.fields
+    } else {
+        let msg = "FromLuaMulti can only be derived for structs";
+        return syn::Error::new(ident.span(), msg).to_compile_error().into();
+    };
+
+    // `#[lua(default)]` on the struct applies to every field
+    let defaults = match parse_attrs(&input.attrs, true) {
+        Ok(attrs) => matches!(attrs.missing, Missing::Default),
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    // Missing values are treated as `nil` (unless the field has a default), extra values are
+    // ignored (unless the last field collects them)
+    let mut values = Vec::new();
+    let count = fields.len();
+    for (i, field) in fields.iter().enumerate() {
+        let attrs = match parse_attrs(&field.attrs, false) {
+            Ok(attrs) => attrs,
+            Err(err) => return err.to_compile_error().into(),
+        };
+        if attrs.rest && i + 1 != count {
+            let msg = "`#[lua(rest)]` can only be used on the last field";
+            return syn::Error::new_spanned(field, msg)
+                .to_compile_error()
+                .into();
+        }
+        values.push(field_value(attrs, defaults));
+    }
+
+    let construct = match &fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote! { Self { #(#names: #values,)* } }
+        }
+        Fields::Unnamed(_) => quote! { Self(#(#values,)*) },
+        Fields::Unit => quote! { Self },
+    };
+
+    let generics = lua_generics(&input.generics, quote! { ::mlua::FromLua<'lua> });
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let gen = quote! {
+        impl #impl_generics ::mlua::FromLuaMulti<'lua> for #ident #ty_generics #where_clause {
+            #[allow(unused_mut, unused_variables)]
+            fn from_lua_multi(mut values: ::mlua::MultiValue<'lua>, lua: &'lua ::mlua::Lua) -> ::mlua::Result<Self> {
+                Ok(#construct)
+            }
+        }
+    };
+
+    gen.into()
+}
+
+fn field_value(attrs: FieldAttrs, defaults: bool) -> TokenStream2 {
+    if attrs.rest {
+        return quote! { ::mlua::FromLuaMulti::from_lua_multi(values, lua)? };
+    }
+    let missing = match attrs.missing {
+        Missing::Nil if !defaults => {
+            return quote! {
+                ::mlua::FromLua::from_lua(values.pop_front().unwrap_or(::mlua::Value::Nil), lua)?
+            };
+        }
+        Missing::Nil | Missing::Default => quote! { ::std::default::Default::default() },
+        Missing::DefaultWith(path) => quote! { #path() },
+    };
+    quote! {
+        match values.pop_front() {
+            Some(value) => ::mlua::FromLua::from_lua(value, lua)?,
+            None => #missing,
+        }
+    }
+}
+
+// Adds the `'lua` lifetime (unless the type already has it) and bounds type parameters by `bound`
+pub(crate) fn lua_generics(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    if !generics.lifetimes().any(|def| def.lifetime.ident == "lua") {
+        generics.params.insert(0, parse_quote!('lua));
+    }
+    let params = generics.type_params().map(|param| param.ident.clone());
+    let predicates = params
+        .map(|param| parse_quote!(#param: #bound))
+        .collect::<Vec<syn::WherePredicate>>();
+    generics.make_where_clause().predicates.extend(predicates);
+    generics
+}
@@ -0,0 +1,50 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+use crate::from_lua_multi::lua_generics;
+
+pub fn into_lua_multi(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+
+    let fields = if let Data::Struct(data_struct) = input.data {
+        data_struct.fields
+    } else {
+        let msg = "IntoLuaMulti can only be derived for structs";
+        return syn::Error::new(ident.span(), msg).to_compile_error().into();
+    };
+
+    // Fields are returned in declaration order
+    let values = match &fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let name = &field.ident;
+                quote! { ::mlua::IntoLua::into_lua(self.#name, lua)? }
+            })
+            .collect::<Vec<_>>(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote! { ::mlua::IntoLua::into_lua(self.#index, lua)? }
+            })
+            .collect::<Vec<_>>(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let generics = lua_generics(&input.generics, quote! { ::mlua::IntoLua<'lua> });
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let gen = quote! {
+        impl #impl_generics ::mlua::IntoLuaMulti<'lua> for #ident #ty_generics #where_clause {
+            fn into_lua_multi(self, lua: &'lua ::mlua::Lua) -> ::mlua::Result<::mlua::MultiValue<'lua>> {
+                Ok(::mlua::MultiValue::from_vec(vec![#(#values),*]))
+            }
+        }
+    };
+
+    gen.into()
+}
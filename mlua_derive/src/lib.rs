@@ -14,6 +14,7 @@ use {
 struct ModuleAttributes {
     name: Option<Ident>,
     skip_memory_check: bool,
+    skip_version_check: bool,
 }
 
 impl ModuleAttributes {
@@ -32,6 +33,11 @@ impl ModuleAttributes {
                 return Err(meta.error("`skip_memory_check` attribute have no values"));
             }
             self.skip_memory_check = true;
+        } else if meta.path.is_ident("skip_version_check") {
+            if meta.value().is_ok() {
+                return Err(meta.error("`skip_version_check` attribute have no values"));
+            }
+            self.skip_version_check = true;
         } else {
             return Err(meta.error("unsupported module attribute"));
         }
@@ -56,6 +62,15 @@ pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
     } else {
         quote! {}
     };
+    let check_version = if args.skip_version_check {
+        quote! {}
+    } else {
+        quote! {
+            if let Some(ret) = mlua::check_runtime_version(state) {
+                return ret;
+            }
+        }
+    };
 
     let wrapped = quote! {
         mlua::require_module_feature!();
@@ -64,6 +79,7 @@ pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
 
         #[no_mangle]
         unsafe extern "C-unwind" fn #ext_entrypoint_name(state: *mut mlua::lua_State) -> ::std::os::raw::c_int {
+            #check_version
             let lua = mlua::Lua::init_from_ptr(state);
             #skip_memory_check
             lua.entrypoint1(state, #func_name)
@@ -171,15 +187,55 @@ pub fn to_lua_table(input: TokenStream) -> TokenStream {
     to_lua_table::to_lua_table(input)
 }
 
+#[cfg(feature = "macros")]
+#[proc_macro_derive(IntoLuaMulti)]
+pub fn into_lua_multi(input: TokenStream) -> TokenStream {
+    into_lua_multi::into_lua_multi(input)
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_derive(FromLuaMulti, attributes(lua))]
+pub fn from_lua_multi(input: TokenStream) -> TokenStream {
+    from_lua_multi::from_lua_multi(input)
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_attribute]
+pub fn lua_export(attr: TokenStream, item: TokenStream) -> TokenStream {
+    export::lua_export(attr, item)
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_derive(LuaModule, attributes(lua))]
+pub fn lua_module_derive(input: TokenStream) -> TokenStream {
+    lua_module::lua_module(input)
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_derive(LuaEnum, attributes(lua))]
+pub fn lua_enum_derive(input: TokenStream) -> TokenStream {
+    lua_enum::lua_enum(input)
+}
+
 #[cfg(feature = "macros")]
 mod chunk;
 #[cfg(feature = "macros")]
+mod export;
+#[cfg(feature = "macros")]
 mod from_lua;
 #[cfg(feature = "macros")]
-mod to_lua;
+mod from_lua_multi;
 #[cfg(feature = "macros")]
 mod from_lua_table;
 #[cfg(feature = "macros")]
+mod into_lua_multi;
+#[cfg(feature = "macros")]
+mod lua_enum;
+#[cfg(feature = "macros")]
+mod lua_module;
+#[cfg(feature = "macros")]
+mod to_lua;
+#[cfg(feature = "macros")]
 mod to_lua_table;
 #[cfg(feature = "macros")]
 mod token;
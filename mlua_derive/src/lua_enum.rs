@@ -0,0 +1,86 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+pub fn lua_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+    let ident_str = ident.to_string();
+
+    let variants = match input.data {
+        Data::Enum(data_enum) => data_enum.variants,
+        _ => {
+            let msg = "LuaEnum can only be derived for enums";
+            return syn::Error::new_spanned(&ident, msg)
+                .to_compile_error()
+                .into();
+        }
+    };
+    if !input.generics.params.is_empty() {
+        let msg = "LuaEnum cannot be derived for generic enums";
+        return syn::Error::new_spanned(input.generics, msg)
+            .to_compile_error()
+            .into();
+    }
+
+    let mut entries = Vec::new();
+    let mut arms = Vec::new();
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            let msg = "LuaEnum can only be derived for enums with unit variants";
+            return syn::Error::new_spanned(variant, msg)
+                .to_compile_error()
+                .into();
+        }
+        let variant_ident = variant.ident;
+        let mut name = variant_ident.to_string();
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("lua"))
+        {
+            let res = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported lua attribute, expected `rename`"))
+                }
+            });
+            if let Err(err) = res {
+                return err.to_compile_error().into();
+            }
+        }
+        entries.push(quote! { (#name, Self::#variant_ident) });
+        arms.push(quote! { Self::#variant_ident => Self::#variant_ident as ::mlua::Integer });
+    }
+
+    let gen = quote! {
+        impl ::mlua::LuaEnum for #ident {
+            const NAME: &'static str = #ident_str;
+            const VARIANTS: &'static [(&'static str, Self)] = &[#(#entries,)*];
+
+            fn to_integer(&self) -> ::mlua::Integer {
+                match *self {
+                    #(#arms,)*
+                }
+            }
+        }
+
+        impl<'lua> ::mlua::FromLua<'lua> for #ident {
+            #[inline]
+            fn from_lua(value: ::mlua::Value<'lua>, _: &'lua ::mlua::Lua) -> ::mlua::Result<Self> {
+                <Self as ::mlua::LuaEnum>::from_lua_value(value)
+            }
+        }
+
+        impl<'lua> ::mlua::IntoLua<'lua> for #ident {
+            #[inline]
+            fn into_lua(self, _: &'lua ::mlua::Lua) -> ::mlua::Result<::mlua::Value<'lua>> {
+                Ok(::mlua::Value::Integer(::mlua::LuaEnum::to_integer(&self)))
+            }
+        }
+    };
+
+    gen.into()
+}
@@ -0,0 +1,71 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+pub fn lua_module(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match input.data {
+        Data::Struct(data_struct) => match data_struct.fields {
+            Fields::Named(fields) => fields,
+            _ => panic!("LuaModule can only be derived for structs with named fields"),
+        },
+        _ => panic!("LuaModule can only be derived for structs"),
+    };
+
+    let mut members = Vec::new();
+    for field in fields.named {
+        let field_ident = field.ident.unwrap();
+        let mut name = field_ident.to_string();
+        let mut nested = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("lua"))
+        {
+            let res = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("module") {
+                    nested = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported lua attribute, expected `module` or `rename`"))
+                }
+            });
+            if let Err(err) = res {
+                return err.to_compile_error().into();
+            }
+        }
+
+        // Nested modules become subtables, other fields are functions
+        let value = match nested {
+            true => quote! { ::mlua::LuaModule::into_table(self.#field_ident, lua, &full_name)? },
+            false => quote! { lua.create_named_function(&full_name, self.#field_ident)? },
+        };
+        members.push(quote! {
+            let full_name = format!("{}.{}", path, #name);
+            module.raw_set(#name, #value)?;
+        });
+    }
+
+    let gen = quote! {
+        impl #impl_generics ::mlua::LuaModule for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn register<'lua>(
+                self,
+                lua: &'lua ::mlua::Lua,
+                module: &::mlua::Table<'lua>,
+                path: &str,
+            ) -> ::mlua::Result<()> {
+                #(#members)*
+                Ok(())
+            }
+        }
+    };
+
+    gen.into()
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+use std::os::raw::c_int;
+use std::result::Result as StdResult;
+use std::string::String as StdString;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::value::{FromLua, FromLuaMulti, MultiValue, Nil, ValueSnapshot};
+
+/// Positional arguments of a Rust callback with helpers producing Lua-style argument errors.
+///
+/// Every argument is converted on demand and conversion failures are reported as
+/// [`Error::BadArgument`] with the argument position and the name of the callback (if known).
+/// Type mismatches are described the way the Lua standard library does, eg.
+/// ``bad argument #2 to `move`: number expected, got string``.
+///
+/// The callback name is known for userdata methods and functions created using
+/// [`Lua::create_named_function`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Args, Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let move_to = lua.create_named_function("move", |_, mut args: Args| {
+///     let direction: String = args.get_one_of(&["up", "down", "left", "right"])?;
+///     let steps: u32 = args.get_in_range(1, 10)?;
+///     Ok(format!("{direction} x{steps}"))
+/// })?;
+/// lua.globals().set("move", move_to)?;
+///
+/// assert_eq!(lua.load("move('up', 3)").eval::<String>()?, "up x3");
+/// let err = lua.load("move('up', 'far')").exec().unwrap_err();
+/// assert!(err.to_string().contains("bad argument #2 to `move`: number expected, got string"));
+/// let err = lua.load("move('back', 3)").exec().unwrap_err();
+/// assert!(err.to_string().contains("invalid option 'back'"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Args<'lua> {
+    values: MultiValue<'lua>,
+    pos: usize,
+    name: Option<StdString>,
+    lua: &'lua Lua,
+}
+
+impl<'lua> Args<'lua> {
+    /// Returns the name of the callback, if known.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the position of the next argument (starting from 1).
+    ///
+    /// For userdata methods the first argument is the userdata itself.
+    pub const fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the number of remaining arguments.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if there are no remaining arguments.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Converts the next argument to `T`.
+    ///
+    /// Missing arguments are treated as `nil`.
+    pub fn get<T: FromLua<'lua>>(&mut self) -> Result<T> {
+        self.get_checked(|_| Ok(()))
+    }
+
+    /// Converts the next argument to `T` and validates it using `check`.
+    ///
+    /// The error message returned by `check` is reported as the cause of the bad argument.
+    pub fn get_checked<T: FromLua<'lua>>(
+        &mut self,
+        check: impl FnOnce(&T) -> StdResult<(), StdString>,
+    ) -> Result<T> {
+        let pos = self.pos;
+        let value = self.values.pop_front().unwrap_or(Nil);
+        self.pos += 1;
+
+        let ty = value.type_name();
+        let snapshot = ValueSnapshot::new(&value);
+        let value = T::from_lua(value, self.lua).map_err(|err| {
+            let err = match err {
+                Error::FromLuaConversionError { to, .. } if !is_compatible(ty, to) => {
+                    let expected = lua_type_name(to).unwrap_or(to);
+                    Error::external(format!("{expected} expected, got {ty}"))
+                }
+                err => err,
+            };
+            snapshot.bad_argument::<T>(self.name(), pos, err)
+        })?;
+        check(&value).map_err(|message| self.error(pos, message))?;
+        Ok(value)
+    }
+
+    /// Converts the next argument to `T` and checks that it lies within the inclusive range.
+    pub fn get_in_range<T>(&mut self, min: T, max: T) -> Result<T>
+    where
+        T: FromLua<'lua> + PartialOrd + fmt::Display,
+    {
+        self.get_checked(|value: &T| match *value >= min && *value <= max {
+            true => Ok(()),
+            false => Err(format!("value {value} is out of range [{min}, {max}]")),
+        })
+    }
+
+    /// Converts the next argument to `T` and checks that it equals one of the `options`.
+    pub fn get_one_of<T, O>(&mut self, options: &[O]) -> Result<T>
+    where
+        T: FromLua<'lua> + PartialEq<O> + fmt::Display,
+        O: fmt::Display,
+    {
+        self.get_checked(|value: &T| match options.iter().any(|opt| value == opt) {
+            true => Ok(()),
+            false => {
+                let options = options.iter().map(|opt| opt.to_string());
+                let options = options.collect::<Vec<_>>().join(", ");
+                Err(format!(
+                    "invalid option '{value}' (expected one of: {options})"
+                ))
+            }
+        })
+    }
+
+    /// Converts all remaining arguments to `T`.
+    pub fn rest<T: FromLua<'lua>>(mut self) -> Result<Vec<T>> {
+        let mut values = Vec::with_capacity(self.values.len());
+        while !self.values.is_empty() {
+            values.push(self.get()?);
+        }
+        Ok(values)
+    }
+
+    /// Creates a bad argument error for the argument at position `pos` with the given message.
+    pub fn error(&self, pos: usize, message: impl fmt::Display) -> Error {
+        self.bad_argument(pos, Error::external(message.to_string()))
+    }
+
+    fn bad_argument(&self, pos: usize, cause: Error) -> Error {
+        Error::BadArgument {
+            to: self.name.clone(),
+            pos,
+            name: None,
+            cause: Arc::new(cause),
+        }
+    }
+}
+
+// Returns the Lua type name expected by a Rust conversion target
+fn lua_type_name(to: &str) -> Option<&'static str> {
+    Some(match to {
+        "i8" | "u8" | "i16" | "u16" | "i32" | "u32" | "i64" | "u64" | "i128" | "u128" | "isize"
+        | "usize" | "f32" | "f64" | "number" | "integer" => "number",
+        "String" | "Box<str>" | "CString" | "BString" | "string" => "string",
+        "bool" | "boolean" => "boolean",
+        "table" | "Vec" | "Array" | "HashMap" | "BTreeMap" | "HashSet" | "BTreeSet" => "table",
+        "function" => "function",
+        "thread" => "thread",
+        "userdata" => "userdata",
+        _ => return None,
+    })
+}
+
+// Checks whether a failed conversion was caused by something other than the value type
+fn is_compatible(ty: &str, to: &str) -> bool {
+    match lua_type_name(to) {
+        Some("number") => matches!(ty, "integer" | "number"),
+        Some(expected) => ty == expected,
+        None => true,
+    }
+}
+
+impl<'lua> FromLuaMulti<'lua> for Args<'lua> {
+    #[inline]
+    fn from_lua_multi(values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
+        Self::from_lua_args(values, 1, None, lua)
+    }
+
+    #[inline]
+    fn from_lua_args(
+        values: MultiValue<'lua>,
+        i: usize,
+        to: Option<&str>,
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        Ok(Args {
+            values,
+            pos: i,
+            name: to.map(|s| s.to_string()),
+            lua,
+        })
+    }
+
+    #[inline]
+    unsafe fn from_stack_args(
+        nargs: c_int,
+        i: usize,
+        to: Option<&str>,
+        lua: &'lua Lua,
+    ) -> Result<Self> {
+        let values = MultiValue::from_stack_multi(nargs, lua)?;
+        Self::from_lua_args(values, i, to, lua)
+    }
+}
+
+impl<'lua> From<Args<'lua>> for MultiValue<'lua> {
+    #[inline]
+    fn from(args: Args<'lua>) -> Self {
+        args.values
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+use futures_util::future;
+use rustc_hash::FxHashMap;
+
+use crate::error::{Error, Result};
+
+/// Policy for concurrent calls of an async userdata method on the same userdata instance.
+///
+/// Async methods borrow the userdata for the whole duration of the call, so by default another
+/// call on the same instance can run while the first one is suspended at an `.await` point.
+/// Methods added using [`UserDataMethods::add_async_method_with`] or
+/// [`UserDataMethods::add_async_method_mut_with`] can instead run one at a time per instance.
+///
+/// All guarded methods of a userdata type share the same per-instance state: a call of one
+/// method also waits for (or is rejected by) a running call of another guarded method.
+///
+/// Requires `feature = "async"`
+///
+/// [`UserDataMethods::add_async_method_with`]: crate::UserDataMethods::add_async_method_with
+/// [`UserDataMethods::add_async_method_mut_with`]: crate::UserDataMethods::add_async_method_mut_with
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AsyncConcurrency {
+    /// Calls run concurrently (the behavior of [`UserDataMethods::add_async_method`]).
+    ///
+    /// [`UserDataMethods::add_async_method`]: crate::UserDataMethods::add_async_method
+    #[default]
+    Concurrent,
+    /// A call waits until the running call on the same instance completes.
+    Queue,
+    /// A call fails immediately if another call on the same instance is running.
+    Reject,
+}
+
+// Running calls of guarded async methods of a userdata type, shared by its methods
+#[derive(Default)]
+pub(crate) struct AsyncCalls(Mutex<CallsState>);
+
+#[derive(Default)]
+struct CallsState {
+    // Userdata with a running call, with the wakers of the calls waiting for it (one per waiter)
+    active: FxHashMap<usize, FxHashMap<usize, Waker>>,
+    next_waiter: usize,
+}
+
+impl AsyncCalls {
+    fn state(&self) -> MutexGuard<'_, CallsState> {
+        mlua_expect!(self.0.lock(), "async calls mutex poisoned")
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AsyncGuard {
+    calls: Arc<AsyncCalls>,
+    concurrency: AsyncConcurrency,
+}
+
+impl AsyncGuard {
+    pub(crate) fn new(calls: Arc<AsyncCalls>, concurrency: AsyncConcurrency) -> Option<Self> {
+        match concurrency {
+            AsyncConcurrency::Concurrent => None,
+            concurrency => Some(AsyncGuard { calls, concurrency }),
+        }
+    }
+
+    // Waits until no other guarded call is running on the instance with the given key (its
+    // address), and marks it as running
+    pub(crate) async fn enter(&self, key: usize) -> Result<AsyncCallPermit> {
+        let mut waiter = Waiter {
+            calls: &self.calls,
+            key,
+            id: None,
+        };
+        future::poll_fn(|cx| {
+            let mut state = self.calls.state();
+            let state = &mut *state;
+            match state.active.get_mut(&key) {
+                None => {
+                    state.active.insert(key, FxHashMap::default());
+                    waiter.id = None;
+                    Poll::Ready(Ok(AsyncCallPermit {
+                        calls: self.calls.clone(),
+                        key,
+                    }))
+                }
+                Some(_) if self.concurrency == AsyncConcurrency::Reject => Poll::Ready(Err(
+                    Error::runtime("another async call is running on this userdata"),
+                )),
+                Some(wakers) => {
+                    // Replace the waker registered by the previous poll, if any
+                    let id = *waiter.id.get_or_insert_with(|| {
+                        state.next_waiter += 1;
+                        state.next_waiter
+                    });
+                    wakers.insert(id, cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+// Removes the waker of a waiting call when the call is cancelled
+struct Waiter<'a> {
+    calls: &'a AsyncCalls,
+    key: usize,
+    id: Option<usize>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            if let Some(wakers) = self.calls.state().active.get_mut(&self.key) {
+                wakers.remove(&id);
+            }
+        }
+    }
+}
+
+// Marks a call as running until dropped, then wakes up the waiting calls
+pub(crate) struct AsyncCallPermit {
+    calls: Arc<AsyncCalls>,
+    key: usize,
+}
+
+impl Drop for AsyncCallPermit {
+    fn drop(&mut self) {
+        let wakers = self.calls.state().active.remove(&self.key);
+        for waker in wakers.into_iter().flat_map(|wakers| wakers.into_values()) {
+            waker.wake();
+        }
+    }
+}
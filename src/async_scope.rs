@@ -0,0 +1,96 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::os::raw::c_int;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+use futures_util::future::LocalBoxFuture;
+
+use crate::error::{Error, Result};
+
+// Future of an async scope function call, owned by its poll closure and tracked by the `Lua`
+pub(crate) struct PendingFuture {
+    future: RefCell<Option<LocalBoxFuture<'static, Result<c_int>>>>,
+    waker: RefCell<Option<Waker>>,
+    cancelled: Cell<bool>,
+}
+
+impl PendingFuture {
+    // Drops the future and wakes the thread waiting on it.
+    // A future being polled right now is dropped as soon as the poll returns.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.set(true);
+        let future = match self.future.try_borrow_mut() {
+            Ok(mut future) => future.take(),
+            Err(_) => None,
+        };
+        drop(future);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// Futures of async scope functions that have not been collected yet
+#[derive(Default)]
+pub(crate) struct PendingFutures(Vec<Weak<PendingFuture>>);
+
+impl PendingFutures {
+    pub(crate) fn track(&mut self, future: LocalBoxFuture<'static, Result<c_int>>) -> ScopedFuture {
+        // Forget collected futures before growing, to keep the list bounded
+        if self.0.len() == self.0.capacity() {
+            self.0.retain(|pending| pending.strong_count() > 0);
+        }
+        let pending = Rc::new(PendingFuture {
+            future: RefCell::new(Some(future)),
+            waker: RefCell::new(None),
+            cancelled: Cell::new(false),
+        });
+        self.0.push(Rc::downgrade(&pending));
+        ScopedFuture(pending)
+    }
+
+    // Takes the futures that have not completed yet
+    pub(crate) fn take(&mut self) -> Vec<Rc<PendingFuture>> {
+        self.0
+            .drain(..)
+            .filter_map(|pending| pending.upgrade())
+            // A future that is being polled cannot be borrowed
+            .filter(|pending| {
+                let future = pending.future.try_borrow();
+                !pending.cancelled.get() && future.map_or(true, |future| future.is_some())
+            })
+            .collect()
+    }
+}
+
+pub(crate) struct ScopedFuture(Rc<PendingFuture>);
+
+impl Future for ScopedFuture {
+    type Output = Result<c_int>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pending = &*self.0;
+        let mut slot = pending.future.borrow_mut();
+        let poll = match slot.as_mut() {
+            Some(future) if !pending.cancelled.get() => future.as_mut().poll(cx),
+            _ => Poll::Pending,
+        };
+        if poll.is_ready() || pending.cancelled.get() {
+            let future = slot.take();
+            drop(slot);
+            drop(future);
+        }
+        match poll {
+            Poll::Ready(res) => Poll::Ready(res),
+            Poll::Pending if pending.cancelled.get() => {
+                Poll::Ready(Err(Error::runtime("async function call was cancelled")))
+            }
+            Poll::Pending => {
+                *pending.waker.borrow_mut() = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::string::String as StdString;
+
+use crate::error::Result;
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::value::Value;
+
+// Globals and functions watched by `SandboxAudit::sensitive`
+const SENSITIVE: &[&str] = &[
+    "load",
+    "loadstring",
+    "loadfile",
+    "dofile",
+    "require",
+    "getfenv",
+    "setfenv",
+    "collectgarbage",
+    "package.loadlib",
+    "os.*",
+    "io.*",
+    "debug.*",
+    "ffi.*",
+];
+
+/// Globals and functions watched by [`Lua::audit_sandbox`].
+///
+/// Each name is a path in the globals table, e.g. `load` or `os.execute`. A name referring to
+/// a table (optionally written as `os.*`) watches every function stored in that table.
+/// Names that cannot be resolved are ignored.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct SandboxAudit {
+    /// Names of watched globals and functions.
+    ///
+    /// Default: **empty**
+    pub watched: Vec<StdString>,
+}
+
+impl SandboxAudit {
+    /// Returns a new instance of `SandboxAudit` without any watched names.
+    pub const fn new() -> Self {
+        SandboxAudit {
+            watched: Vec::new(),
+        }
+    }
+
+    /// Returns a new instance of `SandboxAudit` watching commonly sensitive functions.
+    ///
+    /// This includes code loading functions (`load`, `dofile`, `require`, ...), environment
+    /// manipulation (`getfenv`, `setfenv`), `package.loadlib` and the `os`, `io`, `debug` and
+    /// `ffi` libraries.
+    pub fn sensitive() -> Self {
+        SandboxAudit {
+            watched: SENSITIVE.iter().map(|&name| name.into()).collect(),
+        }
+    }
+
+    /// Adds `name` to the [`watched`] list.
+    ///
+    /// [`watched`]: #structfield.watched
+    #[must_use]
+    pub fn watch(mut self, name: impl Into<StdString>) -> Self {
+        self.watched.push(name.into());
+        self
+    }
+}
+
+/// A call of a watched function reported by [`Lua::audit_sandbox`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// Full name of the called function, e.g. `os.execute`.
+    pub name: StdString,
+    /// A "printable" version of the caller chunk source (`None` if not available).
+    pub source: Option<StdString>,
+    /// The line of the call in the caller chunk (`None` if not available).
+    pub line: Option<usize>,
+}
+
+pub(crate) fn audit_sandbox<F>(lua: &Lua, audit: SandboxAudit, callback: F) -> Result<()>
+where
+    F: Fn(&Lua, AuditEvent) + MaybeSend + 'static,
+{
+    let notify = lua.create_function(move |lua, name: StdString| {
+        // Level 0 is this function and level 1 is the wrapper
+        let (source, line) = match lua.inspect_stack(2) {
+            Some(debug) => {
+                let source = debug.source().short_src.map(|s| s.into_owned());
+                (source, usize::try_from(debug.curr_line()).ok())
+            }
+            None => (None, None),
+        };
+        callback(lua, AuditEvent { name, source, line });
+        Ok(())
+    })?;
+
+    let globals = lua.globals();
+    for name in &audit.watched {
+        let path = name.strip_suffix(".*").unwrap_or(name.as_str());
+        let (table, key) = match path.rsplit_once('.') {
+            Some((parent, key)) => match resolve(&globals, parent)? {
+                Value::Table(table) => (table, key),
+                _ => continue,
+            },
+            None => (globals.clone(), path),
+        };
+
+        match table.raw_get::<_, Value>(key)? {
+            Value::Function(func) => table.raw_set(key, wrap(lua, &notify, path, func)?)?,
+            Value::Table(lib) => {
+                let mut funcs = Vec::new();
+                for pair in lib.clone().pairs::<Value, Value>() {
+                    if let (Value::String(key), Value::Function(func)) = pair? {
+                        funcs.push((key.to_str()?.to_owned(), func));
+                    }
+                }
+                for (key, func) in funcs {
+                    let func = wrap(lua, &notify, &format!("{path}.{key}"), func)?;
+                    lib.raw_set(key, func)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Resolves a dot-separated path starting from the globals table
+fn resolve<'lua>(globals: &Table<'lua>, path: &str) -> Result<Value<'lua>> {
+    let mut value = Value::Table(globals.clone());
+    for key in path.split('.') {
+        value = match value {
+            Value::Table(table) => table.raw_get(key)?,
+            _ => return Ok(Value::Nil),
+        };
+    }
+    Ok(value)
+}
+
+// Wraps `func` into a Lua function which reports every call before forwarding it.
+// The tail call keeps the wrapper out of the call stack in most Lua versions.
+fn wrap<'lua>(
+    lua: &'lua Lua,
+    notify: &Function<'lua>,
+    name: &str,
+    func: Function<'lua>,
+) -> Result<Function<'lua>> {
+    lua.load(
+        r#"
+        local notify, name, func = ...
+        return function(...)
+            notify(name)
+            return func(...)
+        end
+        "#,
+    )
+    .try_cache()
+    .set_name("__mlua_audit")
+    .call((notify.clone(), name, func))
+}
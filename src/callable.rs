@@ -0,0 +1,86 @@
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::types::MaybeSend;
+use crate::userdata::{AnyUserData, MetaMethod, UserData};
+use crate::value::{FromLuaMulti, IntoLuaMulti, MultiValue};
+
+// Name of the user value holding the call handler of a callable userdata
+const CALL_HANDLER: &str = "__mlua_call";
+
+impl Lua {
+    /// Creates a userdata that holds `data` and is directly callable from Lua.
+    ///
+    /// Calling the userdata invokes `handler` with a reference to `data` and the call arguments,
+    /// while the methods and fields of `T` remain available as usual. This is handy for command
+    /// objects and other closures that need to expose their state, eg. `cmd(...)` along with
+    /// `cmd:describe()`.
+    ///
+    /// Every userdata gets its own handler. If `T` handles calls itself (eg. using
+    /// [`UserDataRegistry::add_call`]), that `__call` metamethod takes precedence over `handler`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// struct Command {
+    ///     name: String,
+    /// }
+    ///
+    /// impl UserData for Command {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("describe", |_, this, ()| Ok(format!("command `{}`", this.name)));
+    ///     }
+    /// }
+    ///
+    /// let lua = Lua::new();
+    /// let greet = lua.create_callable(Command { name: "greet".into() }, |_, _, who: String| {
+    ///     Ok(format!("hello, {who}!"))
+    /// })?;
+    /// lua.globals().set("greet", greet)?;
+    ///
+    /// assert_eq!(lua.load("greet('world')").eval::<String>()?, "hello, world!");
+    /// assert_eq!(lua.load("greet:describe()").eval::<String>()?, "command `greet`");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserDataRegistry::add_call`]: crate::UserDataRegistry::add_call
+    pub fn create_callable<'lua, T, F, A, R>(
+        &'lua self,
+        data: T,
+        handler: F,
+    ) -> Result<AnyUserData<'lua>>
+    where
+        T: UserData + MaybeSend + 'static,
+        F: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let ud = self.create_userdata(data)?;
+
+        // The metatable is shared by all userdata of type `T`, so it only forwards calls to the
+        // handler stored in every callable userdata
+        let metatable = ud.get_metatable()?;
+        if !metatable.contains(MetaMethod::Call)? {
+            metatable.set(MetaMethod::Call, self.create_function(forward_call)?)?;
+        }
+
+        let handler = self.create_function(move |lua, (ud, args): (AnyUserData, A)| {
+            handler(lua, &*ud.borrow::<T>()?, args)
+        })?;
+        ud.set_named_user_value(CALL_HANDLER, handler)?;
+        Ok(ud)
+    }
+}
+
+fn forward_call<'lua>(
+    _: &'lua Lua,
+    (ud, args): (AnyUserData<'lua>, MultiValue<'lua>),
+) -> Result<MultiValue<'lua>> {
+    match ud.named_user_value::<Option<Function>>(CALL_HANDLER)? {
+        Some(handler) => handler.call((ud, args)),
+        None => Err(Error::runtime("attempt to call a non-callable userdata")),
+    }
+}
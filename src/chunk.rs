@@ -2,13 +2,16 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::io::Result as IoResult;
+#[cfg(feature = "filesystem")]
 use std::path::{Path, PathBuf};
 use std::string::String as StdString;
+use std::sync::Arc;
 
 use crate::error::{Error, ErrorContext, Result};
 use crate::function::Function;
 use crate::lua::Lua;
 use crate::table::Table;
+use crate::types::SetupStep;
 use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti};
 
 /// Trait for types [loadable by Lua] and convertible to a [`Chunk`]
@@ -74,6 +77,7 @@ impl<'a> AsChunk<'_, 'a> for &'a Vec<u8> {
     }
 }
 
+#[cfg(feature = "filesystem")]
 impl AsChunk<'_, 'static> for &Path {
     fn name(&self) -> Option<StdString> {
         Some(format!("@{}", self.display()))
@@ -84,6 +88,7 @@ impl AsChunk<'_, 'static> for &Path {
     }
 }
 
+#[cfg(feature = "filesystem")]
 impl AsChunk<'_, 'static> for PathBuf {
     fn name(&self) -> Option<StdString> {
         Some(format!("@{}", self.display()))
@@ -340,11 +345,39 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// Execute this chunk of code.
     ///
     /// This is equivalent to calling the chunk function with no arguments and no return values.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn exec(self) -> Result<()> {
         self.call::<_, ()>(())?;
         Ok(())
     }
 
+    /// Execute this chunk of code and record it as a part of the Lua state setup.
+    ///
+    /// The chunk is compiled to bytecode and stored to be replayed by [`Lua::fork`].
+    /// Chunks with a custom environment cannot be recorded.
+    ///
+    /// [`Lua::fork`]: crate::Lua::fork
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn exec_recorded(mut self) -> Result<()> {
+        if matches!(self.env, Ok(Some(_))) {
+            return Err(Error::runtime(
+                "cannot record a chunk with custom environment",
+            ));
+        }
+
+        self.compile();
+        let bytecode = match (&self.source, self.detect_mode()) {
+            (Ok(source), ChunkMode::Binary) => Some(Arc::from(source.as_ref())),
+            _ => None,
+        };
+        let (lua, name) = (self.lua, self.name.clone());
+        self.exec()?;
+        if let Some(bytecode) = bytecode {
+            lua.record_setup_step(SetupStep::Chunk { name, bytecode });
+        }
+        Ok(())
+    }
+
     /// Asynchronously execute this chunk of code.
     ///
     /// See [`exec`] for more details.
@@ -363,6 +396,7 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// If the chunk can be parsed as an expression, this loads and executes the chunk and returns
     /// the value that it evaluates to. Otherwise, the chunk is interpreted as a block as normal,
     /// and this is equivalent to calling `exec`.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn eval<R: FromLuaMulti<'lua>>(self) -> Result<R> {
         // Bytecode is always interpreted as a statement.
         // For source code, first try interpreting the lua as an expression by adding
@@ -402,6 +436,7 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// Load the chunk function and call it with the given arguments.
     ///
     /// This is equivalent to `into_function` and calling the resulting function.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn call<A: IntoLuaMulti<'lua>, R: FromLuaMulti<'lua>>(self, args: A) -> Result<R> {
         self.into_function()?.call(args)
     }
@@ -427,6 +462,7 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     ///
     /// This simply compiles the chunk without actually executing it.
     #[cfg_attr(not(feature = "luau"), allow(unused_mut))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn into_function(mut self) -> Result<Function<'lua>> {
         #[cfg(feature = "luau")]
         if self.compiler.is_some() {
@@ -504,6 +540,7 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         self
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn to_expression(&self) -> Result<Function<'lua>> {
         // We assume that mode is Text
         let source = self.source.as_ref();
@@ -0,0 +1,110 @@
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::string::String;
+use crate::types::{Integer, Number};
+use crate::value::Value;
+
+/// Implicit coercions between strings and numbers accepted by [`FromLua`] conversions.
+///
+/// Lua converts numeric strings to numbers in arithmetic and numbers to strings in
+/// concatenation; by default mlua conversions follow the same rules, so `"42"` is accepted where
+/// an `i32` is expected and `42` where a `String` is expected. APIs validating their arguments
+/// strictly can disable either direction using [`LuaOptions::coercion`].
+///
+/// The policy applies to conversions of numbers (`i32`, `f64`, ...) and strings ([`String`],
+/// `std::string::String`, `Box<str>`, `CString`, `BString`, ...). It does not change Lua
+/// semantics or [`Lua::coerce_string`]/[`Lua::coerce_number`], which always follow Lua rules.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{CoercionPolicy, Lua, LuaOptions, Result, StdLib};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new_with(
+///     StdLib::ALL_SAFE,
+///     LuaOptions::new().coercion(CoercionPolicy::STRICT),
+/// )?;
+/// let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+/// lua.globals().set("add", add)?;
+/// assert!(lua.load(r#"add(1, "2")"#).exec().is_err());
+/// assert_eq!(lua.load("add(1, 2)").eval::<i64>()?, 3);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`FromLua`]: crate::FromLua
+/// [`LuaOptions::coercion`]: crate::LuaOptions::coercion
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CoercionPolicy {
+    /// Accept strings convertible to numbers where a number is expected.
+    pub string_to_number: bool,
+    /// Accept numbers where a string is expected.
+    pub number_to_string: bool,
+}
+
+impl CoercionPolicy {
+    /// Coercions of the Lua language, in both directions.
+    pub const LUA: CoercionPolicy = CoercionPolicy {
+        string_to_number: true,
+        number_to_string: true,
+    };
+
+    /// No implicit coercions: numbers must be numbers and strings must be strings.
+    pub const STRICT: CoercionPolicy = CoercionPolicy {
+        string_to_number: false,
+        number_to_string: false,
+    };
+
+    /// Sets [`string_to_number`] option.
+    ///
+    /// [`string_to_number`]: #structfield.string_to_number
+    #[must_use]
+    pub const fn string_to_number(mut self, enabled: bool) -> Self {
+        self.string_to_number = enabled;
+        self
+    }
+
+    /// Sets [`number_to_string`] option.
+    ///
+    /// [`number_to_string`]: #structfield.number_to_string
+    #[must_use]
+    pub const fn number_to_string(mut self, enabled: bool) -> Self {
+        self.number_to_string = enabled;
+        self
+    }
+}
+
+impl Default for CoercionPolicy {
+    fn default() -> Self {
+        CoercionPolicy::LUA
+    }
+}
+
+// Coercions used by `FromLua` implementations, following the policy of the state
+impl Lua {
+    pub(crate) fn coerce_string_by_policy<'lua>(
+        &'lua self,
+        v: Value<'lua>,
+    ) -> Result<Option<String<'lua>>> {
+        match v {
+            Value::Integer(_) | Value::Number(_) if !self.coercion_policy().number_to_string => {
+                Ok(None)
+            }
+            v => self.coerce_string(v),
+        }
+    }
+
+    pub(crate) fn coerce_integer_by_policy(&self, v: Value) -> Result<Option<Integer>> {
+        match v {
+            Value::String(_) if !self.coercion_policy().string_to_number => Ok(None),
+            v => self.coerce_integer(v),
+        }
+    }
+
+    pub(crate) fn coerce_number_by_policy(&self, v: Value) -> Result<Option<Number>> {
+        match v {
+            Value::String(_) if !self.coercion_policy().string_to_number => Ok(None),
+            v => self.coerce_number(v),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::any::TypeId;
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::{CStr, CString};
@@ -9,13 +10,14 @@ use std::{slice, str};
 use bstr::{BStr, BString};
 use num_traits::cast;
 
+use crate::conversion_limits::collect_limited;
 use crate::error::{Error, Result};
 use crate::function::Function;
 use crate::lua::Lua;
 use crate::string::String;
-use crate::table::Table;
+use crate::table::{Table, TypedTable};
 use crate::thread::Thread;
-use crate::types::{LightUserData, MaybeSend, RegistryKey};
+use crate::types::{LightUserData, MaybeSend, RegistryKey, TypedLightUserData};
 use crate::userdata::{AnyUserData, UserData, UserDataRef, UserDataRefMut};
 use crate::value::{FromLua, IntoLua, Nil, Value};
 
@@ -27,6 +29,7 @@ use crate::{
 
 impl<'lua> IntoLua<'lua> for Value<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(self)
     }
@@ -34,11 +37,13 @@ impl<'lua> IntoLua<'lua> for Value<'lua> {
 
 impl<'lua> IntoLua<'lua> for &Value<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(self.clone())
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_value_ref(self)
     }
@@ -53,6 +58,7 @@ impl<'lua> FromLua<'lua> for Value<'lua> {
 
 impl<'lua> IntoLua<'lua> for String<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(self))
     }
@@ -60,14 +66,15 @@ impl<'lua> IntoLua<'lua> for String<'lua> {
 
 impl<'lua> IntoLua<'lua> for &String<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(self.clone()))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
-        lua.push_ref(&self.0);
-        Ok(())
+        lua.try_push_ref(&self.0)
     }
 }
 
@@ -75,7 +82,7 @@ impl<'lua> FromLua<'lua> for String<'lua> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<String<'lua>> {
         let ty = value.type_name();
-        lua.coerce_string(value)?
+        lua.coerce_string_by_policy(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "string",
@@ -88,6 +95,7 @@ impl<'lua> FromLua<'lua> for String<'lua> {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for OwnedString {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(String(lua.adopt_owned_ref(self.0))))
     }
@@ -97,11 +105,13 @@ impl<'lua> IntoLua<'lua> for OwnedString {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for &OwnedString {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         OwnedString::into_lua(self.clone(), lua)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_owned_ref(&self.0);
         Ok(())
@@ -119,6 +129,7 @@ impl<'lua> FromLua<'lua> for OwnedString {
 
 impl<'lua> IntoLua<'lua> for Table<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(self))
     }
@@ -126,14 +137,15 @@ impl<'lua> IntoLua<'lua> for Table<'lua> {
 
 impl<'lua> IntoLua<'lua> for &Table<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(self.clone()))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
-        lua.push_ref(&self.0);
-        Ok(())
+        lua.try_push_ref(&self.0)
     }
 }
 
@@ -151,10 +163,26 @@ impl<'lua> FromLua<'lua> for Table<'lua> {
     }
 }
 
+impl<'lua, K, V> IntoLua<'lua> for TypedTable<'lua, K, V> {
+    #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(self.into_table()))
+    }
+}
+
+impl<'lua, K, V> FromLua<'lua> for TypedTable<'lua, K, V> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<TypedTable<'lua, K, V>> {
+        Table::from_lua(value, lua).map(TypedTable::new)
+    }
+}
+
 #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for OwnedTable {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(Table(lua.adopt_owned_ref(self.0))))
     }
@@ -164,11 +192,13 @@ impl<'lua> IntoLua<'lua> for OwnedTable {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for &OwnedTable {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         OwnedTable::into_lua(self.clone(), lua)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_owned_ref(&self.0);
         Ok(())
@@ -186,6 +216,7 @@ impl<'lua> FromLua<'lua> for OwnedTable {
 
 impl<'lua> IntoLua<'lua> for Function<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Function(self))
     }
@@ -193,14 +224,15 @@ impl<'lua> IntoLua<'lua> for Function<'lua> {
 
 impl<'lua> IntoLua<'lua> for &Function<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Function(self.clone()))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
-        lua.push_ref(&self.0);
-        Ok(())
+        lua.try_push_ref(&self.0)
     }
 }
 
@@ -222,6 +254,7 @@ impl<'lua> FromLua<'lua> for Function<'lua> {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for OwnedFunction {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Function(Function(lua.adopt_owned_ref(self.0))))
     }
@@ -231,11 +264,13 @@ impl<'lua> IntoLua<'lua> for OwnedFunction {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for &OwnedFunction {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         OwnedFunction::into_lua(self.clone(), lua)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_owned_ref(&self.0);
         Ok(())
@@ -253,6 +288,7 @@ impl<'lua> FromLua<'lua> for OwnedFunction {
 
 impl<'lua> IntoLua<'lua> for Thread<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Thread(self))
     }
@@ -260,14 +296,15 @@ impl<'lua> IntoLua<'lua> for Thread<'lua> {
 
 impl<'lua> IntoLua<'lua> for &Thread<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Thread(self.clone()))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
-        lua.push_ref(&self.0);
-        Ok(())
+        lua.try_push_ref(&self.0)
     }
 }
 
@@ -289,6 +326,7 @@ impl<'lua> FromLua<'lua> for Thread<'lua> {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for OwnedThread {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Thread(Thread(lua.adopt_owned_ref(self.0), self.1)))
     }
@@ -298,11 +336,13 @@ impl<'lua> IntoLua<'lua> for OwnedThread {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for &OwnedThread {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         OwnedThread::into_lua(self.clone(), lua)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_owned_ref(&self.0);
         Ok(())
@@ -320,6 +360,7 @@ impl<'lua> FromLua<'lua> for OwnedThread {
 
 impl<'lua> IntoLua<'lua> for AnyUserData<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::UserData(self))
     }
@@ -327,14 +368,15 @@ impl<'lua> IntoLua<'lua> for AnyUserData<'lua> {
 
 impl<'lua> IntoLua<'lua> for &AnyUserData<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::UserData(self.clone()))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
-        lua.push_ref(&self.0);
-        Ok(())
+        lua.try_push_ref(&self.0)
     }
 }
 
@@ -356,6 +398,7 @@ impl<'lua> FromLua<'lua> for AnyUserData<'lua> {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for OwnedAnyUserData {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::UserData(AnyUserData(
             lua.adopt_owned_ref(self.0),
@@ -368,11 +411,13 @@ impl<'lua> IntoLua<'lua> for OwnedAnyUserData {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
 impl<'lua> IntoLua<'lua> for &OwnedAnyUserData {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         OwnedAnyUserData::into_lua(self.clone(), lua)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_owned_ref(&self.0);
         Ok(())
@@ -390,6 +435,7 @@ impl<'lua> FromLua<'lua> for OwnedAnyUserData {
 
 impl<'lua, T: UserData + MaybeSend + 'static> IntoLua<'lua> for T {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::UserData(lua.create_userdata(self)?))
     }
@@ -411,6 +457,7 @@ impl<'lua, T: 'static> FromLua<'lua> for UserDataRefMut<'lua, T> {
 
 impl<'lua> IntoLua<'lua> for Error {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Error(self))
     }
@@ -432,11 +479,13 @@ impl<'lua> FromLua<'lua> for Error {
 
 impl<'lua> IntoLua<'lua> for RegistryKey {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         lua.registry_value(&self)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         <&RegistryKey>::push_into_stack(&self, lua)
     }
@@ -444,10 +493,12 @@ impl<'lua> IntoLua<'lua> for RegistryKey {
 
 impl<'lua> IntoLua<'lua> for &RegistryKey {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         lua.registry_value(self)
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         if !lua.owns_registry_value(self) {
             return Err(Error::MismatchedRegistryKey);
@@ -472,11 +523,13 @@ impl<'lua> FromLua<'lua> for RegistryKey {
 
 impl<'lua> IntoLua<'lua> for bool {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Boolean(self))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         ffi::lua_pushboolean(lua.state(), self as c_int);
         Ok(())
@@ -501,6 +554,7 @@ impl<'lua> FromLua<'lua> for bool {
 
 impl<'lua> IntoLua<'lua> for LightUserData {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::LightUserData(self))
     }
@@ -520,8 +574,50 @@ impl<'lua> FromLua<'lua> for LightUserData {
     }
 }
 
+impl<'lua, T> IntoLua<'lua> for TypedLightUserData<T> {
+    #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::LightUserData(self.to_light_userdata()))
+    }
+}
+
+impl<'lua, T: 'static> FromLua<'lua> for TypedLightUserData<T> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let ptr = match value {
+            Value::LightUserData(ud) => ud.0,
+            _ => {
+                return Err(Error::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "typed light userdata",
+                    message: None,
+                })
+            }
+        };
+        match lua.light_userdata_type(ptr) {
+            Some((type_id, _)) if type_id == TypeId::of::<T>() => {
+                Ok(TypedLightUserData::new(ptr as *mut T))
+            }
+            Some((_, type_name)) => Err(Error::FromLuaConversionError {
+                from: "light userdata",
+                to: "typed light userdata",
+                message: Some(format!(
+                    "expected pointer to {}, got pointer to {type_name}",
+                    std::any::type_name::<T>()
+                )),
+            }),
+            None => Err(Error::FromLuaConversionError {
+                from: "light userdata",
+                to: "typed light userdata",
+                message: Some("unknown pointer".to_string()),
+            }),
+        }
+    }
+}
+
 #[cfg(feature = "time")]
 impl<'lua> IntoLua<'lua> for time::OffsetDateTime {
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         let datetime_str = self.format(&time::format_description::well_known::Rfc3339).map_err(|e| Error::RuntimeError(e.to_string()))?;
         let lua_string = lua.create_string(&datetime_str)?;
@@ -553,6 +649,7 @@ impl<'lua> FromLua<'lua> for time::OffsetDateTime {
 #[cfg(feature = "json")]
 impl<'lua> IntoLua<'lua> for serde_json::Value {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         match self {
             serde_json::Value::Null => Ok(Value::Nil),
@@ -608,6 +705,7 @@ impl<'lua> FromLua<'lua> for serde_json::Value {
 #[cfg(feature = "json")]
 impl<'lua> IntoLua<'lua> for &'_ serde_json::Value {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         match self {
             serde_json::Value::Null => Ok(Value::Nil),
@@ -652,7 +750,7 @@ impl<'lua> FromLua<'lua> for uuid::Uuid {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<uuid::Uuid> {
         let ty = value.type_name();
-        let string_result = lua.coerce_string(value)?
+        let string_result = lua.coerce_string_by_policy(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "string",
@@ -677,6 +775,7 @@ impl<'lua> FromLua<'lua> for uuid::Uuid {
 #[cfg(feature = "uuid")]
 impl<'lua> IntoLua<'lua> for uuid::Uuid {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         let uuid_string = lua.create_string(self.to_string().as_str())?;
         Ok(Value::String(uuid_string))
@@ -686,12 +785,14 @@ impl<'lua> IntoLua<'lua> for uuid::Uuid {
 #[cfg(feature = "uuid")]
 impl<'lua> IntoLua<'lua> for &uuid::Uuid {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         let uuid_string = lua.create_string(self.to_string().as_str())?;
         Ok(Value::String(uuid_string))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         let uuid_string = lua.create_string(self.to_string().as_str())?;
         lua.push_ref(&uuid_string.0);
@@ -731,6 +832,7 @@ impl<'lua> IntoLua<'lua> for &uuid::Uuid {
 #[cfg(feature = "luau")]
 impl<'lua> IntoLua<'lua> for crate::types::Vector {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Vector(self))
     }
@@ -753,11 +855,13 @@ impl<'lua> FromLua<'lua> for crate::types::Vector {
 
 impl<'lua> IntoLua<'lua> for StdString {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&self)?))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         push_bytes_into_stack(self, lua)
     }
@@ -768,7 +872,7 @@ impl<'lua> FromLua<'lua> for StdString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
         Ok(lua
-            .coerce_string(value)?
+            .coerce_string_by_policy(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "String",
@@ -800,11 +904,13 @@ impl<'lua> FromLua<'lua> for StdString {
 
 impl<'lua> IntoLua<'lua> for &str {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self)?))
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         push_bytes_into_stack(self, lua)
     }
@@ -812,6 +918,7 @@ impl<'lua> IntoLua<'lua> for &str {
 
 impl<'lua> IntoLua<'lua> for Cow<'_, str> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self.as_bytes())?))
     }
@@ -819,6 +926,7 @@ impl<'lua> IntoLua<'lua> for Cow<'_, str> {
 
 impl<'lua> IntoLua<'lua> for Box<str> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&*self)?))
     }
@@ -829,7 +937,7 @@ impl<'lua> FromLua<'lua> for Box<str> {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
         Ok(lua
-            .coerce_string(value)?
+            .coerce_string_by_policy(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "Box<str>",
@@ -843,6 +951,7 @@ impl<'lua> FromLua<'lua> for Box<str> {
 
 impl<'lua> IntoLua<'lua> for CString {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self.as_bytes())?))
     }
@@ -853,7 +962,7 @@ impl<'lua> FromLua<'lua> for CString {
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
         let string = lua
-            .coerce_string(value)?
+            .coerce_string_by_policy(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "CString",
@@ -873,6 +982,7 @@ impl<'lua> FromLua<'lua> for CString {
 
 impl<'lua> IntoLua<'lua> for &CStr {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self.to_bytes())?))
     }
@@ -880,6 +990,7 @@ impl<'lua> IntoLua<'lua> for &CStr {
 
 impl<'lua> IntoLua<'lua> for Cow<'_, CStr> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self.to_bytes())?))
     }
@@ -887,6 +998,7 @@ impl<'lua> IntoLua<'lua> for Cow<'_, CStr> {
 
 impl<'lua> IntoLua<'lua> for BString {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(&self)?))
     }
@@ -905,7 +1017,7 @@ impl<'lua> FromLua<'lua> for BString {
                 Ok(slice::from_raw_parts(buf as *const u8, size).into())
             },
             _ => Ok(lua
-                .coerce_string(value)?
+                .coerce_string_by_policy(value)?
                 .ok_or_else(|| Error::FromLuaConversionError {
                     from: ty,
                     to: "BString",
@@ -941,9 +1053,16 @@ impl<'lua> FromLua<'lua> for BString {
 
 impl<'lua> IntoLua<'lua> for &BStr {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::String(lua.create_string(self)?))
     }
+
+    #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
+        push_bytes_into_stack(self, lua)
+    }
 }
 
 #[inline]
@@ -965,6 +1084,7 @@ macro_rules! lua_convert_int {
     ($x:ty) => {
         impl<'lua> IntoLua<'lua> for $x {
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
                 cast(self)
                     .map(Value::Integer)
@@ -978,6 +1098,7 @@ macro_rules! lua_convert_int {
             }
 
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
                 match cast(self) {
                     Some(i) => ffi::lua_pushinteger(lua.state(), i),
@@ -995,10 +1116,10 @@ macro_rules! lua_convert_int {
                     Value::Integer(i) => cast(i),
                     Value::Number(n) => cast(n),
                     _ => {
-                        if let Some(i) = lua.coerce_integer(value.clone())? {
+                        if let Some(i) = lua.coerce_integer_by_policy(value.clone())? {
                             cast(i)
                         } else {
-                            cast(lua.coerce_number(value)?.ok_or_else(|| {
+                            cast(lua.coerce_number_by_policy(value)?.ok_or_else(|| {
                                 Error::FromLuaConversionError {
                                     from: ty,
                                     to: stringify!($x),
@@ -1037,6 +1158,7 @@ macro_rules! lua_convert_float {
     ($x:ty) => {
         impl<'lua> IntoLua<'lua> for $x {
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
                 cast(self)
                     .ok_or_else(|| Error::ToLuaConversionError {
@@ -1052,7 +1174,7 @@ macro_rules! lua_convert_float {
             #[inline]
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
                 let ty = value.type_name();
-                lua.coerce_number(value)?
+                lua.coerce_number_by_policy(value)?
                     .ok_or_else(|| Error::FromLuaConversionError {
                         from: ty,
                         to: stringify!($x),
@@ -1078,6 +1200,7 @@ where
     T: IntoLua<'lua> + Clone,
 {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(
             lua.create_sequence_from(self.iter().cloned())?,
@@ -1090,6 +1213,7 @@ where
     T: IntoLua<'lua>,
 {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self)?))
     }
@@ -1100,22 +1224,22 @@ where
     T: FromLua<'lua>,
 {
     #[inline]
-    fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
             #[cfg(feature = "luau")]
             #[rustfmt::skip]
             Value::Vector(v) if N == crate::types::Vector::SIZE => unsafe {
                 use std::{mem, ptr};
                 let mut arr: [mem::MaybeUninit<T>; N] = mem::MaybeUninit::uninit().assume_init();
-                ptr::write(arr[0].as_mut_ptr() , T::from_lua(Value::Number(v.x() as _), _lua)?);
-                ptr::write(arr[1].as_mut_ptr(), T::from_lua(Value::Number(v.y() as _), _lua)?);
-                ptr::write(arr[2].as_mut_ptr(), T::from_lua(Value::Number(v.z() as _), _lua)?);
+                ptr::write(arr[0].as_mut_ptr() , T::from_lua(Value::Number(v.x() as _), lua)?);
+                ptr::write(arr[1].as_mut_ptr(), T::from_lua(Value::Number(v.y() as _), lua)?);
+                ptr::write(arr[2].as_mut_ptr(), T::from_lua(Value::Number(v.z() as _), lua)?);
                 #[cfg(feature = "luau-vector4")]
-                ptr::write(arr[3].as_mut_ptr(), T::from_lua(Value::Number(v.w() as _), _lua)?);
+                ptr::write(arr[3].as_mut_ptr(), T::from_lua(Value::Number(v.w() as _), lua)?);
                 Ok(mem::transmute_copy(&arr))
             },
             Value::Table(table) => {
-                let vec = table.sequence_values().collect::<Result<Vec<_>>>()?;
+                let vec: Vec<T> = collect_limited(lua, table.sequence_values())?;
                 vec.try_into()
                     .map_err(|vec: Vec<T>| Error::FromLuaConversionError {
                         from: "table",
@@ -1134,6 +1258,7 @@ where
 
 impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Box<[T]> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self.into_vec())?))
     }
@@ -1148,6 +1273,7 @@ impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Box<[T]> {
 
 impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Vec<T> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_sequence_from(self)?))
     }
@@ -1155,9 +1281,9 @@ impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Vec<T> {
 
 impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Vec<T> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) => table.sequence_values().collect(),
+            Value::Table(table) => collect_limited(lua, table.sequence_values()),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "Vec",
@@ -1171,6 +1297,7 @@ impl<'lua, K: Eq + Hash + IntoLua<'lua>, V: IntoLua<'lua>, S: BuildHasher> IntoL
     for HashMap<K, V, S>
 {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_table_from(self)?))
     }
@@ -1180,9 +1307,9 @@ impl<'lua, K: Eq + Hash + FromLua<'lua>, V: FromLua<'lua>, S: BuildHasher + Defa
     for HashMap<K, V, S>
 {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         if let Value::Table(table) = value {
-            table.pairs().collect()
+            collect_limited(lua, table.pairs())
         } else {
             Err(Error::FromLuaConversionError {
                 from: value.type_name(),
@@ -1195,6 +1322,7 @@ impl<'lua, K: Eq + Hash + FromLua<'lua>, V: FromLua<'lua>, S: BuildHasher + Defa
 
 impl<'lua, K: Ord + IntoLua<'lua>, V: IntoLua<'lua>> IntoLua<'lua> for BTreeMap<K, V> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_table_from(self)?))
     }
@@ -1202,9 +1330,9 @@ impl<'lua, K: Ord + IntoLua<'lua>, V: IntoLua<'lua>> IntoLua<'lua> for BTreeMap<
 
 impl<'lua, K: Ord + FromLua<'lua>, V: FromLua<'lua>> FromLua<'lua> for BTreeMap<K, V> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         if let Value::Table(table) = value {
-            table.pairs().collect()
+            collect_limited(lua, table.pairs())
         } else {
             Err(Error::FromLuaConversionError {
                 from: value.type_name(),
@@ -1217,6 +1345,7 @@ impl<'lua, K: Ord + FromLua<'lua>, V: FromLua<'lua>> FromLua<'lua> for BTreeMap<
 
 impl<'lua, T: Eq + Hash + IntoLua<'lua>, S: BuildHasher> IntoLua<'lua> for HashSet<T, S> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_table_from(
             self.into_iter().map(|val| (val, true)),
@@ -1226,13 +1355,15 @@ impl<'lua, T: Eq + Hash + IntoLua<'lua>, S: BuildHasher> IntoLua<'lua> for HashS
 
 impl<'lua, T: Eq + Hash + FromLua<'lua>, S: BuildHasher + Default> FromLua<'lua> for HashSet<T, S> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) if table.raw_len() > 0 => table.sequence_values().collect(),
-            Value::Table(table) => table
-                .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
-                .collect(),
+            Value::Table(table) if table.raw_len() > 0 => {
+                collect_limited(lua, table.sequence_values())
+            }
+            Value::Table(table) => collect_limited(
+                lua,
+                (table.pairs::<T, Value<'lua>>()).map(|res| res.map(|(k, _)| k)),
+            ),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "HashSet",
@@ -1244,6 +1375,7 @@ impl<'lua, T: Eq + Hash + FromLua<'lua>, S: BuildHasher + Default> FromLua<'lua>
 
 impl<'lua, T: Ord + IntoLua<'lua>> IntoLua<'lua> for BTreeSet<T> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         Ok(Value::Table(lua.create_table_from(
             self.into_iter().map(|val| (val, true)),
@@ -1253,13 +1385,15 @@ impl<'lua, T: Ord + IntoLua<'lua>> IntoLua<'lua> for BTreeSet<T> {
 
 impl<'lua, T: Ord + FromLua<'lua>> FromLua<'lua> for BTreeSet<T> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) if table.raw_len() > 0 => table.sequence_values().collect(),
-            Value::Table(table) => table
-                .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
-                .collect(),
+            Value::Table(table) if table.raw_len() > 0 => {
+                collect_limited(lua, table.sequence_values())
+            }
+            Value::Table(table) => collect_limited(
+                lua,
+                (table.pairs::<T, Value<'lua>>()).map(|res| res.map(|(k, _)| k)),
+            ),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "BTreeSet",
@@ -1271,6 +1405,7 @@ impl<'lua, T: Ord + FromLua<'lua>> FromLua<'lua> for BTreeSet<T> {
 
 impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Option<T> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         match self {
             Some(val) => val.into_lua(lua),
@@ -1279,6 +1414,7 @@ impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Option<T> {
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         match self {
             Some(val) => val.push_into_stack(lua)?,
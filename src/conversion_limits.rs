@@ -0,0 +1,216 @@
+use std::cell::Cell;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Limits of recursive conversions of Lua tables.
+///
+/// Conversions of nested tables, such as `FromLua` for `Vec`, `HashMap` and other collections,
+/// serde deserialization ([`LuaSerdeExt::from_value`]), serialization of Lua values,
+/// [`LuaSerdeExt::to_value`] and [`Table::deep_clone`], fail with [`Error::TooDeep`] or
+/// [`Error::TooLarge`] when exceeding the limits, instead of overflowing the stack or stalling on
+/// adversarial values passed by scripts.
+///
+/// Set using [`Lua::set_conversion_limits`]. By default the nesting depth is limited to 128
+/// (like `serde_json` does), while the number of elements is not limited.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use mlua::{ConversionLimits, Error, Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.set_conversion_limits(ConversionLimits::new().max_depth(Some(2)));
+/// let value = lua.load("{ a = { b = { c = {} } } }").eval()?;
+/// let result = lua.unpack::<HashMap<String, HashMap<String, mlua::Value>>>(value);
+/// assert!(result.is_ok());
+/// let value = lua.load("{ a = { b = { c = {} } } }").eval()?;
+/// let result = lua.unpack::<HashMap<String, HashMap<String, HashMap<String, mlua::Value>>>>(value);
+/// assert!(matches!(result, Err(Error::TooDeep { max_depth: 2 })));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`LuaSerdeExt::from_value`]: crate::LuaSerdeExt::from_value
+/// [`LuaSerdeExt::to_value`]: crate::LuaSerdeExt::to_value
+/// [`Table::deep_clone`]: crate::Table::deep_clone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConversionLimits {
+    /// Maximum nesting depth of tables, the outermost table being at depth 1.
+    ///
+    /// Default: **128**
+    pub max_depth: Option<usize>,
+
+    /// Maximum number of table elements converted by a single conversion, including elements of
+    /// nested tables.
+    ///
+    /// Default: **none**
+    pub max_elements: Option<usize>,
+}
+
+impl Default for ConversionLimits {
+    fn default() -> Self {
+        ConversionLimits::new()
+    }
+}
+
+impl ConversionLimits {
+    /// Returns a new instance of `ConversionLimits` with default limits.
+    pub const fn new() -> Self {
+        ConversionLimits {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_elements: None,
+        }
+    }
+
+    /// Returns a new instance of `ConversionLimits` without limits.
+    pub const fn unlimited() -> Self {
+        ConversionLimits {
+            max_depth: None,
+            max_elements: None,
+        }
+    }
+
+    /// Sets [`max_depth`] option.
+    ///
+    /// [`max_depth`]: #structfield.max_depth
+    #[must_use]
+    pub const fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets [`max_elements`] option.
+    ///
+    /// [`max_elements`]: #structfield.max_elements
+    #[must_use]
+    pub const fn max_elements(mut self, elements: Option<usize>) -> Self {
+        self.max_elements = elements;
+        self
+    }
+
+    const fn is_unlimited(&self) -> bool {
+        self.max_depth.is_none() && self.max_elements.is_none()
+    }
+}
+
+// Per-state limits and progress of the current conversion, stored in `ExtraData`
+#[derive(Default)]
+pub(crate) struct ConversionCounter {
+    limits: Cell<ConversionLimits>,
+    depth: Cell<usize>,
+    elements: Cell<usize>,
+    // Error passed through a serializer, which keeps only error messages (see `ser_error`)
+    #[cfg(feature = "serialize")]
+    error: Cell<Option<Error>>,
+}
+
+// Conversion of a nested table, counting its depth until dropped
+pub(crate) struct NestedConversion<'a> {
+    counter: Option<&'a ConversionCounter>,
+}
+
+impl<'a> NestedConversion<'a> {
+    pub(crate) fn enter(lua: &'a Lua) -> Result<Self> {
+        Self::enter_with_max_depth(lua, None)
+    }
+
+    // Same as `enter`, additionally limiting the depth to `max_depth` (if set)
+    pub(crate) fn enter_with_max_depth(lua: &'a Lua, max_depth: Option<usize>) -> Result<Self> {
+        let counter = lua.conversion_counter();
+        let mut limits = counter.limits.get();
+        if let Some(max_depth) = max_depth {
+            limits.max_depth = Some(limits.max_depth.map_or(max_depth, |d| d.min(max_depth)));
+        }
+        if limits.is_unlimited() {
+            return Ok(NestedConversion { counter: None });
+        }
+
+        let depth = counter.depth.get() + 1;
+        if depth == 1 {
+            // A new conversion
+            counter.elements.set(0);
+        }
+        if let Some(max_depth) = limits.max_depth {
+            if depth > max_depth {
+                return Err(Error::TooDeep { max_depth });
+            }
+        }
+        counter.depth.set(depth);
+        Ok(NestedConversion {
+            counter: Some(counter),
+        })
+    }
+
+    // Counts an element of the table
+    #[inline]
+    pub(crate) fn count_element(&self) -> Result<()> {
+        if let Some(counter) = self.counter {
+            let elements = counter.elements.get() + 1;
+            if let Some(max_elements) = counter.limits.get().max_elements {
+                if elements > max_elements {
+                    return Err(Error::TooLarge { max_elements });
+                }
+            }
+            counter.elements.set(elements);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NestedConversion<'_> {
+    fn drop(&mut self) {
+        if let Some(counter) = self.counter {
+            counter.depth.set(counter.depth.get() - 1);
+        }
+    }
+}
+
+// Collects the converted elements of a table, applying the conversion limits
+pub(crate) fn collect_limited<T, C>(lua: &Lua, items: impl Iterator<Item = Result<T>>) -> Result<C>
+where
+    C: FromIterator<T>,
+{
+    let nested = NestedConversion::enter(lua)?;
+    items
+        .map(|item| {
+            nested.count_element()?;
+            item
+        })
+        .collect()
+}
+
+// Converts an error to a serializer error, keeping the original error to be restored by
+// `restore_error` once the serializer returns
+#[cfg(feature = "serialize")]
+pub(crate) fn ser_error<E: serde::ser::Error>(lua: &Lua, err: Error) -> E {
+    let msg = err.to_string();
+    lua.conversion_counter().error.set(Some(err));
+    E::custom(msg)
+}
+
+// Returns the error converted by `ser_error` if `err` was created from it
+#[cfg(feature = "serialize")]
+pub(crate) fn restore_error(lua: &Lua, err: Error) -> Error {
+    match (lua.conversion_counter().error.take(), err) {
+        (Some(orig), Error::SerializeError(msg)) if orig.to_string() == msg => orig,
+        (_, err) => err,
+    }
+}
+
+impl Lua {
+    /// Sets limits of recursive conversions of Lua tables, returning the previous limits.
+    ///
+    /// See [`ConversionLimits`] for details.
+    pub fn set_conversion_limits(&self, limits: ConversionLimits) -> ConversionLimits {
+        self.conversion_counter().limits.replace(limits)
+    }
+
+    /// Returns limits of recursive conversions set by [`Lua::set_conversion_limits`].
+    pub fn conversion_limits(&self) -> ConversionLimits {
+        self.conversion_counter().limits.get()
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::Result;
+use crate::lua::Lua;
+
+#[cfg(not(feature = "trace_conversions"))]
+use std::marker::PhantomData;
+
+#[cfg(feature = "trace_conversions")]
+use {
+    crate::types::ConversionTraceCallback,
+    std::any::type_name,
+    std::cell::Cell,
+    std::time::{Duration, Instant},
+};
+
+/// Direction of a traced value conversion.
+///
+/// Requires `feature = "trace_conversions"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConversionDirection {
+    /// A Rust value was converted to Lua values ([`IntoLua`] or [`IntoLuaMulti`]).
+    ///
+    /// [`IntoLua`]: crate::IntoLua
+    /// [`IntoLuaMulti`]: crate::IntoLuaMulti
+    IntoLua,
+    /// Lua values were converted to a Rust value ([`FromLua`] or [`FromLuaMulti`]).
+    ///
+    /// [`FromLua`]: crate::FromLua
+    /// [`FromLuaMulti`]: crate::FromLuaMulti
+    FromLua,
+}
+
+/// Record of a value conversion, passed to the callback set by [`Lua::set_conversion_tracer`].
+///
+/// Only the outermost conversion is reported: converting a `(String, Vec<i64>)` tuple produces a
+/// single trace covering the conversion of all its elements.
+///
+/// Requires `feature = "trace_conversions"`
+///
+/// [`Lua::set_conversion_tracer`]: crate::Lua::set_conversion_tracer
+#[cfg(feature = "trace_conversions")]
+#[derive(Clone, Debug)]
+pub struct ConversionTrace {
+    /// Direction of the conversion.
+    pub direction: ConversionDirection,
+    /// Name of the Rust type, as returned by [`std::any::type_name`].
+    pub type_name: &'static str,
+    /// Number of Lua values produced or consumed (zero if the conversion failed).
+    pub values: usize,
+    /// Change of the memory used by Lua during the conversion, in bytes.
+    ///
+    /// Can be negative if the garbage collector ran in the meantime.
+    pub memory_delta: isize,
+    /// Time spent in the conversion, including nested conversions.
+    pub duration: Duration,
+}
+
+// Per-state tracing state, stored in `ExtraData`
+#[cfg(feature = "trace_conversions")]
+#[derive(Default)]
+pub(crate) struct ConversionTracer {
+    pub(crate) callback: Option<ConversionTraceCallback>,
+    // Whether a conversion is in progress, to report only the outermost one
+    active: Cell<bool>,
+}
+
+// Runs the conversion `f` of a `T` value, reporting it to the tracer (if any).
+// Compiles to a plain call of `f` without the `trace_conversions` feature.
+#[inline(always)]
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+pub(crate) fn trace<T: ?Sized, R>(
+    lua: &Lua,
+    direction: ConversionDirection,
+    f: impl FnOnce() -> Result<R>,
+    values: impl FnOnce(&R) -> usize,
+) -> Result<R> {
+    #[cfg(feature = "stack_diagnostics")]
+    let f = {
+        let location = std::panic::Location::caller();
+        move || unsafe { crate::util::with_stack_caller(lua.state(), location, f) }
+    };
+
+    #[cfg(feature = "trace_conversions")]
+    {
+        let tracer = lua.conversion_tracer();
+        let callback = match tracer.callback {
+            Some(ref callback) if !tracer.active.get() => callback.clone(),
+            _ => return f(),
+        };
+
+        // Stays active while running the callback, to not trace its own conversions
+        tracer.active.set(true);
+        let _guard = ActiveGuard(&tracer.active);
+        let used_memory = lua.used_memory();
+        let start = Instant::now();
+        let result = f();
+        let trace = ConversionTrace {
+            direction,
+            type_name: type_name::<T>(),
+            values: result.as_ref().map(values).unwrap_or(0),
+            memory_delta: lua.used_memory() as isize - used_memory as isize,
+            duration: start.elapsed(),
+        };
+        callback(&trace);
+        result
+    }
+    #[cfg(not(feature = "trace_conversions"))]
+    {
+        let _ = (lua, direction, values, PhantomData::<T>);
+        f()
+    }
+}
+
+// Resets the tracer even if the conversion or the callback panics
+#[cfg(feature = "trace_conversions")]
+struct ActiveGuard<'a>(&'a Cell<bool>);
+
+#[cfg(feature = "trace_conversions")]
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
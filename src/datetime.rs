@@ -0,0 +1,252 @@
+use std::fmt;
+use std::string::String as StdString;
+
+use time::format_description::{self, well_known::Rfc3339, OwnedFormatItem};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::userdata::{MetaMethod, UserData, UserDataFields, UserDataMethods};
+use crate::value::{FromLua, Value};
+
+/// Date and time with a UTC offset, used by the `datetime` module.
+///
+/// Created by [`Lua::create_datetime_module`] functions and can be passed between Rust and Lua.
+///
+/// Requires `feature = "time"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime(pub OffsetDateTime);
+
+impl From<OffsetDateTime> for DateTime {
+    #[inline]
+    fn from(dt: OffsetDateTime) -> Self {
+        DateTime(dt)
+    }
+}
+
+impl From<DateTime> for OffsetDateTime {
+    #[inline]
+    fn from(dt: DateTime) -> Self {
+        dt.0
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.format(&Rfc3339) {
+            Ok(s) => write!(f, "{s}"),
+            Err(_) => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl DateTime {
+    fn format(&self, fmt: Option<&str>) -> Result<StdString> {
+        match fmt {
+            Some(fmt) => self.0.format(&parse_format(fmt)?),
+            None => self.0.format(&Rfc3339),
+        }
+        .map_err(|err| Error::RuntimeError(err.to_string()))
+    }
+
+    fn parse(s: &str, fmt: Option<&str>) -> Result<Self> {
+        let res = match fmt {
+            Some(fmt) => {
+                let fmt = parse_format(fmt)?;
+                // Formats without offset are treated as UTC
+                OffsetDateTime::parse(s, &fmt)
+                    .or_else(|_| PrimitiveDateTime::parse(s, &fmt).map(|dt| dt.assume_utc()))
+            }
+            None => OffsetDateTime::parse(s, &Rfc3339),
+        };
+        res.map(DateTime)
+            .map_err(|err| Error::RuntimeError(format!("cannot parse '{s}': {err}")))
+    }
+
+    fn checked_add(&self, seconds: f64) -> Result<Self> {
+        let duration = Duration::checked_seconds_f64(seconds)
+            .ok_or_else(|| Error::RuntimeError(format!("invalid duration: {seconds}")))?;
+        self.0
+            .checked_add(duration)
+            .map(DateTime)
+            .ok_or_else(|| Error::RuntimeError("datetime out of range".to_string()))
+    }
+}
+
+fn parse_format(fmt: &str) -> Result<OwnedFormatItem> {
+    format_description::parse_owned::<2>(fmt)
+        .map_err(|err| Error::RuntimeError(format!("invalid format '{fmt}': {err}")))
+}
+
+// Accepts "Z", "UTC", "+HH:MM", "-HH:MM" or a number of seconds
+fn parse_offset(value: Value) -> Result<UtcOffset> {
+    let invalid =
+        |value: &dyn fmt::Display| Error::RuntimeError(format!("invalid offset '{value}'"));
+    match value {
+        Value::String(s) => {
+            let s = s.to_str()?;
+            if s == "Z" || s == "UTC" {
+                return Ok(UtcOffset::UTC);
+            }
+            let fmt = parse_format("[offset_hour sign:mandatory]:[offset_minute]")?;
+            UtcOffset::parse(s, &fmt).map_err(|_| invalid(&s))
+        }
+        // Out of range values saturate and are rejected by `UtcOffset`
+        Value::Integer(seconds) => {
+            UtcOffset::from_whole_seconds(seconds as f64 as i32).map_err(|_| invalid(&seconds))
+        }
+        Value::Number(seconds) if seconds.fract() == 0.0 => {
+            UtcOffset::from_whole_seconds(seconds as i32).map_err(|_| invalid(&seconds))
+        }
+        value => Err(Error::FromLuaConversionError {
+            from: value.type_name(),
+            to: "UtcOffset",
+            message: Some("expected string or number".to_string()),
+        }),
+    }
+}
+
+impl<'lua> FromLua<'lua> for DateTime {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::UserData(ud) => Ok(*ud.borrow::<Self>()?),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "DateTime",
+                message: None,
+            }),
+        }
+    }
+}
+
+impl UserData for DateTime {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("year", |_, this| Ok(this.0.year()));
+        fields.add_field_method_get("month", |_, this| Ok(u8::from(this.0.month())));
+        fields.add_field_method_get("day", |_, this| Ok(this.0.day()));
+        fields.add_field_method_get("hour", |_, this| Ok(this.0.hour()));
+        fields.add_field_method_get("minute", |_, this| Ok(this.0.minute()));
+        fields.add_field_method_get("second", |_, this| Ok(this.0.second()));
+        fields.add_field_method_get("nanosecond", |_, this| Ok(this.0.nanosecond()));
+        // ISO weekday, Monday is 1
+        fields.add_field_method_get("weekday", |_, this| {
+            Ok(this.0.weekday().number_from_monday())
+        });
+        fields.add_field_method_get("yearday", |_, this| Ok(this.0.ordinal()));
+        fields.add_field_method_get("offset", |_, this| Ok(this.0.offset().whole_seconds()));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("format", |_, this, fmt: Option<StdString>| {
+            this.format(fmt.as_deref())
+        });
+        methods.add_method("timestamp", |_, this, ()| Ok(this.0.unix_timestamp()));
+        methods.add_method("to_offset", |_, this, offset: Value| {
+            let offset = parse_offset(offset)?;
+            (this.0.checked_to_offset(offset))
+                .map(DateTime)
+                .ok_or_else(|| Error::RuntimeError("datetime out of range".to_string()))
+        });
+        methods.add_method("to_utc", |_, this, ()| {
+            (this.0.checked_to_offset(UtcOffset::UTC))
+                .map(DateTime)
+                .ok_or_else(|| Error::RuntimeError("datetime out of range".to_string()))
+        });
+        methods.add_method("add", |_, this, seconds: f64| this.checked_add(seconds));
+
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| Ok(this.to_string()));
+        methods.add_meta_method(
+            MetaMethod::Eq,
+            |_, this, other: DateTime| Ok(*this == other),
+        );
+        methods.add_meta_method(MetaMethod::Lt, |_, this, other: DateTime| Ok(*this < other));
+        methods.add_meta_method(
+            MetaMethod::Le,
+            |_, this, other: DateTime| Ok(*this <= other),
+        );
+        methods.add_meta_function(MetaMethod::Add, |_, (a, b): (Value, Value)| {
+            // Addition is commutative, number of seconds can be on any side
+            match (a, b) {
+                (Value::UserData(ud), seconds) | (seconds, Value::UserData(ud)) => {
+                    let seconds = match seconds {
+                        Value::Integer(i) => i as f64,
+                        Value::Number(n) => n,
+                        value => {
+                            return Err(Error::RuntimeError(format!(
+                                "attempt to add a '{}' to a datetime",
+                                value.type_name()
+                            )))
+                        }
+                    };
+                    ud.borrow::<DateTime>()?.checked_add(seconds)
+                }
+                _ => Err(Error::RuntimeError("invalid datetime addition".to_string())),
+            }
+        });
+        // Subtracting a datetime returns number of seconds, subtracting a number returns datetime
+        methods.add_meta_method(MetaMethod::Sub, |lua, this, other: Value| match other {
+            Value::UserData(ud) => {
+                let other = ud.borrow::<DateTime>()?;
+                Ok(Value::Number((this.0 - other.0).as_seconds_f64()))
+            }
+            other => {
+                let seconds = f64::from_lua(other, lua)?;
+                lua.create_userdata(this.checked_add(-seconds)?)
+                    .map(Value::UserData)
+            }
+        });
+    }
+}
+
+pub(crate) fn create_datetime_module(lua: &Lua) -> Result<Table> {
+    let module = lua.create_table_with_capacity(0, 4)?;
+
+    module.raw_set(
+        "now",
+        lua.create_function(|_, ()| Ok(DateTime(OffsetDateTime::now_utc())))?,
+    )?;
+
+    module.raw_set(
+        "parse",
+        lua.create_function(|_, (s, fmt): (StdString, Option<StdString>)| {
+            DateTime::parse(&s, fmt.as_deref())
+        })?,
+    )?;
+
+    module.raw_set(
+        "from_timestamp",
+        lua.create_function(|_, timestamp: f64| {
+            let nanos = (timestamp * 1e9) as i128;
+            OffsetDateTime::from_unix_timestamp_nanos(nanos)
+                .map(DateTime)
+                .map_err(|err| Error::RuntimeError(err.to_string()))
+        })?,
+    )?;
+
+    module.raw_set(
+        "new",
+        lua.create_function(|_, args: Table| {
+            let month = Month::try_from(args.get::<_, u8>("month")?)
+                .map_err(|err| Error::RuntimeError(err.to_string()))?;
+            let date = Date::from_calendar_date(args.get("year")?, month, args.get("day")?)
+                .map_err(|err| Error::RuntimeError(err.to_string()))?;
+            let time = Time::from_hms_nano(
+                args.get::<_, Option<u8>>("hour")?.unwrap_or(0),
+                args.get::<_, Option<u8>>("minute")?.unwrap_or(0),
+                args.get::<_, Option<u8>>("second")?.unwrap_or(0),
+                args.get::<_, Option<u32>>("nanosecond")?.unwrap_or(0),
+            )
+            .map_err(|err| Error::RuntimeError(err.to_string()))?;
+            let offset = match args.get::<_, Value>("offset")? {
+                Value::Nil => UtcOffset::UTC,
+                offset => parse_offset(offset)?,
+            };
+            Ok(DateTime(
+                PrimitiveDateTime::new(date, time).assume_offset(offset),
+            ))
+        })?,
+    )?;
+
+    Ok(module)
+}
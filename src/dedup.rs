@@ -0,0 +1,101 @@
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::table::Table;
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+use {crate::string::String, crate::value::Value, rustc_hash::FxHashSet};
+
+// Strings up to this length are always interned by Lua 5.2+ (`LUAI_MAXSHORTLEN`)
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+const MAX_SHORT_LEN: usize = 40;
+
+impl Lua {
+    /// Replaces duplicate strings in `table` (and the tables reachable from it) with shared
+    /// instances, returning the number of bytes of the replaced strings.
+    ///
+    /// Lua 5.2+ interns only short strings, so data loaded from redundant sources (e.g. JSON or
+    /// CSV files) can hold many copies of the same long string. After deduplication, the copies
+    /// are freed by the garbage collector unless they are referenced elsewhere.
+    ///
+    /// Only values are replaced (table keys cannot be), metatables are not visited.
+    /// Always returns `0` for Lua 5.1, LuaJIT and Luau, where all strings are interned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let rows = lua.create_table()?;
+    /// for _ in 0..100 {
+    ///     let description = "a long description repeated in every row of the data file";
+    ///     rows.push(lua.create_table_from([("description", description)])?)?;
+    /// }
+    ///
+    /// let saved = lua.dedup_strings(&rows)?;
+    /// # #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    /// assert_eq!(saved, 99 * 57);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dedup_strings(&self, table: &Table) -> Result<usize> {
+        #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+        return {
+            let _ = table;
+            Ok(0)
+        };
+
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        {
+            // Lua compares string keys by content, so this table maps every string to its
+            // first seen instance
+            let instances = self.create_table()?;
+            let mut saved = 0;
+            let mut visited = FxHashSet::default();
+            let mut queue = vec![table.clone()];
+            while let Some(table) = queue.pop() {
+                if !visited.insert(table.to_pointer()) {
+                    continue;
+                }
+                table.for_each(|key: Value, value: Value| {
+                    if let Value::String(key) = &key {
+                        instance(&instances, key.clone())?;
+                    }
+                    match value {
+                        Value::String(value) => {
+                            let bytes = value.as_bytes();
+                            let (ptr, len) = (bytes.as_ptr(), bytes.len());
+                            let instance = instance(&instances, value)?;
+                            // Instances are compared by their contents address (`lua_topointer`
+                            // does not support strings before Lua 5.4)
+                            if instance.as_bytes().as_ptr() != ptr {
+                                // Assigning to existing fields is allowed during traversal
+                                table.raw_set(key, instance)?;
+                                saved += len;
+                            }
+                        }
+                        Value::Table(value) => queue.push(value),
+                        _ => {}
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(saved)
+        }
+    }
+}
+
+// Returns the shared instance of the string `s`, registering it if it's the first one
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+fn instance<'lua>(instances: &Table<'lua>, s: String<'lua>) -> Result<String<'lua>> {
+    if s.as_bytes().len() <= MAX_SHORT_LEN {
+        return Ok(s);
+    }
+    match instances.raw_get::<_, Option<String>>(s.clone())? {
+        Some(instance) => Ok(instance),
+        None => {
+            instances.raw_set(s.clone(), s.clone())?;
+            Ok(s)
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::Cell;
+use std::mem;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::types::Callback;
+
+#[cfg(feature = "async")]
+use {crate::types::AsyncCallback, crate::value::MultiValue, futures_util::future};
+
+/// What happens when a deprecated userdata method is called.
+///
+/// See [`UserDataRegistry::deprecate_method`].
+///
+/// [`UserDataRegistry::deprecate_method`]: crate::UserDataRegistry::deprecate_method
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeprecationPolicy {
+    /// Notify the deprecation handler on the first call only, then call the method.
+    WarnOnce,
+    /// Notify the deprecation handler on every call, then call the method.
+    Log,
+    /// Notify the deprecation handler and fail the call with a runtime error.
+    Error,
+}
+
+/// Call of a deprecated userdata method, passed to the deprecation handler.
+///
+/// See [`Lua::set_deprecation_handler`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Name of the method as `Type.method`.
+    pub name: StdString,
+    /// Message attached to the deprecation (eg. the replacement to use).
+    pub message: StdString,
+    /// Policy of the deprecation.
+    pub policy: DeprecationPolicy,
+    /// Number of calls of the method so far, including this one.
+    pub calls: u64,
+}
+
+// Deprecation of a method shared by its callback wrapper
+struct DeprecatedMethod {
+    name: StdString,
+    message: StdString,
+    policy: DeprecationPolicy,
+    calls: Cell<u64>,
+}
+
+impl DeprecatedMethod {
+    // Counts the call and notifies the handler, returns an error if the method must not be called
+    fn check(&self, lua: &Lua) -> Result<()> {
+        let calls = self.calls.get() + 1;
+        self.calls.set(calls);
+        if self.policy != DeprecationPolicy::WarnOnce || calls == 1 {
+            if let Some(handler) = lua.deprecation_handler() {
+                let deprecation = Deprecation {
+                    name: self.name.clone(),
+                    message: self.message.clone(),
+                    policy: self.policy,
+                    calls,
+                };
+                handler(lua, &deprecation)?;
+            }
+        }
+        match self.policy {
+            DeprecationPolicy::Error => Err(Error::runtime(format!(
+                "{} is deprecated: {}",
+                self.name, self.message
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn deprecate_callback<'lua>(
+    callback: Callback<'lua, 'static>,
+    name: StdString,
+    message: StdString,
+    policy: DeprecationPolicy,
+) -> Callback<'lua, 'static> {
+    let method = DeprecatedMethod {
+        name,
+        message,
+        policy,
+        calls: Cell::new(0),
+    };
+    // Callbacks are called with the `Lua` they are created with (see `Lua::create_callback`), so
+    // the lifetime can be erased to move the callback into the wrapper
+    let callback: Callback<'static, 'static> = unsafe { mem::transmute(callback) };
+    Box::new(move |lua, nargs| {
+        method.check(lua)?;
+        callback(unsafe { mem::transmute::<&Lua, &'static Lua>(lua) }, nargs)
+    })
+}
+
+#[cfg(feature = "async")]
+pub(crate) fn deprecate_async_callback<'lua>(
+    callback: AsyncCallback<'lua, 'static>,
+    name: StdString,
+    message: StdString,
+    policy: DeprecationPolicy,
+) -> AsyncCallback<'lua, 'static> {
+    let method = DeprecatedMethod {
+        name,
+        message,
+        policy,
+        calls: Cell::new(0),
+    };
+    // See `deprecate_callback`
+    let callback: AsyncCallback<'static, 'static> = unsafe { mem::transmute(callback) };
+    Box::new(move |lua, args| match method.check(lua) {
+        Ok(()) => {
+            let lua = unsafe { mem::transmute::<&Lua, &'static Lua>(lua) };
+            let args = unsafe { mem::transmute::<MultiValue, MultiValue<'static>>(args) };
+            callback(lua, args)
+        }
+        Err(err) => Box::pin(future::err(err)),
+    })
+}
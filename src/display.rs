@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::os::raw::c_void;
+use std::ptr;
+use std::string::String as StdString;
+
+use crate::error::Result;
+use crate::function::Function;
+use crate::string::String;
+use crate::table::Table;
+use crate::thread::Thread;
+use crate::types::LuaRef;
+use crate::userdata::AnyUserData;
+use crate::util::{check_stack, StackGuard};
+use crate::value::Value;
+
+/// Formatter of Lua values, with options to expand nested tables.
+///
+/// Returned by [`Value::display`] and [`Table::display`]. With the default options it produces
+/// the same text as the Lua `tostring` function (taking into account `__tostring` and `__name`
+/// metafields), which is also what the `Display` implementations of [`Table`], [`Function`] and
+/// [`AnyUserData`] print.
+///
+/// Tables without `__tostring` and `__name` metafields are expanded up to the given [`depth`],
+/// as a single-line table constructor (`{1, 2, key = "value"}`), showing at most [`width`]
+/// entries of each table.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, Table};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let t: Table = lua.load(r#"{10, 20, name = "point", pos = {x = 1, y = 2}}"#).eval()?;
+/// assert!(t.to_string().starts_with("table: "));
+/// assert_eq!(
+///     t.display().depth(2).to_string(),
+///     r#"{10, 20, name = "point", pos = {x = 1, y = 2}}"#
+/// );
+/// assert_eq!(t.display().depth(1).width(3).to_string(), r#"{10, 20, name = "point", ...}"#);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`depth`]: ValueDisplay::depth
+/// [`width`]: ValueDisplay::width
+/// [`Function`]: crate::Function
+/// [`AnyUserData`]: crate::AnyUserData
+#[derive(Clone)]
+pub struct ValueDisplay<'lua> {
+    value: Value<'lua>,
+    depth: usize,
+    width: usize,
+}
+
+impl<'lua> ValueDisplay<'lua> {
+    pub(crate) fn new(value: Value<'lua>) -> Self {
+        ValueDisplay {
+            value,
+            depth: 0,
+            width: usize::MAX,
+        }
+    }
+
+    /// Sets the number of nested table levels to expand.
+    ///
+    /// Default: 0 (tables are printed as `tostring` does)
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the maximum number of entries to print for each expanded table.
+    ///
+    /// Remaining entries are replaced by `...`.
+    ///
+    /// Default: unlimited
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    fn fmt_value(
+        &self,
+        fmt: &mut fmt::Formatter,
+        value: &Value,
+        depth: usize,
+        visited: &mut HashSet<*const c_void>,
+    ) -> fmt::Result {
+        match value {
+            Value::Table(t)
+                if depth > 0 && is_expandable(t) && !visited.contains(&t.to_pointer()) =>
+            {
+                visited.insert(t.to_pointer());
+                let result = self.fmt_table(fmt, t, depth, visited);
+                visited.remove(&t.to_pointer());
+                result
+            }
+            Value::String(s) => write!(fmt, "{}", s.to_string_lossy()),
+            Value::Table(Table(r))
+            | Value::Function(Function(r))
+            | Value::Thread(Thread(r, ..))
+            | Value::UserData(AnyUserData(r, ..)) => fmt_ref(fmt, r, value.type_name()),
+            value => match value.to_string() {
+                Ok(s) => write!(fmt, "{s}"),
+                Err(_) => Err(fmt::Error),
+            },
+        }
+    }
+
+    fn fmt_table(
+        &self,
+        fmt: &mut fmt::Formatter,
+        t: &Table,
+        depth: usize,
+        visited: &mut HashSet<*const c_void>,
+    ) -> fmt::Result {
+        let mut pairs = t
+            .clone()
+            .pairs::<Value, Value>()
+            .flatten()
+            .collect::<Vec<_>>();
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        write!(fmt, "{{")?;
+        let mut next_index = 1;
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                write!(fmt, ", ")?;
+            }
+            if i == self.width {
+                write!(fmt, "...")?;
+                break;
+            }
+            match key {
+                // Sequence part
+                Value::Integer(i) if *i == next_index => next_index += 1,
+                Value::String(s) if is_identifier(s) => write!(fmt, "{} = ", s.to_string_lossy())?,
+                key => {
+                    write!(fmt, "[")?;
+                    self.fmt_nested(fmt, key, depth - 1, visited)?;
+                    write!(fmt, "] = ")?;
+                }
+            }
+            self.fmt_nested(fmt, value, depth - 1, visited)?;
+        }
+        write!(fmt, "}}")
+    }
+
+    // Strings are quoted inside of tables
+    fn fmt_nested(
+        &self,
+        fmt: &mut fmt::Formatter,
+        value: &Value,
+        depth: usize,
+        visited: &mut HashSet<*const c_void>,
+    ) -> fmt::Result {
+        match value {
+            Value::String(s) => write!(fmt, "{:?}", s.to_string_lossy()),
+            value => self.fmt_value(fmt, value, depth, visited),
+        }
+    }
+}
+
+impl fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_value(fmt, &self.value, self.depth, &mut HashSet::new())
+    }
+}
+
+impl fmt::Debug for ValueDisplay<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        (fmt.debug_struct("ValueDisplay"))
+            .field("value", &self.value)
+            .field("depth", &self.depth)
+            .field("width", &self.width)
+            .finish()
+    }
+}
+
+// Tables with a custom string representation are not expanded
+fn is_expandable(t: &Table) -> bool {
+    match t.get_metatable() {
+        Some(mt) => !["__tostring", "__name"]
+            .into_iter()
+            .any(|name| mt.contains_key(name).unwrap_or(false)),
+        None => true,
+    }
+}
+
+fn is_identifier(s: &String) -> bool {
+    let bytes = s.as_bytes();
+    match bytes.first() {
+        Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {}
+        _ => return false,
+    }
+    bytes
+        .iter()
+        .all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+}
+
+// Formats a reference type as `tostring` does, falling back to the type name and address if
+// the `__tostring` metamethod fails
+pub(crate) fn fmt_ref(fmt: &mut fmt::Formatter, r: &LuaRef, type_name: &str) -> fmt::Result {
+    match tostring(r) {
+        Ok(s) => write!(fmt, "{s}"),
+        Err(_) => write!(fmt, "{type_name}: {:?}", r.to_pointer()),
+    }
+}
+
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+fn tostring(r: &LuaRef) -> Result<StdString> {
+    let lua = r.lua;
+    unsafe {
+        let state = lua.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 3)?;
+
+        lua.push_ref(r);
+        protect_lua!(state, 1, 1, fn(state) {
+            ffi::luaL_tolstring(state, -1, ptr::null_mut());
+        })?;
+        Ok(String(lua.pop_ref()).to_string_lossy().into_owned())
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::string::String as StdString;
+
+use crate::types::MaybeSend;
+
+/// Source of environment variables visible to scripts through `os.getenv`.
+///
+/// Installed using [`Lua::set_env_provider`] to prevent leaking the process environment
+/// (which often contains secrets) to untrusted code.
+///
+/// Implemented for maps of variable names to values and for [`DenyEnv`].
+///
+/// [`Lua::set_env_provider`]: crate::Lua::set_env_provider
+pub trait EnvProvider: MaybeSend + 'static {
+    /// Returns value of the environment variable `name`, or `None` if it's not available.
+    fn var(&self, name: &str) -> Option<StdString>;
+}
+
+/// An [`EnvProvider`] that hides all environment variables.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DenyEnv;
+
+impl EnvProvider for DenyEnv {
+    #[inline]
+    fn var(&self, _name: &str) -> Option<StdString> {
+        None
+    }
+}
+
+impl<S: BuildHasher + MaybeSend + 'static> EnvProvider for HashMap<StdString, StdString, S> {
+    #[inline]
+    fn var(&self, name: &str) -> Option<StdString> {
+        self.get(name).cloned()
+    }
+}
+
+impl EnvProvider for BTreeMap<StdString, StdString> {
+    #[inline]
+    fn var(&self, name: &str) -> Option<StdString> {
+        self.get(name).cloned()
+    }
+}
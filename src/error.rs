@@ -7,7 +7,10 @@ use std::str::Utf8Error;
 use std::string::String as StdString;
 use std::sync::Arc;
 
+use crate::lua_id::LuaId;
 use crate::private::Sealed;
+use crate::quota::QuotaResource;
+use crate::util::short_type_name;
 
 /// Error type returned by `mlua` methods.
 #[derive(Debug, Clone)]
@@ -47,6 +50,23 @@ pub enum Error {
     /// This error can only happen when Lua state was not created by us and does not have the
     /// custom allocator attached.
     MemoryLimitNotAvailable,
+    /// A resource budget of a [`Quota`] has been exhausted.
+    ///
+    /// [`Quota`]: crate::Quota
+    QuotaExceeded {
+        /// The exhausted resource.
+        resource: QuotaResource,
+        /// The budget of the resource.
+        limit: u64,
+    },
+    /// A script requested the process to exit (e.g. by calling `os.exit`), and the request was
+    /// converted into an error by a handler set using [`Lua::on_exit_request`].
+    ///
+    /// [`Lua::on_exit_request`]: crate::Lua::on_exit_request
+    ExitRequested {
+        /// The requested exit code.
+        code: i32,
+    },
     /// A mutable callback has triggered Lua code that has called the same mutable callback again.
     ///
     /// This is an error because a mutable callback can only be borrowed mutably once.
@@ -143,6 +163,15 @@ pub enum Error {
     /// [`AnyUserData`]: crate::AnyUserData
     /// [`UserData`]: crate::UserData
     UserDataBorrowMutError,
+    /// An [`AnyUserData`] borrow failed because the value is locked.
+    ///
+    /// Returned by [`AnyUserData::try_borrow_for`] and [`AnyUserData::try_borrow_mut_for`] when
+    /// the value is held by someone else for longer than the given timeout.
+    ///
+    /// [`AnyUserData`]: crate::AnyUserData
+    /// [`AnyUserData::try_borrow_for`]: crate::AnyUserData::try_borrow_for
+    /// [`AnyUserData::try_borrow_mut_for`]: crate::AnyUserData::try_borrow_mut_for
+    WouldBlock,
     /// A [`MetaMethod`] operation is restricted (typically for `__gc` or `__metatable`).
     ///
     /// [`MetaMethod`]: crate::MetaMethod
@@ -162,6 +191,13 @@ pub enum Error {
     ///
     /// [`RegistryKey`]: crate::RegistryKey
     MismatchedRegistryKey,
+    /// A value (eg. a table or userdata) created by a different Lua state was used.
+    MismatchedLuaState {
+        /// Identifier of the state the value was used in.
+        expected: LuaId,
+        /// Identifier of the state that created the value.
+        found: LuaId,
+    },
     /// A Rust callback returned `Err`, raising the contained `Error` as a Lua error.
     CallbackError {
         /// Lua call stack backtrace.
@@ -182,6 +218,24 @@ pub enum Error {
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
     DeserializeError(StdString),
+    /// A conversion exceeded the maximum nesting depth of tables.
+    ///
+    /// See [`ConversionLimits::max_depth`].
+    ///
+    /// [`ConversionLimits::max_depth`]: crate::ConversionLimits::max_depth
+    TooDeep {
+        /// The maximum allowed depth.
+        max_depth: usize,
+    },
+    /// A conversion exceeded the maximum number of table elements.
+    ///
+    /// See [`ConversionLimits::max_elements`].
+    ///
+    /// [`ConversionLimits::max_elements`]: crate::ConversionLimits::max_elements
+    TooLarge {
+        /// The maximum allowed number of elements.
+        max_elements: usize,
+    },
     /// A custom error.
     ///
     /// This can be used for returning user-defined errors from callbacks.
@@ -221,6 +275,10 @@ impl fmt::Display for Error {
             Error::MemoryLimitNotAvailable => {
                 write!(fmt, "setting memory limit is not available")
             }
+            Error::QuotaExceeded { resource, limit } => {
+                write!(fmt, "{resource} quota of {limit} exceeded")
+            }
+            Error::ExitRequested { code } => write!(fmt, "exit requested with code {code}"),
             Error::RecursiveMutCallback => write!(fmt, "mutable callback called recursively"),
             Error::CallbackDestructed => write!(
                 fmt,
@@ -264,6 +322,7 @@ impl fmt::Display for Error {
             Error::UserDataDestructed => write!(fmt, "userdata has been destructed"),
             Error::UserDataBorrowError => write!(fmt, "error borrowing userdata"),
             Error::UserDataBorrowMutError => write!(fmt, "error mutably borrowing userdata"),
+            Error::WouldBlock => write!(fmt, "userdata is locked, borrowing would block"),
             Error::MetaMethodRestricted(ref method) => write!(fmt, "metamethod {method} is restricted"),
             Error::MetaMethodTypeError { ref method, type_name, ref message } => {
                 write!(fmt, "metamethod {method} has unsupported type {type_name}")?;
@@ -275,6 +334,9 @@ impl fmt::Display for Error {
             Error::MismatchedRegistryKey => {
                 write!(fmt, "RegistryKey used from different Lua state")
             }
+            Error::MismatchedLuaState { expected, found } => {
+                write!(fmt, "value created by Lua state {found} used in Lua state {expected}")
+            }
             Error::CallbackError { ref cause, ref traceback } => {
                 // Trace errors down to the root
                 let (mut cause, mut full_traceback) = (cause, None);
@@ -309,6 +371,12 @@ impl fmt::Display for Error {
             Error::DeserializeError(ref err) => {
                 write!(fmt, "deserialize error: {err}")
             },
+            Error::TooDeep { max_depth } => {
+                write!(fmt, "conversion error: maximum depth of {max_depth} exceeded")
+            }
+            Error::TooLarge { max_elements } => {
+                write!(fmt, "conversion error: maximum number of {max_elements} elements exceeded")
+            }
             Error::ExternalError(ref err) => write!(fmt, "{err}"),
             Error::WithContext { ref context, ref cause } => {
                 writeln!(fmt, "{context}")?;
@@ -364,6 +432,61 @@ impl Error {
         }
     }
 
+    /// Returns details of the received value if the error (or the error raised by a callback) is
+    /// a failed argument conversion.
+    ///
+    /// The details describe the Lua value passed as [`Error::BadArgument`] and the Rust type it
+    /// could not be converted to.
+    pub fn argument_details(&self) -> Option<&ArgumentDetails> {
+        match self {
+            Error::BadArgument { cause, .. } => cause
+                .downcast_ref::<ArgumentError>()
+                .map(|err| &err.details),
+            Error::CallbackError { cause, .. } | Error::WithContext { cause, .. } => {
+                cause.argument_details()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the location in Lua source code where the error was raised, if known.
+    ///
+    /// The location is parsed from the `chunk:line:` prefix that Lua adds to syntax and runtime
+    /// error messages. For [`CallbackError`], the location of the innermost Lua function in the
+    /// traceback is returned if the underlying error does not carry one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let err = lua.load("local x = 1\nerror('boom')").set_name("main").exec().unwrap_err();
+    /// let location = err.source_location().unwrap();
+    /// assert_eq!(location.chunk, "main");
+    /// assert_eq!(location.line, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CallbackError`]: Error::CallbackError
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        match self {
+            Error::SyntaxError { message, .. } => SourceLocation::parse(message),
+            Error::RuntimeError(msg) | Error::MemoryError(msg) => SourceLocation::parse(msg),
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            Error::GarbageCollectorError(msg) => SourceLocation::parse(msg),
+            Error::CallbackError { traceback, cause } => cause.source_location().or_else(|| {
+                let frames = traceback.lines().skip(1);
+                frames.map(str::trim).find_map(SourceLocation::parse)
+            }),
+            Error::BadArgument { cause, .. } | Error::WithContext { cause, .. } => {
+                cause.source_location()
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn bad_self_argument(to: &str, cause: Error) -> Self {
         Error::BadArgument {
             to: Some(to.to_string()),
@@ -373,6 +496,27 @@ impl Error {
         }
     }
 
+    // Creates a bad argument error describing the received value that failed to convert to `T`
+    pub(crate) fn bad_argument<T>(
+        to: Option<&str>,
+        pos: usize,
+        received: &'static str,
+        preview: StdString,
+        cause: Error,
+    ) -> Self {
+        let details = ArgumentDetails {
+            expected: short_type_name::<T>(),
+            received,
+            preview,
+        };
+        Error::BadArgument {
+            to: to.map(|s| s.to_string()),
+            pos,
+            name: None,
+            cause: Arc::new(Error::external(ArgumentError { details, cause })),
+        }
+    }
+
     pub(crate) fn from_lua_conversion<'a>(
         from: &'static str,
         to: &'static str,
@@ -386,6 +530,171 @@ impl Error {
     }
 }
 
+/// Details of a Lua value that could not be converted to a function argument.
+///
+/// Reported by [`Error::argument_details`] to help fixing the call site.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ArgumentDetails {
+    /// Name of the Rust type the argument was converted to.
+    pub expected: StdString,
+    /// Lua type of the received value.
+    pub received: &'static str,
+    /// Short preview of the received value (eg. `"hello"` or `table: 0x...`).
+    ///
+    /// Long values are truncated.
+    pub preview: StdString,
+}
+
+// Cause of `Error::BadArgument` for a failed argument conversion, with details of the value
+#[derive(Debug)]
+struct ArgumentError {
+    details: ArgumentDetails,
+    cause: Error,
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} (value: {})", self.cause, self.details.preview)
+    }
+}
+
+impl StdError for ArgumentError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// Details of a failed value conversion in a Rust callback, passed to the hook set by
+/// [`Lua::on_conversion_error`].
+///
+/// [`Lua::on_conversion_error`]: crate::Lua::on_conversion_error
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConversionErrorInfo {
+    /// Name of the callback (as seen by the caller), if known.
+    pub callback: Option<StdString>,
+    /// Position of the argument that failed to convert (starting from 1).
+    ///
+    /// `None` if the conversion failed elsewhere in the callback (eg. its return values).
+    pub argument: Option<usize>,
+    /// Name of the argument, if known (eg. `self`).
+    pub argument_name: Option<StdString>,
+    /// Type of the value being converted (Lua type for `FromLua` conversions, Rust type for
+    /// `IntoLua` ones).
+    pub from: &'static str,
+    /// Type the value was converted to.
+    pub to: StdString,
+    /// Description of the failure.
+    pub message: Option<StdString>,
+}
+
+impl ConversionErrorInfo {
+    // Extracts details of a conversion failure (the callback name is set only for bad arguments)
+    pub(crate) fn from_error(err: &Error) -> Option<Self> {
+        match err {
+            Error::BadArgument {
+                to,
+                pos,
+                name: arg_name,
+                cause,
+            } => {
+                let mut info = match cause.downcast_ref::<ArgumentError>() {
+                    Some(ArgumentError { details, cause }) => {
+                        Self::from_error(cause).unwrap_or_else(|| ConversionErrorInfo {
+                            // Custom `FromLua` failures
+                            callback: None,
+                            argument: None,
+                            argument_name: None,
+                            from: details.received,
+                            to: details.expected.clone(),
+                            message: Some(cause.to_string()),
+                        })
+                    }
+                    None => Self::from_error(cause)?,
+                };
+                info.callback = to.clone();
+                info.argument = Some(*pos);
+                info.argument_name = arg_name.clone();
+                Some(info)
+            }
+            Error::FromLuaConversionError { from, to, message }
+            | Error::ToLuaConversionError { from, to, message } => Some(ConversionErrorInfo {
+                callback: None,
+                argument: None,
+                argument_name: None,
+                from,
+                to: to.to_string(),
+                message: message.clone(),
+            }),
+            Error::WithContext { cause, .. } => Self::from_error(cause),
+            // Errors of nested callbacks are reported by them
+            _ => None,
+        }
+    }
+}
+
+/// A location in Lua source code, as reported by [`Error::source_location`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    /// Name of the chunk.
+    ///
+    /// For chunks loaded from strings this is the chunk name without the `[string "..."]`
+    /// decoration. Lua may shorten long names.
+    pub chunk: StdString,
+    /// Line number (starting from 1).
+    pub line: usize,
+    /// Column number (starting from 1).
+    ///
+    /// Lua does not report columns, so it is only available when present in the error message
+    /// (in the `chunk:line:column:` form).
+    pub column: Option<usize>,
+}
+
+impl SourceLocation {
+    // Parses the `chunk:line:[column:]` prefix of an error message or traceback frame
+    fn parse(msg: &str) -> Option<Self> {
+        let first_line = msg.lines().next()?;
+        let (chunk, rest) = match first_line.strip_prefix("[string \"") {
+            Some(rest) => {
+                let end = rest.find("\"]:")?;
+                (&rest[..end], &rest[end + 3..])
+            }
+            None => first_line.match_indices(':').find_map(|(i, _)| {
+                let rest = &first_line[i + 1..];
+                let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+                match i > 0 && digits > 0 && rest[digits..].starts_with(':') {
+                    true => Some((&first_line[..i], rest)),
+                    false => None,
+                }
+            })?,
+        };
+
+        let parse_number = |s: &str| -> Option<(usize, usize)> {
+            let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+            match digits > 0 && s[digits..].starts_with(':') {
+                true => Some((s[..digits].parse().ok()?, digits + 1)),
+                false => None,
+            }
+        };
+        let (line, consumed) = parse_number(rest)?;
+        let column = parse_number(&rest[consumed..]).map(|(column, _)| column);
+        Some(SourceLocation {
+            chunk: chunk.to_string(),
+            line,
+            column,
+        })
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.chunk, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Trait for converting [`std::error::Error`] into Lua [`Error`].
 pub trait ExternalError {
     fn into_lua_err(self) -> Error;
@@ -0,0 +1,41 @@
+use crate::error::Result;
+use crate::function::Function;
+use crate::lua::Lua;
+
+/// A Rust function exported to Lua using the [`lua_export`] attribute.
+///
+/// Exports are collected from all crates at link time and installed using
+/// [`Lua::register_exports`].
+///
+/// [`lua_export`]: crate::lua_export
+pub struct LuaExport {
+    name: &'static str,
+    create: for<'lua> fn(&'lua Lua) -> Result<Function<'lua>>,
+}
+
+impl LuaExport {
+    #[doc(hidden)]
+    pub const fn new(
+        name: &'static str,
+        create: for<'lua> fn(&'lua Lua) -> Result<Function<'lua>>,
+    ) -> Self {
+        LuaExport { name, create }
+    }
+
+    /// Returns the (possibly dot-separated) name of the exported function.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Creates a Lua function for the export.
+    pub fn create_function<'lua>(&self, lua: &'lua Lua) -> Result<Function<'lua>> {
+        (self.create)(lua)
+    }
+}
+
+inventory::collect!(LuaExport);
+
+/// Returns an iterator over all collected exports.
+pub(crate) fn exports() -> impl Iterator<Item = &'static LuaExport> {
+    inventory::iter::<LuaExport>.into_iter()
+}
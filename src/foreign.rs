@@ -0,0 +1,232 @@
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::lua::{Lua, LuaInner};
+use crate::types::{MaybeSend, MaybeSync};
+use crate::util::{check_stack, init_gc_metatable, push_gc_userdata, StackGuard};
+
+#[cfg(feature = "send")]
+type CloseHook = Box<dyn FnOnce() + Send>;
+
+#[cfg(not(feature = "send"))]
+type CloseHook = Box<dyn FnOnce()>;
+
+/// Ownership policy of a foreign Lua state wrapped by [`ForeignLua`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateOwnership {
+    /// The state is owned by the host application and never closed by mlua.
+    ///
+    /// Rust callbacks registered in the state stay valid after [`ForeignLua`] is dropped.
+    Borrowed,
+    /// Ownership of the state is transferred to mlua.
+    ///
+    /// The state is closed when [`ForeignLua`] is dropped.
+    Owned,
+}
+
+/// Locking discipline of the host application that owns a foreign Lua state.
+///
+/// The lock is held by [`ForeignLuaGuard`] while the state is accessed from Rust.
+/// The unit type `()` can be used if the host does not require any locking
+/// (e.g. the state is only accessed from a single thread).
+pub trait ForeignLock: MaybeSend + MaybeSync {
+    /// Acquires the host lock.
+    fn lock(&self);
+
+    /// Releases the host lock.
+    fn unlock(&self);
+}
+
+impl ForeignLock for () {
+    #[inline]
+    fn lock(&self) {}
+
+    #[inline]
+    fn unlock(&self) {}
+}
+
+/// A Lua state created outside of mlua (e.g. by an application that loads native plugins).
+///
+/// Unlike [`Lua::init_from_ptr`], it respects ownership of the state and tracks when the state
+/// is closed by the host, so it never accesses a dead state.
+/// Access to the state is provided through [`ForeignLua::lock`] which follows the host
+/// locking discipline.
+pub struct ForeignLua {
+    lua: ManuallyDrop<Lua>,
+    lock: Box<dyn ForeignLock>,
+    shared: Arc<CloseState>,
+    ownership: StateOwnership,
+}
+
+struct CloseState {
+    closed: AtomicBool,
+    hooks: Mutex<Vec<CloseHook>>,
+}
+
+// Stored in the Lua registry, runs close hooks when the state is being closed.
+// Borrowed states also keep the instance alive until then, as callbacks can still be called after
+// the last handle is dropped.
+pub(crate) struct CloseNotifier(Arc<CloseState>, Option<Arc<LuaInner>>);
+
+impl Drop for CloseNotifier {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+        let hooks = mem::take(&mut *mlua_expect!(
+            self.0.hooks.lock(),
+            "hooks mutex poisoned"
+        ));
+        for hook in hooks {
+            hook();
+        }
+        // Release the instance (it does not close borrowed states)
+        drop(self.1.take());
+    }
+}
+
+impl ForeignLua {
+    /// Wraps a foreign Lua state using the given ownership policy and host lock.
+    ///
+    /// Returns an error if the state is already wrapped by mlua.
+    ///
+    /// # Safety
+    /// `state` must be a valid pointer to a Lua state, compatible with the Lua version mlua was
+    /// compiled for. The state must not be accessed from Rust without holding `lock`.
+    pub unsafe fn new(
+        state: *mut ffi::lua_State,
+        ownership: StateOwnership,
+        lock: impl ForeignLock + 'static,
+    ) -> Result<ForeignLua> {
+        lock.lock();
+        let result = Self::init(state, ownership);
+        lock.unlock();
+        let (lua, shared) = result?;
+        Ok(ForeignLua {
+            lua: ManuallyDrop::new(lua),
+            lock: Box::new(lock),
+            shared,
+            ownership,
+        })
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    unsafe fn init(
+        state: *mut ffi::lua_State,
+        ownership: StateOwnership,
+    ) -> Result<(Lua, Arc<CloseState>)> {
+        if Lua::try_from_ptr(state).is_some() {
+            return Err(Error::runtime("Lua state is already wrapped by mlua"));
+        }
+
+        let lua = Lua::init_from_ptr_with(state, ownership == StateOwnership::Owned);
+        let shared = Arc::new(CloseState {
+            closed: AtomicBool::new(false),
+            hooks: Mutex::new(Vec::new()),
+        });
+
+        let state = lua.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 4)?;
+        init_gc_metatable::<CloseNotifier>(state, None)?;
+        let inner = match ownership {
+            StateOwnership::Borrowed => Some(lua.clone()),
+            StateOwnership::Owned => None,
+        };
+        push_gc_userdata(state, CloseNotifier(shared.clone(), inner), true)?;
+        protect_lua!(state, 1, 0, fn(state) {
+            ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX);
+        })?;
+
+        Ok((lua, shared))
+    }
+
+    /// Acquires the host lock and provides access to the Lua state.
+    ///
+    /// Returns an error if the state has been closed.
+    pub fn lock(&self) -> Result<ForeignLuaGuard> {
+        self.lock.lock();
+        if self.is_closed() {
+            self.lock.unlock();
+            return Err(Error::runtime("foreign Lua state is closed"));
+        }
+        Ok(ForeignLuaGuard { foreign: self })
+    }
+
+    /// Returns `true` if the state has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    /// Registers a hook to be called before the state is closed.
+    ///
+    /// The hook must not access the Lua state. If the state is already closed, the hook
+    /// is called immediately.
+    pub fn on_close<F>(&self, f: F)
+    where
+        F: FnOnce() + MaybeSend + 'static,
+    {
+        let mut hooks = mlua_expect!(self.shared.hooks.lock(), "hooks mutex poisoned");
+        if self.is_closed() {
+            drop(hooks);
+            f();
+            return;
+        }
+        hooks.push(Box::new(f));
+    }
+}
+
+impl Drop for ForeignLua {
+    fn drop(&mut self) {
+        self.lock.lock();
+        let lua = unsafe { ManuallyDrop::take(&mut self.lua) };
+        match self.is_closed() {
+            false => drop(lua),
+            // A closed state must not be touched anymore, so only the handle is released.
+            // An owned state closed by the host cannot be released without closing it again.
+            true if self.ownership == StateOwnership::Borrowed => drop(lua.into_inner()),
+            true => mem::forget(lua),
+        }
+        self.lock.unlock();
+    }
+}
+
+impl fmt::Debug for ForeignLua {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ForeignLua")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// Provides access to a foreign Lua state while holding the host lock.
+///
+/// The lock is released on drop.
+pub struct ForeignLuaGuard<'a> {
+    foreign: &'a ForeignLua,
+}
+
+impl<'a> Deref for ForeignLuaGuard<'a> {
+    type Target = Lua;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.foreign.lua
+    }
+}
+
+impl<'a> fmt::Debug for ForeignLuaGuard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ForeignLuaGuard")
+            .field(&*self.foreign.lua)
+            .finish()
+    }
+}
+
+impl<'a> Drop for ForeignLuaGuard<'a> {
+    fn drop(&mut self) {
+        self.foreign.lock.unlock();
+    }
+}
@@ -0,0 +1,386 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::string::String as StdString;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+use crate::userdata::{UserData, UserDataMethods};
+use crate::value::Value;
+
+/// Policy of the `fs` module created by [`Lua::create_fs_module`].
+///
+/// All paths used by scripts are resolved relative to the `root` directory and cannot escape it,
+/// either using `..` components or symbolic links.
+///
+/// Requires `feature = "fs"`
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct FsPolicy {
+    /// Directory that scripts are confined to.
+    pub root: PathBuf,
+
+    /// Disallows creating, modifying and removing files and directories.
+    ///
+    /// Default: **false**
+    pub read_only: bool,
+
+    /// Maximum size of a file that scripts are allowed to read or write.
+    ///
+    /// Default: **none**
+    pub max_file_size: Option<u64>,
+
+    /// Maximum total number of bytes that scripts are allowed to write.
+    ///
+    /// Default: **none**
+    pub write_quota: Option<u64>,
+}
+
+impl FsPolicy {
+    /// Returns a new policy confining scripts to the `root` directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsPolicy {
+            root: root.into(),
+            read_only: false,
+            max_file_size: None,
+            write_quota: None,
+        }
+    }
+
+    /// Sets [`read_only`] option.
+    ///
+    /// [`read_only`]: #structfield.read_only
+    #[must_use]
+    pub const fn read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    /// Sets [`max_file_size`] option.
+    ///
+    /// [`max_file_size`]: #structfield.max_file_size
+    #[must_use]
+    pub const fn max_file_size(mut self, size: Option<u64>) -> Self {
+        self.max_file_size = size;
+        self
+    }
+
+    /// Sets [`write_quota`] option.
+    ///
+    /// [`write_quota`]: #structfield.write_quota
+    #[must_use]
+    pub const fn write_quota(mut self, quota: Option<u64>) -> Self {
+        self.write_quota = quota;
+        self
+    }
+}
+
+// State shared between the module functions and opened files
+struct FsState {
+    // Canonical root path
+    root: PathBuf,
+    policy: FsPolicy,
+    written: AtomicU64,
+}
+
+impl FsState {
+    // Resolves a script path to a canonical path beneath the root
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let relative = relative_path(path)?;
+        self.real_path(path, &self.root.join(relative))
+    }
+
+    // Resolves a script path like `resolve`, but without following its final component (which
+    // can be a symbolic link)
+    fn resolve_entry(&self, path: &str) -> Result<PathBuf> {
+        let relative = relative_path(path)?;
+        match (relative.parent(), relative.file_name()) {
+            (Some(parent), Some(name)) => {
+                Ok(self.real_path(path, &self.root.join(parent))?.join(name))
+            }
+            _ => Ok(self.root.clone()),
+        }
+    }
+
+    // Returns the real location of `full_path`, checking that it's beneath the root
+    fn real_path(&self, path: &str, full_path: &Path) -> Result<PathBuf> {
+        // Check the real location of the deepest existing ancestor to detect symlink escapes
+        let mut existing = full_path;
+        let real_path = loop {
+            match existing.canonicalize() {
+                Ok(real_path) => break real_path,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    // Dangling symlinks would be followed on creation
+                    if existing.symlink_metadata().is_ok() {
+                        return Err(path_error(path, "is a broken symbolic link"));
+                    }
+                    match existing.parent() {
+                        Some(parent) => existing = parent,
+                        None => return Err(path_error(path, "has no existing parent")),
+                    }
+                }
+                Err(err) => return Err(Error::RuntimeError(format!("'{path}': {err}"))),
+            }
+        };
+        if !real_path.starts_with(&self.root) {
+            return Err(path_error(path, "escapes the root directory"));
+        }
+        // Use the real location, so symlinks are not resolved again when the path is opened
+        match mlua_expect!(full_path.strip_prefix(existing), "invalid path ancestor") {
+            missing if missing.as_os_str().is_empty() => Ok(real_path),
+            missing => Ok(real_path.join(missing)),
+        }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.policy.read_only {
+            return Err(Error::RuntimeError("filesystem is read-only".to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_size(&self, path: &str, size: u64) -> Result<()> {
+        match self.policy.max_file_size {
+            Some(max_size) if size > max_size => Err(path_error(path, "exceeds maximum file size")),
+            _ => Ok(()),
+        }
+    }
+
+    // Reserves `size` bytes from the write quota
+    fn consume_quota(&self, size: u64) -> Result<()> {
+        let quota = match self.policy.write_quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        let res = self
+            .written
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |written| {
+                written.checked_add(size).filter(|&total| total <= quota)
+            });
+        match res {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::RuntimeError("write quota exceeded".to_string())),
+        }
+    }
+}
+
+// Normalizes a script path to a path relative to the root
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(path_error(path, "escapes the root directory"));
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Ok(relative)
+}
+
+fn path_error(path: &str, reason: &str) -> Error {
+    Error::RuntimeError(format!("path '{path}' {reason}"))
+}
+
+fn io_error(path: &str) -> impl FnOnce(io::Error) -> Error + '_ {
+    move |err| Error::RuntimeError(format!("'{path}': {err}"))
+}
+
+// File opened by the `fs.open` function
+struct FsFile {
+    // `None` when the file is closed
+    file: Option<File>,
+    path: StdString,
+    state: Arc<FsState>,
+}
+
+impl FsFile {
+    fn file(&mut self) -> Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| Error::RuntimeError("attempt to use a closed file".to_string()))
+    }
+}
+
+impl UserData for FsFile {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        // Reads up to `n` bytes or the rest of the file
+        methods.add_method_mut("read", |lua, this, n: Option<u64>| {
+            let max_size = this.state.policy.max_file_size;
+            let limit = match (n, max_size) {
+                (Some(n), _) => n,
+                // Allow to read one extra byte to detect oversized files
+                (None, Some(max_size)) => max_size.saturating_add(1),
+                (None, None) => u64::MAX,
+            };
+            let mut buf = Vec::new();
+            let path = this.path.clone();
+            (this.file()?.take(limit))
+                .read_to_end(&mut buf)
+                .map_err(io_error(&path))?;
+            this.state.check_size(&path, buf.len() as u64)?;
+            match n {
+                Some(n) if n > 0 && buf.is_empty() => Ok(Value::Nil),
+                _ => lua.create_string(&buf).map(Value::String),
+            }
+        });
+
+        methods.add_method_mut("write", |_, this, data: String| {
+            let data = data.as_bytes();
+            let path = this.path.clone();
+            let file = this.file()?;
+            let size = file.metadata().map_err(io_error(&path))?.len();
+            this.state.check_size(&path, size + data.len() as u64)?;
+            this.state.consume_quota(data.len() as u64)?;
+            this.file()?.write_all(data).map_err(io_error(&path))
+        });
+
+        methods.add_method_mut("close", |_, this, ()| {
+            this.file()?;
+            this.file = None;
+            Ok(())
+        });
+    }
+}
+
+pub(crate) fn create_fs_module(lua: &Lua, policy: FsPolicy) -> Result<Table> {
+    let root = policy.root.canonicalize().map_err(|err| {
+        Error::RuntimeError(format!("invalid root '{}': {err}", policy.root.display()))
+    })?;
+    let state = Arc::new(FsState {
+        root,
+        policy,
+        written: AtomicU64::new(0),
+    });
+
+    let module = lua.create_table_with_capacity(0, 7)?;
+
+    let st = state.clone();
+    module.raw_set(
+        "open",
+        lua.create_function(move |_, (path, mode): (StdString, Option<StdString>)| {
+            let full_path = st.resolve(&path)?;
+            let mut options = OpenOptions::new();
+            match mode.as_deref().unwrap_or("r") {
+                "r" => options.read(true),
+                "w" => options.write(true).create(true).truncate(true),
+                "a" => options.append(true).create(true),
+                mode => return Err(Error::RuntimeError(format!("invalid mode '{mode}'"))),
+            };
+            if mode.as_deref().unwrap_or("r") != "r" {
+                st.check_writable()?;
+            }
+            let file = options.open(full_path).map_err(io_error(&path))?;
+            Ok(FsFile {
+                file: Some(file),
+                path,
+                state: st.clone(),
+            })
+        })?,
+    )?;
+
+    let st = state.clone();
+    module.raw_set(
+        "read",
+        lua.create_function(move |lua, path: StdString| {
+            let full_path = st.resolve(&path)?;
+            let size = fs::metadata(&full_path).map_err(io_error(&path))?.len();
+            st.check_size(&path, size)?;
+            let data = fs::read(full_path).map_err(io_error(&path))?;
+            lua.create_string(data)
+        })?,
+    )?;
+
+    let st = state.clone();
+    module.raw_set(
+        "write",
+        lua.create_function(move |_, (path, data): (StdString, String)| {
+            st.check_writable()?;
+            let full_path = st.resolve(&path)?;
+            let data = data.as_bytes();
+            st.check_size(&path, data.len() as u64)?;
+            st.consume_quota(data.len() as u64)?;
+            fs::write(full_path, data).map_err(io_error(&path))
+        })?,
+    )?;
+
+    let st = state.clone();
+    module.raw_set(
+        "list",
+        lua.create_function(move |lua, path: Option<StdString>| {
+            let path = path.unwrap_or_default();
+            let full_path = st.resolve(&path)?;
+            let mut names = Vec::new();
+            for entry in fs::read_dir(full_path).map_err(io_error(&path))? {
+                let entry = entry.map_err(io_error(&path))?;
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+            names.sort();
+            lua.create_sequence_from(names)
+        })?,
+    )?;
+
+    let st = state.clone();
+    module.raw_set(
+        "stat",
+        lua.create_function(move |lua, path: StdString| {
+            let full_path = st.resolve(&path)?;
+            let metadata = match fs::metadata(full_path) {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(io_error(&path)(err)),
+            };
+            let stat = lua.create_table_with_capacity(0, 3)?;
+            let kind = match () {
+                _ if metadata.is_file() => "file",
+                _ if metadata.is_dir() => "directory",
+                _ => "other",
+            };
+            stat.raw_set("type", kind)?;
+            stat.raw_set("size", metadata.len())?;
+            let modified = (metadata.modified().ok())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs());
+            stat.raw_set("modified", modified)?;
+            Ok(Some(stat))
+        })?,
+    )?;
+
+    let st = state.clone();
+    module.raw_set(
+        "mkdir",
+        lua.create_function(move |_, path: StdString| {
+            st.check_writable()?;
+            let full_path = st.resolve(&path)?;
+            fs::create_dir_all(full_path).map_err(io_error(&path))
+        })?,
+    )?;
+
+    let st = state;
+    module.raw_set(
+        "remove",
+        lua.create_function(move |_, path: StdString| {
+            st.check_writable()?;
+            let full_path = st.resolve_entry(&path)?;
+            if full_path == st.root {
+                return Err(path_error(&path, "is the root directory"));
+            }
+            let metadata = fs::symlink_metadata(&full_path).map_err(io_error(&path))?;
+            if metadata.is_dir() {
+                fs::remove_dir(full_path).map_err(io_error(&path))
+            } else {
+                fs::remove_file(full_path).map_err(io_error(&path))
+            }
+        })?,
+    )?;
+
+    Ok(module)
+}
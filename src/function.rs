@@ -1,19 +1,27 @@
 use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::fmt;
 use std::mem;
 use std::os::raw::{c_int, c_void};
 use std::ptr;
+use std::result::Result as StdResult;
 use std::slice;
 
+use crate::display;
 use crate::error::{Error, Result};
 use crate::lua::Lua;
+use crate::remote::RemoteFunction;
 use crate::table::Table;
 use crate::types::{Callback, LuaRef, MaybeSend};
 use crate::util::{
-    assert_stack, check_stack, linenumber_to_usize, pop_error, ptr_to_lossy_str, ptr_to_str,
-    StackGuard,
+    assert_stack, check_stack, describe_named_callback, linenumber_to_usize, pop_error,
+    ptr_to_lossy_str, ptr_to_str, StackGuard,
 };
 use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti, Value};
 
+#[cfg(not(feature = "luau"))]
+use crate::hook::{Debug, HookTriggers};
+
 #[cfg(feature = "async")]
 use {
     crate::types::AsyncCallback,
@@ -70,6 +78,95 @@ pub struct FunctionInfo {
     pub last_line_defined: Option<usize>,
 }
 
+/// A single call frame captured by [`Function::call_traced`].
+#[derive(Clone, Debug)]
+pub struct TraceFrame {
+    /// A (reasonable) name of the function (`None` if the name cannot be found).
+    pub name: Option<String>,
+    /// A string `Lua` if the function is a Lua function, `C` if it is a C function, `main` if it is the main part of a chunk.
+    pub what: &'static str,
+    /// A "printable" version of the chunk source.
+    pub short_src: Option<String>,
+    /// The line number where the definition of the function starts.
+    pub line_defined: Option<usize>,
+    /// The line being executed in the frame (`None` if not available).
+    pub current_line: Option<usize>,
+}
+
+/// Error returned by [`Function::call_traced`], with the call stack at the point of failure.
+///
+/// Frames are ordered from the innermost (where the error was raised) to the outermost.
+#[derive(Clone, Debug)]
+pub struct TracedError {
+    /// The underlying error.
+    pub error: Error,
+    /// Captured call frames.
+    pub frames: Vec<TraceFrame>,
+}
+
+impl fmt::Display for TracedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.frames.is_empty() {
+            write!(f, "\nstack traceback:")?;
+        }
+        for frame in &self.frames {
+            let src = frame.short_src.as_deref().unwrap_or("?");
+            match frame.current_line {
+                Some(line) => write!(f, "\n\t{src}:{line}: in ")?,
+                None => write!(f, "\n\t{src}: in ")?,
+            }
+            match &frame.name {
+                Some(name) => write!(f, "function '{name}'")?,
+                None if frame.what == "main" => write!(f, "main chunk")?,
+                None => write!(f, "?")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StdError for TracedError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<Error> for TracedError {
+    fn from(error: Error) -> Self {
+        TracedError {
+            error,
+            frames: Vec::new(),
+        }
+    }
+}
+
+/// Outcome of [`Function::pcall`].
+///
+/// The error variant holds the original error value, exactly as Lua's `pcall` would return it.
+#[derive(Clone, Debug)]
+pub enum PcallResult<'lua, R> {
+    /// The function returned successfully.
+    Ok(R),
+    /// The function raised an error (with any value).
+    Err(Value<'lua>),
+}
+
+impl<'lua, R> PcallResult<'lua, R> {
+    /// Returns `true` if the call succeeded.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, PcallResult::Ok(_))
+    }
+
+    /// Converts into a `std::result::Result` with the raised value as error.
+    pub fn into_result(self) -> StdResult<R, Value<'lua>> {
+        match self {
+            PcallResult::Ok(r) => Ok(r),
+            PcallResult::Err(err) => Err(err),
+        }
+    }
+}
+
 /// Luau function coverage snapshot.
 #[cfg(any(feature = "luau", doc))]
 #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
@@ -81,6 +178,45 @@ pub struct CoverageInfo {
     pub hits: Vec<i32>,
 }
 
+// Message handler used by `Function::call_traced`, leaves the error object intact
+unsafe extern "C-unwind" fn capture_trace_frames(state: *mut ffi::lua_State) -> c_int {
+    let frames = ffi::lua_touserdata(state, ffi::lua_upvalueindex(1)) as *mut Vec<TraceFrame>;
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    // Level 0 is the handler itself
+    let mut level = 1;
+    loop {
+        #[cfg(not(feature = "luau"))]
+        {
+            if ffi::lua_getstack(state, level, &mut ar) == 0 {
+                break;
+            }
+            mlua_assert!(
+                ffi::lua_getinfo(state, cstr!("Snlf"), &mut ar) != 0,
+                "lua_getinfo failed with `Snlf`"
+            );
+        }
+        #[cfg(feature = "luau")]
+        if ffi::lua_getinfo(state, level, cstr!("snlf"), &mut ar) == 0 {
+            break;
+        }
+        describe_named_callback(state, &mut ar);
+        ffi::lua_pop(state, 1);
+
+        (*frames).push(TraceFrame {
+            name: ptr_to_lossy_str(ar.name).map(|s| s.into_owned()),
+            what: ptr_to_str(ar.what).unwrap_or("main"),
+            #[cfg(not(feature = "luau"))]
+            short_src: ptr_to_lossy_str(ar.short_src.as_ptr()).map(|s| s.into_owned()),
+            #[cfg(feature = "luau")]
+            short_src: ptr_to_lossy_str(ar.short_src).map(|s| s.into_owned()),
+            line_defined: linenumber_to_usize(ar.linedefined),
+            current_line: linenumber_to_usize(ar.currentline),
+        });
+        level += 1;
+    }
+    1
+}
+
 impl<'lua> Function<'lua> {
     /// Calls the function, passing `args` as function arguments.
     ///
@@ -122,6 +258,7 @@ impl<'lua> Function<'lua> {
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn call<A: IntoLuaMulti<'lua>, R: FromLuaMulti<'lua>>(&self, args: A) -> Result<R> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -146,6 +283,164 @@ impl<'lua> Function<'lua> {
         }
     }
 
+    /// Calls the function in protected mode, mirroring Lua's `pcall`.
+    ///
+    /// Unlike [`call`], errors raised by the function are not converted into [`Error`] and no
+    /// traceback is attached. The raised value is returned as-is in [`PcallResult::Err`],
+    /// preceded by the `false` status flag.
+    ///
+    /// The outer `Result` is used only for errors on the Rust side (e.g. converting the arguments
+    /// or return values).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, PcallResult, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let func: Function = lua.load("function(x) error({code = x}) end").eval()?;
+    ///
+    /// let (ok, res) = func.pcall::<_, ()>(42)?;
+    /// assert!(!ok);
+    /// match res {
+    ///     PcallResult::Err(Value::Table(t)) => assert_eq!(t.get::<_, i32>("code")?, 42),
+    ///     _ => unreachable!(),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn pcall<A, R>(&self, args: A) -> Result<(bool, PcallResult<'lua, R>)>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 2)?;
+
+            let stack_start = ffi::lua_gettop(state);
+            // Push function and the arguments
+            lua.push_ref(&self.0);
+            let nargs = args.push_into_stack_multi(lua)?;
+            // Call the function without message handler to keep the error value intact
+            let ret = ffi::lua_pcall(state, nargs, ffi::LUA_MULTRET, 0);
+            if ret != ffi::LUA_OK {
+                return Ok((false, PcallResult::Err(lua.pop_value())));
+            }
+            // Get the results
+            let nresults = ffi::lua_gettop(state) - stack_start;
+            let results = R::from_stack_multi(nresults, lua)?;
+            Ok((true, PcallResult::Ok(results)))
+        }
+    }
+
+    /// Calls the function, capturing the call stack as a list of frames if an error occurs.
+    ///
+    /// The frames are collected by a message handler only on the failure path, so the success
+    /// path costs about the same as [`call`]. Errors on the Rust side (e.g. converting the
+    /// arguments or return values) are returned without frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let func: Function = lua.load(r#"
+    ///     local function inner() error("boom") end
+    ///     return function() inner() end
+    /// "#).eval()?;
+    ///
+    /// let err = func.call_traced::<_, ()>(()).unwrap_err();
+    /// assert_eq!(err.frames[1].name.as_deref(), Some("inner"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn call_traced<A, R>(&self, args: A) -> StdResult<R, TracedError>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        let mut frames = Vec::new();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            // Push message handler which captures frames into `frames`
+            ffi::lua_pushlightuserdata(state, &mut frames as *mut Vec<TraceFrame> as *mut c_void);
+            protect_lua!(state, 1, 1, fn(state) {
+                ffi::lua_pushcclosure(state, capture_trace_frames, 1);
+            })?;
+            let stack_start = ffi::lua_gettop(state);
+            // Push function and the arguments
+            lua.push_ref(&self.0);
+            let nargs = args.push_into_stack_multi(lua)?;
+            // Call the function
+            let ret = ffi::lua_pcall(state, nargs, ffi::LUA_MULTRET, stack_start);
+            if ret != ffi::LUA_OK {
+                let error = pop_error(state, ret);
+                return Err(TracedError { error, frames });
+            }
+            // Get the results
+            let nresults = ffi::lua_gettop(state) - stack_start;
+            Ok(R::from_stack_multi(nresults, lua)?)
+        }
+    }
+
+    /// Calls the function with a hook installed only for the duration of the call.
+    ///
+    /// The hook is set for the current thread and inherited by coroutines created during the call.
+    /// Any previous hook (set by [`Lua::set_hook`] or [`Thread::set_hook`]) is suspended and
+    /// restored when the call returns, including in coroutines that inherited the scoped hook.
+    ///
+    /// Please note that LuaJIT does not trigger hooks in JIT-compiled code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Function, HookTriggers, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let func: Function = lua.load("function(a, b) return a + b end").eval()?;
+    ///
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let lines2 = lines.clone();
+    /// let hook = move |_: &Lua, debug: mlua::Debug| {
+    ///     lines2.lock().unwrap().push(debug.curr_line());
+    ///     Ok(())
+    /// };
+    /// let sum: i32 = func.call_with_hook(HookTriggers::EVERY_LINE, hook, (1, 2))?;
+    /// assert_eq!(sum, 3);
+    /// assert_eq!(*lines.lock().unwrap(), vec![1]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::set_hook`]: crate::Lua::set_hook
+    /// [`Thread::set_hook`]: crate::Thread::set_hook
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn call_with_hook<A, R, F>(&self, triggers: HookTriggers, hook: F, args: A) -> Result<R>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+        F: Fn(&Lua, Debug) -> Result<()> + MaybeSend + 'static,
+    {
+        let _guard = unsafe { self.0.lua.set_scoped_hook(triggers, hook) };
+        self.call(args)
+    }
+
     /// Returns a future that, when polled, calls `self`, passing `args` as function arguments,
     /// and drives the execution.
     ///
@@ -176,6 +471,7 @@ impl<'lua> Function<'lua> {
     /// [`AsyncThread`]: crate::AsyncThread
     #[cfg(feature = "async")]
     #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn call_async<A, R>(&self, args: A) -> impl Future<Output = Result<R>> + 'lua
     where
         A: IntoLuaMulti<'lua>,
@@ -217,6 +513,7 @@ impl<'lua> Function<'lua> {
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn bind<A: IntoLuaMulti<'lua>>(&self, args: A) -> Result<Function<'lua>> {
         unsafe extern "C-unwind" fn args_wrapper_impl(state: *mut ffi::lua_State) -> c_int {
             let nargs = ffi::lua_gettop(state);
@@ -280,6 +577,7 @@ impl<'lua> Function<'lua> {
     /// By default Lua functions shares a global environment.
     ///
     /// This function always returns `None` for Rust/C functions.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn environment(&self) -> Option<Table> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -317,6 +615,7 @@ impl<'lua> Function<'lua> {
     /// Returns `true` if environment successfully changed, `false` otherwise.
     ///
     /// This function does nothing for Rust/C functions.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_environment(&self, env: Table) -> Result<bool> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -331,7 +630,7 @@ impl<'lua> Function<'lua> {
 
             #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
             {
-                lua.push_ref(&env.0);
+                lua.try_push_ref(&env.0)?;
                 ffi::lua_setfenv(state, -2);
             }
             #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
@@ -363,20 +662,22 @@ impl<'lua> Function<'lua> {
     /// Corresponds to the `>Sn` what mask for [`lua_getinfo`] when applied to the function.
     ///
     /// [`lua_getinfo`]: https://www.lua.org/manual/5.4/manual.html#lua_getinfo
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn info(&self) -> FunctionInfo {
         let lua = self.0.lua;
         let state = lua.state();
         unsafe {
             let _sg = StackGuard::new(state);
-            assert_stack(state, 1);
+            assert_stack(state, 4);
 
             let mut ar: ffi::lua_Debug = mem::zeroed();
             lua.push_ref(&self.0);
             #[cfg(not(feature = "luau"))]
-            let res = ffi::lua_getinfo(state, cstr!(">Sn"), &mut ar);
+            let res = ffi::lua_getinfo(state, cstr!(">Snf"), &mut ar);
             #[cfg(feature = "luau")]
-            let res = ffi::lua_getinfo(state, -1, cstr!("sn"), &mut ar);
-            mlua_assert!(res != 0, "lua_getinfo failed with `>Sn`");
+            let res = ffi::lua_getinfo(state, -1, cstr!("snf"), &mut ar);
+            mlua_assert!(res != 0, "lua_getinfo failed with `>Snf`");
+            describe_named_callback(state, &mut ar);
 
             FunctionInfo {
                 name: ptr_to_lossy_str(ar.name).map(|s| s.into_owned()),
@@ -412,6 +713,7 @@ impl<'lua> Function<'lua> {
     /// [Compiler]: crate::chunk::Compiler
     #[cfg(not(feature = "luau"))]
     #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn dump(&self, strip: bool) -> Vec<u8> {
         unsafe extern "C-unwind" fn writer(
             _state: *mut ffi::lua_State,
@@ -453,6 +755,7 @@ impl<'lua> Function<'lua> {
     /// [`Compiler::set_coverage_level`]: crate::chunk::Compiler::set_coverage_level
     #[cfg(any(feature = "luau", doc))]
     #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn coverage<F>(&self, mut func: F)
     where
         F: FnMut(CoverageInfo),
@@ -525,6 +828,15 @@ impl<'lua> Function<'lua> {
         }
     }
 
+    /// Converts this function to a handle that can be called from other threads.
+    ///
+    /// See [`RemoteFunction`] for details.
+    pub fn into_remote(self) -> Result<RemoteFunction> {
+        let lua = self.0.lua;
+        let key = lua.create_registry_value(self)?;
+        Ok(RemoteFunction::new(lua, key))
+    }
+
     /// Convert this handle to owned version.
     #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
     #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
@@ -534,6 +846,12 @@ impl<'lua> Function<'lua> {
     }
 }
 
+impl fmt::Display for Function<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        display::fmt_ref(fmt, &self.0, "function")
+    }
+}
+
 impl<'lua> PartialEq for Function<'lua> {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -640,6 +958,7 @@ impl<'lua> IntoLua<'lua> for WrappedFunction<'lua> {
 #[cfg(feature = "async")]
 impl<'lua> IntoLua<'lua> for WrappedAsyncFunction<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
         lua.create_async_callback(self.0).map(Value::Function)
     }
@@ -650,6 +969,7 @@ mod assertions {
     use super::*;
 
     static_assertions::assert_not_impl_any!(Function: Send);
+    static_assertions::assert_impl_all!(RemoteFunction: Send, Sync);
 
     #[cfg(all(feature = "unstable", not(feature = "send")))]
     static_assertions::assert_not_impl_any!(OwnedFunction: Send);
@@ -0,0 +1,180 @@
+use std::cmp::Reverse;
+use std::fmt::Write as _;
+use std::os::raw::{c_int, c_void};
+use std::time::{Duration, Instant};
+use std::{mem, ptr};
+
+use rustc_hash::FxHashMap;
+
+use crate::lua::function_label;
+use crate::util::{describe_named_callback, function_name, ptr_to_lossy_str, ptr_to_str};
+
+/// Number of calls and time spent in a function, collected after
+/// [`Lua::enable_function_stats`].
+///
+/// Lua functions are identified by the location of their definition, Rust callbacks created by
+/// [`Lua::create_named_function`] (and userdata methods) by their registered name, and other Rust
+/// callbacks (and C functions) by identity.
+///
+/// [`Lua::enable_function_stats`]: crate::Lua::enable_function_stats
+/// [`Lua::create_named_function`]: crate::Lua::create_named_function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// Name of the function (`main chunk` or `function <source:line>` for unnamed Lua functions).
+    pub name: String,
+    /// Location of the Lua function definition as `source:line`, `None` for Rust/C functions
+    /// (except named Rust callbacks, located where they were created).
+    pub source: Option<String>,
+    /// Number of calls.
+    pub calls: u64,
+    /// Cumulative time spent in the function itself, excluding the functions it called.
+    pub self_time: Duration,
+}
+
+// State of the function stats collection, kept in the Lua extra data
+pub(crate) struct StatsRecorder {
+    pub(crate) prev_hook: (Option<ffi::lua_Hook>, c_int, c_int),
+    stats: Vec<FunctionStats>,
+    // Lua functions by the location of their definition and named Rust callbacks by their name
+    index: FxHashMap<String, usize>,
+    // Other C functions by identity
+    cfunctions: FxHashMap<*const c_void, usize>,
+    // Reused to build the keys without allocating
+    key: String,
+    // Functions (indices in `stats` and identities) on the stack of every thread
+    stacks: FxHashMap<*mut ffi::lua_State, Vec<(usize, *const c_void)>>,
+    // Thread and time of the last event
+    last_event: (*mut ffi::lua_State, Instant),
+}
+
+impl StatsRecorder {
+    pub(crate) fn new(state: *mut ffi::lua_State) -> Self {
+        StatsRecorder {
+            prev_hook: (None, 0, 0),
+            stats: Vec::new(),
+            index: FxHashMap::default(),
+            cfunctions: FxHashMap::default(),
+            key: String::new(),
+            stacks: FxHashMap::default(),
+            last_event: (state, Instant::now()),
+        }
+    }
+
+    // Adds the time since the last event to the function that was running
+    pub(crate) fn tick(&mut self, state: *mut ffi::lua_State) {
+        let now = Instant::now();
+        let (last_state, last_time) = mem::replace(&mut self.last_event, (state, now));
+        if let Some(&(i, _)) = self.stacks.get(&last_state).and_then(|stack| stack.last()) {
+            self.stats[i].self_time += now - last_time;
+        }
+    }
+
+    // Returns the index of the function running in the hook, described by `ar` (filled with the
+    // "Sf" info). The function is expected on top of the stack.
+    // Names are resolved only for functions seen for the first time.
+    pub(crate) unsafe fn function(
+        &mut self,
+        state: *mut ffi::lua_State,
+        ar: *mut ffi::lua_Debug,
+    ) -> usize {
+        // The name is not filled by "Sf"
+        (*ar).name = ptr::null();
+        let named = describe_named_callback(state, ar);
+        self.key.clear();
+        let _ = match ptr_to_str((*ar).what) {
+            Some("C") if named => {
+                let name = ptr_to_lossy_str((*ar).name).unwrap_or_default();
+                write!(self.key, "[C] {name}")
+            }
+            Some("C") => {
+                let function = ffi::lua_topointer(state, -1);
+                if let Some(&i) = self.cfunctions.get(&function) {
+                    return i;
+                }
+                let i = self.insert(state, ar);
+                self.cfunctions.insert(function, i);
+                return i;
+            }
+            _ => {
+                let short_src = ptr_to_lossy_str((*ar).short_src.as_ptr());
+                let short_src = short_src.as_deref().unwrap_or_default();
+                write!(self.key, "{short_src}:{}", (*ar).linedefined)
+            }
+        };
+        if let Some(&i) = self.index.get(self.key.as_str()) {
+            return i;
+        }
+        let i = self.insert(state, ar);
+        self.index.insert(self.key.clone(), i);
+        i
+    }
+
+    unsafe fn insert(&mut self, state: *mut ffi::lua_State, ar: *const ffi::lua_Debug) -> usize {
+        let (mut name, source) = function_label(ar);
+        if ptr_to_str((*ar).what) != Some("main") {
+            // Registered name of named callbacks, the call site or the global name otherwise
+            if let Some(function_name) = function_name(state, 0) {
+                name = function_name;
+            }
+        }
+        self.stats.push(FunctionStats {
+            name,
+            source,
+            calls: 0,
+            self_time: Duration::ZERO,
+        });
+        self.stats.len() - 1
+    }
+
+    pub(crate) fn enter(
+        &mut self,
+        state: *mut ffi::lua_State,
+        function: usize,
+        identity: *const c_void,
+    ) {
+        self.stats[function].calls += 1;
+        let stack = self.stacks.entry(state).or_default();
+        stack.push((function, identity));
+    }
+
+    // Pops the function `identity` from the stack, along with functions above it (unwound by
+    // an error)
+    pub(crate) fn exit(&mut self, state: *mut ffi::lua_State, identity: *const c_void) {
+        if let Some(stack) = self.stacks.get_mut(&state) {
+            // Skip functions entered before the collection was enabled
+            if let Some(pos) = stack.iter().rposition(|&(_, f)| f == identity) {
+                stack.truncate(pos);
+            }
+            if stack.is_empty() {
+                self.stacks.remove(&state);
+            }
+        }
+    }
+
+    // Pops the function on top of the stack (replaced by a tail call)
+    pub(crate) fn pop(&mut self, state: *mut ffi::lua_State) {
+        if let Some(stack) = self.stacks.get_mut(&state) {
+            stack.pop();
+            if stack.is_empty() {
+                self.stacks.remove(&state);
+            }
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for stats in &mut self.stats {
+            stats.calls = 0;
+            stats.self_time = Duration::ZERO;
+        }
+    }
+
+    // Returns the stats sorted by self time (then by number of calls), in descending order
+    pub(crate) fn report(&self) -> Vec<FunctionStats> {
+        let mut report = (self.stats.iter())
+            .filter(|stats| stats.calls > 0 || !stats.self_time.is_zero())
+            .cloned()
+            .collect::<Vec<_>>();
+        report.sort_by_key(|stats| Reverse((stats.self_time, stats.calls)));
+        report
+    }
+}
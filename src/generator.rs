@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures_util::future::LocalBoxFuture;
+use futures_util::task::noop_waker_ref;
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::types::{Callback, MaybeSend};
+use crate::value::{FromLuaMulti, IntoLuaMulti, MultiValue};
+
+/// A handle passed to a generator body to produce values, see [`Lua::create_generator`].
+///
+/// Requires `feature = "async"`
+///
+/// [`Lua::create_generator`]: crate::Lua::create_generator
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub struct Yielder<'lua> {
+    lua: &'lua Lua,
+    slot: Rc<RefCell<Option<MultiValue<'lua>>>>,
+}
+
+impl<'lua> Yielder<'lua> {
+    /// Returns the Lua instance the generator belongs to.
+    pub fn lua(&self) -> &'lua Lua {
+        self.lua
+    }
+
+    /// Suspends the generator body, returning `values` from the current iteration.
+    ///
+    /// The returned future must be awaited for the values to be produced. It resolves when the
+    /// generator is called again by the `for`-loop.
+    pub fn yield_values(&self, values: impl IntoLuaMulti<'lua>) -> YieldValues {
+        match values.into_lua_multi(self.lua) {
+            Ok(values) => {
+                *self.slot.borrow_mut() = Some(values);
+                YieldValues {
+                    yielded: true,
+                    error: None,
+                }
+            }
+            Err(err) => YieldValues {
+                yielded: false,
+                error: Some(err),
+            },
+        }
+    }
+}
+
+impl<'lua> fmt::Debug for Yielder<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Yielder").finish()
+    }
+}
+
+/// Future returned by [`Yielder::yield_values`].
+///
+/// Requires `feature = "async"`
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug)]
+pub struct YieldValues {
+    yielded: bool,
+    error: Option<Error>,
+}
+
+impl Future for YieldValues {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        if let Some(err) = self.error.take() {
+            return Poll::Ready(Err(err));
+        }
+        match mem::replace(&mut self.yielded, false) {
+            true => Poll::Pending,
+            false => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl Lua {
+    /// Wraps a Rust generator body, creating a Lua function that returns an iterator suitable
+    /// for the generic `for` loop.
+    ///
+    /// Every call of the returned function starts a new run of `func` with the call arguments.
+    /// The body is an async block running as a coroutine: each time the iterator is called, it is
+    /// resumed until the next [`Yielder::yield_values`], whose values become the loop variables.
+    /// When the body completes, the iterator returns `nil` and the loop ends. Errors returned by
+    /// the body are raised in Lua.
+    ///
+    /// The body is resumed synchronously, so it must not await anything except the values
+    /// yielded by the [`Yielder`]. Unlike [`create_async_function`], the iterator does not
+    /// need to be called from within a Lua coroutine.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let range = lua.create_generator(|yielder, (from, to, step): (i64, i64, i64)| async move {
+    ///     let mut i = from;
+    ///     while i <= to {
+    ///         yielder.yield_values(i).await?;
+    ///         i += step;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// lua.globals().set("range", range)?;
+    ///
+    /// let sum: i64 = lua.load(r#"
+    ///     local sum = 0
+    ///     for i in range(1, 10, 3) do sum = sum + i end
+    ///     return sum
+    /// "#).eval()?;
+    /// assert_eq!(sum, 1 + 4 + 7 + 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_async_function`]: #method.create_async_function
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn create_generator<'lua, A, F, FR>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        F: Fn(Yielder<'lua>, A) -> FR + MaybeSend + 'static,
+        FR: Future<Output = Result<()>> + 'lua,
+    {
+        self.create_function(move |lua, args: A| {
+            let slot = Rc::new(RefCell::new(None));
+            let yielder = Yielder {
+                lua,
+                slot: slot.clone(),
+            };
+            let fut: LocalBoxFuture<'lua, Result<()>> = Box::pin(func(yielder, args));
+            create_iterator(lua, fut, slot)
+        })
+    }
+}
+
+fn create_iterator<'lua>(
+    lua: &'lua Lua,
+    fut: LocalBoxFuture<'lua, Result<()>>,
+    slot: Rc<RefCell<Option<MultiValue<'lua>>>>,
+) -> Result<Function<'lua>> {
+    // Once completed, the body is dropped and the iterator keeps returning `nil`
+    let fut = RefCell::new(Some(fut));
+    let func: Callback<'lua, 'lua> = Box::new(move |lua, _| unsafe {
+        let mut fut_ref =
+            (fut.try_borrow_mut()).map_err(|_| Error::runtime("generator is already running"))?;
+        let Some(body) = fut_ref.as_mut() else {
+            return ().push_into_stack_multi(lua);
+        };
+        let mut ctx = Context::from_waker(noop_waker_ref());
+        let poll = body.as_mut().poll(&mut ctx);
+        let values = slot.borrow_mut().take();
+        match (poll, values) {
+            (Poll::Pending, Some(values)) => values.push_into_stack_multi(lua),
+            (Poll::Pending, None) => {
+                *fut_ref = None;
+                Err(Error::runtime(
+                    "generator awaited a future other than a yielder",
+                ))
+            }
+            (Poll::Ready(res), _) => {
+                *fut_ref = None;
+                res?;
+                ().push_into_stack_multi(lua)
+            }
+        }
+    });
+    // The callback (together with the values it holds) cannot outlive the Lua instance
+    lua.create_callback(unsafe {
+        mem::transmute::<Callback<'lua, 'lua>, Callback<'lua, 'static>>(func)
+    })
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::{Integer, RegistryKey};
+use crate::util::{check_stack, StackGuard};
+use crate::value::{FromLua, IntoLua};
+
+/// A cached accessor to a (possibly nested) global value, returned by [`Lua::global_handle`].
+///
+/// The table holding the value and the key are resolved once and stored in the registry,
+/// so accessing the value does not require walking the path or interning the key again.
+///
+/// If the script environment is reloaded (e.g. the tables on the path are replaced), the handle
+/// keeps pointing to the old table until [`GlobalHandle::resolve`] is called.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.load("config = { limits = { rate = 10 } }").exec()?;
+///
+/// let rate = lua.global_handle::<u32>("config.limits.rate")?;
+/// assert_eq!(rate.get(&lua)?, 10);
+/// rate.set(&lua, 20)?;
+/// assert_eq!(lua.load("config.limits.rate").eval::<u32>()?, 20);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::global_handle`]: crate::Lua::global_handle
+pub struct GlobalHandle<T> {
+    path: StdString,
+    table: RegistryKey,
+    key: RegistryKey,
+    _phantom: PhantomData<fn(T) -> T>,
+}
+
+impl<T> GlobalHandle<T> {
+    pub(crate) fn new(lua: &Lua, path: &str) -> Result<Self> {
+        let (table, key) = resolve(lua, path)?;
+        Ok(GlobalHandle {
+            path: path.to_string(),
+            table,
+            key,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the dot-separated path of the global value.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the current value.
+    ///
+    /// This might invoke the `__index` metamethod.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn get<'lua>(&self, lua: &'lua Lua) -> Result<T>
+    where
+        T: FromLua<'lua>,
+    {
+        self.check_owner(lua)?;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            self.push_table_key(state);
+            protect_lua!(state, 2, 1, fn(state) ffi::lua_gettable(state, -2))?;
+
+            T::from_stack(-1, lua)
+        }
+    }
+
+    /// Replaces the value.
+    ///
+    /// This might invoke the `__newindex` metamethod.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn set<'lua>(&self, lua: &'lua Lua, value: T) -> Result<()>
+    where
+        T: IntoLua<'lua>,
+    {
+        self.check_owner(lua)?;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            self.push_table_key(state);
+            value.push_into_stack(lua)?;
+            protect_lua!(state, 3, 0, fn(state) ffi::lua_settable(state, -3))
+        }
+    }
+
+    /// Resolves the path again, starting from the current globals table.
+    ///
+    /// Call this function after the script environment was reloaded.
+    pub fn resolve(&mut self, lua: &Lua) -> Result<()> {
+        let (table, key) = resolve(lua, &self.path)?;
+        self.table = table;
+        self.key = key;
+        Ok(())
+    }
+
+    fn check_owner(&self, lua: &Lua) -> Result<()> {
+        match lua.owns_registry_value(&self.table) {
+            true => Ok(()),
+            false => Err(Error::MismatchedRegistryKey),
+        }
+    }
+
+    unsafe fn push_table_key(&self, state: *mut ffi::lua_State) {
+        ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, self.table.id() as Integer);
+        ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, self.key.id() as Integer);
+    }
+}
+
+impl<T> fmt::Debug for GlobalHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("GlobalHandle").field(&self.path).finish()
+    }
+}
+
+// Resolves the table holding the value at a dot-separated path and the (last) key
+fn resolve(lua: &Lua, path: &str) -> Result<(RegistryKey, RegistryKey)> {
+    let (parents, name) = match path.rsplit_once('.') {
+        Some((parents, name)) => (Some(parents), name),
+        None => (None, path),
+    };
+    let mut table = lua.globals();
+    for key in parents.into_iter().flat_map(|path| path.split('.')) {
+        table = match table.get::<_, Option<Table>>(key) {
+            Ok(Some(table)) => table,
+            _ => {
+                let msg = format!("cannot resolve global '{path}': '{key}' is not a table");
+                return Err(Error::runtime(msg));
+            }
+        };
+    }
+    let table = lua.create_registry_value(table)?;
+    let name = lua.create_registry_value(lua.create_string(name)?)?;
+    Ok((table, name))
+}
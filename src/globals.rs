@@ -0,0 +1,252 @@
+use std::string::String as StdString;
+
+use rustc_hash::FxHashSet;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::Value;
+
+#[cfg(not(feature = "luau"))]
+use crate::util::{check_stack, StackGuard};
+
+// Standard library tables which are frozen by `GlobalsProtection::freeze_stdlib`
+const STDLIB_TABLES: &[&str] = &[
+    "coroutine",
+    "table",
+    "io",
+    "os",
+    "string",
+    "utf8",
+    "bit32",
+    "math",
+    "package",
+    "debug",
+    "jit",
+    "buffer",
+    "vector",
+];
+
+/// Enforcement rules installed into the globals table by [`Lua::protect_globals`].
+///
+/// Rust code can still create globals (regardless of the allowlist) using [`Table::raw_set`]
+/// on the table returned by [`Lua::globals`].
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct GlobalsProtection {
+    /// Locks the globals metatable, so scripts cannot read or replace it.
+    ///
+    /// Default: **false**
+    pub lock_metatable: bool,
+
+    /// Makes standard library tables (`string`, `table`, `math`, etc.) read-only.
+    ///
+    /// Only loaded libraries are affected. The protection is shallow, nested tables
+    /// (e.g. `package.loaded`) are left writable.
+    ///
+    /// In Luau the tables are marked as read-only (see [`Table::set_readonly`]), which cannot be
+    /// bypassed by scripts.
+    ///
+    /// Other Lua versions have no read-only tables, so each library table is replaced by an empty
+    /// proxy which forwards reads to the original table and rejects assignments. This has
+    /// limitations:
+    /// - `rawset` on the proxy still succeeds and the new field shadows the original one. Remove
+    ///   `rawset` from the globals if scripts are untrusted.
+    /// - Lua 5.1 and LuaJIT ignore the `__pairs` metamethod, so `pairs` and `next` see the proxy
+    ///   as an empty table.
+    /// - The original tables are still reachable through functions that captured them before
+    ///   the call.
+    ///
+    /// Default: **false**
+    pub freeze_stdlib: bool,
+
+    /// Raises an error when a script assigns to an undeclared global variable.
+    ///
+    /// Names listed in [`allowed_globals`] can still be created. If the globals table already
+    /// has a `__newindex` metamethod, allowed globals are created through it.
+    ///
+    /// Default: **false**
+    ///
+    /// [`allowed_globals`]: #structfield.allowed_globals
+    pub error_on_new_globals: bool,
+
+    /// Names of globals that scripts are allowed to create (e.g. during a setup phase).
+    ///
+    /// Default: **empty**
+    pub allowed_globals: Vec<StdString>,
+}
+
+impl GlobalsProtection {
+    /// Returns a new instance of `GlobalsProtection` with all protections disabled.
+    pub const fn new() -> Self {
+        GlobalsProtection {
+            lock_metatable: false,
+            freeze_stdlib: false,
+            error_on_new_globals: false,
+            allowed_globals: Vec::new(),
+        }
+    }
+
+    /// Sets [`lock_metatable`] option.
+    ///
+    /// [`lock_metatable`]: #structfield.lock_metatable
+    #[must_use]
+    pub const fn lock_metatable(mut self, enabled: bool) -> Self {
+        self.lock_metatable = enabled;
+        self
+    }
+
+    /// Sets [`freeze_stdlib`] option.
+    ///
+    /// [`freeze_stdlib`]: #structfield.freeze_stdlib
+    #[must_use]
+    pub const fn freeze_stdlib(mut self, enabled: bool) -> Self {
+        self.freeze_stdlib = enabled;
+        self
+    }
+
+    /// Sets [`error_on_new_globals`] option.
+    ///
+    /// [`error_on_new_globals`]: #structfield.error_on_new_globals
+    #[must_use]
+    pub const fn error_on_new_globals(mut self, enabled: bool) -> Self {
+        self.error_on_new_globals = enabled;
+        self
+    }
+
+    /// Adds `name` to the [`allowed_globals`] list.
+    ///
+    /// [`allowed_globals`]: #structfield.allowed_globals
+    #[must_use]
+    pub fn allow_global(mut self, name: impl Into<StdString>) -> Self {
+        self.allowed_globals.push(name.into());
+        self
+    }
+}
+
+pub(crate) fn protect_globals(lua: &Lua, protection: GlobalsProtection) -> Result<()> {
+    let globals = lua.globals();
+
+    if protection.freeze_stdlib {
+        freeze_stdlib(lua, &globals)?;
+    }
+
+    if !(protection.lock_metatable || protection.error_on_new_globals) {
+        return Ok(());
+    }
+
+    let mt = match globals.get_metatable() {
+        Some(mt) => mt,
+        None => {
+            let mt = lua.create_table_with_capacity(0, 2)?;
+            globals.set_metatable(Some(mt.clone()));
+            mt
+        }
+    };
+
+    if protection.error_on_new_globals {
+        let allowed: FxHashSet<StdString> = protection.allowed_globals.into_iter().collect();
+        // Allowed globals are still created through the previous handler (if any)
+        let prev_newindex = lua.create_registry_value(mt.raw_get::<_, Value>("__newindex")?)?;
+        let newindex = lua.create_function(move |lua, (t, k, v): (Table, Value, Value)| {
+            let is_allowed = match &k {
+                Value::String(name) => matches!(name.to_str(), Ok(name) if allowed.contains(name)),
+                _ => false,
+            };
+            if !is_allowed {
+                return Err(Error::runtime(format!(
+                    "assignment to undeclared global '{}'",
+                    k.to_string()?
+                )));
+            }
+            match lua.registry_value(&prev_newindex)? {
+                Value::Function(f) => f.call((t, k, v)),
+                Value::Table(newindex) => newindex.set(k, v),
+                _ => t.raw_set(k, v),
+            }
+        })?;
+        mt.raw_set("__newindex", newindex)?;
+    }
+
+    if protection.lock_metatable {
+        mt.raw_set("__metatable", false)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "luau")]
+fn freeze_stdlib(_lua: &Lua, globals: &Table) -> Result<()> {
+    for &name in STDLIB_TABLES {
+        if let Value::Table(t) = globals.raw_get(name)? {
+            t.set_readonly(true);
+        }
+    }
+    Ok(())
+}
+
+// Replaces each library table with an empty read-only proxy which forwards reads to the original
+#[cfg(not(feature = "luau"))]
+fn freeze_stdlib(lua: &Lua, globals: &Table) -> Result<()> {
+    let loaded = match globals.raw_get("package")? {
+        Value::Table(package) => match package.raw_get("loaded")? {
+            Value::Table(loaded) => Some(loaded),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    for &name in STDLIB_TABLES {
+        let table = match globals.raw_get(name)? {
+            Value::Table(t) if t.get_metatable().is_none() => t,
+            _ => continue,
+        };
+
+        let mt = lua.create_table_with_capacity(0, 5)?;
+        mt.raw_set("__index", table.clone())?;
+        let newindex = lua.create_function(move |_, (_, k): (Value, Value)| -> Result<()> {
+            Err(Error::runtime(format!(
+                "attempt to modify read-only table '{name}' (field '{}')",
+                k.to_string()?
+            )))
+        })?;
+        mt.raw_set("__newindex", newindex)?;
+        let pairs = lua.create_function(|lua, proxy: Table| {
+            let table = match proxy.get_metatable() {
+                Some(mt) => mt.raw_get::<_, Table>("__index")?,
+                None => proxy,
+            };
+            Ok((lua.globals().raw_get::<_, Value>("next")?, table))
+        })?;
+        mt.raw_set("__pairs", pairs)?;
+        mt.raw_set("__metatable", false)?;
+
+        let proxy = lua.create_table()?;
+        proxy.set_metatable(Some(mt));
+        globals.raw_set(name, proxy.clone())?;
+        if let Some(loaded) = &loaded {
+            if loaded.raw_get::<_, Value>(name)? == Value::Table(table) {
+                loaded.raw_set(name, proxy)?;
+            }
+        }
+    }
+
+    // Strings share the original `string` table through their metatable
+    lock_string_metatable(lua)
+}
+
+#[cfg(not(feature = "luau"))]
+fn lock_string_metatable(lua: &Lua) -> Result<()> {
+    let state = lua.state();
+    let mt = unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 2)?;
+
+        ffi::lua_pushstring(state, cstr!(""));
+        if ffi::lua_getmetatable(state, -1) == 0 {
+            return Ok(());
+        }
+        Table(lua.pop_ref())
+    };
+    mt.raw_set("__metatable", false)
+}
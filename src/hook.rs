@@ -7,7 +7,9 @@ use std::os::raw::c_int;
 use ffi::lua_Debug;
 
 use crate::lua::Lua;
-use crate::util::{linenumber_to_usize, ptr_to_lossy_str, ptr_to_str};
+use crate::util::{
+    assert_stack, describe_named_callback, linenumber_to_usize, ptr_to_lossy_str, ptr_to_str,
+};
 
 /// Contains information about currently executing Lua code.
 ///
@@ -65,18 +67,25 @@ impl<'lua> Debug<'lua> {
     }
 
     /// Corresponds to the `n` what mask.
+    ///
+    /// Rust callbacks created by [`Lua::create_named_function`] are named by their display name.
+    ///
+    /// [`Lua::create_named_function`]: crate::Lua::create_named_function
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn names(&self) -> DebugNames {
         unsafe {
+            assert_stack(self.lua.state(), 4);
             #[cfg(not(feature = "luau"))]
             mlua_assert!(
-                ffi::lua_getinfo(self.lua.state(), cstr!("n"), self.ar.get()) != 0,
-                "lua_getinfo failed with `n`"
+                ffi::lua_getinfo(self.lua.state(), cstr!("nf"), self.ar.get()) != 0,
+                "lua_getinfo failed with `nf`"
             );
             #[cfg(feature = "luau")]
             mlua_assert!(
-                ffi::lua_getinfo(self.lua.state(), self.level, cstr!("n"), self.ar.get()) != 0,
-                "lua_getinfo failed with `n`"
+                ffi::lua_getinfo(self.lua.state(), self.level, cstr!("nf"), self.ar.get()) != 0,
+                "lua_getinfo failed with `nf`"
             );
+            self.describe_named_callback();
 
             DebugNames {
                 name: ptr_to_lossy_str((*self.ar.get()).name),
@@ -92,18 +101,26 @@ impl<'lua> Debug<'lua> {
     }
 
     /// Corresponds to the `S` what mask.
+    ///
+    /// The source of Rust callbacks created by [`Lua::create_named_function`] is the location
+    /// where they were created.
+    ///
+    /// [`Lua::create_named_function`]: crate::Lua::create_named_function
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn source(&self) -> DebugSource {
         unsafe {
+            assert_stack(self.lua.state(), 4);
             #[cfg(not(feature = "luau"))]
             mlua_assert!(
-                ffi::lua_getinfo(self.lua.state(), cstr!("S"), self.ar.get()) != 0,
-                "lua_getinfo failed with `S`"
+                ffi::lua_getinfo(self.lua.state(), cstr!("Sf"), self.ar.get()) != 0,
+                "lua_getinfo failed with `Sf`"
             );
             #[cfg(feature = "luau")]
             mlua_assert!(
-                ffi::lua_getinfo(self.lua.state(), self.level, cstr!("s"), self.ar.get()) != 0,
-                "lua_getinfo failed with `s`"
+                ffi::lua_getinfo(self.lua.state(), self.level, cstr!("sf"), self.ar.get()) != 0,
+                "lua_getinfo failed with `sf`"
             );
+            self.describe_named_callback();
 
             DebugSource {
                 source: ptr_to_lossy_str((*self.ar.get()).source),
@@ -186,6 +203,15 @@ impl<'lua> Debug<'lua> {
     }
 }
 
+impl<'lua> Debug<'lua> {
+    // Pops the function pushed by the `f` what mask, describing it if it's a named Rust callback
+    unsafe fn describe_named_callback(&self) {
+        let state = self.lua.state();
+        describe_named_callback(state, self.ar.get());
+        ffi::lua_pop(state, 1);
+    }
+}
+
 enum ActivationRecord {
     #[cfg(not(feature = "luau"))]
     Borrowed(*mut lua_Debug),
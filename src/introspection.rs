@@ -0,0 +1,79 @@
+use std::string::String as StdString;
+
+use crate::userdata_impl::UserDataRegistry;
+use crate::util::short_type_name;
+
+/// Metadata of a userdata type registered in a Lua instance.
+///
+/// See [`Lua::registered_types`].
+///
+/// [`Lua::registered_types`]: crate::Lua::registered_types
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisteredType {
+    /// Name of the type (the `__name` metatable field).
+    pub name: StdString,
+    /// Names of the fields, including fields with getters or setters.
+    pub fields: Vec<StdString>,
+    /// Methods and functions, in registration order.
+    pub methods: Vec<RegisteredFunction>,
+    /// Metamethods, in registration order.
+    pub meta_methods: Vec<RegisteredFunction>,
+}
+
+/// Metadata of a Rust function registered in a Lua instance.
+///
+/// See [`Lua::registered_functions`] and [`Lua::registered_types`].
+///
+/// [`Lua::registered_functions`]: crate::Lua::registered_functions
+/// [`Lua::registered_types`]: crate::Lua::registered_types
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegisteredFunction {
+    /// Name of the function, or `None` if the function is anonymous.
+    pub name: Option<StdString>,
+    /// Number of arguments (excluding `self` for methods), or `None` if it's not fixed
+    /// (eg. the function takes [`Variadic`] or [`MultiValue`] arguments).
+    ///
+    /// [`Variadic`]: crate::Variadic
+    /// [`MultiValue`]: crate::MultiValue
+    pub nargs: Option<usize>,
+    /// Whether the function is asynchronous.
+    pub is_async: bool,
+}
+
+// Collects metadata of the type before its registry is consumed to build the metatable
+pub(crate) fn registered_type<T: 'static>(registry: &UserDataRegistry<T>) -> RegisteredType {
+    let mut fields = Vec::new();
+    let field_names = (registry.fields.iter())
+        .chain(&registry.field_getters)
+        .chain(&registry.field_setters)
+        .map(|(name, _)| name);
+    for name in field_names {
+        if !fields.contains(name) {
+            fields.push(name.clone());
+        }
+    }
+
+    let function = |name: &StdString, is_async| RegisteredFunction {
+        name: Some(name.clone()),
+        // The last method added with the name replaces the previous ones
+        nargs: (registry.nargs.iter().rev())
+            .find(|(n, _)| n == name)
+            .and_then(|&(_, nargs)| nargs),
+        is_async,
+    };
+    let methods = (registry.methods.iter()).map(|(name, _)| function(name, false));
+    let meta_methods = (registry.meta_methods.iter()).map(|(name, _)| function(name, false));
+    #[cfg(feature = "async")]
+    let methods =
+        methods.chain((registry.async_methods.iter()).map(|(name, _)| function(name, true)));
+    #[cfg(feature = "async")]
+    let meta_methods = meta_methods
+        .chain((registry.async_meta_methods.iter()).map(|(name, _)| function(name, true)));
+
+    RegisteredType {
+        name: short_type_name::<T>(),
+        fields,
+        methods: methods.collect(),
+        meta_methods: meta_methods.collect(),
+    }
+}
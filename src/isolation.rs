@@ -0,0 +1,169 @@
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::Value;
+
+// Restores the environment state when isolated execution ends (even by panic)
+struct IsolationGuard<'lua> {
+    lua: &'lua Lua,
+    globals: Table<'lua>,
+    globals_snapshot: Table<'lua>,
+    metatable: Option<Table<'lua>>,
+    metatable_snapshot: Option<Table<'lua>>,
+    registry_readonly: bool,
+    finished: bool,
+}
+
+impl<'lua> IsolationGuard<'lua> {
+    // Restores the environment, returning names of the modified fields
+    fn finish(&mut self) -> Result<Vec<StdString>> {
+        self.finished = true;
+        self.globals.set_metatable(self.metatable.clone());
+        self.lua.set_registry_readonly(self.registry_readonly);
+
+        let mut modified = restore(&self.globals, &self.globals_snapshot)?;
+        if let (Some(mt), Some(mt_snapshot)) = (&self.metatable, &self.metatable_snapshot) {
+            let fields = restore(mt, mt_snapshot)?;
+            modified.extend(fields.into_iter().map(|field| format!("metatable.{field}")));
+        }
+        Ok(modified)
+    }
+}
+
+impl<'lua> Drop for IsolationGuard<'lua> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finish();
+        }
+    }
+}
+
+impl Lua {
+    /// Runs `f` in an exclusive "frozen world" mode, ensuring it has no side effects on the
+    /// environment.
+    ///
+    /// While `f` runs:
+    /// - scripts cannot create new globals (assignments raise an error)
+    /// - the globals metatable cannot be read or replaced by scripts
+    /// - writes of named registry values (e.g. [`Lua::set_named_registry_value`]) return an
+    ///   error. Values owned by a [`RegistryKey`] (e.g. created using
+    ///   [`Lua::create_registry_value`]) are not part of the environment and can be stored.
+    ///
+    /// Afterwards, the globals table and its metatable are validated against the snapshot taken
+    /// before the call. Any changes (including raw assignments and modified existing globals)
+    /// are reverted and reported as an error, so a successful result proves that `f` did not
+    /// modify the environment. Mutations of values reachable from the globals (e.g. fields of
+    /// library tables or tables passed to `f`) are not tracked.
+    ///
+    /// If `f` fails, the environment is restored and its error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("limit", 10)?;
+    ///
+    /// let value = lua.run_isolated(|lua| lua.load("limit * 2").eval::<i32>())?;
+    /// assert_eq!(value, 20);
+    ///
+    /// assert!(lua.run_isolated(|lua| lua.load("counter = 1").exec()).is_err());
+    /// assert!(lua.run_isolated(|lua| lua.load("rawset(_G, 'limit', 0)").exec()).is_err());
+    /// assert_eq!(lua.globals().get::<_, i32>("limit")?, 10);
+    /// assert_eq!(lua.globals().get::<_, Option<i32>>("counter")?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RegistryKey`]: crate::RegistryKey
+    pub fn run_isolated<'lua, R>(&'lua self, f: impl FnOnce(&'lua Lua) -> Result<R>) -> Result<R> {
+        let globals = self.globals();
+        let metatable = globals.get_metatable();
+        let globals_snapshot = copy_table(self, &globals)?;
+        let metatable_snapshot = match &metatable {
+            Some(mt) => Some(copy_table(self, mt)?),
+            None => None,
+        };
+
+        // The isolation metatable keeps the original metamethods, except `__newindex`
+        let isolation_mt = match &metatable_snapshot {
+            Some(mt) => copy_table(self, mt)?,
+            None => self.create_table_with_capacity(0, 2)?,
+        };
+        let newindex = self.create_function(|_, (_, key): (Value, Value)| -> Result<()> {
+            Err(Error::runtime(format!(
+                "attempt to create global '{}' during isolated execution",
+                key.to_string()?
+            )))
+        })?;
+        isolation_mt.raw_set("__newindex", newindex)?;
+        if isolation_mt.raw_get::<_, Value>("__metatable")?.is_nil() {
+            isolation_mt.raw_set("__metatable", false)?;
+        }
+
+        let mut guard = IsolationGuard {
+            lua: self,
+            globals: globals.clone(),
+            globals_snapshot,
+            metatable,
+            metatable_snapshot,
+            registry_readonly: self.set_registry_readonly(true),
+            finished: false,
+        };
+        globals.set_metatable(Some(isolation_mt));
+        let result = f(self);
+        let modified = guard.finish()?;
+
+        let result = result?;
+        if !modified.is_empty() {
+            return Err(Error::runtime(format!(
+                "isolated execution modified globals: {}",
+                modified.join(", ")
+            )));
+        }
+        Ok(result)
+    }
+}
+
+fn copy_table<'lua>(lua: &'lua Lua, table: &Table<'lua>) -> Result<Table<'lua>> {
+    let copy = lua.create_table()?;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        copy.raw_set(key, value)?;
+    }
+    Ok(copy)
+}
+
+// Reverts `table` to the `snapshot` fields, returning names of the modified fields
+fn restore(table: &Table, snapshot: &Table) -> Result<Vec<StdString>> {
+    let mut modified = Vec::new();
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if !same_value(&snapshot.raw_get(key.clone())?, &value) {
+            modified.push(key);
+        }
+    }
+    for pair in snapshot.clone().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        if table.raw_get::<_, Value>(key.clone())?.is_nil() {
+            modified.push(key);
+        }
+    }
+
+    let mut names = Vec::with_capacity(modified.len());
+    for key in modified {
+        names.push(key.to_string()?);
+        table.raw_set(key.clone(), snapshot.raw_get::<_, Value>(key)?)?;
+    }
+    Ok(names)
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) if a.is_nan() && b.is_nan() => true,
+        _ => a == b,
+    }
+}
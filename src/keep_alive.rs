@@ -0,0 +1,236 @@
+use std::cell::{Cell, RefCell};
+use std::os::raw::c_void;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rustc_hash::FxHashMap;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::{LuaRef, RegistryKey, SubtypeId};
+use crate::userdata::AnyUserData;
+use crate::util::{check_stack, StackGuard};
+use crate::weak::WeakMode;
+
+// Number of userdata with dependencies in all states, to skip lookups when there are none
+static TRACKED: AtomicUsize = AtomicUsize::new(0);
+
+// Dependencies between userdata declared by `AnyUserData::keep_alive_with`, stored in `ExtraData`
+#[derive(Default)]
+pub(crate) struct KeepAlive {
+    // Userdata with dependencies (in either direction), keyed by pointer
+    nodes: FxHashMap<*const c_void, Rc<Node>>,
+    // Ephemeron table mapping userdata to the set of userdata they keep alive
+    table: Option<RegistryKey>,
+}
+
+#[derive(Default)]
+struct Node {
+    // Number of dependents which values are not dropped yet
+    dependents: Cell<usize>,
+    // Userdata this one keeps alive
+    parents: RefCell<Vec<Rc<Node>>>,
+    // Set when the userdata is collected (or its value is taken out)
+    finalized: Cell<bool>,
+    // Value of the collected userdata, waiting for its dependents to be dropped
+    deferred: RefCell<Option<DeferredDrop>>,
+}
+
+// Type-erased boxed value
+struct DeferredDrop {
+    ptr: *mut c_void,
+    drop: unsafe fn(*mut c_void),
+}
+
+impl DeferredDrop {
+    #[cfg(not(feature = "luau"))]
+    fn new<T>(value: T) -> Self {
+        unsafe fn drop_boxed<T>(ptr: *mut c_void) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+        DeferredDrop {
+            ptr: Box::into_raw(Box::new(value)) as *mut c_void,
+            drop: drop_boxed::<T>,
+        }
+    }
+}
+
+impl Drop for DeferredDrop {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.ptr) };
+    }
+}
+
+impl Node {
+    fn finalize(&self, value: Option<DeferredDrop>) {
+        self.finalized.set(true);
+        *self.deferred.borrow_mut() = value;
+        self.release();
+    }
+
+    // Drops the value once all dependents are dropped, then releases the userdata kept alive
+    fn release(&self) {
+        if !self.finalized.get() || self.dependents.get() > 0 {
+            return;
+        }
+        let value = self.deferred.borrow_mut().take();
+        drop(value);
+        for parent in self.parents.take() {
+            parent.dependents.set(parent.dependents.get() - 1);
+            parent.release();
+        }
+    }
+
+    // Checks whether this node (transitively) keeps `other` alive
+    #[cfg(not(feature = "luau"))]
+    fn depends_on(&self, other: &Rc<Node>) -> bool {
+        (self.parents.borrow().iter()).any(|p| Rc::ptr_eq(p, other) || p.depends_on(other))
+    }
+}
+
+impl KeepAlive {
+    #[cfg(not(feature = "luau"))]
+    fn node(&mut self, ptr: *const c_void) -> Rc<Node> {
+        let node = self.nodes.entry(ptr).or_insert_with(|| {
+            TRACKED.fetch_add(1, Ordering::Relaxed);
+            Rc::default()
+        });
+        node.clone()
+    }
+
+    fn take_node(&mut self, ptr: *const c_void) -> Option<Rc<Node>> {
+        let node = self.nodes.remove(&ptr)?;
+        TRACKED.fetch_sub(1, Ordering::Relaxed);
+        Some(node)
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        TRACKED.fetch_sub(self.nodes.len(), Ordering::Relaxed);
+    }
+}
+
+// Drops the value of a userdata collected by Lua, after the values of userdata depending on it
+#[cfg(not(feature = "luau"))]
+pub(crate) unsafe fn drop_userdata<T>(state: *mut ffi::lua_State, ptr: *const c_void, value: T) {
+    let node = match TRACKED.load(Ordering::Relaxed) {
+        0 => None,
+        _ => match crate::lua::state_keep_alive(state) {
+            keep_alive if keep_alive.is_null() => None,
+            keep_alive => (*keep_alive).take_node(ptr),
+        },
+    };
+    match node {
+        Some(node) if node.dependents.get() > 0 => node.finalize(Some(DeferredDrop::new(value))),
+        Some(node) => {
+            drop(value);
+            node.finalize(None);
+        }
+        None => drop(value),
+    }
+}
+
+impl Lua {
+    // Releases the userdata kept alive by a userdata which value was taken out
+    pub(crate) fn release_keep_alive(&self, ud: &LuaRef) {
+        let keep_alive = unsafe { &mut *self.keep_alive() };
+        let key = match &keep_alive.table {
+            Some(key) => key,
+            None => return,
+        };
+        if let Ok(table) = self.registry_value::<Table>(key) {
+            let _ = table.raw_remove(AnyUserData(ud.clone(), SubtypeId::None));
+        }
+        if let Some(node) = keep_alive.take_node(ud.to_pointer()) {
+            node.finalize(None);
+        }
+    }
+
+    fn keep_alive_table(&self) -> Result<Table<'_>> {
+        let keep_alive = unsafe { &mut *self.keep_alive() };
+        if let Some(key) = &keep_alive.table {
+            return self.registry_value(key);
+        }
+        let table = self.create_weak_table(WeakMode::Keys)?;
+        keep_alive.table = Some(self.create_registry_value(table.clone())?);
+        Ok(table)
+    }
+}
+
+impl<'lua> AnyUserData<'lua> {
+    /// Keeps `other` userdata alive as long as this userdata is alive.
+    ///
+    /// Declares that this userdata depends on `other`, eg. a statement handle on the connection
+    /// owning it: `other` is not collected while this userdata is reachable, and when both become
+    /// unreachable in the same garbage collection cycle, the value of this userdata is dropped
+    /// first, regardless of the order Lua runs their finalizers. This way `Drop` implementations
+    /// can rely on the state of the values they depend on.
+    ///
+    /// Dependencies are released when this userdata is collected, or its value is taken out using
+    /// [`AnyUserData::take`]. Declaring the same dependency again has no effect, and circular
+    /// dependencies are rejected.
+    ///
+    /// In Luau, userdata values are dropped when their memory is freed, in unspecified order.
+    /// Dependencies only keep `other` alive there.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData};
+    /// # fn main() -> Result<()> {
+    /// struct Connection;
+    /// struct Statement;
+    /// impl UserData for Connection {}
+    /// impl UserData for Statement {}
+    ///
+    /// let lua = Lua::new();
+    /// let conn = lua.create_userdata(Connection)?;
+    /// let stmt = lua.create_userdata(Statement)?;
+    /// stmt.keep_alive_with(&conn)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn keep_alive_with(&self, other: &AnyUserData) -> Result<()> {
+        let lua = self.0.lua;
+        // Check that both userdata are valid
+        unsafe {
+            let state = lua.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 2)?;
+            lua.push_userdata_ref(&self.0)?;
+            lua.push_userdata_ref(&other.0)?;
+        }
+        let (ptr, other_ptr) = (self.to_pointer(), other.to_pointer());
+        if ptr == other_ptr {
+            return Err(Error::runtime("userdata cannot keep itself alive"));
+        }
+
+        let table = lua.keep_alive_table()?;
+        let parents = match table.raw_get::<_, Option<Table>>(self.clone())? {
+            Some(parents) => parents,
+            None => {
+                let parents = lua.create_table()?;
+                table.raw_set(self.clone(), parents.clone())?;
+                parents
+            }
+        };
+        if parents.contains_key(other.clone())? {
+            return Ok(());
+        }
+
+        #[cfg(not(feature = "luau"))]
+        {
+            let keep_alive = unsafe { &mut *lua.keep_alive() };
+            let (node, other_node) = (keep_alive.node(ptr), keep_alive.node(other_ptr));
+            if other_node.depends_on(&node) {
+                return Err(Error::runtime("circular userdata dependency"));
+            }
+            other_node.dependents.set(other_node.dependents.get() + 1);
+            node.parents.borrow_mut().push(other_node);
+        }
+        parents.raw_set(other.clone(), true)
+    }
+}
@@ -79,54 +79,172 @@
 #[macro_use]
 mod macros;
 
+mod args;
+#[cfg(feature = "async")]
+mod async_guard;
+#[cfg(feature = "async")]
+mod async_scope;
+mod audit;
+mod callable;
 mod chunk;
+mod coercion;
 mod conversion;
+mod conversion_limits;
+mod conversion_trace;
+#[cfg(feature = "time")]
+mod datetime;
+mod dedup;
+mod deprecation;
+mod display;
+#[cfg(not(feature = "luau"))]
+mod env;
 mod error;
+#[cfg(feature = "export")]
+mod export;
+mod foreign;
+#[cfg(feature = "fs")]
+mod fs;
 mod function;
+#[cfg(not(feature = "luau"))]
+mod function_stats;
+#[cfg(feature = "async")]
+mod generator;
+mod global_handle;
+mod globals;
 mod hook;
+mod introspection;
+mod isolation;
+mod keep_alive;
+#[cfg(not(feature = "luau"))]
+mod loaded_modules;
+mod loaders;
 mod lua;
+mod lua_enum;
+mod lua_id;
 #[cfg(feature = "luau")]
 mod luau;
 mod memory;
+mod memory_pressure;
+mod metatable;
+mod middleware;
+mod module;
 mod multi;
+mod pool;
+mod prelude_builder;
+#[cfg(feature = "process")]
+mod process;
+mod quota;
+#[cfg(feature = "regex")]
+mod regex;
+#[cfg(not(feature = "send"))]
+mod registration;
+mod remote;
+mod safe_debug;
+#[cfg(feature = "async")]
+mod scheduler;
+mod schema;
 mod scope;
+#[cfg(all(feature = "async", feature = "send"))]
+mod shared;
+#[cfg(feature = "signing")]
+mod signing;
+mod stack;
+mod stdio;
 mod stdlib;
 mod string;
+#[cfg(feature = "luajit")]
+mod string_buffer;
+mod string_builder;
+mod string_pack;
 mod table;
+mod table_observe;
+mod table_proxy;
 mod thread;
+#[cfg(feature = "trace_events")]
+mod trace_events;
 mod types;
 mod userdata;
 mod userdata_ext;
 mod userdata_impl;
+#[cfg(feature = "send")]
+mod userdata_lock;
 mod util;
 mod value;
+mod version;
+mod weak;
 
 pub mod prelude;
 
 pub use ffi::{self, lua_CFunction, lua_State};
 
+pub use crate::args::Args;
+pub use crate::audit::{AuditEvent, SandboxAudit};
 pub use crate::chunk::{AsChunk, Chunk, ChunkMode};
-pub use crate::error::{Error, ErrorContext, ExternalError, ExternalResult, Result};
-pub use crate::function::{Function, FunctionInfo};
+pub use crate::coercion::CoercionPolicy;
+pub use crate::conversion_limits::ConversionLimits;
+pub use crate::deprecation::{Deprecation, DeprecationPolicy};
+pub use crate::display::ValueDisplay;
+pub use crate::error::{
+    ArgumentDetails, ConversionErrorInfo, Error, ErrorContext, ExternalError, ExternalResult,
+    Result, SourceLocation,
+};
+#[cfg(feature = "export")]
+#[cfg_attr(docsrs, doc(cfg(feature = "export")))]
+pub use crate::export::LuaExport;
+pub use crate::foreign::{ForeignLock, ForeignLua, ForeignLuaGuard, StateOwnership};
+pub use crate::function::{Function, FunctionInfo, PcallResult, TraceFrame, TracedError};
+pub use crate::global_handle::GlobalHandle;
+pub use crate::globals::GlobalsProtection;
 pub use crate::hook::{Debug, DebugEvent, DebugNames, DebugSource, DebugStack};
-pub use crate::lua::{GCMode, Lua, LuaOptions};
+pub use crate::introspection::{RegisteredFunction, RegisteredType};
+pub use crate::loaders::Loaders;
+pub use crate::lua::{GCMode, GcEvent, Lua, LuaBuilder, LuaOptions};
+pub use crate::lua_enum::LuaEnum;
+pub use crate::lua_id::LuaId;
+pub use crate::metatable::MetatableBuilder;
+pub use crate::middleware::{CallbackCtx, CallbackNext};
+pub use crate::module::LuaModule;
 pub use crate::multi::Variadic;
+pub use crate::pool::{LuaPool, PooledLua};
+pub use crate::prelude_builder::PreludeBuilder;
+pub use crate::quota::{Quota, QuotaResource};
+#[cfg(not(feature = "send"))]
+pub use crate::registration::RegistrationSet;
+pub use crate::remote::RemoteFunction;
+pub use crate::schema::{Schema, SchemaField, SchemaType, Violation};
 pub use crate::scope::Scope;
+pub use crate::stack::{Stack, StackStats};
 pub use crate::stdlib::StdLib;
-pub use crate::string::String;
-pub use crate::table::{Table, TableExt, TablePairs, TableSequence};
-pub use crate::thread::{Thread, ThreadStatus};
-pub use crate::types::{AppDataRef, AppDataRefMut, Integer, LightUserData, Number, RegistryKey};
+pub use crate::string::{BorrowedStr, String};
+pub use crate::string_builder::StringBuilder;
+pub use crate::table::{Table, TableExt, TablePairs, TableSequence, TypedTable};
+pub use crate::table_observe::TableEvent;
+pub use crate::table_proxy::TableProxy;
+pub use crate::thread::{ResumeOutcome, Thread, ThreadIter, ThreadPool, ThreadStatus};
+pub use crate::types::{
+    AppDataRef, AppDataRefMut, Integer, LightUserData, Number, RegistryKey, TypedLightUserData,
+};
 pub use crate::userdata::{
     AnyUserData, MetaMethod, UserData, UserDataFields, UserDataMetatable, UserDataMethods,
-    UserDataRef, UserDataRefMut,
+    UserDataRef, UserDataRefMut, UserDataStats,
 };
 pub use crate::userdata_ext::AnyUserDataExt;
 pub use crate::userdata_impl::UserDataRegistry;
-pub use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil, Value};
+pub use crate::value::{
+    ArithOp, BorrowedValue, CompareOp, FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue,
+    Nil, Value, ValueId,
+};
+pub use crate::version::LuaVersion;
+pub use crate::weak::{WeakCache, WeakMode};
 
 #[cfg(not(feature = "luau"))]
-pub use crate::hook::HookTriggers;
+pub use crate::{
+    env::{DenyEnv, EnvProvider},
+    function_stats::FunctionStats,
+    hook::HookTriggers,
+    loaded_modules::LoadedModules,
+    lua::ExitAction,
+};
 
 #[cfg(any(feature = "luau", doc))]
 #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
@@ -136,15 +254,68 @@ pub use crate::{
     types::{Vector, VmState},
 };
 
+#[cfg(feature = "luau")]
+#[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+pub use crate::luau::ModuleGraph;
+
+#[cfg(feature = "luajit")]
+#[cfg_attr(docsrs, doc(cfg(feature = "luajit")))]
+pub use crate::string_buffer::StringBuffer;
+
 #[cfg(feature = "async")]
-pub use crate::thread::AsyncThread;
+pub use crate::{
+    async_guard::AsyncConcurrency,
+    generator::{YieldValues, Yielder},
+    scheduler::{LuaScheduler, TaskId},
+    thread::{AsyncSchedulerHooks, AsyncThread},
+};
+
+#[cfg(feature = "trace_events")]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace_events")))]
+pub use crate::trace_events::TraceEvents;
+
+#[cfg(feature = "trace_conversions")]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace_conversions")))]
+pub use crate::conversion_trace::{ConversionDirection, ConversionTrace};
+
+#[cfg(feature = "send")]
+#[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+pub use crate::{
+    remote::RemoteLua,
+    userdata_lock::{UserDataGuard, UserDataGuardMut},
+};
+
+#[cfg(all(feature = "async", feature = "send"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "async", feature = "send"))))]
+pub use crate::shared::{LuaLockGuard, SharedLua, SharedLuaGuard};
+
+#[cfg(feature = "time")]
+#[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+pub use crate::datetime::DateTime;
+
+#[cfg(feature = "fs")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fs")))]
+pub use crate::fs::FsPolicy;
+
+#[cfg(feature = "signing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "signing")))]
+pub use {crate::signing::CompiledChunk, ed25519_dalek};
+
+#[cfg(feature = "process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "process")))]
+pub use crate::process::ProcessPolicy;
 
 #[cfg(feature = "serialize")]
 #[doc(inline)]
 pub use crate::serde::{
     de::Options as DeserializeOptions, ser::Options as SerializeOptions, LuaSerdeExt,
+    UserDataSerialize,
 };
 
+#[cfg(any(feature = "json", feature = "msgpack"))]
+#[doc(inline)]
+pub use crate::serde::SerializeFormat;
+
 #[cfg(feature = "serialize")]
 #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
 pub mod serde;
@@ -226,7 +397,7 @@ pub use mlua_derive::chunk;
 pub use mlua_derive::FromLua;
 
 /// Derive [`ToLua`] for a Rust type.
-/// 
+///
 /// Nested types require [`IntoLua`] as well
 #[cfg(feature = "macros")]
 #[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
@@ -240,6 +411,176 @@ pub use mlua_derive::ToLuaTable;
 #[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
 pub use mlua_derive::FromLuaTable;
 
+/// Derive [`IntoLuaMulti`] for a struct, returning its fields as multiple Lua values.
+///
+/// Fields are converted using [`IntoLua`] in declaration order, which is convenient for the
+/// `return ok, value, err` idiom.
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::IntoLuaMulti;
+
+/// Derive [`FromLuaMulti`] for a struct, filling its fields from multiple Lua values.
+///
+/// Fields are converted using [`FromLua`] in declaration order. Missing values are treated as
+/// `nil` and extra values are ignored.
+///
+/// Attributes allow accepting values from evolving scripts that add return values over time:
+/// - `#[lua(default)]` on the struct fills every field without a value using [`Default`].
+/// - `#[lua(default)]` on a field does the same for this field only, and
+///   `#[lua(default = "path")]` calls the given function instead.
+/// - `#[lua(rest)]` on the last field collects the remaining values (eg. into [`Variadic`] or
+///   [`MultiValue`]).
+///
+/// Only missing values fall back to defaults, an explicit `nil` is converted as usual.
+///
+/// ```
+/// use mlua::{FromLuaMulti, Lua, Result, Variadic};
+///
+/// #[derive(FromLuaMulti)]
+/// struct Response {
+///     status: u16,
+///     #[lua(default)]
+///     body: String,
+///     #[lua(default = "default_retries")]
+///     retries: u32,
+///     #[lua(rest)]
+///     extra: Variadic<String>,
+/// }
+///
+/// fn default_retries() -> u32 {
+///     3
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let resp: Response = lua.load("return 200").eval()?;
+/// assert_eq!((resp.status, resp.body.as_str(), resp.retries), (200, "", 3));
+///
+/// let resp: Response = lua.load("return 404, 'not found', 1, 'a', 'b'").eval()?;
+/// assert_eq!((resp.body.as_str(), resp.retries, resp.extra.len()), ("not found", 1, 2));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::FromLuaMulti;
+
+/// Derive [`LuaModule`] for a struct of functions.
+///
+/// Every field is registered as a Lua function with the same name. Fields must be functions
+/// accepted by [`Lua::create_function`] (usually function pointers).
+///
+/// Field attributes:
+/// - `#[lua(rename = "name")]` registers the field under a different name.
+/// - `#[lua(module)]` registers a field implementing [`LuaModule`] as a nested table.
+///
+/// ```
+/// use mlua::{Lua, LuaModule, Result};
+///
+/// #[derive(LuaModule)]
+/// struct MathApi {
+///     add: fn(&Lua, (i64, i64)) -> Result<i64>,
+///     #[lua(rename = "abs")]
+///     absolute: fn(&Lua, i64) -> Result<i64>,
+///     #[lua(module)]
+///     consts: Consts,
+/// }
+///
+/// #[derive(LuaModule)]
+/// struct Consts {
+///     pi: fn(&Lua, ()) -> Result<f64>,
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.register_module("math2", MathApi {
+///     add: |_, (a, b)| Ok(a + b),
+///     absolute: |_, n| Ok(n.abs()),
+///     consts: Consts { pi: |_, ()| Ok(std::f64::consts::PI) },
+/// })?;
+/// lua.load("assert(math2.add(1, math2.abs(-2)) == 3 and math2.consts.pi() > 3)").exec()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::LuaModule;
+
+/// Derive [`LuaEnum`] (along with [`FromLua`] and [`IntoLua`]) for an enum with unit variants.
+///
+/// Variants are converted into their discriminants. The `FromLua` implementation accepts
+/// the discriminants and the variant names as well. The enum must implement [`Clone`].
+///
+/// Variant attributes:
+/// - `#[lua(rename = "name")]` exposes the variant under a different name.
+///
+/// ```
+/// use mlua::{Lua, LuaEnum, Result};
+///
+/// #[derive(Clone, Copy, Debug, PartialEq, LuaEnum)]
+/// enum Color {
+///     Red,
+///     Green,
+///     #[lua(rename = "BLUE")]
+///     Blue = 10,
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.register_enum::<Color>("Color")?;
+/// lua.globals().set("paint", lua.create_function(|_, color: Color| Ok(color.name()))?)?;
+///
+/// lua.load(r#"
+///     assert(Color.Green == 1 and Color.BLUE == 10 and Color[10] == "BLUE")
+///     assert(paint(Color.Red) == "Red" and paint("BLUE") == "BLUE")
+///     assert(not pcall(function() Color.Yellow = 3 end))
+/// "#).exec()?;
+/// assert_eq!(lua.load("Color.BLUE").eval::<Color>()?, Color::Blue);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::LuaEnum;
+
+/// Exports a Rust function to Lua, to be installed by [`Lua::register_exports`].
+///
+/// The function must be accepted by [`Lua::create_function`]. It's registered as a global
+/// with the function name, or the name given by the `name` option. Dot-separated names
+/// are registered in nested tables, which are created as needed.
+///
+/// Exports are collected from all linked crates, so bindings can be defined next to the code
+/// they wrap.
+///
+/// ```
+/// use mlua::{lua_export, Lua, Result};
+///
+/// #[lua_export]
+/// fn greet(_: &Lua, name: String) -> Result<String> {
+///     Ok(format!("Hello, {name}!"))
+/// }
+///
+/// #[lua_export(name = "strings.reverse")]
+/// fn reverse(_: &Lua, s: String) -> Result<String> {
+///     Ok(s.chars().rev().collect())
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.register_exports()?;
+/// lua.load(r#"assert(greet("Lua") == "Hello, Lua!" and strings.reverse("ab") == "ba")"#)
+///     .exec()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "export")]
+#[cfg_attr(docsrs, doc(cfg(feature = "export")))]
+pub use mlua_derive::lua_export;
+
+#[cfg(feature = "export")]
+#[doc(hidden)]
+pub use inventory as __inventory;
+
 /// Registers Lua module entrypoint.
 ///
 /// You can register multiple entrypoints as required.
@@ -268,6 +609,19 @@ pub use mlua_derive::FromLuaTable;
 /// }
 /// ```
 ///
+/// * skip_version_check - do not check the Lua interpreter version at load time.
+///
+/// By default the entrypoint inspects the host interpreter (see [`LuaVersion::detect`]) and
+/// raises a descriptive error if it does not match the Lua version the module was compiled for,
+/// instead of crashing on mismatched ABI.
+///
+/// ```ignore
+/// #[mlua::lua_module(skip_version_check)]
+/// fn my_module(lua: &Lua) -> Result<Table> {
+///     ...
+/// }
+/// ```
+///
 /// * skip_memory_check - skip memory allocation checks for some operations.
 ///
 /// In module mode, mlua runs in unknown environment and cannot say are there any memory
@@ -286,6 +640,10 @@ pub use mlua_derive::FromLuaTable;
 #[cfg_attr(docsrs, doc(cfg(feature = "module")))]
 pub use mlua_derive::lua_module;
 
+#[cfg(feature = "module")]
+#[doc(hidden)]
+pub use crate::version::check_runtime_version;
+
 pub(crate) mod private {
     use super::*;
 
@@ -296,4 +654,6 @@ pub(crate) mod private {
     impl Sealed for Lua {}
     impl Sealed for Table<'_> {}
     impl Sealed for AnyUserData<'_> {}
+    impl<K, V, S> Sealed for std::collections::HashMap<K, V, S> {}
+    impl<T> Sealed for Vec<T> {}
 }
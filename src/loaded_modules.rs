@@ -0,0 +1,302 @@
+use std::os::raw::c_void;
+use std::string::String as StdString;
+
+use rustc_hash::FxHashSet;
+
+use crate::chunk::ChunkMode;
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::{Integer, Number};
+use crate::util::{check_stack, StackGuard};
+use crate::value::Value;
+
+/// Snapshot of the pure Lua modules of `package.loaded`, to warm start new Lua states.
+///
+/// Created by [`Lua::export_loaded_modules`] and installed by [`Lua::import_loaded_modules`].
+///
+/// A module is exported only if it consists of plain data (booleans, numbers, strings and
+/// tables without metatables) and Lua functions, which are stored as bytecode. Upvalues of the
+/// functions can only refer to the global environment or to the module itself (eg. the `M`
+/// table of the `local M = {}` idiom), other modules (eg. standard libraries or modules with
+/// Rust functions) are skipped.
+///
+/// Requires `feature = "lua54/lua53/lua52/lua51/luajit"`
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.load(r#"
+///     package.preload["greet"] = function()
+///         local M = { greeting = "hello" }
+///         function M.greet(name) return M.greeting .. " " .. name end
+///         return M
+///     end
+///     require("greet")
+/// "#).exec()?;
+/// let modules = lua.export_loaded_modules()?;
+/// assert!(modules.names().any(|name| name == "greet"));
+///
+/// let lua2 = Lua::new();
+/// lua2.import_loaded_modules(&modules)?;
+/// let greeting: String = lua2.load(r#"require("greet").greet("world")"#).eval()?;
+/// assert_eq!(greeting, "hello world");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LoadedModules {
+    modules: Vec<(StdString, ModuleValue)>,
+}
+
+impl LoadedModules {
+    /// Returns names of the exported modules, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.modules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the number of exported modules.
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Returns `true` if no modules were exported.
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ModuleValue {
+    Nil,
+    Boolean(bool),
+    Integer(Integer),
+    Number(Number),
+    String(Vec<u8>),
+    Table(Vec<(ModuleValue, ModuleValue)>),
+    Function {
+        bytecode: Vec<u8>,
+        upvalues: Vec<ModuleValue>,
+    },
+    // The global environment
+    Globals,
+    // The module itself
+    Module,
+}
+
+// Converts a module to `ModuleValue`, returning `None` if the module is not pure Lua
+struct Exporter<'lua> {
+    globals: Table<'lua>,
+    module: *const c_void,
+    // Tables and functions must not be shared or cyclic, as their identity is not preserved
+    seen: FxHashSet<*const c_void>,
+}
+
+impl<'lua> Exporter<'lua> {
+    fn export(&mut self, value: Value<'lua>) -> Result<Option<ModuleValue>> {
+        let value = match value {
+            Value::Nil => ModuleValue::Nil,
+            Value::Boolean(b) => ModuleValue::Boolean(b),
+            Value::Integer(i) => ModuleValue::Integer(i),
+            Value::Number(n) => ModuleValue::Number(n),
+            Value::String(s) => ModuleValue::String(s.as_bytes().to_vec()),
+            Value::Table(t) if t.to_pointer() == self.globals.to_pointer() => ModuleValue::Globals,
+            Value::Table(t) if t.to_pointer() == self.module => ModuleValue::Module,
+            Value::Table(t) => return self.export_table(t),
+            Value::Function(f) => {
+                if f.info().what == "C" || !self.seen.insert(f.to_pointer()) {
+                    return Ok(None);
+                }
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                match f.environment() {
+                    Some(env) if env.to_pointer() == self.globals.to_pointer() => {}
+                    _ => return Ok(None),
+                }
+                let mut upvalues = Vec::new();
+                for value in unsafe { get_upvalues(&f)? } {
+                    match self.export(value)? {
+                        Some(
+                            value @ (ModuleValue::Nil | ModuleValue::Globals | ModuleValue::Module),
+                        ) => upvalues.push(value),
+                        _ => return Ok(None),
+                    }
+                }
+                ModuleValue::Function {
+                    bytecode: f.dump(false),
+                    upvalues,
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    fn export_table(&mut self, t: Table<'lua>) -> Result<Option<ModuleValue>> {
+        if t.get_metatable().is_some() || !self.seen.insert(t.to_pointer()) {
+            return Ok(None);
+        }
+        let mut pairs = Vec::new();
+        for pair in t.pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            match (self.export(key)?, self.export(value)?) {
+                (Some(key), Some(value)) => pairs.push((key, value)),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(ModuleValue::Table(pairs)))
+    }
+}
+
+struct Importer<'lua> {
+    lua: &'lua Lua,
+    globals: Table<'lua>,
+    module: Value<'lua>,
+}
+
+impl<'lua> Importer<'lua> {
+    fn import(&self, value: &ModuleValue) -> Result<Value<'lua>> {
+        let lua = self.lua;
+        Ok(match value {
+            ModuleValue::Nil => Value::Nil,
+            ModuleValue::Boolean(b) => Value::Boolean(*b),
+            ModuleValue::Integer(i) => Value::Integer(*i),
+            ModuleValue::Number(n) => Value::Number(*n),
+            ModuleValue::String(s) => Value::String(lua.create_string(s)?),
+            ModuleValue::Table(pairs) => {
+                let t = lua.create_table_with_capacity(0, pairs.len())?;
+                self.fill_table(&t, pairs)?;
+                Value::Table(t)
+            }
+            ModuleValue::Function { bytecode, upvalues } => {
+                let f = lua
+                    .load(bytecode.as_slice())
+                    .set_mode(ChunkMode::Binary)
+                    .into_function()?;
+                let upvalues = (upvalues.iter())
+                    .map(|value| self.import(value))
+                    .collect::<Result<Vec<_>>>()?;
+                unsafe { set_upvalues(&f, upvalues)? };
+                Value::Function(f)
+            }
+            ModuleValue::Globals => Value::Table(self.globals.clone()),
+            ModuleValue::Module => self.module.clone(),
+        })
+    }
+
+    fn fill_table(&self, t: &Table<'lua>, pairs: &[(ModuleValue, ModuleValue)]) -> Result<()> {
+        for (key, value) in pairs {
+            t.raw_set(self.import(key)?, self.import(value)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+unsafe fn get_upvalues<'lua>(f: &Function<'lua>) -> Result<Vec<Value<'lua>>> {
+    let lua = f.0.lua;
+    let state = lua.state();
+    let _sg = StackGuard::new(state);
+    check_stack(state, 3)?;
+
+    lua.push_ref(&f.0);
+    let mut upvalues = Vec::new();
+    for i in 1.. {
+        if ffi::lua_getupvalue(state, -1, i).is_null() {
+            break;
+        }
+        upvalues.push(lua.pop_value());
+    }
+    Ok(upvalues)
+}
+
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+unsafe fn set_upvalues<'lua>(f: &Function<'lua>, upvalues: Vec<Value<'lua>>) -> Result<()> {
+    let lua = f.0.lua;
+    let state = lua.state();
+    let _sg = StackGuard::new(state);
+    check_stack(state, 3)?;
+
+    lua.push_ref(&f.0);
+    for (i, value) in (1..).zip(upvalues) {
+        lua.push_value(value)?;
+        if ffi::lua_setupvalue(state, -2, i).is_null() {
+            return Err(Error::runtime(
+                "bytecode of the module does not match its upvalues",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn package_loaded(lua: &Lua) -> Result<Table<'_>> {
+    match lua.globals().raw_get::<_, Option<Table>>("package")? {
+        Some(package) => package.raw_get("loaded"),
+        None => Err(Error::runtime("package library is not loaded")),
+    }
+}
+
+impl Lua {
+    /// Exports the pure Lua modules of `package.loaded`, to install them in other states.
+    ///
+    /// See [`LoadedModules`] for the modules that can be exported.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/lua51/luajit"`
+    pub fn export_loaded_modules(&self) -> Result<LoadedModules> {
+        let globals = self.globals();
+        let mut modules = Vec::new();
+        for pair in package_loaded(self)?.pairs::<Value, Value>() {
+            let (name, module) = match pair? {
+                (Value::String(name), module) => (name.to_str()?.to_owned(), module),
+                _ => continue,
+            };
+            let mut exporter = Exporter {
+                globals: globals.clone(),
+                module: module.to_pointer(),
+                seen: FxHashSet::default(),
+            };
+            let module = match module {
+                // The global environment (`_G`) is not a module to export
+                Value::Table(t) if t.to_pointer() == globals.to_pointer() => continue,
+                // The module table itself is not a reference to the module
+                Value::Table(t) => exporter.export_table(t)?,
+                module => exporter.export(module)?,
+            };
+            if let Some(module) = module {
+                modules.push((name, module));
+            }
+        }
+        modules.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(LoadedModules { modules })
+    }
+
+    /// Installs modules exported by [`Lua::export_loaded_modules`] into `package.loaded`,
+    /// replacing already loaded modules with the same names.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/lua51/luajit"`
+    pub fn import_loaded_modules(&self, modules: &LoadedModules) -> Result<()> {
+        let loaded = package_loaded(self)?;
+        for (name, module) in &modules.modules {
+            let mut importer = Importer {
+                lua: self,
+                globals: self.globals(),
+                module: Value::Nil,
+            };
+            // Create the module table first, so it can be referenced by its functions
+            let module = match module {
+                ModuleValue::Table(pairs) => {
+                    let t = self.create_table_with_capacity(0, pairs.len())?;
+                    importer.module = Value::Table(t.clone());
+                    importer.fill_table(&t, pairs)?;
+                    Value::Table(t)
+                }
+                module => importer.import(module)?,
+            };
+            loaded.raw_set(name.as_str(), module)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::{Integer, MaybeSend};
+use crate::value::{IntoLuaMulti, Value};
+
+/// Module resolution settings of the `package` library: search paths and searchers.
+///
+/// Searchers are the functions `require` calls (in order) to find a loader for a module, kept in
+/// `package.searchers` (`package.loaders` in Lua 5.1, LuaJIT and Luau). They are indexed from `0`.
+///
+/// Returned by [`Lua::loaders`].
+#[derive(Clone, Debug)]
+pub struct Loaders<'lua> {
+    lua: &'lua Lua,
+    package: Table<'lua>,
+    searchers: Table<'lua>,
+}
+
+impl Lua {
+    /// Returns a handle to manage module resolution (`package.path`, `package.cpath` and the
+    /// searchers).
+    ///
+    /// Returns an error if the `package` library is not loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let loaders = lua.loaders()?;
+    ///
+    /// let mut path = loaders.path()?;
+    /// path.insert(0, "scripts/?.lua".to_string());
+    /// loaders.set_path(path)?;
+    ///
+    /// // Resolve modules from memory before looking at the filesystem
+    /// let searcher = lua.create_function(|lua, name: String| match name.as_str() {
+    ///     "greeting" => Ok(Some(lua.load("return 'hello'").into_function()?)),
+    ///     _ => Ok(None),
+    /// })?;
+    /// loaders.insert(0, searcher)?;
+    /// assert_eq!(lua.load("require('greeting')").eval::<String>()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn loaders(&self) -> Result<Loaders> {
+        let package = match self.globals().raw_get::<_, Value>("package")? {
+            Value::Table(package) => package,
+            _ => return Err(Error::runtime("package library is not loaded")),
+        };
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        let searchers = package.raw_get("searchers")?;
+        #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+        let searchers = package.raw_get("loaders")?;
+        Ok(Loaders {
+            lua: self,
+            package,
+            searchers,
+        })
+    }
+}
+
+impl<'lua> Loaders<'lua> {
+    /// Returns the templates of `package.path` used to search for Lua modules.
+    ///
+    /// Empty templates (eg. from `;;`) are preserved.
+    pub fn path(&self) -> Result<Vec<StdString>> {
+        self.templates("path")
+    }
+
+    /// Replaces `package.path` with the given templates.
+    pub fn set_path<I, S>(&self, templates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.set_templates("path", templates)
+    }
+
+    /// Returns the templates of `package.cpath` used to search for binary modules.
+    ///
+    /// Empty templates (eg. from `;;`) are preserved.
+    pub fn cpath(&self) -> Result<Vec<StdString>> {
+        self.templates("cpath")
+    }
+
+    /// Replaces `package.cpath` with the given templates.
+    pub fn set_cpath<I, S>(&self, templates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.set_templates("cpath", templates)
+    }
+
+    /// Returns the number of searchers.
+    pub fn len(&self) -> usize {
+        self.searchers.raw_len()
+    }
+
+    /// Returns `true` if there are no searchers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the searcher at `index`, or `None` if the index is out of bounds.
+    pub fn get(&self, index: usize) -> Result<Option<Function<'lua>>> {
+        self.searchers.raw_get(index as Integer + 1)
+    }
+
+    /// Returns all searchers in the order they are called.
+    pub fn searchers(&self) -> Result<Vec<Function<'lua>>> {
+        (self.searchers.clone().sequence_values()).collect()
+    }
+
+    /// Inserts a searcher at `index`, shifting the following searchers.
+    ///
+    /// The searcher receives the module name and returns a loader function (and in Lua 5.2+, a
+    /// value passed to the loader), or a string explaining why the module was not found.
+    pub fn insert(&self, index: usize, searcher: Function<'lua>) -> Result<()> {
+        self.check_index(index, self.len() + 1)?;
+        self.searchers.raw_insert(index as Integer + 1, searcher)
+    }
+
+    /// Appends a searcher, so it's called after the existing ones.
+    pub fn push(&self, searcher: Function<'lua>) -> Result<()> {
+        self.searchers.raw_push(searcher)
+    }
+
+    /// Removes and returns the searcher at `index`, shifting the following searchers.
+    pub fn remove(&self, index: usize) -> Result<Function<'lua>> {
+        self.check_index(index, self.len())?;
+        let searcher = self.searchers.raw_get(index as Integer + 1)?;
+        self.searchers.raw_remove(index as Integer + 1)?;
+        Ok(searcher)
+    }
+
+    /// Moves the searcher at `from` to `to`, shifting the searchers in between.
+    pub fn move_to(&self, from: usize, to: usize) -> Result<()> {
+        self.check_index(to, self.len())?;
+        let searcher = self.remove(from)?;
+        self.insert(to, searcher)
+    }
+
+    /// Replaces the searcher at `index` with `func`, which receives the original searcher and the
+    /// module name.
+    ///
+    /// Useful to intercept or post-process module resolution (e.g. logging or access control).
+    pub fn wrap<F, R>(&self, index: usize, func: F) -> Result<()>
+    where
+        F: Fn(&'lua Lua, Function<'lua>, StdString) -> Result<R> + MaybeSend + 'static,
+        R: IntoLuaMulti<'lua>,
+    {
+        let searcher = self.remove(index)?;
+        let wrapper = self
+            .lua
+            .create_function(move |lua, (searcher, name)| func(lua, searcher, name))?;
+        self.insert(index, wrapper.bind(searcher)?)
+    }
+
+    // Empty templates (eg. from `;;`) are kept for `set_templates` to restore the same value
+    fn templates(&self, key: &str) -> Result<Vec<StdString>> {
+        let templates = self.package.raw_get::<_, Option<StdString>>(key)?;
+        Ok(match templates.as_deref() {
+            None | Some("") => Vec::new(),
+            Some(templates) => templates.split(';').map(str::to_string).collect(),
+        })
+    }
+
+    fn set_templates<I, S>(&self, key: &str, templates: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let templates = (templates.into_iter())
+            .map(|template| template.as_ref().to_string())
+            .collect::<Vec<_>>();
+        self.package.raw_set(key, templates.join(";"))
+    }
+
+    fn check_index(&self, index: usize, bound: usize) -> Result<()> {
+        if index >= bound {
+            let len = self.len();
+            return Err(Error::runtime(format!(
+                "searcher index {index} is out of bounds (number of searchers is {len})"
+            )));
+        }
+        Ok(())
+    }
+}
@@ -2,34 +2,74 @@ use std::any::TypeId;
 use std::cell::{RefCell, UnsafeCell};
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::io;
 use std::marker::PhantomData;
-use std::mem::{self, MaybeUninit};
+use std::mem::{self, ManuallyDrop, MaybeUninit};
 use std::ops::Deref;
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe, Location};
 use std::ptr;
 use std::result::Result as StdResult;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
+use crate::audit::{AuditEvent, SandboxAudit};
 use crate::chunk::{AsChunk, Chunk, ChunkMode};
-use crate::error::{Error, Result};
+use crate::coercion::CoercionPolicy;
+use crate::conversion_limits::ConversionCounter;
+use crate::conversion_trace::{trace, ConversionDirection};
+use crate::deprecation::Deprecation;
+use crate::error::{ConversionErrorInfo, Error, Result};
+use crate::foreign::CloseNotifier;
 use crate::function::Function;
+use crate::global_handle::GlobalHandle;
+use crate::globals::GlobalsProtection;
 use crate::hook::Debug;
+use crate::introspection::{registered_type, RegisteredFunction, RegisteredType};
+use crate::keep_alive::KeepAlive;
+use crate::lua_enum::LuaEnum;
+use crate::lua_id::LuaId;
 use crate::memory::{MemoryState, ALLOCATOR};
+use crate::memory_pressure::MemoryPressure;
+use crate::metatable::MetatableBuilder;
+use crate::middleware::{call_with_middleware, CallbackCtx, CallbackNext};
+use crate::module::LuaModule;
+use crate::prelude_builder::parse_prelude;
+use crate::quota::Quota;
+#[cfg(not(feature = "send"))]
+use crate::registration::{prepare_userdata, PreparedRef};
+use crate::remote::RemoteCallQueue;
+#[cfg(feature = "send")]
+use crate::remote::RemoteLua;
 use crate::scope::Scope;
+use crate::stack::{Stack, StackStats};
 use crate::stdlib::StdLib;
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
+
+#[cfg(feature = "trace_conversions")]
+use crate::conversion_trace::{ConversionTrace, ConversionTracer};
+#[cfg(not(feature = "luau"))]
+use crate::function_stats::{FunctionStats, StatsRecorder};
+#[cfg(all(feature = "async", feature = "send"))]
+use crate::shared::StateLock;
+#[cfg(feature = "trace_events")]
+use crate::trace_events::{TraceEvents, TraceRecorder};
 use crate::types::{
-    AppData, AppDataRef, AppDataRefMut, Callback, CallbackUpvalue, DestructedUserdata, Integer,
-    LightUserData, LuaRef, MaybeSend, Number, RegistryKey, SubtypeId,
+    AppData, AppDataRef, AppDataRefMut, Callback, CallbackMiddleware, CallbackName,
+    CallbackUpvalue, ConversionErrorHook, DeprecationHandler, DestructedUserdata, GcCallback,
+    GcStepCallback, Integer, LightUserData, LuaRef, MaybeSend, MaybeSync, Number, RegistryKey,
+    SetupStep, SubtypeId, TypedLightUserData,
+};
+use crate::userdata::{
+    AnyUserData, MetaMethod, UserData, UserDataCell, UserDataCounter, UserDataStats,
+    UserDataTracker,
 };
-use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataCell};
-use crate::userdata_impl::{UserDataProxy, UserDataRegistry};
+use crate::userdata_impl::{get_function_name, UserDataProxy, UserDataRegistry};
 use crate::util::{
     self, assert_stack, check_stack, error_traceback, get_destructed_userdata_metatable,
     get_gc_metatable, get_gc_userdata, get_main_state, get_userdata, init_error_registry,
@@ -37,6 +77,7 @@ use crate::util::{
     push_table, rawset_field, safe_pcall, safe_xpcall, short_type_name, StackGuard, WrappedFailure,
 };
 use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil, Value};
+use crate::weak::WeakMode;
 
 #[cfg(not(feature = "lua54"))]
 use crate::util::push_userdata;
@@ -44,8 +85,14 @@ use crate::util::push_userdata;
 use crate::{types::WarnCallback, userdata::USER_VALUE_MAXSLOT, util::push_userdata_uv};
 
 #[cfg(not(feature = "luau"))]
-use crate::{hook::HookTriggers, types::HookCallback};
+use {
+    crate::env::EnvProvider,
+    crate::hook::HookTriggers,
+    crate::types::{ExitCallback, HookCallback},
+};
 
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+use crate::thread::ThreadStatus;
 #[cfg(feature = "luau")]
 use crate::types::InterruptCallback;
 #[cfg(any(feature = "luau", doc))]
@@ -56,14 +103,19 @@ use crate::{
 
 #[cfg(feature = "async")]
 use {
+    crate::async_scope::PendingFutures,
+    crate::thread::AsyncSchedulerHooks,
     crate::types::{AsyncCallback, AsyncCallbackUpvalue, AsyncPollUpvalue},
-    futures_util::future::{self, Future},
+    futures_util::future::{self, Future, LocalBoxFuture},
     futures_util::task::{noop_waker_ref, Context, Poll, Waker},
     std::ptr::NonNull,
 };
 
 #[cfg(feature = "serialize")]
-use serde::Serialize;
+use {
+    crate::serde::{UserDataFromValue, UserDataSerialize, UserDataToValue},
+    serde::Serialize,
+};
 
 /// Top level Lua struct which represents an instance of Lua VM.
 #[repr(transparent)]
@@ -75,32 +127,74 @@ pub struct LuaInner {
     state: AtomicPtr<ffi::lua_State>,
     main_state: *mut ffi::lua_State,
     extra: Arc<UnsafeCell<ExtraData>>,
+    // Whether the state must be closed on drop
+    #[cfg_attr(feature = "module", allow(dead_code))]
+    owned: bool,
+    // Lock of `Lua::lock_async`, kept out of `ExtraData` to be reachable from other threads
+    // (through `SharedLua`)
+    #[cfg(all(feature = "async", feature = "send"))]
+    state_lock: StateLock,
 }
 
 // Data associated with the Lua.
 pub(crate) struct ExtraData {
     // Same layout as `Lua`
     inner: MaybeUninit<Arc<LuaInner>>,
+    id: LuaId,
 
     registered_userdata: FxHashMap<TypeId, c_int>,
+    // Userdata types with prebuilt registries (see `RegistrationSet`), and the `UserData` types
+    // with metatables built in this instance, whose registries can be built for exporting
+    #[cfg(not(feature = "send"))]
+    imported_userdata: FxHashMap<TypeId, PreparedRef>,
+    #[cfg(not(feature = "send"))]
+    exportable_userdata: FxHashMap<TypeId, fn() -> PreparedRef>,
+    // Metadata of registered userdata types and Rust functions (see `Lua::registered_types`)
+    registered_types: FxHashMap<TypeId, RegisteredType>,
+    // Rust functions are keyed by their pointers, which are kept valid by the weak table
+    // (registry reference) of live functions
+    registered_functions: FxHashMap<*const c_void, RegisteredFunction>,
+    registered_functions_ref: Option<c_int>,
+    // Number of registered functions at which the collected ones are forgotten
+    registered_functions_limit: usize,
     registered_userdata_mt: FxHashMap<*const c_void, Option<TypeId>>,
     last_checked_userdata_mt: (*const c_void, Option<TypeId>),
+    userdata_counters: FxHashMap<TypeId, Arc<UserDataCounter>>,
+    // Weak tables (registry references) of live userdata instances
+    userdata_instances: FxHashMap<TypeId, c_int>,
+    // Known typed light userdata pointers
+    light_userdata_types: FxHashMap<*const c_void, (TypeId, &'static str)>,
+    // Converters of registered `UserDataSerialize` types (by type id and by type name)
+    #[cfg(feature = "serialize")]
+    userdata_serializers: FxHashMap<TypeId, (&'static str, UserDataToValue)>,
+    #[cfg(feature = "serialize")]
+    userdata_deserializers: FxHashMap<std::string::String, UserDataFromValue>,
+    // Weak table (registry reference) of coroutine-local data containers
+    thread_locals: Option<c_int>,
 
     // When Lua instance dropped, setting `None` would prevent collecting `RegistryKey`s
     registry_unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
+    // Set by `Lua::run_isolated` to deny registry writes
+    registry_readonly: bool,
 
     // Container to store arbitrary data (extensions)
     app_data: AppData,
 
     safe: bool,
     libs: StdLib,
+    options: LuaOptions,
     #[cfg(feature = "module")]
     skip_memory_check: bool,
 
+    // Recorded initialization steps (used to fork the state)
+    setup: Vec<SetupStep>,
+
     // Auxiliary thread to store references
     ref_thread: *mut ffi::lua_State,
     ref_stack_size: c_int,
     ref_stack_top: c_int,
+    // Peak number of live references in the ref thread
+    ref_stack_peak: c_int,
     ref_free: Vec<c_int>,
 
     // Pool of `WrappedFailure` enums in the ref thread (as userdata)
@@ -117,20 +211,64 @@ pub(crate) struct ExtraData {
     // Waker for polling futures
     #[cfg(feature = "async")]
     waker: NonNull<Waker>,
+    #[cfg(feature = "async")]
+    async_scheduler_hooks: Option<AsyncSchedulerHooks>,
+    // Futures of async scope functions (see `Lua::create_async_scope_function`)
+    #[cfg(feature = "async")]
+    pending_async: PendingFutures,
 
     #[cfg(not(feature = "luau"))]
     hook_callback: Option<HookCallback>,
     #[cfg(not(feature = "luau"))]
     hook_thread: *mut ffi::lua_State,
+    #[cfg(not(feature = "luau"))]
+    scoped_hook: Option<ScopedHook>,
+    gc_notifier: Option<Arc<GcNotifier>>,
+    #[cfg(not(feature = "luau"))]
+    exit_callback: Option<ExitCallback>,
     #[cfg(feature = "lua54")]
     warn_callback: Option<WarnCallback>,
+    // Callback called after host-driven GC steps (see `Lua::gc_set_incremental_callback`)
+    gc_step_callback: Option<GcStepCallback>,
+    #[cfg(not(feature = "luau"))]
+    env_provider: Option<Box<dyn EnvProvider>>,
     #[cfg(feature = "luau")]
     interrupt_callback: Option<InterruptCallback>,
+    callback_middleware: Option<CallbackMiddleware>,
+    deprecation_handler: Option<DeprecationHandler>,
+    memory_pressure: MemoryPressure,
+    keep_alive: KeepAlive,
+    conversion_counter: ConversionCounter,
+    conversion_error_hook: Option<ConversionErrorHook>,
+    #[cfg(feature = "trace_conversions")]
+    conversion_tracer: ConversionTracer,
+    // Calls queued by `RemoteFunction`s
+    remote_calls: RemoteCallQueue,
+    quota: Option<Quota>,
+    // Set once a quota is attached to any thread (see `ThreadQuota`)
+    thread_quotas: bool,
+    // Hooks replaced by the quota hook of `Lua::set_quota`, for every hooked state
+    #[cfg(not(feature = "luau"))]
+    quota_prev_hooks: Vec<(*mut ffi::lua_State, PrevHook)>,
+    // Interrupt replaced by the quota interrupt
+    #[cfg(feature = "luau")]
+    quota_prev_interrupt: Option<InterruptProc>,
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    time_slice: Option<TimeSlice>,
+    #[cfg(feature = "trace_events")]
+    trace_events: Option<TraceRecorder>,
+    #[cfg(not(feature = "luau"))]
+    function_stats: Option<StatsRecorder>,
+    autorelease_pools: Vec<AutoreleasePool>,
 
     #[cfg(feature = "luau")]
     sandboxed: bool,
     #[cfg(feature = "luau")]
     compiler: Option<Compiler>,
+    #[cfg(feature = "luau")]
+    memory_categories: Vec<std::string::String>,
+    #[cfg(feature = "luau")]
+    memory_category: c_int,
     #[cfg(feature = "luau-jit")]
     enable_jit: bool,
 }
@@ -152,6 +290,35 @@ pub enum GCMode {
     Generational,
 }
 
+/// Garbage collector event reported to a callback set by [`Lua::on_gc`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GcEvent {
+    /// A new garbage collection cycle has started.
+    CycleStart,
+    /// A garbage collection cycle has finished.
+    CycleEnd {
+        /// Time elapsed since the cycle start (including time spent running Lua code between
+        /// incremental steps).
+        duration: Duration,
+        /// Approximate amount of memory (in bytes) freed during the cycle.
+        freed_bytes: usize,
+    },
+}
+
+/// Action to take when a script requests the process to exit, returned by a handler set using
+/// [`Lua::on_exit_request`].
+#[cfg(not(feature = "luau"))]
+#[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExitAction {
+    /// Raise [`Error::ExitRequested`] instead of exiting.
+    Error,
+    /// Let the process exit (the default Lua behavior).
+    Exit,
+}
+
 /// Controls Lua interpreter behavior such as Rust panics handling.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
@@ -181,6 +348,33 @@ pub struct LuaOptions {
     #[cfg(feature = "async")]
     #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
     pub thread_pool_size: usize,
+
+    /// Implicit coercions between strings and numbers accepted by [`FromLua`] conversions.
+    ///
+    /// Default: [`CoercionPolicy::LUA`]
+    ///
+    /// [`FromLua`]: crate::FromLua
+    pub coercion: CoercionPolicy,
+
+    /// Allow scripts to access the filesystem and start processes through the standard
+    /// libraries.
+    ///
+    /// If disabled, the `io` library is never loaded, and `loadfile`, `dofile`, `os.execute`,
+    /// `os.exit`, `os.remove`, `os.rename`, `os.tmpname` and `package.loadlib` are removed from
+    /// the loaded libraries. `require` can only load modules from `package.preload` (or registered
+    /// with [`Lua::register_module`]), as the `package.path` and `package.cpath` searchers are
+    /// removed as well. The same applies to libraries loaded later using
+    /// [`Lua::load_from_std_lib`].
+    ///
+    /// Loading chunks from a [`Path`] on the Rust side is not affected.
+    ///
+    /// This is a runtime layer on top of the `filesystem` cargo feature: without the feature
+    /// the functions above are compiled out (or removed) regardless of this option.
+    ///
+    /// Default: **true**
+    ///
+    /// [`Path`]: std::path::Path
+    pub fs_access: bool,
 }
 
 impl Default for LuaOptions {
@@ -196,6 +390,8 @@ impl LuaOptions {
             catch_rust_panics: true,
             #[cfg(feature = "async")]
             thread_pool_size: 0,
+            coercion: CoercionPolicy::LUA,
+            fs_access: true,
         }
     }
 
@@ -218,15 +414,214 @@ impl LuaOptions {
         self.thread_pool_size = size;
         self
     }
+
+    /// Sets [`coercion`] option.
+    ///
+    /// [`coercion`]: #structfield.coercion
+    #[must_use]
+    pub const fn coercion(mut self, policy: CoercionPolicy) -> Self {
+        self.coercion = policy;
+        self
+    }
+
+    /// Sets [`fs_access`] option.
+    ///
+    /// [`fs_access`]: #structfield.fs_access
+    #[must_use]
+    pub const fn fs_access(mut self, enabled: bool) -> Self {
+        self.fs_access = enabled;
+        self
+    }
+}
+
+/// A builder to construct a new Lua state with custom parameters.
+///
+/// Created using [`Lua::builder`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{GCMode, Lua, LuaOptions, Result, StdLib};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::builder()
+///     .libs(StdLib::TABLE | StdLib::STRING)
+///     .options(LuaOptions::new().catch_rust_panics(false))
+///     .stack_size(1000)
+///     .gc_mode(GCMode::Incremental)
+///     .build()?;
+/// assert_eq!(lua.load("string.rep('a', 3)").eval::<String>()?, "aaa");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+#[must_use]
+pub struct LuaBuilder {
+    libs: StdLib,
+    options: LuaOptions,
+    stack_size: Option<usize>,
+    ref_thread_capacity: Option<usize>,
+    gc_mode: Option<GCMode>,
+    #[cfg(not(feature = "luau"))]
+    panic_fn: Option<ffi::lua_CFunction>,
+    #[cfg(not(feature = "luau"))]
+    allocator: Option<(ffi::lua_Alloc, *mut c_void)>,
+}
+
+impl Default for LuaBuilder {
+    fn default() -> Self {
+        LuaBuilder::new()
+    }
+}
+
+impl LuaBuilder {
+    /// Returns a new builder that would create a Lua state with the **safe** subset of
+    /// the standard libraries and default options.
+    pub const fn new() -> Self {
+        LuaBuilder {
+            libs: StdLib::ALL_SAFE,
+            options: LuaOptions::new(),
+            stack_size: None,
+            ref_thread_capacity: None,
+            gc_mode: None,
+            #[cfg(not(feature = "luau"))]
+            panic_fn: None,
+            #[cfg(not(feature = "luau"))]
+            allocator: None,
+        }
+    }
+
+    /// Sets the standard libraries to load.
+    ///
+    /// Default: [`StdLib::ALL_SAFE`]
+    pub const fn libs(mut self, libs: StdLib) -> Self {
+        self.libs = libs;
+        self
+    }
+
+    /// Sets the Lua state options.
+    pub const fn options(mut self, options: LuaOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Ensures that the main thread stack has space for at least `size` slots.
+    pub const fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Preallocates space for at least `capacity` values in the auxiliary thread used to keep
+    /// references to Lua values (held by [`Table`], [`Function`], etc.).
+    ///
+    /// The auxiliary stack grows on demand, this option helps to avoid reallocations when
+    /// a large number of values are expected to be held at the same time.
+    pub const fn ref_thread_capacity(mut self, capacity: usize) -> Self {
+        self.ref_thread_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the garbage collector mode (using default parameters).
+    pub const fn gc_mode(mut self, mode: GCMode) -> Self {
+        self.gc_mode = Some(mode);
+        self
+    }
+
+    /// Sets a panic function that is called by Lua in case of an unprotected error.
+    ///
+    /// See [`lua_atpanic`] for details.
+    ///
+    /// [`lua_atpanic`]: https://www.lua.org/manual/5.4/manual.html#lua_atpanic
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub const fn panic_fn(mut self, f: ffi::lua_CFunction) -> Self {
+        self.panic_fn = Some(f);
+        self
+    }
+
+    /// Sets a custom memory allocation function with an opaque pointer `ud` passed to it.
+    ///
+    /// [`Lua::used_memory`] and [`Lua::set_memory_limit`] are not available for states created
+    /// with a custom allocator.
+    ///
+    /// # Safety
+    /// The allocation function must follow the [`lua_Alloc`] contract and `ud` must remain valid
+    /// during the whole lifetime of the Lua state.
+    ///
+    /// [`lua_Alloc`]: https://www.lua.org/manual/5.4/manual.html#lua_Alloc
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub unsafe fn allocator(mut self, f: ffi::lua_Alloc, ud: *mut c_void) -> Self {
+        self.allocator = Some((f, ud));
+        self
+    }
+
+    /// Creates a new Lua state.
+    ///
+    /// The created Lua state would have _some_ safety guarantees and would not allow to load unsafe
+    /// standard libraries or C modules.
+    ///
+    /// See [`StdLib`] documentation for a list of unsafe modules that cannot be loaded.
+    pub fn build(self) -> Result<Lua> {
+        #[cfg(not(feature = "luau"))]
+        if self.libs.contains(StdLib::DEBUG) {
+            return Err(Error::SafetyError(
+                "The unsafe `debug` module can't be loaded into a safe Lua state".to_string(),
+            ));
+        }
+        #[cfg(feature = "luajit")]
+        if self.libs.contains(StdLib::FFI) {
+            return Err(Error::SafetyError(
+                "The unsafe `ffi` module can't be loaded into a safe Lua state".to_string(),
+            ));
+        }
+
+        let lua = unsafe { Lua::inner_new(&self)? };
+
+        if self.libs.contains(StdLib::PACKAGE) {
+            mlua_expect!(lua.disable_c_modules(), "Error during disabling C modules");
+        }
+        unsafe { (*lua.extra.get()).safe = true };
+
+        Ok(lua)
+    }
+
+    /// Creates a new Lua state without safety restrictions.
+    ///
+    /// # Safety
+    /// The created Lua state will not have safety guarantees and allow to load C modules.
+    pub unsafe fn unsafe_build(self) -> Result<Lua> {
+        // Workaround to avoid stripping a few unused Lua symbols that could be imported
+        // by C modules in unsafe mode
+        let mut _symbols: Vec<*const extern "C-unwind" fn()> =
+            vec![ffi::lua_isuserdata as _, ffi::lua_tocfunction as _];
+
+        #[cfg(not(feature = "luau"))]
+        _symbols.extend_from_slice(&[
+            ffi::lua_atpanic as _,
+            ffi::luaL_loadstring as _,
+            ffi::luaL_openlibs as _,
+        ]);
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        {
+            _symbols.push(ffi::lua_getglobal as _);
+            _symbols.push(ffi::lua_setglobal as _);
+            _symbols.push(ffi::luaL_setfuncs as _);
+        }
+
+        Lua::inner_new(&self)
+    }
 }
 
 #[cfg(feature = "async")]
 pub(crate) static ASYNC_POLL_PENDING: u8 = 0;
 pub(crate) static EXTRA_REGISTRY_KEY: u8 = 0;
+#[cfg(not(feature = "luau"))]
+static GC_SENTINEL_MT_KEY: u8 = 0;
 
 const WRAPPED_FAILURE_POOL_SIZE: usize = 64;
 const MULTIVALUE_POOL_SIZE: usize = 64;
 const REF_STACK_RESERVE: c_int = 1;
+const REGISTERED_FUNCTIONS_MIN_LIMIT: usize = 64;
 
 /// Requires `feature = "send"`
 #[cfg(feature = "send")]
@@ -243,9 +638,22 @@ impl Drop for Lua {
 #[cfg(not(feature = "module"))]
 impl Drop for LuaInner {
     fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
         unsafe {
             let mem_state = MemoryState::get(self.main_state);
 
+            // Do not report GC events and memory pressure while closing the state
+            (*self.extra.get()).memory_pressure.clear();
+            if let Some(notifier) = (*self.extra.get()).gc_notifier.take() {
+                notifier.active.store(false, Ordering::Relaxed);
+            }
+
+            // Futures of async scope functions must not outlive the state
+            #[cfg(feature = "async")]
+            self.cancel_pending_async_futures();
+
             ffi::lua_close(self.main_state);
 
             // Deallocate MemoryState
@@ -264,6 +672,9 @@ impl Drop for ExtraData {
         }
 
         *mlua_expect!(self.registry_unref_list.lock(), "unref list poisoned") = None;
+
+        #[cfg(feature = "send")]
+        crate::lua_id::unregister(self.id);
     }
 }
 
@@ -326,27 +737,7 @@ impl Lua {
     ///
     /// [`StdLib`]: crate::StdLib
     pub fn new_with(libs: StdLib, options: LuaOptions) -> Result<Lua> {
-        #[cfg(not(feature = "luau"))]
-        if libs.contains(StdLib::DEBUG) {
-            return Err(Error::SafetyError(
-                "The unsafe `debug` module can't be loaded using safe `new_with`".to_string(),
-            ));
-        }
-        #[cfg(feature = "luajit")]
-        if libs.contains(StdLib::FFI) {
-            return Err(Error::SafetyError(
-                "The unsafe `ffi` module can't be loaded using safe `new_with`".to_string(),
-            ));
-        }
-
-        let lua = unsafe { Self::inner_new(libs, options) };
-
-        if libs.contains(StdLib::PACKAGE) {
-            mlua_expect!(lua.disable_c_modules(), "Error during disabling C modules");
-        }
-        unsafe { (*lua.extra.get()).safe = true };
-
-        Ok(lua)
+        Self::builder().libs(libs).options(options).build()
     }
 
     /// Creates a new Lua state and loads the specified subset of the standard libraries.
@@ -358,37 +749,49 @@ impl Lua {
     ///
     /// [`StdLib`]: crate::StdLib
     pub unsafe fn unsafe_new_with(libs: StdLib, options: LuaOptions) -> Lua {
-        // Workaround to avoid stripping a few unused Lua symbols that could be imported
-        // by C modules in unsafe mode
-        let mut _symbols: Vec<*const extern "C-unwind" fn()> =
-            vec![ffi::lua_isuserdata as _, ffi::lua_tocfunction as _];
-
-        #[cfg(not(feature = "luau"))]
-        _symbols.extend_from_slice(&[
-            ffi::lua_atpanic as _,
-            ffi::luaL_loadstring as _,
-            ffi::luaL_openlibs as _,
-        ]);
-        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
-        {
-            _symbols.push(ffi::lua_getglobal as _);
-            _symbols.push(ffi::lua_setglobal as _);
-            _symbols.push(ffi::luaL_setfuncs as _);
-        }
+        mlua_expect!(
+            Self::builder().libs(libs).options(options).unsafe_build(),
+            "Cannot create new Lua state"
+        )
+    }
 
-        Self::inner_new(libs, options)
+    /// Returns a [`LuaBuilder`] to construct a new Lua state with custom parameters.
+    pub const fn builder() -> LuaBuilder {
+        LuaBuilder::new()
     }
 
-    /// Creates a new Lua state with required `libs` and `options`
-    unsafe fn inner_new(libs: StdLib, options: LuaOptions) -> Lua {
-        let mem_state: *mut MemoryState = Box::into_raw(Box::default());
-        let mut state = ffi::lua_newstate(ALLOCATOR, mem_state as *mut c_void);
-        // If state is null then switch to Lua internal allocator
+    /// Creates a new Lua state with parameters from the `builder`
+    unsafe fn inner_new(builder: &LuaBuilder) -> Result<Lua> {
+        let (libs, options) = (builder.libs, &builder.options);
+
+        #[cfg(not(feature = "luau"))]
+        let custom_state = builder.allocator.map(|(f, ud)| ffi::lua_newstate(f, ud));
+        #[cfg(feature = "luau")]
+        let custom_state = None;
+
+        let state = match custom_state {
+            Some(state) => state,
+            None => {
+                let mem_state: *mut MemoryState = Box::into_raw(Box::default());
+                let mut state = ffi::lua_newstate(ALLOCATOR, mem_state as *mut c_void);
+                // If state is null then switch to Lua internal allocator
+                if state.is_null() {
+                    drop(Box::from_raw(mem_state));
+                    state = ffi::luaL_newstate();
+                }
+                state
+            }
+        };
         if state.is_null() {
-            drop(Box::from_raw(mem_state));
-            state = ffi::luaL_newstate();
+            return Err(Error::MemoryError(
+                "Failed to instantiate Lua VM".to_string(),
+            ));
+        }
+
+        #[cfg(not(feature = "luau"))]
+        if let Some(panic_fn) = builder.panic_fn {
+            ffi::lua_atpanic(state, panic_fn);
         }
-        assert!(!state.is_null(), "Failed to instantiate Lua VM");
 
         ffi::luaL_requiref(state, cstr!("_G"), ffi::luaopen_base, 1);
         ffi::lua_pop(state, 1);
@@ -401,6 +804,7 @@ impl Lua {
 
         let lua = Lua::init_from_ptr(state);
         let extra = lua.extra.get();
+        (*extra).options = options.clone();
 
         mlua_expect!(
             load_from_std_lib(state, libs),
@@ -438,15 +842,59 @@ impl Lua {
         #[cfg(feature = "luau")]
         mlua_expect!(lua.configure_luau(), "Error configuring Luau");
 
-        lua
+        if let Some(size) = builder.stack_size {
+            check_stack(state, size.try_into().map_err(|_| Error::StackError)?)?;
+        }
+
+        if let Some(capacity) = builder.ref_thread_capacity {
+            let extra = &mut *extra;
+            let capacity: c_int = capacity.try_into().map_err(|_| Error::StackError)?;
+            if capacity > extra.ref_stack_size {
+                let inc = capacity - extra.ref_stack_top + REF_STACK_RESERVE;
+                check_stack(extra.ref_thread, inc)?;
+                extra.ref_stack_size = capacity;
+            }
+        }
+
+        match builder.gc_mode {
+            Some(GCMode::Incremental) => {
+                lua.gc_inc(0, 0, 0);
+            }
+            #[cfg(feature = "lua54")]
+            Some(GCMode::Generational) => {
+                lua.gc_gen(0, 0);
+            }
+            None => {}
+        }
+
+        // Stack reserved during initialization is not caused by the user code
+        #[cfg(feature = "stack_diagnostics")]
+        {
+            let mem_state = MemoryState::get(state);
+            if !mem_state.is_null() {
+                (*mem_state).reset_stack_peak();
+            }
+        }
+
+        Ok(lua)
     }
 
     /// Constructs a new Lua instance from an existing raw state.
     ///
     /// Once called, a returned Lua state is cached in the registry and can be retrieved
     /// by calling this function again.
-    #[allow(clippy::missing_safety_doc, clippy::arc_with_non_send_sync)]
+    ///
+    /// Outside of module mode, the state is owned by the returned instance and closed when it is
+    /// dropped. Use [`ForeignLua`] to wrap a state owned by the host application.
+    ///
+    /// [`ForeignLua`]: crate::ForeignLua
+    #[allow(clippy::missing_safety_doc)]
     pub unsafe fn init_from_ptr(state: *mut ffi::lua_State) -> Lua {
+        Self::init_from_ptr_with(state, true)
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub(crate) unsafe fn init_from_ptr_with(state: *mut ffi::lua_State, owned: bool) -> Lua {
         assert!(!state.is_null(), "Lua state is NULL");
         if let Some(lua) = Lua::try_from_ptr(state) {
             return lua;
@@ -465,6 +913,7 @@ impl Lua {
                 init_gc_metatable::<Arc<UnsafeCell<ExtraData>>>(state, None)?;
                 init_gc_metatable::<Callback>(state, None)?;
                 init_gc_metatable::<CallbackUpvalue>(state, None)?;
+                init_gc_metatable::<CallbackName>(state, None)?;
                 #[cfg(feature = "async")]
                 {
                     init_gc_metatable::<AsyncCallback>(state, None)?;
@@ -510,19 +959,40 @@ impl Lua {
         // Create ExtraData
         let extra = Arc::new(UnsafeCell::new(ExtraData {
             inner: MaybeUninit::uninit(),
+            id: LuaId::next(),
             registered_userdata: FxHashMap::default(),
+            #[cfg(not(feature = "send"))]
+            imported_userdata: FxHashMap::default(),
+            #[cfg(not(feature = "send"))]
+            exportable_userdata: FxHashMap::default(),
+            registered_types: FxHashMap::default(),
+            registered_functions: FxHashMap::default(),
+            registered_functions_ref: None,
+            registered_functions_limit: REGISTERED_FUNCTIONS_MIN_LIMIT,
             registered_userdata_mt: FxHashMap::default(),
             last_checked_userdata_mt: (ptr::null(), None),
+            userdata_counters: FxHashMap::default(),
+            userdata_instances: FxHashMap::default(),
+            light_userdata_types: FxHashMap::default(),
+            #[cfg(feature = "serialize")]
+            userdata_serializers: FxHashMap::default(),
+            #[cfg(feature = "serialize")]
+            userdata_deserializers: FxHashMap::default(),
+            thread_locals: None,
             registry_unref_list: Arc::new(Mutex::new(Some(Vec::new()))),
+            registry_readonly: false,
             app_data: AppData::default(),
             safe: false,
             libs: StdLib::NONE,
+            options: LuaOptions::new(),
             #[cfg(feature = "module")]
             skip_memory_check: false,
+            setup: Vec::new(),
             ref_thread,
             // We need some reserved stack space to move values in and out of the ref stack.
             ref_stack_size: ffi::LUA_MINSTACK - REF_STACK_RESERVE,
             ref_stack_top: ffi::lua_gettop(ref_thread),
+            ref_stack_peak: 0,
             ref_free: Vec::new(),
             wrapped_failure_pool: Vec::with_capacity(WRAPPED_FAILURE_POOL_SIZE),
             multivalue_pool: Vec::with_capacity(MULTIVALUE_POOL_SIZE),
@@ -531,18 +1001,56 @@ impl Lua {
             wrapped_failure_mt_ptr,
             #[cfg(feature = "async")]
             waker: NonNull::from(noop_waker_ref()),
+            #[cfg(feature = "async")]
+            async_scheduler_hooks: None,
+            #[cfg(feature = "async")]
+            pending_async: PendingFutures::default(),
             #[cfg(not(feature = "luau"))]
             hook_callback: None,
             #[cfg(not(feature = "luau"))]
             hook_thread: ptr::null_mut(),
+            #[cfg(not(feature = "luau"))]
+            scoped_hook: None,
+            gc_notifier: None,
+            #[cfg(not(feature = "luau"))]
+            exit_callback: None,
             #[cfg(feature = "lua54")]
             warn_callback: None,
+            gc_step_callback: None,
+            #[cfg(not(feature = "luau"))]
+            env_provider: None,
             #[cfg(feature = "luau")]
             interrupt_callback: None,
+            callback_middleware: None,
+            deprecation_handler: None,
+            memory_pressure: MemoryPressure::default(),
+            keep_alive: KeepAlive::default(),
+            conversion_counter: ConversionCounter::default(),
+            conversion_error_hook: None,
+            #[cfg(feature = "trace_conversions")]
+            conversion_tracer: ConversionTracer::default(),
+            remote_calls: RemoteCallQueue::default(),
+            quota: None,
+            thread_quotas: false,
+            #[cfg(not(feature = "luau"))]
+            quota_prev_hooks: Vec::new(),
+            #[cfg(feature = "luau")]
+            quota_prev_interrupt: None,
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+            time_slice: None,
+            #[cfg(feature = "trace_events")]
+            trace_events: None,
+            #[cfg(not(feature = "luau"))]
+            function_stats: None,
+            autorelease_pools: Vec::new(),
             #[cfg(feature = "luau")]
             sandboxed: false,
             #[cfg(feature = "luau")]
             compiler: None,
+            #[cfg(feature = "luau")]
+            memory_categories: vec!["main".into()],
+            #[cfg(feature = "luau")]
+            memory_category: 0,
             #[cfg(feature = "luau-jit")]
             enable_jit: true,
         }));
@@ -572,30 +1080,102 @@ impl Lua {
             state: AtomicPtr::new(state),
             main_state,
             extra: Arc::clone(&extra),
+            owned,
+            #[cfg(all(feature = "async", feature = "send"))]
+            state_lock: StateLock::default(),
         });
 
         (*extra.get()).inner.write(Arc::clone(&inner));
+        #[cfg(feature = "send")]
+        crate::lua_id::register((*extra.get()).id, &(*extra.get()).remote_calls);
         #[cfg(not(feature = "module"))]
         Arc::decrement_strong_count(Arc::as_ptr(&inner));
 
         Lua(inner)
     }
 
-    /// Loads the specified subset of the standard libraries into an existing Lua state.
+    /// Returns the unique identifier of the Lua state.
     ///
-    /// Use the [`StdLib`] flags to specify the libraries you want to load.
+    /// All `Lua` instances sharing the same main state (eg. the one passed to callbacks) have
+    /// the same identifier.
     ///
-    /// [`StdLib`]: crate::StdLib
-    pub fn load_from_std_lib(&self, libs: StdLib) -> Result<()> {
-        let is_safe = unsafe { (*self.extra.get()).safe };
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let id = lua.id();
+    /// let get_id = lua.create_function(|lua, ()| Ok(lua.id().as_u64()))?;
+    /// assert_eq!(get_id.call::<_, u64>(())?, id.as_u64());
+    /// assert_ne!(Lua::new().id(), id);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn id(&self) -> LuaId {
+        unsafe { (*self.extra.get()).id }
+    }
 
-        #[cfg(not(feature = "luau"))]
-        if is_safe && libs.contains(StdLib::DEBUG) {
-            return Err(Error::SafetyError(
-                "the unsafe `debug` module can't be loaded in safe mode".to_string(),
-            ));
-        }
-        #[cfg(feature = "luajit")]
+    /// Returns a handle to the live Lua state with the given identifier.
+    ///
+    /// The handle runs closures with the state on the thread owning it (see [`RemoteLua`]), so
+    /// it can be used to route messages to a state from any thread.
+    /// Returns `None` if the state has been closed.
+    ///
+    /// Requires `feature = "send"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let id = lua.id();
+    ///
+    /// let worker = std::thread::spawn(move || {
+    ///     let remote = Lua::find(id).unwrap();
+    ///     remote.call(|lua| lua.globals().set("message", "hello"))
+    /// });
+    /// while !worker.is_finished() {
+    ///     lua.run_remote_calls();
+    /// }
+    /// worker.join().unwrap()?;
+    /// assert_eq!(lua.globals().get::<_, String>("message")?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RemoteLua`]: crate::RemoteLua
+    #[cfg(feature = "send")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn find(id: LuaId) -> Option<RemoteLua> {
+        crate::lua_id::find(id)
+    }
+
+    /// Returns identifiers of all live Lua states of the process, in creation order.
+    ///
+    /// Requires `feature = "send"`
+    #[cfg(feature = "send")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn live_ids() -> Vec<LuaId> {
+        crate::lua_id::live_ids()
+    }
+
+    /// Loads the specified subset of the standard libraries into an existing Lua state.
+    ///
+    /// Use the [`StdLib`] flags to specify the libraries you want to load.
+    ///
+    /// [`StdLib`]: crate::StdLib
+    pub fn load_from_std_lib(&self, libs: StdLib) -> Result<()> {
+        let is_safe = unsafe { (*self.extra.get()).safe };
+
+        #[cfg(not(feature = "luau"))]
+        if is_safe && libs.contains(StdLib::DEBUG) {
+            return Err(Error::SafetyError(
+                "the unsafe `debug` module can't be loaded in safe mode".to_string(),
+            ));
+        }
+        #[cfg(feature = "luajit")]
         if is_safe && libs.contains(StdLib::FFI) {
             return Err(Error::SafetyError(
                 "the unsafe `ffi` module can't be loaded in safe mode".to_string(),
@@ -611,9 +1191,250 @@ impl Lua {
         }
         unsafe { (*self.extra.get()).libs |= libs };
 
+        // Newly loaded `os` library should use the environment provider
+        #[cfg(not(feature = "luau"))]
+        if libs.contains(StdLib::OS) && unsafe { (*self.extra.get()).env_provider.is_some() } {
+            self.install_env_provider()?;
+        }
+
         res
     }
 
+    /// Unloads the specified subset of the standard libraries.
+    ///
+    /// The library tables are removed from the globals and from `package.loaded`, so scripts can
+    /// no longer reach them by name or using `require`. Unloading the `string` library also
+    /// removes methods of string values (e.g. `s:upper()`).
+    ///
+    /// References to library functions already stored elsewhere (e.g. `local insert =
+    /// table.insert`) are not affected, so this is intended to lock down a state after a trusted
+    /// initialization phase, before running untrusted code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, StdLib};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.load("startup_time = os.time()").exec()?;
+    ///
+    /// lua.unload_std_lib(StdLib::OS)?;
+    /// assert!(lua.load("os.time()").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unload_std_lib(&self, libs: StdLib) -> Result<()> {
+        let globals = self.globals();
+        let loaded = self.named_registry_value::<Option<Table>>("_LOADED")?;
+        for name in libs.names() {
+            globals.raw_remove(name)?;
+            if let Some(loaded) = &loaded {
+                loaded.raw_remove(name)?;
+            }
+        }
+        if libs.contains(StdLib::STRING) {
+            self.set_string_methods(None)?;
+        }
+        let curr_libs = unsafe { (*self.extra.get()).libs };
+        unsafe { (*self.extra.get()).libs = curr_libs ^ (curr_libs & libs) };
+        Ok(())
+    }
+
+    /// Replaces a standard library with the given table.
+    ///
+    /// The table is set as the library global and in `package.loaded`. Replacing the `string`
+    /// library also makes the table the source of methods of string values.
+    ///
+    /// `lib` must specify exactly one library.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, StdLib};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let os = lua.create_table()?;
+    /// os.set("time", lua.create_function(|_, ()| Ok(0))?)?;
+    ///
+    /// lua.replace_std_lib(StdLib::OS, os)?;
+    /// assert_eq!(lua.load("os.time()").eval::<i64>()?, 0);
+    /// assert!(lua.load("os.clock()").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn replace_std_lib(&self, lib: StdLib, table: Table) -> Result<()> {
+        let name = match lib.names()[..] {
+            [name] => name,
+            _ => return Err(Error::runtime("expected exactly one standard library")),
+        };
+        self.globals().raw_set(name, &table)?;
+        if let Some(loaded) = self.named_registry_value::<Option<Table>>("_LOADED")? {
+            loaded.raw_set(name, &table)?;
+        }
+        if lib.contains(StdLib::STRING) {
+            self.set_string_methods(Some(&table))?;
+        }
+        unsafe { (*self.extra.get()).libs |= lib };
+        Ok(())
+    }
+
+    // Sets `__index` of the string metatable, which resolves methods of string values
+    fn set_string_methods(&self, methods: Option<&Table>) -> Result<()> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            match methods {
+                Some(methods) => self.try_push_ref(&methods.0)?,
+                None => ffi::lua_pushnil(state),
+            }
+            protect_lua!(state, 1, 0, fn(state) {
+                ffi::lua_pushstring(state, cstr!(""));
+                if ffi::lua_getmetatable(state, -1) != 0 {
+                    ffi::lua_pushvalue(state, -3);
+                    ffi::lua_setfield(state, -2, cstr!("__index"));
+                }
+            })
+        }
+    }
+
+    /// Sets the source of environment variables returned by `os.getenv`.
+    ///
+    /// By default `os.getenv` reads the process environment. Once a provider is set, scripts can
+    /// only see variables supplied by it (use [`DenyEnv`] to hide everything). The provider also
+    /// applies to the `os` library loaded later using [`Lua::load_from_std_lib`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let env = HashMap::from([("APP_MODE".to_string(), "test".to_string())]);
+    /// lua.set_env_provider(env)?;
+    ///
+    /// assert_eq!(lua.load("os.getenv('APP_MODE')").eval::<String>()?, "test");
+    /// assert_eq!(lua.load("os.getenv('PATH')").eval::<Option<String>>()?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`DenyEnv`]: crate::DenyEnv
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn set_env_provider(&self, provider: impl EnvProvider) -> Result<()> {
+        unsafe { (*self.extra.get()).env_provider = Some(Box::new(provider)) };
+        self.install_env_provider()
+    }
+
+    // Replaces `os.getenv` with a function that consults the environment provider
+    #[cfg(not(feature = "luau"))]
+    fn install_env_provider(&self) -> Result<()> {
+        let os = match self.globals().raw_get("os")? {
+            Value::Table(os) => os,
+            _ => return Ok(()),
+        };
+        let getenv = self.create_function(|lua, name: String| {
+            let provider = unsafe { (*lua.extra.get()).env_provider.as_ref() };
+            Ok(match (provider, name.to_str()) {
+                (Some(provider), Ok(name)) => provider.var(name),
+                _ => None,
+            })
+        })?;
+        os.raw_set("getenv", getenv)
+    }
+
+    /// Runs the setup function `f` on this Lua state and records it to be replayed by [`Lua::fork`].
+    ///
+    /// The function is recorded only if it completes successfully.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.record_setup(|lua| {
+    ///     lua.globals().set("double", lua.create_function(|_, x: i64| Ok(x * 2))?)
+    /// })?;
+    ///
+    /// let forked = lua.fork()?;
+    /// assert_eq!(forked.load("double(21)").eval::<i64>()?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn record_setup<F>(&self, f: F) -> Result<()>
+    where
+        F: Fn(&Lua) -> Result<()> + MaybeSend + MaybeSync + 'static,
+    {
+        f(self)?;
+        self.record_setup_step(SetupStep::Callback(Arc::new(f)));
+        Ok(())
+    }
+
+    /// Creates a new independent Lua state initialized by replaying the recorded setup of this state.
+    ///
+    /// The new state has the same standard libraries and options as this one, and all setup
+    /// steps recorded using [`Lua::record_setup`] and [`Chunk::exec_recorded`] are replayed in order.
+    /// Recorded chunks are stored as precompiled bytecode, so replaying them skips parsing.
+    ///
+    /// Any other changes made to this state (e.g. by running non-recorded code) are not transferred.
+    ///
+    /// [`Chunk::exec_recorded`]: crate::Chunk::exec_recorded
+    pub fn fork(&self) -> Result<Lua> {
+        let (libs, options, safe, steps) = unsafe {
+            let extra = &*self.extra.get();
+            let libs = extra.libs;
+            (libs, extra.options.clone(), extra.safe, extra.setup.clone())
+        };
+
+        let lua = if safe {
+            Lua::new_with(libs, options)?
+        } else {
+            unsafe { Lua::unsafe_new_with(libs, options) }
+        };
+        #[cfg(feature = "luau")]
+        unsafe {
+            (*lua.extra.get()).compiler = (*self.extra.get()).compiler.clone();
+        }
+
+        for step in &steps {
+            match step {
+                SetupStep::Chunk { name, bytecode } => lua
+                    .load(&bytecode[..])
+                    .set_name(name)
+                    .set_mode(ChunkMode::Binary)
+                    .exec()?,
+                SetupStep::Callback(f) => f(&lua)?,
+            }
+        }
+        unsafe { (*lua.extra.get()).setup = steps };
+
+        Ok(lua)
+    }
+
+    /// Executes a prelude precompiled by [`PreludeBuilder`] (usually at build time).
+    ///
+    /// The chunks are executed in the order they were added to the builder.
+    /// Returns an error if the prelude is malformed or was compiled for a different Lua version.
+    ///
+    /// [`PreludeBuilder`]: crate::PreludeBuilder
+    pub fn load_precompiled_prelude(&self, bytes: &[u8]) -> Result<()> {
+        for (name, bytecode) in parse_prelude(bytes)? {
+            (self.load(bytecode))
+                .set_name(name)
+                .set_mode(ChunkMode::Binary)
+                .exec()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_setup_step(&self, step: SetupStep) {
+        unsafe { (*self.extra.get()).setup.push(step) };
+    }
+
     /// Loads module `modname` into an existing Lua state using the specified entrypoint
     /// function.
     ///
@@ -629,6 +1450,7 @@ impl Lua {
     /// Behavior is similar to Lua's [`require`] function.
     ///
     /// [`require`]: https://www.lua.org/manual/5.4/manual.html#pdf-require
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn load_from_function<'lua, T>(&'lua self, modname: &str, func: Function<'lua>) -> Result<T>
     where
         T: FromLua<'lua>,
@@ -665,6 +1487,7 @@ impl Lua {
     /// unloaded only by closing Lua state.
     ///
     /// [`package.loaded`]: https://www.lua.org/manual/5.4/manual.html#pdf-package.loaded
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn unload(&self, modname: &str) -> Result<()> {
         let state = self.state();
         let loaded = unsafe {
@@ -747,6 +1570,74 @@ impl Lua {
         unsafe { (*self.extra.get()).skip_memory_check = skip };
     }
 
+    /// Returns the current value of the high-resolution monotonic clock (in seconds).
+    ///
+    /// This is the clock used by `os.clock` in Luau, so measurements taken in Rust
+    /// can be compared with measurements taken by scripts.
+    ///
+    /// Requires `feature = "luau"`
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn monotonic_time(&self) -> f64 {
+        unsafe { ffi::lua_clock() }
+    }
+
+    /// Sets `os.clock` to a function returning [`Lua::monotonic_time`].
+    ///
+    /// Can be used to restore the shared clock source if `os.clock` was replaced.
+    /// Has no effect if the `os` library is not loaded.
+    ///
+    /// Must be called before enabling the [sandbox] mode, as libraries become read-only.
+    ///
+    /// Requires `feature = "luau"`
+    ///
+    /// [sandbox]: #method.sandbox
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn override_os_clock(&self) -> Result<()> {
+        match self.globals().raw_get("os")? {
+            Value::Table(os) => {
+                let clock = unsafe { self.create_c_function(crate::luau::os_clock)? };
+                os.raw_set("clock", clock)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Installs the `vector` library matching the Luau standard vector library.
+    ///
+    /// Replaces the `vector` constructor global with a table of functions operating on
+    /// [`Vector`] values: `create`, `magnitude`, `normalize`, `cross`, `dot`, `angle`, `floor`,
+    /// `ceil`, `abs`, `sign`, `clamp`, `max`, `min` and the `zero` and `one` constants.
+    /// The table can still be called as a constructor, eg. `vector(1, 2, 3)`.
+    ///
+    /// Must be called before enabling the [sandbox] mode, as globals become read-only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.register_vector_library()?;
+    ///
+    /// let len: f32 = lua.load("vector.magnitude(vector.create(3, 4, 0))").eval()?;
+    /// assert_eq!(len, 5.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Requires `feature = "luau"`
+    ///
+    /// [`Vector`]: crate::Vector
+    /// [sandbox]: #method.sandbox
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn register_vector_library(&self) -> Result<()> {
+        let lib = crate::luau::create_vector_library(self)?;
+        self.globals().raw_set("vector", lib)
+    }
+
     /// Enables (or disables) sandbox mode on this Lua instance.
     ///
     /// This method, in particular:
@@ -777,6 +1668,7 @@ impl Lua {
     /// Requires `feature = "luau"`
     #[cfg(any(feature = "luau", docsrs))]
     #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn sandbox(&self, enabled: bool) -> Result<()> {
         unsafe {
             if (*self.extra.get()).sandboxed != enabled {
@@ -860,6 +1752,8 @@ impl Lua {
             let extra = extra_data(state);
             if (*extra).hook_thread != state {
                 // Hook was destined for a different thread, ignore
+                // (LuaJIT hooks are global, so keep it to reach the target thread)
+                #[cfg(not(feature = "luajit"))]
                 ffi::lua_sethook(state, None, 0, 0);
                 return;
             }
@@ -881,6 +1775,40 @@ impl Lua {
         ffi::lua_sethook(state, Some(hook_proc), triggers.mask(), triggers.count());
     }
 
+    // Installs a hook for the current thread until the returned guard is dropped
+    #[cfg(not(feature = "luau"))]
+    pub(crate) unsafe fn set_scoped_hook<F>(
+        &self,
+        triggers: HookTriggers,
+        callback: F,
+    ) -> ScopedHookGuard<'_>
+    where
+        F: Fn(&Lua, Debug) -> Result<()> + MaybeSend + 'static,
+    {
+        let state = self.state();
+        let prev = (
+            ffi::lua_gethook(state),
+            ffi::lua_gethookmask(state),
+            ffi::lua_gethookcount(state),
+        );
+        let scope = ScopedHook {
+            callback: Arc::new(callback),
+            prev,
+        };
+        let outer = (*self.extra.get()).scoped_hook.replace(scope);
+        ffi::lua_sethook(
+            state,
+            Some(scoped_hook_proc),
+            triggers.mask(),
+            triggers.count(),
+        );
+        ScopedHookGuard {
+            lua: self,
+            state,
+            outer,
+        }
+    }
+
     /// Removes any hook previously set by [`Lua::set_hook()`] or [`Thread::set_hook()`].
     ///
     /// This function has no effect if a hook was not previously set.
@@ -950,31 +1878,6 @@ impl Lua {
     where
         F: Fn(&Lua) -> Result<VmState> + MaybeSend + 'static,
     {
-        unsafe extern "C-unwind" fn interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
-            if gc >= 0 {
-                // We don't support GC interrupts since they cannot survive Lua exceptions
-                return;
-            }
-            let extra = extra_data(state);
-            let result = callback_error_ext(state, extra, move |_| {
-                let interrupt_cb = (*extra).interrupt_callback.clone();
-                let interrupt_cb =
-                    mlua_expect!(interrupt_cb, "no interrupt callback set in interrupt_proc");
-                if Arc::strong_count(&interrupt_cb) > 2 {
-                    return Ok(VmState::Continue); // Don't allow recursion
-                }
-                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
-                let _guard = StateGuard::new(&lua.0, state);
-                interrupt_cb(lua)
-            });
-            match result {
-                VmState::Continue => {}
-                VmState::Yield => {
-                    ffi::lua_yield(state, 0);
-                }
-            }
-        }
-
         unsafe {
             (*self.extra.get()).interrupt_callback = Some(Arc::new(callback));
             (*ffi::lua_callbacks(self.main_state)).interrupt = Some(interrupt_proc);
@@ -988,35 +1891,689 @@ impl Lua {
     #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
     pub fn remove_interrupt(&self) {
         unsafe {
-            (*self.extra.get()).interrupt_callback = None;
-            (*ffi::lua_callbacks(self.main_state)).interrupt = None;
+            let extra = &mut *self.extra.get();
+            extra.interrupt_callback = None;
+            // The interrupt handler is still needed to track GC cycles
+            if extra.gc_notifier.is_none() {
+                (*ffi::lua_callbacks(self.main_state)).interrupt = None;
+            }
         }
     }
 
-    /// Sets the warning function to be used by Lua to emit warnings.
+    /// Sets a middleware wrapping every invocation of Rust callbacks (functions and methods).
     ///
-    /// Requires `feature = "lua54"`
-    #[cfg(feature = "lua54")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
-    pub fn set_warning_function<F>(&self, callback: F)
+    /// The middleware receives the invocation context and the rest of the chain, which it must
+    /// call to run the callback. This allows implementing cross-cutting concerns like
+    /// access checks, logging or timing in one place.
+    ///
+    /// Only one middleware can be set at a time, setting a new one replaces the previous.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_callback_middleware(|_, ctx, next| {
+    ///     if ctx.name().as_deref() == Some("shutdown") {
+    ///         return Err(Error::runtime("access denied"));
+    ///     }
+    ///     next.call()
+    /// });
+    ///
+    /// let shutdown = lua.create_function(|_, ()| Ok(()))?;
+    /// lua.globals().set("shutdown", shutdown)?;
+    /// assert!(lua.load("shutdown()").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_callback_middleware<F>(&self, middleware: F)
     where
-        F: Fn(&Lua, &str, bool) -> Result<()> + MaybeSend + 'static,
+        F: Fn(&Lua, CallbackCtx, CallbackNext) -> Result<()> + MaybeSend + 'static,
     {
-        unsafe extern "C-unwind" fn warn_proc(ud: *mut c_void, msg: *const c_char, tocont: c_int) {
-            let extra = ud as *mut ExtraData;
-            let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
-            callback_error_ext(lua.state(), extra, |_| {
-                let cb = mlua_expect!(
-                    (*extra).warn_callback.as_ref(),
-                    "no warning callback set in warn_proc"
-                );
-                let msg = std::string::String::from_utf8_lossy(CStr::from_ptr(msg).to_bytes());
-                cb(lua, &msg, tocont != 0)
-            });
-        }
+        unsafe { (*self.extra.get()).callback_middleware = Some(Arc::new(middleware)) };
+    }
 
-        let state = self.main_state;
-        unsafe {
+    /// Removes the callback middleware previously set by [`Lua::set_callback_middleware`].
+    pub fn remove_callback_middleware(&self) {
+        unsafe { (*self.extra.get()).callback_middleware = None };
+    }
+
+    /// Sets a handler called when scripts use userdata methods deprecated with
+    /// [`UserDataRegistry::deprecate_method`].
+    ///
+    /// The handler is called according to the [`DeprecationPolicy`] of the method, which allows
+    /// warning or logging the remaining usages of old APIs. If the handler returns an error, the
+    /// call fails with it.
+    ///
+    /// Without a handler, deprecated methods are called silently (unless their policy is
+    /// [`DeprecationPolicy::Error`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{DeprecationPolicy, Lua, Result, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// struct Player;
+    ///
+    /// let lua = Lua::new();
+    /// lua.register_userdata_type::<Player>(|reg| {
+    ///     reg.add_method("health", |_, _, ()| Ok(100));
+    ///     reg.add_method("get_health", |_, _, ()| Ok(100));
+    ///     reg.deprecate_method("get_health", "use `health`", DeprecationPolicy::WarnOnce);
+    /// })?;
+    /// lua.set_deprecation_handler(|_, deprecation| {
+    ///     println!("warning: {} is deprecated, {}", deprecation.name, deprecation.message);
+    ///     Ok(())
+    /// });
+    ///
+    /// lua.globals().set("player", lua.create_any_userdata(Player)?)?;
+    /// lua.load("player:get_health()").exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserDataRegistry::deprecate_method`]: crate::UserDataRegistry::deprecate_method
+    /// [`DeprecationPolicy`]: crate::DeprecationPolicy
+    /// [`DeprecationPolicy::Error`]: crate::DeprecationPolicy::Error
+    pub fn set_deprecation_handler<F>(&self, handler: F)
+    where
+        F: Fn(&Lua, &Deprecation) -> Result<()> + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).deprecation_handler = Some(Arc::new(handler)) };
+    }
+
+    /// Removes the deprecation handler previously set by [`Lua::set_deprecation_handler`].
+    pub fn remove_deprecation_handler(&self) {
+        unsafe { (*self.extra.get()).deprecation_handler = None };
+    }
+
+    pub(crate) fn deprecation_handler(&self) -> Option<DeprecationHandler> {
+        unsafe { (*self.extra.get()).deprecation_handler.clone() }
+    }
+
+    pub(crate) fn remote_calls(&self) -> &RemoteCallQueue {
+        unsafe { &(*self.extra.get()).remote_calls }
+    }
+
+    #[cfg(all(feature = "async", feature = "send"))]
+    #[inline]
+    pub(crate) fn state_lock(&self) -> &StateLock {
+        &self.0.state_lock
+    }
+
+    /// Sets a hook called whenever a value conversion fails in a Rust callback (functions and
+    /// methods).
+    ///
+    /// It covers arguments that cannot be converted to the expected Rust types, as well as
+    /// [`FromLua`] and [`IntoLua`] failures returned by the callback body. The hook receives the
+    /// callback name, argument position and the types involved, which allows aggregating the
+    /// script call sites passing bad data. The error is raised in Lua as usual.
+    ///
+    /// Only one hook can be set at a time, setting a new one replaces the previous.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use std::sync::Arc;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let failures = Arc::new(AtomicUsize::new(0));
+    /// let failures2 = failures.clone();
+    /// lua.on_conversion_error(move |info| {
+    ///     assert_eq!(info.callback.as_deref(), Some("sqrt"));
+    ///     assert_eq!((info.argument, info.from), (Some(1), "string"));
+    ///     failures2.fetch_add(1, Ordering::Relaxed);
+    /// });
+    ///
+    /// let sqrt = lua.create_function(|_, x: f64| Ok(x.sqrt()))?;
+    /// lua.globals().set("sqrt", sqrt)?;
+    /// assert!(lua.load("sqrt('four')").exec().is_err());
+    /// assert_eq!(failures.load(Ordering::Relaxed), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_conversion_error<F>(&self, hook: F)
+    where
+        F: Fn(&ConversionErrorInfo) + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).conversion_error_hook = Some(Arc::new(hook)) };
+    }
+
+    /// Removes the hook previously set by [`Lua::on_conversion_error`].
+    pub fn remove_conversion_error_hook(&self) {
+        unsafe { (*self.extra.get()).conversion_error_hook = None };
+    }
+
+    /// Sets a callback called after every value conversion between Rust and Lua.
+    ///
+    /// Conversions of function arguments and results (both Lua functions called from Rust and
+    /// Rust callbacks called from Lua), [`Lua::pack`]/[`Lua::unpack`] and their multi-value
+    /// variants, and values of table [`get`]/[`set`] (including the raw ones) are traced with
+    /// the type name, number of Lua values, memory delta and duration. It helps to find
+    /// accidental conversions of deep tables which silently dominate the frame time.
+    ///
+    /// Conversions made while running the callback are not traced.
+    ///
+    /// Requires `feature = "trace_conversions"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{ConversionDirection, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let traces = Arc::new(Mutex::new(Vec::new()));
+    /// let traces2 = traces.clone();
+    /// lua.set_conversion_tracer(move |trace| {
+    ///     traces2.lock().unwrap().push((trace.direction, trace.type_name));
+    /// });
+    ///
+    /// let items: Vec<i64> = lua.load("{1, 2, 3}").eval()?;
+    /// assert_eq!(items.len(), 3);
+    /// assert_eq!(
+    ///     traces.lock().unwrap()[0],
+    ///     (ConversionDirection::FromLua, std::any::type_name::<Vec<i64>>())
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: crate::Table::get
+    /// [`set`]: crate::Table::set
+    #[cfg(feature = "trace_conversions")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace_conversions")))]
+    pub fn set_conversion_tracer<F>(&self, callback: F)
+    where
+        F: Fn(&ConversionTrace) + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).conversion_tracer.callback = Some(Arc::new(callback)) };
+    }
+
+    /// Removes the callback previously set by [`Lua::set_conversion_tracer`].
+    ///
+    /// Requires `feature = "trace_conversions"`
+    #[cfg(feature = "trace_conversions")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace_conversions")))]
+    pub fn remove_conversion_tracer(&self) {
+        unsafe { (*self.extra.get()).conversion_tracer.callback = None };
+    }
+
+    #[inline]
+    pub(crate) fn memory_pressure(&self) -> *mut MemoryPressure {
+        unsafe { &mut (*self.extra.get()).memory_pressure }
+    }
+
+    #[inline]
+    pub(crate) fn keep_alive(&self) -> *mut KeepAlive {
+        unsafe { &mut (*self.extra.get()).keep_alive }
+    }
+
+    #[inline]
+    pub(crate) fn conversion_counter(&self) -> &ConversionCounter {
+        unsafe { &(*self.extra.get()).conversion_counter }
+    }
+
+    #[cfg(feature = "trace_conversions")]
+    pub(crate) fn conversion_tracer(&self) -> &ConversionTracer {
+        unsafe { &(*self.extra.get()).conversion_tracer }
+    }
+
+    #[inline]
+    pub(crate) fn coercion_policy(&self) -> CoercionPolicy {
+        unsafe { (*self.extra.get()).options.coercion }
+    }
+
+    /// Sets a quota limiting resources consumed by all Lua code of this instance.
+    ///
+    /// Once any budget of the quota is exhausted, the running code fails with
+    /// [`Error::QuotaExceeded`]. See [`Quota`] for details.
+    ///
+    /// Instructions are counted using a hook (or an interrupt in Luau). A hook (interrupt) set
+    /// before the quota keeps working, the quota forwards events to it. Setting a hook using
+    /// [`Lua::set_hook`] (or `Lua::set_interrupt`) afterwards replaces the quota hook.
+    /// The hook is inherited by coroutines created afterwards.
+    ///
+    /// Setting a new quota replaces the previous one. Threads with their own quota (see
+    /// [`Thread::set_quota`]) are not affected.
+    ///
+    /// [`Lua::set_hook`]: #method.set_hook
+    pub fn set_quota(&self, quota: Quota) {
+        unsafe {
+            let extra = self.extra.get();
+            // LuaJIT does not recover reliably from failed allocations, memory usage is checked
+            // at hook steps instead
+            #[cfg(not(feature = "luajit"))]
+            {
+                let mem_state = MemoryState::get(self.main_state);
+                if !mem_state.is_null() {
+                    (*mem_state).set_quota_limit(quota.memory_limit().unwrap_or(0));
+                }
+            }
+            (*extra).quota = Some(quota);
+
+            #[cfg(not(feature = "luau"))]
+            {
+                let state = self.state();
+                let main_state = get_main_state(self.main_state).unwrap_or(state);
+                for state in [state, main_state] {
+                    if (*extra).quota_prev_hooks.iter().any(|&(s, _)| s == state) {
+                        continue;
+                    }
+                    let prev = install_quota_hook(state, None);
+                    (*extra).quota_prev_hooks.push((state, prev));
+                }
+            }
+            #[cfg(feature = "luau")]
+            install_quota_interrupt(self.main_state);
+        }
+    }
+
+    /// Returns the quota set by [`Lua::set_quota`].
+    #[cfg(feature = "send")]
+    pub(crate) fn quota(&self) -> Option<Quota> {
+        unsafe { (*self.extra.get()).quota.clone() }
+    }
+
+    /// Sets a quota for a thread (coroutine).
+    pub(crate) unsafe fn set_thread_quota(&self, thread: &LuaRef, quota: Quota) -> Result<()> {
+        let extra = self.extra.get();
+        let locals = self.thread_locals(thread, true)?;
+        (*extra).thread_quotas = true;
+
+        #[cfg(not(feature = "luau"))]
+        {
+            let state = ffi::lua_tothread(self.ref_thread(), thread.index);
+            // Keep the hook replaced by the previous quota of the thread
+            let prev = (*locals).remove::<ThreadQuota>().map(|q| q.prev_hook);
+            let prev_hook = install_quota_hook(state, prev);
+            (*locals).insert(ThreadQuota { quota, prev_hook });
+        }
+        #[cfg(feature = "luau")]
+        {
+            (*locals).insert(ThreadQuota { quota });
+            install_quota_interrupt(self.main_state);
+        }
+        Ok(())
+    }
+
+    /// Removes the quota of a thread (coroutine), restoring its hook.
+    pub(crate) unsafe fn remove_thread_quota(&self, thread: &LuaRef) -> Result<()> {
+        let locals = self.thread_locals(thread, false)?;
+        if locals.is_null() {
+            return Ok(());
+        }
+        #[cfg(not(feature = "luau"))]
+        if let Some(ThreadQuota { prev_hook, .. }) = (*locals).remove::<ThreadQuota>() {
+            let state = ffi::lua_tothread(self.ref_thread(), thread.index);
+            if is_quota_hook(ffi::lua_gethook(state)) {
+                let (hook, mask, count) = prev_hook;
+                ffi::lua_sethook(state, hook, mask, count);
+            }
+        }
+        #[cfg(feature = "luau")]
+        (*locals).remove::<ThreadQuota>();
+        Ok(())
+    }
+
+    /// Removes the quota previously set by [`Lua::set_quota`].
+    ///
+    /// The hook (interrupt) replaced by the quota is restored. Quotas attached to threads
+    /// using [`Thread::set_quota`] are not removed.
+    ///
+    /// This function has no effect if a quota was not previously set.
+    pub fn remove_quota(&self) {
+        unsafe {
+            let extra = self.extra.get();
+            #[cfg(not(feature = "luajit"))]
+            {
+                let mem_state = MemoryState::get(self.main_state);
+                if !mem_state.is_null() {
+                    (*mem_state).set_quota_limit(0);
+                }
+            }
+            (*extra).quota = None;
+
+            // Keep the hook (interrupt) if it was replaced after setting the quota
+            #[cfg(not(feature = "luau"))]
+            for (state, (hook, mask, count)) in (*extra).quota_prev_hooks.drain(..) {
+                if is_quota_hook(ffi::lua_gethook(state)) {
+                    ffi::lua_sethook(state, hook, mask, count);
+                }
+            }
+            #[cfg(feature = "luau")]
+            if !(*extra).thread_quotas {
+                let callbacks = ffi::lua_callbacks(self.main_state);
+                if is_quota_interrupt((*callbacks).interrupt) {
+                    (*callbacks).interrupt = (*extra).quota_prev_interrupt.take();
+                }
+            }
+        }
+    }
+
+    /// Starts recording function enter/exit events of Lua code.
+    ///
+    /// The events are recorded using a hook for function calls and returns (or in Luau, an
+    /// interrupt that compares the call stack with the previous one, so functions returning
+    /// before the next interrupt may be missed and returns are noticed with a delay).
+    /// Any hook (interrupt) already set is kept and still called. The hook is inherited by
+    /// coroutines created afterwards.
+    ///
+    /// If the recording is already running, it's restarted and the recorded events are discarded.
+    ///
+    /// Please note that LuaJIT does not trigger hooks in JIT-compiled code.
+    ///
+    /// Requires `feature = "trace_events"`
+    #[cfg(feature = "trace_events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace_events")))]
+    pub fn start_trace_events(&self) {
+        unsafe {
+            let extra = self.extra.get();
+            let state = self.main_state;
+            let mut recorder = TraceRecorder::new(state);
+            match (*extra).trace_events.take() {
+                // Keep the hook (interrupt) that was set before the previous recording
+                #[cfg(not(feature = "luau"))]
+                Some(prev) => recorder.prev_hook = prev.prev_hook,
+                #[cfg(feature = "luau")]
+                Some(prev) => recorder.prev_interrupt = prev.prev_interrupt,
+                #[cfg(not(feature = "luau"))]
+                None => {
+                    recorder.prev_hook = (
+                        ffi::lua_gethook(state),
+                        ffi::lua_gethookmask(state),
+                        ffi::lua_gethookcount(state),
+                    );
+                }
+                #[cfg(feature = "luau")]
+                None => recorder.prev_interrupt = (*ffi::lua_callbacks(state)).interrupt,
+            }
+
+            #[cfg(not(feature = "luau"))]
+            {
+                let (_, mask, count) = recorder.prev_hook;
+                let mask = mask | ffi::LUA_MASKCALL | ffi::LUA_MASKRET;
+                ffi::lua_sethook(state, Some(trace_events_hook_proc), mask, count);
+            }
+            #[cfg(feature = "luau")]
+            {
+                (*ffi::lua_callbacks(state)).interrupt = Some(trace_events_interrupt_proc);
+            }
+            (*extra).trace_events = Some(recorder);
+        }
+    }
+
+    /// Stops recording function enter/exit events and returns them.
+    ///
+    /// Functions that are still running are closed at the current time.
+    /// Returns `None` if the recording was not started by [`Lua::start_trace_events`].
+    ///
+    /// Requires `feature = "trace_events"`
+    #[cfg(feature = "trace_events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace_events")))]
+    pub fn stop_trace_events(&self) -> Option<TraceEvents> {
+        unsafe {
+            let recorder = (*self.extra.get()).trace_events.take()?;
+            let state = self.main_state;
+            #[cfg(not(feature = "luau"))]
+            {
+                let (hook, mask, count) = recorder.prev_hook;
+                ffi::lua_sethook(state, hook, mask, count);
+            }
+            #[cfg(feature = "luau")]
+            {
+                (*ffi::lua_callbacks(state)).interrupt = recorder.prev_interrupt;
+            }
+            Some(recorder.finish())
+        }
+    }
+
+    /// Enables collecting the number of calls and the self time of Lua functions and Rust
+    /// callbacks.
+    ///
+    /// Lua functions are identified by the location of their definition (`source:line`), Rust
+    /// callbacks created by [`Lua::create_named_function`] (and userdata methods) by their
+    /// registered name, and other Rust callbacks (and C functions) by identity. Function names
+    /// are resolved once, on the first call. Unlike sampling, every call is counted using a hook
+    /// for function calls and returns, so the collection is cheap enough to be left enabled.
+    /// The statistics are returned by [`Lua::function_stats`].
+    ///
+    /// Any hook already set is kept and still called. The hook is inherited by coroutines created
+    /// afterwards. Calling this method when the collection is already enabled has no effect.
+    ///
+    /// Please note that LuaJIT does not trigger hooks in JIT-compiled code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// # #[cfg(feature = "luajit")]
+    /// # lua.load("jit.off()").exec()?;
+    /// lua.enable_function_stats();
+    /// lua.globals().set("double", lua.create_function(|_, x: i64| Ok(x * 2))?)?;
+    /// lua.load(r#"
+    ///     local function sum(n) local s = 0 for i = 1, n do s = s + double(i) end return s end
+    ///     sum(10)
+    /// "#).exec()?;
+    ///
+    /// let stats = lua.function_stats();
+    /// let double = stats.iter().find(|stats| stats.name == "double").unwrap();
+    /// assert_eq!(double.calls, 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn enable_function_stats(&self) {
+        unsafe {
+            let extra = self.extra.get();
+            if (*extra).function_stats.is_some() {
+                return;
+            }
+            let state = self.main_state;
+            let mut recorder = StatsRecorder::new(state);
+            recorder.prev_hook = (
+                ffi::lua_gethook(state),
+                ffi::lua_gethookmask(state),
+                ffi::lua_gethookcount(state),
+            );
+            let (_, mask, count) = recorder.prev_hook;
+            let mask = mask | ffi::LUA_MASKCALL | ffi::LUA_MASKRET;
+            ffi::lua_sethook(state, Some(function_stats_hook_proc), mask, count);
+            (*extra).function_stats = Some(recorder);
+        }
+    }
+
+    /// Disables collecting the function statistics and discards the collected ones.
+    ///
+    /// The hook that was set before [`Lua::enable_function_stats`] is restored, unless the
+    /// collection hook has been replaced since (e.g. by [`Lua::set_hook`]).
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn disable_function_stats(&self) {
+        unsafe {
+            if let Some(recorder) = (*self.extra.get()).function_stats.take() {
+                // Keep the hook that replaced the stats one (e.g. set by `Lua::set_hook`)
+                let hook = ffi::lua_gethook(self.main_state).map(|hook| hook as usize);
+                if hook == Some(function_stats_hook_proc as ffi::lua_Hook as usize) {
+                    let (hook, mask, count) = recorder.prev_hook;
+                    ffi::lua_sethook(self.main_state, hook, mask, count);
+                }
+            }
+        }
+    }
+
+    /// Resets the collected function statistics, keeping the collection enabled.
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn reset_function_stats(&self) {
+        unsafe {
+            if let Some(ref mut recorder) = (*self.extra.get()).function_stats {
+                recorder.reset();
+            }
+        }
+    }
+
+    /// Returns the function statistics collected since [`Lua::enable_function_stats`] (or the
+    /// last [`Lua::reset_function_stats`]), sorted by self time in descending order.
+    ///
+    /// Calls that are still running are counted, along with the time spent in them so far.
+    /// Returns an empty list if the collection is not enabled.
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn function_stats(&self) -> Vec<FunctionStats> {
+        unsafe {
+            match (*self.extra.get()).function_stats {
+                Some(ref recorder) => recorder.report(),
+                None => Vec::new(),
+            }
+        }
+    }
+
+    /// Resumes `thread` for at most `duration`, preempting it when the time slice expires.
+    ///
+    /// Returns `Ok(None)` if the time slice has expired. The thread remains resumable and can be
+    /// continued by calling this function again (`args` are ignored in that case).
+    /// Otherwise returns values yielded or returned by the thread, same as [`Thread::resume`].
+    ///
+    /// The deadline is checked using a hook (or an interrupt in Luau) that is installed for the
+    /// duration of the call and wraps the current one (e.g. a [`Quota`]).
+    /// Code running in a context where the thread cannot yield (such as Rust callbacks or
+    /// metamethods) is preempted as soon as it returns to plain Lua code.
+    ///
+    /// Requires `feature = "lua54/lua53/luau"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mlua::{Lua, Result, ThreadStatus};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let thread = lua.create_thread(lua.load("while true do end").into_function()?)?;
+    ///
+    /// // Each call runs the script for 1ms and then gives control back
+    /// for _ in 0..3 {
+    ///     assert_eq!(lua.run_for::<_, ()>(Duration::from_millis(1), &thread, ())?, None);
+    ///     assert_eq!(thread.status(), ThreadStatus::Resumable);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "lua54", feature = "lua53", feature = "luau")))
+    )]
+    pub fn run_for<'lua, A, R>(
+        &'lua self,
+        duration: Duration,
+        thread: &Thread<'lua>,
+        args: A,
+    ) -> Result<Option<R>>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        if thread.status() != ThreadStatus::Resumable {
+            return Err(Error::CoroutineInactive);
+        }
+
+        // Overflowing durations run without a deadline
+        let deadline = Instant::now().checked_add(duration);
+        let resume = || thread.resume::<_, MultiValue>(args);
+        let (result, expired) = unsafe { self.with_time_slice(thread.1, deadline, resume) };
+        let values = result?;
+        if expired && thread.status() == ThreadStatus::Resumable {
+            return Ok(None);
+        }
+        R::from_lua_multi(values, self).map(Some)
+    }
+
+    // Runs `f`, yielding the thread `thread_state` (when it resumes within `f`) once the deadline
+    // (if any) passes. Returns the result of `f` and whether the thread was preempted.
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    pub(crate) unsafe fn with_time_slice<R>(
+        &self,
+        thread_state: *mut ffi::lua_State,
+        deadline: Option<Instant>,
+        f: impl FnOnce() -> R,
+    ) -> (R, bool) {
+        let extra = self.extra.get();
+        #[cfg(not(feature = "luau"))]
+        let (prev_mask, prev_count) = (
+            ffi::lua_gethookmask(thread_state),
+            ffi::lua_gethookcount(thread_state),
+        );
+        let slice = TimeSlice {
+            thread: thread_state,
+            deadline,
+            expired: false,
+            #[cfg(not(feature = "luau"))]
+            prev_hook: ffi::lua_gethook(thread_state),
+            #[cfg(not(feature = "luau"))]
+            prev_mask,
+            #[cfg(feature = "luau")]
+            prev_interrupt: (*ffi::lua_callbacks(self.main_state)).interrupt,
+        };
+        let prev_slice = (*extra).time_slice.replace(slice);
+
+        #[cfg(not(feature = "luau"))]
+        {
+            let count = match prev_mask & ffi::LUA_MASKCOUNT {
+                0 => TimeSlice::INSTRUCTIONS_STEP,
+                _ => prev_count,
+            };
+            let mask = prev_mask | ffi::LUA_MASKCOUNT;
+            ffi::lua_sethook(thread_state, Some(time_slice_hook_proc), mask, count);
+        }
+        #[cfg(feature = "luau")]
+        {
+            (*ffi::lua_callbacks(self.main_state)).interrupt = Some(time_slice_interrupt_proc);
+        }
+
+        let result = f();
+
+        let slice = mem::replace(&mut (*extra).time_slice, prev_slice);
+        let slice = mlua_expect!(slice, "time slice is missing");
+        #[cfg(not(feature = "luau"))]
+        ffi::lua_sethook(thread_state, slice.prev_hook, slice.prev_mask, prev_count);
+        #[cfg(feature = "luau")]
+        {
+            (*ffi::lua_callbacks(self.main_state)).interrupt = slice.prev_interrupt;
+        }
+        (result, slice.expired)
+    }
+
+    /// Sets the warning function to be used by Lua to emit warnings.
+    ///
+    /// Requires `feature = "lua54"`
+    #[cfg(feature = "lua54")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
+    pub fn set_warning_function<F>(&self, callback: F)
+    where
+        F: Fn(&Lua, &str, bool) -> Result<()> + MaybeSend + 'static,
+    {
+        unsafe extern "C-unwind" fn warn_proc(ud: *mut c_void, msg: *const c_char, tocont: c_int) {
+            let extra = ud as *mut ExtraData;
+            let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+            callback_error_ext(lua.state(), extra, |_| {
+                let cb = mlua_expect!(
+                    (*extra).warn_callback.as_ref(),
+                    "no warning callback set in warn_proc"
+                );
+                let msg = std::string::String::from_utf8_lossy(CStr::from_ptr(msg).to_bytes());
+                cb(lua, &msg, tocont != 0)
+            });
+        }
+
+        let state = self.main_state;
+        unsafe {
             (*self.extra.get()).warn_callback = Some(Box::new(callback));
             ffi::lua_setwarnf(state, Some(warn_proc), self.extra.get() as *mut c_void);
         }
@@ -1084,17 +2641,83 @@ impl Lua {
 
     /// Returns the amount of memory (in bytes) currently used inside this Lua state.
     pub fn used_memory(&self) -> usize {
+        unsafe { used_memory(self.main_state) }
+    }
+
+    /// Returns statistics of live userdata instances for every userdata type created
+    /// in this Lua state, sorted by the amount of used memory (largest first).
+    ///
+    /// Instances created using [`Scope::create_nonstatic_userdata`] are not counted.
+    ///
+    /// [`Scope::create_nonstatic_userdata`]: crate::Scope::create_nonstatic_userdata
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let _ud = lua.create_any_userdata([0u8; 64])?;
+    /// let stats = lua.userdata_stats();
+    /// assert_eq!(stats[0].live, 1);
+    /// assert!(stats[0].bytes >= 64);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn userdata_stats(&self) -> Vec<UserDataStats> {
+        let extra = unsafe { &*self.extra.get() };
+        let mut stats = extra
+            .userdata_counters
+            .values()
+            .map(|counter| counter.stats())
+            .collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.type_name.cmp(b.type_name)));
+        stats
+    }
+
+    /// Returns usage statistics of the Lua stacks used by mlua.
+    ///
+    /// Helps to diagnose [`Error::StackError`] failures, eg. caused by deeply nested conversions
+    /// or by holding too many references at once.
+    ///
+    /// The deepest stack usage and the call site responsible for it are tracked only with
+    /// `feature = "stack_diagnostics"`, which adds a small overhead to every mlua call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let tables = (0..100).map(|_| lua.create_table()).collect::<Result<Vec<_>>>()?;
+    /// let stats = lua.stack_stats();
+    /// assert!(stats.ref_used >= 100 && stats.ref_peak >= stats.ref_used);
+    ///
+    /// drop(tables);
+    /// assert!(lua.stack_stats().ref_used < 100);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stack_stats(&self) -> StackStats {
+        let extra = unsafe { &*self.extra.get() };
+        #[allow(unused_mut)]
+        let mut stats = StackStats {
+            current: unsafe { ffi::lua_gettop(self.state()) } as usize,
+            ref_used: (extra.ref_stack_top as usize).saturating_sub(extra.ref_free.len()),
+            ref_peak: extra.ref_stack_peak as usize,
+            ref_capacity: extra.ref_stack_size as usize,
+            peak: 0,
+            peak_location: None,
+        };
+        #[cfg(feature = "stack_diagnostics")]
         unsafe {
-            match MemoryState::get(self.main_state) {
-                mem_state if !mem_state.is_null() => (*mem_state).used_memory(),
-                _ => {
-                    // Get data from the Lua GC
-                    let used_kbytes = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNT, 0);
-                    let used_kbytes_rem = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNTB, 0);
-                    (used_kbytes as usize) * 1024 + (used_kbytes_rem as usize)
-                }
+            let mem_state = MemoryState::get(self.main_state);
+            if let Some((peak, location)) = mem_state.as_ref().and_then(|m| m.stack_peak()) {
+                stats.peak = peak as usize;
+                stats.peak_location = Some(location);
             }
         }
+        stats
     }
 
     /// Sets a memory limit (in bytes) on this Lua state.
@@ -1113,6 +2736,81 @@ impl Lua {
         }
     }
 
+    /// Calls `f` attributing memory allocated by Lua during the call to the category `name`.
+    ///
+    /// Categories are created on first use, up to 255 in addition to the default `main`
+    /// category. Objects are attributed to the category which was active when they were created.
+    /// Coroutines inherit the category of the thread creating them.
+    ///
+    /// Memory usage of every category can be retrieved using [`Lua::memory_by_category`].
+    ///
+    /// Requires `feature = "luau"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.with_memory_category("ui", || {
+    ///     lua.load("widgets = table.create(1000, 0)").exec()
+    /// })?;
+    /// assert!(lua.memory_by_category()["ui"] >= 1000 * 16);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn with_memory_category<R>(&self, name: &str, f: impl FnOnce() -> Result<R>) -> Result<R> {
+        struct CategoryGuard<'a>(&'a Lua, *mut ffi::lua_State, c_int);
+
+        impl<'a> Drop for CategoryGuard<'a> {
+            fn drop(&mut self) {
+                unsafe {
+                    (*self.0.extra.get()).memory_category = self.2;
+                    ffi::lua_setmemcat(self.1, self.2);
+                }
+            }
+        }
+
+        let extra = unsafe { &mut *self.extra.get() };
+        let category = match extra.memory_categories.iter().position(|n| n == name) {
+            Some(category) => category as c_int,
+            None if extra.memory_categories.len() < ffi::LUA_MEMORY_CATEGORIES as usize => {
+                extra.memory_categories.push(name.into());
+                (extra.memory_categories.len() - 1) as c_int
+            }
+            None => {
+                return Err(Error::RuntimeError(format!(
+                    "cannot create memory category '{name}': too many categories"
+                )))
+            }
+        };
+
+        let state = self.state();
+        let _guard = CategoryGuard(self, state, extra.memory_category);
+        extra.memory_category = category;
+        unsafe { ffi::lua_setmemcat(state, category) };
+        f()
+    }
+
+    /// Returns the amount of memory (in bytes) currently used by every memory category.
+    ///
+    /// Always contains the default `main` category. See [`Lua::with_memory_category`].
+    ///
+    /// Requires `feature = "luau"`
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn memory_by_category(&self) -> std::collections::HashMap<std::string::String, usize> {
+        let extra = unsafe { &*self.extra.get() };
+        let mut usage = std::collections::HashMap::with_capacity(extra.memory_categories.len());
+        for (category, name) in extra.memory_categories.iter().enumerate() {
+            let bytes = unsafe { ffi::lua_totalbytes(self.main_state, category as c_int) };
+            usage.insert(name.clone(), bytes);
+        }
+        usage
+    }
+
     /// Returns true if the garbage collector is currently running automatically.
     ///
     /// Requires `feature = "lua54/lua53/lua52/luau"`
@@ -1140,6 +2838,7 @@ impl Lua {
     ///
     /// It may be necessary to call this function twice to collect all currently unreachable
     /// objects. Once to finish the current gc cycle, and once to start and finish the next cycle.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn gc_collect(&self) -> Result<()> {
         unsafe {
             check_stack(self.main_state, 2)?;
@@ -1158,6 +2857,7 @@ impl Lua {
     ///
     /// if `kbytes` is 0, then this is the same as calling `gc_step`. Returns true if this step has
     /// finished a collection cycle.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn gc_step_kbytes(&self, kbytes: c_int) -> Result<bool> {
         unsafe {
             check_stack(self.main_state, 3)?;
@@ -1167,6 +2867,87 @@ impl Lua {
         }
     }
 
+    /// Steps the garbage collector within the given budget.
+    ///
+    /// The collector does incremental work as though `kbytes` kilobytes had been allocated (or a
+    /// single basic step if `kbytes` is 0). Returns true if this step has finished a collection
+    /// cycle.
+    ///
+    /// Unlike [`gc_step_kbytes`], it keeps the host-driven mode set by
+    /// [`gc_set_incremental_callback`]: automatic collection is not resumed after the step on any
+    /// backend. The incremental callback, if any, is called after the step.
+    ///
+    /// [`gc_step_kbytes`]: #method.gc_step_kbytes
+    /// [`gc_set_incremental_callback`]: #method.gc_set_incremental_callback
+    pub fn gc_step_budget(&self, kbytes: c_int) -> Result<bool> {
+        let finished = self.gc_step_kbytes(kbytes)?;
+        let callback = unsafe { (*self.extra.get()).gc_step_callback.clone() };
+        if let Some(callback) = callback {
+            // Stepping resumes automatic collection in Lua 5.1, LuaJIT and Luau
+            self.gc_stop();
+            callback(self, finished)?;
+        }
+        Ok(finished)
+    }
+
+    /// Switches the garbage collector to host-driven incremental mode.
+    ///
+    /// Automatic collection is stopped, and the host is expected to call [`gc_step_budget`]
+    /// regularly (e.g. once per frame) to spread collection work deterministically. The
+    /// `callback` is called after every budgeted step with a flag whether the step finished a
+    /// collection cycle, and can be used to adjust the budget. Errors returned by the callback
+    /// are propagated from [`gc_step_budget`].
+    ///
+    /// Memory is not reclaimed unless the host steps the collector, so the budget must keep up
+    /// with the allocation rate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use std::sync::Arc;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let cycles = Arc::new(AtomicUsize::new(0));
+    /// let cycles2 = cycles.clone();
+    /// lua.gc_set_incremental_callback(move |_, finished| {
+    ///     if finished {
+    ///         cycles2.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// // The host frame loop
+    /// while cycles.load(Ordering::Relaxed) == 0 {
+    ///     lua.load("local t = {} for i = 1, 100 do t[i] = {} end").exec()?;
+    ///     lua.gc_step_budget(16)?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`gc_step_budget`]: #method.gc_step_budget
+    pub fn gc_set_incremental_callback<F>(&self, callback: F)
+    where
+        F: Fn(&Lua, bool) -> Result<()> + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).gc_step_callback = Some(Arc::new(callback)) };
+        self.gc_stop();
+    }
+
+    /// Removes the callback set by [`gc_set_incremental_callback`] and restarts automatic
+    /// garbage collection.
+    ///
+    /// This function has no effect if the callback was not previously set.
+    ///
+    /// [`gc_set_incremental_callback`]: #method.gc_set_incremental_callback
+    pub fn gc_remove_incremental_callback(&self) {
+        if unsafe { (*self.extra.get()).gc_step_callback.take() }.is_some() {
+            self.gc_restart();
+        }
+    }
+
     /// Sets the 'pause' value of the collector.
     ///
     /// Returns the previous value of 'pause'. More information can be found in the Lua
@@ -1264,6 +3045,207 @@ impl Lua {
         }
     }
 
+    /// Sets a callback to be notified about garbage collection cycles.
+    ///
+    /// The callback is called with [`GcEvent::CycleEnd`] when a full GC cycle is completed
+    /// (detected using a sentinel object with a `__gc` metamethod), followed by
+    /// [`GcEvent::CycleStart`] for the next cycle.
+    /// The callback must not access the Lua state. Panics in the callback are ignored.
+    ///
+    /// Only one callback can be set at a time, setting a new one replaces the previous.
+    ///
+    /// In Luau the sentinel is a userdata with a destructor, which cannot allocate a new one, so
+    /// the next cycle is tracked (and [`GcEvent::CycleStart`] reported) at the next interrupt
+    /// safepoint. Until Lua code is run again, collected cycles are not reported.
+    /// The callback shares the interrupt handler with [`Lua::set_interrupt`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use std::sync::Arc;
+    /// # use mlua::{GcEvent, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let cycles = Arc::new(AtomicUsize::new(0));
+    /// let cycles2 = cycles.clone();
+    /// lua.on_gc(move |event| {
+    ///     if let GcEvent::CycleEnd { duration, freed_bytes } = event {
+    ///         println!("GC cycle took {duration:?} and freed {freed_bytes} bytes");
+    ///         cycles2.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// })?;
+    /// lua.gc_collect()?;
+    /// assert!(cycles.load(Ordering::Relaxed) > 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::arc_with_non_send_sync)]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn on_gc<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(GcEvent) + MaybeSend + 'static,
+    {
+        let state = self.main_state;
+        unsafe {
+            let extra = &mut *self.extra.get();
+            if let Some(notifier) = extra.gc_notifier.take() {
+                notifier.active.store(false, Ordering::Relaxed);
+            }
+
+            let notifier = Arc::new(GcNotifier {
+                callback: Box::new(callback),
+                active: AtomicBool::new(true),
+                #[cfg(feature = "luau")]
+                rearm: AtomicBool::new(false),
+            });
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+            push_gc_sentinel(state, notifier.clone())?;
+            extra.gc_notifier = Some(notifier);
+            #[cfg(feature = "luau")]
+            {
+                (*ffi::lua_callbacks(state)).interrupt = Some(interrupt_proc);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a callback previously set by [`Lua::on_gc`].
+    ///
+    /// This function has no effect if a callback was not previously set.
+    pub fn remove_gc_callback(&self) {
+        unsafe {
+            let extra = &mut *self.extra.get();
+            if let Some(notifier) = extra.gc_notifier.take() {
+                notifier.active.store(false, Ordering::Relaxed);
+            }
+            #[cfg(feature = "luau")]
+            if extra.interrupt_callback.is_none() {
+                (*ffi::lua_callbacks(self.main_state)).interrupt = None;
+            }
+        }
+    }
+
+    /// Redirects the standard streams used by scripts to host-provided streams.
+    ///
+    /// Replaces the global `print` function and (when the `io` library is loaded) the `io.write`,
+    /// `io.read` and `io.lines` functions and the `io.stdout`, `io.stderr` and `io.stdin` file
+    /// objects. This allows to capture the output of every Lua state separately instead of
+    /// interleaving it on the process stdio.
+    ///
+    /// Functions that open other files (e.g. `io.open`) are not affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::{self, Write};
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// #[derive(Clone, Default)]
+    /// struct Capture(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Capture {
+    ///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let lua = Lua::new();
+    /// let output = Capture::default();
+    /// lua.set_stdio(output.clone(), io::sink(), io::empty())?;
+    /// lua.load(r#"print("hello", 1)"#).exec()?;
+    /// assert_eq!(*output.0.lock().unwrap(), b"hello\t1\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_stdio<O, E, I>(&self, stdout: O, stderr: E, stdin: I) -> Result<()>
+    where
+        O: io::Write + MaybeSend + 'static,
+        E: io::Write + MaybeSend + 'static,
+        I: io::Read + MaybeSend + 'static,
+    {
+        crate::stdio::set_stdio(self, Box::new(stdout), Box::new(stderr), Box::new(stdin))
+    }
+
+    /// Sets a handler intercepting attempts of scripts to terminate the host process.
+    ///
+    /// The handler is called with the requested exit code when a script calls `os.exit`, and
+    /// with `1` when Lua is about to abort the process because of an unprotected error (in the
+    /// panic function). The returned [`ExitAction`] decides what happens next:
+    ///
+    /// * [`ExitAction::Error`] raises [`Error::ExitRequested`] instead. Scripts can catch it using
+    ///   `pcall`, otherwise it is returned to the Rust caller (wrapped into
+    ///   [`Error::CallbackError`]). In the panic function it is raised as a Rust panic with the
+    ///   error as a payload, after which the Lua state must not be used anymore.
+    /// * [`ExitAction::Exit`] proceeds with the default behavior.
+    ///
+    /// The `os` library must be loaded before setting the handler for `os.exit` to be intercepted.
+    /// The handler replaces a panic function set using [`LuaBuilder::panic_fn`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, ExitAction, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.on_exit_request(|code| {
+    ///     println!("script requested exit with code {code}");
+    ///     ExitAction::Error
+    /// })?;
+    ///
+    /// match lua.load("os.exit(3)").exec() {
+    ///     Err(Error::CallbackError { cause, .. }) => {
+    ///         assert!(matches!(*cause, Error::ExitRequested { code: 3 }));
+    ///     }
+    ///     r => panic!("unexpected result: {r:?}"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn on_exit_request<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(i32) -> ExitAction + MaybeSend + 'static,
+    {
+        unsafe {
+            let extra = &mut *self.extra.get();
+            if extra.exit_callback.replace(Arc::new(callback)).is_some() {
+                // `os.exit` is already intercepted
+                return Ok(());
+            }
+            ffi::lua_atpanic(self.main_state, exit_panic_proc);
+        }
+
+        let Some(os) = self.globals().raw_get::<_, Option<Table>>("os")? else {
+            return Ok(());
+        };
+        let Some(exit) = os.raw_get::<_, Option<Function>>("exit")? else {
+            return Ok(());
+        };
+        let exit = self.create_registry_value(exit)?;
+        let exit = self.create_function(move |lua, args: MultiValue| {
+            let code = match args.get(0) {
+                None | Some(Value::Nil) | Some(Value::Boolean(true)) => 0,
+                Some(Value::Boolean(false)) => 1,
+                Some(code) => lua.unpack::<i32>(code.clone())?,
+            };
+            let callback = unsafe { (*lua.extra.get()).exit_callback.clone() };
+            match callback.map(|callback| callback(code)) {
+                Some(ExitAction::Error) => Err(Error::ExitRequested { code }),
+                _ => lua
+                    .registry_value::<Function>(&exit)?
+                    .call::<_, MultiValue>(args),
+            }
+        })?;
+        os.raw_set("exit", exit)
+    }
+
     /// Sets a default Luau compiler (with custom options).
     ///
     /// This compiler will be used by default to load all Lua chunks
@@ -1324,6 +3306,7 @@ impl Lua {
         }
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn load_chunk<'lua>(
         &'lua self,
         name: Option<&CStr>,
@@ -1351,7 +3334,7 @@ impl Lua {
             ) {
                 ffi::LUA_OK => {
                     if let Some(env) = env {
-                        self.push_ref(&env.0);
+                        self.try_push_ref(&env.0)?;
                         #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
                         ffi::lua_setupvalue(state, -2, 1);
                         #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
@@ -1373,6 +3356,7 @@ impl Lua {
     /// Create and return an interned Lua string. Lua strings can be arbitrary [u8] data including
     /// embedded nulls, so in addition to `&str` and `&String`, you can also pass plain `&[u8]`
     /// here.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_string(&self, s: impl AsRef<[u8]>) -> Result<String> {
         let state = self.state();
         unsafe {
@@ -1394,6 +3378,7 @@ impl Lua {
     ///
     /// [buffer]: https://luau-lang.org/library#buffer-library
     #[cfg(feature = "luau")]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_buffer(&self, buf: impl AsRef<[u8]>) -> Result<AnyUserData> {
         let state = self.state();
         unsafe {
@@ -1418,6 +3403,7 @@ impl Lua {
     /// `narr` is a hint for how many elements the table will have as a sequence;
     /// `nrec` is a hint for how many other elements the table will have.
     /// Lua may use these hints to preallocate memory for the new table.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_table_with_capacity(&self, narr: usize, nrec: usize) -> Result<Table> {
         let state = self.state();
         unsafe {
@@ -1433,7 +3419,53 @@ impl Lua {
         }
     }
 
+    /// Creates and returns a new empty weak table with the specified [`WeakMode`].
+    ///
+    /// [`WeakMode`]: crate::WeakMode
+    pub fn create_weak_table(&self, mode: WeakMode) -> Result<Table> {
+        let table = self.create_table()?;
+        let metatable = self.create_table_with_capacity(0, 1)?;
+        metatable.raw_set("__mode", mode.as_str())?;
+        table.set_metatable(Some(metatable));
+        Ok(table)
+    }
+
+    /// Creates and returns a new metatable built by `f`.
+    ///
+    /// Metamethods are implemented by Rust callbacks and the resulting table can be applied
+    /// to plain tables, e.g. to implement proxy objects without defining a [`UserData`] type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, MetaMethod, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let mt = lua.create_metatable(|mt| {
+    ///     mt.add_meta_function(MetaMethod::Index, |_, (_, key): (Table, String)| {
+    ///         Ok(key.to_uppercase())
+    ///     });
+    /// })?;
+    ///
+    /// let proxy = lua.create_table()?;
+    /// proxy.set_metatable(Some(mt));
+    /// assert_eq!(proxy.get::<_, String>("hello")?, "HELLO");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserData`]: crate::UserData
+    pub fn create_metatable<'lua, F>(&'lua self, f: F) -> Result<Table<'lua>>
+    where
+        F: FnOnce(&mut MetatableBuilder<'lua>),
+    {
+        let mut builder = MetatableBuilder::new(self)?;
+        f(&mut builder);
+        builder.build()
+    }
+
     /// Creates a table and fills it with values from an iterator.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_table_from<'lua, K, V, I>(&'lua self, iter: I) -> Result<Table<'lua>>
     where
         K: IntoLua<'lua>,
@@ -1464,6 +3496,7 @@ impl Lua {
     }
 
     /// Creates a table from an iterator of values, using `1..` as the keys.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_sequence_from<'lua, T, I>(&'lua self, iter: I) -> Result<Table<'lua>>
     where
         T: IntoLua<'lua>,
@@ -1526,28 +3559,83 @@ impl Lua {
     /// ```
     /// # use mlua::{Lua, Result};
     /// # fn main() -> Result<()> {
-    /// # let lua = Lua::new();
-    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
-    ///     println!("{} is {} years old!", name, age);
-    ///     Ok(())
-    /// });
-    /// # let _ = print_person;    // used
+    /// # let lua = Lua::new();
+    /// let print_person = lua.create_function(|_, (name, age): (String, u8)| {
+    ///     println!("{} is {} years old!", name, age);
+    ///     Ok(())
+    /// });
+    /// # let _ = print_person;    // used
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`IntoLua`]: crate::IntoLua
+    /// [`IntoLuaMulti`]: crate::IntoLuaMulti
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn create_function<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+    {
+        let func = self.create_callback(Box::new(move |lua, nargs| unsafe {
+            let args = A::from_stack_args(nargs, 1, None, lua)?;
+            func(lua, args)?.push_into_stack_multi(lua)
+        }))?;
+        self.register_function(func, None, A::NARGS, false)
+    }
+
+    /// Wraps a Rust function, creating a callable Lua function with the given name.
+    ///
+    /// This is a version of [`create_function`] that names the function in diagnostics instead
+    /// of an anonymous `[C]` entry. The name and the location of the call to this method (as the
+    /// function source) are reported by argument conversion errors (eg. ``bad argument #1 to
+    /// `greet`: ...``), the traceback of errors raised by the function, `debug.traceback` and
+    /// `debug.getinfo`, hooks ([`Debug::names`] and [`Debug::source`]), [`Function::info`],
+    /// [`Function::call_traced`] and function stats. See also [`Args`].
+    ///
+    /// The function is also registered for introspection (see [`Lua::registered_functions`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let get = lua.create_named_function("http.get", |_, url: String| Ok(url.len()))?;
+    /// assert_eq!(get.info().name.as_deref(), Some("http.get"));
+    /// assert_eq!(get.info().short_src.as_deref(), Some(file!()));
     /// # Ok(())
     /// # }
     /// ```
     ///
-    /// [`IntoLua`]: crate::IntoLua
-    /// [`IntoLuaMulti`]: crate::IntoLuaMulti
-    pub fn create_function<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    /// [`create_function`]: #method.create_function
+    /// [`Args`]: crate::Args
+    /// [`Debug::names`]: crate::hook::Debug::names
+    /// [`Debug::source`]: crate::hook::Debug::source
+    /// [`Function::info`]: crate::Function::info
+    /// [`Function::call_traced`]: crate::Function::call_traced
+    /// [`Lua::registered_functions`]: #method.registered_functions
+    #[track_caller]
+    pub fn create_named_function<'lua, A, R, F>(
+        &'lua self,
+        name: &str,
+        func: F,
+    ) -> Result<Function<'lua>>
     where
         A: FromLuaMulti<'lua>,
         R: IntoLuaMulti<'lua>,
         F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
     {
-        self.create_callback(Box::new(move |lua, nargs| unsafe {
-            let args = A::from_stack_args(nargs, 1, None, lua)?;
+        let callback_name = CallbackName::new(name, Some(Location::caller()))?;
+        let name = name.to_string();
+        let func_name = name.clone();
+        let func = Box::new(move |lua, nargs| unsafe {
+            let args = A::from_stack_args(nargs, 1, Some(&func_name), lua)?;
             func(lua, args)?.push_into_stack_multi(lua)
-        }))
+        });
+        let func = self.create_callback_with_name(func, Some(callback_name))?;
+        self.register_function(func, Some(name), A::NARGS, false)
     }
 
     /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
@@ -1570,10 +3658,98 @@ impl Lua {
         })
     }
 
+    /// Wraps a Rust function, creating a callable Lua function that reports errors using the
+    /// `nil, message` convention instead of raising them.
+    ///
+    /// If `func` returns an error, the Lua function returns `nil` followed by the error message,
+    /// as many functions in the Lua standard library (e.g. `io.open`) do.
+    /// Errors converting the arguments are still raised.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Error};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let parse = lua.create_function_fallible(|_, s: String| {
+    ///     s.parse::<i64>().map_err(Error::external)
+    /// })?;
+    /// lua.globals().set("parse", parse)?;
+    /// lua.load(r#"
+    ///     local n, err = parse("nan")
+    ///     assert(n == nil and err == "invalid digit found in string")
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn create_function_fallible<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+    {
+        let func = self.create_callback(Box::new(move |lua, nargs| unsafe {
+            let args = A::from_stack_args(nargs, 1, None, lua)?;
+            match func(lua, args) {
+                Ok(ret) => ret.push_into_stack_multi(lua),
+                Err(err) => (Nil, err.to_string()).push_into_stack_multi(lua),
+            }
+        }))?;
+        self.register_function(func, None, A::NARGS, false)
+    }
+
+    /// Wraps a Rust function, creating a callable Lua function with direct access to its stack.
+    ///
+    /// This is a low-level alternative to [`create_function`] for performance-critical bindings.
+    /// Arguments are read and results are pushed using [`Stack`], avoiding intermediate
+    /// conversions, while errors and panics are still handled by mlua.
+    /// The function must return the number of values (from the top of the stack) to return to Lua.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let sum = lua.create_raw_function(|_, stack| {
+    ///     let mut sum = 0;
+    ///     for i in 1..=stack.nargs() {
+    ///         sum += stack.get::<i64>(i)?;
+    ///     }
+    ///     stack.push(sum)?;
+    ///     Ok(1)
+    /// })?;
+    /// assert_eq!(sum.call::<_, i64>((1, 2, 3))?, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn create_raw_function<'lua, F>(&'lua self, func: F) -> Result<Function<'lua>>
+    where
+        F: Fn(&'lua Lua, &mut Stack<'lua>) -> Result<usize> + MaybeSend + 'static,
+    {
+        let func = self.create_callback(Box::new(move |lua, nargs| unsafe {
+            let mut stack = Stack::new(lua, nargs);
+            let nresults = func(lua, &mut stack)?;
+            if nresults > stack.len() {
+                return Err(Error::RuntimeError(format!(
+                    "raw function returned {nresults} values, but only {} are on the stack",
+                    stack.len()
+                )));
+            }
+            Ok(nresults as c_int)
+        }))?;
+        self.register_function(func, None, None, false)
+    }
+
     /// Wraps a C function, creating a callable Lua function handle to it.
     ///
     /// # Safety
     /// This function is unsafe because provides a way to execute unsafe C function.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub unsafe fn create_c_function(&self, func: ffi::lua_CFunction) -> Result<Function> {
         let state = self.state();
         check_stack(state, 1)?;
@@ -1622,6 +3798,7 @@ impl Lua {
     /// [`AsyncThread`]: crate::AsyncThread
     #[cfg(feature = "async")]
     #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_async_function<'lua, A, R, F, FR>(&'lua self, func: F) -> Result<Function<'lua>>
     where
         A: FromLuaMulti<'lua>,
@@ -1629,26 +3806,186 @@ impl Lua {
         F: Fn(&'lua Lua, A) -> FR + MaybeSend + 'static,
         FR: Future<Output = Result<R>> + 'lua,
     {
-        self.create_async_callback(Box::new(move |lua, args| unsafe {
+        let func = self.create_async_callback(Box::new(move |lua, args| unsafe {
             let args = match A::from_lua_args(args, 1, None, lua) {
                 Ok(args) => args,
                 Err(e) => return Box::pin(future::err(e)),
             };
             let fut = func(lua, args);
             Box::pin(async move { fut.await?.push_into_stack_multi(lua) })
-        }))
+        }))?;
+        self.register_function(func, None, A::NARGS, true)
+    }
+
+    /// Wraps a Rust async function or closure, creating a callable Lua function handle to it,
+    /// whose calls are tracked by the `Lua` instance.
+    ///
+    /// Works like [`Lua::create_async_function`], but the pending futures are force-cancelled
+    /// (dropped, running their cleanup) when [`Lua::cancel_pending_async`] is called or the Lua
+    /// state is closed, so they never outlive the state.
+    ///
+    /// A thread waiting on a cancelled future is woken and the call raises a runtime error.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use mlua::{Lua, Result};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     let sleep = lua.create_async_scope_function(|_, n: u64| async move {
+    ///         tokio::time::sleep(Duration::from_millis(n)).await;
+    ///         Ok(())
+    ///     })?;
+    ///
+    ///     let thread = lua.create_thread(sleep)?;
+    ///     let _ = futures::poll!(Box::pin(thread.clone().into_async::<_, ()>(1000)));
+    ///     assert_eq!(lua.cancel_pending_async(), 1);
+    ///     assert!(thread.into_async::<_, ()>(()).await.is_err());
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn create_async_scope_function<'lua, A, R, F, FR>(
+        &'lua self,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: Fn(&'lua Lua, A) -> FR + MaybeSend + 'static,
+        FR: Future<Output = Result<R>> + 'lua,
+    {
+        let func = self.create_async_callback(Box::new(move |lua, args| unsafe {
+            let args = match A::from_lua_args(args, 1, None, lua) {
+                Ok(args) => args,
+                Err(e) => return Box::pin(future::err(e)),
+            };
+            let fut = func(lua, args);
+            let fut: LocalBoxFuture<'lua, Result<c_int>> =
+                Box::pin(async move { fut.await?.push_into_stack_multi(lua) });
+            // Futures are dropped by `cancel_pending_async` or before the state is closed
+            let fut: LocalBoxFuture<'static, Result<c_int>> = mem::transmute(fut);
+            Box::pin((*lua.extra.get()).pending_async.track(fut))
+        }))?;
+        self.register_function(func, None, A::NARGS, true)
+    }
+
+    /// Cancels all pending calls of functions created by [`Lua::create_async_scope_function`].
+    ///
+    /// The futures are dropped immediately, unless being polled (then as soon as the poll
+    /// returns). Returns the number of cancelled futures.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn cancel_pending_async(&self) -> usize {
+        self.cancel_pending_async_futures()
+    }
+
+    /// Sets hooks notified when an [`AsyncThread`] is resumed, yields and completes.
+    ///
+    /// See [`AsyncSchedulerHooks`] for details.
+    /// Only one set of hooks can be active at a time, setting new hooks replaces the previous.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// [`AsyncThread`]: crate::AsyncThread
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn set_async_scheduler_hooks(&self, hooks: AsyncSchedulerHooks) {
+        unsafe { (*self.extra.get()).async_scheduler_hooks = Some(hooks) };
+    }
+
+    /// Removes the hooks previously set by [`Lua::set_async_scheduler_hooks`].
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn remove_async_scheduler_hooks(&self) {
+        unsafe { (*self.extra.get()).async_scheduler_hooks = None };
+    }
+
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) fn async_scheduler_hooks(&self) -> Option<AsyncSchedulerHooks> {
+        unsafe { (*self.extra.get()).async_scheduler_hooks.clone() }
     }
 
     /// Wraps a Lua function into a new thread (or coroutine).
     ///
     /// Equivalent to `coroutine.create`.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_thread<'lua>(&'lua self, func: Function) -> Result<Thread<'lua>> {
         self.create_thread_inner(&func)
     }
 
+    /// Creates a new thread (or coroutine), letting `init` set up its initial stack directly.
+    ///
+    /// `init` is called with the raw state of the new thread and must push the thread main
+    /// function, optionally followed by arguments. The arguments are passed to the function when
+    /// the thread is resumed for the first time, before the arguments given to the resume call.
+    ///
+    /// Returns an error if the bottom of the thread stack is not a function after `init` returns.
+    ///
+    /// # Safety
+    ///
+    /// `init` must only push values onto the thread stack, and must not run or resume the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ffi, Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let add: Function = lua.load("function(a, b) return a + b end").eval()?;
+    /// let thread = unsafe {
+    ///     lua.create_thread_with(|state| {
+    ///         lua.push_value_to(state, &mlua::Value::Function(add))?;
+    ///         ffi::lua_pushinteger(state, 40);
+    ///         Ok(())
+    ///     })?
+    /// };
+    /// assert_eq!(thread.resume::<_, i64>(2)?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub unsafe fn create_thread_with<'lua, F>(&'lua self, init: F) -> Result<Thread<'lua>>
+    where
+        F: FnOnce(*mut ffi::lua_State) -> Result<()>,
+    {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 3)?;
+
+        let thread_state = if self.unlikely_memory_error() {
+            ffi::lua_newthread(state)
+        } else {
+            protect_lua!(state, 0, 1, |state| ffi::lua_newthread(state))?
+        };
+        let thread = Thread::new(self.pop_ref());
+
+        init(thread_state)?;
+        if ffi::lua_type(thread_state, 1) != ffi::LUA_TFUNCTION {
+            ffi::lua_settop(thread_state, 0);
+            return Err(Error::runtime(
+                "thread stack must start with the thread main function",
+            ));
+        }
+        Ok(thread)
+    }
+
     /// Wraps a Lua function into a new thread (or coroutine).
     ///
     /// Takes function by reference.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn create_thread_inner<'lua>(&'lua self, func: &Function) -> Result<Thread<'lua>> {
         let state = self.state();
         unsafe {
@@ -1660,7 +3997,7 @@ impl Lua {
             } else {
                 protect_lua!(state, 0, 1, |state| ffi::lua_newthread(state))?
             };
-            self.push_ref(&func.0);
+            self.try_push_ref(&func.0)?;
             ffi::lua_xmove(state, thread_state, 1);
 
             Ok(Thread::new(self.pop_ref()))
@@ -1669,6 +4006,7 @@ impl Lua {
 
     /// Wraps a Lua function into a new or recycled thread (coroutine).
     #[cfg(feature = "async")]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn create_recycled_thread<'lua>(
         &'lua self,
         func: &Function,
@@ -1691,6 +4029,7 @@ impl Lua {
                     ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
                 }
 
+                self.track_ref(index);
                 return Ok(Thread::new(LuaRef::new(self, index)));
             }
         };
@@ -1722,10 +4061,42 @@ impl Lua {
         false
     }
 
+    /// Creates a [`TypedLightUserData`] from a raw pointer.
+    ///
+    /// The pointer and its type are recorded in a host-side registry, so that the value
+    /// can be safely converted back to `TypedLightUserData<T>` when received from Lua.
+    /// Registering the same pointer again replaces its type.
+    pub fn create_light_userdata_typed<T: 'static>(&self, ptr: *mut T) -> TypedLightUserData<T> {
+        let extra = unsafe { &mut *self.extra.get() };
+        extra.light_userdata_types.insert(
+            ptr as *const c_void,
+            (TypeId::of::<T>(), std::any::type_name::<T>()),
+        );
+        TypedLightUserData::new(ptr)
+    }
+
+    /// Removes the pointer from the typed light userdata registry.
+    ///
+    /// Returns `true` if the pointer was registered.
+    pub fn remove_light_userdata_typed<T>(&self, ptr: *mut T) -> bool {
+        let extra = unsafe { &mut *self.extra.get() };
+        extra
+            .light_userdata_types
+            .remove(&(ptr as *const c_void))
+            .is_some()
+    }
+
+    // Returns the type id and name of a registered typed light userdata pointer
+    pub(crate) fn light_userdata_type(&self, ptr: *const c_void) -> Option<(TypeId, &'static str)> {
+        let extra = unsafe { &*self.extra.get() };
+        extra.light_userdata_types.get(&ptr).copied()
+    }
+
     /// Creates a Lua userdata object from a custom userdata type.
     ///
     /// All userdata instances of the same type `T` shares the same metatable.
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
     where
         T: UserData + MaybeSend + 'static,
@@ -1739,6 +4110,7 @@ impl Lua {
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData>
     where
         T: UserData + Serialize + MaybeSend + 'static,
@@ -1746,6 +4118,102 @@ impl Lua {
         unsafe { self.make_userdata(UserDataCell::new_ser(data)) }
     }
 
+    /// Registers a userdata type with custom conversion through the serde bridge.
+    ///
+    /// See [`UserDataSerialize`] for details. Registering the same type again replaces the previous
+    /// registration.
+    ///
+    /// Requires `feature = "serialize"`
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn register_userdata_serialize<T: UserDataSerialize>(&self) {
+        let extra = unsafe { &mut *self.extra.get() };
+        let to_value: UserDataToValue = crate::serde::userdata_to_value::<T>;
+        let from_value: UserDataFromValue = Arc::new(crate::serde::userdata_from_value::<T>);
+        extra
+            .userdata_serializers
+            .insert(TypeId::of::<T>(), (T::type_name(), to_value));
+        extra
+            .userdata_deserializers
+            .insert(T::type_name().to_owned(), from_value);
+    }
+
+    // Returns the type name and converter of a registered `UserDataSerialize` type
+    #[cfg(feature = "serialize")]
+    pub(crate) fn userdata_serializer(
+        &self,
+        type_id: TypeId,
+    ) -> Option<(&'static str, UserDataToValue)> {
+        let extra = unsafe { &*self.extra.get() };
+        extra.userdata_serializers.get(&type_id).copied()
+    }
+
+    // Returns the constructor of userdata registered for the type name
+    #[cfg(feature = "serialize")]
+    pub(crate) fn userdata_deserializer(&self, name: &str) -> Option<UserDataFromValue> {
+        let extra = unsafe { &*self.extra.get() };
+        extra.userdata_deserializers.get(name).cloned()
+    }
+
+    /// Registers a factory to reconstruct userdata of the type `name` from a serialized value.
+    ///
+    /// The factory is called for `{"$userdata": name, "$value": value}` maps, as produced by the
+    /// [`tag_userdata`] option, when converting values to Lua with the [`resolve_userdata`]
+    /// option. It receives the `$value` converted to Lua.
+    /// Unlike [`register_userdata_serialize`], it can be used for userdata created using
+    /// [`create_ser_userdata`], which is tagged by the name of its type.
+    /// Registering a factory for the same type name replaces the previous one.
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mlua::{Lua, LuaSerdeExt, Result, SerializeOptions, UserData, UserDataRef, Value};
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Vector {
+    ///     x: f64,
+    ///     y: f64,
+    /// }
+    ///
+    /// impl UserData for Vector {}
+    ///
+    /// fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     lua.register_userdata_factory("Vector", |lua, value| {
+    ///         let t = value.as_table().ok_or_else(|| mlua::Error::runtime("expected table"))?;
+    ///         lua.create_ser_userdata(Vector { x: t.get("x")?, y: t.get("y")? })
+    ///     });
+    ///
+    ///     // Save the userdata as `{"$userdata": "Vector", "$value": {x = 1, y = 2}}` and restore it
+    ///     let vector = Value::UserData(lua.create_ser_userdata(Vector { x: 1.0, y: 2.0 })?);
+    ///     let saved = vector.to_serializable().tag_userdata(true);
+    ///     let options = SerializeOptions::new().resolve_userdata(true);
+    ///     let vector: UserDataRef<Vector> = lua.unpack(lua.to_value_with(&saved, options)?)?;
+    ///     assert_eq!((vector.x, vector.y), (1.0, 2.0));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// [`tag_userdata`]: crate::serde::de::Options::tag_userdata
+    /// [`resolve_userdata`]: crate::serde::ser::Options::resolve_userdata
+    /// [`register_userdata_serialize`]: #method.register_userdata_serialize
+    /// [`create_ser_userdata`]: #method.create_ser_userdata
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn register_userdata_factory<F>(&self, name: impl Into<std::string::String>, factory: F)
+    where
+        F: for<'lua> Fn(&'lua Lua, Value<'lua>) -> Result<AnyUserData<'lua>> + MaybeSend + 'static,
+    {
+        let extra = unsafe { &mut *self.extra.get() };
+        extra
+            .userdata_deserializers
+            .insert(name.into(), Arc::new(factory));
+    }
+
     /// Creates a Lua userdata object from a custom Rust type.
     ///
     /// You can register the type using [`Lua::register_userdata_type()`] to add fields or methods
@@ -1754,6 +4222,7 @@ impl Lua {
     ///
     /// All userdata instances of the same type `T` shares the same metatable.
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_any_userdata<T>(&self, data: T) -> Result<AnyUserData>
     where
         T: MaybeSend + 'static,
@@ -1769,6 +4238,7 @@ impl Lua {
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_ser_any_userdata<T>(&self, data: T) -> Result<AnyUserData>
     where
         T: Serialize + MaybeSend + 'static,
@@ -1776,27 +4246,203 @@ impl Lua {
         unsafe { self.make_any_userdata(UserDataCell::new_ser(data)) }
     }
 
-    /// Registers a custom Rust type in Lua to use in userdata objects.
+    /// Registers a custom Rust type in Lua to use in userdata objects.
+    ///
+    /// This methods provides a way to add fields or methods to userdata objects of a type `T`.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn register_userdata_type<T: 'static>(
+        &self,
+        f: impl FnOnce(&mut UserDataRegistry<T>),
+    ) -> Result<()> {
+        let mut registry = UserDataRegistry::new();
+        f(&mut registry);
+
+        unsafe {
+            // Deregister the type if it already registered
+            let type_id = TypeId::of::<T>();
+            if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
+                ffi::luaL_unref(self.state(), ffi::LUA_REGISTRYINDEX, table_id);
+            }
+            #[cfg(not(feature = "send"))]
+            {
+                (*self.extra.get()).imported_userdata.remove(&type_id);
+                (*self.extra.get()).exportable_userdata.remove(&type_id);
+            }
+
+            // Register the type
+            self.register_userdata_metatable(registry)?;
+            Ok(())
+        }
+    }
+
+    /// Returns metadata of the userdata types registered in this Lua instance, sorted by name.
+    ///
+    /// A type is registered when its first userdata is created, or by
+    /// [`Lua::register_userdata_type`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataFields, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// struct Vec2(f64, f64);
+    ///
+    /// impl UserData for Vec2 {
+    ///     fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+    ///         fields.add_field_method_get("x", |_, v| Ok(v.0));
+    ///     }
+    ///
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("scale", |_, v, k: f64| Ok(Vec2(v.0 * k, v.1 * k)));
+    ///     }
+    /// }
+    ///
+    /// let lua = Lua::new();
+    /// lua.create_userdata(Vec2(1.0, 2.0))?;
+    ///
+    /// let types = lua.registered_types();
+    /// assert_eq!(types[0].name, "Vec2");
+    /// assert_eq!(types[0].fields, ["x"]);
+    /// assert_eq!(types[0].methods[0].name.as_deref(), Some("scale"));
+    /// assert_eq!(types[0].methods[0].nargs, Some(1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn registered_types(&self) -> Vec<RegisteredType> {
+        let registered_types = unsafe { &(*self.extra.get()).registered_types };
+        let mut types = registered_types.values().cloned().collect::<Vec<_>>();
+        types.sort_by(|a, b| a.name.cmp(&b.name));
+        types
+    }
+
+    /// Returns metadata of the live Rust functions created in this Lua instance, sorted by name
+    /// (anonymous functions first).
+    ///
+    /// Every function created by [`Lua::create_function`] and its variants is included, until it's
+    /// garbage collected. Functions created by [`Lua::create_named_function`] (which is also used
+    /// for [`LuaModule`] members and functions exported using [`lua_export`]) have a name.
+    ///
+    /// [`lua_export`]: crate::lua_export
+    pub fn registered_functions(&self) -> Result<Vec<RegisteredFunction>> {
+        let live = unsafe { self.live_registered_functions()? };
+        let registered_functions = unsafe { &(*self.extra.get()).registered_functions };
+        let mut functions = (registered_functions.iter())
+            .filter(|(ptr, _)| live.contains(ptr))
+            .map(|(_, function)| function.clone())
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(functions)
+    }
+
+    // Returns `true` if the type was registered using `Lua::register_userdata_type`
+    // or `Lua::import_registrations`
+    pub(crate) fn is_userdata_type_registered(&self, type_id: TypeId) -> bool {
+        let extra = unsafe { &*self.extra.get() };
+        #[cfg(not(feature = "send"))]
+        if extra.imported_userdata.contains_key(&type_id) {
+            return true;
+        }
+        extra.registered_userdata.contains_key(&type_id)
+    }
+
+    // Adds a prebuilt registry to build the type metatable from, unless it's already registered
+    #[cfg(not(feature = "send"))]
+    pub(crate) fn import_userdata(&self, type_id: TypeId, prepared: PreparedRef) {
+        if !self.is_userdata_type_registered(type_id) {
+            let extra = unsafe { &mut *self.extra.get() };
+            extra.imported_userdata.insert(type_id, prepared);
+        }
+    }
+
+    // Returns prebuilt registries of imported types, building them for the used `UserData` types
+    #[cfg(not(feature = "send"))]
+    pub(crate) fn exported_userdata(&self) -> Vec<(TypeId, PreparedRef)> {
+        let extra = unsafe { &*self.extra.get() };
+        let imported = (extra.imported_userdata.iter())
+            .map(|(&type_id, prepared)| (type_id, prepared.clone()));
+        let used = (extra.exportable_userdata.iter())
+            .filter(|(type_id, _)| !extra.imported_userdata.contains_key(type_id))
+            .map(|(&type_id, prepare)| (type_id, prepare()));
+        imported.chain(used).collect()
+    }
+
+    // Builds metatable of an imported type, if any
+    #[cfg(not(feature = "send"))]
+    unsafe fn register_imported_userdata(&self, type_id: TypeId) -> Result<Option<Integer>> {
+        match (*self.extra.get()).imported_userdata.get(&type_id).cloned() {
+            Some(prepared) => prepared.register(self).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Enables tracking of userdata instances of type `T` for [`Lua::iter_userdata`].
     ///
-    /// This methods provides a way to add fields or methods to userdata objects of a type `T`.
-    pub fn register_userdata_type<T: 'static>(
-        &self,
-        f: impl FnOnce(&mut UserDataRegistry<T>),
-    ) -> Result<()> {
-        let mut registry = UserDataRegistry::new();
-        f(&mut registry);
+    /// Instances created after this call are kept in an internal weak table, so tracking does
+    /// not prevent them from being garbage collected. Tracking the same type again has no effect.
+    pub fn track_userdata<T: 'static>(&self) -> Result<()> {
+        unsafe { self.userdata_instances::<T>(true) }.map(|_| ())
+    }
 
-        unsafe {
-            // Deregister the type if it already registered
-            let type_id = TypeId::of::<T>();
-            if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
-                ffi::luaL_unref(self.state(), ffi::LUA_REGISTRYINDEX, table_id);
+    /// Calls `f` for every live userdata instance of type `T`.
+    ///
+    /// Only instances created after enabling tracking of the type using [`Lua::track_userdata`]
+    /// are visited, an error is returned if the type is not tracked. Instances that were taken,
+    /// destructed or are mutably borrowed (eg. by a running method) are skipped.
+    ///
+    /// It's allowed to create new instances of `T` from `f`, they will not be visited
+    /// during the current call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.track_userdata::<i32>()?;
+    /// let _ud1 = lua.create_any_userdata(1)?;
+    /// let _ud2 = lua.create_any_userdata(2)?;
+    ///
+    /// let mut sum = 0;
+    /// lua.iter_userdata::<i32, _>(|_, val| {
+    ///     sum += val;
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(sum, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn iter_userdata<'lua, T, F>(&'lua self, mut f: F) -> Result<()>
+    where
+        T: 'static,
+        F: FnMut(&AnyUserData<'lua>, &T) -> Result<()>,
+    {
+        let instances = match unsafe { self.userdata_instances::<T>(false)? } {
+            Some(instances) => instances,
+            None => {
+                let name = std::any::type_name::<T>();
+                let msg =
+                    format!("userdata type '{name}' is not tracked (see Lua::track_userdata)");
+                return Err(Error::runtime(msg));
             }
-
-            // Register the type
-            self.register_userdata_metatable(registry)?;
-            Ok(())
+        };
+        // Collect instances first as the table can be modified during traversal
+        let instances = instances
+            .pairs::<AnyUserData, Value>()
+            .map(|pair| pair.map(|(ud, _)| ud))
+            .collect::<Result<Vec<_>>>()?;
+        for ud in instances {
+            let data = match ud.borrow::<T>() {
+                Ok(data) => data,
+                Err(
+                    Error::UserDataDestructed
+                    | Error::UserDataTypeMismatch
+                    | Error::UserDataBorrowError,
+                ) => continue,
+                Err(err) => return Err(err),
+            };
+            f(&ud, &data)?;
         }
+        Ok(())
     }
 
     /// Create a Lua userdata "proxy" object from a custom userdata type.
@@ -1832,6 +4478,7 @@ impl Lua {
     /// # }
     /// ```
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_proxy<T>(&self) -> Result<AnyUserData>
     where
         T: UserData + 'static,
@@ -1840,8 +4487,13 @@ impl Lua {
     }
 
     /// Sets the metatable for a Luau builtin vector type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the metatable belongs to a different main Lua state.
     #[cfg(any(all(feature = "luau", feature = "unstable"), doc))]
     #[cfg_attr(docsrs, doc(cfg(all(feature = "luau", feature = "unstable"))))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_vector_metatable(&self, metatable: Option<Table>) {
         unsafe {
             let state = self.state();
@@ -1853,14 +4505,68 @@ impl Lua {
             #[cfg(feature = "luau-vector4")]
             ffi::lua_pushvector(state, 0., 0., 0., 0.);
             match metatable {
-                Some(metatable) => self.push_ref(&metatable.0),
+                Some(metatable) => {
+                    if let Err(err) = self.try_push_ref(&metatable.0) {
+                        panic!("{err}");
+                    }
+                }
                 None => ffi::lua_pushnil(state),
             };
             ffi::lua_setmetatable(state, -2);
         }
     }
 
+    /// Registers a [`LuaModule`] as a global table with the given name and returns the table.
+    ///
+    /// Functions of the module are named `name.function` in error messages.
+    pub fn register_module<M: LuaModule>(&self, name: &str, module: M) -> Result<Table> {
+        let table = module.into_table(self, name)?;
+        self.globals().set(name, &table)?;
+        Ok(table)
+    }
+
+    /// Registers the variants of a [`LuaEnum`] as a global read-only table with the given name and
+    /// returns the table.
+    ///
+    /// The table maps the variant names to their integer values and the values back to the names.
+    /// Scripts cannot modify it.
+    pub fn register_enum<E: LuaEnum>(&self, name: &str) -> Result<Table> {
+        let table = E::create_table(self, name)?;
+        self.globals().set(name, &table)?;
+        Ok(table)
+    }
+
+    /// Installs all functions exported using the [`lua_export`] attribute as globals.
+    ///
+    /// Dot-separated export names are installed into nested tables, which are created if missing.
+    ///
+    /// [`lua_export`]: crate::lua_export
+    #[cfg(feature = "export")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "export")))]
+    pub fn register_exports(&self) -> Result<()> {
+        for export in crate::export::exports() {
+            let (path, name) = match export.name().rsplit_once('.') {
+                Some((path, name)) => (Some(path), name),
+                None => (None, export.name()),
+            };
+            let mut table = self.globals();
+            for key in path.into_iter().flat_map(|path| path.split('.')) {
+                table = match table.raw_get::<_, Option<Table>>(key)? {
+                    Some(table) => table,
+                    None => {
+                        let new_table = self.create_table()?;
+                        table.raw_set(key, &new_table)?;
+                        new_table
+                    }
+                };
+            }
+            table.raw_set(name, export.create_function(self)?)?;
+        }
+        Ok(())
+    }
+
     /// Returns a handle to the global environment.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn globals(&self) -> Table {
         let state = self.state();
         unsafe {
@@ -1874,8 +4580,389 @@ impl Lua {
         }
     }
 
+    /// Replaces the global environment with `globals`, returning the previous one.
+    ///
+    /// Chunks loaded (and threads created) after the call run in the new environment, which
+    /// allows switching tenants or resetting the environment of a long-lived (e.g. pooled) state
+    /// without recreating it. Functions loaded before keep the environment they were created with.
+    ///
+    /// In Lua 5.1, LuaJIT and Luau every thread has its own globals, so threads that already exist
+    /// (including a coroutine calling this function) keep using the previous environment.
+    ///
+    /// Note that the standard libraries are not copied: the new environment contains only the
+    /// fields of `globals`.
+    ///
+    /// Returns an error if the Lua instance is sandboxed (Luau).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("tenant", "alice")?;
+    ///
+    /// let env = lua.create_table_from([("tenant", "bob")])?;
+    /// let previous = lua.swap_globals(env)?;
+    /// assert_eq!(lua.load("return tenant").eval::<String>()?, "bob");
+    ///
+    /// lua.swap_globals(previous)?;
+    /// assert_eq!(lua.load("return tenant").eval::<String>()?, "alice");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn swap_globals<'lua>(&'lua self, globals: Table<'lua>) -> Result<Table<'lua>> {
+        #[cfg(feature = "luau")]
+        if unsafe { (*self.extra.get()).sandboxed } {
+            return Err(Error::runtime(
+                "cannot swap globals of a sandboxed Lua instance",
+            ));
+        }
+
+        let previous = self.globals();
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 2)?;
+            self.try_push_ref(&globals.0)?;
+
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            ffi::lua_rawseti(state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
+
+            // Update the main thread (inherited by new threads), the current one and the ref thread
+            // (used by Luau to restore the globals when disabling sandbox)
+            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+            for thread_state in [self.main_state, self.ref_thread(), state] {
+                check_stack(thread_state, 1)?;
+                ffi::lua_xpush(state, thread_state, -1);
+                ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
+            }
+        }
+        Ok(previous)
+    }
+
+    /// Resolves a global value at the dot-separated `path` (e.g. `"config.limits.rate"`) once and
+    /// returns a [`GlobalHandle`] for fast typed access to it.
+    ///
+    /// All tables on the path except the last segment must exist at the moment of the call.
+    /// Use [`GlobalHandle::resolve`] to re-resolve the path after the script environment was
+    /// reloaded.
+    pub fn global_handle<T>(&self, path: &str) -> Result<GlobalHandle<T>> {
+        GlobalHandle::new(self, path)
+    }
+
+    /// Installs enforcement rules into the globals table.
+    ///
+    /// Can be used to prevent scripts from replacing standard library functions
+    /// (e.g. `string.format`) or creating accidental globals.
+    /// See [`GlobalsProtection`] for the available rules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{GlobalsProtection, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let protection = GlobalsProtection::new()
+    ///     .freeze_stdlib(true)
+    ///     .error_on_new_globals(true)
+    ///     .allow_global("config");
+    /// lua.protect_globals(protection)?;
+    ///
+    /// lua.load("config = {}").exec()?;
+    /// assert!(lua.load("counter = 1").exec().is_err());
+    /// assert!(lua.load("string.format = nil").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn protect_globals(&self, protection: GlobalsProtection) -> Result<()> {
+        crate::globals::protect_globals(self, protection)
+    }
+
+    /// Reports calls of sensitive globals and functions without blocking them.
+    ///
+    /// Every function listed in `audit` is replaced by a wrapper which invokes `callback`
+    /// with the function name and the calling script location before forwarding the call.
+    /// This can be used to assess what third-party scripts actually use before tightening
+    /// a sandbox. See [`SandboxAudit`] for how functions are selected.
+    ///
+    /// Must be called before [`Lua::protect_globals`] or `Lua::sandbox` (Luau), as read-only
+    /// tables cannot be modified. The wrappers are visible to scripts comparing functions by
+    /// identity or inspecting the call stack (e.g. using `debug.getinfo` in Luau).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Lua, Result, SandboxAudit};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let calls = Arc::new(Mutex::new(Vec::new()));
+    /// let calls2 = calls.clone();
+    /// lua.audit_sandbox(SandboxAudit::sensitive(), move |_, event| {
+    ///     calls2.lock().unwrap().push(format!("{} at line {:?}", event.name, event.line));
+    /// })?;
+    ///
+    /// lua.load("local t = os.time()").exec()?;
+    /// assert_eq!(*calls.lock().unwrap(), ["os.time at line Some(1)"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`SandboxAudit`]: crate::SandboxAudit
+    pub fn audit_sandbox<F>(&self, audit: SandboxAudit, callback: F) -> Result<()>
+    where
+        F: Fn(&Lua, AuditEvent) + MaybeSend + 'static,
+    {
+        crate::audit::audit_sandbox(self, audit, callback)
+    }
+
+    /// Creates a `process` module table which allows scripts to run host-allowed programs.
+    ///
+    /// The module provides a single function `run(program, args?, opts?)` which runs the program
+    /// to completion and returns a table with `status` (exit code or `nil`), `success`,
+    /// `timed_out`, `stdout`, `stderr` and `truncated` fields. The only supported option is
+    /// `stdin` (a string to pass as input).
+    ///
+    /// Programs, arguments, running time and captured output size are restricted by `policy`.
+    /// Unlike `os.execute`, programs are started directly without a shell.
+    ///
+    /// Requires `feature = "process"`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use mlua::{Lua, ProcessPolicy, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let policy = ProcessPolicy::new()
+    ///     .allow_program("/usr/bin/git")
+    ///     .timeout(Some(Duration::from_secs(10)));
+    /// lua.globals().set("process", lua.create_process_module(policy)?)?;
+    ///
+    /// lua.load(r#"
+    ///     local res = process.run("/usr/bin/git", {"--version"})
+    ///     print(res.stdout)
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// Creates an `fs` module table which gives scripts access to files beneath a root directory.
+    ///
+    /// The module provides the following functions (paths are relative to the root):
+    /// - `open(path, mode?)` opens a file in `"r"` (default), `"w"` or `"a"` mode and returns
+    ///   a handle with `read(n?)`, `write(data)` and `close()` methods
+    /// - `read(path)` and `write(path, data)` read or replace the whole file
+    /// - `list(path?)` returns sorted names of the directory entries
+    /// - `stat(path)` returns a table with `type`, `size` and `modified` fields (or `nil`)
+    /// - `mkdir(path)` and `remove(path)` create a directory or remove a file (empty directory)
+    ///
+    /// Paths cannot escape the root, neither using `..` nor symbolic links. Access can be further
+    /// restricted by `policy`. Errors are raised as Lua errors.
+    ///
+    /// The checks are performed before accessing a path, so the root directory must not be
+    /// concurrently modified by untrusted parties.
+    ///
+    /// Requires `feature = "fs"`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mlua::{FsPolicy, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let policy = FsPolicy::new("/srv/scripts/data").write_quota(Some(1024 * 1024));
+    /// lua.globals().set("fs", lua.create_fs_module(policy)?)?;
+    ///
+    /// lua.load(r#"
+    ///     fs.write("hello.txt", "hello")
+    ///     assert(fs.read("hello.txt") == "hello")
+    ///     assert(not pcall(fs.read, "../../etc/passwd"))
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "fs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fs")))]
+    pub fn create_fs_module(&self, policy: crate::FsPolicy) -> Result<Table> {
+        crate::fs::create_fs_module(self, policy)
+    }
+
+    #[cfg(feature = "process")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "process")))]
+    pub fn create_process_module(&self, policy: crate::ProcessPolicy) -> Result<Table> {
+        crate::process::create_process_module(self, policy)
+    }
+
+    /// Creates a `datetime` module table with date and time support.
+    ///
+    /// Values are [`DateTime`] userdata with a fixed UTC offset (named time zones are not
+    /// supported), supporting comparison, `__tostring` (RFC 3339) and arithmetic with seconds
+    /// (`dt + 60`, `dt2 - dt1`).
+    ///
+    /// The module provides the following functions:
+    /// - `now()` returns the current time in UTC
+    /// - `parse(s, format?)` parses RFC 3339 string or uses the [format description]
+    /// - `from_timestamp(seconds)` converts a Unix timestamp (UTC)
+    /// - `new{year, month, day, hour?, minute?, second?, nanosecond?, offset?}`
+    ///
+    /// Offsets are specified as `"Z"`, `"UTC"`, `"+HH:MM"` or a number of seconds.
+    /// Values have `year`, `month`, `day`, `hour`, `minute`, `second`, `nanosecond`, `weekday`,
+    /// `yearday` and `offset` fields, and `format(format?)`, `timestamp()`, `to_offset(offset)`,
+    /// `to_utc()` and `add(seconds)` methods.
+    ///
+    /// Requires `feature = "time"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("datetime", lua.create_datetime_module()?)?;
+    ///
+    /// lua.load(r#"
+    ///     local dt = datetime.parse("2024-02-28T22:30:00+01:00")
+    ///     local next_day = (dt + 86400):to_utc()
+    ///     assert(next_day:format("[year]-[month]-[day] [hour]:[minute]") == "2024-02-29 21:30")
+    ///     assert(next_day - dt == 86400)
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`DateTime`]: crate::DateTime
+    /// [format description]: https://time-rs.github.io/book/api/format-description.html
+    #[cfg(feature = "time")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "time")))]
+    pub fn create_datetime_module(&self) -> Result<Table> {
+        crate::datetime::create_datetime_module(self)
+    }
+
+    /// Creates an `re` module table with regular expressions backed by the [regex] crate.
+    ///
+    /// Unlike Lua patterns, matching runs in linear time with respect to the input size, which
+    /// makes it suitable for untrusted patterns and input.
+    ///
+    /// The module provides `compile(pattern, opts?)` and `escape(s)` functions. Options are
+    /// `case_insensitive`, `multi_line`, `dot_matches_new_line`, `ignore_whitespace` and `unicode`.
+    ///
+    /// Compiled expressions operate on bytes of Lua strings (positions are 1-based) and have
+    /// the following methods:
+    /// - `is_match(s, init?)` checks whether the string contains a match
+    /// - `find(s, init?)` returns start and end positions of the first match, like `string.find`
+    /// - `match(s, init?)` returns the first matched substring
+    /// - `captures(s, init?)` returns a table with the whole match at index `0`, groups at
+    ///   indices `1..n` and named groups by name
+    /// - `gmatch(s)` and `gcaptures(s)` return iterators over successive matches
+    /// - `replace(s, rep, limit?)` replaces matches, `rep` can refer to groups as `$1` or `$name`
+    /// - `split(s, limit?)` returns a table of substrings separated by matches
+    ///
+    /// Requires `feature = "regex"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("re", lua.create_regex_module()?)?;
+    ///
+    /// lua.load(r#"
+    ///     local date = re.compile([[(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})]])
+    ///     local caps = date:captures("released on 2024-05-17")
+    ///     assert(caps[0] == "2024-05-17" and caps.y == "2024" and caps[3] == "17")
+    ///     assert(date:replace("2024-05-17", "$d.$m.$y") == "17.05.2024")
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [regex]: https://docs.rs/regex
+    #[cfg(feature = "regex")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "regex")))]
+    pub fn create_regex_module(&self) -> Result<Table> {
+        crate::regex::create_regex_module(self)
+    }
+
+    /// Creates a `json` module table for encoding and decoding JSON using [serde_json].
+    ///
+    /// The module provides the following functions and fields:
+    /// - `encode(value, opts?)` returns a JSON string, options are `pretty` and `sort_keys`
+    /// - `decode(s, opts?)` returns a Lua value, options are `null_as_nil` (decode `null` as `nil`
+    ///   instead of `json.null`) and `preserve_integers` (decode integers as Lua integers, enabled
+    ///   by default)
+    /// - `null` is a value to represent JSON `null` (see [`LuaSerdeExt::null`])
+    /// - `array_mt` is a metatable to encode tables as arrays (see [`LuaSerdeExt::array_metatable`])
+    ///
+    /// Decoded arrays have the `array_mt` metatable attached, so they are encoded back as arrays
+    /// even when empty.
+    ///
+    /// Requires `feature = "json"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("json", lua.create_json_module()?)?;
+    ///
+    /// lua.load(r#"
+    ///     local v = json.decode('{"name": "mlua", "tags": []}')
+    ///     assert(v.name == "mlua")
+    ///     assert(json.encode(v, {sort_keys = true}) == '{"name":"mlua","tags":[]}')
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [serde_json]: https://docs.rs/serde_json
+    /// [`LuaSerdeExt::null`]: crate::LuaSerdeExt::null
+    /// [`LuaSerdeExt::array_metatable`]: crate::LuaSerdeExt::array_metatable
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn create_json_module(&self) -> Result<Table> {
+        crate::serde::json::create_json_module(self)
+    }
+
+    /// Creates a `msgpack` module table for encoding and decoding [MessagePack] binary data.
+    ///
+    /// The module provides `encode(value)` and `decode(s)` functions operating on Lua strings,
+    /// and the `null` field to represent `nil` values (see [`LuaSerdeExt::null`]).
+    ///
+    /// Requires `feature = "msgpack"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.globals().set("msgpack", lua.create_msgpack_module()?)?;
+    ///
+    /// lua.load(r#"
+    ///     local data = msgpack.encode({id = 42, payload = "\xff\x00"})
+    ///     local v = msgpack.decode(data)
+    ///     assert(v.id == 42 and v.payload == "\xff\x00")
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [MessagePack]: https://msgpack.org
+    /// [`LuaSerdeExt::null`]: crate::LuaSerdeExt::null
+    #[cfg(feature = "msgpack")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+    pub fn create_msgpack_module(&self) -> Result<Table> {
+        crate::serde::msgpack::create_msgpack_module(self)
+    }
+
     /// Returns a handle to the active `Thread`. For calls to `Lua` this will be the main Lua thread,
     /// for parameters given to a callback, this will be whatever Lua thread called the callback.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn current_thread(&self) -> Thread {
         let state = self.state();
         unsafe {
@@ -1916,11 +5003,117 @@ impl Lua {
         f(&Scope::new(self))
     }
 
+    /// Calls the given function with a `Lua` handle whose created values are released in bulk
+    /// when the function returns.
+    ///
+    /// Every `Table`, `Function`, `String` (or any other value handle) created through the
+    /// passed `Lua` reference, or through handles derived from it, is registered in an
+    /// auto-release pool. On completion, the references still held by these handles are
+    /// released, even if the handles were forgotten (e.g. with [`std::mem::forget`]). This bounds
+    /// the growth of the auxiliary reference stack in long-running loops that process requests.
+    ///
+    /// The handles cannot escape the function, as they are bound to the lifetime of the passed
+    /// `Lua` reference. Values created through another `Lua` reference (e.g. captured by the
+    /// function) are not registered in the pool. To keep a value longer, store it in the
+    /// registry using [`Lua::create_registry_value`].
+    ///
+    /// Scopes can be nested, each one releasing only values created through its own handle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// for i in 0..10 {
+    ///     lua.autorelease_scope(|lua| {
+    ///         let request = lua.create_table()?;
+    ///         request.set("id", i)?;
+    ///         // The handle is released at the end of the scope anyway
+    ///         std::mem::forget(request);
+    ///         Ok(())
+    ///     })?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn autorelease_scope<R, F>(&self, f: F) -> Result<R>
+    where
+        F: for<'lua> FnOnce(&'lua Lua) -> Result<R>,
+    {
+        struct AutoreleaseGuard<'a>(&'a Lua);
+
+        impl<'a> Drop for AutoreleaseGuard<'a> {
+            fn drop(&mut self) {
+                unsafe { self.0.release_autorelease_pool() }
+            }
+        }
+
+        // Use a distinct `Lua` handle to recognize values created within the scope
+        let inner = self.0.clone();
+        let lua: &Lua = unsafe { mem::transmute(&inner) };
+        let pool = AutoreleasePool {
+            owner: lua,
+            refs: Vec::new(),
+        };
+        unsafe { (*self.extra.get()).autorelease_pools.push(pool) };
+        let _guard = AutoreleaseGuard(lua);
+        f(lua)
+    }
+
+    // Releases references of the innermost auto-release pool that are still held
+    unsafe fn release_autorelease_pool(&self) {
+        let extra = self.extra.get();
+        let pool = mlua_expect!((*extra).autorelease_pools.pop(), "no autorelease pool");
+
+        // Find the last owner of every used slot
+        let mut owners = FxHashMap::default();
+        for (index, owner) in pool.refs {
+            owners.insert(index, owner);
+        }
+        let extra = &mut *extra;
+        #[cfg_attr(not(feature = "async"), allow(unused_mut))]
+        let mut unused = (extra.ref_free.iter())
+            .chain(&extra.wrapped_failure_pool)
+            .copied()
+            .collect::<FxHashSet<_>>();
+        #[cfg(feature = "async")]
+        unused.extend(&extra.thread_pool);
+        for (index, owner) in owners {
+            if ptr::eq(owner, pool.owner) {
+                if !unused.contains(&index) {
+                    self.drop_ref_index(index);
+                }
+            } else if let Some(parent) = extra.autorelease_pools.last_mut() {
+                // Pass the slots owned by other handles to the outer pool
+                parent.refs.push((index, owner));
+            }
+        }
+    }
+
+    // Registers a reference created by this `Lua` handle in the active auto-release pool
+    #[inline]
+    unsafe fn track_ref(&self, index: c_int) {
+        if let Some(pool) = (*self.extra.get()).autorelease_pools.last_mut() {
+            pool.refs.push((index, self));
+        }
+    }
+
+    // Excludes the reference from auto-release pools (it's going to outlive them)
+    #[cfg(feature = "unstable")]
+    pub(crate) fn untrack_ref(&self, index: c_int) {
+        let extra = unsafe { &mut *self.extra.get() };
+        for pool in &mut extra.autorelease_pools {
+            pool.refs.retain(|&(i, _)| i != index);
+        }
+    }
+
     /// Attempts to coerce a Lua value into a String in a manner consistent with Lua's internal
     /// behavior.
     ///
     /// To succeed, the value must be a string (in which case this is a no-op), an integer, or a
     /// number.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn coerce_string<'lua>(&'lua self, v: Value<'lua>) -> Result<Option<String<'lua>>> {
         Ok(match v {
             Value::String(s) => Some(s),
@@ -1952,6 +5145,7 @@ impl Lua {
     /// To succeed, the value must be an integer, a floating point number that has an exact
     /// representation as an integer, or a string that can be converted to an integer. Refer to the
     /// Lua manual for details.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn coerce_integer(&self, v: Value) -> Result<Option<Integer>> {
         Ok(match v {
             Value::Integer(i) => Some(i),
@@ -1977,6 +5171,7 @@ impl Lua {
     ///
     /// To succeed, the value must be a number or a string that can be converted to a number. Refer
     /// to the Lua manual for details.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn coerce_number(&self, v: Value) -> Result<Option<Number>> {
         Ok(match v {
             Value::Number(n) => Some(n),
@@ -1999,12 +5194,22 @@ impl Lua {
 
     /// Converts a value that implements `IntoLua` into a `Value` instance.
     pub fn pack<'lua, T: IntoLua<'lua>>(&'lua self, t: T) -> Result<Value<'lua>> {
-        t.into_lua(self)
+        trace::<T, _>(
+            self,
+            ConversionDirection::IntoLua,
+            || t.into_lua(self),
+            |_| 1,
+        )
     }
 
     /// Converts a `Value` instance into a value that implements `FromLua`.
     pub fn unpack<'lua, T: FromLua<'lua>>(&'lua self, value: Value<'lua>) -> Result<T> {
-        T::from_lua(value, self)
+        trace::<T, _>(
+            self,
+            ConversionDirection::FromLua,
+            || T::from_lua(value, self),
+            |_| 1,
+        )
     }
 
     /// Converts a value that implements `IntoLuaMulti` into a `MultiValue` instance.
@@ -2024,10 +5229,12 @@ impl Lua {
     ///
     /// This value will be available to rust from all `Lua` instances which share the same main
     /// state.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_named_registry_value<'lua, T>(&'lua self, name: &str, t: T) -> Result<()>
     where
         T: IntoLua<'lua>,
     {
+        self.check_registry_writable()?;
         let state = self.state();
         unsafe {
             let _sg = StackGuard::new(state);
@@ -2044,6 +5251,7 @@ impl Lua {
     /// get a value previously set by [`set_named_registry_value`].
     ///
     /// [`set_named_registry_value`]: #method.set_named_registry_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn named_registry_value<'lua, T>(&'lua self, name: &str) -> Result<T>
     where
         T: FromLua<'lua>,
@@ -2080,6 +5288,7 @@ impl Lua {
     /// However, dropped [`RegistryKey`]s automatically reused to store new values.
     ///
     /// [`RegistryKey`]: crate::RegistryKey
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_registry_value<'lua, T: IntoLua<'lua>>(&'lua self, t: T) -> Result<RegistryKey> {
         let state = self.state();
         unsafe {
@@ -2123,6 +5332,7 @@ impl Lua {
     /// previously placed by [`create_registry_value`].
     ///
     /// [`create_registry_value`]: #method.create_registry_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn registry_value<'lua, T: FromLua<'lua>>(&'lua self, key: &RegistryKey) -> Result<T> {
         if !self.owns_registry_value(key) {
             return Err(Error::MismatchedRegistryKey);
@@ -2166,6 +5376,7 @@ impl Lua {
     /// See [`create_registry_value`] for more details.
     ///
     /// [`create_registry_value`]: #method.create_registry_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn replace_registry_value<'lua, T: IntoLua<'lua>>(
         &'lua self,
         key: &RegistryKey,
@@ -2206,6 +5417,20 @@ impl Lua {
         Ok(())
     }
 
+    // Sets whether writes of named registry values are denied, returning the previous setting
+    pub(crate) fn set_registry_readonly(&self, readonly: bool) -> bool {
+        unsafe { mem::replace(&mut (*self.extra.get()).registry_readonly, readonly) }
+    }
+
+    fn check_registry_writable(&self) -> Result<()> {
+        match unsafe { (*self.extra.get()).registry_readonly } {
+            true => Err(Error::runtime(
+                "named registry values are read-only during isolated execution",
+            )),
+            false => Ok(()),
+        }
+    }
+
     /// Returns true if the given `RegistryKey` was created by a `Lua` which shares the underlying
     /// main state with this `Lua` instance.
     ///
@@ -2319,11 +5544,27 @@ impl Lua {
         extra.app_data.remove()
     }
 
+    // Returns types of the stored application data
+    pub(crate) fn app_data_types(&self) -> Vec<TypeId> {
+        let extra = unsafe { &*self.extra.get() };
+        extra.app_data.type_ids()
+    }
+
+    // Removes application data of types not listed in `keep`
+    pub(crate) fn retain_app_data(&self, keep: &[TypeId]) -> Result<()> {
+        let extra = unsafe { &*self.extra.get() };
+        match extra.app_data.retain(keep) {
+            true => Ok(()),
+            false => Err(Error::runtime("app data container is borrowed")),
+        }
+    }
+
     /// Pushes a value that implements `IntoLua` onto the Lua stack.
     ///
     /// Uses 2 stack spaces, does not call checkstack.
     #[doc(hidden)]
     #[inline(always)]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub unsafe fn push<'lua>(&'lua self, value: impl IntoLua<'lua>) -> Result<()> {
         value.push_into_stack(self)
     }
@@ -2332,6 +5573,7 @@ impl Lua {
     ///
     /// Uses 2 stack spaces, does not call checkstack.
     #[doc(hidden)]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub unsafe fn push_value(&self, value: Value) -> Result<()> {
         if let Value::Error(err) = value {
             let protect = !self.unlikely_memory_error();
@@ -2358,11 +5600,11 @@ impl Lua {
                 #[cfg(feature = "luau-vector4")]
                 ffi::lua_pushvector(state, v.x(), v.y(), v.z(), v.w());
             }
-            Value::String(s) => self.push_ref(&s.0),
-            Value::Table(t) => self.push_ref(&t.0),
-            Value::Function(f) => self.push_ref(&f.0),
-            Value::Thread(t) => self.push_ref(&t.0),
-            Value::UserData(ud) => self.push_ref(&ud.0),
+            Value::String(s) => self.try_push_ref(&s.0)?,
+            Value::Table(t) => self.try_push_ref(&t.0)?,
+            Value::Function(f) => self.try_push_ref(&f.0)?,
+            Value::Thread(t) => self.try_push_ref(&t.0)?,
+            Value::UserData(ud) => self.try_push_ref(&ud.0)?,
             Value::Error(err) => {
                 let protect = !self.unlikely_memory_error();
                 push_gc_userdata(state, WrappedFailure::Error(err.clone()), protect)?;
@@ -2481,6 +5723,65 @@ impl Lua {
         }
     }
 
+    /// Pushes a `Value` onto the stack of the given Lua thread.
+    ///
+    /// Unlike [`Lua::push_value`], this function can target any thread (coroutine) of the same
+    /// main Lua state, which is useful when interoperating with C APIs that expect values on
+    /// a specific stack. Returns an error if `state` belongs to a different main Lua state.
+    ///
+    /// # Safety
+    /// `state` must be a valid pointer to a Lua thread that is not running concurrently.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub unsafe fn push_value_to(&self, state: *mut ffi::lua_State, value: &Value) -> Result<()> {
+        check_stack(state, 3)?;
+        self.check_same_main_state(state)?;
+
+        let lua_state = self.state();
+        if state == lua_state {
+            return self.push_value_ref(value);
+        }
+
+        let _sg = StackGuard::new(lua_state);
+        check_stack(lua_state, 3)?;
+        self.push_value_ref(value)?;
+        ffi::lua_xmove(lua_state, state, 1);
+        Ok(())
+    }
+
+    /// Reads a `Value` at the given index from the stack of the given Lua thread.
+    ///
+    /// The value is not popped from the stack. Returns an error if `state` belongs to a different
+    /// main Lua state or `idx` is not a valid stack index.
+    ///
+    /// # Safety
+    /// `state` must be a valid pointer to a Lua thread that is not running concurrently.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub unsafe fn read_value_from(&self, state: *mut ffi::lua_State, idx: c_int) -> Result<Value> {
+        check_stack(state, 2)?;
+        self.check_same_main_state(state)?;
+        if ffi::lua_type(state, idx) == ffi::LUA_TNONE {
+            return Err(Error::RuntimeError(format!("invalid stack index {idx}")));
+        }
+        let idx = ffi::lua_absindex(state, idx);
+
+        let lua_state = self.state();
+        let _sg = StackGuard::new(lua_state);
+        check_stack(lua_state, 3)?;
+        ffi::lua_xpush(state, lua_state, idx);
+        Ok(self.pop_value())
+    }
+
+    // Checks that `state` shares the main state (and registry) with this instance.
+    // Uses 1 stack space, does not call checkstack.
+    unsafe fn check_same_main_state(&self, state: *mut ffi::lua_State) -> Result<()> {
+        if extra_data(state) != self.extra.get() {
+            return Err(Error::RuntimeError(
+                "Lua thread belongs to a different main Lua state".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Returns value at given stack index without popping it.
     ///
     /// Uses 2 stack spaces, does not call checkstack.
@@ -2588,14 +5889,28 @@ impl Lua {
     }
 
     // Pushes a LuaRef value onto the stack, uses 1 stack space, does not call checkstack
+    // The reference must belong to this main state (eg. a handle pushed by its own method),
+    // references passed by the user are pushed by `try_push_ref`.
     pub(crate) unsafe fn push_ref(&self, lref: &LuaRef) {
-        assert!(
+        mlua_debug_assert!(
             Arc::ptr_eq(&lref.lua.0, &self.0),
-            "Lua instance passed Value created from a different main Lua state"
+            "reference of a different main Lua state"
         );
         ffi::lua_xpush(self.ref_thread(), self.state(), lref.index);
     }
 
+    // Same as `push_ref` but returns an error if the reference belongs to a different main state
+    pub(crate) unsafe fn try_push_ref(&self, lref: &LuaRef) -> Result<()> {
+        if !Arc::ptr_eq(&lref.lua.0, &self.0) {
+            return Err(Error::MismatchedLuaState {
+                expected: self.id(),
+                found: lref.lua.id(),
+            });
+        }
+        ffi::lua_xpush(self.ref_thread(), self.state(), lref.index);
+        Ok(())
+    }
+
     #[cfg(all(feature = "unstable", not(feature = "send")))]
     pub(crate) unsafe fn push_owned_ref(&self, loref: &crate::types::LuaOwnedRef) {
         assert!(
@@ -2617,12 +5932,14 @@ impl Lua {
     pub(crate) unsafe fn pop_ref(&self) -> LuaRef {
         ffi::lua_xmove(self.state(), self.ref_thread(), 1);
         let index = ref_stack_pop(self.extra.get());
+        self.track_ref(index);
         LuaRef::new(self, index)
     }
 
     // Same as `pop_ref` but assumes the value is already on the reference thread
     pub(crate) unsafe fn pop_ref_thread(&self) -> LuaRef {
         let index = ref_stack_pop(self.extra.get());
+        self.track_ref(index);
         LuaRef::new(self, index)
     }
 
@@ -2630,6 +5947,7 @@ impl Lua {
         unsafe {
             ffi::lua_pushvalue(self.ref_thread(), lref.index);
             let index = ref_stack_pop(self.extra.get());
+            self.track_ref(index);
             LuaRef::new(self, index)
         }
     }
@@ -2667,7 +5985,8 @@ impl Lua {
         ffi::lua_pushcfunction(state, error_traceback);
     }
 
-    unsafe fn register_userdata_metatable<'lua, T: 'static>(
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub(crate) unsafe fn register_userdata_metatable<'lua, T: 'static>(
         &'lua self,
         mut registry: UserDataRegistry<'lua, T>,
     ) -> Result<Integer> {
@@ -2675,13 +5994,21 @@ impl Lua {
         let _sg = StackGuard::new(state);
         check_stack(state, 13)?;
 
+        let mut registered_type = registered_type(&registry);
+
+        // Name callbacks after the type and the name they are registered with
+        let create_callback = |name: &str, func: Callback<'lua, 'static>| {
+            let name = CallbackName::new(&get_function_name::<T>(name), None)?;
+            self.create_callback_with_name(func, Some(name))
+        };
+
         // Prepare metatable, add meta methods first and then meta fields
         let metatable_nrec = registry.meta_methods.len() + registry.meta_fields.len();
         #[cfg(feature = "async")]
         let metatable_nrec = metatable_nrec + registry.async_meta_methods.len();
         push_table(state, 0, metatable_nrec, true)?;
         for (k, m) in registry.meta_methods {
-            self.push(self.create_callback(m)?)?;
+            self.push(create_callback(&k, m)?)?;
             rawset_field(state, -2, MetaMethod::validate(&k)?)?;
         }
         #[cfg(feature = "async")]
@@ -2693,6 +6020,9 @@ impl Lua {
         for (k, f) in registry.meta_fields {
             has_name = has_name || k == MetaMethod::Type;
             mlua_assert!(f(self, 0)? == 1, "field function must return one value");
+            if k == MetaMethod::Type && ffi::lua_type(state, -1) == ffi::LUA_TSTRING {
+                registered_type.name = util::to_string(state, -1);
+            }
             rawset_field(state, -2, MetaMethod::validate(&k)?)?;
         }
         // Set `__name/__type` if not provided
@@ -2737,7 +6067,7 @@ impl Lua {
         if field_getters_nrec > 0 {
             push_table(state, 0, field_getters_nrec, true)?;
             for (k, m) in registry.field_getters {
-                self.push(self.create_callback(m)?)?;
+                self.push(create_callback(&k, m)?)?;
                 rawset_field(state, -2, &k)?;
             }
             field_getters_index = Some(ffi::lua_absindex(state, -1));
@@ -2749,7 +6079,7 @@ impl Lua {
         if field_setters_nrec > 0 {
             push_table(state, 0, field_setters_nrec, true)?;
             for (k, m) in registry.field_setters {
-                self.push(self.create_callback(m)?)?;
+                self.push(create_callback(&k, m)?)?;
                 rawset_field(state, -2, &k)?;
             }
             field_setters_index = Some(ffi::lua_absindex(state, -1));
@@ -2772,7 +6102,7 @@ impl Lua {
                 }
             }
             for (k, m) in registry.methods {
-                self.push(self.create_callback(m)?)?;
+                self.push(create_callback(&k, m)?)?;
                 rawset_field(state, -2, &k)?;
             }
             #[cfg(feature = "async")]
@@ -2824,6 +6154,9 @@ impl Lua {
         (*self.extra.get())
             .registered_userdata_mt
             .insert(mt_ptr, Some(type_id));
+        (*self.extra.get())
+            .registered_types
+            .insert(type_id, registered_type);
 
         Ok(id as Integer)
     }
@@ -2904,9 +6237,19 @@ impl Lua {
     //
     // So we instead use a caller provided lifetime, which without the 'static requirement would be
     // unsafe.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn create_callback<'lua>(
         &'lua self,
         func: Callback<'lua, 'static>,
+    ) -> Result<Function<'lua>> {
+        self.create_callback_with_name(func, None)
+    }
+
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn create_callback_with_name<'lua>(
+        &'lua self,
+        func: Callback<'lua, 'static>,
+        name: Option<CallbackName>,
     ) -> Result<Function<'lua>> {
         unsafe extern "C-unwind" fn call_callback(state: *mut ffi::lua_State) -> c_int {
             // Normal functions can be scoped and therefore destroyed,
@@ -2928,25 +6271,57 @@ impl Lua {
                 let _guard = StateGuard::new(&lua.0, state);
                 let func = &*(*upvalue).data;
 
-                func(lua, nargs)
+                if let Some(quota) = thread_quota(extra, state) {
+                    quota.add_call(lua)?;
+                }
+                if MemoryState::pressure_pending(state) {
+                    lua.check_memory_pressure()?;
+                }
+                match (*extra).callback_middleware.clone() {
+                    Some(middleware) => {
+                        call_with_middleware(lua, state, nargs, &middleware, |nargs| {
+                            func(lua, nargs)
+                        })
+                    }
+                    None => func(lua, nargs),
+                }
             })
         }
 
         let state = self.state();
         unsafe {
             let _sg = StackGuard::new(state);
-            check_stack(state, 4)?;
+            check_stack(state, 5)?;
 
             let func = mem::transmute(func);
             let extra = Arc::clone(&self.extra);
             let protect = !self.unlikely_memory_error();
             push_gc_userdata(state, CallbackUpvalue { data: func, extra }, protect)?;
+            let nups = match name {
+                Some(name) => {
+                    push_gc_userdata(state, name, protect)?;
+                    2
+                }
+                None => 1,
+            };
+            // Luau keeps a pointer to the name, owned by the upvalue
+            #[cfg(feature = "luau")]
+            let debugname = match nups {
+                2 => (*(ffi::lua_touserdata(state, -1) as *const CallbackName))
+                    .name
+                    .as_ptr(),
+                _ => ptr::null(),
+            };
+            let push_closure = |state| {
+                #[cfg(not(feature = "luau"))]
+                ffi::lua_pushcclosure(state, call_callback, nups);
+                #[cfg(feature = "luau")]
+                ffi::lua_pushcclosured(state, call_callback, debugname, nups);
+            };
             if protect {
-                protect_lua!(state, 1, 1, fn(state) {
-                    ffi::lua_pushcclosure(state, call_callback, 1);
-                })?;
+                protect_lua!(state, nups, 1, push_closure)?;
             } else {
-                ffi::lua_pushcclosure(state, call_callback, 1);
+                push_closure(state);
             }
 
             Ok(Function(self.pop_ref()))
@@ -2954,6 +6329,7 @@ impl Lua {
     }
 
     #[cfg(feature = "async")]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn create_async_callback<'lua>(
         &'lua self,
         func: AsyncCallback<'lua, 'static>,
@@ -3123,6 +6499,7 @@ impl Lua {
         LightUserData(&ASYNC_POLL_PENDING as *const u8 as *mut c_void)
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) unsafe fn make_userdata<T>(&self, data: UserDataCell<T>) -> Result<AnyUserData>
     where
         T: UserData + 'static,
@@ -3133,16 +6510,26 @@ impl Lua {
             if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
                 return Ok(table_id as Integer);
             }
+            #[cfg(not(feature = "send"))]
+            if let Some(table_id) = self.register_imported_userdata(type_id)? {
+                return Ok(table_id);
+            }
 
             // Create new metatable from UserData definition
             let mut registry = UserDataRegistry::new();
             T::add_fields(&mut registry);
             T::add_methods(&mut registry);
 
-            self.register_userdata_metatable(registry)
+            let table_id = self.register_userdata_metatable(registry)?;
+            #[cfg(not(feature = "send"))]
+            (*self.extra.get())
+                .exportable_userdata
+                .insert(type_id, prepare_userdata::<T>);
+            Ok(table_id)
         })
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) unsafe fn make_any_userdata<T>(&self, data: UserDataCell<T>) -> Result<AnyUserData>
     where
         T: 'static,
@@ -3153,6 +6540,10 @@ impl Lua {
             if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
                 return Ok(table_id as Integer);
             }
+            #[cfg(not(feature = "send"))]
+            if let Some(table_id) = self.register_imported_userdata(type_id)? {
+                return Ok(table_id);
+            }
 
             // Create empty metatable
             let registry = UserDataRegistry::new();
@@ -3160,11 +6551,18 @@ impl Lua {
         })
     }
 
-    unsafe fn make_userdata_with_metatable<T>(
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn make_userdata_with_metatable<T: 'static>(
         &self,
-        data: UserDataCell<T>,
+        mut data: UserDataCell<T>,
         get_metatable_id: impl FnOnce() -> Result<Integer>,
     ) -> Result<AnyUserData> {
+        let counter = (*self.extra.get())
+            .userdata_counters
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(UserDataCounter::new::<T>()));
+        data.set_tracker(UserDataTracker::new(counter.clone()));
+
         let state = self.state();
         let _sg = StackGuard::new(state);
         check_stack(state, 3)?;
@@ -3192,12 +6590,180 @@ impl Lua {
             ffi::lua_setuservalue(state, -2);
         }
 
-        Ok(AnyUserData(self.pop_ref(), SubtypeId::None))
+        let ud = AnyUserData(self.pop_ref(), SubtypeId::None);
+        if let Some(instances) = self.userdata_instances::<T>(false)? {
+            instances.raw_set(ud.clone(), true)?;
+        }
+        Ok(ud)
+    }
+
+    // Records metadata of a Rust function for `Lua::registered_functions`
+    fn register_function<'lua>(
+        &'lua self,
+        func: Function<'lua>,
+        name: Option<std::string::String>,
+        nargs: Option<usize>,
+        is_async: bool,
+    ) -> Result<Function<'lua>> {
+        let extra = self.extra.get();
+        let functions = unsafe { self.registered_functions_table()? };
+        functions.raw_set(func.clone(), true)?;
+        let function = RegisteredFunction {
+            name,
+            nargs,
+            is_async,
+        };
+        let len = unsafe {
+            (*extra)
+                .registered_functions
+                .insert(func.to_pointer(), function);
+            (*extra).registered_functions.len()
+        };
+
+        // Forget collected functions (whose pointers can be reused) once the number of entries
+        // doubles, keeping the amortized cost constant
+        if len > unsafe { (*extra).registered_functions_limit } {
+            let live = unsafe { self.live_registered_functions()? };
+            unsafe {
+                (*extra)
+                    .registered_functions
+                    .retain(|ptr, _| live.contains(ptr));
+                let limit = (*extra).registered_functions.len() * 2;
+                (*extra).registered_functions_limit = limit.max(REGISTERED_FUNCTIONS_MIN_LIMIT);
+            }
+        }
+        Ok(func)
+    }
+
+    // Returns the weak table of live registered functions, creating it on first use
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn registered_functions_table(&self) -> Result<Table> {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 2)?;
+
+        if let Some(id) = (*self.extra.get()).registered_functions_ref {
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as _);
+            return Ok(Table(self.pop_ref()));
+        }
+
+        let functions = self.create_weak_table(WeakMode::Keys)?;
+        self.push_ref(&functions.0);
+        let id = protect_lua!(state, 1, 0, |state| {
+            ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+        })?;
+        (*self.extra.get()).registered_functions_ref = Some(id);
+        Ok(functions)
+    }
+
+    // Returns pointers of the registered functions which are not garbage collected yet
+    unsafe fn live_registered_functions(&self) -> Result<FxHashSet<*const c_void>> {
+        let functions = self.registered_functions_table()?;
+        let mut live = FxHashSet::default();
+        for pair in functions.pairs::<Function, bool>() {
+            live.insert(pair?.0.to_pointer());
+        }
+        Ok(live)
+    }
+
+    // Returns the weak table of live userdata instances of type `T` if the type is tracked
+    // (enabling tracking if requested)
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn userdata_instances<T: 'static>(&self, create: bool) -> Result<Option<Table>> {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 2)?;
+
+        let type_id = TypeId::of::<T>();
+        if let Some(&id) = (*self.extra.get()).userdata_instances.get(&type_id) {
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as _);
+            return Ok(Some(Table(self.pop_ref())));
+        }
+        if !create {
+            return Ok(None);
+        }
+
+        let instances = self.create_weak_table(WeakMode::Keys)?;
+        self.push_ref(&instances.0);
+        let id = protect_lua!(state, 1, 0, |state| {
+            ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+        })?;
+        (*self.extra.get()).userdata_instances.insert(type_id, id);
+        Ok(Some(instances))
+    }
+
+    // Returns coroutine-local data container of the given thread.
+    // If `create` is false and the container does not exist, returns null.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub(crate) unsafe fn thread_locals(
+        &self,
+        thread: &LuaRef,
+        create: bool,
+    ) -> Result<*mut AppData> {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 6)?;
+
+        match (*self.extra.get()).thread_locals {
+            Some(id) => {
+                ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as _);
+            }
+            None if !create => return Ok(ptr::null_mut()),
+            None => {
+                init_gc_metatable::<AppData>(state, None)?;
+                let locals = self.create_weak_table(WeakMode::Keys)?;
+                self.push_ref(&locals.0);
+                ffi::lua_pushvalue(state, -1);
+                let id = protect_lua!(state, 1, 0, |state| {
+                    ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+                })?;
+                (*self.extra.get()).thread_locals = Some(id);
+            }
+        }
+
+        self.push_ref(thread);
+        if ffi::lua_rawget(state, -2) == ffi::LUA_TUSERDATA {
+            return Ok(get_userdata::<AppData>(state, -1));
+        }
+        if !create {
+            return Ok(ptr::null_mut());
+        }
+
+        ffi::lua_pop(state, 1);
+        self.push_ref(thread);
+        push_gc_userdata(state, AppData::default(), true)?;
+        let locals = get_userdata::<AppData>(state, -1);
+        protect_lua!(state, 3, 0, fn(state) ffi::lua_rawset(state, -3))?;
+        Ok(locals)
+    }
+
+    // Removes coroutine-local data container of the given thread
+    #[cfg(any(feature = "lua54", feature = "luau"))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub(crate) unsafe fn clear_thread_locals(&self, thread: &LuaRef) -> Result<()> {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 3)?;
+
+        let id = match (*self.extra.get()).thread_locals {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as _);
+        self.push_ref(thread);
+        ffi::lua_pushnil(state);
+        ffi::lua_rawset(state, -3);
+        Ok(())
     }
 
     // Luau version located in `luau/mod.rs`
     #[cfg(not(feature = "luau"))]
     fn disable_c_modules(&self) -> Result<()> {
+        // Searchers and `package.loadlib` are already removed if filesystem access is disabled
+        if !self.fs_access() {
+            return Ok(());
+        }
+
         let package: Table = self.globals().get("package")?;
 
         package.set(
@@ -3223,6 +6789,13 @@ impl Lua {
         Ok(())
     }
 
+    // Returns `false` if scripts are not allowed to access the filesystem (the `filesystem`
+    // feature is disabled or `LuaOptions::fs_access` is unset)
+    #[inline]
+    pub(crate) fn fs_access(&self) -> bool {
+        cfg!(feature = "filesystem") && unsafe { (*self.extra.get()).options.fs_access }
+    }
+
     pub(crate) unsafe fn try_from_ptr(state: *mut ffi::lua_State) -> Option<Self> {
         let extra = extra_data(state);
         if extra.is_null() {
@@ -3243,11 +6816,17 @@ impl Lua {
         }
     }
 
-    #[cfg(feature = "unstable")]
     #[inline]
     pub(crate) fn clone(&self) -> Arc<LuaInner> {
         Arc::clone(&self.0)
     }
+
+    // Releases the handle without collecting garbage, for states that are already closed
+    #[inline]
+    pub(crate) fn into_inner(self) -> Arc<LuaInner> {
+        let lua = ManuallyDrop::new(self);
+        unsafe { ptr::read(&lua.0) }
+    }
 }
 
 impl LuaInner {
@@ -3283,6 +6862,17 @@ impl LuaInner {
                 .push(unsafe { mem::transmute(multivalue) });
         }
     }
+
+    // Cancels the futures of async scope functions, returns the number of cancelled futures
+    #[cfg(feature = "async")]
+    pub(crate) fn cancel_pending_async_futures(&self) -> usize {
+        // Take the list first, as dropping a future may call back into `Lua`
+        let pending = unsafe { (*self.extra.get()).pending_async.take() };
+        for future in &pending {
+            future.cancel();
+        }
+        pending.len()
+    }
 }
 
 impl ExtraData {
@@ -3304,56 +6894,256 @@ impl<'a> Drop for StateGuard<'a> {
     fn drop(&mut self) {
         self.0.state.store(self.1, Ordering::Relaxed);
     }
-}
+}
+
+// Panic function used when a handler is set by `Lua::on_exit_request`
+#[cfg(not(feature = "luau"))]
+unsafe extern "C-unwind" fn exit_panic_proc(state: *mut ffi::lua_State) -> c_int {
+    let extra = extra_data(state);
+    if let Some(callback) = extra.as_ref().and_then(|extra| extra.exit_callback.clone()) {
+        if callback(1) == ExitAction::Error {
+            resume_unwind(Box::new(Error::ExitRequested { code: 1 }));
+        }
+    }
+    // Lua aborts the process after returning
+    0
+}
+
+// Returns `KeepAlive` of the state (used in userdata finalizers), or null if the state is foreign
+#[cfg(not(feature = "luau"))]
+pub(crate) unsafe fn state_keep_alive(state: *mut ffi::lua_State) -> *mut KeepAlive {
+    let extra = extra_data(state);
+    if extra.is_null() {
+        return ptr::null_mut();
+    }
+    &mut (*extra).keep_alive
+}
+
+unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
+    #[cfg(feature = "luau")]
+    if cfg!(not(feature = "module")) {
+        // In the main app we can use `lua_callbacks` to access ExtraData
+        return (*ffi::lua_callbacks(state)).userdata as *mut _;
+    }
+
+    let extra_key = &EXTRA_REGISTRY_KEY as *const u8 as *const c_void;
+    if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, extra_key) != ffi::LUA_TUSERDATA {
+        // `ExtraData` can be null only when Lua state is foreign.
+        // This case in used in `Lua::try_from_ptr()`.
+        ffi::lua_pop(state, 1);
+        return ptr::null_mut();
+    }
+    let extra_ptr = ffi::lua_touserdata(state, -1) as *mut Arc<UnsafeCell<ExtraData>>;
+    ffi::lua_pop(state, 1);
+    (*extra_ptr).get()
+}
+
+unsafe fn set_extra_data(
+    state: *mut ffi::lua_State,
+    extra: &Arc<UnsafeCell<ExtraData>>,
+) -> Result<()> {
+    #[cfg(feature = "luau")]
+    if cfg!(not(feature = "module")) {
+        (*ffi::lua_callbacks(state)).userdata = extra.get() as *mut _;
+        return Ok(());
+    }
+
+    push_gc_userdata(state, Arc::clone(extra), true)?;
+    protect_lua!(state, 1, 0, fn(state) {
+        let extra_key = &EXTRA_REGISTRY_KEY as *const u8 as *const c_void;
+        ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, extra_key);
+    })
+}
+
+// Creates required entries in the metatable cache (see `util::METATABLE_CACHE`)
+pub(crate) fn init_metatable_cache(cache: &mut FxHashMap<TypeId, u8>) {
+    cache.insert(TypeId::of::<Arc<UnsafeCell<ExtraData>>>(), 0);
+    cache.insert(TypeId::of::<Callback>(), 0);
+    cache.insert(TypeId::of::<CallbackUpvalue>(), 0);
+    cache.insert(TypeId::of::<CallbackName>(), 0);
+    cache.insert(TypeId::of::<CloseNotifier>(), 0);
+    cache.insert(TypeId::of::<AppData>(), 0);
+
+    #[cfg(feature = "async")]
+    {
+        cache.insert(TypeId::of::<AsyncCallback>(), 0);
+        cache.insert(TypeId::of::<AsyncCallbackUpvalue>(), 0);
+        cache.insert(TypeId::of::<AsyncPollUpvalue>(), 0);
+        cache.insert(TypeId::of::<Option<Waker>>(), 0);
+    }
+}
+
+unsafe fn used_memory(state: *mut ffi::lua_State) -> usize {
+    match MemoryState::get(state) {
+        mem_state if !mem_state.is_null() => (*mem_state).used_memory(),
+        _ => {
+            // Get data from the Lua GC
+            let used_kbytes = ffi::lua_gc(state, ffi::LUA_GCCOUNT, 0);
+            let used_kbytes_rem = ffi::lua_gc(state, ffi::LUA_GCCOUNTB, 0);
+            (used_kbytes as usize) * 1024 + (used_kbytes_rem as usize)
+        }
+    }
+}
+
+unsafe fn freed_memory(state: *mut ffi::lua_State) -> Option<usize> {
+    let mem_state = MemoryState::get(state);
+    (!mem_state.is_null()).then(|| (*mem_state).freed_memory())
+}
+
+pub(crate) struct GcNotifier {
+    callback: GcCallback,
+    active: AtomicBool,
+    // Set when the sentinel was collected and a new one must be pushed at the next interrupt
+    #[cfg(feature = "luau")]
+    rearm: AtomicBool,
+}
+
+// Unreachable object that is finalized at the end of every GC cycle
+struct GcSentinel {
+    notifier: Arc<GcNotifier>,
+    started: Instant,
+    used_memory: usize,
+    freed_memory: Option<usize>,
+    // Main state (the thread the sentinel was created in can be collected in the same cycle)
+    #[cfg(feature = "luau")]
+    main_state: *mut ffi::lua_State,
+}
+
+impl GcSentinel {
+    // Reports the end of the tracked GC cycle. Does not call into Lua.
+    unsafe fn report_cycle_end(&self, state: *mut ffi::lua_State) {
+        // Use exact numbers if the state has our allocator, otherwise estimate from memory usage
+        let freed_bytes = match (freed_memory(state), self.freed_memory) {
+            (Some(freed), Some(freed_at_start)) => freed.wrapping_sub(freed_at_start),
+            _ => self.used_memory.saturating_sub(used_memory(state)),
+        };
+        let event = GcEvent::CycleEnd {
+            duration: self.started.elapsed(),
+            freed_bytes,
+        };
+        let _ = catch_unwind(AssertUnwindSafe(|| (self.notifier.callback)(event)));
+    }
+}
+
+// Luau userdata destructors run when the sentinel is collected
+#[cfg(feature = "luau")]
+impl Drop for GcSentinel {
+    fn drop(&mut self) {
+        if self.notifier.active.load(Ordering::Relaxed) {
+            unsafe { self.report_cycle_end(self.main_state) };
+            self.notifier.rearm.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// Pushes a new sentinel onto the stack, starting tracking of a GC cycle.
+// Uses 3 stack spaces, does not call checkstack.
+#[cfg(not(feature = "luau"))]
+#[allow(clippy::arc_with_non_send_sync)]
+unsafe fn push_gc_sentinel(state: *mut ffi::lua_State, notifier: Arc<GcNotifier>) -> Result<()> {
+    unsafe extern "C-unwind" fn sentinel_gc(state: *mut ffi::lua_State) -> c_int {
+        let sentinel = util::take_userdata::<GcSentinel>(state);
+        if !sentinel.notifier.active.load(Ordering::Relaxed) {
+            return 0;
+        }
+        sentinel.report_cycle_end(state);
+        let notifier = sentinel.notifier;
+
+        // The new sentinel is unreachable and will be finalized in the next cycle
+        if ffi::lua_checkstack(state, 3) != 0 && push_gc_sentinel(state, notifier.clone()).is_ok() {
+            ffi::lua_pop(state, 1);
+            let _ = catch_unwind(AssertUnwindSafe(|| {
+                (notifier.callback)(GcEvent::CycleStart)
+            }));
+        }
+        0
+    }
 
-unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
-    #[cfg(feature = "luau")]
-    if cfg!(not(feature = "module")) {
-        // In the main app we can use `lua_callbacks` to access ExtraData
-        return (*ffi::lua_callbacks(state)).userdata as *mut _;
-    }
+    let sentinel = GcSentinel {
+        notifier,
+        started: Instant::now(),
+        used_memory: used_memory(state),
+        freed_memory: freed_memory(state),
+    };
+    util::push_userdata(state, sentinel, true)?;
 
-    let extra_key = &EXTRA_REGISTRY_KEY as *const u8 as *const c_void;
-    if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, extra_key) != ffi::LUA_TUSERDATA {
-        // `ExtraData` can be null only when Lua state is foreign.
-        // This case in used in `Lua::try_from_ptr()`.
+    let mt_key = &GC_SENTINEL_MT_KEY as *const u8 as *const c_void;
+    if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, mt_key) != ffi::LUA_TTABLE {
         ffi::lua_pop(state, 1);
-        return ptr::null_mut();
+        push_table(state, 0, 2, true)?;
+        ffi::lua_pushcfunction(state, sentinel_gc);
+        rawset_field(state, -2, "__gc")?;
+        ffi::lua_pushboolean(state, 0);
+        rawset_field(state, -2, "__metatable")?;
+        ffi::lua_pushvalue(state, -1);
+        protect_lua!(state, 1, 0, fn(state) {
+            let mt_key = &GC_SENTINEL_MT_KEY as *const u8 as *const c_void;
+            ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, mt_key);
+        })?;
     }
-    let extra_ptr = ffi::lua_touserdata(state, -1) as *mut Arc<UnsafeCell<ExtraData>>;
-    ffi::lua_pop(state, 1);
-    (*extra_ptr).get()
+    ffi::lua_setmetatable(state, -2);
+    Ok(())
 }
 
-unsafe fn set_extra_data(
-    state: *mut ffi::lua_State,
-    extra: &Arc<UnsafeCell<ExtraData>>,
-) -> Result<()> {
-    #[cfg(feature = "luau")]
-    if cfg!(not(feature = "module")) {
-        (*ffi::lua_callbacks(state)).userdata = extra.get() as *mut _;
-        return Ok(());
-    }
-
-    push_gc_userdata(state, Arc::clone(extra), true)?;
-    protect_lua!(state, 1, 0, fn(state) {
-        let extra_key = &EXTRA_REGISTRY_KEY as *const u8 as *const c_void;
-        ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, extra_key);
-    })
+// Pushes a new sentinel onto the stack, starting tracking of a GC cycle.
+// Uses 1 stack space, does not call checkstack.
+#[cfg(feature = "luau")]
+#[allow(clippy::arc_with_non_send_sync)]
+unsafe fn push_gc_sentinel(state: *mut ffi::lua_State, notifier: Arc<GcNotifier>) -> Result<()> {
+    let main_state = ffi::lua_mainthread(state);
+    let sentinel = GcSentinel {
+        notifier,
+        started: Instant::now(),
+        used_memory: used_memory(main_state),
+        freed_memory: freed_memory(main_state),
+        main_state,
+    };
+    util::push_userdata(state, sentinel, true)
 }
 
-// Creates required entries in the metatable cache (see `util::METATABLE_CACHE`)
-pub(crate) fn init_metatable_cache(cache: &mut FxHashMap<TypeId, u8>) {
-    cache.insert(TypeId::of::<Arc<UnsafeCell<ExtraData>>>(), 0);
-    cache.insert(TypeId::of::<Callback>(), 0);
-    cache.insert(TypeId::of::<CallbackUpvalue>(), 0);
+// Tracks the next GC cycle if the previous sentinel was collected
+#[cfg(feature = "luau")]
+unsafe fn rearm_gc_sentinel(state: *mut ffi::lua_State, extra: *mut ExtraData) {
+    let notifier = match (*extra).gc_notifier {
+        Some(ref notifier) if notifier.rearm.swap(false, Ordering::Relaxed) => notifier.clone(),
+        _ => return,
+    };
+    if ffi::lua_checkstack(state, 3) != 0 && push_gc_sentinel(state, notifier.clone()).is_ok() {
+        ffi::lua_pop(state, 1);
+        let _ = catch_unwind(AssertUnwindSafe(|| {
+            (notifier.callback)(GcEvent::CycleStart)
+        }));
+    }
+}
 
-    #[cfg(feature = "async")]
-    {
-        cache.insert(TypeId::of::<AsyncCallback>(), 0);
-        cache.insert(TypeId::of::<AsyncCallbackUpvalue>(), 0);
-        cache.insert(TypeId::of::<AsyncPollUpvalue>(), 0);
-        cache.insert(TypeId::of::<Option<Waker>>(), 0);
+// Interrupt handler shared by `Lua::set_interrupt` and `Lua::on_gc`
+#[cfg(feature = "luau")]
+unsafe extern "C-unwind" fn interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
+    if gc >= 0 {
+        // We don't support GC interrupts since they cannot survive Lua exceptions
+        return;
+    }
+    let extra = extra_data(state);
+    rearm_gc_sentinel(state, extra);
+    if (*extra).interrupt_callback.is_none() {
+        return;
+    }
+    let result = callback_error_ext(state, extra, move |_| {
+        let interrupt_cb = (*extra).interrupt_callback.clone();
+        let interrupt_cb =
+            mlua_expect!(interrupt_cb, "no interrupt callback set in interrupt_proc");
+        if Arc::strong_count(&interrupt_cb) > 2 {
+            return Ok(VmState::Continue); // Don't allow recursion
+        }
+        let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+        let _guard = StateGuard::new(&lua.0, state);
+        interrupt_cb(lua)
+    });
+    match result {
+        VmState::Continue => {}
+        VmState::Yield => {
+            ffi::lua_yield(state, 0);
+        }
     }
 }
 
@@ -3446,21 +7236,34 @@ where
     // to store a wrapped failure (error or panic) *before* we proceed.
     let prealloc_failure = PreallocatedFailure::reserve(state, extra);
 
-    match catch_unwind(AssertUnwindSafe(|| f(nargs))) {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let result = f(nargs);
+        if let (Err(err), Some(hook)) = (&result, (*extra).conversion_error_hook.clone()) {
+            if let Some(mut info) = ConversionErrorInfo::from_error(err) {
+                if info.callback.is_none() {
+                    info.callback = resolve_callback_name(state);
+                }
+                hook(&info);
+            }
+        }
+        result
+    }));
+    match result {
         Ok(Ok(r)) => {
             // Return unused `WrappedFailure` to the pool
             prealloc_failure.release(state, extra);
             r
         }
-        Ok(Err(err)) => {
+        Ok(Err(mut err)) => {
             let wrapped_error = prealloc_failure.r#use(state, extra);
 
-            // Build `CallbackError` with traceback
+            // Build `CallbackError` with traceback, naming the callback by its registered name
+            // (or the name it was called by) in argument errors
             let traceback = if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
-                ffi::luaL_traceback(state, state, ptr::null(), 0);
-                let traceback = util::to_string(state, -1);
-                ffi::lua_pop(state, 1);
-                traceback
+                if let Error::BadArgument { to: to @ None, .. } = &mut err {
+                    *to = util::function_name(state, 0);
+                }
+                util::traceback(state, 0)
             } else {
                 "<not enough stack space for traceback>".to_string()
             };
@@ -3484,6 +7287,536 @@ where
     }
 }
 
+// A hook function with its mask and count
+#[cfg(not(feature = "luau"))]
+type PrevHook = (Option<ffi::lua_Hook>, c_int, c_int);
+
+#[cfg(feature = "luau")]
+type InterruptProc = unsafe extern "C-unwind" fn(*mut ffi::lua_State, c_int);
+
+// Quota attached to a thread using `Thread::set_quota`, kept in its coroutine-local data
+struct ThreadQuota {
+    quota: Quota,
+    // Hook of the thread replaced by the quota hook
+    #[cfg(not(feature = "luau"))]
+    prev_hook: PrevHook,
+}
+
+// Returns coroutine-local data container of the thread `state` (or null), using its stack
+unsafe fn raw_thread_locals(extra: *mut ExtraData, state: *mut ffi::lua_State) -> *mut AppData {
+    let id = match (*extra).thread_locals {
+        Some(id) => id,
+        None => return ptr::null_mut(),
+    };
+    if ffi::lua_checkstack(state, 2) == 0 {
+        return ptr::null_mut();
+    }
+    ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as _);
+    ffi::lua_pushthread(state);
+    let locals = match ffi::lua_rawget(state, -2) {
+        ffi::LUA_TUSERDATA => get_userdata::<AppData>(state, -1),
+        _ => ptr::null_mut(),
+    };
+    ffi::lua_pop(state, 2);
+    locals
+}
+
+// Returns the quota applied to the thread `state`, if any
+unsafe fn thread_quota(extra: *mut ExtraData, state: *mut ffi::lua_State) -> Option<Quota> {
+    if (*extra).thread_quotas {
+        let locals = raw_thread_locals(extra, state);
+        if !locals.is_null() {
+            if let Some(thread_quota) = (*locals).borrow::<ThreadQuota>() {
+                return Some(thread_quota.quota.clone());
+            }
+        }
+    }
+    (*extra).quota.clone()
+}
+
+#[cfg(not(feature = "luau"))]
+fn is_quota_hook(hook: Option<ffi::lua_Hook>) -> bool {
+    hook.map(|hook| hook as usize) == Some(quota_hook_proc as ffi::lua_Hook as usize)
+}
+
+// Installs the quota hook on the thread `state`, returning the hook it replaces
+// (or `prev` if the quota hook is already installed)
+#[cfg(not(feature = "luau"))]
+unsafe fn install_quota_hook(state: *mut ffi::lua_State, prev: Option<PrevHook>) -> PrevHook {
+    let hook = ffi::lua_gethook(state);
+    let (mask, count) = (ffi::lua_gethookmask(state), ffi::lua_gethookcount(state));
+    let prev = match is_quota_hook(hook) {
+        true => prev.unwrap_or((None, 0, 0)),
+        false => (hook, mask, count),
+    };
+    // Reuse the instruction count of the replaced hook to forward it all its events
+    let count = match prev.1 & ffi::LUA_MASKCOUNT {
+        0 => Quota::INSTRUCTIONS_STEP as c_int,
+        _ => prev.2,
+    };
+    ffi::lua_sethook(
+        state,
+        Some(quota_hook_proc),
+        prev.1 | ffi::LUA_MASKCOUNT,
+        count,
+    );
+    prev
+}
+
+// Returns the hook replaced by the quota hook of the thread `state`
+#[cfg(not(feature = "luau"))]
+unsafe fn quota_prev_hook(extra: *mut ExtraData, state: *mut ffi::lua_State) -> PrevHook {
+    if (*extra).thread_quotas {
+        let locals = raw_thread_locals(extra, state);
+        if !locals.is_null() {
+            if let Some(thread_quota) = (*locals).borrow::<ThreadQuota>() {
+                return thread_quota.prev_hook;
+            }
+        }
+    }
+    let prev_hooks = &(*extra).quota_prev_hooks;
+    let prev = prev_hooks.iter().find(|&&(s, _)| s == state);
+    prev.map(|&(_, prev)| prev).unwrap_or((None, 0, 0))
+}
+
+// Forgets the hook replaced by the quota hook of the thread `state`
+#[cfg(not(feature = "luau"))]
+unsafe fn clear_quota_prev_hook(extra: *mut ExtraData, state: *mut ffi::lua_State) {
+    if (*extra).thread_quotas {
+        let locals = raw_thread_locals(extra, state);
+        if !locals.is_null() {
+            if let Some(mut thread_quota) = (*locals).borrow_mut::<ThreadQuota>() {
+                thread_quota.prev_hook = (None, 0, 0);
+                return;
+            }
+        }
+    }
+    for (s, prev) in &mut (*extra).quota_prev_hooks {
+        if *s == state {
+            *prev = (None, 0, 0);
+        }
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+unsafe extern "C-unwind" fn quota_hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    let extra = extra_data(state);
+    let prev = quota_prev_hook(extra, state);
+    let (hook, mask, _) = prev;
+    let quota = thread_quota(extra, state);
+    match quota {
+        Some(ref quota) if (*ar).event == ffi::LUA_HOOKCOUNT => {
+            let quota = quota.clone();
+            let step = ffi::lua_gethookcount(state) as u64;
+            callback_error_ext(state, extra, move |_| {
+                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+                let _guard = StateGuard::new(&lua.0, state);
+                quota.add_instructions(step, lua)
+            });
+        }
+        Some(_) => {}
+        None => {
+            // Quota was removed or destined for a different thread, restore the replaced hook
+            // (LuaJIT hooks are global, so keep it to reach the target thread)
+            #[cfg(not(feature = "luajit"))]
+            ffi::lua_sethook(state, prev.0, prev.1, prev.2);
+        }
+    }
+
+    // Forward the event to the replaced hook
+    let event_mask = match (*ar).event {
+        ffi::LUA_HOOKCALL => ffi::LUA_MASKCALL,
+        ffi::LUA_HOOKRET => ffi::LUA_MASKRET,
+        ffi::LUA_HOOKLINE => ffi::LUA_MASKLINE,
+        ffi::LUA_HOOKCOUNT => ffi::LUA_MASKCOUNT,
+        // Tail calls (returns in Lua 5.1)
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        _ => ffi::LUA_MASKRET,
+        #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+        _ => ffi::LUA_MASKCALL,
+    };
+    if let Some(hook) = hook.filter(|_| mask & event_mask != 0) {
+        hook(state, ar);
+        // The replaced hook may remove itself (e.g. if it was destined for a different thread)
+        if quota.is_some() && ffi::lua_gethook(state).is_none() {
+            clear_quota_prev_hook(extra, state);
+            install_quota_hook(state, None);
+        }
+    }
+}
+
+#[cfg(feature = "luau")]
+fn is_quota_interrupt(interrupt: Option<InterruptProc>) -> bool {
+    interrupt.map(|f| f as usize) == Some(quota_interrupt_proc as InterruptProc as usize)
+}
+
+// Installs the quota interrupt, keeping the replaced one to forward it the calls
+#[cfg(feature = "luau")]
+unsafe fn install_quota_interrupt(main_state: *mut ffi::lua_State) {
+    let callbacks = ffi::lua_callbacks(main_state);
+    if !is_quota_interrupt((*callbacks).interrupt) {
+        (*extra_data(main_state)).quota_prev_interrupt = (*callbacks).interrupt;
+        (*callbacks).interrupt = Some(quota_interrupt_proc);
+    }
+}
+
+#[cfg(feature = "luau")]
+unsafe extern "C-unwind" fn quota_interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
+    let extra = extra_data(state);
+    if gc < 0 {
+        if let Some(quota) = thread_quota(extra, state) {
+            callback_error_ext(state, extra, move |_| {
+                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+                let _guard = StateGuard::new(&lua.0, state);
+                quota.add_instructions(1, lua)
+            })
+        }
+    }
+    if let Some(interrupt) = (*extra).quota_prev_interrupt {
+        interrupt(state, gc);
+    }
+}
+
+// Values created within `Lua::autorelease_scope`
+struct AutoreleasePool {
+    owner: *const Lua,
+    // Slots of the created references and `Lua` handles that created them
+    refs: Vec<(c_int, *const Lua)>,
+}
+
+// Hook installed by `Function::call_with_hook` for the duration of a call
+#[cfg(not(feature = "luau"))]
+pub(crate) struct ScopedHook {
+    callback: HookCallback,
+    prev: (Option<ffi::lua_Hook>, c_int, c_int),
+}
+
+#[cfg(not(feature = "luau"))]
+pub(crate) struct ScopedHookGuard<'a> {
+    lua: &'a Lua,
+    state: *mut ffi::lua_State,
+    outer: Option<ScopedHook>,
+}
+
+#[cfg(not(feature = "luau"))]
+impl<'a> Drop for ScopedHookGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            let extra = self.lua.extra.get();
+            let scope = (*extra).scoped_hook.take();
+            let (hook, mask, count) = mlua_expect!(scope, "scoped hook is missing").prev;
+            ffi::lua_sethook(self.state, hook, mask, count);
+            (*extra).scoped_hook = self.outer.take();
+        }
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+unsafe extern "C-unwind" fn scoped_hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    let extra = extra_data(state);
+    let callback = match (*extra).scoped_hook {
+        Some(ref scope) => scope.callback.clone(),
+        None => {
+            // The call has returned, replace the hook inherited by the coroutine with the one
+            // of the main thread
+            let main_state = (*extra).inner.assume_init_ref().main_state;
+            let hook = ffi::lua_gethook(main_state);
+            let (mask, count) = (
+                ffi::lua_gethookmask(main_state),
+                ffi::lua_gethookcount(main_state),
+            );
+            ffi::lua_sethook(state, hook, mask, count);
+            return;
+        }
+    };
+    callback_error_ext(state, extra, move |_| {
+        if Arc::strong_count(&callback) > 2 {
+            return Ok(()); // Don't allow recursion
+        }
+        let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+        let _guard = StateGuard::new(&lua.0, state);
+        callback(lua, Debug::new(lua, ar))
+    })
+}
+
+// Time slice of a thread executed by `Lua::run_for`
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+struct TimeSlice {
+    thread: *mut ffi::lua_State,
+    deadline: Option<Instant>,
+    expired: bool,
+    #[cfg(not(feature = "luau"))]
+    prev_hook: Option<ffi::lua_Hook>,
+    #[cfg(not(feature = "luau"))]
+    prev_mask: c_int,
+    #[cfg(feature = "luau")]
+    prev_interrupt: Option<unsafe extern "C-unwind" fn(*mut ffi::lua_State, c_int)>,
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+impl TimeSlice {
+    #[cfg(not(feature = "luau"))]
+    const INSTRUCTIONS_STEP: c_int = 1000;
+
+    // Yields the thread `state` if it's the target of the expired time slice
+    unsafe fn check_deadline(extra: *mut ExtraData, state: *mut ffi::lua_State) {
+        match (*extra).time_slice {
+            Some(ref mut slice)
+                if slice.thread == state
+                    && ffi::lua_isyieldable(state) != 0
+                    && slice
+                        .deadline
+                        .is_some_and(|deadline| Instant::now() >= deadline) =>
+            {
+                slice.expired = true;
+                ffi::lua_yield(state, 0);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(any(feature = "lua54", feature = "lua53"))]
+unsafe extern "C-unwind" fn time_slice_hook_proc(
+    state: *mut ffi::lua_State,
+    ar: *mut ffi::lua_Debug,
+) {
+    let extra = extra_data(state);
+    let (prev_hook, prev_mask) = match (*extra).time_slice {
+        Some(ref slice) if slice.thread == state => (slice.prev_hook, slice.prev_mask),
+        _ => {
+            // Hook was inherited by a coroutine created during the time slice, ignore
+            ffi::lua_sethook(state, None, 0, 0);
+            return;
+        }
+    };
+    // Forward the event to the wrapped hook
+    let event_mask = match (*ar).event {
+        ffi::LUA_HOOKTAILCALL => ffi::LUA_MASKCALL,
+        event => 1 << event,
+    };
+    if let Some(prev_hook) = prev_hook.filter(|_| prev_mask & event_mask != 0) {
+        prev_hook(state, ar);
+    }
+    if (*ar).event == ffi::LUA_HOOKCOUNT {
+        TimeSlice::check_deadline(extra, state);
+    }
+}
+
+#[cfg(feature = "luau")]
+unsafe extern "C-unwind" fn time_slice_interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
+    let extra = extra_data(state);
+    if let Some(prev_interrupt) = (*extra).time_slice.as_ref().and_then(|s| s.prev_interrupt) {
+        prev_interrupt(state, gc);
+        if ffi::lua_status(state) == ffi::LUA_YIELD {
+            return;
+        }
+    }
+    if gc < 0 {
+        TimeSlice::check_deadline(extra, state);
+    }
+}
+
+#[cfg(all(feature = "trace_events", not(feature = "luau")))]
+unsafe extern "C-unwind" fn trace_events_hook_proc(
+    state: *mut ffi::lua_State,
+    ar: *mut ffi::lua_Debug,
+) {
+    let extra = extra_data(state);
+    let recorder = match (*extra).trace_events {
+        Some(ref mut recorder) => recorder,
+        None => {
+            // Hook was inherited by a coroutine created during the recording
+            #[cfg(not(feature = "luajit"))]
+            ffi::lua_sethook(state, None, 0, 0);
+            return;
+        }
+    };
+
+    // Forward the event to the wrapped hook (hooks set for the main thread only)
+    let (prev_hook, prev_mask, _) = recorder.prev_hook;
+    let event_mask = match (*ar).event {
+        ffi::LUA_HOOKTAILCALL => ffi::LUA_MASKCALL,
+        event => 1 << event,
+    };
+    let forward_event = prev_mask & event_mask != 0;
+
+    match (*ar).event {
+        ffi::LUA_HOOKCALL => {
+            let (name, source) = match hook_function_info(state, ar) {
+                false => ("?".to_string(), None),
+                true => function_label(ar),
+            };
+            recorder.enter(state, name, source);
+        }
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        ffi::LUA_HOOKTAILCALL => {
+            let (name, source) = match hook_function_info(state, ar) {
+                false => ("?".to_string(), None),
+                true => function_label(ar),
+            };
+            recorder.exit(state);
+            recorder.enter(state, name, source);
+        }
+        ffi::LUA_HOOKRET => recorder.exit(state),
+        // Tail returns in Lua 5.1/LuaJIT
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        ffi::LUA_HOOKTAILCALL => recorder.exit(state),
+        _ => {}
+    }
+
+    if let Some(prev_hook) = prev_hook.filter(|_| forward_event) {
+        if state == (*extra).inner.assume_init_ref().main_state {
+            prev_hook(state, ar);
+        }
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+unsafe extern "C-unwind" fn function_stats_hook_proc(
+    state: *mut ffi::lua_State,
+    ar: *mut ffi::lua_Debug,
+) {
+    let extra = extra_data(state);
+    let recorder = match (*extra).function_stats {
+        Some(ref mut recorder) => recorder,
+        None => {
+            // Hook was inherited by a coroutine created during the collection
+            #[cfg(not(feature = "luajit"))]
+            ffi::lua_sethook(state, None, 0, 0);
+            return;
+        }
+    };
+
+    // Forward the event to the wrapped hook (hooks set for the main thread only)
+    let (prev_hook, prev_mask, _) = recorder.prev_hook;
+    let event_mask = match (*ar).event {
+        ffi::LUA_HOOKTAILCALL => ffi::LUA_MASKCALL,
+        event => 1 << event,
+    };
+    let forward_event = prev_mask & event_mask != 0;
+
+    recorder.tick(state);
+    match (*ar).event {
+        ffi::LUA_HOOKCALL if ffi::lua_getinfo(state, cstr!("Sf"), ar) != 0 => {
+            let function = recorder.function(state, ar);
+            recorder.enter(state, function, ffi::lua_topointer(state, -1));
+            ffi::lua_pop(state, 1);
+        }
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        ffi::LUA_HOOKTAILCALL => {
+            recorder.pop(state);
+            if ffi::lua_getinfo(state, cstr!("Sf"), ar) != 0 {
+                let function = recorder.function(state, ar);
+                recorder.enter(state, function, ffi::lua_topointer(state, -1));
+                ffi::lua_pop(state, 1);
+            }
+        }
+        // Returning functions are found on the stack by identity
+        ffi::LUA_HOOKRET if ffi::lua_getinfo(state, cstr!("f"), ar) != 0 => {
+            recorder.exit(state, ffi::lua_topointer(state, -1));
+            ffi::lua_pop(state, 1);
+        }
+        // Tail returns in Lua 5.1/LuaJIT
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        ffi::LUA_HOOKTAILCALL => recorder.pop(state),
+        _ => {}
+    }
+
+    if let Some(prev_hook) = prev_hook.filter(|_| forward_event) {
+        if state == (*extra).inner.assume_init_ref().main_state {
+            prev_hook(state, ar);
+        }
+    }
+}
+
+// Fills the `nS` info of the function running in the hook, using the display name of named Rust
+// callbacks
+#[cfg(all(feature = "trace_events", not(feature = "luau")))]
+unsafe fn hook_function_info(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) -> bool {
+    if ffi::lua_getinfo(state, cstr!("nSf"), ar) == 0 {
+        return false;
+    }
+    util::describe_named_callback(state, ar);
+    ffi::lua_pop(state, 1);
+    true
+}
+
+// Returns the name and the source location of the function described by `ar`
+#[cfg(any(feature = "trace_events", not(feature = "luau")))]
+pub(crate) unsafe fn function_label(
+    ar: *const ffi::lua_Debug,
+) -> (std::string::String, Option<std::string::String>) {
+    #[cfg(not(feature = "luau"))]
+    let short_src = util::ptr_to_lossy_str((*ar).short_src.as_ptr());
+    #[cfg(feature = "luau")]
+    let short_src = util::ptr_to_lossy_str((*ar).short_src);
+    let source = format!("{}:{}", short_src.unwrap_or_default(), (*ar).linedefined);
+    let name = util::ptr_to_lossy_str((*ar).name).map(|name| name.into_owned());
+    match util::ptr_to_str((*ar).what) {
+        // Named Rust callbacks have the location where they were created
+        Some("C") if (*ar).linedefined > 0 => (name.unwrap_or_default(), Some(source)),
+        Some("C") => (name.unwrap_or_else(|| "?".to_string()), None),
+        Some("main") => ("main chunk".to_string(), Some(source)),
+        _ => match name {
+            Some(name) => (name, Some(source)),
+            None => (format!("function <{source}>"), Some(source)),
+        },
+    }
+}
+
+#[cfg(all(feature = "trace_events", feature = "luau"))]
+unsafe extern "C-unwind" fn trace_events_interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
+    let extra = extra_data(state);
+    let prev_interrupt = match (*extra).trace_events {
+        Some(ref recorder) => recorder.prev_interrupt,
+        None => return,
+    };
+    if let Some(prev_interrupt) = prev_interrupt {
+        prev_interrupt(state, gc);
+    }
+    let recorder = match (*extra).trace_events {
+        Some(ref mut recorder) if gc < 0 => recorder,
+        _ => return,
+    };
+    if ffi::lua_checkstack(state, 4) == 0 {
+        return;
+    }
+
+    // Collect functions on the stack, starting from the outermost one
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    let mut stack = Vec::new();
+    let mut level = 0;
+    while ffi::lua_getinfo(state, level, cstr!("f"), &mut ar) != 0 {
+        stack.push(ffi::lua_topointer(state, -1));
+        ffi::lua_pop(state, 1);
+        level += 1;
+    }
+    stack.reverse();
+
+    let depth = stack.len();
+    recorder.sync_stack(state, stack, |i| {
+        match ffi::lua_getinfo(state, (depth - 1 - i) as c_int, cstr!("snf"), &mut ar) {
+            0 => ("?".to_string(), None),
+            _ => {
+                util::describe_named_callback(state, &mut ar);
+                ffi::lua_pop(state, 1);
+                function_label(&ar)
+            }
+        }
+    });
+}
+
+// Returns the registered name of the running callback (or the name it was called by)
+pub(crate) unsafe fn resolve_callback_name(
+    state: *mut ffi::lua_State,
+) -> Option<std::string::String> {
+    if ffi::lua_checkstack(state, 6) == 0 {
+        return None;
+    }
+    util::function_name(state, 0)
+}
+
 // Uses 3 stack spaces
 unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<()> {
     #[inline(always)]
@@ -3540,8 +7873,8 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<
         ffi::lua_pop(state, 1);
     }
 
-    #[cfg(not(feature = "luau"))]
-    if libs.contains(StdLib::IO) {
+    #[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+    if libs.contains(StdLib::IO) && (*extra_data(state)).options.fs_access {
         requiref(state, ffi::LUA_IOLIBNAME, ffi::luaopen_io, 1)?;
         ffi::lua_pop(state, 1);
     }
@@ -3594,6 +7927,11 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<
     if libs.contains(StdLib::DEBUG) {
         requiref(state, ffi::LUA_DBLIBNAME, ffi::luaopen_debug, 1)?;
         ffi::lua_pop(state, 1);
+        #[cfg(not(feature = "luau"))]
+        {
+            let lua: &Lua = mem::transmute((*extra_data(state)).inner.assume_init_ref());
+            crate::safe_debug::describe_named_callbacks(lua)?;
+        }
     }
 
     #[cfg(not(feature = "luau"))]
@@ -3607,6 +7945,12 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<
         crate::luau::register_package_module(lua)?;
     }
 
+    // Registered after `package` to be found by `require`
+    if libs.contains(StdLib::DEBUG_SAFE) && !libs.contains(StdLib::DEBUG) {
+        let lua: &Lua = mem::transmute((*extra_data(state)).inner.assume_init_ref());
+        crate::safe_debug::register_safe_debug(lua)?;
+    }
+
     #[cfg(feature = "luajit")]
     {
         if libs.contains(StdLib::JIT) {
@@ -3620,13 +7964,74 @@ unsafe fn load_from_std_lib(state: *mut ffi::lua_State, libs: StdLib) -> Result<
         }
     }
 
+    if !cfg!(feature = "filesystem") || !(*extra_data(state)).options.fs_access {
+        disable_fs_access(state)?;
+    }
+
     Ok(())
 }
 
+// Removes functions that can access the filesystem or start processes from the loaded standard
+// libraries
+unsafe fn disable_fs_access(state: *mut ffi::lua_State) -> Result<()> {
+    let _sg = StackGuard::new(state);
+    check_stack(state, 4)?;
+
+    protect_lua!(state, 0, 0, fn(state) {
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
+        #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+        ffi::lua_pushvalue(state, ffi::LUA_GLOBALSINDEX);
+
+        for name in [cstr!("loadfile"), cstr!("dofile")] {
+            ffi::lua_pushnil(state);
+            ffi::lua_setfield(state, -2, name);
+        }
+
+        if ffi::lua_getfield(state, -1, cstr!("os")) == ffi::LUA_TTABLE {
+            let names = [
+                cstr!("execute"),
+                cstr!("exit"),
+                cstr!("remove"),
+                cstr!("rename"),
+                cstr!("tmpname"),
+            ];
+            for name in names {
+                ffi::lua_pushnil(state);
+                ffi::lua_setfield(state, -2, name);
+            }
+        }
+        ffi::lua_pop(state, 1);
+
+        #[cfg(not(feature = "luau"))]
+        if ffi::lua_getfield(state, -1, cstr!("package")) == ffi::LUA_TTABLE {
+            for name in [cstr!("loadlib"), cstr!("searchpath"), cstr!("path"), cstr!("cpath")] {
+                ffi::lua_pushnil(state);
+                ffi::lua_setfield(state, -2, name);
+            }
+
+            // Keep only the `package.preload` searcher
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            let searchers = cstr!("searchers");
+            #[cfg(any(feature = "lua51", feature = "luajit"))]
+            let searchers = cstr!("loaders");
+            if ffi::lua_getfield(state, -1, searchers) == ffi::LUA_TTABLE {
+                for i in (2..=ffi::lua_rawlen(state, -1) as ffi::lua_Integer).rev() {
+                    ffi::lua_pushnil(state);
+                    ffi::lua_rawseti(state, -2, i);
+                }
+            }
+            ffi::lua_pop(state, 1);
+        }
+    })
+}
+
 unsafe fn ref_stack_pop(extra: *mut ExtraData) -> c_int {
     let extra = &mut *extra;
     if let Some(free) = extra.ref_free.pop() {
         ffi::lua_replace(extra.ref_thread, free);
+        let used = extra.ref_stack_top - extra.ref_free.len() as c_int;
+        extra.ref_stack_peak = extra.ref_stack_peak.max(used);
         return free;
     }
 
@@ -3650,6 +8055,8 @@ unsafe fn ref_stack_pop(extra: *mut ExtraData) -> c_int {
         extra.ref_stack_size += inc;
     }
     extra.ref_stack_top += 1;
+    let used = extra.ref_stack_top - extra.ref_free.len() as c_int;
+    extra.ref_stack_peak = extra.ref_stack_peak.max(used);
     extra.ref_stack_top
 }
 
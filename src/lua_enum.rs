@@ -0,0 +1,116 @@
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::Integer;
+use crate::value::Value;
+
+/// A fieldless Rust enum that can be exposed to Lua as a table of constants.
+///
+/// This trait is usually derived (see the [`LuaEnum`][derive] derive macro), which also
+/// implements [`FromLua`] and [`IntoLua`] for the enum, but can be implemented manually as well.
+///
+/// Variants are converted into Lua integers. Use [`Lua::register_enum`] to register the
+/// constants as a global table.
+///
+/// [derive]: macro@crate::LuaEnum
+/// [`FromLua`]: crate::FromLua
+/// [`IntoLua`]: crate::IntoLua
+pub trait LuaEnum: Clone + Sized + 'static {
+    /// Name of the Rust type, used in conversion errors.
+    const NAME: &'static str;
+
+    /// Names and variants of the enum, in declaration order.
+    const VARIANTS: &'static [(&'static str, Self)];
+
+    /// Returns the integer value of the variant.
+    fn to_integer(&self) -> Integer;
+
+    /// Returns the name of the variant.
+    fn name(&self) -> &'static str {
+        let value = self.to_integer();
+        (Self::VARIANTS.iter())
+            .find(|(_, variant)| variant.to_integer() == value)
+            .map_or("?", |(name, _)| name)
+    }
+
+    /// Returns the variant with the given name.
+    fn from_name(name: &str) -> Option<Self> {
+        (Self::VARIANTS.iter())
+            .find(|(variant_name, _)| *variant_name == name)
+            .map(|(_, variant)| variant.clone())
+    }
+
+    /// Returns the variant with the given integer value.
+    fn from_integer(value: Integer) -> Option<Self> {
+        (Self::VARIANTS.iter())
+            .find(|(_, variant)| variant.to_integer() == value)
+            .map(|(_, variant)| variant.clone())
+    }
+
+    /// Converts a Lua value, either the integer value of a variant or its name, into the enum.
+    fn from_lua_value(value: Value) -> Result<Self> {
+        let variant = match &value {
+            Value::Integer(i) => Self::from_integer(*i),
+            Value::Number(n) if n.fract() == 0.0 => Self::from_integer(*n as Integer),
+            Value::String(s) => s.to_str().ok().and_then(Self::from_name),
+            _ => None,
+        };
+        variant.ok_or_else(|| {
+            let names = Self::VARIANTS.iter().map(|(name, _)| *name);
+            Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: Self::NAME,
+                message: Some(format!(
+                    "expected one of {}",
+                    names.collect::<Vec<_>>().join(", ")
+                )),
+            }
+        })
+    }
+
+    /// Creates a read-only table mapping the variant names to their values, and the values back
+    /// to the names.
+    ///
+    /// The `name` is used in errors raised when scripts try to modify the table.
+    ///
+    /// In Luau the table is made read-only using [`Table::set_readonly`]. Other Lua versions return
+    /// an empty proxy which rejects assignments, but fields can still be added to the proxy by
+    /// `rawset` (shadowing the constants).
+    fn create_table<'lua>(lua: &'lua Lua, name: &str) -> Result<Table<'lua>> {
+        let table = lua.create_table_with_capacity(0, Self::VARIANTS.len() * 2)?;
+        for (variant_name, variant) in Self::VARIANTS {
+            table.raw_set(*variant_name, variant.to_integer())?;
+            table.raw_set(variant.to_integer(), *variant_name)?;
+        }
+        make_readonly(lua, table, name)
+    }
+}
+
+#[cfg(feature = "luau")]
+fn make_readonly<'lua>(_lua: &'lua Lua, table: Table<'lua>, _name: &str) -> Result<Table<'lua>> {
+    table.set_readonly(true);
+    Ok(table)
+}
+
+// Returns an empty proxy which forwards reads to `table` and rejects writes
+#[cfg(not(feature = "luau"))]
+fn make_readonly<'lua>(lua: &'lua Lua, table: Table<'lua>, name: &str) -> Result<Table<'lua>> {
+    let name = name.to_string();
+    let mt = lua.create_table_with_capacity(0, 4)?;
+    let newindex = lua.create_function(move |_, (_, key): (Value, Value)| -> Result<()> {
+        Err(Error::runtime(format!(
+            "attempt to modify read-only enum '{name}' (field '{}')",
+            key.to_string()?
+        )))
+    })?;
+    mt.raw_set("__newindex", newindex)?;
+    // Iterate over the original table in `pairs` (Lua 5.2+)
+    let next = lua.globals().raw_get::<_, Value>("next")?;
+    let pairs = lua.create_function(|_, (next, table): (Value, Table)| Ok((next, table)))?;
+    mt.raw_set("__pairs", pairs.bind((next, table.clone()))?)?;
+    mt.raw_set("__index", table)?;
+    mt.raw_set("__metatable", false)?;
+    let proxy = lua.create_table()?;
+    proxy.set_metatable(Some(mt));
+    Ok(proxy)
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "send")]
+use {
+    crate::remote::{RemoteCallQueue, RemoteLua},
+    once_cell::sync::Lazy,
+    rustc_hash::FxHashMap,
+    std::sync::{Arc, Mutex},
+};
+
+/// Unique identifier of a Lua state, never reused within the process.
+///
+/// Returned by [`Lua::id`]. Useful to tag logs and metrics of applications running multiple
+/// states, or to record in userdata which state created it.
+///
+/// [`Lua::id`]: crate::Lua::id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LuaId(u64);
+
+impl LuaId {
+    pub(crate) fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        LuaId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the raw value of the identifier.
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for LuaId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// Process-wide registry of live states, by id.
+// States are reachable only through their remote call queues, to be accessed by the owning thread.
+#[cfg(feature = "send")]
+static LIVE_STATES: Lazy<Mutex<FxHashMap<LuaId, RemoteLua>>> = Lazy::new(Default::default);
+
+#[cfg(feature = "send")]
+fn live_states() -> std::sync::MutexGuard<'static, FxHashMap<LuaId, RemoteLua>> {
+    mlua_expect!(LIVE_STATES.lock(), "live states registry poisoned")
+}
+
+#[cfg(feature = "send")]
+pub(crate) fn register(id: LuaId, queue: &RemoteCallQueue) {
+    live_states().insert(id, RemoteLua::new(id, Arc::downgrade(queue)));
+}
+
+#[cfg(feature = "send")]
+pub(crate) fn unregister(id: LuaId) {
+    live_states().remove(&id);
+}
+
+#[cfg(feature = "send")]
+pub(crate) fn find(id: LuaId) -> Option<RemoteLua> {
+    live_states()
+        .get(&id)
+        .filter(|state| state.is_live())
+        .cloned()
+}
+
+#[cfg(feature = "send")]
+pub(crate) fn live_ids() -> Vec<LuaId> {
+    let states = live_states();
+    let mut ids = (states.iter())
+        .filter(|(_, state)| state.is_live())
+        .map(|(&id, _)| id)
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
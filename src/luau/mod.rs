@@ -83,6 +83,15 @@ unsafe extern "C-unwind" fn lua_vector(state: *mut ffi::lua_State) -> c_int {
     1
 }
 
+// `os.clock` backed by the Luau high-resolution clock
+pub(crate) unsafe extern "C-unwind" fn os_clock(state: *mut ffi::lua_State) -> c_int {
+    ffi::lua_pushnumber(state, ffi::lua_clock());
+    1
+}
+
 pub(crate) use package::register_package_module;
+pub use package::ModuleGraph;
+pub(crate) use vector::create_vector_library;
 
 mod package;
+mod vector;
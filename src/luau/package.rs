@@ -1,25 +1,142 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::CStr;
 use std::fmt::Write;
-use std::os::raw::c_int;
-use std::path::{PathBuf, MAIN_SEPARATOR_STR};
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
 use std::string::String as StdString;
-use std::{env, fs};
 
-use crate::chunk::ChunkMode;
+use rustc_hash::FxHashSet;
+
 use crate::error::Result;
 use crate::lua::Lua;
-use crate::table::Table;
-use crate::types::RegistryKey;
-use crate::value::{IntoLua, Value};
+use crate::util::ptr_to_lossy_str;
+
+#[cfg(feature = "filesystem")]
+use {
+    crate::chunk::ChunkMode,
+    crate::table::Table,
+    crate::types::RegistryKey,
+    crate::value::{IntoLua, Value},
+    std::path::MAIN_SEPARATOR_STR,
+    std::{env, fs},
+};
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "filesystem"))]
 use {libloading::Library, rustc_hash::FxHashMap};
 
+/// Dependencies between chunks and the modules they load using `require`.
+///
+/// Modules are identified by the name passed to `require`, other chunks (e.g. the main script)
+/// by their name. Dependencies are recorded even when the required module was already loaded.
+///
+/// Returned by [`Lua::module_graph`].
+#[derive(Clone, Debug, Default)]
+pub struct ModuleGraph {
+    dependencies: BTreeMap<StdString, Vec<StdString>>,
+    paths: BTreeMap<StdString, PathBuf>,
+}
+
+impl ModuleGraph {
+    /// Returns the modules required by the module or chunk `name`, in the order they were first
+    /// required.
+    pub fn dependencies(&self, name: &str) -> &[StdString] {
+        self.dependencies.get(name).map_or(&[], |deps| &deps[..])
+    }
+
+    /// Returns the modules and chunks that depend on the module `name`, directly or through other
+    /// modules.
+    ///
+    /// These are the ones to reload (after [`Lua::unload`]-ing the modules) when `name` changes.
+    pub fn dependents(&self, name: &str) -> Vec<&str> {
+        let mut dependents = Vec::new();
+        let mut visited = FxHashSet::default();
+        let mut queue = VecDeque::from([name]);
+        while let Some(module) = queue.pop_front() {
+            for (requirer, deps) in &self.dependencies {
+                if deps.iter().any(|dep| dep == module) && visited.insert(requirer.as_str()) {
+                    dependents.push(requirer.as_str());
+                    queue.push_back(requirer);
+                }
+            }
+        }
+        dependents.retain(|&requirer| requirer != name);
+        dependents
+    }
+
+    /// Returns the path of the file the module `name` was loaded from.
+    ///
+    /// The path is the one produced by `package.path` (or `package.cpath`) templates.
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.paths.get(name).map(|path| path.as_path())
+    }
+
+    /// Returns the name of the module loaded from the file at `path`.
+    pub fn module_at(&self, path: impl AsRef<Path>) -> Option<&str> {
+        let path = path.as_ref();
+        (self.paths.iter())
+            .find(|(_, module_path)| *module_path == path)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn add_dependency(&mut self, source: &str, name: &str) {
+        // Chunks of modules loaded from files are named after the file path
+        let chunk_name = (source.strip_prefix('=')).or_else(|| source.strip_prefix('@'));
+        let requirer = match chunk_name {
+            Some(chunk_name) => (self.paths.iter())
+                .find(|(_, path)| path.as_os_str() == chunk_name)
+                .map(|(module, _)| module.as_str())
+                .unwrap_or(chunk_name),
+            None => source,
+        };
+        let deps = self.dependencies.entry(requirer.to_string()).or_default();
+        if !deps.iter().any(|dep| dep == name) {
+            deps.push(name.to_string());
+        }
+    }
+}
+
+// Dependencies recorded by `require`, kept in application data
+struct ModuleDeps(ModuleGraph);
+
+impl Lua {
+    /// Returns the dependencies between chunks and modules recorded by `require`.
+    ///
+    /// Hot-reload systems can use it to find the modules to unload when a file changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let searcher = lua.create_function(|lua, name: String| match name.as_str() {
+    ///     "config" => lua.load("return {}").set_name("config").into_function().map(Some),
+    ///     _ => Ok(None),
+    /// })?;
+    /// lua.loaders()?.insert(0, searcher)?;
+    ///
+    /// lua.load("require('config')").set_name("main").exec()?;
+    /// let graph = lua.module_graph();
+    /// assert_eq!(graph.dependencies("main"), ["config"]);
+    /// assert_eq!(graph.dependents("config"), ["main"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn module_graph(&self) -> ModuleGraph {
+        match self.app_data_ref::<ModuleDeps>() {
+            Some(deps) => deps.0.clone(),
+            None => ModuleGraph::default(),
+        }
+    }
+}
+
 //
 // Luau package module
 //
 
-#[cfg(unix)]
+#[cfg(all(unix, any(feature = "module", feature = "filesystem")))]
 const TARGET_MLUA_LUAU_ABI_VERSION: u32 = 1;
 
 #[cfg(all(unix, feature = "module"))]
@@ -28,13 +145,14 @@ const TARGET_MLUA_LUAU_ABI_VERSION: u32 = 1;
 pub static MLUA_LUAU_ABI_VERSION: u32 = TARGET_MLUA_LUAU_ABI_VERSION;
 
 // We keep reference to the `package` table in registry under this key
+#[cfg(feature = "filesystem")]
 struct PackageKey(RegistryKey);
 
 // We keep reference to the loaded dylibs in application data
-#[cfg(unix)]
+#[cfg(all(unix, feature = "filesystem"))]
 struct LoadedDylibs(FxHashMap<PathBuf, Library>);
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "filesystem"))]
 impl std::ops::Deref for LoadedDylibs {
     type Target = FxHashMap<PathBuf, Library>;
 
@@ -43,7 +161,7 @@ impl std::ops::Deref for LoadedDylibs {
     }
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "filesystem"))]
 impl std::ops::DerefMut for LoadedDylibs {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
@@ -53,20 +171,28 @@ impl std::ops::DerefMut for LoadedDylibs {
 pub(crate) fn register_package_module(lua: &Lua) -> Result<()> {
     // Create the package table and store it in app_data for later use (bypassing globals lookup)
     let package = lua.create_table()?;
+    #[cfg(feature = "filesystem")]
     lua.set_app_data(PackageKey(lua.create_registry_value(package.clone())?));
 
+    // Paths and file loaders are not available if filesystem access is disabled
+    #[cfg(feature = "filesystem")]
+    let fs_access = lua.fs_access();
+
     // Set `package.path`
-    let mut search_path = env::var("LUAU_PATH")
-        .or_else(|_| env::var("LUA_PATH"))
-        .unwrap_or_default();
-    if search_path.is_empty() {
-        search_path = "?.luau;?.lua".to_string();
+    #[cfg(feature = "filesystem")]
+    if fs_access {
+        let mut search_path = env::var("LUAU_PATH")
+            .or_else(|_| env::var("LUA_PATH"))
+            .unwrap_or_default();
+        if search_path.is_empty() {
+            search_path = "?.luau;?.lua".to_string();
+        }
+        package.raw_set("path", search_path)?;
     }
-    package.raw_set("path", search_path)?;
 
     // Set `package.cpath`
-    #[cfg(unix)]
-    {
+    #[cfg(all(unix, feature = "filesystem"))]
+    if fs_access {
         let mut search_cpath = env::var("LUAU_CPATH")
             .or_else(|_| env::var("LUA_CPATH"))
             .unwrap_or_default();
@@ -84,14 +210,19 @@ pub(crate) fn register_package_module(lua: &Lua) -> Result<()> {
     let loaded = lua.create_table()?;
     package.raw_set("loaded", loaded.clone())?;
     lua.set_named_registry_value("_LOADED", loaded)?;
+    lua.set_app_data(ModuleDeps(ModuleGraph::default()));
 
     // Set `package.loaders`
-    let loaders = lua.create_sequence_from([lua.create_function(lua_loader)?])?;
+    let loaders = lua.create_table()?;
     package.raw_set("loaders", loaders.clone())?;
-    #[cfg(unix)]
-    {
-        loaders.push(lua.create_function(dylib_loader)?)?;
-        lua.set_app_data(LoadedDylibs(FxHashMap::default()));
+    #[cfg(feature = "filesystem")]
+    if fs_access {
+        loaders.push(lua.create_function(lua_loader)?)?;
+        #[cfg(unix)]
+        {
+            loaders.push(lua.create_function(dylib_loader)?)?;
+            lua.set_app_data(LoadedDylibs(FxHashMap::default()));
+        }
     }
     lua.set_named_registry_value("_LOADERS", loaders)?;
 
@@ -107,13 +238,14 @@ pub(crate) fn register_package_module(lua: &Lua) -> Result<()> {
 pub(crate) fn disable_dylibs(lua: &Lua) {
     // Presence of `LoadedDylibs` in app data is used as a flag
     // to check whether binary modules are enabled
-    #[cfg(unix)]
+    #[cfg(all(unix, feature = "filesystem"))]
     lua.remove_app_data::<LoadedDylibs>();
 }
 
 unsafe extern "C-unwind" fn lua_require(state: *mut ffi::lua_State) -> c_int {
     ffi::lua_settop(state, 1);
     let name = ffi::luaL_checkstring(state, 1);
+    record_dependency(state, name);
     ffi::luaL_getsubtable(state, ffi::LUA_REGISTRYINDEX, cstr!("_LOADED")); // _LOADED is at index 2
     if ffi::lua_rawgetfield(state, 2, name) != ffi::LUA_TNIL {
         return 1; // module is already loaded
@@ -164,9 +296,33 @@ unsafe extern "C-unwind" fn lua_require(state: *mut ffi::lua_State) -> c_int {
     1
 }
 
+// Records that the chunk calling `require` depends on the module `name`
+unsafe fn record_dependency(state: *mut ffi::lua_State, name: *const c_char) {
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    if ffi::lua_getinfo(state, 1, cstr!("s"), &mut ar) == 0 {
+        return;
+    }
+    let source = ptr_to_lossy_str(ar.source).unwrap_or_default();
+    let name = CStr::from_ptr(name).to_string_lossy();
+    if let Some(lua) = Lua::try_from_ptr(state) {
+        if let Some(mut deps) = lua.app_data_mut::<ModuleDeps>() {
+            deps.0.add_dependency(&source, &name);
+        }
+    }
+}
+
+// Records the file the module `name` is loaded from
+#[cfg(feature = "filesystem")]
+fn record_path(lua: &Lua, name: &str, path: &Path) {
+    if let Some(mut deps) = lua.app_data_mut::<ModuleDeps>() {
+        deps.0.paths.insert(name.to_string(), path.to_path_buf());
+    }
+}
+
 /// Searches for the given `name` in the given `path`.
 ///
 /// `path` is a string containing a sequence of templates separated by semicolons.
+#[cfg(feature = "filesystem")]
 fn package_searchpath(name: &str, search_path: &str, try_prefix: bool) -> Option<PathBuf> {
     let mut names = vec![name.replace('.', MAIN_SEPARATOR_STR)];
     if try_prefix && name.contains('.') {
@@ -189,6 +345,7 @@ fn package_searchpath(name: &str, search_path: &str, try_prefix: bool) -> Option
 //
 
 /// Tries to load a lua (text) file
+#[cfg(feature = "filesystem")]
 fn lua_loader(lua: &Lua, modname: StdString) -> Result<Value> {
     let package = {
         let key = lua.app_data_ref::<PackageKey>().unwrap();
@@ -199,6 +356,7 @@ fn lua_loader(lua: &Lua, modname: StdString) -> Result<Value> {
     if let Some(file_path) = package_searchpath(&modname, &search_path, false) {
         match fs::read(&file_path) {
             Ok(buf) => {
+                record_path(lua, &modname, &file_path);
                 return lua
                     .load(&buf)
                     .set_name(&format!("={}", file_path.display()))
@@ -216,7 +374,7 @@ fn lua_loader(lua: &Lua, modname: StdString) -> Result<Value> {
 }
 
 /// Tries to load a dynamic library
-#[cfg(unix)]
+#[cfg(all(unix, feature = "filesystem"))]
 fn dylib_loader(lua: &Lua, modname: StdString) -> Result<Value> {
     let package = {
         let key = lua.app_data_ref::<PackageKey>().unwrap();
@@ -259,6 +417,7 @@ fn dylib_loader(lua: &Lua, modname: StdString) -> Result<Value> {
                     return err.into_lua(lua);
                 }
                 let symbol = find_symbol(&lib);
+                record_path(lua, &modname, &file_path);
                 loaded_dylibs.insert(file_path, lib);
                 return symbol;
             }
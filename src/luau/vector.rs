@@ -0,0 +1,157 @@
+use crate::args::Args;
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::multi::Variadic;
+use crate::table::Table;
+use crate::types::Vector;
+use crate::userdata::MetaMethod;
+use crate::value::{MultiValue, Value};
+
+// Creates the `vector` library table matching the Luau standard vector library
+pub(crate) fn create_vector_library(lua: &Lua) -> Result<Table> {
+    let lib = lua.create_table_with_capacity(0, 16)?;
+
+    lib.raw_set("create", lua.create_named_function("create", create)?)?;
+
+    lib.raw_set(
+        "magnitude",
+        lua.create_named_function("magnitude", |_, v: Vector| Ok(magnitude(v)))?,
+    )?;
+    lib.raw_set(
+        "normalize",
+        lua.create_named_function("normalize", |_, v: Vector| {
+            let len = magnitude(v);
+            Ok(map(v, |c| c / len))
+        })?,
+    )?;
+    lib.raw_set(
+        "cross",
+        lua.create_named_function("cross", |_, (a, b): (Vector, Vector)| Ok(cross(a, b)))?,
+    )?;
+    lib.raw_set(
+        "dot",
+        lua.create_named_function("dot", |_, (a, b): (Vector, Vector)| Ok(dot(a, b)))?,
+    )?;
+    lib.raw_set(
+        "angle",
+        lua.create_named_function(
+            "angle",
+            |_, (a, b, axis): (Vector, Vector, Option<Vector>)| {
+                let cross = cross(a, b);
+                let sin = magnitude(cross);
+                let cos = a.x() * b.x() + a.y() * b.y() + a.z() * b.z();
+                let angle = sin.atan2(cos);
+                match axis {
+                    Some(axis) if dot(cross, axis) < 0.0 => Ok(-angle),
+                    _ => Ok(angle),
+                }
+            },
+        )?,
+    )?;
+
+    lib.raw_set(
+        "floor",
+        lua.create_named_function("floor", |_, v: Vector| Ok(map(v, f32::floor)))?,
+    )?;
+    lib.raw_set(
+        "ceil",
+        lua.create_named_function("ceil", |_, v: Vector| Ok(map(v, f32::ceil)))?,
+    )?;
+    lib.raw_set(
+        "abs",
+        lua.create_named_function("abs", |_, v: Vector| Ok(map(v, f32::abs)))?,
+    )?;
+    lib.raw_set(
+        "sign",
+        lua.create_named_function("sign", |_, v: Vector| Ok(map(v, sign)))?,
+    )?;
+    lib.raw_set(
+        "clamp",
+        lua.create_named_function("clamp", |_, mut args: Args| {
+            let v: Vector = args.get()?;
+            let min: Vector = args.get()?;
+            let max: Vector = args.get_checked(|max: &Vector| {
+                for (i, c) in ["x", "y", "z", "w"].iter().take(Vector::SIZE).enumerate() {
+                    if max.0[i] < min.0[i] {
+                        return Err(format!("max.{c} must be greater than or equal to min.{c}"));
+                    }
+                }
+                Ok(())
+            })?;
+            Ok(zip(zip(v, min, f32::max), max, f32::min))
+        })?,
+    )?;
+    lib.raw_set(
+        "max",
+        lua.create_named_function("max", |_, (v, rest): (Vector, Variadic<Vector>)| {
+            Ok(rest.iter().fold(v, |acc, &v| zip(acc, v, f32::max)))
+        })?,
+    )?;
+    lib.raw_set(
+        "min",
+        lua.create_named_function("min", |_, (v, rest): (Vector, Variadic<Vector>)| {
+            Ok(rest.iter().fold(v, |acc, &v| zip(acc, v, f32::min)))
+        })?,
+    )?;
+
+    lib.raw_set("zero", Vector::zero())?;
+    lib.raw_set("one", Vector([1.0; Vector::SIZE]))?;
+
+    // Keep `vector(x, y, z)` working as a constructor
+    let mt = lua.create_table_with_capacity(0, 1)?;
+    let call = lua.create_function(|lua, (_, args): (Value, MultiValue)| {
+        create(lua, lua.unpack_multi(args)?)
+    })?;
+    mt.raw_set(MetaMethod::Call.name(), call)?;
+    lib.set_metatable(Some(mt));
+
+    Ok(lib)
+}
+
+#[cfg(not(feature = "luau-vector4"))]
+fn create(_: &Lua, (x, y, z): (f32, f32, f32)) -> Result<Vector> {
+    Ok(Vector::new(x, y, z))
+}
+
+#[cfg(feature = "luau-vector4")]
+fn create(_: &Lua, (x, y, z, w): (f32, f32, f32, Option<f32>)) -> Result<Vector> {
+    Ok(Vector::new(x, y, z, w.unwrap_or(0.0)))
+}
+
+fn map(v: Vector, f: impl Fn(f32) -> f32) -> Vector {
+    Vector(v.0.map(f))
+}
+
+fn zip(a: Vector, b: Vector, f: impl Fn(f32, f32) -> f32) -> Vector {
+    let mut v = a;
+    for (c, b) in v.0.iter_mut().zip(b.0) {
+        *c = f(*c, b);
+    }
+    v
+}
+
+fn dot(a: Vector, b: Vector) -> f32 {
+    a.0.iter().zip(b.0).map(|(a, b)| a * b).sum()
+}
+
+fn magnitude(v: Vector) -> f32 {
+    dot(v, v).sqrt()
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    let mut v = Vector::zero();
+    v.0[0] = a.y() * b.z() - a.z() * b.y();
+    v.0[1] = a.z() * b.x() - a.x() * b.z();
+    v.0[2] = a.x() * b.y() - a.y() * b.x();
+    v
+}
+
+fn sign(c: f32) -> f32 {
+    if c > 0.0 {
+        1.0
+    } else if c < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
@@ -1,6 +1,9 @@
 use std::alloc::{self, Layout};
 use std::os::raw::c_void;
-use std::ptr;
+use std::{mem, ptr};
+
+#[cfg(feature = "stack_diagnostics")]
+use {std::os::raw::c_int, std::panic::Location};
 
 pub(crate) static ALLOCATOR: ffi::lua_Alloc = allocator;
 
@@ -9,12 +12,33 @@ pub(crate) static ALLOCATOR: ffi::lua_Alloc = allocator;
 pub(crate) struct MemoryState {
     used_memory: isize,
     memory_limit: isize,
+    // Total amount of memory freed since the state creation
+    freed_memory: usize,
     // Can be set to temporary ignore the memory limit.
     // This is used when calling `lua_pushcfunction` for lua5.1/jit/luau.
     ignore_limit: bool,
     // Indicates that the memory limit was reached on the last allocation.
     #[cfg(feature = "luau")]
     limit_reached: bool,
+    // Memory limit of the quota set by `Lua::set_quota` (zero if unset)
+    quota_limit: isize,
+    // Indicates that an allocation failed because of the quota limit
+    quota_reached: bool,
+    // Memory usage marks (zero if unset) which raise `pressure_pending` when crossed, to deliver
+    // memory pressure callbacks at the next safe point (see `Lua::on_memory_pressure`)
+    pressure_high: isize,
+    pressure_low: isize,
+    pressure_pending: bool,
+    // Lowest memory usage since the pressure callbacks were last checked
+    pressure_min: isize,
+    // Deepest stack slot reserved by `check_stack` in any thread of the state and its call site.
+    // It's kept here as `MemoryState` can be cheaply reached from any `lua_State`.
+    #[cfg(feature = "stack_diagnostics")]
+    stack_peak: Option<(c_int, &'static Location<'static>)>,
+    // Call site the stack usage is attributed to while running closures (which cannot be
+    // `#[track_caller]`) on behalf of an mlua API, see `with_stack_caller`
+    #[cfg(feature = "stack_diagnostics")]
+    stack_caller: Option<&'static Location<'static>>,
 }
 
 impl MemoryState {
@@ -38,6 +62,11 @@ impl MemoryState {
         self.used_memory as usize
     }
 
+    #[inline]
+    pub(crate) fn freed_memory(&self) -> usize {
+        self.freed_memory
+    }
+
     #[inline]
     pub(crate) fn memory_limit(&self) -> usize {
         self.memory_limit as usize
@@ -50,6 +79,54 @@ impl MemoryState {
         prev_limit as usize
     }
 
+    #[cfg(not(feature = "luajit"))]
+    #[inline]
+    pub(crate) fn set_quota_limit(&mut self, limit: usize) {
+        self.quota_limit = limit as isize;
+        self.quota_reached = false;
+    }
+
+    // Returns the quota limit if the last failed allocation exceeded it
+    #[inline]
+    pub(crate) unsafe fn take_quota_reached(state: *mut ffi::lua_State) -> Option<usize> {
+        let mem_state = Self::get(state);
+        if mem_state.is_null() || !mem::take(&mut (*mem_state).quota_reached) {
+            return None;
+        }
+        Some((*mem_state).quota_limit as usize)
+    }
+
+    #[inline]
+    pub(crate) fn set_pressure_marks(&mut self, high: usize, low: usize) {
+        self.pressure_high = high as isize;
+        self.pressure_low = low as isize;
+        self.check_pressure();
+    }
+
+    // Returns the lowest memory usage since the last call if a mark was crossed
+    #[inline]
+    pub(crate) fn take_pressure_pending(&mut self) -> Option<usize> {
+        let min_used = mem::replace(&mut self.pressure_min, self.used_memory);
+        mem::take(&mut self.pressure_pending).then_some(min_used as usize)
+    }
+
+    // Returns `true` if memory usage crossed a memory pressure mark
+    #[inline]
+    pub(crate) unsafe fn pressure_pending(state: *mut ffi::lua_State) -> bool {
+        let mem_state = Self::get(state);
+        !mem_state.is_null() && (*mem_state).pressure_pending
+    }
+
+    #[inline(always)]
+    fn check_pressure(&mut self) {
+        self.pressure_min = self.pressure_min.min(self.used_memory);
+        if (self.pressure_high > 0 && self.used_memory >= self.pressure_high)
+            || self.used_memory < self.pressure_low
+        {
+            self.pressure_pending = true;
+        }
+    }
+
     // This function is used primarily for calling `lua_pushcfunction` in lua5.1/jit/luau
     // to bypass the memory limit (if set).
     #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
@@ -72,6 +149,44 @@ impl MemoryState {
         f();
     }
 
+    #[cfg(feature = "stack_diagnostics")]
+    #[inline]
+    pub(crate) fn stack_peak(&self) -> Option<(c_int, &'static Location<'static>)> {
+        self.stack_peak
+    }
+
+    #[cfg(feature = "stack_diagnostics")]
+    #[inline]
+    pub(crate) fn reset_stack_peak(&mut self) {
+        self.stack_peak = None;
+    }
+
+    #[cfg(feature = "stack_diagnostics")]
+    #[inline]
+    pub(crate) fn record_stack_usage(
+        &mut self,
+        slots: c_int,
+        location: &'static Location<'static>,
+    ) {
+        if self.stack_peak.map_or(true, |(peak, _)| slots > peak) {
+            self.stack_peak = Some((slots, self.stack_caller.unwrap_or(location)));
+        }
+    }
+
+    // Sets the call site of the stack usage, returns `false` if it's already set by an outer call
+    #[cfg(feature = "stack_diagnostics")]
+    #[inline]
+    pub(crate) fn set_stack_caller(
+        &mut self,
+        location: Option<&'static Location<'static>>,
+    ) -> bool {
+        if location.is_some() && self.stack_caller.is_some() {
+            return false;
+        }
+        self.stack_caller = location;
+        true
+    }
+
     // Returns `true` if the memory limit was reached on the last memory operation
     #[cfg(feature = "luau")]
     #[inline]
@@ -99,6 +214,8 @@ unsafe extern "C-unwind" fn allocator(
             let layout = Layout::from_size_align_unchecked(osize, ffi::SYS_MIN_ALIGN);
             alloc::dealloc(ptr as *mut u8, layout);
             mem_state.used_memory -= osize as isize;
+            mem_state.freed_memory = mem_state.freed_memory.wrapping_add(osize);
+            mem_state.check_pressure();
         }
         return ptr::null_mut();
     }
@@ -122,7 +239,23 @@ unsafe extern "C-unwind" fn allocator(
         }
         return ptr::null_mut();
     }
+    let quota_limit = mem_state.quota_limit;
+    if mem_diff > 0 && quota_limit > 0 && new_used_memory > quota_limit && !mem_state.ignore_limit {
+        #[cfg(feature = "luau")]
+        {
+            mem_state.limit_reached = true;
+        }
+        mem_state.quota_reached = true;
+        return ptr::null_mut();
+    }
+    if mem_diff > 0 {
+        mem_state.quota_reached = false;
+    }
     mem_state.used_memory += mem_diff;
+    if mem_diff < 0 {
+        mem_state.freed_memory = mem_state.freed_memory.wrapping_add(-mem_diff as usize);
+    }
+    mem_state.check_pressure();
 
     if ptr.is_null() {
         // Allocate new memory
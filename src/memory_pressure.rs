@@ -0,0 +1,180 @@
+use std::mem;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::memory::MemoryState;
+use crate::types::{MaybeSend, MemoryPressureCallback};
+
+// Memory pressure callbacks of a state, stored in `ExtraData`
+#[derive(Default)]
+pub(crate) struct MemoryPressure {
+    thresholds: Vec<PressureThreshold>,
+    // Set while running the callbacks, to not deliver them recursively
+    delivering: bool,
+}
+
+struct PressureThreshold {
+    threshold: usize,
+    callback: MemoryPressureCallback,
+    // Whether the callback was called since memory usage went above the threshold
+    fired: bool,
+}
+
+impl MemoryPressure {
+    // Returns the lowest threshold to reach and the highest threshold to go below to get notified
+    fn marks(&self) -> (usize, usize) {
+        let high = (self.thresholds.iter())
+            .filter(|t| !t.fired)
+            .map(|t| t.threshold)
+            .min()
+            .unwrap_or(0);
+        let low = (self.thresholds.iter())
+            .filter(|t| t.fired)
+            .map(|t| t.threshold)
+            .max()
+            .unwrap_or(0);
+        (high, low)
+    }
+
+    // Disables delivery of the callbacks, before closing the state
+    pub(crate) fn clear(&mut self) {
+        drop(mem::take(&mut self.thresholds));
+        self.delivering = true;
+    }
+}
+
+// Resets the delivery flag even if a callback panics
+struct DeliveringGuard<'a>(&'a Lua);
+
+impl Drop for DeliveringGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { (*self.0.memory_pressure()).delivering = false };
+    }
+}
+
+impl Lua {
+    /// Sets a callback to be called when memory used by this Lua state goes above `threshold`
+    /// (in bytes).
+    ///
+    /// The callback receives the amount of memory currently used and can free memory held by
+    /// Rust caches, eg. by dropping [`RegistryKey`]s and calling [`Lua::expire_registry_values`]
+    /// or [`Lua::gc_collect`], before the [memory limit] is reached.
+    ///
+    /// Memory usage is tracked by the allocator, but as Lua cannot be reentered from it, the
+    /// callback is called at the next safe point: before running a Rust function called from Lua,
+    /// or by [`Lua::check_memory_pressure`]. An error returned by the callback is propagated to
+    /// the caller of the Rust function.
+    ///
+    /// The callback is called once per crossing: it's called again only after memory usage
+    /// goes below the threshold and then above it again. Multiple callbacks can be set with
+    /// different thresholds, they are called in ascending threshold order.
+    ///
+    /// Does not work in module mode where Lua state is managed externally.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Lua, RegistryKey, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let cache: Arc<Mutex<Vec<RegistryKey>>> = Arc::default();
+    ///
+    /// let cache2 = cache.clone();
+    /// let threshold = lua.used_memory() + 1024 * 1024;
+    /// lua.on_memory_pressure(threshold, move |lua, _used_memory| {
+    ///     cache2.lock().unwrap().clear();
+    ///     lua.expire_registry_values();
+    ///     lua.gc_collect()
+    /// })?;
+    ///
+    /// let cache2 = cache.clone();
+    /// let remember = lua.create_function(move |lua, data: mlua::String| {
+    ///     cache2.lock().unwrap().push(lua.create_registry_value(data)?);
+    ///     Ok(())
+    /// })?;
+    /// lua.globals().set("remember", remember)?;
+    /// lua.load("for i = 1, 20 do remember(string.rep('x', 100 * 1024)) end").exec()?;
+    /// assert!(cache.lock().unwrap().len() < 20);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`RegistryKey`]: crate::RegistryKey
+    /// [memory limit]: Lua::set_memory_limit
+    pub fn on_memory_pressure<F>(&self, threshold: usize, callback: F) -> Result<()>
+    where
+        F: Fn(&Lua, usize) -> Result<()> + MaybeSend + 'static,
+    {
+        if unsafe { MemoryState::get(self.state()) }.is_null() {
+            return Err(Error::MemoryLimitNotAvailable);
+        }
+        let pressure = unsafe { &mut *self.memory_pressure() };
+        pressure.thresholds.push(PressureThreshold {
+            // Zero is reserved to mark no threshold
+            threshold: threshold.max(1),
+            callback: Arc::new(callback),
+            fired: false,
+        });
+        pressure.thresholds.sort_by_key(|t| t.threshold);
+        self.update_pressure_marks();
+        Ok(())
+    }
+
+    /// Removes all callbacks set by [`Lua::on_memory_pressure`].
+    pub fn remove_memory_pressure_callbacks(&self) {
+        unsafe { (*self.memory_pressure()).thresholds.clear() };
+        self.update_pressure_marks();
+    }
+
+    /// Calls the callbacks set by [`Lua::on_memory_pressure`] whose thresholds were crossed,
+    /// if any.
+    ///
+    /// Useful to react to memory pressure from Rust code running long scripts, which do not
+    /// call Rust functions.
+    pub fn check_memory_pressure(&self) -> Result<()> {
+        let pressure = unsafe { &mut *self.memory_pressure() };
+        if pressure.delivering {
+            return Ok(());
+        }
+        let min_used = unsafe {
+            let mem_state = MemoryState::get(self.state());
+            match mem_state.as_mut().and_then(|m| m.take_pressure_pending()) {
+                Some(min_used) => min_used,
+                None => return Ok(()),
+            }
+        };
+
+        let used_memory = self.used_memory();
+        let mut callbacks = Vec::new();
+        for t in &mut pressure.thresholds {
+            // Rearm if memory usage went below the threshold in the meantime
+            if min_used < t.threshold {
+                t.fired = false;
+            }
+            if used_memory >= t.threshold && !t.fired {
+                callbacks.push(t.callback.clone());
+                t.fired = true;
+            }
+        }
+        pressure.delivering = true;
+        self.update_pressure_marks();
+
+        let _guard = DeliveringGuard(self);
+        for callback in callbacks {
+            callback(self, used_memory)?;
+        }
+        Ok(())
+    }
+
+    fn update_pressure_marks(&self) {
+        unsafe {
+            let (high, low) = (*self.memory_pressure()).marks();
+            let mem_state = MemoryState::get(self.state());
+            if !mem_state.is_null() {
+                (*mem_state).set_pressure_marks(high, low);
+            }
+        }
+    }
+}
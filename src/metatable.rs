@@ -0,0 +1,108 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti};
+
+/// Builder of a standalone metatable, used by [`Lua::create_metatable`].
+///
+/// The resulting metatable can be applied to plain tables (e.g. to implement proxy objects)
+/// using [`Table::set_metatable`].
+///
+/// Errors raised while registering metamethods are deferred and returned by
+/// [`Lua::create_metatable`].
+pub struct MetatableBuilder<'lua> {
+    lua: &'lua Lua,
+    table: Table<'lua>,
+    error: Option<Error>,
+}
+
+impl<'lua> MetatableBuilder<'lua> {
+    pub(crate) fn new(lua: &'lua Lua) -> Result<Self> {
+        Ok(MetatableBuilder {
+            lua,
+            table: lua.create_table()?,
+            error: None,
+        })
+    }
+
+    /// Add a metamethod which accepts the table (the metatable is attached to) as the first
+    /// parameter.
+    ///
+    /// # Note
+    ///
+    /// Metamethods for binary operators can be triggered if either the left or right argument
+    /// has the metatable, so the first argument is not always the expected table.
+    /// Use [`add_meta_function`] to accept any value.
+    ///
+    /// [`add_meta_function`]: #method.add_meta_function
+    pub fn add_meta_method<M, A, R>(&mut self, name: impl AsRef<str>, method: M)
+    where
+        M: Fn(&'lua Lua, Table<'lua>, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let func = self
+            .lua
+            .create_function(move |lua, (this, args): (Table, A)| method(lua, this, args));
+        self.set(name.as_ref(), func);
+    }
+
+    /// Add a metamethod as a function which accepts generic arguments.
+    pub fn add_meta_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let func = self.lua.create_function(function);
+        self.set(name.as_ref(), func);
+    }
+
+    /// Add a metamethod as a mutable function which accepts generic arguments.
+    ///
+    /// This is a version of [`add_meta_function`] that accepts a FnMut argument.
+    ///
+    /// [`add_meta_function`]: #method.add_meta_function
+    pub fn add_meta_function_mut<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: FnMut(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let func = self.lua.create_function_mut(function);
+        self.set(name.as_ref(), func);
+    }
+
+    /// Add a metatable field (e.g. `__index` table or `__name` string).
+    pub fn add_meta_field<V: IntoLua<'lua>>(&mut self, name: impl AsRef<str>, value: V) {
+        let value = value.into_lua(self.lua);
+        self.set(name.as_ref(), value);
+    }
+
+    fn set<V: IntoLua<'lua>>(&mut self, name: &str, value: Result<V>) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = value.and_then(|value| self.table.raw_set(name, value)) {
+            self.error = Some(err);
+        }
+    }
+
+    pub(crate) fn build(self) -> Result<Table<'lua>> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.table),
+        }
+    }
+}
+
+impl<'lua> fmt::Debug for MetatableBuilder<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("MetatableBuilder")
+            .field(&self.table)
+            .finish()
+    }
+}
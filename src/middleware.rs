@@ -0,0 +1,69 @@
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+use std::string::String as StdString;
+
+use crate::error::Result;
+use crate::lua::{resolve_callback_name, Lua};
+use crate::types::CallbackMiddleware;
+
+/// Information about a Rust callback invocation passed to the callback middleware.
+///
+/// See [`Lua::set_callback_middleware`].
+pub struct CallbackCtx<'a> {
+    state: *mut ffi::lua_State,
+    nargs: usize,
+    _phantom: PhantomData<&'a ()>,
+}
+
+impl<'a> CallbackCtx<'a> {
+    /// Returns the name the callback was called by (eg. global, field or method name), if known.
+    ///
+    /// The name is resolved from the call site, so it may be unavailable (eg. Luau method calls).
+    pub fn name(&self) -> Option<StdString> {
+        unsafe { resolve_callback_name(self.state) }
+    }
+
+    /// Returns the number of arguments passed to the callback.
+    pub const fn nargs(&self) -> usize {
+        self.nargs
+    }
+}
+
+/// The rest of the callback invocation chain passed to the callback middleware.
+///
+/// See [`Lua::set_callback_middleware`].
+pub struct CallbackNext<'a> {
+    f: &'a mut dyn FnMut() -> Result<()>,
+}
+
+impl<'a> CallbackNext<'a> {
+    /// Invokes the callback.
+    ///
+    /// If the middleware does not call this method, the callback is skipped and returns nothing.
+    pub fn call(self) -> Result<()> {
+        (self.f)()
+    }
+}
+
+// Invokes the callback `f` through the middleware, returning the number of results
+pub(crate) unsafe fn call_with_middleware(
+    lua: &Lua,
+    state: *mut ffi::lua_State,
+    nargs: c_int,
+    middleware: &CallbackMiddleware,
+    f: impl FnOnce(c_int) -> Result<c_int>,
+) -> Result<c_int> {
+    let ctx = CallbackCtx {
+        state,
+        nargs: nargs as usize,
+        _phantom: PhantomData,
+    };
+    let mut f = Some(f);
+    let mut nresults = 0;
+    let mut next = || match f.take() {
+        Some(f) => f(nargs).map(|n| nresults = n),
+        None => Ok(()),
+    };
+    middleware(lua, ctx, CallbackNext { f: &mut next })?;
+    Ok(nresults)
+}
@@ -0,0 +1,25 @@
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::table::Table;
+
+/// A Rust type that can be registered as a Lua module, a table of functions.
+///
+/// This trait is usually derived for structs whose fields are functions (see the
+/// [`LuaModule`][derive] derive macro), but can be implemented manually as well.
+///
+/// Use [`Lua::register_module`] to register a module as a global table.
+///
+/// [derive]: macro@crate::LuaModule
+pub trait LuaModule: Sized {
+    /// Adds the module members to the `module` table.
+    ///
+    /// The `path` is a dot-separated path of the module, used to name functions in errors.
+    fn register<'lua>(self, lua: &'lua Lua, module: &Table<'lua>, path: &str) -> Result<()>;
+
+    /// Creates a new table with the module members.
+    fn into_table<'lua>(self, lua: &'lua Lua, path: &str) -> Result<Table<'lua>> {
+        let module = lua.create_table()?;
+        self.register(lua, &module, path)?;
+        Ok(module)
+    }
+}
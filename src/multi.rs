@@ -2,6 +2,7 @@ use std::ops::{Deref, DerefMut};
 use std::os::raw::c_int;
 use std::result::Result as StdResult;
 
+use crate::conversion_trace::{trace, ConversionDirection};
 use crate::error::Result;
 use crate::lua::Lua;
 use crate::util::check_stack;
@@ -11,21 +12,26 @@ use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil
 /// on success, or in the case of an error, returning `nil` and an error message.
 impl<'lua, T: IntoLua<'lua>, E: IntoLua<'lua>> IntoLuaMulti<'lua> for StdResult<T, E> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
-        let mut result = MultiValue::with_lua_and_capacity(lua, 2);
-        match self {
-            Ok(v) => result.push_front(v.into_lua(lua)?),
-            Err(e) => {
-                result.push_front(e.into_lua(lua)?);
-                result.push_front(Nil);
+        let convert = || {
+            let mut result = MultiValue::with_lua_and_capacity(lua, 2);
+            match self {
+                Ok(v) => result.push_front(v.into_lua(lua)?),
+                Err(e) => {
+                    result.push_front(e.into_lua(lua)?);
+                    result.push_front(Nil);
+                }
             }
-        }
-        Ok(result)
+            Ok(result)
+        };
+        trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |r| r.len())
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack_multi(self, lua: &'lua Lua) -> Result<c_int> {
-        match self {
+        let convert = || match self {
             Ok(v) => v.push_into_stack(lua).map(|_| 1),
             Err(e) => {
                 let state = lua.state();
@@ -34,27 +40,31 @@ impl<'lua, T: IntoLua<'lua>, E: IntoLua<'lua>> IntoLuaMulti<'lua> for StdResult<
                 e.push_into_stack(lua)?;
                 Ok(2)
             }
-        }
+        };
+        trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |&n| n as usize)
     }
 }
 
 impl<'lua, E: IntoLua<'lua>> IntoLuaMulti<'lua> for StdResult<(), E> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
-        match self {
-            Ok(_) => return Ok(MultiValue::new()),
+        let convert = || match self {
+            Ok(_) => Ok(MultiValue::new()),
             Err(e) => {
                 let mut result = MultiValue::with_lua_and_capacity(lua, 2);
                 result.push_front(e.into_lua(lua)?);
                 result.push_front(Nil);
                 Ok(result)
             }
-        }
+        };
+        trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |r| r.len())
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack_multi(self, lua: &'lua Lua) -> Result<c_int> {
-        match self {
+        let convert = || match self {
             Ok(_) => Ok(0),
             Err(e) => {
                 let state = lua.state();
@@ -63,29 +73,40 @@ impl<'lua, E: IntoLua<'lua>> IntoLuaMulti<'lua> for StdResult<(), E> {
                 e.push_into_stack(lua)?;
                 Ok(2)
             }
-        }
+        };
+        trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |&n| n as usize)
     }
 }
 
 impl<'lua, T: IntoLua<'lua>> IntoLuaMulti<'lua> for T {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
-        let mut v = MultiValue::with_lua_and_capacity(lua, 1);
-        v.push_front(self.into_lua(lua)?);
-        Ok(v)
+        let convert = || {
+            let mut v = MultiValue::with_lua_and_capacity(lua, 1);
+            v.push_front(self.into_lua(lua)?);
+            Ok(v)
+        };
+        trace::<T, _>(lua, ConversionDirection::IntoLua, convert, |_| 1)
     }
 
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack_multi(self, lua: &'lua Lua) -> Result<c_int> {
-        self.push_into_stack(lua)?;
+        let convert = || self.push_into_stack(lua);
+        trace::<T, _>(lua, ConversionDirection::IntoLua, convert, |_| 1)?;
         Ok(1)
     }
 }
 
 impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
+    const NARGS: Option<usize> = Some(1);
+
     #[inline]
     fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
-        T::from_lua(values.pop_front().unwrap_or(Nil), lua)
+        let nvals = values.len();
+        let convert = || T::from_lua(values.pop_front().unwrap_or(Nil), lua);
+        trace::<T, _>(lua, ConversionDirection::FromLua, convert, |_| nvals)
     }
 
     #[inline]
@@ -95,15 +116,22 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
         to: Option<&str>,
         lua: &'lua Lua,
     ) -> Result<Self> {
-        T::from_lua_arg(args.pop_front().unwrap_or(Nil), i, to, lua)
+        let nargs = args.len();
+        let convert = || T::from_lua_arg(args.pop_front().unwrap_or(Nil), i, to, lua);
+        trace::<T, _>(lua, ConversionDirection::FromLua, convert, |_| nargs)
     }
 
     #[inline]
     unsafe fn from_stack_multi(nvals: c_int, lua: &'lua Lua) -> Result<Self> {
-        if nvals == 0 {
-            return T::from_lua(Nil, lua);
-        }
-        T::from_stack(-nvals, lua)
+        let convert = || {
+            if nvals == 0 {
+                return T::from_lua(Nil, lua);
+            }
+            T::from_stack(-nvals, lua)
+        };
+        trace::<T, _>(lua, ConversionDirection::FromLua, convert, |_| {
+            nvals as usize
+        })
     }
 
     #[inline]
@@ -113,15 +141,21 @@ impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for T {
         to: Option<&str>,
         lua: &'lua Lua,
     ) -> Result<Self> {
-        if nargs == 0 {
-            return T::from_lua_arg(Nil, i, to, lua);
-        }
-        T::from_stack_arg(-nargs, i, to, lua)
+        let convert = || {
+            if nargs == 0 {
+                return T::from_lua_arg(Nil, i, to, lua);
+            }
+            T::from_stack_arg(-nargs, i, to, lua)
+        };
+        trace::<T, _>(lua, ConversionDirection::FromLua, convert, |_| {
+            nargs as usize
+        })
     }
 }
 
 impl<'lua> IntoLuaMulti<'lua> for MultiValue<'lua> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, _: &'lua Lua) -> Result<MultiValue<'lua>> {
         Ok(self)
     }
@@ -207,21 +241,29 @@ impl<T> DerefMut for Variadic<T> {
 
 impl<'lua, T: IntoLua<'lua>> IntoLuaMulti<'lua> for Variadic<T> {
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
-        let mut values = MultiValue::with_lua_and_capacity(lua, self.0.len());
-        values.refill(self.0.into_iter().map(|e| e.into_lua(lua)))?;
-        Ok(values)
+        let convert = || {
+            let mut values = MultiValue::with_lua_and_capacity(lua, self.0.len());
+            values.refill(self.0.into_iter().map(|e| e.into_lua(lua)))?;
+            Ok(values)
+        };
+        trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |r| r.len())
     }
 }
 
 impl<'lua, T: FromLua<'lua>> FromLuaMulti<'lua> for Variadic<T> {
     #[inline]
     fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
-        values
-            .drain_all()
-            .map(|e| T::from_lua(e, lua))
-            .collect::<Result<Vec<T>>>()
-            .map(Variadic)
+        let nvals = values.len();
+        let convert = || {
+            values
+                .drain_all()
+                .map(|e| T::from_lua(e, lua))
+                .collect::<Result<Vec<T>>>()
+                .map(Variadic)
+        };
+        trace::<Self, _>(lua, ConversionDirection::FromLua, convert, |_| nvals)
     }
 }
 
@@ -229,17 +271,21 @@ macro_rules! impl_tuple {
     () => (
         impl<'lua> IntoLuaMulti<'lua> for () {
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
                 Ok(MultiValue::with_lua_and_capacity(lua, 0))
             }
 
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             unsafe fn push_into_stack_multi(self, _lua: &'lua Lua) -> Result<c_int> {
                 Ok(0)
             }
         }
 
         impl<'lua> FromLuaMulti<'lua> for () {
+            const NARGS: Option<usize> = Some(0);
+
             #[inline]
             fn from_lua_multi(_values: MultiValue<'lua>, _lua: &'lua Lua) -> Result<Self> {
                 Ok(())
@@ -262,29 +308,37 @@ macro_rules! impl_tuple {
         {
             #[allow(unused_mut, non_snake_case)]
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>> {
-                let ($($name,)* $last,) = self;
-
-                let mut results = $last.into_lua_multi(lua)?;
-                push_reverse!(results, $($name.into_lua(lua)?,)*);
-                Ok(results)
+                let convert = || {
+                    let ($($name,)* $last,) = self;
+
+                    let mut results = $last.into_lua_multi(lua)?;
+                    push_reverse!(results, $($name.into_lua(lua)?,)*);
+                    Ok(results)
+                };
+                trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |r| r.len())
             }
 
             #[allow(non_snake_case)]
             #[inline]
+            #[cfg_attr(feature = "stack_diagnostics", track_caller)]
             unsafe fn push_into_stack_multi(self, lua: &'lua Lua) -> Result<c_int> {
-                let ($($name,)* $last,) = self;
-                let mut nresults = 0;
-                $(
-                    _ = $name;
-                    nresults += 1;
-                )*
-                check_stack(lua.state(), nresults + 1)?;
-                $(
-                    $name.push_into_stack(lua)?;
-                )*
-                nresults += $last.push_into_stack_multi(lua)?;
-                Ok(nresults)
+                let convert = || {
+                    let ($($name,)* $last,) = self;
+                    let mut nresults = 0;
+                    $(
+                        _ = $name;
+                        nresults += 1;
+                    )*
+                    check_stack(lua.state(), nresults + 1)?;
+                    $(
+                        $name.push_into_stack(lua)?;
+                    )*
+                    nresults += $last.push_into_stack_multi(lua)?;
+                    Ok(nresults)
+                };
+                trace::<Self, _>(lua, ConversionDirection::IntoLua, convert, |&n| n as usize)
             }
         }
 
@@ -292,54 +346,75 @@ macro_rules! impl_tuple {
             where $($name: FromLua<'lua>,)*
                   $last: FromLuaMulti<'lua>
         {
+            const NARGS: Option<usize> = match $last::NARGS {
+                Some(n) => Some(n + <[&str]>::len(&[$(stringify!($name)),*])),
+                None => None,
+            };
+
             #[allow(unused_mut, non_snake_case)]
             #[inline]
             fn from_lua_multi(mut values: MultiValue<'lua>, lua: &'lua Lua) -> Result<Self> {
-                $(let $name = FromLua::from_lua(values.pop_front().unwrap_or(Nil), lua)?;)*
-                let $last = FromLuaMulti::from_lua_multi(values, lua)?;
-                Ok(($($name,)* $last,))
+                let nvals = values.len();
+                let convert = || {
+                    $(let $name = FromLua::from_lua(values.pop_front().unwrap_or(Nil), lua)?;)*
+                    let $last = FromLuaMulti::from_lua_multi(values, lua)?;
+                    Ok(($($name,)* $last,))
+                };
+                trace::<Self, _>(lua, ConversionDirection::FromLua, convert, |_| nvals)
             }
 
             #[allow(unused_mut, non_snake_case)]
             #[inline]
             fn from_lua_args(mut args: MultiValue<'lua>, mut i: usize, to: Option<&str>, lua: &'lua Lua) -> Result<Self> {
-                $(
-                    let $name = FromLua::from_lua_arg(args.pop_front().unwrap_or(Nil), i, to, lua)?;
-                    i += 1;
-                )*
-                let $last = FromLuaMulti::from_lua_args(args, i, to, lua)?;
-                Ok(($($name,)* $last,))
+                let nargs = args.len();
+                let convert = || {
+                    $(
+                        let $name = FromLua::from_lua_arg(args.pop_front().unwrap_or(Nil), i, to, lua)?;
+                        i += 1;
+                    )*
+                    let $last = FromLuaMulti::from_lua_args(args, i, to, lua)?;
+                    Ok(($($name,)* $last,))
+                };
+                trace::<Self, _>(lua, ConversionDirection::FromLua, convert, |_| nargs)
             }
 
             #[allow(unused_mut, non_snake_case)]
             #[inline]
             unsafe fn from_stack_multi(mut nvals: c_int, lua: &'lua Lua) -> Result<Self> {
-                $(
-                    let $name = if nvals > 0 {
-                        nvals -= 1;
-                        FromLua::from_stack(-(nvals + 1), lua)
-                    } else {
-                        FromLua::from_lua(Nil, lua)
-                    }?;
-                )*
-                let $last = FromLuaMulti::from_stack_multi(nvals, lua)?;
-                Ok(($($name,)* $last,))
+                let total = nvals as usize;
+                let convert = || {
+                    $(
+                        let $name = if nvals > 0 {
+                            nvals -= 1;
+                            FromLua::from_stack(-(nvals + 1), lua)
+                        } else {
+                            FromLua::from_lua(Nil, lua)
+                        }?;
+                    )*
+                    let $last = FromLuaMulti::from_stack_multi(nvals, lua)?;
+                    Ok(($($name,)* $last,))
+                };
+                trace::<Self, _>(lua, ConversionDirection::FromLua, convert, |_| total)
             }
 
             #[allow(unused_mut, non_snake_case)]
             #[inline]
             unsafe fn from_stack_args(mut nargs: c_int, mut i: usize, to: Option<&str>, lua: &'lua Lua) -> Result<Self> {
-                $(
-                    let $name = if nargs > 0 {
-                        nargs -= 1;
-                        FromLua::from_stack_arg(-(nargs + 1), i, to, lua)
-                    } else {
-                        FromLua::from_lua_arg(Nil, i, to, lua)
-                    }?;
-                    i += 1;
-                )*
-                let $last = FromLuaMulti::from_stack_args(nargs, i, to, lua)?;
-                Ok(($($name,)* $last,))
+                let total = nargs as usize;
+                let convert = || {
+                    $(
+                        let $name = if nargs > 0 {
+                            nargs -= 1;
+                            FromLua::from_stack_arg(-(nargs + 1), i, to, lua)
+                        } else {
+                            FromLua::from_lua_arg(Nil, i, to, lua)
+                        }?;
+                        i += 1;
+                    )*
+                    let $last = FromLuaMulti::from_stack_args(nargs, i, to, lua)?;
+                    Ok(($($name,)* $last,))
+                };
+                trace::<Self, _>(lua, ConversionDirection::FromLua, convert, |_| total)
             }
         }
     );
@@ -358,20 +433,19 @@ macro_rules! push_reverse {
     ($multi_value:expr,) => ();
 }
 
-impl_tuple!();
-impl_tuple!(A);
-impl_tuple!(A B);
-impl_tuple!(A B C);
-impl_tuple!(A B C D);
-impl_tuple!(A B C D E);
-impl_tuple!(A B C D E F);
-impl_tuple!(A B C D E F G);
-impl_tuple!(A B C D E F G H);
-impl_tuple!(A B C D E F G H I);
-impl_tuple!(A B C D E F G H I J);
-impl_tuple!(A B C D E F G H I J K);
-impl_tuple!(A B C D E F G H I J K L);
-impl_tuple!(A B C D E F G H I J K L M);
-impl_tuple!(A B C D E F G H I J K L M N);
-impl_tuple!(A B C D E F G H I J K L M N O);
-impl_tuple!(A B C D E F G H I J K L M N O P);
+// Implements the traits for every tuple with up to as many elements as there are identifiers.
+//
+// Longer argument lists can be expressed by nesting a tuple in the last position, eg. `(A, B, (C, D))`
+// converts exactly like `(A, B, C, D)`.
+macro_rules! impl_tuples {
+    () => (
+        impl_tuple!();
+    );
+
+    ($first:ident $($rest:ident)*) => (
+        impl_tuple!($first $($rest)*);
+        impl_tuples!($($rest)*);
+    );
+}
+
+impl_tuples!(A B C D E F G H I J K L M N O P Q R S T U V W X Y Z A1 B1 C1 D1 E1 F1);
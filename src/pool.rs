@@ -0,0 +1,751 @@
+use std::any::TypeId;
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+#[cfg(feature = "async")]
+use {
+    std::collections::BTreeMap,
+    std::future::Future,
+    std::pin::Pin,
+    std::task::{Context, Poll, Waker},
+};
+
+use crate::error::{Error, Result};
+use crate::lua::{Lua, LuaOptions};
+use crate::stdlib::StdLib;
+use crate::table::Table;
+use crate::types::{MaybeSend, MaybeSync, RegistryKey};
+use crate::util::{check_stack, StackGuard, CAN_BLOCK};
+use crate::value::Value;
+
+#[cfg(feature = "send")]
+use {
+    crate::function::Function,
+    crate::quota::Quota,
+    crate::value::{FromLuaMulti, IntoLuaMulti},
+};
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
+#[cfg(all(feature = "send", feature = "serialize"))]
+use {
+    crate::serde::LuaSerdeExt,
+    serde::{de::DeserializeOwned, Serialize},
+};
+
+#[cfg(feature = "send")]
+type InitFn = Box<dyn Fn(&Lua) -> Result<()> + Send + Sync>;
+
+#[cfg(not(feature = "send"))]
+type InitFn = Box<dyn Fn(&Lua) -> Result<()>>;
+
+/// A pool of pre-initialized Lua states for request-scoped execution.
+///
+/// Every state in the pool is created with the same set of standard libraries and options and
+/// then initialized using the provided `init` function (e.g. to load a prelude).
+/// States are checked out using [`LuaPool::get`] (or [`LuaPool::get_async`]) and automatically
+/// returned back to the pool when the [`PooledLua`] guard is dropped.
+///
+/// When a state is returned, the pool sanitizes it:
+/// - Globals created after initialization are removed and the original globals are restored
+/// - Fields of tables stored in globals (e.g. standard libraries) are restored (shallow)
+/// - The globals metatable is restored
+/// - Modules loaded after initialization are removed from `package.loaded`
+/// - Named registry values (see [`Lua::set_named_registry_value`]) set after initialization are
+///   removed and the original ones are restored
+/// - Application data (see [`Lua::set_app_data`]) of types added after initialization is removed
+/// - Expired registry values are removed and a full garbage collection cycle is performed
+///
+/// If a state cannot be sanitized, it is replaced by a freshly initialized one. If this fails
+/// too, the next checkout tries to initialize a new state again and returns the error on failure.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{LuaPool, Result};
+/// # fn main() -> Result<()> {
+/// let pool = LuaPool::new(2, |lua| lua.load("function greet(name) return 'hi ' .. name end").exec())?;
+///
+/// {
+///     let lua = pool.get()?;
+///     lua.load("leaked = 1").exec()?;
+///     assert_eq!(lua.load("greet('bob')").eval::<String>()?, "hi bob");
+/// }
+///
+/// let lua = pool.get()?;
+/// assert_eq!(lua.globals().get::<_, Option<i32>>("leaked")?, None);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct LuaPool(Arc<PoolInner>);
+
+struct PoolInner {
+    state: Mutex<PoolState>,
+    available: Condvar,
+    size: usize,
+    libs: StdLib,
+    options: LuaOptions,
+    init: InitFn,
+}
+
+struct PoolState {
+    idle: Vec<PoolEntry>,
+    // Number of states owned by the pool (idle or checked out)
+    live: usize,
+    // Wakers of the pending async checkouts (one per checkout), in the order of arrival
+    #[cfg(feature = "async")]
+    wakers: BTreeMap<usize, Waker>,
+    #[cfg(feature = "async")]
+    next_waiter: usize,
+}
+
+enum Checkout {
+    Idle(PoolEntry),
+    // A state was lost, a new one must be initialized
+    Replace,
+}
+
+struct PoolEntry {
+    lua: Lua,
+    snapshot: Snapshot,
+}
+
+// Initial state of the globals table, captured right after initialization
+struct Snapshot {
+    // globals key -> value
+    globals: RegistryKey,
+    // table stored in globals or named registry values -> its shallow copy
+    tables: RegistryKey,
+    metatable: Option<RegistryKey>,
+    // registry name -> value
+    named_values: RegistryKey,
+    app_data: Vec<TypeId>,
+}
+
+impl LuaPool {
+    /// Creates a new pool of `size` Lua states with the **safe** subset of the standard libraries.
+    ///
+    /// Each state is initialized using the `init` function.
+    pub fn new<F>(size: usize, init: F) -> Result<LuaPool>
+    where
+        F: Fn(&Lua) -> Result<()> + MaybeSend + MaybeSync + 'static,
+    {
+        Self::new_with(size, StdLib::ALL_SAFE, LuaOptions::default(), init)
+    }
+
+    /// Creates a new pool of `size` Lua states with the specified subset of the standard libraries
+    /// and options.
+    ///
+    /// Each state is initialized using the `init` function.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn new_with<F>(size: usize, libs: StdLib, options: LuaOptions, init: F) -> Result<LuaPool>
+    where
+        F: Fn(&Lua) -> Result<()> + MaybeSend + MaybeSync + 'static,
+    {
+        let inner = PoolInner {
+            state: Mutex::new(PoolState {
+                idle: Vec::with_capacity(size),
+                live: size,
+                #[cfg(feature = "async")]
+                wakers: BTreeMap::new(),
+                #[cfg(feature = "async")]
+                next_waiter: 0,
+            }),
+            available: Condvar::new(),
+            size,
+            libs,
+            options,
+            init: Box::new(init),
+        };
+        let mut idle = Vec::with_capacity(size);
+        for _ in 0..size {
+            idle.push(inner.new_entry()?);
+        }
+        inner.lock().idle = idle;
+        Ok(LuaPool(Arc::new(inner)))
+    }
+
+    /// Checks out a Lua state from the pool, blocking the current thread until one is available.
+    ///
+    /// Returns an error if a state lost by the pool cannot be initialized again, or if no state is
+    /// available on targets that cannot block (wasm without threads support).
+    pub fn get(&self) -> Result<PooledLua> {
+        let mut state = self.0.lock();
+        loop {
+            match state.checkout(self.0.size) {
+                Some(Checkout::Idle(entry)) => return Ok(PooledLua::new(self, entry)),
+                Some(Checkout::Replace) => {
+                    drop(state);
+                    return self.replace_lost();
+                }
+                None if !CAN_BLOCK => {
+                    return Err(Error::runtime("no Lua state is available in the pool"));
+                }
+                None => state = mlua_expect!(self.0.available.wait(state), "pool mutex poisoned"),
+            }
+        }
+    }
+
+    /// Checks out a Lua state from the pool if one is immediately available.
+    pub fn try_get(&self) -> Option<PooledLua> {
+        let entry = self.0.lock().idle.pop()?;
+        Some(PooledLua::new(self, entry))
+    }
+
+    /// Asynchronously checks out a Lua state from the pool, waiting until one is available.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn get_async(&self) -> impl Future<Output = Result<PooledLua>> + '_ {
+        PoolCheckout {
+            pool: self,
+            waiter: None,
+        }
+    }
+
+    /// Returns the number of idle Lua states in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.0.lock().idle.len()
+    }
+
+    // Initializes a new state in place of a lost one
+    fn replace_lost(&self) -> Result<PooledLua> {
+        match self.0.new_entry() {
+            Ok(entry) => Ok(PooledLua::new(self, entry)),
+            Err(err) => {
+                self.0.lose_state();
+                Err(err)
+            }
+        }
+    }
+
+    fn release(&self, lua: Lua, snapshot: Snapshot) {
+        let entry = match restore_snapshot(&lua, &snapshot) {
+            Ok(()) => PoolEntry { lua, snapshot },
+            Err(_) => {
+                drop(snapshot);
+                drop(lua);
+                match self.0.new_entry() {
+                    Ok(entry) => entry,
+                    // The next checkout will try to initialize the state again
+                    Err(_) => return self.0.lose_state(),
+                }
+            }
+        };
+
+        let mut state = self.0.lock();
+        state.idle.push(entry);
+        self.0.notify_one(state);
+    }
+}
+
+#[cfg(feature = "send")]
+impl LuaPool {
+    /// Runs a chunk for every input in parallel, using the states of the pool.
+    ///
+    /// The chunk is compiled once per state and called with every input passed as arguments
+    /// (accessible using `...`), its return values are converted to `R`. One worker is started for
+    /// each idle state (or a single one if all states are checked out), and the inputs are
+    /// distributed between them as they become free. Workers run on their own threads, or on the
+    /// [rayon] global thread pool with `feature = "rayon"`.
+    ///
+    /// Returns results in the order of the inputs. Inputs that could not be processed because the
+    /// chunk cannot be compiled (or a state cannot be checked out) get the corresponding error.
+    ///
+    /// Tasks executed by the same state share its globals, the state is sanitized only once all
+    /// inputs are processed.
+    ///
+    /// Requires `feature = "send"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{LuaPool, Result};
+    /// # fn main() -> Result<()> {
+    /// let pool = LuaPool::new(4, |_| Ok(()))?;
+    /// let squares = pool.par_execute::<_, _, i64>("local x = ... return x * x", 1..=100);
+    /// assert_eq!(squares[9].as_ref().ok(), Some(&100));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [rayon]: https://docs.rs/rayon
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn par_execute<I, T, R>(&self, chunk: impl AsRef<[u8]>, inputs: I) -> Vec<Result<R>>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        T: for<'lua> IntoLuaMulti<'lua> + Send,
+        R: for<'lua> FromLuaMulti<'lua> + Send,
+    {
+        self.par_execute_with_quota(chunk, inputs, None)
+    }
+
+    /// Runs a chunk for every input in parallel, limiting resources consumed by every task.
+    ///
+    /// Every task gets its own budget with the limits of `quota`. A task that exhausts it fails
+    /// with [`Error::QuotaExceeded`] without affecting other tasks. A quota set by the `init`
+    /// function of the pool is restored afterwards.
+    ///
+    /// See [`LuaPool::par_execute`] for details.
+    ///
+    /// Requires `feature = "send"`
+    ///
+    /// [`Error::QuotaExceeded`]: crate::Error::QuotaExceeded
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn par_execute_with_quota<I, T, R>(
+        &self,
+        chunk: impl AsRef<[u8]>,
+        inputs: I,
+        quota: Option<Quota>,
+    ) -> Vec<Result<R>>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        T: for<'lua> IntoLuaMulti<'lua> + Send,
+        R: for<'lua> FromLuaMulti<'lua> + Send,
+    {
+        self.par_run(chunk.as_ref(), inputs, quota, |func, input| {
+            func.call(input)
+        })
+    }
+
+    /// Runs a chunk for every input in parallel, converting inputs and outputs using serde.
+    ///
+    /// See [`LuaPool::par_execute_with_quota`] for details.
+    ///
+    /// Requires `feature = "send"` and `feature = "serialize"`
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "send", feature = "serialize"))))]
+    pub fn par_execute_serde<I, T, R>(
+        &self,
+        chunk: impl AsRef<[u8]>,
+        inputs: I,
+        quota: Option<Quota>,
+    ) -> Vec<Result<R>>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        T: Serialize + Send,
+        R: DeserializeOwned + Send,
+    {
+        self.par_run(chunk.as_ref(), inputs, quota, |func, input| {
+            let lua = func.0.lua;
+            lua.from_value(func.call::<_, Value>(lua.to_value(&input)?)?)
+        })
+    }
+
+    /// Runs a chunk for every input in parallel without blocking the async runtime.
+    ///
+    /// Executes [`LuaPool::par_execute_with_quota`] on the [tokio] blocking thread pool, so it
+    /// must be called within a tokio runtime. Returns an error if the blocking task was cancelled
+    /// (the runtime is shutting down).
+    ///
+    /// Requires `feature = "tokio"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{LuaPool, Result};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<()> {
+    /// let pool = LuaPool::new(4, |_| Ok(()))?;
+    /// let chunk = "local x = ... return x * x";
+    /// let squares = pool.par_execute_async::<_, _, i64>(chunk, 1..=100, None).await?;
+    /// assert_eq!(squares[9].as_ref().ok(), Some(&100));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [tokio]: https://docs.rs/tokio
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn par_execute_async<I, T, R>(
+        &self,
+        chunk: impl Into<Vec<u8>>,
+        inputs: I,
+        quota: Option<Quota>,
+    ) -> Result<Vec<Result<R>>>
+    where
+        I: IntoIterator<Item = T> + Send + 'static,
+        I::IntoIter: Send,
+        T: for<'lua> IntoLuaMulti<'lua> + Send + 'static,
+        R: for<'lua> FromLuaMulti<'lua> + Send + 'static,
+    {
+        let pool = self.clone();
+        let chunk = chunk.into();
+        let task = move || pool.par_execute_with_quota(chunk, inputs, quota);
+        match tokio::task::spawn_blocking(task).await {
+            Ok(results) => Ok(results),
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => Err(Error::runtime(err)),
+        }
+    }
+
+    fn par_run<I, T, R, F>(
+        &self,
+        chunk: &[u8],
+        inputs: I,
+        quota: Option<Quota>,
+        call: F,
+    ) -> Vec<Result<R>>
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send,
+        T: Send,
+        R: Send,
+        F: for<'lua> Fn(&Function<'lua>, T) -> Result<R> + Sync,
+    {
+        let inputs = Mutex::new(inputs.into_iter().enumerate());
+        let workers = self.idle_count().max(1);
+
+        // A worker that fails to start does not take any inputs
+        let worker = || -> Result<Vec<(usize, Result<R>)>> {
+            let lua = self.get()?;
+            let func = lua.load(chunk).set_name("=par_execute").into_function()?;
+            // Keep the quota set by the init function
+            let prev_quota = quota.as_ref().and_then(|_| lua.quota());
+            let quota = quota.as_ref().map(|quota| quota.detached());
+            if let Some(quota) = &quota {
+                lua.set_quota(quota.clone());
+            }
+            let mut results = Vec::new();
+            loop {
+                let next = mlua_expect!(inputs.lock(), "inputs mutex poisoned").next();
+                let Some((i, input)) = next else { break };
+                if let Some(quota) = &quota {
+                    quota.reset();
+                }
+                results.push((i, call(&func, input)));
+            }
+            match prev_quota {
+                Some(prev_quota) => lua.set_quota(prev_quota),
+                None if quota.is_some() => lua.remove_quota(),
+                None => {}
+            }
+            Ok(results)
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let worker_results = std::thread::scope(|scope| {
+            let handles = (0..workers)
+                .map(|_| scope.spawn(worker))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| match handle.join() {
+                    Ok(results) => results,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect::<Vec<_>>()
+        });
+        // Every worker is a separate rayon task, panics are propagated by rayon
+        #[cfg(feature = "rayon")]
+        let worker_results = (0..workers)
+            .into_par_iter()
+            .with_max_len(1)
+            .map(|_| worker())
+            .collect::<Vec<_>>();
+
+        let mut output = Vec::new();
+        let mut worker_error = None;
+        for results in worker_results {
+            match results {
+                Ok(results) => output.extend(results),
+                Err(err) => worker_error = Some(err),
+            }
+        }
+
+        // Inputs left over by failed workers get their error
+        if let Some(err) = worker_error {
+            let inputs = mlua_expect!(inputs.into_inner(), "inputs mutex poisoned");
+            output.extend(inputs.map(|(i, _)| (i, Err(err.clone()))));
+        }
+        output.sort_unstable_by_key(|(i, _)| *i);
+        output.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+impl fmt::Debug for LuaPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LuaPool")
+            .field("idle", &self.idle_count())
+            .finish()
+    }
+}
+
+impl PoolInner {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        mlua_expect!(self.state.lock(), "pool mutex poisoned")
+    }
+
+    // Wakes up one waiting checkout (blocking or async), releasing the lock
+    fn notify_one(&self, state: MutexGuard<'_, PoolState>) {
+        #[cfg(feature = "async")]
+        let waker = {
+            let mut state = state;
+            state.wakers.pop_first().map(|(_, waker)| waker)
+        };
+        #[cfg(not(feature = "async"))]
+        drop(state);
+
+        self.available.notify_one();
+        #[cfg(feature = "async")]
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    // Forgets a state that could not be initialized, and lets a waiting checkout try again
+    fn lose_state(&self) {
+        let mut state = self.lock();
+        state.live -= 1;
+        self.notify_one(state);
+    }
+
+    fn new_entry(&self) -> Result<PoolEntry> {
+        let lua = Lua::new_with(self.libs, self.options.clone())?;
+        (self.init)(&lua)?;
+        let snapshot = take_snapshot(&lua)?;
+        Ok(PoolEntry { lua, snapshot })
+    }
+}
+
+impl PoolState {
+    fn checkout(&mut self, size: usize) -> Option<Checkout> {
+        if let Some(entry) = self.idle.pop() {
+            return Some(Checkout::Idle(entry));
+        }
+        if self.live < size {
+            self.live += 1;
+            return Some(Checkout::Replace);
+        }
+        None
+    }
+}
+
+/// A Lua state checked out from a [`LuaPool`].
+///
+/// The state is sanitized and returned back to the pool on drop.
+pub struct PooledLua {
+    pool: LuaPool,
+    lua: ManuallyDrop<Lua>,
+    snapshot: ManuallyDrop<Snapshot>,
+}
+
+impl PooledLua {
+    fn new(pool: &LuaPool, entry: PoolEntry) -> Self {
+        PooledLua {
+            pool: pool.clone(),
+            lua: ManuallyDrop::new(entry.lua),
+            snapshot: ManuallyDrop::new(entry.snapshot),
+        }
+    }
+}
+
+impl Deref for PooledLua {
+    type Target = Lua;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.lua
+    }
+}
+
+impl fmt::Debug for PooledLua {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PooledLua").field(&*self.lua).finish()
+    }
+}
+
+impl Drop for PooledLua {
+    fn drop(&mut self) {
+        let (lua, snapshot) = unsafe {
+            (
+                ManuallyDrop::take(&mut self.lua),
+                ManuallyDrop::take(&mut self.snapshot),
+            )
+        };
+        self.pool.release(lua, snapshot);
+    }
+}
+
+#[cfg(feature = "async")]
+struct PoolCheckout<'a> {
+    pool: &'a LuaPool,
+    // Id of the waker slot, once registered
+    waiter: Option<usize>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> Future for PoolCheckout<'a> {
+    type Output = Result<PooledLua>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.pool.0.lock();
+        let checkout = state.checkout(this.pool.0.size);
+        if checkout.is_some() {
+            if let Some(id) = this.waiter.take() {
+                state.wakers.remove(&id);
+            }
+        }
+        match checkout {
+            Some(Checkout::Idle(entry)) => Poll::Ready(Ok(PooledLua::new(this.pool, entry))),
+            Some(Checkout::Replace) => {
+                drop(state);
+                Poll::Ready(this.pool.replace_lost())
+            }
+            None => {
+                // Replace the waker registered by the previous poll, if any
+                let id = *this.waiter.get_or_insert_with(|| {
+                    state.next_waiter += 1;
+                    state.next_waiter
+                });
+                state.wakers.insert(id, cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for PoolCheckout<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.waiter else { return };
+        let mut state = self.pool.0.lock();
+        if state.wakers.remove(&id).is_none() && !state.idle.is_empty() {
+            // The checkout was woken up but cancelled, pass the state to the next one
+            self.pool.0.notify_one(state);
+        }
+    }
+}
+
+fn take_snapshot(lua: &Lua) -> Result<Snapshot> {
+    let globals = lua.globals();
+    let values = lua.create_table()?;
+    let tables = lua.create_table()?;
+    for pair in globals.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if let Value::Table(t) = &value {
+            if *t != globals {
+                tables.raw_set(t.clone(), shallow_copy(lua, t)?)?;
+            }
+        }
+        values.raw_set(key, value)?;
+    }
+    let metatable = match globals.get_metatable() {
+        Some(mt) => Some(lua.create_registry_value(mt)?),
+        None => None,
+    };
+
+    // Named registry values, including `package.loaded` (`_LOADED`)
+    let named_values = lua.create_table()?;
+    for pair in registry(lua)?.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if let (Value::String(_), Value::Table(t)) = (&key, &value) {
+            tables.raw_set(t.clone(), shallow_copy(lua, t)?)?;
+        }
+        if let Value::String(_) = key {
+            named_values.raw_set(key, value)?;
+        }
+    }
+
+    Ok(Snapshot {
+        globals: lua.create_registry_value(values)?,
+        tables: lua.create_registry_value(tables)?,
+        metatable,
+        named_values: lua.create_registry_value(named_values)?,
+        app_data: lua.app_data_types(),
+    })
+}
+
+fn restore_snapshot(lua: &Lua, snapshot: &Snapshot) -> Result<()> {
+    let globals = lua.globals();
+    let values: Table = lua.registry_value(&snapshot.globals)?;
+    let tables: Table = lua.registry_value(&snapshot.tables)?;
+    let named_values: Table = lua.registry_value(&snapshot.named_values)?;
+
+    restore_table(&globals, &values)?;
+    restore_named_values(lua, &named_values)?;
+    for pair in tables.pairs::<Table, Table>() {
+        let (table, copy) = pair?;
+        restore_table(&table, &copy)?;
+    }
+    let metatable = match &snapshot.metatable {
+        Some(key) => Some(lua.registry_value::<Table>(key)?),
+        None => None,
+    };
+    globals.set_metatable(metatable);
+    lua.retain_app_data(&snapshot.app_data)?;
+
+    lua.expire_registry_values();
+    lua.gc_collect()
+}
+
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+fn registry(lua: &Lua) -> Result<Table<'_>> {
+    let state = lua.state();
+    unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 1)?;
+
+        ffi::lua_pushvalue(state, ffi::LUA_REGISTRYINDEX);
+        Ok(Table(lua.pop_ref()))
+    }
+}
+
+// Makes the string keys of the registry match `original` (other keys are used by references)
+fn restore_named_values(lua: &Lua, original: &Table) -> Result<()> {
+    let registry = registry(lua)?;
+    let mut extra_keys = Vec::new();
+    for pair in registry.clone().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        if let Value::String(_) = key {
+            if original.raw_get::<_, Value>(key.clone())?.is_nil() {
+                extra_keys.push(key);
+            }
+        }
+    }
+    for key in extra_keys {
+        registry.raw_set(key, Value::Nil)?;
+    }
+    for pair in original.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        registry.raw_set(key, value)?;
+    }
+    Ok(())
+}
+
+fn shallow_copy<'lua>(lua: &'lua Lua, table: &Table<'lua>) -> Result<Table<'lua>> {
+    let copy = lua.create_table()?;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        copy.raw_set(key, value)?;
+    }
+    Ok(copy)
+}
+
+// Makes `table` contain exactly the same (raw) fields as `original`
+fn restore_table(table: &Table, original: &Table) -> Result<()> {
+    let mut extra_keys = Vec::new();
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        if original.raw_get::<_, Value>(key.clone())?.is_nil() {
+            extra_keys.push(key);
+        }
+    }
+    for key in extra_keys {
+        table.raw_set(key, Value::Nil)?;
+    }
+    for pair in original.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        table.raw_set(key, value)?;
+    }
+    Ok(())
+}
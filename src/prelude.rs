@@ -2,40 +2,119 @@
 
 #[doc(no_inline)]
 pub use crate::{
-    AnyUserData as LuaAnyUserData, AnyUserDataExt as LuaAnyUserDataExt, Chunk as LuaChunk,
-    Error as LuaError, ErrorContext as LuaErrorContext, ExternalError as LuaExternalError,
+    AnyUserData as LuaAnyUserData, AnyUserDataExt as LuaAnyUserDataExt, Args as LuaArgs,
+    ArgumentDetails as LuaArgumentDetails, ArithOp as LuaArithOp, AuditEvent as LuaAuditEvent,
+    BorrowedStr as LuaBorrowedStr, BorrowedValue as LuaBorrowedValue,
+    CallbackCtx as LuaCallbackCtx, CallbackNext as LuaCallbackNext, Chunk as LuaChunk,
+    CoercionPolicy as LuaCoercionPolicy, CompareOp as LuaCompareOp,
+    ConversionErrorInfo as LuaConversionErrorInfo, ConversionLimits as LuaConversionLimits,
+    Deprecation as LuaDeprecation, DeprecationPolicy as LuaDeprecationPolicy, Error as LuaError,
+    ErrorContext as LuaErrorContext, ExternalError as LuaExternalError,
     ExternalResult as LuaExternalResult, FromLua, FromLuaMulti, Function as LuaFunction,
-    FunctionInfo as LuaFunctionInfo, GCMode as LuaGCMode, Integer as LuaInteger, IntoLua,
-    IntoLuaMulti, LightUserData as LuaLightUserData, Lua, LuaOptions, MetaMethod as LuaMetaMethod,
-    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, RegistryKey as LuaRegistryKey,
-    Result as LuaResult, StdLib as LuaStdLib, String as LuaString, Table as LuaTable,
-    TableExt as LuaTableExt, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
-    Thread as LuaThread, ThreadStatus as LuaThreadStatus, UserData as LuaUserData,
-    UserDataFields as LuaUserDataFields, UserDataMetatable as LuaUserDataMetatable,
-    UserDataMethods as LuaUserDataMethods, UserDataRef as LuaUserDataRef,
-    UserDataRefMut as LuaUserDataRefMut, UserDataRegistry as LuaUserDataRegistry,
-    Value as LuaValue,
+    FunctionInfo as LuaFunctionInfo, GCMode as LuaGCMode, GlobalHandle as LuaGlobalHandle,
+    GlobalsProtection as LuaGlobalsProtection, Integer as LuaInteger, IntoLua, IntoLuaMulti,
+    LightUserData as LuaLightUserData, Loaders as LuaLoaders, Lua, LuaEnum, LuaId, LuaModule,
+    LuaOptions, MetaMethod as LuaMetaMethod, MetatableBuilder as LuaMetatableBuilder,
+    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, PcallResult as LuaPcallResult,
+    PreludeBuilder as LuaPreludeBuilder, Quota as LuaQuota, QuotaResource as LuaQuotaResource,
+    RegisteredFunction as LuaRegisteredFunction, RegisteredType as LuaRegisteredType,
+    RegistryKey as LuaRegistryKey, RemoteFunction as LuaRemoteFunction, Result as LuaResult,
+    ResumeOutcome as LuaResumeOutcome, SandboxAudit as LuaSandboxAudit, Schema as LuaSchema,
+    SchemaField as LuaSchemaField, SchemaType as LuaSchemaType,
+    SourceLocation as LuaSourceLocation, Stack as LuaStack, StackStats as LuaStackStats,
+    StdLib as LuaStdLib, String as LuaString, StringBuilder as LuaStringBuilder, Table as LuaTable,
+    TableEvent as LuaTableEvent, TableExt as LuaTableExt, TablePairs as LuaTablePairs,
+    TableProxy as LuaTableProxy, TableSequence as LuaTableSequence, Thread as LuaThread,
+    ThreadIter as LuaThreadIter, ThreadPool as LuaThreadPool, ThreadStatus as LuaThreadStatus,
+    TraceFrame as LuaTraceFrame, TracedError as LuaTracedError,
+    TypedLightUserData as LuaTypedLightUserData, TypedTable as LuaTypedTable,
+    UserData as LuaUserData, UserDataFields as LuaUserDataFields,
+    UserDataMetatable as LuaUserDataMetatable, UserDataMethods as LuaUserDataMethods,
+    UserDataRef as LuaUserDataRef, UserDataRefMut as LuaUserDataRefMut,
+    UserDataRegistry as LuaUserDataRegistry, UserDataStats as LuaUserDataStats, Value as LuaValue,
+    ValueDisplay as LuaValueDisplay, ValueId as LuaValueId, Violation as LuaViolation,
+    WeakCache as LuaWeakCache, WeakMode as LuaWeakMode,
 };
 
 #[cfg(not(feature = "luau"))]
 #[doc(no_inline)]
-pub use crate::HookTriggers as LuaHookTriggers;
+pub use crate::{
+    DenyEnv as LuaDenyEnv, EnvProvider as LuaEnvProvider, ExitAction as LuaExitAction,
+    FunctionStats as LuaFunctionStats, HookTriggers as LuaHookTriggers,
+    LoadedModules as LuaLoadedModules,
+};
 
 #[cfg(feature = "luau")]
 #[doc(no_inline)]
-pub use crate::{CoverageInfo as LuaCoverageInfo, Vector as LuaVector, VmState as LuaVmState};
+pub use crate::{
+    CoverageInfo as LuaCoverageInfo, ModuleGraph as LuaModuleGraph, Vector as LuaVector,
+    VmState as LuaVmState,
+};
+
+#[cfg(feature = "luajit")]
+#[doc(no_inline)]
+pub use crate::StringBuffer as LuaStringBuffer;
 
 #[cfg(feature = "async")]
 #[doc(no_inline)]
-pub use crate::AsyncThread as LuaAsyncThread;
+pub use crate::{
+    AsyncConcurrency as LuaAsyncConcurrency, AsyncSchedulerHooks as LuaAsyncSchedulerHooks,
+    AsyncThread as LuaAsyncThread, LuaScheduler, TaskId as LuaTaskId,
+    YieldValues as LuaYieldValues, Yielder as LuaYielder,
+};
+
+#[cfg(feature = "send")]
+#[doc(no_inline)]
+pub use crate::{
+    RemoteLua as LuaRemoteLua, UserDataGuard as LuaUserDataGuard,
+    UserDataGuardMut as LuaUserDataGuardMut,
+};
+
+#[cfg(not(feature = "send"))]
+#[doc(no_inline)]
+pub use crate::RegistrationSet as LuaRegistrationSet;
+
+#[cfg(all(feature = "async", feature = "send"))]
+#[doc(no_inline)]
+pub use crate::{LuaLockGuard, SharedLua as LuaSharedLua, SharedLuaGuard as LuaSharedLuaGuard};
+
+#[cfg(feature = "trace_events")]
+#[doc(no_inline)]
+pub use crate::TraceEvents as LuaTraceEvents;
+
+#[cfg(feature = "trace_conversions")]
+#[doc(no_inline)]
+pub use crate::{
+    ConversionDirection as LuaConversionDirection, ConversionTrace as LuaConversionTrace,
+};
+
+#[cfg(feature = "time")]
+#[doc(no_inline)]
+pub use crate::DateTime as LuaDateTime;
+
+#[cfg(feature = "signing")]
+#[doc(no_inline)]
+pub use crate::CompiledChunk as LuaCompiledChunk;
+
+#[cfg(feature = "fs")]
+#[doc(no_inline)]
+pub use crate::FsPolicy as LuaFsPolicy;
+
+#[cfg(feature = "process")]
+#[doc(no_inline)]
+pub use crate::ProcessPolicy as LuaProcessPolicy;
 
 #[cfg(feature = "serialize")]
 #[doc(no_inline)]
 pub use crate::{
     DeserializeOptions as LuaDeserializeOptions, LuaSerdeExt,
-    SerializeOptions as LuaSerializeOptions,
+    SerializeOptions as LuaSerializeOptions, UserDataSerialize as LuaUserDataSerialize,
 };
 
+#[cfg(any(feature = "json", feature = "msgpack"))]
+#[doc(no_inline)]
+pub use crate::SerializeFormat as LuaSerializeFormat;
+
 #[cfg(feature = "unstable")]
 #[doc(no_inline)]
 pub use crate::{
@@ -0,0 +1,218 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::version::LuaVersion;
+
+#[cfg(feature = "luau")]
+use crate::chunk::ChunkMode;
+#[cfg(any(feature = "luau", doc))]
+use crate::chunk::Compiler;
+
+const MAGIC: &[u8; 8] = b"MLUAPRE\x01";
+
+/// Compiles a set of Lua sources into a single precompiled prelude.
+///
+/// This helper is intended to be used from a build script (with mlua added to
+/// `[build-dependencies]` using the same Lua version as the main dependency): the prelude is
+/// compiled to bytecode at `cargo build` time, embedded into the binary, and executed by
+/// [`Lua::load_precompiled_prelude`] without parsing the sources on every state creation.
+///
+/// Lua bytecode is not portable across Lua versions and (except for Luau) depends on the target
+/// integer and pointer sizes. When cross-compiling, make sure the build host produces bytecode
+/// compatible with the target.
+///
+/// # Examples
+///
+/// In `build.rs`:
+///
+/// ```no_run
+/// # fn main() -> mlua::Result<()> {
+/// mlua::PreludeBuilder::new()
+///     .add_file("lua/prelude.lua")
+///     .add_source("version", "APP_VERSION = '1.0'")
+///     .strip(true)
+///     .write_to_out_dir("prelude.bin")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// In the application:
+///
+/// ```ignore
+/// let lua = Lua::new();
+/// lua.load_precompiled_prelude(include_bytes!(concat!(env!("OUT_DIR"), "/prelude.bin")))?;
+/// ```
+///
+/// [`Lua::load_precompiled_prelude`]: crate::Lua::load_precompiled_prelude
+#[derive(Clone, Debug, Default)]
+pub struct PreludeBuilder {
+    sources: Vec<PreludeSource>,
+    strip: bool,
+    #[cfg(feature = "luau")]
+    compiler: Option<Compiler>,
+}
+
+#[derive(Clone, Debug)]
+enum PreludeSource {
+    Code(StdString, Vec<u8>),
+    File(PathBuf),
+}
+
+impl PreludeBuilder {
+    /// Creates a new empty prelude builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chunk with the given `name` and source code.
+    ///
+    /// Chunks are executed in the order they were added.
+    #[must_use]
+    pub fn add_source(mut self, name: impl Into<StdString>, source: impl AsRef<[u8]>) -> Self {
+        let source = source.as_ref().to_vec();
+        self.sources.push(PreludeSource::Code(name.into(), source));
+        self
+    }
+
+    /// Adds a chunk read from the file at `path`.
+    ///
+    /// The chunk is named after the path prefixed with `@`, as Lua does for files.
+    #[must_use]
+    pub fn add_file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        self.sources.push(PreludeSource::File(path));
+        self
+    }
+
+    /// Sets whether to strip debug information from the bytecode.
+    ///
+    /// Default: **false**
+    #[must_use]
+    pub const fn strip(mut self, enabled: bool) -> Self {
+        self.strip = enabled;
+        self
+    }
+
+    /// Sets the Luau compiler used to compile the sources.
+    ///
+    /// Requires `feature = "luau"`
+    #[cfg(any(feature = "luau", doc))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    #[must_use]
+    pub fn set_compiler(mut self, compiler: Compiler) -> Self {
+        self.compiler = Some(compiler);
+        self
+    }
+
+    /// Compiles all chunks and returns the precompiled prelude.
+    pub fn compile(&self) -> Result<Vec<u8>> {
+        let lua = Lua::new();
+        let mut output = MAGIC.to_vec();
+        write_bytes(&mut output, LuaVersion::COMPILED.as_str().as_bytes());
+        write_len(&mut output, self.sources.len());
+        for source in &self.sources {
+            let (name, source) = match source {
+                PreludeSource::Code(name, source) => (name.clone(), source.clone()),
+                PreludeSource::File(path) => (format!("@{}", path.display()), fs::read(path)?),
+            };
+            let bytecode = self.compile_chunk(&lua, &name, &source)?;
+            write_bytes(&mut output, name.as_bytes());
+            write_bytes(&mut output, &bytecode);
+        }
+        Ok(output)
+    }
+
+    /// Compiles all chunks and writes the precompiled prelude to `file_name` in the `OUT_DIR`
+    /// directory of the running build script.
+    ///
+    /// Also instructs Cargo to rerun the build script when any of the added files change.
+    /// Returns the full path of the written file.
+    pub fn write_to_out_dir(&self, file_name: impl AsRef<Path>) -> Result<PathBuf> {
+        let out_dir = env::var_os("OUT_DIR")
+            .ok_or_else(|| Error::runtime("OUT_DIR is not set (not running in a build script?)"))?;
+        let path = Path::new(&out_dir).join(file_name);
+        fs::write(&path, self.compile()?)?;
+        for source in &self.sources {
+            if let PreludeSource::File(path) = source {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+        Ok(path)
+    }
+
+    #[cfg(not(feature = "luau"))]
+    fn compile_chunk(&self, lua: &Lua, name: &str, source: &[u8]) -> Result<Vec<u8>> {
+        let func = lua.load(source).set_name(name).into_function()?;
+        Ok(func.dump(self.strip))
+    }
+
+    #[cfg(feature = "luau")]
+    fn compile_chunk(&self, lua: &Lua, name: &str, source: &[u8]) -> Result<Vec<u8>> {
+        let mut compiler = self.compiler.clone().unwrap_or_default();
+        if self.strip {
+            compiler = compiler.set_debug_level(0);
+        }
+        let bytecode = compiler.compile(source);
+        // Surface syntax errors (encoded into the bytecode by Luau) at build time
+        (lua.load(&bytecode[..]))
+            .set_name(name)
+            .set_mode(ChunkMode::Binary)
+            .into_function()?;
+        Ok(bytecode)
+    }
+}
+
+/// Parses a precompiled prelude into a list of `(name, bytecode)` chunks.
+pub(crate) fn parse_prelude(mut data: &[u8]) -> Result<Vec<(&str, &[u8])>> {
+    let invalid = |msg: &str| Error::runtime(format!("invalid precompiled prelude: {msg}"));
+
+    if !data.starts_with(MAGIC) {
+        return Err(invalid("bad header"));
+    }
+    data = &data[MAGIC.len()..];
+    let version = read_bytes(&mut data).ok_or_else(|| invalid("truncated data"))?;
+    if version != LuaVersion::COMPILED.as_str().as_bytes() {
+        let version = StdString::from_utf8_lossy(version);
+        let msg = format!("compiled for {version}, expected {}", LuaVersion::COMPILED);
+        return Err(invalid(&msg));
+    }
+    let count = read_len(&mut data).ok_or_else(|| invalid("truncated data"))?;
+    let mut chunks = Vec::new();
+    for _ in 0..count {
+        let name = read_bytes(&mut data).ok_or_else(|| invalid("truncated data"))?;
+        let name = std::str::from_utf8(name).map_err(|_| invalid("bad chunk name"))?;
+        let bytecode = read_bytes(&mut data).ok_or_else(|| invalid("truncated data"))?;
+        chunks.push((name, bytecode));
+    }
+    if !data.is_empty() {
+        return Err(invalid("trailing data"));
+    }
+    Ok(chunks)
+}
+
+fn write_len(output: &mut Vec<u8>, len: usize) {
+    output.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn write_bytes(output: &mut Vec<u8>, bytes: &[u8]) {
+    write_len(output, bytes.len());
+    output.extend_from_slice(bytes);
+}
+
+fn read_len(data: &mut &[u8]) -> Option<usize> {
+    let len = data.get(..4)?;
+    let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+    *data = &data[4..];
+    Some(len)
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_len(data)?;
+    let bytes = data.get(..len)?;
+    *data = &data[len..];
+    Some(bytes)
+}
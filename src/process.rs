@@ -0,0 +1,337 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::string::String as StdString;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+
+// Interval between checks whether a child process with timeout has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// Time to wait for the output streams to be closed once the program has exited (they can be kept
+// open by its descendants)
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+type ArgValidator = Arc<dyn Fn(&str, &[StdString]) -> Result<()> + Send + Sync>;
+
+// Captured bytes of an output stream and truncation flag
+type Captured = Arc<Mutex<(Vec<u8>, bool)>>;
+
+// Thread reading an output stream
+struct Reader {
+    handle: JoinHandle<io::Result<()>>,
+    captured: Captured,
+}
+
+/// Policy of the `process` module created by [`Lua::create_process_module`].
+///
+/// Scripts can run only explicitly allowed programs. By default no programs are allowed.
+///
+/// Requires `feature = "process"`
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ProcessPolicy {
+    /// Programs that scripts are allowed to run.
+    ///
+    /// A program requested by a script must match one of the entries exactly, so using absolute
+    /// paths is recommended to avoid depending on `PATH` lookup.
+    ///
+    /// Default: **empty**
+    pub allowed_programs: Vec<PathBuf>,
+
+    /// Maximum time a program is allowed to run before being killed.
+    ///
+    /// On Unix, programs are started in a new process group, and the whole group is killed.
+    ///
+    /// Default: **none**
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of bytes captured from each of the `stdout` and `stderr` streams.
+    ///
+    /// The rest of the output is discarded. Output written after the program has exited (by its
+    /// descendants that keep the streams open) is discarded too.
+    ///
+    /// Default: **1 MiB**
+    pub max_output_size: usize,
+
+    arg_validator: Option<ArgValidator>,
+}
+
+impl Default for ProcessPolicy {
+    fn default() -> Self {
+        ProcessPolicy::new()
+    }
+}
+
+impl fmt::Debug for ProcessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProcessPolicy")
+            .field("allowed_programs", &self.allowed_programs)
+            .field("timeout", &self.timeout)
+            .field("max_output_size", &self.max_output_size)
+            .field("arg_validator", &self.arg_validator.is_some())
+            .finish()
+    }
+}
+
+impl ProcessPolicy {
+    /// Returns a new policy which does not allow running any program.
+    pub const fn new() -> Self {
+        ProcessPolicy {
+            allowed_programs: Vec::new(),
+            timeout: None,
+            max_output_size: 1024 * 1024,
+            arg_validator: None,
+        }
+    }
+
+    /// Adds `program` to the [`allowed_programs`] list.
+    ///
+    /// [`allowed_programs`]: #structfield.allowed_programs
+    #[must_use]
+    pub fn allow_program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.allowed_programs.push(program.into());
+        self
+    }
+
+    /// Sets [`timeout`] option.
+    ///
+    /// [`timeout`]: #structfield.timeout
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets [`max_output_size`] option.
+    ///
+    /// [`max_output_size`]: #structfield.max_output_size
+    #[must_use]
+    pub const fn max_output_size(mut self, size: usize) -> Self {
+        self.max_output_size = size;
+        self
+    }
+
+    /// Sets a function to validate arguments before running an (allowed) program.
+    ///
+    /// The function receives the program name and arguments, and returns an error to reject them.
+    #[must_use]
+    pub fn arg_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&str, &[StdString]) -> Result<()> + Send + Sync + 'static,
+    {
+        self.arg_validator = Some(Arc::new(validator));
+        self
+    }
+
+    fn check(&self, program: &str, args: &[StdString]) -> Result<()> {
+        if !self
+            .allowed_programs
+            .iter()
+            .any(|p| p.as_os_str() == program)
+        {
+            return Err(Error::RuntimeError(format!(
+                "program '{program}' is not allowed"
+            )));
+        }
+        match &self.arg_validator {
+            Some(validator) => validator(program, args),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Output {
+    status: Option<i32>,
+    success: bool,
+    timed_out: bool,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    truncated: bool,
+}
+
+pub(crate) fn create_process_module(lua: &Lua, policy: ProcessPolicy) -> Result<Table> {
+    let module = lua.create_table_with_capacity(0, 1)?;
+
+    let run = lua.create_function(
+        move |lua, (program, args, opts): (StdString, Option<Vec<StdString>>, Option<Table>)| {
+            let args = args.unwrap_or_default();
+            policy.check(&program, &args)?;
+            let stdin = match &opts {
+                Some(opts) => opts.raw_get::<_, Option<String>>("stdin")?,
+                None => None,
+            };
+
+            let output = run(
+                &policy,
+                &program,
+                &args,
+                stdin.as_ref().map(|s| s.as_bytes()),
+            )
+            .map_err(|err| {
+                Error::RuntimeError(format!("failed to run program '{program}': {err}"))
+            })?;
+
+            let result = lua.create_table_with_capacity(0, 6)?;
+            result.raw_set("status", output.status)?;
+            result.raw_set("success", output.success)?;
+            result.raw_set("timed_out", output.timed_out)?;
+            result.raw_set("stdout", lua.create_string(&output.stdout)?)?;
+            result.raw_set("stderr", lua.create_string(&output.stderr)?)?;
+            result.raw_set("truncated", output.truncated)?;
+            Ok(result)
+        },
+    )?;
+    module.raw_set("run", run)?;
+
+    Ok(module)
+}
+
+fn run(
+    policy: &ProcessPolicy,
+    program: &str,
+    args: &[StdString],
+    stdin: Option<&[u8]>,
+) -> io::Result<Output> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Start a new process group to kill descendants of the program on timeout
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+
+    let max_size = policy.max_output_size;
+    let stdout = child.stdout.take().map(|r| read_capped(r, max_size));
+    let stderr = child.stderr.take().map(|r| read_capped(r, max_size));
+    let writer = match (child.stdin.take(), stdin) {
+        (Some(mut pipe), Some(input)) => {
+            // Write in background to not block on a program that does not read the input
+            // (errors are ignored, the program may exit early)
+            let input = input.to_vec();
+            Some(thread::spawn(move || {
+                let _ = pipe.write_all(&input);
+            }))
+        }
+        _ => None,
+    };
+
+    let (status, timed_out) = wait(&mut child, policy.timeout)?;
+
+    // Threads blocked on streams kept open by descendants of the program are left behind
+    let deadline = Instant::now() + OUTPUT_TIMEOUT;
+    if let Some(writer) = writer {
+        let _ = join_until(writer, deadline);
+    }
+    let join = |reader: Option<Reader>| -> io::Result<(Vec<u8>, bool)> {
+        let Some(reader) = reader else {
+            return Ok((Vec::new(), false));
+        };
+        let finished = match join_until(reader.handle, deadline) {
+            Some(Ok(res)) => res.map(|_| true)?,
+            Some(Err(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "output reader panicked",
+                ))
+            }
+            None => false,
+        };
+        let mut captured = mlua_expect!(reader.captured.lock(), "output mutex poisoned");
+        let (output, truncated) = &mut *captured;
+        Ok((std::mem::take(output), *truncated || !finished))
+    };
+    let (stdout, stdout_truncated) = join(stdout)?;
+    let (stderr, stderr_truncated) = join(stderr)?;
+
+    Ok(Output {
+        status: status.code(),
+        success: status.success() && !timed_out,
+        timed_out,
+        stdout,
+        stderr,
+        truncated: stdout_truncated || stderr_truncated,
+    })
+}
+
+fn wait(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> io::Result<(std::process::ExitStatus, bool)> {
+    // Overflowing timeouts wait without a deadline
+    let deadline = match timeout.and_then(|timeout| Instant::now().checked_add(timeout)) {
+        Some(deadline) => deadline,
+        None => return Ok((child.wait()?, false)),
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            // The process may have exited in the meantime
+            kill(child);
+            return Ok((child.wait()?, true));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Kills the child process and its process group
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        // The group id is the id of the child (see `run`)
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+// Joins the thread if it finishes before the deadline
+fn join_until<T>(handle: JoinHandle<T>, deadline: Instant) -> Option<thread::Result<T>> {
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Some(handle.join())
+}
+
+// Reads the stream to the end, keeping at most `max_size` bytes
+fn read_capped<R: Read + Send + 'static>(mut reader: R, max_size: usize) -> Reader {
+    let captured = Captured::default();
+    let output = captured.clone();
+    let handle = thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            let mut output = mlua_expect!(output.lock(), "output mutex poisoned");
+            let (output, truncated) = &mut *output;
+            let room = max_size - output.len();
+            if n > room {
+                *truncated = true;
+            }
+            output.extend_from_slice(&buf[..n.min(room)]);
+        }
+    });
+    Reader { handle, captured }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+
+/// A resource limited by a [`Quota`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum QuotaResource {
+    /// Number of Rust callback invocations.
+    Calls,
+    /// Memory allocated by the Lua state (in bytes).
+    Memory,
+    /// Number of executed VM instructions.
+    Instructions,
+}
+
+impl fmt::Display for QuotaResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuotaResource::Calls => write!(f, "calls"),
+            QuotaResource::Memory => write!(f, "memory"),
+            QuotaResource::Instructions => write!(f, "instructions"),
+        }
+    }
+}
+
+/// Budgets of resources that Lua code is allowed to consume.
+///
+/// A quota can be attached to a [`Lua`] instance using [`Lua::set_quota`] or to a single
+/// coroutine using [`Thread::set_quota`]. Once any budget is exhausted, the running code fails
+/// with [`Error::QuotaExceeded`].
+///
+/// Clones of a quota share the consumed resources, so a clone kept by the host can be used to
+/// inspect or [`reset`] the usage.
+///
+/// Budgets are checked at the following points:
+/// - calls and memory, on every Rust callback invocation
+/// - instructions and memory, every [`Quota::INSTRUCTIONS_STEP`] instructions (in Luau, on every
+///   VM interrupt, which happens at function calls and loop iterations, each counted as one
+///   instruction)
+///
+/// Memory is measured for the whole Lua state, even if the quota is attached to a coroutine.
+/// The memory budget of a quota set using [`Lua::set_quota`] is also enforced on every
+/// allocation (except in LuaJIT), while the budget of a coroutine quota is checked only at the
+/// points above, so the limit may be exceeded in between.
+///
+/// In LuaJIT, compiled code does not trigger hooks, so the JIT should be disabled (`jit.off()`)
+/// for the instructions budget to be enforced.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Error, Lua, Quota, QuotaResource, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// # #[cfg(feature = "luajit")]
+/// # lua.load("jit.off()").exec()?;
+/// let quota = Quota::new().max_instructions(100_000);
+/// lua.set_quota(quota.clone());
+///
+/// let err = lua.load("while true do end").exec().unwrap_err();
+/// assert!(err.to_string().contains("instructions quota of 100000 exceeded"));
+/// assert!(quota.instructions() >= 100_000);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Thread::set_quota`]: crate::Thread::set_quota
+/// [`reset`]: Quota::reset
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    max_calls: Option<u64>,
+    max_memory: Option<usize>,
+    max_instructions: Option<u64>,
+    usage: Arc<QuotaUsage>,
+}
+
+#[derive(Debug, Default)]
+struct QuotaUsage {
+    calls: AtomicU64,
+    instructions: AtomicU64,
+}
+
+impl Quota {
+    /// Number of instructions executed between checks of the instructions budget.
+    pub const INSTRUCTIONS_STEP: u32 = 1000;
+
+    /// Creates a new quota without any limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of Rust callback invocations.
+    #[must_use]
+    pub fn max_calls(mut self, limit: u64) -> Self {
+        self.max_calls = Some(limit);
+        self
+    }
+
+    /// Sets the maximum amount of memory (in bytes) used by the Lua state.
+    #[must_use]
+    pub fn max_memory(mut self, limit: usize) -> Self {
+        self.max_memory = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of executed VM instructions.
+    ///
+    /// In Luau, instructions are not counted individually: every VM interrupt (function call or
+    /// loop iteration) is counted as one instruction.
+    #[must_use]
+    pub fn max_instructions(mut self, limit: u64) -> Self {
+        self.max_instructions = Some(limit);
+        self
+    }
+
+    /// Returns the number of Rust callback invocations so far.
+    pub fn calls(&self) -> u64 {
+        self.usage.calls.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of executed VM instructions so far.
+    pub fn instructions(&self) -> u64 {
+        self.usage.instructions.load(Ordering::Relaxed)
+    }
+
+    /// Resets the consumed resources, eg. to start a new rate limiting period.
+    pub fn reset(&self) {
+        self.usage.calls.store(0, Ordering::Relaxed);
+        self.usage.instructions.store(0, Ordering::Relaxed);
+    }
+
+    #[cfg(not(feature = "luajit"))]
+    pub(crate) fn memory_limit(&self) -> Option<usize> {
+        self.max_memory
+    }
+
+    // Returns a quota with the same limits that does not share the consumed resources
+    #[cfg(feature = "send")]
+    pub(crate) fn detached(&self) -> Quota {
+        Quota {
+            usage: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn add_call(&self, lua: &Lua) -> Result<()> {
+        let calls = self.usage.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        check_budget(QuotaResource::Calls, calls, self.max_calls)?;
+        self.check_memory(lua)
+    }
+
+    pub(crate) fn add_instructions(&self, count: u64, lua: &Lua) -> Result<()> {
+        let instructions = self.usage.instructions.fetch_add(count, Ordering::Relaxed) + count;
+        check_budget(
+            QuotaResource::Instructions,
+            instructions,
+            self.max_instructions,
+        )?;
+        self.check_memory(lua)
+    }
+
+    fn check_memory(&self, lua: &Lua) -> Result<()> {
+        let limit = self.max_memory.map(|limit| limit as u64);
+        check_budget(QuotaResource::Memory, lua.used_memory() as u64, limit)
+    }
+}
+
+fn check_budget(resource: QuotaResource, used: u64, limit: Option<u64>) -> Result<()> {
+    match limit {
+        Some(limit) if used > limit => Err(Error::QuotaExceeded { resource, limit }),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,205 @@
+use std::string::String as StdString;
+
+use regex::bytes::{Captures, Regex, RegexBuilder};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+use crate::userdata::{MetaMethod, UserData, UserDataMethods};
+
+// Compiled regular expression, matches bytes of Lua strings
+struct LuaRegex(Regex);
+
+// Converts optional 1-based (possibly negative) init position to a byte offset, like `string.find`
+fn start_offset(init: Option<i64>, len: usize) -> Option<usize> {
+    let len = len as i64;
+    let init = match init.unwrap_or(1) {
+        init if init > 0 => init - 1,
+        0 => 0,
+        init => (len + init).max(0),
+    };
+    (init <= len).then_some(init as usize)
+}
+
+fn captures_table<'lua>(lua: &'lua Lua, re: &Regex, caps: &Captures) -> Result<Table<'lua>> {
+    let table = lua.create_table_with_capacity(caps.len(), 0)?;
+    for (i, name) in re.capture_names().enumerate() {
+        if let Some(m) = caps.get(i) {
+            let s = lua.create_string(m.as_bytes())?;
+            table.raw_set(i, &s)?;
+            if let Some(name) = name {
+                table.raw_set(name, s)?;
+            }
+        }
+    }
+    Ok(table)
+}
+
+// Position of an iterator over successive non-overlapping matches
+#[derive(Default)]
+struct MatchIter {
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl MatchIter {
+    // Calls `find` (which returns a value and the match bounds) starting from the current position
+    fn next<T>(
+        &mut self,
+        len: usize,
+        find: impl Fn(usize) -> Option<(T, usize, usize)>,
+    ) -> Option<T> {
+        while self.pos <= len {
+            let (value, start, end) = find(self.pos)?;
+            if start == end && Some(end) == self.last_end {
+                // Skip an empty match immediately following the previous match
+                self.pos = end + 1;
+                continue;
+            }
+            self.pos = end;
+            self.last_end = Some(end);
+            return Some(value);
+        }
+        None
+    }
+}
+
+impl UserData for LuaRegex {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("is_match", |_, this, (s, init): (String, Option<i64>)| {
+            let s = s.as_bytes();
+            Ok(match start_offset(init, s.len()) {
+                Some(start) => this.0.is_match_at(s, start),
+                None => false,
+            })
+        });
+
+        // Returns start and end (inclusive) positions of the first match, like `string.find`
+        methods.add_method("find", |_, this, (s, init): (String, Option<i64>)| {
+            let s = s.as_bytes();
+            let m = start_offset(init, s.len()).and_then(|start| this.0.find_at(s, start));
+            Ok(match m {
+                Some(m) => (Some(m.start() + 1), Some(m.end())),
+                None => (None, None),
+            })
+        });
+
+        methods.add_method("match", |lua, this, (s, init): (String, Option<i64>)| {
+            let s = s.as_bytes();
+            match start_offset(init, s.len()).and_then(|start| this.0.find_at(s, start)) {
+                Some(m) => lua.create_string(m.as_bytes()).map(Some),
+                None => Ok(None),
+            }
+        });
+
+        methods.add_method("captures", |lua, this, (s, init): (String, Option<i64>)| {
+            let s = s.as_bytes();
+            let start = start_offset(init, s.len());
+            match start.and_then(|start| this.0.captures_at(s, start)) {
+                Some(caps) => captures_table(lua, &this.0, &caps).map(Some),
+                None => Ok(None),
+            }
+        });
+
+        methods.add_method("gmatch", |lua, this, s: String| {
+            let re = this.0.clone();
+            let s = s.as_bytes().to_vec();
+            let mut iter = MatchIter::default();
+            lua.create_function_mut(move |lua, ()| {
+                let m = iter.next(s.len(), |pos| {
+                    re.find_at(&s, pos).map(|m| (m, m.start(), m.end()))
+                });
+                match m {
+                    Some(m) => lua.create_string(m.as_bytes()).map(Some),
+                    None => Ok(None),
+                }
+            })
+        });
+
+        methods.add_method("gcaptures", |lua, this, s: String| {
+            let re = this.0.clone();
+            let s = s.as_bytes().to_vec();
+            let mut iter = MatchIter::default();
+            lua.create_function_mut(move |lua, ()| {
+                let caps = iter.next(s.len(), |pos| {
+                    let caps = re.captures_at(&s, pos)?;
+                    let m = caps.get(0)?;
+                    Some((caps, m.start(), m.end()))
+                });
+                match caps {
+                    Some(caps) => captures_table(lua, &re, &caps).map(Some),
+                    None => Ok(None),
+                }
+            })
+        });
+
+        // Replaces matches using `$1` / `$name` syntax, all of them unless `limit` is given
+        methods.add_method(
+            "replace",
+            |lua, this, (s, rep, limit): (String, String, Option<usize>)| {
+                let res = this
+                    .0
+                    .replacen(s.as_bytes(), limit.unwrap_or(0), rep.as_bytes());
+                lua.create_string(&*res)
+            },
+        );
+
+        methods.add_method("split", |lua, this, (s, limit): (String, Option<usize>)| {
+            let s = s.as_bytes();
+            let parts = match limit {
+                Some(limit) => this.0.splitn(s, limit).collect::<Vec<_>>(),
+                None => this.0.split(s).collect(),
+            };
+            let table = lua.create_table_with_capacity(parts.len(), 0)?;
+            for part in parts {
+                table.raw_push(lua.create_string(part)?)?;
+            }
+            Ok(table)
+        });
+
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            Ok(this.0.as_str().to_string())
+        });
+    }
+}
+
+pub(crate) fn create_regex_module(lua: &Lua) -> Result<Table> {
+    let module = lua.create_table_with_capacity(0, 2)?;
+
+    module.raw_set(
+        "compile",
+        lua.create_function(|_, (pattern, opts): (StdString, Option<Table>)| {
+            let mut builder = RegexBuilder::new(&pattern);
+            if let Some(opts) = opts {
+                let flag = |name| opts.raw_get::<_, Option<bool>>(name);
+                if let Some(enabled) = flag("case_insensitive")? {
+                    builder.case_insensitive(enabled);
+                }
+                if let Some(enabled) = flag("multi_line")? {
+                    builder.multi_line(enabled);
+                }
+                if let Some(enabled) = flag("dot_matches_new_line")? {
+                    builder.dot_matches_new_line(enabled);
+                }
+                if let Some(enabled) = flag("ignore_whitespace")? {
+                    builder.ignore_whitespace(enabled);
+                }
+                if let Some(enabled) = flag("unicode")? {
+                    builder.unicode(enabled);
+                }
+            }
+            let re = builder
+                .build()
+                .map_err(|err| Error::RuntimeError(err.to_string()))?;
+            Ok(LuaRegex(re))
+        })?,
+    )?;
+
+    module.raw_set(
+        "escape",
+        lua.create_function(|_, s: StdString| Ok(regex::escape(&s)))?,
+    )?;
+
+    Ok(module)
+}
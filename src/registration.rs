@@ -0,0 +1,259 @@
+use std::any::TypeId;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::types::{Callback, Integer};
+use crate::userdata::UserData;
+use crate::userdata_impl::UserDataRegistry;
+
+#[cfg(feature = "async")]
+use {crate::types::AsyncCallback, crate::value::MultiValue};
+
+// Userdata type with registry built ahead of time, which can build its metatable in any state
+pub(crate) trait PreparedUserData {
+    // Builds the metatable in `lua`, returning its registry reference
+    unsafe fn register(&self, lua: &Lua) -> Result<Integer>;
+}
+
+pub(crate) type PreparedRef = Rc<dyn PreparedUserData>;
+
+type SharedCallbacks = Vec<(String, Rc<Callback<'static, 'static>>)>;
+
+#[cfg(feature = "async")]
+type SharedAsyncCallbacks = Vec<(String, Rc<AsyncCallback<'static, 'static>>)>;
+
+// Callbacks of `UserDataRegistry`, shared by all states the type is imported into
+struct SharedRegistry<T: 'static> {
+    fields: SharedCallbacks,
+    field_getters: SharedCallbacks,
+    field_setters: SharedCallbacks,
+    meta_fields: SharedCallbacks,
+    methods: SharedCallbacks,
+    #[cfg(feature = "async")]
+    async_methods: SharedAsyncCallbacks,
+    meta_methods: SharedCallbacks,
+    #[cfg(feature = "async")]
+    async_meta_methods: SharedAsyncCallbacks,
+    nargs: Vec<(String, Option<usize>)>,
+    _type: PhantomData<T>,
+}
+
+fn share(callbacks: Vec<(String, Callback<'static, 'static>)>) -> SharedCallbacks {
+    (callbacks.into_iter())
+        .map(|(name, f)| (name, Rc::new(f)))
+        .collect()
+}
+
+#[cfg(feature = "async")]
+fn share_async(callbacks: Vec<(String, AsyncCallback<'static, 'static>)>) -> SharedAsyncCallbacks {
+    (callbacks.into_iter())
+        .map(|(name, f)| (name, Rc::new(f)))
+        .collect()
+}
+
+// Callbacks are 'static and called only with the `Lua` of the metatable they are pushed to, so the
+// lifetime can be erased the same way as in `deprecate_callback`
+fn unshare<'lua>(callbacks: &SharedCallbacks) -> Vec<(String, Callback<'lua, 'static>)> {
+    (callbacks.iter())
+        .map(|(name, f)| {
+            let f = f.clone();
+            let callback: Callback<'lua, 'static> = Box::new(move |lua: &'lua Lua, nargs| {
+                f(unsafe { mem::transmute::<&Lua, &'static Lua>(lua) }, nargs)
+            });
+            (name.clone(), callback)
+        })
+        .collect()
+}
+
+#[cfg(feature = "async")]
+fn unshare_async<'lua>(
+    callbacks: &SharedAsyncCallbacks,
+) -> Vec<(String, AsyncCallback<'lua, 'static>)> {
+    (callbacks.iter())
+        .map(|(name, f)| {
+            let f = f.clone();
+            let callback: AsyncCallback<'lua, 'static> =
+                Box::new(move |lua: &'lua Lua, args: MultiValue<'lua>| {
+                    let lua = unsafe { mem::transmute::<&Lua, &'static Lua>(lua) };
+                    let args = unsafe { mem::transmute::<MultiValue, MultiValue<'static>>(args) };
+                    f(lua, args)
+                });
+            (name.clone(), callback)
+        })
+        .collect()
+}
+
+impl<T: 'static> PreparedUserData for SharedRegistry<T> {
+    unsafe fn register(&self, lua: &Lua) -> Result<Integer> {
+        let mut registry = UserDataRegistry::<T>::new();
+        registry.fields = unshare(&self.fields);
+        registry.field_getters = unshare(&self.field_getters);
+        registry.field_setters = unshare(&self.field_setters);
+        registry.meta_fields = unshare(&self.meta_fields);
+        registry.methods = unshare(&self.methods);
+        registry.meta_methods = unshare(&self.meta_methods);
+        #[cfg(feature = "async")]
+        {
+            registry.async_methods = unshare_async(&self.async_methods);
+            registry.async_meta_methods = unshare_async(&self.async_meta_methods);
+        }
+        registry.nargs = self.nargs.clone();
+        lua.register_userdata_metatable(registry)
+    }
+}
+
+// Builds the registry of `T` once, using `f` to fill it
+fn prepare<T: 'static>(f: impl FnOnce(&mut UserDataRegistry<'static, T>)) -> PreparedRef {
+    let mut registry = UserDataRegistry::new();
+    f(&mut registry);
+    Rc::new(SharedRegistry::<T> {
+        fields: share(registry.fields),
+        field_getters: share(registry.field_getters),
+        field_setters: share(registry.field_setters),
+        meta_fields: share(registry.meta_fields),
+        methods: share(registry.methods),
+        #[cfg(feature = "async")]
+        async_methods: share_async(registry.async_methods),
+        meta_methods: share(registry.meta_methods),
+        #[cfg(feature = "async")]
+        async_meta_methods: share_async(registry.async_meta_methods),
+        nargs: registry.nargs,
+        _type: PhantomData,
+    })
+}
+
+// Builds the registry of a `UserData` type once
+pub(crate) fn prepare_userdata<T: UserData + 'static>() -> PreparedRef {
+    prepare::<T>(|registry| {
+        T::add_fields(registry);
+        T::add_methods(registry);
+    })
+}
+
+/// A set of userdata types with registries built ahead of time, to import into Lua states.
+///
+/// Registering a type runs its `add_fields`/`add_methods` (or the registration function) and
+/// creates a callback for every field and method. A set does this once per type, and every state
+/// it is imported into using [`Lua::import_registrations`] shares the prebuilt callbacks. The
+/// metatable of an imported type is built from them on first use of the type in the state, so
+/// states (e.g. created by a [`LuaPool`]) don't pay the registration cost for every type.
+///
+/// The callbacks, and any state captured by them (such as deprecation warnings issued once, or
+/// [`AsyncConcurrency`] limits), are shared between states. For this reason sets are not
+/// available with `feature = "send"`.
+///
+/// [`LuaPool`]: crate::LuaPool
+/// [`AsyncConcurrency`]: crate::AsyncConcurrency
+#[cfg_attr(docsrs, doc(cfg(not(feature = "send"))))]
+#[derive(Clone, Default)]
+pub struct RegistrationSet {
+    types: Vec<(TypeId, PreparedRef)>,
+}
+
+impl RegistrationSet {
+    /// Creates an empty set of registrations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a [`UserData`] type `T`, building its registry.
+    pub fn add<T: UserData + 'static>(mut self) -> Self {
+        self.insert(TypeId::of::<T>(), prepare_userdata::<T>());
+        self
+    }
+
+    /// Adds a type `T`, building its registry using a function.
+    ///
+    /// The function is called once, similar to [`Lua::register_userdata_type`].
+    pub fn add_with<T: 'static>(mut self, f: impl FnOnce(&mut UserDataRegistry<T>)) -> Self {
+        self.insert(TypeId::of::<T>(), prepare::<T>(f));
+        self
+    }
+
+    /// Returns the number of types in the set.
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Returns `true` if the set has no types.
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    // Replaces any existing registration of the same type
+    fn insert(&mut self, type_id: TypeId, prepared: PreparedRef) {
+        match self.types.iter_mut().find(|(id, _)| *id == type_id) {
+            Some(entry) => entry.1 = prepared,
+            None => self.types.push((type_id, prepared)),
+        }
+    }
+}
+
+impl fmt::Debug for RegistrationSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RegistrationSet")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Lua {
+    /// Exports the userdata types registered in this Lua instance.
+    ///
+    /// The set includes types imported using [`Lua::import_registrations`], and [`UserData`]
+    /// types used in this instance, whose registries are built once for the set. Types
+    /// registered using [`Lua::register_userdata_type`] are not included, since their
+    /// registration function is consumed.
+    ///
+    /// Requires `feature = "send"` to be disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// struct Counter(i64);
+    ///
+    /// impl UserData for Counter {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("get", |_, this, ()| Ok(this.0));
+    ///     }
+    /// }
+    ///
+    /// let lua = Lua::new();
+    /// lua.create_userdata(Counter(0))?;
+    /// let registrations = lua.export_registrations();
+    ///
+    /// let lua2 = Lua::new();
+    /// lua2.import_registrations(&registrations);
+    /// lua2.globals().set("counter", Counter(5))?;
+    /// assert_eq!(lua2.load("counter:get()").eval::<i64>()?, 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "send"))))]
+    pub fn export_registrations(&self) -> RegistrationSet {
+        let mut set = RegistrationSet::new();
+        for (type_id, prepared) in self.exported_userdata() {
+            set.insert(type_id, prepared);
+        }
+        set
+    }
+
+    /// Imports userdata types from a set into this Lua instance.
+    ///
+    /// Metatables of imported types are built from their shared callbacks on first use. Types
+    /// which are already registered in this instance are left untouched.
+    ///
+    /// Requires `feature = "send"` to be disabled.
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "send"))))]
+    pub fn import_registrations(&self, registrations: &RegistrationSet) {
+        for (type_id, prepared) in &registrations.types {
+            self.import_userdata(*type_id, prepared.clone());
+        }
+    }
+}
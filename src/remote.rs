@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::types::RegistryKey;
+use crate::value::{FromLuaMulti, IntoLuaMulti};
+
+#[cfg(feature = "send")]
+use crate::lua_id::LuaId;
+
+// Call of a remote function, run by the thread owning the `Lua`
+pub(crate) type RemoteCall = Box<dyn FnOnce(&Lua) + Send>;
+
+pub(crate) type RemoteCallQueue = Arc<Mutex<VecDeque<RemoteCall>>>;
+
+/// Handle to a Lua function that can be called from any thread.
+///
+/// It's `Send + Sync` regardless of the `send` feature: calls are queued and run by the thread
+/// owning the [`Lua`] instance when it calls [`Lua::run_remote_calls`], while the calling thread
+/// waits for the result. Arguments and results are converted on the owning thread, so they must
+/// be `Send` types that don't borrow the Lua state (eg. `String` rather than `mlua::String`).
+///
+/// Created by [`Function::into_remote`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let add = lua.load("function(a, b) return a + b end").eval::<mlua::Function>()?;
+/// let add = add.into_remote()?;
+///
+/// let worker = std::thread::spawn(move || add.call::<_, i64>((1, 2)));
+/// while !worker.is_finished() {
+///     lua.run_remote_calls();
+/// }
+/// assert_eq!(worker.join().unwrap()?, 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RemoteFunction {
+    key: Arc<RegistryKey>,
+    queue: Weak<Mutex<VecDeque<RemoteCall>>>,
+}
+
+impl fmt::Debug for RemoteFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RemoteFunction").field(&self.key).finish()
+    }
+}
+
+impl RemoteFunction {
+    pub(crate) fn new(lua: &Lua, key: RegistryKey) -> Self {
+        RemoteFunction {
+            key: Arc::new(key),
+            queue: Arc::downgrade(lua.remote_calls()),
+        }
+    }
+
+    /// Calls the function, passing `args` as function arguments, and waits for the result.
+    ///
+    /// Must not be called from the thread owning the Lua instance, as the call would never run.
+    /// Returns an error if the Lua instance is dropped before running the call.
+    pub fn call<A, R>(&self, args: A) -> Result<R>
+    where
+        A: for<'lua> IntoLuaMulti<'lua> + Send + 'static,
+        R: for<'lua> FromLuaMulti<'lua> + Send + 'static,
+    {
+        let key = self.key.clone();
+        call_remote(&self.queue, move |lua| {
+            (lua.registry_value::<Function>(&key)).and_then(|func| func.call(args))
+        })
+    }
+}
+
+/// Handle to a Lua state that can be used from any thread, returned by [`Lua::find`].
+///
+/// Like [`RemoteFunction`], it's `Send + Sync` and does not give direct access to the state:
+/// closures are queued and run by the thread owning the [`Lua`] instance when it calls
+/// [`Lua::run_remote_calls`].
+///
+/// Requires `feature = "send"`
+#[cfg(feature = "send")]
+#[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+#[derive(Clone)]
+pub struct RemoteLua {
+    id: LuaId,
+    queue: Weak<Mutex<VecDeque<RemoteCall>>>,
+}
+
+#[cfg(feature = "send")]
+impl fmt::Debug for RemoteLua {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("RemoteLua").field(&self.id).finish()
+    }
+}
+
+#[cfg(feature = "send")]
+impl RemoteLua {
+    pub(crate) fn new(id: LuaId, queue: Weak<Mutex<VecDeque<RemoteCall>>>) -> Self {
+        RemoteLua { id, queue }
+    }
+
+    /// Returns the identifier of the Lua state.
+    pub fn id(&self) -> LuaId {
+        self.id
+    }
+
+    pub(crate) fn is_live(&self) -> bool {
+        self.queue.strong_count() > 0
+    }
+
+    /// Queues `f` to be run with the Lua instance, without waiting for it.
+    ///
+    /// Returns an error if the Lua instance has been dropped.
+    pub fn post<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&Lua) + Send + 'static,
+    {
+        let queue = self.queue.upgrade().ok_or_else(lua_dropped)?;
+        mlua_expect!(queue.lock(), "remote call queue poisoned").push_back(Box::new(f));
+        Ok(())
+    }
+
+    /// Runs `f` with the Lua instance and waits for the result.
+    ///
+    /// Must not be called from the thread owning the Lua instance, as the call would never run.
+    /// Returns an error if the Lua instance is dropped before running `f`.
+    pub fn call<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Lua) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        call_remote(&self.queue, f)
+    }
+}
+
+// Queues `f` to the thread owning the Lua instance and waits for the result
+fn call_remote<F, R>(queue: &Weak<Mutex<VecDeque<RemoteCall>>>, f: F) -> Result<R>
+where
+    F: FnOnce(&Lua) -> Result<R> + Send + 'static,
+    R: Send + 'static,
+{
+    let queue = queue.upgrade().ok_or_else(lua_dropped)?;
+    let (tx, rx) = mpsc::sync_channel(1);
+    let call: RemoteCall = Box::new(move |lua| {
+        let _ = tx.send(f(lua));
+    });
+    mlua_expect!(queue.lock(), "remote call queue poisoned").push_back(call);
+    drop(queue);
+    rx.recv().map_err(|_| lua_dropped())?
+}
+
+fn lua_dropped() -> Error {
+    Error::runtime("Lua instance has been dropped")
+}
+
+impl Lua {
+    /// Runs the calls of [`RemoteFunction`]s (and [`RemoteLua`] handles) queued by other threads.
+    ///
+    /// [`RemoteLua`]: crate::RemoteLua
+    ///
+    /// Errors raised by the calls are returned to the calling threads.
+    /// Returns the number of calls run.
+    pub fn run_remote_calls(&self) -> usize {
+        let mut count = 0;
+        loop {
+            // Do not hold the lock while running the call, as it can queue new calls
+            let call =
+                mlua_expect!(self.remote_calls().lock(), "remote call queue poisoned").pop_front();
+            match call {
+                Some(call) => call(self),
+                None => return count,
+            }
+            count += 1;
+        }
+    }
+}
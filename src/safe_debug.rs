@@ -0,0 +1,178 @@
+use std::os::raw::c_int;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+#[cfg(not(feature = "luau"))]
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::thread::Thread;
+use crate::util::{check_stack, traceback};
+use crate::value::Value;
+#[cfg(not(feature = "luau"))]
+use crate::value::{MultiValue, Nil};
+
+// Registers the `debug` library limited to `traceback` and `getinfo`
+pub(crate) fn register_safe_debug(lua: &Lua) -> Result<()> {
+    let debug = lua.create_table_with_capacity(0, 2)?;
+
+    let traceback = lua.create_function(|lua, (msg, level): (Value, Option<c_int>)| {
+        write_traceback(lua, None, msg, level)
+    })?;
+    debug.raw_set("traceback", traceback)?;
+
+    // The second argument (`what`) is ignored, all available fields are returned
+    let getinfo =
+        lua.create_function(|lua, (target, _): (Value, Option<Value>)| getinfo(lua, target))?;
+    debug.raw_set("getinfo", getinfo)?;
+
+    lua.globals().raw_set("debug", &debug)?;
+    if let Some(loaded) = lua.named_registry_value::<Option<Table>>("_LOADED")? {
+        loaded.raw_set("debug", debug)?;
+    }
+    Ok(())
+}
+
+fn getinfo<'lua>(lua: &'lua Lua, target: Value<'lua>) -> Result<Option<Table<'lua>>> {
+    match target {
+        Value::Function(func) => {
+            let info = func.info();
+            let result = lua.create_table_with_capacity(0, 7)?;
+            result.raw_set("name", info.name)?;
+            result.raw_set("namewhat", info.name_what.unwrap_or(""))?;
+            result.raw_set("what", info.what)?;
+            result.raw_set("source", info.source)?;
+            result.raw_set("short_src", info.short_src)?;
+            result.raw_set("linedefined", info.line_defined)?;
+            result.raw_set("lastlinedefined", info.last_line_defined)?;
+            Ok(Some(result))
+        }
+        Value::Integer(_) | Value::Number(_) => {
+            let level = lua.coerce_integer(target)?.unwrap_or(-1);
+            match usize::try_from(level) {
+                Ok(level) => frame_info(lua, level),
+                Err(_) => Ok(None),
+            }
+        }
+        target => Err(Error::runtime(format!(
+            "bad argument #1 to 'getinfo' (function or level expected, got {})",
+            target.type_name()
+        ))),
+    }
+}
+
+fn frame_info(lua: &Lua, level: usize) -> Result<Option<Table<'_>>> {
+    let Some(frame) = lua.inspect_stack(level) else {
+        return Ok(None);
+    };
+    let (names, source) = (frame.names(), frame.source());
+    let result = lua.create_table_with_capacity(0, 8)?;
+    result.raw_set("name", names.name)?;
+    result.raw_set("namewhat", names.name_what.unwrap_or(""))?;
+    result.raw_set("what", source.what)?;
+    result.raw_set("source", source.source)?;
+    result.raw_set("short_src", source.short_src)?;
+    result.raw_set("linedefined", source.line_defined)?;
+    result.raw_set("lastlinedefined", source.last_line_defined)?;
+    result.raw_set("currentline", frame.curr_line())?;
+    Ok(Some(result))
+}
+
+// Replaces `getinfo` and `traceback` of the standard `debug` library with versions describing
+// Rust callbacks created by `Lua::create_named_function` by their display name
+#[cfg(not(feature = "luau"))]
+pub(crate) fn describe_named_callbacks(lua: &Lua) -> Result<()> {
+    let debug: Table = lua.globals().raw_get("debug")?;
+
+    let traceback = lua.create_function(|lua, args: MultiValue| {
+        let mut args = args.into_iter();
+        let (thread, msg) = match args.next() {
+            Some(Value::Thread(thread)) => (Some(thread), args.next()),
+            msg => (None, msg),
+        };
+        let level = args.next().map(|level| lua.unpack(level)).transpose()?;
+        write_traceback(lua, thread, msg.unwrap_or(Nil), level)
+    })?;
+    debug.raw_set("traceback", traceback)?;
+
+    let getinfo = lua.create_registry_value(debug.raw_get::<_, Function>("getinfo")?)?;
+    let getinfo = lua.create_function(move |lua, args: MultiValue| {
+        let mut args = args.into_vec();
+        let pos = match args.first() {
+            Some(Value::Thread(thread)) if thread.state() != lua.state() => 1,
+            Some(Value::Thread(_)) => {
+                args.remove(0);
+                0
+            }
+            _ => 0,
+        };
+        // Levels of the running thread are shifted by this function
+        if pos == 0 {
+            match args.get_mut(0) {
+                Some(Value::Integer(level)) if *level >= 0 => *level += 1,
+                Some(Value::Number(level)) if *level >= 0.0 => *level += 1.0,
+                _ => {}
+            }
+        }
+        // The function is needed to recognize named callbacks
+        let mut strip_func = false;
+        if let Some(Value::String(what)) = args.get(pos + 1) {
+            if !what.as_bytes().contains(&b'f') {
+                let what = lua.create_string([what.as_bytes(), b"f"].concat())?;
+                args[pos + 1] = Value::String(what);
+                strip_func = true;
+            }
+        }
+
+        let getinfo = lua.registry_value::<Function>(&getinfo)?;
+        let info = getinfo.call::<_, Value>(MultiValue::from_vec(args))?;
+        if let Value::Table(ref info) = info {
+            if let Some(func) = info.raw_get::<_, Option<Function>>("func")? {
+                let func_info = func.info();
+                if func_info.what == "C" && func_info.name.is_some() {
+                    if !info.raw_get::<_, Value>("namewhat")?.is_nil() {
+                        info.raw_set("name", func_info.name)?;
+                    }
+                    if !info.raw_get::<_, Value>("source")?.is_nil() {
+                        info.raw_set("source", func_info.source)?;
+                        info.raw_set("short_src", func_info.short_src)?;
+                        info.raw_set("linedefined", func_info.line_defined)?;
+                    }
+                }
+            }
+            if strip_func {
+                info.raw_set("func", Nil)?;
+            }
+        }
+        Ok(info)
+    })?;
+    debug.raw_set("getinfo", getinfo)
+}
+
+// Implements `debug.traceback` for the running thread or `thread`
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+fn write_traceback<'lua>(
+    lua: &'lua Lua,
+    thread: Option<Thread<'lua>>,
+    msg: Value<'lua>,
+    level: Option<c_int>,
+) -> Result<Value<'lua>> {
+    let mut result = match msg {
+        Value::Nil => StdString::new(),
+        Value::String(ref s) => format!("{}\n", s.to_string_lossy()),
+        Value::Integer(_) | Value::Number(_) => format!("{}\n", msg.to_string()?),
+        // Other values are returned untouched, as in the `debug` library
+        msg => return Ok(msg),
+    };
+    let state = match thread {
+        Some(ref thread) if thread.state() != lua.state() => thread.state(),
+        _ => lua.state(),
+    };
+    // Level 1 is the function that called `traceback`
+    let level = level.unwrap_or(if state == lua.state() { 1 } else { 0 });
+    unsafe {
+        check_stack(state, 6)?;
+        result.push_str(&traceback(state, level));
+    }
+    lua.create_string(result).map(Value::String)
+}
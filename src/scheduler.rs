@@ -0,0 +1,206 @@
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use rustc_hash::FxHashMap;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::thread::AsyncThread;
+use crate::value::{FromLuaMulti, MultiValue};
+
+/// Identifier of a thread spawned on a [`LuaScheduler`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+/// A scheduler running a set of [`AsyncThread`]s within a time budget, without an async runtime.
+///
+/// It's designed for hosts driven by a frame loop (e.g. games): every frame, [`LuaScheduler::tick`]
+/// polls the threads that are ready (spawned, woken or yielded in the previous tick) until the
+/// budget is spent. A thread running past the budget is preempted and continued in the next tick.
+///
+/// Scripts can call `coroutine.yield()` to wait for the next tick.
+///
+/// Preemption requires `feature = "lua54/lua53/luau"` (see [`Lua::run_for`]). With other Lua
+/// versions the budget is only checked between threads, so a thread runs until it yields.
+///
+/// Requires `feature = "async"`
+///
+/// # Examples
+///
+/// ```
+/// # use std::time::Duration;
+/// # use mlua::{Lua, LuaScheduler, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let mut scheduler = LuaScheduler::new(&lua);
+///
+/// let npc = lua.load("for i = 1, 3 do coroutine.yield() end return 'done'").into_function()?;
+/// scheduler.spawn(lua.create_thread(npc)?.into_async::<_, String>(()));
+///
+/// let mut frames = 0;
+/// while !scheduler.is_empty() {
+///     for (_, result) in scheduler.tick(Duration::from_millis(2)) {
+///         assert_eq!(result?, "done");
+///     }
+///     frames += 1;
+/// }
+/// assert_eq!(frames, 4);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::run_for`]: crate::Lua::run_for
+pub struct LuaScheduler<'lua, R = MultiValue<'lua>> {
+    lua: &'lua Lua,
+    tasks: FxHashMap<TaskId, Task<'lua, R>>,
+    next_id: u64,
+    // Threads to poll in the next tick, in order
+    ready: Arc<Mutex<Vec<TaskId>>>,
+}
+
+struct Task<'lua, R> {
+    thread: Pin<Box<AsyncThread<'lua, R>>>,
+    waker: Arc<TaskWaker>,
+}
+
+// Waker queueing the task to be polled in the next tick
+struct TaskWaker {
+    id: TaskId,
+    queued: AtomicBool,
+    ready: Arc<Mutex<Vec<TaskId>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            mlua_expect!(self.ready.lock(), "scheduler queue poisoned").push(self.id);
+        }
+    }
+}
+
+impl<'lua, R> LuaScheduler<'lua, R>
+where
+    R: FromLuaMulti<'lua>,
+{
+    /// Creates a new scheduler without threads.
+    pub fn new(lua: &'lua Lua) -> Self {
+        LuaScheduler {
+            lua,
+            tasks: FxHashMap::default(),
+            next_id: 0,
+            ready: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Adds a thread to the scheduler, it's polled first in the next tick.
+    pub fn spawn(&mut self, thread: AsyncThread<'lua, R>) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        let waker = Arc::new(TaskWaker {
+            id,
+            queued: AtomicBool::new(false),
+            ready: self.ready.clone(),
+        });
+        waker.wake_by_ref();
+        let thread = Box::pin(thread);
+        self.tasks.insert(id, Task { thread, waker });
+        id
+    }
+
+    /// Removes the thread from the scheduler without running it further.
+    ///
+    /// Returns `false` if the thread has already finished or was cancelled.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        self.tasks.remove(&id).is_some()
+    }
+
+    /// Returns `true` if the thread has not finished yet.
+    pub fn contains(&self, id: TaskId) -> bool {
+        self.tasks.contains_key(&id)
+    }
+
+    /// Returns the number of unfinished threads.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if all threads have finished.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Polls the ready threads until the `budget` is spent, returning the results of the
+    /// threads that finished.
+    ///
+    /// Every ready thread is polled at most once per tick, and at least one thread is polled even
+    /// if the budget is zero. Threads not reached within the budget are polled first in the next
+    /// tick.
+    pub fn tick(&mut self, budget: Duration) -> Vec<(TaskId, Result<R>)> {
+        // Overflowing budgets poll all ready threads without a deadline
+        let deadline = Instant::now().checked_add(budget);
+        let mut batch = mem::take(&mut *mlua_expect!(
+            self.ready.lock(),
+            "scheduler queue poisoned"
+        ));
+        let mut finished = Vec::new();
+        let mut polled = 0;
+        for &id in &batch {
+            if polled > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            polled += 1;
+            let Some(task) = self.tasks.get_mut(&id) else {
+                // Cancelled
+                continue;
+            };
+            if let Poll::Ready(result) = poll_task(self.lua, task, deadline) {
+                self.tasks.remove(&id);
+                finished.push((id, result));
+            }
+        }
+
+        if polled < batch.len() {
+            let mut ready = mlua_expect!(self.ready.lock(), "scheduler queue poisoned");
+            batch.drain(..polled);
+            batch.append(&mut ready);
+            *ready = batch;
+        }
+        finished
+    }
+}
+
+// Polls the task, preempting its thread when the deadline (if any) passes
+fn poll_task<'lua, R>(
+    lua: &Lua,
+    task: &mut Task<'lua, R>,
+    deadline: Option<Instant>,
+) -> Poll<Result<R>>
+where
+    R: FromLuaMulti<'lua>,
+{
+    task.waker.queued.store(false, Ordering::Release);
+    let waker = Waker::from(task.waker.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    {
+        let thread_state = task.thread.thread().1;
+        let poll = || task.thread.as_mut().poll(&mut cx);
+        unsafe { lua.with_time_slice(thread_state, deadline, poll).0 }
+    }
+
+    #[cfg(not(any(feature = "lua54", feature = "lua53", feature = "luau")))]
+    {
+        let _ = (lua, deadline);
+        task.thread.as_mut().poll(&mut cx)
+    }
+}
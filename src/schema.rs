@@ -0,0 +1,308 @@
+use std::fmt;
+use std::result::Result as StdResult;
+use std::string::String as StdString;
+
+use crate::table::Table;
+use crate::value::Value;
+
+/// Expected type of a value in a [`Schema`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchemaType {
+    /// Any non-nil value.
+    Any,
+    /// A boolean.
+    Boolean,
+    /// An integer or a float without a fractional part.
+    Integer,
+    /// An integer or a float.
+    Number,
+    /// A string.
+    String,
+    /// A table.
+    Table,
+    /// A function.
+    Function,
+    /// A userdata.
+    UserData,
+    /// A thread (coroutine).
+    Thread,
+}
+
+impl SchemaType {
+    fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (SchemaType::Any, _) => true,
+            (SchemaType::Boolean, Value::Boolean(_)) => true,
+            (SchemaType::Integer, Value::Integer(_)) => true,
+            (SchemaType::Integer, Value::Number(n)) => n.fract() == 0.0,
+            (SchemaType::Number, Value::Integer(_) | Value::Number(_)) => true,
+            (SchemaType::String, Value::String(_)) => true,
+            (SchemaType::Table, Value::Table(_)) => true,
+            (SchemaType::Function, Value::Function(_)) => true,
+            (SchemaType::UserData, Value::UserData(_)) => true,
+            (SchemaType::Thread, Value::Thread(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SchemaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SchemaType::Any => "any value",
+            SchemaType::Boolean => "boolean",
+            SchemaType::Integer => "integer",
+            SchemaType::Number => "number",
+            SchemaType::String => "string",
+            SchemaType::Table => "table",
+            SchemaType::Function => "function",
+            SchemaType::UserData => "userdata",
+            SchemaType::Thread => "thread",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Rules of a single value in a [`Schema`].
+#[derive(Debug, Clone)]
+pub struct SchemaField {
+    ty: SchemaType,
+    required: bool,
+    range: Option<(f64, f64)>,
+    length: Option<(usize, usize)>,
+    schema: Option<Schema>,
+    items: Option<Box<SchemaField>>,
+}
+
+impl SchemaField {
+    /// Creates a new optional field of the given type.
+    pub const fn new(ty: SchemaType) -> Self {
+        SchemaField {
+            ty,
+            required: false,
+            range: None,
+            length: None,
+            schema: None,
+            items: None,
+        }
+    }
+
+    /// Creates a new table field validated by a nested schema.
+    pub fn table(schema: Schema) -> Self {
+        SchemaField {
+            schema: Some(schema),
+            ..Self::new(SchemaType::Table)
+        }
+    }
+
+    /// Creates a new table field whose sequence values are validated by `items`.
+    pub fn array(items: SchemaField) -> Self {
+        SchemaField {
+            items: Some(Box::new(items)),
+            ..Self::new(SchemaType::Table)
+        }
+    }
+
+    /// Makes the field required (cannot be `nil`).
+    #[must_use]
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Sets an inclusive range of numeric values.
+    #[must_use]
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Sets an inclusive range of string lengths (in bytes) or table sequence lengths.
+    #[must_use]
+    pub fn length(mut self, min: usize, max: usize) -> Self {
+        self.length = Some((min, max));
+        self
+    }
+
+    fn check(&self, value: Value, path: &str, violations: &mut Vec<Violation>) {
+        let mut violation = |message| violations.push(Violation::new(path, message));
+        if value.is_nil() {
+            if self.required {
+                violation("required value is missing".to_string());
+            }
+            return;
+        }
+        if !self.ty.matches(&value) {
+            violation(format!("expected {}, got {}", self.ty, value.type_name()));
+            return;
+        }
+
+        if let Some((min, max)) = self.range {
+            let n = match value {
+                Value::Integer(i) => Some(i as f64),
+                Value::Number(n) => Some(n),
+                _ => None,
+            };
+            if let Some(n) = n.filter(|n| !(min..=max).contains(n)) {
+                violation(format!("value {n} is out of range [{min}, {max}]"));
+            }
+        }
+
+        if let Some((min, max)) = self.length {
+            let len = match &value {
+                Value::String(s) => Some(s.as_bytes().len()),
+                Value::Table(t) => Some(t.raw_len()),
+                _ => None,
+            };
+            if let Some(len) = len.filter(|len| !(min..=max).contains(len)) {
+                violation(format!("length {len} is out of range [{min}, {max}]"));
+            }
+        }
+
+        if let Value::Table(table) = value {
+            if let Some(schema) = &self.schema {
+                schema.check(&table, path, violations);
+            }
+            if let Some(items) = &self.items {
+                for (i, item) in table.sequence_values::<Value>().enumerate() {
+                    let path = format!("{path}[{}]", i + 1);
+                    match item {
+                        Ok(item) => items.check(item, &path, violations),
+                        Err(err) => violations.push(Violation::new(&path, err.to_string())),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A schema to validate Lua tables, eg. script-provided configuration.
+///
+/// # Example
+///
+/// ```
+/// use mlua::{Lua, Result, Schema, SchemaField, SchemaType};
+///
+/// fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     let config = lua.load(r#"{name = "server", port = 70000, tags = {"a", 1}}"#).eval()?;
+///
+///     let schema = Schema::new()
+///         .field("name", SchemaField::new(SchemaType::String).required())
+///         .field("port", SchemaField::new(SchemaType::Integer).range(1.0, 65535.0))
+///         .field("tags", SchemaField::array(SchemaField::new(SchemaType::String)));
+///
+///     let violations = schema.validate(&config).unwrap_err();
+///     assert_eq!(violations[0].to_string(), "port: value 70000 is out of range [1, 65535]");
+///     assert_eq!(violations[1].to_string(), "tags[2]: expected string, got integer");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    fields: Vec<(StdString, SchemaField)>,
+    deny_unknown_keys: bool,
+}
+
+impl Schema {
+    /// Creates a new schema without fields.
+    pub const fn new() -> Self {
+        Schema {
+            fields: Vec::new(),
+            deny_unknown_keys: false,
+        }
+    }
+
+    /// Adds a field with the given name.
+    #[must_use]
+    pub fn field(mut self, name: impl Into<StdString>, field: SchemaField) -> Self {
+        self.fields.push((name.into(), field));
+        self
+    }
+
+    /// If true, keys not described by the schema are reported as violations.
+    ///
+    /// Default: **false**
+    #[must_use]
+    pub fn deny_unknown_keys(mut self, enabled: bool) -> Self {
+        self.deny_unknown_keys = enabled;
+        self
+    }
+
+    /// Validates the table, returning all found violations.
+    ///
+    /// Fields are checked in order of definition, using raw access (without metamethods).
+    pub fn validate(&self, table: &Table) -> StdResult<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        self.check(table, "", &mut violations);
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations),
+        }
+    }
+
+    fn check(&self, table: &Table, path: &str, violations: &mut Vec<Violation>) {
+        let join = |key: &str| match path {
+            "" => key.to_string(),
+            _ => format!("{path}.{key}"),
+        };
+
+        for (name, field) in &self.fields {
+            match table.raw_get::<_, Value>(name.as_str()) {
+                Ok(value) => field.check(value, &join(name), violations),
+                Err(err) => violations.push(Violation::new(&join(name), err.to_string())),
+            }
+        }
+
+        if self.deny_unknown_keys {
+            for pair in table.clone().pairs::<Value, Value>() {
+                let key = match pair {
+                    Ok((key, _)) => key,
+                    Err(err) => {
+                        violations.push(Violation::new(path, err.to_string()));
+                        break;
+                    }
+                };
+                let known = match &key {
+                    Value::String(s) => self.fields.iter().any(|(name, _)| s == name.as_str()),
+                    _ => false,
+                };
+                if !known {
+                    let key = key
+                        .to_string()
+                        .unwrap_or_else(|_| key.type_name().to_string());
+                    violations.push(Violation::new(&join(&key), "unknown key".to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// A violation of a [`Schema`] found during validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Path to the invalid value, eg. `server.ports[2]` (empty for the validated table itself).
+    pub path: StdString,
+    /// Description of the violation.
+    pub message: StdString,
+}
+
+impl Violation {
+    fn new(path: &str, message: StdString) -> Self {
+        Violation {
+            path: path.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path.as_str() {
+            "" => write!(f, "{}", self.message),
+            path => write!(f, "{path}: {}", self.message),
+        }
+    }
+}
@@ -60,6 +60,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     ///
     /// [`Lua::create_function`]: crate::Lua::create_function
     /// [`Lua::scope`]: crate::Lua::scope
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_function<'callback, A, R, F>(&'callback self, func: F) -> Result<Function<'lua>>
     where
         A: FromLuaMulti<'callback>,
@@ -117,6 +118,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     ///
     /// [`Lua::create_userdata`]: crate::Lua::create_userdata
     /// [`Lua::scope`]: crate::Lua::scope
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData<'lua>>
     where
         T: UserData + 'static,
@@ -143,6 +145,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// [`Lua::scope`]: crate::Lua::scope
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData<'lua>>
     where
         T: UserData + Serialize + 'static,
@@ -161,6 +164,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// reference to the data. See [`Lua::scope`] for more details.
     ///
     /// Userdata created with this method will not be able to be mutated from Lua.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_userdata_ref<T>(&self, data: &'scope T) -> Result<AnyUserData<'lua>>
     where
         T: UserData + 'static,
@@ -177,6 +181,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// This is a version of [`Lua::create_userdata`] that creates a userdata which expires on
     /// scope drop, and does not require that the userdata type be Send. This method takes non-'static
     /// mutable reference to the data. See [`Lua::scope`] for more details.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_userdata_ref_mut<T>(&self, data: &'scope mut T) -> Result<AnyUserData<'lua>>
     where
         T: UserData + 'static,
@@ -194,6 +199,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// scope drop and does not require that the userdata type be Send (but still requires that the
     /// UserData be 'static). See [`Lua::scope`] for more details.
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_any_userdata<T>(&self, data: T) -> Result<AnyUserData<'lua>>
     where
         T: 'static,
@@ -212,6 +218,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// reference to the data. See [`Lua::scope`] for more details.
     ///
     /// Userdata created with this method will not be able to be mutated from Lua.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_any_userdata_ref<T>(&self, data: &'scope T) -> Result<AnyUserData<'lua>>
     where
         T: 'static,
@@ -228,6 +235,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     /// This is a version of [`Lua::create_any_userdata`] that creates a userdata which expires on
     /// scope drop, and does not require that the Rust type be Send. This method takes non-'static
     /// mutable reference to the data. See [`Lua::scope`] for more details.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn create_any_userdata_ref_mut<T>(&self, data: &'scope mut T) -> Result<AnyUserData<'lua>>
     where
         T: 'static,
@@ -241,6 +249,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     }
 
     /// Shortens the lifetime of a userdata to the lifetime of the scope.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn seal_userdata<T: 'static>(&self, ud: &AnyUserData<'lua>) -> Result<()> {
         #[cfg(any(feature = "lua51", feature = "luajit"))]
         let newtable = self.lua.create_table()?;
@@ -271,7 +280,9 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                 ffi::lua_setuservalue(state, -2);
             }
 
-            vec![Box::new(take_userdata::<UserDataCell<T>>(state))]
+            let data = take_userdata::<UserDataCell<T>>(state);
+            ud.lua.release_keep_alive(&ud);
+            vec![Box::new(data)]
         });
         self.destructors
             .borrow_mut()
@@ -312,6 +323,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         // 'lua. This is safe though, because `UserData::add_methods` does not get to pick the 'lua
         // lifetime, so none of the static methods UserData types can add can possibly capture
         // parameters.
+        #[cfg_attr(feature = "stack_diagnostics", track_caller)]
         unsafe fn wrap_method<'scope, 'lua, 'callback: 'scope, T: 'scope>(
             scope: &Scope<'lua, 'scope>,
             ud_ptr: *const UserDataCell<T>,
@@ -555,8 +567,9 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
                     mem::transmute(f)
                 }
 
-                let ud = take_userdata::<UserDataCell<T>>(state);
-                vec![Box::new(seal(ud))]
+                let data = take_userdata::<UserDataCell<T>>(state);
+                ud.lua.release_keep_alive(&ud);
+                vec![Box::new(seal(data))]
             });
             self.destructors
                 .borrow_mut()
@@ -571,6 +584,7 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
     // lifetime of the callback itself is 'scope (non-'static), the borrow checker will happily pick
     // a 'callback that outlives 'scope to allow this. In order for this to be safe, the callback
     // must NOT capture any parameters.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn create_callback<'callback>(
         &self,
         f: Callback<'callback, 'scope>,
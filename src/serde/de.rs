@@ -3,11 +3,14 @@ use std::os::raw::c_void;
 use std::rc::Rc;
 use std::result::Result as StdResult;
 use std::string::String as StdString;
+use std::vec;
 
 use rustc_hash::FxHashSet;
 use serde::de::{self, IntoDeserializer};
 
+use crate::conversion_limits::NestedConversion;
 use crate::error::{Error, Result};
+use crate::string::String;
 use crate::table::{Table, TablePairs, TableSequence};
 use crate::userdata::AnyUserData;
 use crate::value::Value;
@@ -18,6 +21,79 @@ pub struct Deserializer<'lua> {
     value: Value<'lua>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    // Whether to check the value for a type hint (see `Options::type_hint_field`)
+    type_hint: bool,
+    // Strings kept alive by a `DeserializeScope`, if deserializing borrowed values
+    strings: Option<StringPool<'lua>>,
+}
+
+// Lua strings that deserialized values borrow from
+type StringPool<'lua> = Rc<RefCell<Vec<String<'lua>>>>;
+
+/// A scope for deserializing Rust values that borrow strings from Lua values.
+///
+/// Unlike [`LuaSerdeExt::from_value`], which requires [`DeserializeOwned`] types, the scope
+/// deserializes types such as `&str`, `&[u8]` or `Cow<str>` without copying Lua strings.
+/// The scope keeps every borrowed string alive, so the garbage collector cannot free them
+/// while the deserialized values are in use.
+///
+/// Borrowing is supported for strings only, other values (eg. numbers converted to strings) must
+/// be deserialized into owned types.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use mlua::{Lua, Result};
+/// use mlua::serde::de::DeserializeScope;
+///
+/// fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     let value = lua.load(r#"{name = "mlua", kind = "crate"}"#).eval()?;
+///
+///     let scope = DeserializeScope::new();
+///     let map: HashMap<&str, &str> = scope.from_value(value)?;
+///     assert_eq!(map["name"], "mlua");
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`LuaSerdeExt::from_value`]: crate::LuaSerdeExt::from_value
+/// [`DeserializeOwned`]: serde::de::DeserializeOwned
+#[derive(Debug, Default)]
+pub struct DeserializeScope<'lua> {
+    strings: StringPool<'lua>,
+}
+
+impl<'lua> DeserializeScope<'lua> {
+    /// Creates a new empty scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deserializes a [`Value`] into a Rust value that can borrow strings from the scope.
+    ///
+    /// [`Value`]: crate::Value
+    pub fn from_value<'de, T>(&'de self, value: Value<'lua>) -> Result<T>
+    where
+        T: de::Deserialize<'de>,
+    {
+        self.from_value_with(value, Options::default())
+    }
+
+    /// Deserializes a [`Value`] into a Rust value that can borrow strings from the scope,
+    /// using custom options.
+    ///
+    /// [`Value`]: crate::Value
+    pub fn from_value_with<'de, T>(&'de self, value: Value<'lua>, options: Options) -> Result<T>
+    where
+        T: de::Deserialize<'de>,
+    {
+        let mut deserializer = Deserializer::new_with_options(value, options);
+        deserializer.strings = Some(Rc::clone(&self.strings));
+        T::deserialize(deserializer)
+    }
 }
 
 /// A struct with options to change default deserializer behavior.
@@ -47,6 +123,82 @@ pub struct Options {
     ///
     /// Default: **false**
     pub sort_keys: bool,
+
+    /// Policy of deserializing tables that are not proper sequences, eg. sequences with holes or
+    /// tables with both sequence and other keys.
+    ///
+    /// Default: [`MixedTablePolicy::Truncate`]
+    pub mixed_tables: MixedTablePolicy,
+
+    /// If true, integer and number keys in tables can be deserialized as string map keys, and
+    /// numeric string keys (such as `"1"`) as integer or float map keys.
+    ///
+    /// Default: **false**
+    pub coerce_numeric_keys: bool,
+
+    /// Name of a metatable field with a type hint.
+    ///
+    /// Tables whose metatable has this field set to a string are deserialized as externally
+    /// tagged enum variants of that name. For example, with the `__type` field, a table `t`
+    /// with the `{__type = "Point"}` metatable is deserialized as `{Point = t}`.
+    ///
+    /// Default: **none**
+    pub type_hint_field: Option<&'static str>,
+
+    /// If true, tables referenced more than once (including recursive tables) are serialized
+    /// only once, as `{"$id": n, "$value": table}`, and other references to them as
+    /// `{"$ref": n}`. String keys of tables starting with `$` are escaped by doubling it (`"$id"`
+    /// becomes `"$$id"`), so they are not confused with the tags.
+    ///
+    /// Used when serializing Lua values (see [`SerializableValue`]), has no effect on
+    /// [`Deserializer`]. Use the [`resolve_references`] serializer option to restore the
+    /// references.
+    ///
+    /// Default: **false**
+    ///
+    /// [`SerializableValue`]: crate::SerializableValue
+    /// [`resolve_references`]: crate::serde::ser::Options::resolve_references
+    pub preserve_references: bool,
+
+    /// If true, serializable userdata is serialized as `{"$userdata": type_name, "$value": value}`,
+    /// so it can be restored using the [`resolve_userdata`] serializer option.
+    ///
+    /// Userdata of registered [`UserDataSerialize`] types is tagged by [`UserDataSerialize::type_name`],
+    /// other userdata by the name of its type.
+    ///
+    /// Used when serializing Lua values (see [`SerializableValue`]), has no effect on
+    /// [`Deserializer`].
+    ///
+    /// Default: **false**
+    ///
+    /// [`UserDataSerialize`]: crate::UserDataSerialize
+    /// [`SerializableValue`]: crate::SerializableValue
+    /// [`resolve_userdata`]: crate::serde::ser::Options::resolve_userdata
+    pub tag_userdata: bool,
+}
+
+/// Policy of deserializing tables that are not proper sequences.
+///
+/// A table is a proper sequence if its keys are exactly `1..n`.
+///
+/// Used by [`Options::mixed_tables`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MixedTablePolicy {
+    /// Tables with a non-empty sequence part are deserialized as sequences of values
+    /// until the first `nil`, other keys are ignored.
+    #[default]
+    Truncate,
+    /// Tables with only positive integer keys are deserialized as sequences up to the largest key,
+    /// with holes filled with `nil`. Other tables are deserialized as maps.
+    ///
+    /// Excessively sparse tables (whose largest key is above 10 and more than twice the number of
+    /// keys) are deserialized as maps too, or cause an error if a sequence is expected.
+    FillHoles,
+    /// Tables that are not proper sequences are deserialized as maps.
+    Map,
+    /// Tables that are not proper sequences cause an error (unless they have no integer keys).
+    Error,
 }
 
 impl Default for Options {
@@ -62,6 +214,11 @@ impl Options {
             deny_unsupported_types: true,
             deny_recursive_tables: true,
             sort_keys: false,
+            mixed_tables: MixedTablePolicy::Truncate,
+            coerce_numeric_keys: false,
+            type_hint_field: None,
+            preserve_references: false,
+            tag_userdata: false,
         }
     }
 
@@ -91,6 +248,51 @@ impl Options {
         self.sort_keys = enabled;
         self
     }
+
+    /// Sets [`mixed_tables`] option.
+    ///
+    /// [`mixed_tables`]: #structfield.mixed_tables
+    #[must_use]
+    pub const fn mixed_tables(mut self, policy: MixedTablePolicy) -> Self {
+        self.mixed_tables = policy;
+        self
+    }
+
+    /// Sets [`coerce_numeric_keys`] option.
+    ///
+    /// [`coerce_numeric_keys`]: #structfield.coerce_numeric_keys
+    #[must_use]
+    pub const fn coerce_numeric_keys(mut self, enabled: bool) -> Self {
+        self.coerce_numeric_keys = enabled;
+        self
+    }
+
+    /// Sets [`type_hint_field`] option.
+    ///
+    /// [`type_hint_field`]: #structfield.type_hint_field
+    #[must_use]
+    pub const fn type_hint_field(mut self, field: Option<&'static str>) -> Self {
+        self.type_hint_field = field;
+        self
+    }
+
+    /// Sets [`preserve_references`] option.
+    ///
+    /// [`preserve_references`]: #structfield.preserve_references
+    #[must_use]
+    pub const fn preserve_references(mut self, enabled: bool) -> Self {
+        self.preserve_references = enabled;
+        self
+    }
+
+    /// Sets [`tag_userdata`] option.
+    ///
+    /// [`tag_userdata`]: #structfield.tag_userdata
+    #[must_use]
+    pub const fn tag_userdata(mut self, enabled: bool) -> Self {
+        self.tag_userdata = enabled;
+        self
+    }
 }
 
 impl<'lua> Deserializer<'lua> {
@@ -105,6 +307,8 @@ impl<'lua> Deserializer<'lua> {
             value,
             options,
             visited: Rc::new(RefCell::new(FxHashSet::default())),
+            type_hint: true,
+            strings: None,
         }
     }
 
@@ -112,11 +316,37 @@ impl<'lua> Deserializer<'lua> {
         value: Value<'lua>,
         options: Options,
         visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+        strings: Option<StringPool<'lua>>,
     ) -> Self {
         Deserializer {
             value,
             options,
             visited,
+            type_hint: true,
+            strings,
+        }
+    }
+
+    // Returns the type hint from the table metatable (if enabled)
+    fn type_hint(&self, table: &Table) -> Result<Option<StdString>> {
+        match (self.options.type_hint_field, table.get_metatable()) {
+            (Some(field), Some(mt)) if self.type_hint => mt.raw_get(field),
+            _ => Ok(None),
+        }
+    }
+
+    // Checks whether a table should be deserialized as a sequence
+    fn is_sequence(&self, table: &Table) -> Result<bool> {
+        let policy = self.options.mixed_tables;
+        if policy == MixedTablePolicy::Truncate {
+            return Ok(table.raw_len() > 0 || table.is_array());
+        }
+        match TableShape::new(table)? {
+            TableShape::Sequence(len) => Ok(len > 0 || table.is_array()),
+            TableShape::Map => Ok(table.is_array()),
+            TableShape::Holes(_) if policy == MixedTablePolicy::FillHoles => Ok(true),
+            _ if policy == MixedTablePolicy::Error => Err(mixed_table_error()),
+            _ => Ok(false),
         }
     }
 }
@@ -138,12 +368,33 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
             Value::Number(n) => visitor.visit_f64(n.into()),
             #[cfg(feature = "luau")]
             Value::Vector(_) => self.deserialize_seq(visitor),
-            Value::String(s) => match s.to_str() {
-                Ok(s) => visitor.visit_str(s),
-                Err(_) => visitor.visit_bytes(s.as_bytes()),
+            Value::String(s) => match self.strings {
+                Some(strings) => {
+                    let bytes = borrow_string(&strings, s);
+                    match std::str::from_utf8(bytes) {
+                        Ok(s) => visitor.visit_borrowed_str(s),
+                        Err(_) => visitor.visit_borrowed_bytes(bytes),
+                    }
+                }
+                None => match s.to_str() {
+                    Ok(s) => visitor.visit_str(s),
+                    Err(_) => visitor.visit_bytes(s.as_bytes()),
+                },
             },
-            Value::Table(ref t) if t.raw_len() > 0 || t.is_array() => self.deserialize_seq(visitor),
-            Value::Table(_) => self.deserialize_map(visitor),
+            Value::Table(ref t) => {
+                if let Some(variant) = self.type_hint(t)? {
+                    let mut deserializer = HintDeserializer {
+                        variant: Some(variant),
+                        value: Some(self),
+                    };
+                    return visitor.visit_map(&mut deserializer);
+                }
+                if self.is_sequence(t)? {
+                    self.deserialize_seq(visitor)
+                } else {
+                    self.deserialize_map(visitor)
+                }
+            }
             Value::LightUserData(ud) if ud.0.is_null() => visitor.visit_none(),
             Value::UserData(ud) if ud.is_serializable() => {
                 serde_userdata(ud, |value| value.deserialize_any(visitor))
@@ -193,8 +444,19 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     where
         V: de::Visitor<'de>,
     {
+        let hint = match self.value {
+            Value::Table(ref table) => self.type_hint(table)?,
+            _ => None,
+        };
+        let type_hint = hint.is_none();
         let (variant, value, _guard) = match self.value {
+            // The table itself is a value of the hinted variant
+            value @ Value::Table(_) if hint.is_some() => {
+                (hint.unwrap_or_default(), Some(value), None)
+            }
             Value::Table(table) => {
+                let nested = NestedConversion::enter(table.0.lua)?;
+                nested.count_element()?;
                 let _guard = RecursionGuard::new(&table, &self.visited);
 
                 let mut iter = table.pairs::<StdString, Value>();
@@ -220,7 +482,7 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
                     return Err(de::Error::custom("bad enum value"));
                 }
 
-                (variant, Some(value), Some(_guard))
+                (variant, Some(value), Some((_guard, nested)))
             }
             Value::String(variant) => (variant.to_str()?.to_owned(), None, None),
             Value::UserData(ud) if ud.is_serializable() => {
@@ -234,6 +496,8 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
             value,
             options: self.options,
             visited: self.visited,
+            type_hint,
+            strings: self.strings,
         })
     }
 
@@ -254,13 +518,30 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
                 visitor.visit_seq(&mut deserializer)
             }
             Value::Table(t) => {
+                let nested = NestedConversion::enter(t.0.lua)?;
                 let _guard = RecursionGuard::new(&t, &self.visited);
 
                 let len = t.raw_len();
+                let seq = match self.options.mixed_tables {
+                    MixedTablePolicy::Truncate => SeqValues::Table(t.sequence_values()),
+                    policy => match TableShape::new(&t)? {
+                        TableShape::Sequence(_) => SeqValues::Table(t.sequence_values()),
+                        TableShape::Holes(len) if policy == MixedTablePolicy::FillHoles => {
+                            let values = (1..=len).map(|i| t.raw_get(i));
+                            SeqValues::Vec(values.collect::<Result<Vec<_>>>()?.into_iter())
+                        }
+                        TableShape::Sparse if policy == MixedTablePolicy::FillHoles => {
+                            return Err(de::Error::custom("table is too sparse to be a sequence"))
+                        }
+                        _ => return Err(mixed_table_error()),
+                    },
+                };
                 let mut deserializer = SeqDeserializer {
-                    seq: t.sequence_values(),
+                    seq,
+                    nested,
                     options: self.options,
                     visited: self.visited,
+                    strings: self.strings,
                 };
                 let seq = visitor.visit_seq(&mut deserializer)?;
                 if deserializer.seq.count() == 0 {
@@ -310,14 +591,18 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     {
         match self.value {
             Value::Table(t) => {
+                let nested = NestedConversion::enter(t.0.lua)?;
                 let _guard = RecursionGuard::new(&t, &self.visited);
 
+                let sort_keys = self.options.sort_keys || t.is_sorted_map();
                 let mut deserializer = MapDeserializer {
-                    pairs: MapPairs::new(t, self.options.sort_keys)?,
+                    pairs: MapPairs::new(t, sort_keys)?,
+                    nested,
                     value: None,
                     options: self.options,
                     visited: self.visited,
                     processed: 0,
+                    strings: self.strings,
                 };
                 let map = visitor.visit_map(&mut deserializer)?;
                 let count = deserializer.pairs.count();
@@ -395,9 +680,87 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
 }
 
 struct SeqDeserializer<'lua> {
-    seq: TableSequence<'lua, Value<'lua>>,
+    seq: SeqValues<'lua>,
+    nested: NestedConversion<'lua>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    strings: Option<StringPool<'lua>>,
+}
+
+enum SeqValues<'lua> {
+    Table(TableSequence<'lua, Value<'lua>>),
+    // Values of a sequence with holes
+    Vec(vec::IntoIter<Value<'lua>>),
+}
+
+impl<'lua> Iterator for SeqValues<'lua> {
+    type Item = Result<Value<'lua>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SeqValues::Table(iter) => iter.next(),
+            SeqValues::Vec(iter) => iter.next().map(Ok),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            SeqValues::Table(iter) => iter.size_hint(),
+            SeqValues::Vec(iter) => iter.size_hint(),
+        }
+    }
+}
+
+// Largest key of a table with holes that is never considered excessively sparse, and the
+// maximum ratio of the largest key to the number of keys of larger tables (as in lua-cjson)
+const SPARSE_SAFE_LEN: usize = 10;
+const SPARSE_RATIO: usize = 2;
+
+// Shape of table keys, used to apply `MixedTablePolicy`
+enum TableShape {
+    // Keys are exactly `1..n`
+    Sequence(usize),
+    // Only positive integer keys, with the largest key
+    Holes(usize),
+    // Only positive integer keys, too sparse to fill the holes
+    Sparse,
+    // Positive integer and other keys
+    Mixed,
+    // No positive integer keys
+    Map,
+}
+
+impl TableShape {
+    fn new(table: &Table) -> Result<Self> {
+        let (mut count, mut max, mut other) = (0, 0, false);
+        for pair in table.clone().pairs::<Value, Value>() {
+            let index = match pair?.0 {
+                Value::Integer(i) if i > 0 => i as usize,
+                Value::Number(n) if n >= 1.0 && n.fract() == 0.0 && n <= usize::MAX as f64 => {
+                    n as usize
+                }
+                _ => {
+                    other = true;
+                    continue;
+                }
+            };
+            count += 1;
+            max = max.max(index);
+        }
+        Ok(match () {
+            _ if count == 0 && other => TableShape::Map,
+            _ if other => TableShape::Mixed,
+            _ if count == max => TableShape::Sequence(max),
+            _ if max <= SPARSE_SAFE_LEN || max <= count.saturating_mul(SPARSE_RATIO) => {
+                TableShape::Holes(max)
+            }
+            _ => TableShape::Sparse,
+        })
+    }
+}
+
+fn mixed_table_error() -> Error {
+    de::Error::custom("table is not a proper sequence")
 }
 
 impl<'lua, 'de> de::SeqAccess<'de> for SeqDeserializer<'lua> {
@@ -416,8 +779,11 @@ impl<'lua, 'de> de::SeqAccess<'de> for SeqDeserializer<'lua> {
                     if skip {
                         continue;
                     }
+                    self.nested.count_element()?;
                     let visited = Rc::clone(&self.visited);
-                    let deserializer = Deserializer::from_parts(value, self.options, visited);
+                    let strings = self.strings.clone();
+                    let deserializer =
+                        Deserializer::from_parts(value, self.options, visited, strings);
                     return seed.deserialize(deserializer).map(Some);
                 }
                 None => return Ok(None),
@@ -453,8 +819,8 @@ impl<'de> de::SeqAccess<'de> for VecDeserializer {
             Some(&n) => {
                 self.next += 1;
                 let visited = Rc::clone(&self.visited);
-                let deserializer =
-                    Deserializer::from_parts(Value::Number(n as _), self.options, visited);
+                let value = Value::Number(n as _);
+                let deserializer = Deserializer::from_parts(value, self.options, visited, None);
                 seed.deserialize(deserializer).map(Some)
             }
             None => Ok(None),
@@ -510,10 +876,12 @@ impl<'lua> Iterator for MapPairs<'lua> {
 
 struct MapDeserializer<'lua> {
     pairs: MapPairs<'lua>,
+    nested: NestedConversion<'lua>,
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
     processed: usize,
+    strings: Option<StringPool<'lua>>,
 }
 
 impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
@@ -534,10 +902,15 @@ impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
                     if skip_key || skip_value {
                         continue;
                     }
+                    self.nested.count_element()?;
                     self.processed += 1;
                     self.value = Some(value);
                     let visited = Rc::clone(&self.visited);
-                    let key_de = Deserializer::from_parts(key, self.options, visited);
+                    let strings = self.strings.clone();
+                    let key_de = Deserializer::from_parts(key, self.options, visited, strings);
+                    if self.options.coerce_numeric_keys {
+                        return seed.deserialize(KeyDeserializer(key_de)).map(Some);
+                    }
                     return seed.deserialize(key_de).map(Some);
                 }
                 None => return Ok(None),
@@ -552,7 +925,12 @@ impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
         match self.value.take() {
             Some(value) => {
                 let visited = Rc::clone(&self.visited);
-                seed.deserialize(Deserializer::from_parts(value, self.options, visited))
+                seed.deserialize(Deserializer::from_parts(
+                    value,
+                    self.options,
+                    visited,
+                    self.strings.clone(),
+                ))
             }
             None => Err(de::Error::custom("value is missing")),
         }
@@ -566,11 +944,188 @@ impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
     }
 }
 
+// A single entry map of a table with a type hint, keyed by the hint
+struct HintDeserializer<'lua> {
+    variant: Option<StdString>,
+    value: Option<Deserializer<'lua>>,
+}
+
+impl<'lua, 'de> de::MapAccess<'de> for HintDeserializer<'lua> {
+    type Error = Error;
+
+    fn next_key_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.variant.take() {
+            Some(variant) => seed.deserialize(variant.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(mut deserializer) => {
+                deserializer.type_hint = false;
+                seed.deserialize(deserializer)
+            }
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+// Deserializer of map keys with numeric coercion (see `Options::coerce_numeric_keys`)
+struct KeyDeserializer<'lua>(Deserializer<'lua>);
+
+impl<'lua> KeyDeserializer<'lua> {
+    fn parse<T: std::str::FromStr>(&self) -> Option<T> {
+        match &self.0.value {
+            Value::String(s) => s.to_str().ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! deserialize_key_number {
+    ($name:ident, $t:ty, $visit:ident) => {
+        fn $name<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: de::Visitor<'de>,
+        {
+            match self.parse::<$t>() {
+                Some(n) => visitor.$visit(n),
+                None => self.0.$name(visitor),
+            }
+        }
+    };
+}
+
+macro_rules! deserialize_key_forward {
+    ($($name:ident)*) => {
+        $(
+            fn $name<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: de::Visitor<'de>,
+            {
+                self.0.$name(visitor)
+            }
+        )*
+    };
+}
+
+impl<'lua, 'de> serde::Deserializer<'de> for KeyDeserializer<'lua> {
+    type Error = Error;
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0.value {
+            #[allow(clippy::useless_conversion)]
+            Value::Integer(i) => visitor.visit_string(i64::from(i).to_string()),
+            Value::Number(n) => visitor.visit_string(n.to_string()),
+            _ => self.0.deserialize_str(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    deserialize_key_number!(deserialize_i8, i64, visit_i64);
+    deserialize_key_number!(deserialize_i16, i64, visit_i64);
+    deserialize_key_number!(deserialize_i32, i64, visit_i64);
+    deserialize_key_number!(deserialize_i64, i64, visit_i64);
+    deserialize_key_number!(deserialize_i128, i128, visit_i128);
+    deserialize_key_number!(deserialize_u8, u64, visit_u64);
+    deserialize_key_number!(deserialize_u16, u64, visit_u64);
+    deserialize_key_number!(deserialize_u32, u64, visit_u64);
+    deserialize_key_number!(deserialize_u64, u64, visit_u64);
+    deserialize_key_number!(deserialize_u128, u128, visit_u128);
+    deserialize_key_number!(deserialize_f32, f64, visit_f64);
+    deserialize_key_number!(deserialize_f64, f64, visit_f64);
+
+    deserialize_key_forward! {
+        deserialize_any deserialize_bool deserialize_char deserialize_bytes deserialize_byte_buf
+        deserialize_option deserialize_unit deserialize_seq deserialize_map deserialize_identifier
+        deserialize_ignored_any
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+}
+
 struct EnumDeserializer<'lua> {
     variant: StdString,
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    type_hint: bool,
+    strings: Option<StringPool<'lua>>,
 }
 
 impl<'lua, 'de> de::EnumAccess<'de> for EnumDeserializer<'lua> {
@@ -586,6 +1141,8 @@ impl<'lua, 'de> de::EnumAccess<'de> for EnumDeserializer<'lua> {
             value: self.value,
             options: self.options,
             visited: self.visited,
+            type_hint: self.type_hint,
+            strings: self.strings,
         };
         seed.deserialize(variant).map(|v| (v, variant_access))
     }
@@ -595,6 +1152,18 @@ struct VariantDeserializer<'lua> {
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    type_hint: bool,
+    strings: Option<StringPool<'lua>>,
+}
+
+impl<'lua> VariantDeserializer<'lua> {
+    fn deserializer(&self, value: Value<'lua>) -> Deserializer<'lua> {
+        let visited = Rc::clone(&self.visited);
+        let strings = self.strings.clone();
+        let mut deserializer = Deserializer::from_parts(value, self.options, visited, strings);
+        deserializer.type_hint = self.type_hint;
+        deserializer
+    }
 }
 
 impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
@@ -610,14 +1179,12 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
         }
     }
 
-    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    fn newtype_variant_seed<T>(mut self, seed: T) -> Result<T::Value>
     where
         T: de::DeserializeSeed<'de>,
     {
-        match self.value {
-            Some(value) => {
-                seed.deserialize(Deserializer::from_parts(value, self.options, self.visited))
-            }
+        match self.value.take() {
+            Some(value) => seed.deserialize(self.deserializer(value)),
             None => Err(de::Error::invalid_type(
                 de::Unexpected::UnitVariant,
                 &"newtype variant",
@@ -625,15 +1192,12 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
         }
     }
 
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(mut self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            Some(value) => serde::Deserializer::deserialize_seq(
-                Deserializer::from_parts(value, self.options, self.visited),
-                visitor,
-            ),
+        match self.value.take() {
+            Some(value) => serde::Deserializer::deserialize_seq(self.deserializer(value), visitor),
             None => Err(de::Error::invalid_type(
                 de::Unexpected::UnitVariant,
                 &"tuple variant",
@@ -641,15 +1205,12 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
         }
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(mut self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.value {
-            Some(value) => serde::Deserializer::deserialize_map(
-                Deserializer::from_parts(value, self.options, self.visited),
-                visitor,
-            ),
+        match self.value.take() {
+            Some(value) => serde::Deserializer::deserialize_map(self.deserializer(value), visitor),
             None => Err(de::Error::invalid_type(
                 de::Unexpected::UnitVariant,
                 &"struct variant",
@@ -658,6 +1219,17 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
     }
 }
 
+// Keeps the string alive in the pool and returns its bytes borrowed for the pool lifetime
+fn borrow_string<'lua, 'de>(strings: &StringPool<'lua>, s: String<'lua>) -> &'de [u8] {
+    let bytes = s.as_bytes();
+    let (ptr, len) = (bytes.as_ptr(), bytes.len());
+    strings.borrow_mut().push(s);
+    // SAFETY: Lua does not move or modify strings, and the pool holds a reference to the string
+    // until the `DeserializeScope` is dropped. Borrowing the pool is only enabled by the scope,
+    // which ties `'de` to its own lifetime.
+    unsafe { std::slice::from_raw_parts(ptr, len) }
+}
+
 // Adds `ptr` to the `visited` map and removes on drop
 // Used to track recursive tables but allow to traverse same tables multiple times
 pub(crate) struct RecursionGuard {
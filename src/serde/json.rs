@@ -0,0 +1,68 @@
+use serde_json::{Number, Value as JsonValue};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::serde::{ser, LuaSerdeExt};
+use crate::string::String;
+use crate::table::Table;
+use crate::value::Value;
+
+// Reads an optional boolean flag from the options table
+fn flag(opts: &Option<Table>, name: &str) -> Result<Option<bool>> {
+    match opts {
+        Some(opts) => opts.raw_get(name),
+        None => Ok(None),
+    }
+}
+
+fn encode<'lua>(lua: &'lua Lua, value: Value<'lua>, opts: Option<Table>) -> Result<String<'lua>> {
+    let sort_keys = flag(&opts, "sort_keys")?.unwrap_or(false);
+    let value = value.to_serializable().sort_keys(sort_keys);
+    let res = match flag(&opts, "pretty")?.unwrap_or(false) {
+        true => serde_json::to_vec_pretty(&value),
+        false => serde_json::to_vec(&value),
+    };
+    lua.create_string(res.map_err(|err| Error::SerializeError(err.to_string()))?)
+}
+
+fn decode<'lua>(lua: &'lua Lua, s: String, opts: Option<Table>) -> Result<Value<'lua>> {
+    let mut json: JsonValue = serde_json::from_slice(s.as_bytes())
+        .map_err(|err| Error::DeserializeError(err.to_string()))?;
+    if !flag(&opts, "preserve_integers")?.unwrap_or(true) {
+        integers_to_floats(&mut json);
+    }
+    let null_as_nil = flag(&opts, "null_as_nil")?.unwrap_or(false);
+    let options = ser::Options::new()
+        .serialize_none_to_null(!null_as_nil)
+        .serialize_unit_to_null(!null_as_nil)
+        .detect_serde_json_arbitrary_precision(true);
+    lua.to_value_with(&json, options)
+}
+
+fn integers_to_floats(json: &mut JsonValue) {
+    match json {
+        JsonValue::Number(n) if !n.is_f64() => {
+            if let Some(n) = n.as_f64().and_then(Number::from_f64) {
+                *json = JsonValue::Number(n);
+            }
+        }
+        JsonValue::Array(array) => array.iter_mut().for_each(integers_to_floats),
+        JsonValue::Object(object) => object.values_mut().for_each(integers_to_floats),
+        _ => {}
+    }
+}
+
+pub(crate) fn create_json_module(lua: &Lua) -> Result<Table> {
+    let module = lua.create_table_with_capacity(0, 4)?;
+    module.raw_set(
+        "encode",
+        lua.create_function(|lua, (value, opts)| encode(lua, value, opts))?,
+    )?;
+    module.raw_set(
+        "decode",
+        lua.create_function(|lua, (s, opts)| decode(lua, s, opts))?,
+    )?;
+    module.raw_set("null", lua.null())?;
+    module.raw_set("array_mt", lua.array_metatable())?;
+    Ok(module)
+}
@@ -1,13 +1,20 @@
 //! (De)Serialization support using serde.
 
+#[cfg(any(feature = "json", feature = "msgpack"))]
+use std::io;
 use std::os::raw::c_void;
+use std::result::Result as StdResult;
+use std::sync::Arc;
 
 use serde::{de::DeserializeOwned, ser::Serialize};
 
-use crate::error::Result;
+use crate::conversion_limits::restore_error;
+use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::private::Sealed;
 use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::userdata::{AnyUserData, UserData};
 use crate::util::check_stack;
 use crate::value::Value;
 
@@ -191,6 +198,103 @@ pub trait LuaSerdeExt: Sealed {
     #[allow(clippy::wrong_self_convention)]
     fn from_value_with<T: DeserializeOwned>(&self, value: Value, options: de::Options)
         -> Result<T>;
+
+    /// Encodes a [`Value`] into the [MessagePack] binary format.
+    ///
+    /// Requires `feature = "msgpack"`
+    ///
+    /// [`Value`]: crate::Value
+    /// [MessagePack]: https://msgpack.org
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mlua::{Lua, Result, LuaSerdeExt};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     let val = lua.load(r#"{name = "John Smith", tags = {1, 2}}"#).eval()?;
+    ///     let bytes = lua.to_msgpack(&val)?;
+    ///
+    ///     let val = lua.from_msgpack(&bytes)?;
+    ///     lua.globals().set("user", val)?;
+    ///     lua.load(r#"assert(user.name == "John Smith" and user.tags[2] == 2)"#).exec()
+    /// }
+    /// ```
+    #[cfg(feature = "msgpack")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+    fn to_msgpack(&self, value: &Value) -> Result<Vec<u8>>;
+
+    /// Decodes a [`Value`] from the [MessagePack] binary format.
+    ///
+    /// Arrays are decoded as tables with the [`array_metatable`] attached, and `nil` values
+    /// as [`null`].
+    ///
+    /// Requires `feature = "msgpack"`
+    ///
+    /// [`Value`]: crate::Value
+    /// [MessagePack]: https://msgpack.org
+    /// [`array_metatable`]: #tymethod.array_metatable
+    /// [`null`]: #tymethod.null
+    #[cfg(feature = "msgpack")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+    #[allow(clippy::wrong_self_convention)]
+    fn from_msgpack<'lua>(&'lua self, bytes: &[u8]) -> Result<Value<'lua>>;
+
+    /// Serializes a [`Value`] to the writer in the given format.
+    ///
+    /// Tables are traversed and written incrementally, without building an intermediate copy
+    /// of the value in memory, so it's suitable for dumping large states.
+    /// The writer is not buffered, consider wrapping it into [`BufWriter`].
+    ///
+    /// Requires `feature = "json"` or `feature = "msgpack"`
+    ///
+    /// [`Value`]: crate::Value
+    /// [`BufWriter`]: std::io::BufWriter
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mlua::{Lua, Result, LuaSerdeExt, SerializeFormat};
+    ///
+    /// fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     let val = lua.load(r#"{tags = {"a", "b"}}"#).eval()?;
+    ///
+    ///     let mut output = Vec::new();
+    ///     lua.serialize_to_writer(&val, &mut output, SerializeFormat::Json)?;
+    ///     assert_eq!(output, br#"{"tags":["a","b"]}"#);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(any(feature = "json", feature = "msgpack"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "json", feature = "msgpack"))))]
+    fn serialize_to_writer<W: io::Write + ?Sized>(
+        &self,
+        value: &Value,
+        writer: &mut W,
+        format: SerializeFormat,
+    ) -> Result<()>;
+}
+
+/// Output format of [`LuaSerdeExt::serialize_to_writer`].
+///
+/// Requires `feature = "json"` or `feature = "msgpack"`
+#[cfg(any(feature = "json", feature = "msgpack"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "json", feature = "msgpack"))))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SerializeFormat {
+    /// Compact JSON.
+    #[cfg(feature = "json")]
+    Json,
+    /// JSON with indentation.
+    #[cfg(feature = "json")]
+    JsonPretty,
+    /// [MessagePack](https://msgpack.org) binary format.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
 }
 
 impl LuaSerdeExt for Lua {
@@ -210,13 +314,20 @@ impl LuaSerdeExt for Lua {
         T: Serialize + ?Sized,
     {
         t.serialize(ser::Serializer::new(self))
+            .map_err(|err| restore_error(self, err))
     }
 
     fn to_value_with<'lua, T>(&'lua self, t: &T, options: ser::Options) -> Result<Value<'lua>>
     where
         T: Serialize + ?Sized,
     {
-        t.serialize(ser::Serializer::new_with_options(self, options))
+        let value = t
+            .serialize(ser::Serializer::new_with_options(self, options))
+            .map_err(|err| restore_error(self, err))?;
+        match options.resolve_references {
+            true => refs::resolve_references(value),
+            false => Ok(value),
+        }
     }
 
     fn from_value<T>(&self, value: Value) -> Result<T>
@@ -232,6 +343,178 @@ impl LuaSerdeExt for Lua {
     {
         T::deserialize(de::Deserializer::new_with_options(value, options))
     }
+
+    #[cfg(feature = "msgpack")]
+    fn to_msgpack(&self, value: &Value) -> Result<Vec<u8>> {
+        msgpack::encode(value)
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn from_msgpack<'lua>(&'lua self, bytes: &[u8]) -> Result<Value<'lua>> {
+        msgpack::decode(self, bytes)
+    }
+
+    #[cfg(any(feature = "json", feature = "msgpack"))]
+    fn serialize_to_writer<W: io::Write + ?Sized>(
+        &self,
+        value: &Value,
+        writer: &mut W,
+        format: SerializeFormat,
+    ) -> Result<()> {
+        let res = match format {
+            #[cfg(feature = "json")]
+            SerializeFormat::Json => {
+                serde_json::to_writer(writer, value).map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "json")]
+            SerializeFormat::JsonPretty => {
+                serde_json::to_writer_pretty(writer, value).map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "msgpack")]
+            SerializeFormat::MessagePack => {
+                rmp_serde::encode::write(writer, value).map_err(|e| e.to_string())
+            }
+        };
+        res.map_err(Error::SerializeError)
+    }
+}
+
+/// Trait for userdata types with custom conversion through the serde bridge.
+///
+/// By default serialization stops at userdata boundary, unless the userdata was created using
+/// [`Lua::create_ser_userdata`]. Types implementing this trait and registered using
+/// [`Lua::register_userdata_serialize`] are instead serialized as the value returned by
+/// [`to_value`], eg. a handle to a game entity can be serialized as the entity id.
+///
+/// To restore userdata from serialized values, enable the [`tag_userdata`] option when
+/// serializing, which wraps the value into a `{"$userdata": type_name, "$value": value}` table,
+/// and the [`resolve_userdata`] option when converting it back to Lua.
+///
+/// Requires `feature = "serialize"`
+///
+/// # Example
+///
+/// ```
+/// use mlua::{IntoLua, Lua, LuaSerdeExt, Result, UserData, UserDataSerialize, Value};
+///
+/// struct Entity(u32);
+///
+/// impl UserData for Entity {}
+///
+/// impl UserDataSerialize for Entity {
+///     fn to_value<'lua>(&self, lua: &'lua Lua) -> Result<Value<'lua>> {
+///         self.0.into_lua(lua)
+///     }
+///
+///     fn from_value<'lua>(_: &'lua Lua, value: Value<'lua>) -> Result<Self> {
+///         let id = value.as_u32().ok_or_else(|| mlua::Error::runtime("invalid entity id"))?;
+///         Ok(Entity(id))
+///     }
+/// }
+///
+/// fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     lua.register_userdata_serialize::<Entity>();
+///
+///     let ud = lua.create_userdata(Entity(7))?;
+///     let id: u32 = lua.from_value(Value::UserData(ud))?;
+///     assert_eq!(id, 7);
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`Lua::create_ser_userdata`]: crate::Lua::create_ser_userdata
+/// [`Lua::register_userdata_serialize`]: crate::Lua::register_userdata_serialize
+/// [`to_value`]: UserDataSerialize::to_value
+/// [`tag_userdata`]: de::Options::tag_userdata
+/// [`resolve_userdata`]: ser::Options::resolve_userdata
+#[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+pub trait UserDataSerialize: UserData + MaybeSend + Sized + 'static {
+    /// Name of the type, used to tag serialized values.
+    ///
+    /// Defaults to the Rust type name.
+    fn type_name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Converts the userdata into a Lua value to serialize.
+    fn to_value<'lua>(&self, lua: &'lua Lua) -> Result<Value<'lua>>;
+
+    /// Restores the userdata from a value previously returned by [`to_value`].
+    ///
+    /// [`to_value`]: UserDataSerialize::to_value
+    fn from_value<'lua>(lua: &'lua Lua, value: Value<'lua>) -> Result<Self>;
+}
+
+// Converters of registered `UserDataSerialize` types
+pub(crate) type UserDataToValue = for<'lua> fn(&AnyUserData<'lua>) -> Result<Value<'lua>>;
+
+// Constructors of userdata from values tagged by `tag_userdata` option, registered using
+// `Lua::register_userdata_serialize` or `Lua::register_userdata_factory`
+#[cfg(feature = "send")]
+pub(crate) type UserDataFromValue =
+    Arc<dyn for<'lua> Fn(&'lua Lua, Value<'lua>) -> Result<AnyUserData<'lua>> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type UserDataFromValue =
+    Arc<dyn for<'lua> Fn(&'lua Lua, Value<'lua>) -> Result<AnyUserData<'lua>>>;
+
+pub(crate) fn userdata_to_value<'lua, T: UserDataSerialize>(
+    ud: &AnyUserData<'lua>,
+) -> Result<Value<'lua>> {
+    ud.borrow::<T>()?.to_value(ud.0.lua)
+}
+
+pub(crate) fn userdata_from_value<'lua, T: UserDataSerialize>(
+    lua: &'lua Lua,
+    value: Value<'lua>,
+) -> Result<AnyUserData<'lua>> {
+    lua.create_userdata(T::from_value(lua, value)?)
+}
+
+// Keys of tables tagged by `tag_userdata` option
+pub(crate) const USERDATA_KEY: &str = "$userdata";
+pub(crate) const USERDATA_VALUE_KEY: &str = "$value";
+
+// Serializes userdata as `{"$userdata": type_name, "$value": value}` (see `tag_userdata` option).
+// Registered `UserDataSerialize` types are tagged by their name, other userdata by the name of
+// its type.
+pub(crate) fn serialize_tagged_userdata<S>(
+    ud: &AnyUserData,
+    serializer: S,
+) -> StdResult<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::{Error as _, SerializeMap};
+
+    let name = match ud.userdata_serializer() {
+        Some((name, _)) => Some(name.to_owned()),
+        None => ud.type_name().map_err(S::Error::custom)?,
+    };
+    let name =
+        name.ok_or_else(|| S::Error::custom("cannot serialize userdata without type name"))?;
+    let mut map = serializer.serialize_map(Some(2))?;
+    map.serialize_entry(USERDATA_KEY, &name)?;
+    map.serialize_entry(USERDATA_VALUE_KEY, ud)?;
+    map.end()
+}
+
+// Converts a table tagged by `tag_userdata` option back to userdata
+pub(crate) fn resolve_userdata<'lua>(table: &Table<'lua>) -> Result<Option<AnyUserData<'lua>>> {
+    let lua = table.0.lua;
+    let name = match table.raw_get(USERDATA_KEY)? {
+        Value::String(name) => name,
+        _ => return Ok(None),
+    };
+    let name = name.to_str()?;
+    match lua.userdata_deserializer(name) {
+        Some(from_value) => from_value(lua, table.raw_get(USERDATA_VALUE_KEY)?).map(Some),
+        None => Err(Error::SerializeError(format!(
+            "userdata type '{name}' is not registered"
+        ))),
+    }
 }
 
 // Uses 2 stack spaces and calls checkstack.
@@ -246,6 +529,16 @@ pub(crate) unsafe fn init_metatables(state: *mut ffi::lua_State) -> Result<()> {
 
         let array_metatable_key = &ARRAY_METATABLE_REGISTRY_KEY as *const u8 as *const c_void;
         ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, array_metatable_key);
+
+        ffi::lua_createtable(state, 0, 1);
+
+        ffi::lua_pushstring(state, cstr!("__metatable"));
+        ffi::lua_pushboolean(state, 0);
+        ffi::lua_rawset(state, -3);
+
+        let sorted_map_metatable_key =
+            &SORTED_MAP_METATABLE_REGISTRY_KEY as *const u8 as *const c_void;
+        ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, sorted_map_metatable_key);
     })
 }
 
@@ -254,9 +547,29 @@ pub(crate) unsafe fn push_array_metatable(state: *mut ffi::lua_State) {
     ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, array_metatable_key);
 }
 
+// Metatable of tables created with the `sort_keys` serializer option, their keys are serialized
+// in sorted order
+pub(crate) unsafe fn push_sorted_map_metatable(state: *mut ffi::lua_State) {
+    let sorted_map_metatable_key = &SORTED_MAP_METATABLE_REGISTRY_KEY as *const u8 as *const c_void;
+    ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, sorted_map_metatable_key);
+}
+
+pub(crate) fn sorted_map_metatable(lua: &Lua) -> Table<'_> {
+    unsafe {
+        push_sorted_map_metatable(lua.ref_thread());
+        Table(lua.pop_ref_thread())
+    }
+}
+
 static ARRAY_METATABLE_REGISTRY_KEY: u8 = 0;
+static SORTED_MAP_METATABLE_REGISTRY_KEY: u8 = 0;
 
 pub mod de;
+#[cfg(feature = "json")]
+pub(crate) mod json;
+#[cfg(feature = "msgpack")]
+pub(crate) mod msgpack;
+pub(crate) mod refs;
 pub mod ser;
 
 #[doc(inline)]
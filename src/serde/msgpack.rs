@@ -0,0 +1,31 @@
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::serde::LuaSerdeExt;
+use crate::string::String;
+use crate::table::Table;
+use crate::value::Value;
+
+pub(crate) fn encode(value: &Value) -> Result<Vec<u8>> {
+    rmp_serde::to_vec(value).map_err(|err| Error::SerializeError(err.to_string()))
+}
+
+pub(crate) fn decode<'lua>(lua: &'lua Lua, bytes: &[u8]) -> Result<Value<'lua>> {
+    // MessagePack is self-describing, so the intermediate value preserves all data types
+    let value: serde_value::Value =
+        rmp_serde::from_slice(bytes).map_err(|err| Error::DeserializeError(err.to_string()))?;
+    lua.to_value(&value)
+}
+
+pub(crate) fn create_msgpack_module(lua: &Lua) -> Result<Table> {
+    let module = lua.create_table_with_capacity(0, 3)?;
+    module.raw_set(
+        "encode",
+        lua.create_function(|lua, value: Value| lua.create_string(encode(&value)?))?,
+    )?;
+    module.raw_set(
+        "decode",
+        lua.create_function(|lua, data: String| decode(lua, data.as_bytes()))?,
+    )?;
+    module.raw_set("null", lua.null())?;
+    Ok(module)
+}
@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::os::raw::c_void;
+use std::string::String as StdString;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::error::{Error, Result};
+use crate::table::Table;
+use crate::types::Integer;
+use crate::value::Value;
+
+// Keys of tables tagged by `preserve_references` option:
+// `{"$id": n, "$value": table}` is the first reference to a shared table, `{"$ref": n}` is any other
+pub(crate) const ID_KEY: &str = "$id";
+pub(crate) const VALUE_KEY: &str = "$value";
+pub(crate) const REF_KEY: &str = "$ref";
+
+// Keys of user tables starting with the prefix are escaped by doubling it, so they cannot be
+// confused with tags
+const ESCAPE_PREFIX: &str = "$";
+
+// Tables referenced more than once in a value, with ids assigned in serialization order
+#[derive(Debug, Default)]
+pub(crate) struct SharedTables {
+    ids: FxHashMap<*const c_void, Option<usize>>,
+    next_id: usize,
+}
+
+impl SharedTables {
+    pub(crate) fn new(value: &Value) -> Result<Self> {
+        let mut counts = FxHashMap::default();
+        count_tables(value, &mut counts)?;
+        let ids = counts
+            .into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(ptr, _)| (ptr, None))
+            .collect();
+        Ok(SharedTables { ids, next_id: 1 })
+    }
+
+    // Returns the table id and whether it is the first reference to the table
+    fn id(&mut self, table: &Table) -> Option<(usize, bool)> {
+        let id = self.ids.get_mut(&table.to_pointer())?;
+        match *id {
+            Some(id) => Some((id, false)),
+            None => {
+                *id = Some(self.next_id);
+                self.next_id += 1;
+                Some((self.next_id - 1, true))
+            }
+        }
+    }
+}
+
+fn count_tables(value: &Value, counts: &mut FxHashMap<*const c_void, usize>) -> Result<()> {
+    if let Value::Table(table) = value {
+        let count = counts.entry(table.to_pointer()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            table.for_each(|key: Value, value: Value| {
+                count_tables(&key, counts)?;
+                count_tables(&value, counts)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// Serializes `value` (the `table` contents), tagging it if the table is shared
+pub(crate) fn serialize_table<S, T>(
+    table: &Table,
+    value: &T,
+    shared: &RefCell<SharedTables>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let id = shared.borrow_mut().id(table);
+    match id {
+        Some((id, true)) => {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry(ID_KEY, &id)?;
+            map.serialize_entry(VALUE_KEY, value)?;
+            map.end()
+        }
+        Some((id, false)) => {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry(REF_KEY, &id)?;
+            map.end()
+        }
+        None => value.serialize(serializer),
+    }
+}
+
+// Returns the escaped key if it starts with the tags prefix
+pub(crate) fn escape_key(key: &Value) -> Option<StdString> {
+    match key {
+        Value::String(s) => (s.to_str().ok())
+            .filter(|s| s.starts_with(ESCAPE_PREFIX))
+            .map(|s| format!("{ESCAPE_PREFIX}{s}")),
+        _ => None,
+    }
+}
+
+// Reverts `escape_key`
+fn unescape_key<'lua>(table: &Table<'lua>, key: Value<'lua>) -> Result<Value<'lua>> {
+    if let Value::String(s) = &key {
+        let prefix = ESCAPE_PREFIX.as_bytes();
+        match s.as_bytes().strip_prefix(prefix) {
+            Some(rest) if rest.starts_with(prefix) => {
+                return table.0.lua.create_string(rest).map(Value::String)
+            }
+            _ => {}
+        }
+    }
+    Ok(key)
+}
+
+enum Tag<'lua> {
+    Shared(Integer, Table<'lua>),
+    Ref(Integer),
+}
+
+fn tag<'lua>(table: &Table<'lua>) -> Result<Option<Tag<'lua>>> {
+    let mut keys = 0;
+    table.for_each(|_: Value, _: Value| {
+        keys += 1;
+        Ok(())
+    })?;
+    let tag = match keys {
+        1 => match table.raw_get(REF_KEY)? {
+            Value::Integer(id) => Some(Tag::Ref(id)),
+            _ => None,
+        },
+        2 => match (table.raw_get(ID_KEY)?, table.raw_get(VALUE_KEY)?) {
+            (Value::Integer(id), Value::Table(value)) => Some(Tag::Shared(id, value)),
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok(tag)
+}
+
+// Replaces tagged tables produced by `preserve_references` option with the referenced tables
+pub(crate) fn resolve_references(value: Value) -> Result<Value> {
+    let mut shared = FxHashMap::default();
+    collect_shared(&value, &mut shared, &mut FxHashSet::default())?;
+    resolve(value, &shared, &mut FxHashSet::default())
+}
+
+fn collect_shared<'lua>(
+    value: &Value<'lua>,
+    shared: &mut FxHashMap<Integer, Table<'lua>>,
+    visited: &mut FxHashSet<*const c_void>,
+) -> Result<()> {
+    if let Value::Table(table) = value {
+        if !visited.insert(table.to_pointer()) {
+            return Ok(());
+        }
+        if let Some(Tag::Shared(id, value)) = tag(table)? {
+            shared.insert(id, value);
+        }
+        table.for_each(|key: Value, value: Value| {
+            collect_shared(&key, shared, visited)?;
+            collect_shared(&value, shared, visited)
+        })?;
+    }
+    Ok(())
+}
+
+fn resolve<'lua>(
+    value: Value<'lua>,
+    shared: &FxHashMap<Integer, Table<'lua>>,
+    resolved: &mut FxHashSet<*const c_void>,
+) -> Result<Value<'lua>> {
+    let table = match value {
+        Value::Table(table) => match tag(&table)? {
+            Some(Tag::Shared(_, value)) => value,
+            Some(Tag::Ref(id)) => shared.get(&id).cloned().ok_or_else(|| {
+                Error::SerializeError(format!("reference to unknown table id {id}"))
+            })?,
+            None => table,
+        },
+        value => return Ok(value),
+    };
+    if resolved.insert(table.to_pointer()) {
+        let pairs = table
+            .clone()
+            .pairs::<Value, Value>()
+            .collect::<Result<Vec<_>>>()?;
+        for (key, value) in pairs {
+            let new_key = unescape_key(&table, resolve(key.clone(), shared, resolved)?)?;
+            let value = resolve(value, shared, resolved)?;
+            if new_key != key {
+                table.raw_set(key, Value::Nil)?;
+            }
+            table.raw_set(new_key, value)?;
+        }
+    }
+    Ok(Value::Table(table))
+}
@@ -1,9 +1,11 @@
 use serde::{ser, Serialize};
 
 use super::LuaSerdeExt;
+use crate::conversion_limits::NestedConversion;
 use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::table::Table;
+use crate::types::Integer;
 use crate::value::{IntoLua, Value};
 
 /// A struct for serializing Rust values into Lua values.
@@ -48,6 +50,73 @@ pub struct Options {
     ///
     /// Default: **false**
     pub detect_serde_json_arbitrary_precision: bool,
+
+    /// If true, tables created from maps and structs are marked to have their keys serialized
+    /// (and deserialized) in sorted order.
+    ///
+    /// Lua tables are unordered, so the marker (a metatable) makes serializing the result
+    /// independent of the source map order (eg. `HashMap`) and of the Lua hashing seed.
+    ///
+    /// Default: **false**
+    pub sort_keys: bool,
+
+    /// Maximum nesting depth of Lua tables created during serialization.
+    ///
+    /// Applied in addition to the [`ConversionLimits::max_depth`] of the Lua state, the stricter
+    /// limit wins. Serializing a deeper value fails with [`Error::TooDeep`].
+    ///
+    /// Default: **none**
+    ///
+    /// [`ConversionLimits::max_depth`]: crate::ConversionLimits::max_depth
+    /// [`Error::TooDeep`]: crate::Error::TooDeep
+    pub max_depth: Option<usize>,
+
+    /// Policy of converting floating point numbers to Lua values.
+    ///
+    /// Default: [`FloatPolicy::Preserve`]
+    pub float_policy: FloatPolicy,
+
+    /// If true, tables tagged by the [`preserve_references`] option are replaced with the
+    /// tables they refer to, restoring shared and recursive tables. Escaped keys (starting with
+    /// `$$`) are unescaped.
+    ///
+    /// Applied by [`LuaSerdeExt::to_value_with`] once the whole value is serialized.
+    ///
+    /// Default: **false**
+    ///
+    /// [`preserve_references`]: crate::serde::de::Options::preserve_references
+    /// [`LuaSerdeExt::to_value_with`]: crate::LuaSerdeExt::to_value_with
+    pub resolve_references: bool,
+
+    /// If true, maps tagged by the [`tag_userdata`] option are converted back to userdata
+    /// using registered [`UserDataSerialize`] types or factories registered using
+    /// [`Lua::register_userdata_factory`].
+    ///
+    /// Default: **false**
+    ///
+    /// [`tag_userdata`]: crate::serde::de::Options::tag_userdata
+    /// [`UserDataSerialize`]: crate::UserDataSerialize
+    /// [`Lua::register_userdata_factory`]: crate::Lua::register_userdata_factory
+    pub resolve_userdata: bool,
+}
+
+/// Policy of converting floating point numbers to Lua values.
+///
+/// Used by [`Options::float_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FloatPolicy {
+    /// Convert floats to Lua numbers as is.
+    #[default]
+    Preserve,
+    /// Convert negative zero to zero and all NaN values to a single canonical NaN,
+    /// so equal values always have the same representation.
+    Canonical,
+    /// Same as [`Canonical`], and additionally convert floats without a fractional part
+    /// to Lua integers (if they fit).
+    ///
+    /// [`Canonical`]: FloatPolicy::Canonical
+    Integral,
 }
 
 impl Default for Options {
@@ -64,6 +133,11 @@ impl Options {
             serialize_none_to_null: true,
             serialize_unit_to_null: true,
             detect_serde_json_arbitrary_precision: false,
+            sort_keys: false,
+            max_depth: None,
+            float_policy: FloatPolicy::Preserve,
+            resolve_references: false,
+            resolve_userdata: false,
         }
     }
 
@@ -107,6 +181,51 @@ impl Options {
         self.detect_serde_json_arbitrary_precision = enabled;
         self
     }
+
+    /// Sets [`sort_keys`] option.
+    ///
+    /// [`sort_keys`]: #structfield.sort_keys
+    #[must_use]
+    pub const fn sort_keys(mut self, enabled: bool) -> Self {
+        self.sort_keys = enabled;
+        self
+    }
+
+    /// Sets [`max_depth`] option.
+    ///
+    /// [`max_depth`]: #structfield.max_depth
+    #[must_use]
+    pub const fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets [`float_policy`] option.
+    ///
+    /// [`float_policy`]: #structfield.float_policy
+    #[must_use]
+    pub const fn float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Sets [`resolve_references`] option.
+    ///
+    /// [`resolve_references`]: #structfield.resolve_references
+    #[must_use]
+    pub const fn resolve_references(mut self, enabled: bool) -> Self {
+        self.resolve_references = enabled;
+        self
+    }
+
+    /// Sets [`resolve_userdata`] option.
+    ///
+    /// [`resolve_userdata`]: #structfield.resolve_userdata
+    #[must_use]
+    pub const fn resolve_userdata(mut self, enabled: bool) -> Self {
+        self.resolve_userdata = enabled;
+        self
+    }
 }
 
 impl<'lua> Serializer<'lua> {
@@ -119,6 +238,28 @@ impl<'lua> Serializer<'lua> {
     pub fn new_with_options(lua: &'lua Lua, options: Options) -> Self {
         Serializer { lua, options }
     }
+
+    // Starts serializing a nested table, checking the depth limits
+    fn enter_table(&self) -> Result<NestedConversion<'lua>> {
+        NestedConversion::enter_with_max_depth(self.lua, self.options.max_depth)
+    }
+
+    // Creates a table for map entries or struct fields
+    fn create_map_table(&self, len: usize) -> Result<Table<'lua>> {
+        let table = self.lua.create_table_with_capacity(0, len)?;
+        if self.options.sort_keys {
+            table.set_metatable(Some(super::sorted_map_metatable(self.lua)));
+        }
+        Ok(table)
+    }
+}
+
+// Serializes a value nested in a table
+fn to_value<'lua, T>(lua: &'lua Lua, value: &T, options: Options) -> Result<Value<'lua>>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(Serializer::new_with_options(lua, options))
 }
 
 macro_rules! lua_serialize_number {
@@ -160,8 +301,28 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     lua_serialize_number!(serialize_i128, i128);
     lua_serialize_number!(serialize_u128, u128);
 
-    lua_serialize_number!(serialize_f32, f32);
-    lua_serialize_number!(serialize_f64, f64);
+    #[inline]
+    fn serialize_f32(self, value: f32) -> Result<Value<'lua>> {
+        self.serialize_f64(value as f64)
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Value<'lua>> {
+        let value = match self.options.float_policy {
+            FloatPolicy::Preserve => return Ok(Value::Number(value)),
+            _ if value.is_nan() => f64::NAN,
+            // Also converts `-0.0` to `0.0`
+            _ if value == 0.0 => 0.0,
+            _ => value,
+        };
+        if self.options.float_policy == FloatPolicy::Integral
+            && value.fract() == 0.0
+            && value >= Integer::MIN as f64
+            && value < -(Integer::MIN as f64)
+        {
+            return Ok(Value::Integer(value as Integer));
+        }
+        Ok(Value::Number(value))
+    }
 
     #[inline]
     fn serialize_char(self, value: char) -> Result<Value<'lua>> {
@@ -242,20 +403,23 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     where
         T: Serialize + ?Sized,
     {
+        let nested = self.enter_table()?;
+        nested.count_element()?;
         let table = self.lua.create_table()?;
         let variant = self.lua.create_string(variant)?;
-        let value = self.lua.to_value_with(value, self.options)?;
+        let value = to_value(self.lua, value, self.options)?;
         table.raw_set(variant, value)?;
         Ok(Value::Table(table))
     }
 
     #[inline]
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        let nested = self.enter_table()?;
         let table = self.lua.create_table_with_capacity(len.unwrap_or(0), 0)?;
         if self.options.set_array_metatable {
             table.set_metatable(Some(self.lua.array_metatable()));
         }
-        Ok(SerializeSeq::new(table, self.options))
+        Ok(SerializeSeq::new(table, nested, self.options))
     }
 
     #[inline]
@@ -287,6 +451,7 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     ) -> Result<Self::SerializeTupleVariant> {
         Ok(SerializeTupleVariant {
             variant,
+            nested: self.enter_table()?,
             table: self.lua.create_table()?,
             options: self.options,
         })
@@ -296,7 +461,8 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
         Ok(SerializeMap {
             key: None,
-            table: self.lua.create_table_with_capacity(0, len.unwrap_or(0))?,
+            nested: self.enter_table()?,
+            table: self.create_map_table(len.unwrap_or(0))?,
             options: self.options,
         })
     }
@@ -310,13 +476,15 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
             return Ok(SerializeStruct {
                 lua: self.lua,
                 inner: None,
+                nested: None,
                 options: self.options,
             });
         }
 
         Ok(SerializeStruct {
             lua: self.lua,
-            inner: Some(Value::Table(self.lua.create_table_with_capacity(0, len)?)),
+            nested: Some(self.enter_table()?),
+            inner: Some(Value::Table(self.create_map_table(len)?)),
             options: self.options,
         })
     }
@@ -331,7 +499,8 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     ) -> Result<Self::SerializeStructVariant> {
         Ok(SerializeStructVariant {
             variant,
-            table: self.lua.create_table_with_capacity(0, len)?,
+            nested: self.enter_table()?,
+            table: self.create_map_table(len)?,
             options: self.options,
         })
     }
@@ -343,17 +512,20 @@ pub struct SerializeSeq<'lua> {
     #[cfg(feature = "luau")]
     vector: Option<crate::types::Vector>,
     table: Option<Table<'lua>>,
+    // Not set for vectors
+    nested: Option<NestedConversion<'lua>>,
     next: usize,
     options: Options,
 }
 
 impl<'lua> SerializeSeq<'lua> {
-    const fn new(table: Table<'lua>, options: Options) -> Self {
+    fn new(table: Table<'lua>, nested: NestedConversion<'lua>, options: Options) -> Self {
         Self {
             lua: table.0.lua,
             #[cfg(feature = "luau")]
             vector: None,
             table: Some(table),
+            nested: Some(nested),
             next: 0,
             options,
         }
@@ -365,6 +537,7 @@ impl<'lua> SerializeSeq<'lua> {
             lua,
             vector: Some(crate::types::Vector::zero()),
             table: None,
+            nested: None,
             next: 0,
             options,
         }
@@ -379,7 +552,10 @@ impl<'lua> ser::SerializeSeq for SerializeSeq<'lua> {
     where
         T: Serialize + ?Sized,
     {
-        let value = self.lua.to_value_with(value, self.options)?;
+        if let Some(nested) = &self.nested {
+            nested.count_element()?;
+        }
+        let value = to_value(self.lua, value, self.options)?;
         let table = self.table.as_ref().unwrap();
         table.raw_seti(self.next + 1, value)?;
         self.next += 1;
@@ -417,7 +593,7 @@ impl<'lua> ser::SerializeTupleStruct for SerializeSeq<'lua> {
     {
         #[cfg(feature = "luau")]
         if let Some(vector) = self.vector.as_mut() {
-            let value = self.lua.to_value_with(value, self.options)?;
+            let value = to_value(self.lua, value, self.options)?;
             let value = self.lua.unpack(value)?;
             vector.0[self.next] = value;
             self.next += 1;
@@ -439,6 +615,7 @@ impl<'lua> ser::SerializeTupleStruct for SerializeSeq<'lua> {
 pub struct SerializeTupleVariant<'lua> {
     variant: &'static str,
     table: Table<'lua>,
+    nested: NestedConversion<'lua>,
     options: Options,
 }
 
@@ -450,8 +627,9 @@ impl<'lua> ser::SerializeTupleVariant for SerializeTupleVariant<'lua> {
     where
         T: Serialize + ?Sized,
     {
+        self.nested.count_element()?;
         let lua = self.table.0.lua;
-        self.table.raw_push(lua.to_value_with(value, self.options)?)
+        self.table.raw_push(to_value(lua, value, self.options)?)
     }
 
     fn end(self) -> Result<Value<'lua>> {
@@ -466,6 +644,7 @@ impl<'lua> ser::SerializeTupleVariant for SerializeTupleVariant<'lua> {
 pub struct SerializeMap<'lua> {
     table: Table<'lua>,
     key: Option<Value<'lua>>,
+    nested: NestedConversion<'lua>,
     options: Options,
 }
 
@@ -477,8 +656,9 @@ impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
     where
         T: Serialize + ?Sized,
     {
+        self.nested.count_element()?;
         let lua = self.table.0.lua;
-        self.key = Some(lua.to_value_with(key, self.options)?);
+        self.key = Some(to_value(lua, key, self.options)?);
         Ok(())
     }
 
@@ -491,11 +671,16 @@ impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
             self.key.take(),
             "serialize_value called before serialize_key"
         );
-        let value = lua.to_value_with(value, self.options)?;
+        let value = to_value(lua, value, self.options)?;
         self.table.raw_set(key, value)
     }
 
     fn end(self) -> Result<Value<'lua>> {
+        if self.options.resolve_userdata {
+            if let Some(ud) = super::resolve_userdata(&self.table)? {
+                return Ok(Value::UserData(ud));
+            }
+        }
         Ok(Value::Table(self.table))
     }
 }
@@ -504,6 +689,8 @@ impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
 pub struct SerializeStruct<'lua> {
     lua: &'lua Lua,
     inner: Option<Value<'lua>>,
+    // Not set for `serde_json::Number`
+    nested: Option<NestedConversion<'lua>>,
     options: Options,
 }
 
@@ -517,12 +704,15 @@ impl<'lua> ser::SerializeStruct for SerializeStruct<'lua> {
     {
         match self.inner {
             Some(Value::Table(ref table)) => {
-                table.raw_set(key, self.lua.to_value_with(value, self.options)?)?;
+                if let Some(nested) = &self.nested {
+                    nested.count_element()?;
+                }
+                table.raw_set(key, to_value(self.lua, value, self.options)?)?;
             }
             None if self.options.detect_serde_json_arbitrary_precision => {
                 // A special case for `serde_json::Number` with arbitrary precision.
                 assert_eq!(key, "$serde_json::private::Number");
-                self.inner = Some(self.lua.to_value_with(value, self.options)?);
+                self.inner = Some(to_value(self.lua, value, self.options)?);
             }
             _ => unreachable!(),
         }
@@ -554,6 +744,7 @@ impl<'lua> ser::SerializeStruct for SerializeStruct<'lua> {
 pub struct SerializeStructVariant<'lua> {
     variant: &'static str,
     table: Table<'lua>,
+    nested: NestedConversion<'lua>,
     options: Options,
 }
 
@@ -565,9 +756,10 @@ impl<'lua> ser::SerializeStructVariant for SerializeStructVariant<'lua> {
     where
         T: Serialize + ?Sized,
     {
+        self.nested.count_element()?;
         let lua = self.table.0.lua;
         self.table
-            .raw_set(key, lua.to_value_with(value, self.options)?)?;
+            .raw_set(key, to_value(lua, value, self.options)?)?;
         Ok(())
     }
 
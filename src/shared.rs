@@ -0,0 +1,389 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::lua::Lua;
+use crate::util::CAN_BLOCK;
+
+impl Lua {
+    /// Asynchronously acquires the lock of the Lua state, waiting for other holders to release
+    /// it.
+    ///
+    /// The lock is advisory: it serializes access between tasks that share the state (on one
+    /// thread, or on many using [`SharedLua`]), yielding to the executor while waiting instead
+    /// of blocking the thread. Waiters acquire the lock in order of arrival.
+    ///
+    /// Requires `feature = "async"` and `feature = "send"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let task = |n: i32| {
+    ///     let lua = &lua;
+    ///     async move {
+    ///         let lua = lua.lock_async().await;
+    ///         lua.globals().set("value", n)?;
+    ///         tokio::task::yield_now().await;
+    ///         let value = lua.globals().get::<_, i32>("value")?;
+    ///         Ok::<_, mlua::Error>(value)
+    ///     }
+    /// };
+    /// let (a, b) = tokio::join!(task(1), task(2));
+    /// assert_eq!((a?, b?), (1, 2));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_async(&self) -> impl Future<Output = LuaLockGuard<'_>> + '_ {
+        let lock = LockFuture {
+            lock: self.state_lock(),
+            id: None,
+        };
+        async move {
+            lock.await;
+            LuaLockGuard { lua: self }
+        }
+    }
+
+    /// Acquires the lock of the Lua state if it's immediately available.
+    ///
+    /// See [`Lua::lock_async`] for details.
+    ///
+    /// Requires `feature = "async"` and `feature = "send"`
+    pub fn try_lock(&self) -> Option<LuaLockGuard<'_>> {
+        (self.state_lock().try_lock()).then(|| LuaLockGuard { lua: self })
+    }
+
+    /// Acquires the lock of the Lua state, blocking the current thread for at most `timeout`.
+    ///
+    /// Returns `None` if the lock could not be acquired in time. On targets that cannot block
+    /// (wasm without threads support) it's the same as [`Lua::try_lock`].
+    /// See [`Lua::lock_async`] for details.
+    ///
+    /// Requires `feature = "async"` and `feature = "send"`
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<LuaLockGuard<'_>> {
+        (self.state_lock().try_lock_for(timeout)).then(|| LuaLockGuard { lua: self })
+    }
+}
+
+// Fair (FIFO) lock of a Lua state, see `Lua::lock_async`
+pub(crate) struct StateLock {
+    state: Mutex<LockState>,
+    available: Condvar,
+}
+
+struct LockState {
+    locked: bool,
+    next_id: u64,
+    // Waiters in order of arrival, async waiters have a waker
+    waiters: VecDeque<(u64, Option<Waker>)>,
+}
+
+impl Default for StateLock {
+    fn default() -> Self {
+        StateLock {
+            state: Mutex::new(LockState {
+                locked: false,
+                next_id: 0,
+                waiters: VecDeque::new(),
+            }),
+            available: Condvar::new(),
+        }
+    }
+}
+
+impl StateLock {
+    fn lock(&self) -> MutexGuard<'_, LockState> {
+        mlua_expect!(self.state.lock(), "lock mutex poisoned")
+    }
+
+    fn try_lock(&self) -> bool {
+        let mut state = self.lock();
+        if state.locked || !state.waiters.is_empty() {
+            return false;
+        }
+        state.locked = true;
+        true
+    }
+
+    fn try_lock_for(&self, timeout: Duration) -> bool {
+        if !CAN_BLOCK {
+            return self.try_lock();
+        }
+        // Overflowing timeouts wait without a deadline
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.lock();
+        let id = match state.try_acquire_new() {
+            Ok(()) => return true,
+            Err(id) => id,
+        };
+        loop {
+            if state.try_acquire(id) {
+                return true;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.cancel(state, id);
+                        return false;
+                    }
+                    let res = self.available.wait_timeout(state, deadline - now);
+                    mlua_expect!(res, "lock mutex poisoned").0
+                }
+                None => mlua_expect!(self.available.wait(state), "lock mutex poisoned"),
+            };
+        }
+    }
+
+    fn unlock(&self) {
+        let mut state = self.lock();
+        state.locked = false;
+        self.notify_next(state);
+    }
+
+    // Wakes up the first waiter (if any) to let it acquire the lock
+    fn notify_next(&self, state: MutexGuard<LockState>) {
+        let waker = match state.waiters.front() {
+            Some((_, waker)) if !state.locked => waker.clone(),
+            _ => return,
+        };
+        drop(state);
+        self.available.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    // Removes a waiter that gave up waiting
+    fn cancel(&self, mut state: MutexGuard<LockState>, id: u64) {
+        state.waiters.retain(|(waiter_id, _)| *waiter_id != id);
+        self.notify_next(state);
+    }
+}
+
+impl LockState {
+    // Acquires the lock immediately if there are no waiters, otherwise joins the queue
+    fn try_acquire_new(&mut self) -> Result<(), u64> {
+        if !self.locked && self.waiters.is_empty() {
+            self.locked = true;
+            return Ok(());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiters.push_back((id, None));
+        Err(id)
+    }
+
+    // Acquires the lock if the waiter is the first in the queue
+    fn try_acquire(&mut self, id: u64) -> bool {
+        if !self.locked && matches!(self.waiters.front(), Some((front, _)) if *front == id) {
+            self.waiters.pop_front();
+            self.locked = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// Provides access to a Lua state while holding its lock.
+///
+/// The lock is released on drop. See [`Lua::lock_async`].
+///
+/// Requires `feature = "async"` and `feature = "send"`
+pub struct LuaLockGuard<'a> {
+    lua: &'a Lua,
+}
+
+impl<'a> Deref for LuaLockGuard<'a> {
+    type Target = Lua;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.lua
+    }
+}
+
+impl<'a> fmt::Debug for LuaLockGuard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("LuaLockGuard").field(self.lua).finish()
+    }
+}
+
+impl<'a> Drop for LuaLockGuard<'a> {
+    fn drop(&mut self) {
+        self.lua.state_lock().unlock();
+    }
+}
+
+struct LockFuture<'a> {
+    lock: &'a StateLock,
+    // Position in the waiters queue
+    id: Option<u64>,
+}
+
+impl<'a> Future for LockFuture<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.lock.lock();
+        let id = match self.id {
+            Some(id) => id,
+            None => match state.try_acquire_new() {
+                Ok(()) => return Poll::Ready(()),
+                Err(id) => {
+                    self.id = Some(id);
+                    id
+                }
+            },
+        };
+        if state.try_acquire(id) {
+            self.id = None;
+            return Poll::Ready(());
+        }
+        if let Some((_, waker)) = state
+            .waiters
+            .iter_mut()
+            .find(|(waiter_id, _)| *waiter_id == id)
+        {
+            *waker = Some(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for LockFuture<'a> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.lock.cancel(self.lock.lock(), id);
+        }
+    }
+}
+
+/// A Lua state shared between multiple threads and async tasks.
+///
+/// [`Lua`] is not `Sync`, so the shared state is only reachable through its lock
+/// ([`Lua::lock_async`], [`Lua::try_lock`] and [`Lua::try_lock_for`]).
+///
+/// Requires `feature = "async"` and `feature = "send"`
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, SharedLua};
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// let shared = SharedLua::new(Lua::new());
+///
+/// let handle = tokio::spawn({
+///     let shared = shared.clone();
+///     async move {
+///         let lua = shared.lock_async().await;
+///         lua.load("counter = 1").exec()
+///     }
+/// });
+/// handle.await.unwrap()?;
+///
+/// let lua = shared.lock_async().await;
+/// assert_eq!(lua.globals().get::<_, i32>("counter")?, 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedLua(Arc<SharedInner>);
+
+struct SharedInner(Lua);
+
+// `Lua` is `Send` and the shared state is only accessed through `SharedLuaGuard`s while holding
+// its lock, which makes `SharedInner` equivalent to `Mutex<Lua>` (the lock itself is `Sync`)
+unsafe impl Sync for SharedInner {}
+
+impl SharedLua {
+    /// Wraps a Lua state to share it between threads and async tasks.
+    pub fn new(lua: Lua) -> Self {
+        SharedLua(Arc::new(SharedInner(lua)))
+    }
+
+    /// Asynchronously acquires the lock of the state.
+    ///
+    /// See [`Lua::lock_async`].
+    pub fn lock_async(&self) -> impl Future<Output = SharedLuaGuard<'_>> + '_ {
+        let lock = LockFuture {
+            lock: self.0 .0.state_lock(),
+            id: None,
+        };
+        async move {
+            lock.await;
+            SharedLuaGuard { shared: self }
+        }
+    }
+
+    /// Acquires the lock of the state if it's immediately available.
+    ///
+    /// See [`Lua::try_lock`].
+    pub fn try_lock(&self) -> Option<SharedLuaGuard<'_>> {
+        (self.0 .0.state_lock().try_lock()).then(|| SharedLuaGuard { shared: self })
+    }
+
+    /// Acquires the lock of the state, blocking the current thread for at most `timeout`.
+    ///
+    /// See [`Lua::try_lock_for`].
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<SharedLuaGuard<'_>> {
+        let locked = self.0 .0.state_lock().try_lock_for(timeout);
+        locked.then(|| SharedLuaGuard { shared: self })
+    }
+}
+
+impl From<Lua> for SharedLua {
+    fn from(lua: Lua) -> Self {
+        SharedLua::new(lua)
+    }
+}
+
+impl fmt::Debug for SharedLua {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.0 .0.state_lock().lock();
+        f.debug_struct("SharedLua")
+            .field("locked", &state.locked)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+/// Provides access to a [`SharedLua`] state while holding its lock.
+///
+/// The lock is released on drop.
+///
+/// Requires `feature = "async"` and `feature = "send"`
+pub struct SharedLuaGuard<'a> {
+    shared: &'a SharedLua,
+}
+
+impl<'a> Deref for SharedLuaGuard<'a> {
+    type Target = Lua;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.shared.0 .0
+    }
+}
+
+impl<'a> fmt::Debug for SharedLuaGuard<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SharedLuaGuard").field(&**self).finish()
+    }
+}
+
+impl<'a> Drop for SharedLuaGuard<'a> {
+    fn drop(&mut self) {
+        self.shared.0 .0.state_lock().unlock();
+    }
+}
@@ -0,0 +1,109 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SIGNATURE_LENGTH};
+
+use crate::chunk::{Chunk, ChunkMode};
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+
+#[cfg(not(feature = "luau"))]
+use crate::function::Function;
+
+/// Precompiled Lua bytecode, which can be signed to be shipped to untrusted environments.
+///
+/// The signed form is the 64 bytes ed25519 signature of the bytecode followed by the bytecode
+/// itself. It's loaded with [`Lua::load_signed`], which refuses to parse bytecode that was not
+/// signed by the expected key.
+///
+/// Requires `feature = "signing"`
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{CompiledChunk, Lua, Result};
+/// # use mlua::ed25519_dalek::SigningKey;
+/// # #[cfg(not(feature = "luau"))]
+/// # fn main() -> Result<()> {
+/// let key = SigningKey::from_bytes(&[7; 32]);
+///
+/// // Build time
+/// let lua = Lua::new();
+/// let func = lua.load("return 1 + 2").into_function()?;
+/// let signed = CompiledChunk::from_function(&func, true).sign(&key);
+///
+/// // Client
+/// let lua = Lua::new();
+/// let sum: i32 = lua.load_signed(&signed, &key.verifying_key())?.call(())?;
+/// assert_eq!(sum, 3);
+/// # Ok(())
+/// # }
+/// # #[cfg(feature = "luau")]
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CompiledChunk {
+    bytecode: Vec<u8>,
+}
+
+impl CompiledChunk {
+    /// Wraps the bytecode produced by [`Function::dump`] or the Luau [`Compiler`].
+    ///
+    /// [`Function::dump`]: crate::Function::dump
+    /// [`Compiler`]: crate::Compiler
+    pub fn new(bytecode: impl Into<Vec<u8>>) -> Self {
+        CompiledChunk {
+            bytecode: bytecode.into(),
+        }
+    }
+
+    /// Dumps the function to bytecode.
+    ///
+    /// See [`Function::dump`] for the meaning of `strip`.
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn from_function(func: &Function, strip: bool) -> Self {
+        Self::new(func.dump(strip))
+    }
+
+    /// Returns the bytecode.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// Consumes the chunk, returning the bytecode.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytecode
+    }
+
+    /// Signs the bytecode with the key, returning the signed chunk.
+    pub fn sign(&self, key: &SigningKey) -> Vec<u8> {
+        let signature = key.sign(&self.bytecode);
+        let mut signed = Vec::with_capacity(SIGNATURE_LENGTH + self.bytecode.len());
+        signed.extend_from_slice(&signature.to_bytes());
+        signed.extend_from_slice(&self.bytecode);
+        signed
+    }
+}
+
+impl Lua {
+    /// Verifies a chunk signed by [`CompiledChunk::sign`] and loads its bytecode.
+    ///
+    /// Returns an error without passing the bytecode to Lua if the signature does not match
+    /// the public key.
+    ///
+    /// Requires `feature = "signing"`
+    pub fn load_signed<'lua, 'a>(
+        &'lua self,
+        bytes: &'a [u8],
+        public_key: &VerifyingKey,
+    ) -> Result<Chunk<'lua, 'a>> {
+        if bytes.len() < SIGNATURE_LENGTH {
+            return Err(Error::runtime("signed chunk is too short"));
+        }
+        let (signature, bytecode) = bytes.split_at(SIGNATURE_LENGTH);
+        let signature = Signature::from_slice(signature)
+            .map_err(|_| Error::runtime("invalid chunk signature"))?;
+        public_key
+            .verify(bytecode, &signature)
+            .map_err(|_| Error::runtime("invalid chunk signature"))?;
+        Ok(self.load(bytecode).set_mode(ChunkMode::Binary))
+    }
+}
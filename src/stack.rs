@@ -0,0 +1,137 @@
+use std::fmt;
+use std::os::raw::c_int;
+use std::panic::Location;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::util::check_stack;
+use crate::value::{FromLua, IntoLua, Nil};
+
+/// Usage statistics of the Lua stacks used by mlua, returned by [`Lua::stack_stats`].
+///
+/// Values are in stack slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackStats {
+    /// Slots used in the stack of the current thread.
+    pub current: usize,
+    /// Live references (eg. [`Table`] or [`Function`] handles) held in the auxiliary thread.
+    ///
+    /// [`Table`]: crate::Table
+    /// [`Function`]: crate::Function
+    pub ref_used: usize,
+    /// Peak number of live references since the state creation.
+    pub ref_peak: usize,
+    /// Slots reserved in the auxiliary thread.
+    pub ref_capacity: usize,
+    /// Deepest slot reserved by mlua in the stack of any thread.
+    ///
+    /// Tracked only with `feature = "stack_diagnostics"` and not in module mode (zero
+    /// otherwise).
+    pub peak: usize,
+    /// Call site of the mlua API that reserved the [`peak`] slot.
+    ///
+    /// [`peak`]: StackStats::peak
+    pub peak_location: Option<&'static Location<'static>>,
+}
+
+/// Direct access to the Lua stack of a function created by [`Lua::create_raw_function`].
+///
+/// Positions are 1-based and relative to the first argument of the function. Values pushed by
+/// the function are placed after the arguments.
+pub struct Stack<'lua> {
+    lua: &'lua Lua,
+    state: *mut ffi::lua_State,
+    base: c_int,
+    nargs: c_int,
+}
+
+impl<'lua> Stack<'lua> {
+    pub(crate) unsafe fn new(lua: &'lua Lua, nargs: c_int) -> Self {
+        let state = lua.state();
+        Stack {
+            lua,
+            state,
+            base: ffi::lua_gettop(state) - nargs,
+            nargs,
+        }
+    }
+
+    /// Returns the number of arguments passed to the function.
+    #[inline]
+    pub fn nargs(&self) -> usize {
+        self.nargs as usize
+    }
+
+    /// Returns the number of values on the stack (arguments and pushed values).
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { (ffi::lua_gettop(self.state) - self.base).max(0) as usize }
+    }
+
+    /// Returns `true` if the stack has no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Converts the value at position `pos` to `V` without removing it.
+    ///
+    /// Positions outside of the stack are treated as `nil`, like missing arguments in Lua.
+    pub fn get<V: FromLua<'lua>>(&self, pos: usize) -> Result<V> {
+        if pos == 0 || pos > self.len() {
+            return V::from_lua_arg(Nil, pos, None, self.lua);
+        }
+        unsafe { V::from_stack_arg(self.base + pos as c_int, pos, None, self.lua) }
+    }
+
+    /// Pushes a value on top of the stack.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn push<V: IntoLua<'lua>>(&mut self, value: V) -> Result<()> {
+        unsafe {
+            check_stack(self.state, 3)?;
+            value.push_into_stack(self.lua)
+        }
+    }
+
+    /// Removes the value from the top of the stack and converts it to `V`.
+    ///
+    /// Returns an error if the stack is empty.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn pop<V: FromLua<'lua>>(&mut self) -> Result<V> {
+        if self.is_empty() {
+            return Err(Error::RuntimeError("stack is empty".to_string()));
+        }
+        unsafe {
+            check_stack(self.state, 2)?;
+            let value = V::from_stack(-1, self.lua);
+            ffi::lua_pop(self.state, 1);
+            value
+        }
+    }
+
+    /// Shortens the stack, keeping the first `len` values and dropping the rest.
+    ///
+    /// Has no effect if `len` is greater than the current stack length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            unsafe { ffi::lua_settop(self.state, self.base + len as c_int) };
+        }
+    }
+
+    /// Returns the underlying raw Lua state.
+    ///
+    /// Any direct manipulation of the stack must keep the values below the arguments intact.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut ffi::lua_State {
+        self.state
+    }
+}
+
+impl<'lua> fmt::Debug for Stack<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stack")
+            .field("nargs", &self.nargs)
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::userdata::{AnyUserData, UserData, UserDataMethods};
+use crate::value::{MultiValue, Value};
+
+#[cfg(feature = "send")]
+pub(crate) type WriteStream = Box<dyn Write + Send>;
+#[cfg(not(feature = "send"))]
+pub(crate) type WriteStream = Box<dyn Write>;
+
+#[cfg(feature = "send")]
+pub(crate) type ReadStream = Box<dyn Read + Send>;
+#[cfg(not(feature = "send"))]
+pub(crate) type ReadStream = Box<dyn Read>;
+
+// Host-provided standard streams of a Lua state
+struct Stdio {
+    stdout: Mutex<WriteStream>,
+    stderr: Mutex<WriteStream>,
+    stdin: Mutex<BufReader<ReadStream>>,
+}
+
+impl Stdio {
+    fn stdout(&self) -> MutexGuard<'_, WriteStream> {
+        self.stdout.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn stderr(&self) -> MutexGuard<'_, WriteStream> {
+        self.stderr.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn stdin(&self) -> MutexGuard<'_, BufReader<ReadStream>> {
+        self.stdin.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StreamKind {
+    Stdout,
+    Stderr,
+    Stdin,
+}
+
+// Replacement of the `io.stdout`, `io.stderr` and `io.stdin` file objects
+struct StdStream {
+    stdio: Arc<Stdio>,
+    kind: StreamKind,
+}
+
+impl StdStream {
+    fn writer(&self) -> Result<MutexGuard<'_, WriteStream>> {
+        match self.kind {
+            StreamKind::Stdout => Ok(self.stdio.stdout()),
+            StreamKind::Stderr => Ok(self.stdio.stderr()),
+            StreamKind::Stdin => Err(Error::runtime("cannot write to standard input")),
+        }
+    }
+
+    fn check_readable(&self) -> Result<()> {
+        match self.kind {
+            StreamKind::Stdin => Ok(()),
+            _ => Err(Error::runtime("cannot read from standard output")),
+        }
+    }
+}
+
+impl UserData for StdStream {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("write", |lua, (ud, args): (AnyUserData, MultiValue)| {
+            write_values(lua, &mut *ud.borrow::<Self>()?.writer()?, args)?;
+            Ok(ud)
+        });
+
+        methods.add_method("read", |lua, this, formats: MultiValue| {
+            this.check_readable()?;
+            read_formats(lua, &mut this.stdio.stdin(), &parse_formats(formats)?)
+        });
+
+        methods.add_method("lines", |lua, this, formats: MultiValue| {
+            this.check_readable()?;
+            lines_iter(lua, this.stdio.clone(), formats)
+        });
+
+        methods.add_method("flush", |_, this, ()| match this.kind {
+            StreamKind::Stdin => Ok(()),
+            _ => this.writer()?.flush().map_err(io_error),
+        });
+
+        methods.add_method("setvbuf", |_, _, ()| Ok(true));
+
+        methods.add_method("close", |_, _, ()| -> Result<()> {
+            Err(Error::runtime("cannot close standard file"))
+        });
+    }
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::RuntimeError(err.to_string())
+}
+
+#[allow(clippy::arc_with_non_send_sync)]
+pub(crate) fn set_stdio(
+    lua: &Lua,
+    stdout: WriteStream,
+    stderr: WriteStream,
+    stdin: ReadStream,
+) -> Result<()> {
+    let stdio = Arc::new(Stdio {
+        stdout: Mutex::new(stdout),
+        stderr: Mutex::new(stderr),
+        stdin: Mutex::new(BufReader::new(stdin)),
+    });
+    let globals = lua.globals();
+
+    let st = stdio.clone();
+    let print = lua.create_function(move |lua, args: MultiValue| {
+        let mut stdout = st.stdout();
+        for (i, value) in args.into_iter().enumerate() {
+            if i > 0 {
+                stdout.write_all(b"\t").map_err(io_error)?;
+            }
+            match value {
+                Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                    let s = lua.coerce_string(value)?.unwrap();
+                    stdout.write_all(s.as_bytes())
+                }
+                value => stdout.write_all(value.to_string()?.as_bytes()),
+            }
+            .map_err(io_error)?;
+        }
+        stdout.write_all(b"\n").map_err(io_error)?;
+        stdout.flush().map_err(io_error)
+    })?;
+    globals.raw_set("print", print)?;
+
+    let Some(io) = globals.raw_get::<_, Option<Table>>("io")? else {
+        return Ok(());
+    };
+
+    let stream = |kind| {
+        let stdio = stdio.clone();
+        lua.create_userdata(StdStream { stdio, kind })
+    };
+    let stdout = stream(StreamKind::Stdout)?;
+    io.raw_set("stdout", &stdout)?;
+    io.raw_set("stderr", stream(StreamKind::Stderr)?)?;
+    io.raw_set("stdin", stream(StreamKind::Stdin)?)?;
+
+    let stdout = lua.create_registry_value(stdout)?;
+    let st = stdio.clone();
+    let write = lua.create_function(move |lua, args: MultiValue| {
+        write_values(lua, &mut *st.stdout(), args)?;
+        lua.registry_value::<AnyUserData>(&stdout)
+    })?;
+    io.raw_set("write", write)?;
+
+    let st = stdio.clone();
+    let read = lua.create_function(move |lua, formats: MultiValue| {
+        read_formats(lua, &mut st.stdin(), &parse_formats(formats)?)
+    })?;
+    io.raw_set("read", read)?;
+
+    // `io.lines` without a file name reads from the standard input
+    if let Some(lines) = io.raw_get::<_, Option<Function>>("lines")? {
+        let lines = lua.create_registry_value(lines)?;
+        let lines = lua.create_function(move |lua, mut args: MultiValue| {
+            if let None | Some(Value::Nil) = args.get(0) {
+                args.pop_front();
+                return lines_iter(lua, stdio.clone(), args).map(Value::Function);
+            }
+            lua.registry_value::<Function>(&lines)?.call(args)
+        })?;
+        io.raw_set("lines", lines)?;
+    }
+
+    Ok(())
+}
+
+fn write_values(lua: &Lua, out: &mut dyn Write, args: MultiValue) -> Result<()> {
+    for (i, value) in args.into_iter().enumerate() {
+        match value {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                let s = lua.coerce_string(value)?.unwrap();
+                out.write_all(s.as_bytes()).map_err(io_error)?;
+            }
+            value => {
+                let msg = format!(
+                    "bad argument #{} to 'write' (string expected, got {})",
+                    i + 1,
+                    value.type_name()
+                );
+                return Err(Error::RuntimeError(msg));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lines_iter<'lua>(
+    lua: &'lua Lua,
+    stdio: Arc<Stdio>,
+    formats: MultiValue,
+) -> Result<Function<'lua>> {
+    let formats = parse_formats(formats)?;
+    lua.create_function(move |lua, ()| read_formats(lua, &mut stdio.stdin(), &formats))
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Number,
+    Line { keep_newline: bool },
+    All,
+    Count(usize),
+}
+
+fn parse_formats(formats: MultiValue) -> Result<Vec<Format>> {
+    let mut result = Vec::with_capacity(formats.len());
+    for format in formats {
+        result.push(match format {
+            Value::Integer(n) => Format::Count(n.max(0) as usize),
+            Value::Number(n) => Format::Count(n.max(0.) as usize),
+            format => match format.to_string()?.trim_start_matches('*').chars().next() {
+                Some('n') => Format::Number,
+                Some('l') => Format::Line {
+                    keep_newline: false,
+                },
+                Some('L') => Format::Line { keep_newline: true },
+                Some('a') => Format::All,
+                _ => return Err(Error::runtime("bad argument to 'read' (invalid format)")),
+            },
+        });
+    }
+    if result.is_empty() {
+        result.push(Format::Line {
+            keep_newline: false,
+        });
+    }
+    Ok(result)
+}
+
+// Reads values in the given formats, stopping at the first failure (`nil`)
+fn read_formats<'lua>(
+    lua: &'lua Lua,
+    reader: &mut BufReader<ReadStream>,
+    formats: &[Format],
+) -> Result<MultiValue<'lua>> {
+    let mut values = Vec::with_capacity(formats.len());
+    for &format in formats {
+        let value = read_format(lua, reader, format)?;
+        let is_nil = value.is_nil();
+        values.push(value);
+        if is_nil {
+            break;
+        }
+    }
+    Ok(MultiValue::from_vec(values))
+}
+
+fn read_format<'lua>(
+    lua: &'lua Lua,
+    reader: &mut BufReader<ReadStream>,
+    format: Format,
+) -> Result<Value<'lua>> {
+    let mut data = Vec::new();
+    match format {
+        Format::Line { keep_newline } => {
+            if reader.read_until(b'\n', &mut data).map_err(io_error)? == 0 {
+                return Ok(Value::Nil);
+            }
+            if !keep_newline && data.last() == Some(&b'\n') {
+                data.pop();
+            }
+        }
+        Format::All => {
+            reader.read_to_end(&mut data).map_err(io_error)?;
+        }
+        Format::Count(n) => {
+            (reader.take(n as u64))
+                .read_to_end(&mut data)
+                .map_err(io_error)?;
+            // Reading zero bytes tests for the end of file
+            if data.is_empty() && (n > 0 || reader.fill_buf().map_err(io_error)?.is_empty()) {
+                return Ok(Value::Nil);
+            }
+        }
+        Format::Number => {
+            // Skip leading whitespace and collect the numeral
+            loop {
+                let buf = reader.fill_buf().map_err(io_error)?;
+                let Some(&c) = buf.first() else { break };
+                match c {
+                    _ if data.is_empty() && c.is_ascii_whitespace() => {}
+                    b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => data.push(c),
+                    b'.' | b'+' | b'-' | b'x' | b'X' | b'p' | b'P' => data.push(c),
+                    _ => break,
+                }
+                reader.consume(1);
+            }
+            let numeral = lua.create_string(&data)?;
+            if let Ok(Some(i)) = std::str::from_utf8(&data).map(|s| s.parse().ok()) {
+                return Ok(Value::Integer(i));
+            }
+            return Ok(match lua.coerce_number(Value::String(numeral))? {
+                Some(n) => Value::Number(n),
+                None => Value::Nil,
+            });
+        }
+    }
+    Ok(Value::String(lua.create_string(data)?))
+}
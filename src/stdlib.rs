@@ -20,11 +20,21 @@ impl StdLib {
     pub const TABLE: StdLib = StdLib(1 << 1);
 
     /// [`io`](https://www.lua.org/manual/5.4/manual.html#6.8) library
-    #[cfg(not(feature = "luau"))]
-    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    ///
+    /// Requires `feature = "filesystem"`. Not loaded if [`LuaOptions::fs_access`] is disabled.
+    ///
+    /// [`LuaOptions::fs_access`]: crate::LuaOptions::fs_access
+    #[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+    #[cfg_attr(docsrs, doc(cfg(all(not(feature = "luau"), feature = "filesystem"))))]
     pub const IO: StdLib = StdLib(1 << 2);
 
     /// [`os`](https://www.lua.org/manual/5.4/manual.html#6.9) library
+    ///
+    /// Without `feature = "filesystem"` or if [`LuaOptions::fs_access`] is disabled, filesystem
+    /// and process functions (`os.execute`,
+    /// `os.exit`, `os.remove`, `os.rename` and `os.tmpname`) are not available.
+    ///
+    /// [`LuaOptions::fs_access`]: crate::LuaOptions::fs_access
     pub const OS: StdLib = StdLib(1 << 3);
 
     /// [`string`](https://www.lua.org/manual/5.4/manual.html#6.4) library
@@ -46,6 +56,12 @@ impl StdLib {
     pub const MATH: StdLib = StdLib(1 << 7);
 
     /// [`package`](https://www.lua.org/manual/5.4/manual.html#6.3) library
+    ///
+    /// Without `feature = "filesystem"` or if [`LuaOptions::fs_access`] is disabled, modules can
+    /// be loaded only from `package.preload` (or `package.loaded`), path-based searchers and
+    /// `package.loadlib` are not available.
+    ///
+    /// [`LuaOptions::fs_access`]: crate::LuaOptions::fs_access
     pub const PACKAGE: StdLib = StdLib(1 << 8);
 
     /// [`buffer`](https://luau-lang.org/library#buffer-library) library
@@ -60,6 +76,15 @@ impl StdLib {
     #[cfg_attr(docsrs, doc(cfg(feature = "luajit")))]
     pub const JIT: StdLib = StdLib(1 << 9);
 
+    /// Safe subset of the [`debug`](https://www.lua.org/manual/5.4/manual.html#6.10) library
+    ///
+    /// Provides only `debug.traceback` and `debug.getinfo` (returning a limited set of fields),
+    /// allowing scripts to produce error reports without gaining access to the internals of
+    /// functions and values. Ignored if the full `debug` library is loaded at the same time.
+    ///
+    /// Not included in [`StdLib::ALL_SAFE`].
+    pub const DEBUG_SAFE: StdLib = StdLib(1 << 29);
+
     /// (**unsafe**) [`ffi`](http://luajit.org/ext_ffi.html) library
     ///
     /// Requires `feature = "luajit"`
@@ -76,13 +101,53 @@ impl StdLib {
     pub const ALL: StdLib = StdLib(u32::MAX);
     /// The safe subset of the standard libraries
     #[cfg(not(feature = "luau"))]
-    pub const ALL_SAFE: StdLib = StdLib((1 << 30) - 1);
+    pub const ALL_SAFE: StdLib = StdLib((1 << 29) - 1);
     #[cfg(feature = "luau")]
     pub const ALL_SAFE: StdLib = StdLib(u32::MAX);
 
     pub fn contains(self, lib: Self) -> bool {
         (self & lib).0 != 0
     }
+
+    // Returns names of the global tables of the libraries
+    pub(crate) fn names(self) -> Vec<&'static str> {
+        let libs: &[(StdLib, &str)] = &[
+            #[cfg(any(
+                feature = "lua54",
+                feature = "lua53",
+                feature = "lua52",
+                feature = "luau"
+            ))]
+            (StdLib::COROUTINE, "coroutine"),
+            (StdLib::TABLE, "table"),
+            #[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+            (StdLib::IO, "io"),
+            (StdLib::OS, "os"),
+            (StdLib::STRING, "string"),
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+            (StdLib::UTF8, "utf8"),
+            #[cfg(any(feature = "lua52", feature = "luau"))]
+            (StdLib::BIT, "bit32"),
+            #[cfg(feature = "luajit")]
+            (StdLib::BIT, "bit"),
+            #[cfg(feature = "luau")]
+            (StdLib::BUFFER, "buffer"),
+            (StdLib::MATH, "math"),
+            (StdLib::PACKAGE, "package"),
+            #[cfg(feature = "luajit")]
+            (StdLib::JIT, "jit"),
+            #[cfg(feature = "luajit")]
+            (StdLib::FFI, "ffi"),
+            (StdLib::DEBUG_SAFE, "debug"),
+            (StdLib::DEBUG, "debug"),
+        ];
+        let mut names: Vec<_> = (libs.iter())
+            .filter(|(lib, _)| self.contains(*lib))
+            .map(|(_, name)| *name)
+            .collect();
+        names.dedup();
+        names
+    }
 }
 
 impl BitAnd for StdLib {
@@ -1,5 +1,6 @@
 use std::borrow::{Borrow, Cow};
 use std::hash::{Hash, Hasher};
+use std::ops::Deref;
 use std::os::raw::c_void;
 use std::string::String as StdString;
 use std::{fmt, slice, str};
@@ -91,6 +92,39 @@ impl<'lua> String<'lua> {
         StdString::from_utf8_lossy(self.as_bytes())
     }
 
+    /// Returns a [`BorrowedStr`] guard that can be used as `&str`.
+    ///
+    /// Valid UTF-8 strings are borrowed directly from Lua memory without copying. Any non-Unicode
+    /// sequences are replaced with [`U+FFFD REPLACEMENT CHARACTER`][U+FFFD], which requires a copy.
+    ///
+    /// [U+FFFD]: std::char::REPLACEMENT_CHARACTER
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result, String};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let handlers = HashMap::from([("jump", 1), ("run", 2)]);
+    ///
+    /// let action: String = lua.load("'jump'").eval()?;
+    /// assert_eq!(handlers.get(&*action.as_str_lossy()), Some(&1));
+    /// assert_eq!(action.as_str_lossy(), "jump");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn as_str_lossy(&self) -> BorrowedStr<'lua> {
+        let lossy = match StdString::from_utf8_lossy(self.as_bytes()) {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(s) => Some(s),
+        };
+        BorrowedStr {
+            string: self.clone(),
+            lossy,
+        }
+    }
+
     /// Get the bytes that make up this string.
     ///
     /// The returned slice will not contain the terminating nul byte, but will contain any nul
@@ -230,6 +264,86 @@ impl<'lua> Serialize for String<'lua> {
     }
 }
 
+/// A `&str` view of a Lua string, returned by [`String::as_str_lossy`].
+///
+/// It keeps the Lua string alive and dereferences to its contents, so it can be compared with
+/// Rust strings or used to look up maps keyed by strings without copying.
+#[derive(Clone)]
+pub struct BorrowedStr<'lua> {
+    string: String<'lua>,
+    // Contents with the non-Unicode sequences replaced, if any
+    lossy: Option<StdString>,
+}
+
+impl<'lua> BorrowedStr<'lua> {
+    /// Returns the underlying Lua string.
+    pub fn as_lua_string(&self) -> &String<'lua> {
+        &self.string
+    }
+
+    /// Returns `true` if the Lua string is not valid UTF-8 and its contents were replaced.
+    pub fn is_lossy(&self) -> bool {
+        self.lossy.is_some()
+    }
+}
+
+impl<'lua> Deref for BorrowedStr<'lua> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        match self.lossy {
+            Some(ref s) => s,
+            // Checked to be valid UTF-8 when created
+            None => unsafe { str::from_utf8_unchecked(self.string.as_bytes()) },
+        }
+    }
+}
+
+impl<'lua> AsRef<str> for BorrowedStr<'lua> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl<'lua> Borrow<str> for BorrowedStr<'lua> {
+    #[inline]
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl<'lua> fmt::Debug for BorrowedStr<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'lua> fmt::Display for BorrowedStr<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'lua, T> PartialEq<T> for BorrowedStr<'lua>
+where
+    T: AsRef<str> + ?Sized,
+{
+    fn eq(&self, other: &T) -> bool {
+        **self == *other.as_ref()
+    }
+}
+
+impl<'lua> Eq for BorrowedStr<'lua> {}
+
+// Must be consistent with `str` to look up maps using the `Borrow<str>` impl
+impl<'lua> Hash for BorrowedStr<'lua> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
 // Additional shortcuts
 #[cfg(feature = "unstable")]
 impl OwnedString {
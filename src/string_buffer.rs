@@ -0,0 +1,253 @@
+use std::os::raw::{c_char, c_int};
+use std::{ptr, slice};
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::types::SubtypeId;
+use crate::userdata::AnyUserData;
+use crate::util::{check_stack, StackGuard};
+use crate::value::{FromLua, IntoLua, Value};
+
+/// Handle to a LuaJIT [string buffer] object.
+///
+/// String buffers are mutable byte buffers used by LuaJIT's serialization and
+/// buffer-based libraries. Data is written directly into the buffer memory, without creating
+/// intermediate Lua strings.
+///
+/// Requires `feature = "luajit"`
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, StringBuffer};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let buf = lua.create_string_buffer()?;
+/// buf.put(b"hello")?;
+/// lua.globals().set("buf", buf)?;
+///
+/// lua.load(r#"buf:put(", world")"#).exec()?;
+/// let buf: StringBuffer = lua.globals().get("buf")?;
+/// assert_eq!(buf.to_vec()?, b"hello, world");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [string buffer]: https://luajit.org/ext_buffer.html
+#[derive(Clone, Debug)]
+pub struct StringBuffer<'lua>(pub(crate) AnyUserData<'lua>);
+
+impl<'lua> StringBuffer<'lua> {
+    /// Appends `data` to the buffer.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn put(&self, data: impl AsRef<[u8]>) -> Result<()> {
+        let data = data.as_ref();
+        let lua = self.0 .0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            lua.push_ref(&self.0 .0);
+            protect_lua!(state, 1, 0, |state| {
+                // `reserve` returns a pointer to the writable space
+                call_method(state, cstr!("reserve"), data.len(), 1);
+                let buf = *(ffi::lua_topointer(state, -1) as *const *mut u8);
+                ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+                ffi::lua_pop(state, 1);
+                call_method(state, cstr!("commit"), data.len(), 0);
+            })
+        }
+    }
+
+    /// Calls `f` with the buffer contents, without copying them.
+    ///
+    /// # Safety
+    ///
+    /// The slice borrows the buffer memory directly. `f` must not modify the buffer (eg. call
+    /// [`put`] or [`reset`]) or run Lua code, which could reallocate or free that memory while
+    /// the slice is alive. Use [`to_vec`] to get a copy of the contents instead.
+    ///
+    /// [`put`]: #method.put
+    /// [`reset`]: #method.reset
+    /// [`to_vec`]: #method.to_vec
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub unsafe fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        let (buf, len) = self.contents()?;
+        match len {
+            0 => Ok(f(&[])),
+            _ => Ok(f(slice::from_raw_parts(buf, len))),
+        }
+    }
+
+    /// Returns a copy of the buffer contents.
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        unsafe { self.with_bytes(|bytes| bytes.to_vec()) }
+    }
+
+    /// Returns a copy of the buffer contents as [`Bytes`].
+    ///
+    /// Requires `feature = "bytes"`
+    ///
+    /// [`Bytes`]: bytes::Bytes
+    #[cfg(feature = "bytes")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+    pub fn to_bytes(&self) -> Result<Bytes> {
+        unsafe { self.with_bytes(Bytes::copy_from_slice) }
+    }
+
+    /// Returns the length of the buffer contents (in bytes).
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.contents()?.1)
+    }
+
+    /// Returns `true` if the buffer is empty.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Removes the buffer contents, keeping the allocated memory.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn reset(&self) -> Result<()> {
+        let lua = self.0 .0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            lua.push_ref(&self.0 .0);
+            protect_lua!(state, 1, 0, |state| {
+                ffi::lua_getfield(state, -1, cstr!("reset"));
+                ffi::lua_pushvalue(state, -2);
+                ffi::lua_call(state, 1, 0);
+            })
+        }
+    }
+
+    // Returns a pointer to the buffer contents and their length
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn contents(&self) -> Result<(*const u8, usize)> {
+        let lua = self.0 .0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            lua.push_ref(&self.0 .0);
+            protect_lua!(state, 1, 0, |state| {
+                // `ref` returns a pointer to the contents and their length
+                call_method(state, cstr!("ref"), 0, 2);
+                let buf = *(ffi::lua_topointer(state, -2) as *const *const u8);
+                let len = ffi::lua_tointeger(state, -1) as usize;
+                ffi::lua_pop(state, 2);
+                (buf, len)
+            })
+        }
+    }
+
+    /// Returns the underlying userdata.
+    pub fn into_userdata(self) -> AnyUserData<'lua> {
+        self.0
+    }
+}
+
+// Calls `buf:name(arg)` on the buffer at the top of the stack
+unsafe fn call_method(
+    state: *mut ffi::lua_State,
+    name: *const c_char,
+    arg: usize,
+    nresults: c_int,
+) {
+    ffi::lua_getfield(state, -1, name);
+    ffi::lua_pushvalue(state, -2);
+    ffi::lua_pushinteger(state, arg as ffi::lua_Integer);
+    ffi::lua_call(state, 2, nresults);
+}
+
+impl Lua {
+    /// Creates and returns a new empty LuaJIT [string buffer].
+    ///
+    /// Requires `feature = "luajit"`
+    ///
+    /// [string buffer]: https://luajit.org/ext_buffer.html
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn create_string_buffer(&self) -> Result<StringBuffer> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            protect_lua!(state, 0, 1, fn(state) {
+                // The module is not registered in `package.loaded` when opened
+                ffi::luaL_getsubtable(state, ffi::LUA_REGISTRYINDEX, cstr!("_LOADED"));
+                if ffi::lua_getfield(state, -1, cstr!("string.buffer")) == ffi::LUA_TNIL {
+                    ffi::lua_pop(state, 1);
+                    ffi::lua_pushcfunction(state, ffi::luaopen_string_buffer);
+                    ffi::lua_call(state, 0, 1);
+                    ffi::lua_pushvalue(state, -1);
+                    ffi::lua_setfield(state, -3, cstr!("string.buffer"));
+                }
+                ffi::lua_getfield(state, -1, cstr!("new"));
+                ffi::lua_call(state, 0, 1);
+            })?;
+            Ok(StringBuffer(AnyUserData(self.pop_ref(), SubtypeId::None)))
+        }
+    }
+
+    /// Creates a LuaJIT [string buffer] filled with `data`.
+    ///
+    /// Requires `feature = "luajit"`
+    ///
+    /// [string buffer]: https://luajit.org/ext_buffer.html
+    pub fn create_string_buffer_from(&self, data: impl AsRef<[u8]>) -> Result<StringBuffer> {
+        let buf = self.create_string_buffer()?;
+        buf.put(data)?;
+        Ok(buf)
+    }
+}
+
+impl<'lua> IntoLua<'lua> for StringBuffer<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::UserData(self.0))
+    }
+}
+
+impl<'lua> FromLua<'lua> for StringBuffer<'lua> {
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        let type_name = value.type_name();
+        if let Value::UserData(ud) = value {
+            if is_string_buffer(lua, &ud)? {
+                return Ok(StringBuffer(ud));
+            }
+        }
+        Err(Error::FromLuaConversionError {
+            from: type_name,
+            to: "StringBuffer",
+            message: None,
+        })
+    }
+}
+
+// Buffer objects have the `__metatable` field set to "buffer"
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
+fn is_string_buffer(lua: &Lua, ud: &AnyUserData) -> Result<bool> {
+    let state = lua.state();
+    unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 2)?;
+
+        lua.push_ref(&ud.0);
+        if ffi::luaL_getmetafield(state, -1, cstr!("__metatable")) == 0 {
+            return Ok(false);
+        }
+        let mut len = 0;
+        let name = ffi::lua_tolstring(state, -1, &mut len);
+        Ok(!name.is_null() && slice::from_raw_parts(name as *const u8, len) == b"buffer")
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::types::LuaRef;
+use crate::util::{check_stack, StackGuard};
+
+const MIN_CAPACITY: usize = 64;
+
+/// An incremental builder of a Lua string.
+///
+/// Appended data is accumulated in a growable block of Lua-managed memory (a userdata, or a
+/// [buffer] object in Luau), similar to how `luaL_Buffer` works. The final string is interned only
+/// once in [`StringBuilder::build`], so generating a big string from many pieces neither
+/// concatenates intermediate Lua strings nor requires a temporary Rust allocation.
+///
+/// The memory used by the builder is accounted against the Lua memory limit.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// use std::fmt::Write;
+///
+/// let lua = Lua::new();
+/// let mut builder = lua.create_string_builder();
+/// builder.append("return {")?;
+/// for i in 1..=3 {
+///     write!(builder, "{i},").unwrap();
+/// }
+/// builder.append(b"}")?;
+///
+/// let source = builder.build()?;
+/// assert_eq!(source, "return {1,2,3,}");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [buffer]: https://luau-lang.org/library#buffer-library
+pub struct StringBuilder<'lua> {
+    lua: &'lua Lua,
+    // Lua object owning the memory block
+    #[allow(unused)]
+    storage: Option<LuaRef<'lua>>,
+    data: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl<'lua> StringBuilder<'lua> {
+    /// Appends `data` to the string.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn append(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        let data = data.as_ref();
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.capacity - self.len < data.len() {
+            self.grow(data.len())?;
+        }
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), self.data.add(self.len), data.len()) };
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Returns the number of bytes appended so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if nothing has been appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes the builder can hold without allocating more memory.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Reserves capacity for at least `additional` more bytes.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        if self.capacity - self.len < additional {
+            self.grow(additional)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the Lua string from the appended data.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn build(self) -> Result<String<'lua>> {
+        let lua = self.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            let data = match self.data.is_null() {
+                true => b"".as_ptr() as *const c_char,
+                false => self.data as *const c_char,
+            };
+            let len = self.len;
+            protect_lua!(state, 0, 1, |state| ffi::lua_pushlstring(state, data, len))?;
+            Ok(String(lua.pop_ref()))
+        }
+    }
+
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn grow(&mut self, additional: usize) -> Result<()> {
+        let required = (self.len.checked_add(additional))
+            .ok_or_else(|| Error::MemoryError("string builder capacity overflow".to_string()))?;
+        let capacity = (self.capacity.saturating_mul(2)).max(required);
+        let capacity = capacity.max(MIN_CAPACITY);
+
+        let lua = self.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            #[cfg(not(feature = "luau"))]
+            let data = protect_lua!(state, 0, 1, |state| ffi::lua_newuserdata(state, capacity))?;
+            #[cfg(feature = "luau")]
+            let data = protect_lua!(state, 0, 1, |state| ffi::lua_newbuffer(state, capacity))?;
+            let data = data as *mut u8;
+            if self.len > 0 {
+                ptr::copy_nonoverlapping(self.data, data, self.len);
+            }
+            // The previous block is released once its reference is dropped
+            self.storage = Some(lua.pop_ref());
+            self.data = data;
+            self.capacity = capacity;
+        }
+        Ok(())
+    }
+}
+
+impl<'lua> fmt::Write for StringBuilder<'lua> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.append(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<'lua> fmt::Debug for StringBuilder<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StringBuilder")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl Lua {
+    /// Creates a new empty [`StringBuilder`].
+    pub fn create_string_builder(&self) -> StringBuilder<'_> {
+        StringBuilder {
+            lua: self,
+            storage: None,
+            data: ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::value::{IntoLua, IntoLuaMulti, MultiValue, Value};
+
+// Size of Lua integers, 64-bit integers are used on all backends
+const SZINT: usize = 8;
+// Maximum size of integers in format strings
+const MAXINTSIZE: usize = 16;
+// Maximum alignment used by `!` without a size
+const MAXALIGN: usize = 8;
+
+// Format options, same as in `lstrlib.c`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KOption {
+    Int,
+    Uint,
+    Float,
+    Double,
+    Char,
+    String,
+    Zstr,
+    Padding,
+    PaddAlign,
+    Nop,
+}
+
+// Parser state of a format string
+struct Format<'a> {
+    fmt: &'a [u8],
+    little: bool,
+    maxalign: usize,
+}
+
+impl<'a> Format<'a> {
+    fn new(fmt: &'a str) -> Self {
+        Format {
+            fmt: fmt.as_bytes(),
+            little: cfg!(target_endian = "little"),
+            maxalign: 1,
+        }
+    }
+
+    fn read_num(&mut self) -> Option<usize> {
+        let digits = self.fmt.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let (num, rest) = self.fmt.split_at(digits);
+        self.fmt = rest;
+        // Saturate on overflow, the value is validated by callers
+        Some(num.iter().fold(0usize, |n, &c| {
+            n.saturating_mul(10).saturating_add((c - b'0') as usize)
+        }))
+    }
+
+    fn read_size(&mut self, default: usize) -> Result<usize> {
+        match self.read_num() {
+            None => Ok(default),
+            Some(size) if (1..=MAXINTSIZE).contains(&size) => Ok(size),
+            Some(size) => Err(Error::runtime(format!(
+                "integral size ({size}) out of limits [1,{MAXINTSIZE}]"
+            ))),
+        }
+    }
+
+    // Reads the next option and its size
+    fn option(&mut self) -> Result<(KOption, usize)> {
+        let (&opt, rest) = self.fmt.split_first().expect("format string is empty");
+        self.fmt = rest;
+        Ok(match opt {
+            b'b' => (KOption::Int, 1),
+            b'B' => (KOption::Uint, 1),
+            b'h' => (KOption::Int, 2),
+            b'H' => (KOption::Uint, 2),
+            b'i' => (KOption::Int, self.read_size(4)?),
+            b'I' => (KOption::Uint, self.read_size(4)?),
+            b'l' | b'j' => (KOption::Int, 8),
+            b'L' | b'J' | b'T' => (KOption::Uint, 8),
+            b'f' => (KOption::Float, 4),
+            b'n' | b'd' => (KOption::Double, 8),
+            b's' => (KOption::String, self.read_size(8)?),
+            b'c' => match self.read_num() {
+                Some(size) if size <= i32::MAX as usize => (KOption::Char, size),
+                Some(_) => return Err(Error::runtime("size for format option 'c' is too large")),
+                None => return Err(Error::runtime("missing size for format option 'c'")),
+            },
+            b'z' => (KOption::Zstr, 0),
+            b'x' => (KOption::Padding, 1),
+            b'X' => (KOption::PaddAlign, 0),
+            b' ' => (KOption::Nop, 0),
+            b'<' => {
+                self.little = true;
+                (KOption::Nop, 0)
+            }
+            b'>' => {
+                self.little = false;
+                (KOption::Nop, 0)
+            }
+            b'=' => {
+                self.little = cfg!(target_endian = "little");
+                (KOption::Nop, 0)
+            }
+            b'!' => {
+                self.maxalign = self.read_size(MAXALIGN)?;
+                (KOption::Nop, 0)
+            }
+            opt => {
+                let msg = format!("invalid format option '{}'", opt as char);
+                return Err(Error::runtime(msg));
+            }
+        })
+    }
+
+    // Reads the next option, returning it with its size and the padding required to align it
+    // at offset `total`
+    fn next(&mut self, total: usize) -> Result<Option<(KOption, usize, usize)>> {
+        if self.fmt.is_empty() {
+            return Ok(None);
+        }
+        let (opt, size) = self.option()?;
+        let mut align = size;
+        if opt == KOption::PaddAlign {
+            // 'X' gets alignment from the following option
+            let next = match self.fmt.is_empty() {
+                true => None,
+                false => Some(self.option()?),
+            };
+            match next {
+                Some((next, size)) if next != KOption::Char && size != 0 => align = size,
+                _ => return Err(Error::runtime("invalid next option for option 'X'")),
+            }
+        }
+
+        if align <= 1 || opt == KOption::Char {
+            return Ok(Some((opt, size, 0)));
+        }
+        let align = align.min(self.maxalign);
+        if !align.is_power_of_two() {
+            return Err(Error::runtime("format asks for alignment not power of 2"));
+        }
+        Ok(Some((
+            opt,
+            size,
+            (align - (total & (align - 1))) & (align - 1),
+        )))
+    }
+}
+
+fn pack_int(buf: &mut Vec<u8>, n: u64, little: bool, size: usize, neg: bool) {
+    let start = buf.len();
+    buf.extend((0..size).map(|i| match i < SZINT {
+        true => (n >> (i * 8)) as u8,
+        false if neg => 0xff,
+        false => 0,
+    }));
+    if !little {
+        buf[start..].reverse();
+    }
+}
+
+fn unpack_int(bytes: &[u8], little: bool, signed: bool) -> Result<i64> {
+    let size = bytes.len();
+    let byte = |i: usize| {
+        if little {
+            bytes[i]
+        } else {
+            bytes[size - 1 - i]
+        }
+    };
+    let limit = size.min(SZINT);
+    let mut res = (0..limit)
+        .rev()
+        .fold(0u64, |res, i| (res << 8) | byte(i) as u64);
+    if size < SZINT && signed {
+        // Sign extension
+        let mask = 1u64 << (size * 8 - 1);
+        res = (res ^ mask).wrapping_sub(mask);
+    } else if size > SZINT {
+        // Unread bytes must be a sign extension
+        let mask = if !signed || (res as i64) >= 0 {
+            0
+        } else {
+            0xff
+        };
+        if (limit..size).any(|i| byte(i) != mask) {
+            let msg = format!("{size}-byte integer does not fit into Lua integer");
+            return Err(Error::runtime(msg));
+        }
+    }
+    Ok(res as i64)
+}
+
+fn bad_value(pos: usize, message: &str) -> Error {
+    Error::BadArgument {
+        to: None,
+        pos,
+        name: None,
+        cause: Arc::new(Error::external(message.to_string())),
+    }
+}
+
+fn check_integer(lua: &Lua, value: Value, pos: usize) -> Result<i64> {
+    let ty = value.type_name();
+    let n = match value {
+        #[allow(clippy::useless_conversion)]
+        Value::Integer(i) => return Ok(i64::from(i)),
+        Value::Number(n) => Some(n),
+        value => lua.coerce_number(value)?,
+    };
+    match n {
+        // The upper bound (2^63) is exactly representable, unlike `i64::MAX`
+        Some(n) if n.fract() == 0.0 && n >= i64::MIN as f64 && n < -(i64::MIN as f64) => {
+            Ok(n as i64)
+        }
+        Some(_) => Err(bad_value(pos, "number has no integer representation")),
+        None => Err(bad_value(pos, &format!("number expected, got {ty}"))),
+    }
+}
+
+fn check_number(lua: &Lua, value: Value, pos: usize) -> Result<f64> {
+    let ty = value.type_name();
+    match lua.coerce_number(value)? {
+        Some(n) => Ok(n),
+        None => Err(bad_value(pos, &format!("number expected, got {ty}"))),
+    }
+}
+
+fn check_string<'lua>(lua: &'lua Lua, value: Value<'lua>, pos: usize) -> Result<String<'lua>> {
+    let ty = value.type_name();
+    match lua.coerce_string(value)? {
+        Some(s) => Ok(s),
+        None => Err(bad_value(pos, &format!("string expected, got {ty}"))),
+    }
+}
+
+impl Lua {
+    /// Packs values into a binary string according to the format string `fmt`.
+    ///
+    /// This is the same as `string.pack` from Lua 5.3+, but available on all Lua versions
+    /// (including Lua 5.1, LuaJIT and Luau), so binary data can be exchanged using the same format
+    /// strings in Rust and in scripts. Integers are always 64-bit, as in Lua 5.4.
+    ///
+    /// Refer to the [Lua manual] for the format string syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RuntimeError` if the format string is invalid and a `BadArgument` error (with
+    /// the position of the value starting from 1) if a value cannot be packed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let packet = lua.string_pack("<I2 s1 z", (513, "abc", "def"))?;
+    /// assert_eq!(packet.as_bytes(), b"\x01\x02\x03abcdef\0");
+    ///
+    /// let (id, name, tag): (u16, String, String) =
+    ///     lua.unpack_multi(lua.string_unpack("<I2 s1 z", &packet)?)?;
+    /// assert_eq!((id, name.as_str(), tag.as_str()), (513, "abc", "def"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [Lua manual]: https://www.lua.org/manual/5.4/manual.html#6.4.2
+    pub fn string_pack<'lua>(
+        &'lua self,
+        fmt: &str,
+        values: impl IntoLuaMulti<'lua>,
+    ) -> Result<String<'lua>> {
+        let mut values = values.into_lua_multi(self)?.into_iter();
+        let mut format = Format::new(fmt);
+        let mut buf = Vec::new();
+        let mut pos = 0;
+        while let Some((opt, size, ntoalign)) = format.next(buf.len())? {
+            buf.resize(buf.len() + ntoalign, 0);
+            if !matches!(opt, KOption::Padding | KOption::PaddAlign | KOption::Nop) {
+                pos += 1;
+            }
+            let mut next_value = || values.next().unwrap_or(Value::Nil);
+            match opt {
+                KOption::Int => {
+                    let n = check_integer(self, next_value(), pos)?;
+                    if size < SZINT {
+                        let lim = 1i64 << (size * 8 - 1);
+                        if !(-lim..lim).contains(&n) {
+                            return Err(bad_value(pos, "integer overflow"));
+                        }
+                    }
+                    pack_int(&mut buf, n as u64, format.little, size, n < 0);
+                }
+                KOption::Uint => {
+                    let n = check_integer(self, next_value(), pos)?;
+                    if size < SZINT && (n as u64) >= 1u64 << (size * 8) {
+                        return Err(bad_value(pos, "unsigned overflow"));
+                    }
+                    pack_int(&mut buf, n as u64, format.little, size, false);
+                }
+                KOption::Float => {
+                    let n = check_number(self, next_value(), pos)? as f32;
+                    match format.little {
+                        true => buf.extend(n.to_le_bytes()),
+                        false => buf.extend(n.to_be_bytes()),
+                    }
+                }
+                KOption::Double => {
+                    let n = check_number(self, next_value(), pos)?;
+                    match format.little {
+                        true => buf.extend(n.to_le_bytes()),
+                        false => buf.extend(n.to_be_bytes()),
+                    }
+                }
+                KOption::Char => {
+                    let s = check_string(self, next_value(), pos)?;
+                    let s = s.as_bytes();
+                    if s.len() > size {
+                        return Err(bad_value(pos, "string longer than given size"));
+                    }
+                    buf.extend(s);
+                    buf.resize(buf.len() + size - s.len(), 0);
+                }
+                KOption::String => {
+                    let s = check_string(self, next_value(), pos)?;
+                    let s = s.as_bytes();
+                    if size < SZINT && (s.len() as u64) >= 1u64 << (size * 8) {
+                        let msg = "string length does not fit in given size";
+                        return Err(bad_value(pos, msg));
+                    }
+                    pack_int(&mut buf, s.len() as u64, format.little, size, false);
+                    buf.extend(s);
+                }
+                KOption::Zstr => {
+                    let s = check_string(self, next_value(), pos)?;
+                    let s = s.as_bytes();
+                    if s.contains(&0) {
+                        return Err(bad_value(pos, "string contains zeros"));
+                    }
+                    buf.extend(s);
+                    buf.push(0);
+                }
+                KOption::Padding => buf.push(0),
+                KOption::PaddAlign | KOption::Nop => {}
+            }
+        }
+        self.create_string(buf)
+    }
+
+    /// Unpacks values from a binary string according to the format string `fmt`.
+    ///
+    /// This is the same as `string.unpack` from Lua 5.3+ (starting from the beginning of `data`),
+    /// but available on all Lua versions. Unlike `string.unpack`, the position after the last
+    /// read byte is not returned.
+    ///
+    /// Integers that do not fit into Lua integers of the current Lua version (eg. 64-bit integers
+    /// in Luau) are returned as numbers.
+    ///
+    /// See [`Lua::string_pack`] for more details.
+    pub fn string_unpack<'lua>(
+        &'lua self,
+        fmt: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<MultiValue<'lua>> {
+        let data = data.as_ref();
+        let mut format = Format::new(fmt);
+        let mut values = Vec::new();
+        let mut pos = 0;
+        while let Some((opt, size, ntoalign)) = format.next(pos)? {
+            if ntoalign + size > data.len() - pos {
+                return Err(Error::runtime("data string too short"));
+            }
+            pos += ntoalign;
+            let bytes = &data[pos..pos + size];
+            match opt {
+                KOption::Int | KOption::Uint => {
+                    let n = unpack_int(bytes, format.little, opt == KOption::Int)?;
+                    values.push(n.into_lua(self)?);
+                }
+                KOption::Float => {
+                    let bytes = bytes.try_into().unwrap();
+                    let n = match format.little {
+                        true => f32::from_le_bytes(bytes),
+                        false => f32::from_be_bytes(bytes),
+                    };
+                    values.push(n.into_lua(self)?);
+                }
+                KOption::Double => {
+                    let bytes = bytes.try_into().unwrap();
+                    let n = match format.little {
+                        true => f64::from_le_bytes(bytes),
+                        false => f64::from_be_bytes(bytes),
+                    };
+                    values.push(n.into_lua(self)?);
+                }
+                KOption::Char => values.push(Value::String(self.create_string(bytes)?)),
+                KOption::String => {
+                    let len = unpack_int(bytes, format.little, false)? as u64;
+                    if len > (data.len() - pos - size) as u64 {
+                        return Err(Error::runtime("data string too short"));
+                    }
+                    let s = &data[pos + size..pos + size + len as usize];
+                    values.push(Value::String(self.create_string(s)?));
+                    pos += len as usize;
+                }
+                KOption::Zstr => {
+                    let len = (data[pos..].iter().position(|&c| c == 0))
+                        .ok_or_else(|| Error::runtime("unfinished string for format 'z'"))?;
+                    values.push(Value::String(self.create_string(&data[pos..pos + len])?));
+                    pos += len + 1;
+                }
+                KOption::Padding | KOption::PaddAlign | KOption::Nop => {}
+            }
+            pos += size;
+        }
+        Ok(MultiValue::from_vec(values))
+    }
+}
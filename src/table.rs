@@ -1,7 +1,10 @@
 use std::collections::HashSet;
 use std::fmt;
 use std::marker::PhantomData;
-use std::os::raw::c_void;
+use std::os::raw::{c_int, c_void};
+
+use bstr::BStr;
+use rustc_hash::FxHashMap;
 
 #[cfg(feature = "serialize")]
 use {
@@ -10,12 +13,16 @@ use {
     std::{cell::RefCell, rc::Rc, result::Result as StdResult},
 };
 
+use crate::conversion_limits::NestedConversion;
+use crate::conversion_trace::{trace, ConversionDirection};
+use crate::display::{self, ValueDisplay};
 use crate::error::{Error, Result};
 use crate::function::Function;
 use crate::private::Sealed;
-use crate::types::{Integer, LuaRef};
+use crate::table_observe::{self, TableEvent};
+use crate::types::{Integer, LuaRef, MaybeSend};
 use crate::util::{assert_stack, check_stack, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Nil, Value};
+use crate::value::{BorrowedValue, FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Nil, Value};
 
 #[cfg(feature = "async")]
 use futures_util::future::{self, LocalBoxFuture};
@@ -79,6 +86,7 @@ impl<'lua> Table<'lua> {
     /// ```
     ///
     /// [`raw_set`]: #method.raw_set
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         // Fast track
         if !self.has_metatable() {
@@ -93,7 +101,8 @@ impl<'lua> Table<'lua> {
 
             lua.push_ref(&self.0);
             key.push_into_stack(lua)?;
-            value.push_into_stack(lua)?;
+            let push_value = || value.push_into_stack(lua);
+            trace::<V, _>(lua, ConversionDirection::IntoLua, push_value, |_| 1)?;
             protect_lua!(state, 3, 0, fn(state) ffi::lua_settable(state, -3))
         }
     }
@@ -122,6 +131,7 @@ impl<'lua> Table<'lua> {
     /// ```
     ///
     /// [`raw_get`]: #method.raw_get
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
         // Fast track
         if !self.has_metatable() {
@@ -138,10 +148,35 @@ impl<'lua> Table<'lua> {
             key.push_into_stack(lua)?;
             protect_lua!(state, 2, 1, fn(state) ffi::lua_gettable(state, -2))?;
 
-            V::from_stack(-1, lua)
+            let convert = || V::from_stack(-1, lua);
+            trace::<V, _>(lua, ConversionDirection::FromLua, convert, |_| 1)
         }
     }
 
+    /// Gets the value associated to the string key `key` given as bytes.
+    ///
+    /// Unlike passing a Lua [`String`] key, this pushes the bytes directly to the Lua stack
+    /// (when it's safe), without creating an intermediate string handle.
+    ///
+    /// This might invoke the `__index` metamethod.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let config: Table = lua.load("{ ['key\\0with nul'] = 42 }").eval()?;
+    /// assert_eq!(config.get_by_bytes::<i64>(b"key\0with nul")?, 42);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`String`]: crate::String
+    pub fn get_by_bytes<V: FromLua<'lua>>(&self, key: &[u8]) -> Result<V> {
+        self.get(BStr::new(key))
+    }
+
     /// Checks whether the table contains a non-nil value for `key`.
     ///
     /// This might invoke the `__index` metamethod.
@@ -152,6 +187,7 @@ impl<'lua> Table<'lua> {
     /// Appends a value to the back of the table.
     ///
     /// This might invoke the `__len` and `__newindex` metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn push<V: IntoLua<'lua>>(&self, value: V) -> Result<()> {
         // Fast track
         if !self.has_metatable() {
@@ -177,6 +213,7 @@ impl<'lua> Table<'lua> {
     /// Removes the last element from the table and returns it.
     ///
     /// This might invoke the `__len` and `__newindex` metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn pop<V: FromLua<'lua>>(&self) -> Result<V> {
         // Fast track
         if !self.has_metatable() {
@@ -257,6 +294,7 @@ impl<'lua> Table<'lua> {
     }
 
     /// Sets a key-value pair without invoking metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
         #[cfg(feature = "luau")]
         self.check_readonly_write()?;
@@ -269,7 +307,8 @@ impl<'lua> Table<'lua> {
 
             lua.push_ref(&self.0);
             key.push_into_stack(lua)?;
-            value.push_into_stack(lua)?;
+            let push_value = || value.push_into_stack(lua);
+            trace::<V, _>(lua, ConversionDirection::IntoLua, push_value, |_| 1)?;
 
             if lua.unlikely_memory_error() {
                 ffi::lua_rawset(state, -3);
@@ -281,7 +320,68 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Sets multiple key-value pairs without invoking metamethods.
+    ///
+    /// This is faster than calling [`Table::raw_set`] for each pair: keys and values are pushed
+    /// directly to the Lua stack and set in batches, using a single protected call per batch.
+    /// Pairs are set in iteration order, so for duplicate keys the last value wins.
+    ///
+    /// If an error occurs, the pairs of the failed batch are not set (the previous ones are).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.create_table()?;
+    /// config.set_many([("host", "localhost"), ("port", "8080")])?;
+    /// assert_eq!(config.get::<_, String>("port")?, "8080");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn set_many<K, V, I>(&self, pairs: I) -> Result<()>
+    where
+        K: IntoLua<'lua>,
+        V: IntoLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        #[cfg(feature = "luau")]
+        self.check_readonly_write()?;
+
+        const BATCH_SIZE: c_int = 64;
+
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, BATCH_SIZE * 2 + 6)?;
+
+            let mut pairs = pairs.into_iter().peekable();
+            while pairs.peek().is_some() {
+                lua.push_ref(&self.0);
+                let mut n = 0;
+                for (key, value) in pairs.by_ref().take(BATCH_SIZE as usize) {
+                    key.push_into_stack(lua)?;
+                    value.push_into_stack(lua)?;
+                    n += 1;
+                }
+
+                protect_lua!(state, n * 2 + 1, 0, |state| {
+                    for i in 0..n {
+                        ffi::lua_pushvalue(state, 2 + i * 2);
+                        ffi::lua_pushvalue(state, 3 + i * 2);
+                        ffi::lua_rawset(state, 1);
+                    }
+                })?;
+            }
+            Ok(())
+        }
+    }
+
     /// Gets the value associated to `key` without invoking metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -293,12 +393,14 @@ impl<'lua> Table<'lua> {
             key.push_into_stack(lua)?;
             ffi::lua_rawget(state, -2);
 
-            V::from_stack(-1, lua)
+            let convert = || V::from_stack(-1, lua);
+            trace::<V, _>(lua, ConversionDirection::FromLua, convert, |_| 1)
         }
     }
 
     /// Inserts element value at position `idx` to the table, shifting up the elements from `table[idx]`.
     /// The worst case complexity is O(n), where n is the table length.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_insert<V: IntoLua<'lua>>(&self, idx: Integer, value: V) -> Result<()> {
         let size = self.raw_len() as Integer;
         if idx < 1 || idx > size + 1 {
@@ -325,6 +427,7 @@ impl<'lua> Table<'lua> {
     }
 
     /// Appends a value to the back of the table without invoking metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_push<V: IntoLua<'lua>>(&self, value: V) -> Result<()> {
         #[cfg(feature = "luau")]
         self.check_readonly_write()?;
@@ -353,6 +456,7 @@ impl<'lua> Table<'lua> {
     }
 
     /// Removes the last element from the table and returns it, without invoking metamethods.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_pop<V: FromLua<'lua>>(&self) -> Result<V> {
         #[cfg(feature = "luau")]
         self.check_readonly_write()?;
@@ -381,6 +485,7 @@ impl<'lua> Table<'lua> {
     /// where n is the table length.
     ///
     /// For other key types this is equivalent to setting `table[key] = nil`.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_remove<K: IntoLua<'lua>>(&self, key: K) -> Result<()> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -414,6 +519,7 @@ impl<'lua> Table<'lua> {
     /// without invoking metamethods.
     ///
     /// This method is useful to clear the table while keeping its capacity.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn clear(&self) -> Result<()> {
         #[cfg(feature = "luau")]
         self.check_readonly_write()?;
@@ -456,6 +562,7 @@ impl<'lua> Table<'lua> {
     /// This might invoke the `__len` metamethod. Use the [`raw_len`] method if that is not desired.
     ///
     /// [`raw_len`]: #method.raw_len
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn len(&self) -> Result<Integer> {
         // Fast track
         if !self.has_metatable() {
@@ -482,6 +589,7 @@ impl<'lua> Table<'lua> {
     /// Returns `true` if the table is empty, without invoking metamethods.
     ///
     /// It checks both the array part and the hash part.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn is_empty(&self) -> bool {
         // Check array part
         if self.raw_len() != 0 {
@@ -508,6 +616,7 @@ impl<'lua> Table<'lua> {
     /// Returns a reference to the metatable of this table, or `None` if no metatable is set.
     ///
     /// Unlike the `getmetatable` Lua function, this method ignores the `__metatable` field.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn get_metatable(&self) -> Option<Table<'lua>> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -528,6 +637,11 @@ impl<'lua> Table<'lua> {
     ///
     /// If `metatable` is `None`, the metatable is removed (if no metatable is set, this does
     /// nothing).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the metatable belongs to a different main Lua state.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_metatable(&self, metatable: Option<Table<'lua>>) {
         // Workaround to throw readonly error without returning Result
         #[cfg(feature = "luau")]
@@ -543,7 +657,9 @@ impl<'lua> Table<'lua> {
 
             lua.push_ref(&self.0);
             if let Some(metatable) = metatable {
-                lua.push_ref(&metatable.0);
+                if let Err(err) = lua.try_push_ref(&metatable.0) {
+                    panic!("{err}");
+                }
             } else {
                 ffi::lua_pushnil(state);
             }
@@ -591,6 +707,90 @@ impl<'lua> Table<'lua> {
         unsafe { ffi::lua_getreadonly(ref_thread, self.0.index) != 0 }
     }
 
+    /// Reports changes of the table made by scripts (or [`Table::set`]) to `f`.
+    ///
+    /// The contents of the table are moved to a hidden backing table, which is returned. The table
+    /// itself becomes an empty proxy with a metatable forwarding reads to the backing table and
+    /// reporting every assignment that changes a value (as [`TableEvent::Set`] or
+    /// [`TableEvent::Remove`]) after it's applied. Errors returned by `f` are raised from the
+    /// assignment. The returned backing table can be used to read or write the contents
+    /// without triggering events.
+    ///
+    /// Indexing the proxy works in all Lua versions, while `#` and `pairs` require Lua 5.2+
+    /// (`#` and generalized iteration in Luau). Rust code should iterate the backing table.
+    /// Raw accessors (`rawget`, `rawlen`, [`Table::raw_get`], etc.) see the empty proxy.
+    /// Values stored with `rawset` (or [`Table::raw_set`]) escape observation: they are stored in
+    /// the proxy itself, hide the contents of the backing table, and later assignments to these
+    /// keys are not reported. Use the backing table to write without events instead.
+    ///
+    /// Returns an error if the table already has a metatable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Lua, Result, TableEvent};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let state = lua.create_table()?;
+    /// lua.globals().set("state", &state)?;
+    ///
+    /// let changes = Arc::new(Mutex::new(Vec::new()));
+    /// let changes2 = changes.clone();
+    /// state.observe(move |event| {
+    ///     changes2.lock().unwrap().push(event.key().to_string()?);
+    ///     Ok(())
+    /// })?;
+    ///
+    /// lua.load("state.score = 10; state.score = 10; state.name = 'bob'; state.name = nil").exec()?;
+    /// assert_eq!(*changes.lock().unwrap(), ["score", "name", "name"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn observe<F>(&self, f: F) -> Result<Table<'lua>>
+    where
+        F: Fn(TableEvent<'lua>) -> Result<()> + MaybeSend + 'static,
+    {
+        table_observe::observe(self, f)
+    }
+
+    /// Creates a deep copy of the table.
+    ///
+    /// Nested tables stored as values are copied recursively, tables referenced more than once
+    /// (including recursive tables) are copied once and stay shared in the copy. Keys, metatables
+    /// and values of other types are not copied.
+    ///
+    /// Fails with [`Error::TooDeep`] or [`Error::TooLarge`] when exceeding the limits set by
+    /// [`Lua::set_conversion_limits`].
+    ///
+    /// [`Lua::set_conversion_limits`]: crate::Lua::set_conversion_limits
+    pub fn deep_clone(&self) -> Result<Table<'lua>> {
+        self.deep_clone_with(&mut FxHashMap::default())
+    }
+
+    fn deep_clone_with(
+        &self,
+        copies: &mut FxHashMap<*const c_void, Table<'lua>>,
+    ) -> Result<Table<'lua>> {
+        let lua = self.0.lua;
+        let nested = NestedConversion::enter(lua)?;
+        let copy = lua.create_table()?;
+        copy.set_metatable(self.get_metatable());
+        copies.insert(self.to_pointer(), copy.clone());
+        self.for_each(|key: Value<'lua>, value: Value<'lua>| {
+            nested.count_element()?;
+            let value = match value {
+                Value::Table(t) => match copies.get(&t.to_pointer()) {
+                    Some(t) => Value::Table(t.clone()),
+                    None => Value::Table(t.deep_clone_with(copies)?),
+                },
+                value => value,
+            };
+            copy.raw_set(key, value)
+        })?;
+        Ok(copy)
+    }
+
     /// Converts this table to a generic C pointer.
     ///
     /// Different tables will give different pointers.
@@ -602,6 +802,13 @@ impl<'lua> Table<'lua> {
         self.0.to_pointer()
     }
 
+    /// Returns a formatter of the table, which can expand its contents.
+    ///
+    /// See [`ValueDisplay`] for the available options.
+    pub fn display(&self) -> ValueDisplay<'lua> {
+        ValueDisplay::new(Value::Table(self.clone()))
+    }
+
     /// Convert this handle to owned version.
     #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
     #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
@@ -610,6 +817,12 @@ impl<'lua> Table<'lua> {
         OwnedTable(self.0.into_owned())
     }
 
+    /// Wraps this table into a [`TypedTable`] with fixed key and value types.
+    #[inline]
+    pub fn typed<K, V>(self) -> TypedTable<'lua, K, V> {
+        TypedTable::new(self)
+    }
+
     /// Consume this table and return an iterator over the pairs of the table.
     ///
     /// This works like the Lua `pairs` function, but does not invoke the `__pairs` metamethod.
@@ -655,6 +868,7 @@ impl<'lua> Table<'lua> {
     ///
     /// This method is similar to [`Table::pairs`], but optimized for performance.
     /// It does not invoke the `__pairs` metamethod.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn for_each<K, V>(&self, mut f: impl FnMut(K, V) -> Result<()>) -> Result<()>
     where
         K: FromLua<'lua>,
@@ -679,6 +893,60 @@ impl<'lua> Table<'lua> {
         Ok(())
     }
 
+    /// Visits the pairs of the table, invoking the given closure on each pair of values borrowed
+    /// from the Lua stack.
+    ///
+    /// Unlike [`Table::for_each`], no value handles are created, which makes this method suitable
+    /// for read-only scans of huge tables. Strings are passed as byte slices and numbers
+    /// directly; other objects are passed by their pointers (see [`BorrowedValue`]).
+    /// It does not invoke the `__pairs` metamethod.
+    ///
+    /// The table must not be modified by the closure (except for assigning `nil` to existing
+    /// fields), otherwise the traversal behavior is undefined as with Lua's `next` function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{BorrowedValue, Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load(r#"{ apples = 3, pears = 5, note = "fresh" }"#).eval()?;
+    ///
+    /// let mut total = 0;
+    /// table.visit(|key, value| {
+    ///     if let (Some(_), BorrowedValue::Integer(n)) = (key.as_str(), value) {
+    ///         total += n;
+    ///     }
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(total, 8);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn visit<F>(&self, mut f: F) -> Result<()>
+    where
+        F: for<'a> FnMut(BorrowedValue<'a>, BorrowedValue<'a>) -> Result<()>,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            lua.push_ref(&self.0);
+            ffi::lua_pushnil(state);
+            while ffi::lua_next(state, -2) != 0 {
+                let k = BorrowedValue::from_stack(state, -2);
+                let v = BorrowedValue::from_stack(state, -1);
+                f(k, v)?;
+                // Keep key for next iteration
+                ffi::lua_pop(state, 1);
+            }
+        }
+        Ok(())
+    }
+
     /// Consume this table and return an iterator over all values in the sequence part of the table.
     ///
     /// The iterator will yield all values `t[1]`, `t[2]` and so on, until a `nil` value is
@@ -732,6 +1000,7 @@ impl<'lua> Table<'lua> {
     }
 
     #[cfg(feature = "serialize")]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn for_each_value<V>(&self, mut f: impl FnMut(V) -> Result<()>) -> Result<()>
     where
         V: FromLua<'lua>,
@@ -755,6 +1024,7 @@ impl<'lua> Table<'lua> {
 
     /// Sets element value at position `idx` without invoking metamethods.
     #[doc(hidden)]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn raw_seti<V: IntoLua<'lua>>(&self, idx: usize, value: V) -> Result<()> {
         #[cfg(feature = "luau")]
         self.check_readonly_write()?;
@@ -780,19 +1050,29 @@ impl<'lua> Table<'lua> {
 
     #[cfg(feature = "serialize")]
     pub(crate) fn is_array(&self) -> bool {
+        unsafe { self.has_serde_metatable(crate::serde::push_array_metatable) }
+    }
+
+    #[cfg(feature = "serialize")]
+    pub(crate) fn is_sorted_map(&self) -> bool {
+        unsafe { self.has_serde_metatable(crate::serde::push_sorted_map_metatable) }
+    }
+
+    // Checks whether the table has the metatable pushed by `push_metatable`
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn has_serde_metatable(&self, push_metatable: unsafe fn(*mut ffi::lua_State)) -> bool {
         let lua = self.0.lua;
         let state = lua.state();
-        unsafe {
-            let _sg = StackGuard::new(state);
-            assert_stack(state, 3);
+        let _sg = StackGuard::new(state);
+        assert_stack(state, 3);
 
-            lua.push_ref(&self.0);
-            if ffi::lua_getmetatable(state, -1) == 0 {
-                return false;
-            }
-            crate::serde::push_array_metatable(state);
-            ffi::lua_rawequal(state, -1, -2) != 0
+        lua.push_ref(&self.0);
+        if ffi::lua_getmetatable(state, -1) == 0 {
+            return false;
         }
+        push_metatable(state);
+        ffi::lua_rawequal(state, -1, -2) != 0
     }
 
     #[cfg(feature = "luau")]
@@ -841,6 +1121,12 @@ impl fmt::Debug for Table<'_> {
     }
 }
 
+impl fmt::Display for Table<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        display::fmt_ref(fmt, &self.0, "table")
+    }
+}
+
 impl<'lua> PartialEq for Table<'lua> {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -858,6 +1144,7 @@ impl<'lua, T> PartialEq<[T]> for Table<'lua>
 where
     T: IntoLua<'lua> + Clone,
 {
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn eq(&self, other: &[T]) -> bool {
         let lua = self.0.lua;
         let state = lua.state();
@@ -1057,13 +1344,15 @@ pub(crate) struct SerializableTable<'a, 'lua> {
     table: &'a Table<'lua>,
     options: crate::serde::de::Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    shared: Option<Rc<RefCell<crate::serde::refs::SharedTables>>>,
 }
 
 #[cfg(feature = "serialize")]
 impl<'lua> Serialize for Table<'lua> {
     #[inline]
     fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
-        SerializableTable::new(self, Default::default(), Default::default()).serialize(serializer)
+        SerializableTable::new(self, Default::default(), Default::default(), None)
+            .serialize(serializer)
     }
 }
 
@@ -1074,11 +1363,13 @@ impl<'a, 'lua> SerializableTable<'a, 'lua> {
         table: &'a Table<'lua>,
         options: crate::serde::de::Options,
         visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+        shared: Option<Rc<RefCell<crate::serde::refs::SharedTables>>>,
     ) -> Self {
         Self {
             table,
             options,
             visited,
+            shared,
         }
     }
 }
@@ -1089,19 +1380,27 @@ impl<'a, 'lua> Serialize for SerializableTable<'a, 'lua> {
     where
         S: Serializer,
     {
+        use crate::conversion_limits::{ser_error, NestedConversion};
         use crate::serde::de::{check_value_for_skip, MapPairs, RecursionGuard};
+        use crate::serde::refs::escape_key;
         use crate::value::SerializableValue;
 
+        let lua = self.table.0.lua;
         let convert_result = |res: Result<()>, serialize_err: Option<S::Error>| match res {
             Ok(v) => Ok(v),
             Err(Error::SerializeError(_)) if serialize_err.is_some() => Err(serialize_err.unwrap()),
             Err(Error::SerializeError(msg)) => Err(serde::ser::Error::custom(msg)),
-            Err(err) => Err(serde::ser::Error::custom(err.to_string())),
+            Err(err) => Err(ser_error(lua, err)),
         };
 
         let options = self.options;
         let visited = &self.visited;
-        let _guard = RecursionGuard::new(self.table, visited);
+        let shared = self.shared.as_ref();
+        // Recursive tables are serialized as references when they are preserved
+        let _guard = shared
+            .is_none()
+            .then(|| RecursionGuard::new(self.table, visited));
+        let nested = NestedConversion::enter(lua).map_err(|err| ser_error(lua, err))?;
 
         // Array
         let len = self.table.raw_len();
@@ -1115,11 +1414,17 @@ impl<'a, 'lua> Serialize for SerializableTable<'a, 'lua> {
                     // continue iteration
                     return Ok(());
                 }
-                seq.serialize_element(&SerializableValue::new(&value, options, Some(visited)))
-                    .map_err(|err| {
-                        serialize_err = Some(err);
-                        Error::SerializeError(String::new())
-                    })
+                nested.count_element()?;
+                seq.serialize_element(&SerializableValue::new(
+                    &value,
+                    options,
+                    Some(visited),
+                    shared,
+                ))
+                .map_err(|err| {
+                    serialize_err = Some(err);
+                    Error::SerializeError(String::new())
+                })
             });
             convert_result(res, serialize_err)?;
             return seq.end();
@@ -1137,21 +1442,27 @@ impl<'a, 'lua> Serialize for SerializableTable<'a, 'lua> {
                 // continue iteration
                 return Ok(());
             }
-            map.serialize_entry(
-                &SerializableValue::new(&key, options, Some(visited)),
-                &SerializableValue::new(&value, options, Some(visited)),
-            )
+            nested.count_element()?;
+            let value = SerializableValue::new(&value, options, Some(visited), shared);
+            // Keys that look like reference tags are escaped
+            match shared.and_then(|_| escape_key(&key)) {
+                Some(key) => map.serialize_entry(&key, &value),
+                None => map.serialize_entry(
+                    &SerializableValue::new(&key, options, Some(visited), shared),
+                    &value,
+                ),
+            }
             .map_err(|err| {
                 serialize_err = Some(err);
                 Error::SerializeError(String::new())
             })
         };
 
-        let res = if !self.options.sort_keys {
+        let res = if !self.options.sort_keys && !self.table.is_sorted_map() {
             // Fast track
             self.table.for_each(process_pair)
         } else {
-            MapPairs::new(self.table.clone(), self.options.sort_keys)
+            MapPairs::new(self.table.clone(), true)
                 .map_err(serde::ser::Error::custom)?
                 .try_for_each(|kv| {
                     let (key, value) = kv?;
@@ -1163,6 +1474,186 @@ impl<'a, 'lua> Serialize for SerializableTable<'a, 'lua> {
     }
 }
 
+/// A Lua table with fixed key and value types.
+///
+/// The wrapper does not validate the table contents, instead it monomorphizes accessors to the
+/// key type `K` and value type `V` so misuse is caught at compile time. Values of other types
+/// will fail to convert on access.
+///
+/// Tables with [`Integer`] keys additionally provide array-like methods that use raw integer
+/// indexing.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Integer, Lua, Result, TypedTable};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let scores: TypedTable<String, u32> = lua.create_table()?.typed();
+/// scores.set("alice".to_string(), 10)?;
+/// scores.set("bob".to_string(), 7)?;
+/// assert_eq!(scores.get("alice".to_string())?, 10);
+///
+/// let list: TypedTable<Integer, f64> = lua.load("{1.5, 2.5}").eval()?;
+/// list.push(3.5)?;
+/// let sum = list.values().sum::<Result<f64>>()?;
+/// assert_eq!(sum, 7.5);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedTable<'lua, K, V> {
+    table: Table<'lua>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'lua, K, V> TypedTable<'lua, K, V> {
+    /// Wraps the table without checking its contents.
+    #[inline]
+    pub const fn new(table: Table<'lua>) -> Self {
+        TypedTable {
+            table,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the underlying table.
+    #[inline]
+    pub const fn as_table(&self) -> &Table<'lua> {
+        &self.table
+    }
+
+    /// Consumes the wrapper and returns the underlying table.
+    #[inline]
+    pub fn into_table(self) -> Table<'lua> {
+        self.table
+    }
+
+    /// Returns the result of the Lua `#` operator, without invoking the `__len` metamethod.
+    #[inline]
+    pub fn raw_len(&self) -> usize {
+        self.table.raw_len()
+    }
+
+    /// Returns `true` if the table is empty, without invoking metamethods.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+impl<'lua, K, V> TypedTable<'lua, K, V>
+where
+    K: IntoLua<'lua>,
+    V: IntoLua<'lua> + FromLua<'lua>,
+{
+    /// Gets the value associated to `key` from the table.
+    ///
+    /// See [`Table::get`] for details.
+    #[inline]
+    pub fn get(&self, key: K) -> Result<V> {
+        self.table.get(key)
+    }
+
+    /// Sets a key-value pair in the table.
+    ///
+    /// See [`Table::set`] for details.
+    #[inline]
+    pub fn set(&self, key: K, value: V) -> Result<()> {
+        self.table.set(key, value)
+    }
+
+    /// Gets the value associated to `key` without invoking metamethods.
+    #[inline]
+    pub fn raw_get(&self, key: K) -> Result<V> {
+        self.table.raw_get(key)
+    }
+
+    /// Sets a key-value pair without invoking metamethods.
+    #[inline]
+    pub fn raw_set(&self, key: K, value: V) -> Result<()> {
+        self.table.raw_set(key, value)
+    }
+
+    /// Checks whether the table contains a non-nil value for `key`.
+    #[inline]
+    pub fn contains_key(&self, key: K) -> Result<bool> {
+        self.table.contains_key(key)
+    }
+
+    /// Removes a key from the table without invoking metamethods.
+    #[inline]
+    pub fn raw_remove(&self, key: K) -> Result<()> {
+        self.table.raw_remove(key)
+    }
+}
+
+impl<'lua, K, V> TypedTable<'lua, K, V>
+where
+    K: FromLua<'lua>,
+    V: FromLua<'lua>,
+{
+    /// Returns an iterator over the pairs of the table.
+    ///
+    /// See [`Table::pairs`] for details.
+    #[inline]
+    pub fn iter(&self) -> TablePairs<'lua, K, V> {
+        self.table.clone().pairs()
+    }
+
+    /// Iterates over the pairs of the table, invoking the given closure on each pair.
+    ///
+    /// See [`Table::for_each`] for details.
+    #[inline]
+    pub fn for_each(&self, f: impl FnMut(K, V) -> Result<()>) -> Result<()> {
+        self.table.for_each(f)
+    }
+}
+
+impl<'lua, V> TypedTable<'lua, Integer, V>
+where
+    V: IntoLua<'lua> + FromLua<'lua>,
+{
+    /// Appends a value to the back of the sequence, without invoking metamethods.
+    #[inline]
+    pub fn push(&self, value: V) -> Result<()> {
+        self.table.raw_push(value)
+    }
+
+    /// Removes the last element from the sequence and returns it, without invoking metamethods.
+    #[inline]
+    pub fn pop(&self) -> Result<V> {
+        self.table.raw_pop()
+    }
+
+    /// Returns an iterator over the values of the sequence part of the table.
+    ///
+    /// See [`Table::sequence_values`] for details.
+    #[inline]
+    pub fn values(&self) -> TableSequence<'lua, V> {
+        self.table.clone().sequence_values()
+    }
+}
+
+impl<K, V> Clone for TypedTable<'_, K, V> {
+    #[inline]
+    fn clone(&self) -> Self {
+        TypedTable::new(self.table.clone())
+    }
+}
+
+impl<K, V> fmt::Debug for TypedTable<'_, K, V> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.table, fmt)
+    }
+}
+
+impl<'lua, K, V> AsRef<Table<'lua>> for TypedTable<'lua, K, V> {
+    #[inline]
+    fn as_ref(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
 /// An iterator over the pairs of a Lua table.
 ///
 /// This struct is created by the [`Table::pairs`] method.
@@ -1181,6 +1672,7 @@ where
 {
     type Item = Result<(K, V)>;
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(prev_key) = self.key.take() {
             let lua = self.table.lua;
@@ -1240,6 +1732,7 @@ where
 {
     type Item = Result<V>;
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn next(&mut self) -> Option<Self::Item> {
         let lua = self.table.lua;
         let state = lua.state();
@@ -0,0 +1,97 @@
+use crate::error::{Error, Result};
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::value::Value;
+
+/// Change of an observed table, passed to the callback set by [`Table::observe`].
+#[derive(Clone, Debug)]
+pub enum TableEvent<'lua> {
+    /// A key was added or its value was changed.
+    Set {
+        /// Key that was set.
+        key: Value<'lua>,
+        /// New value.
+        value: Value<'lua>,
+        /// Previous value, `nil` if the key was added.
+        old_value: Value<'lua>,
+    },
+    /// A key was removed (set to `nil`).
+    Remove {
+        /// Key that was removed.
+        key: Value<'lua>,
+        /// Value before the removal.
+        old_value: Value<'lua>,
+    },
+}
+
+impl<'lua> TableEvent<'lua> {
+    /// Returns the key that was changed.
+    pub fn key(&self) -> &Value<'lua> {
+        match self {
+            TableEvent::Set { key, .. } | TableEvent::Remove { key, .. } => key,
+        }
+    }
+}
+
+// Moves the contents of the table to a backing table, turning the table into an empty proxy
+// that forwards reads to the backing table and reports writes to `f`
+pub(crate) fn observe<'lua, F>(table: &Table<'lua>, f: F) -> Result<Table<'lua>>
+where
+    F: Fn(TableEvent<'lua>) -> Result<()> + MaybeSend + 'static,
+{
+    #[cfg(feature = "luau")]
+    if table.is_readonly() {
+        return Err(Error::runtime("cannot observe a readonly table"));
+    }
+    if table.has_metatable() {
+        return Err(Error::runtime("cannot observe a table with a metatable"));
+    }
+
+    let lua = table.0.lua;
+    let backing = lua.create_table()?;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        backing.raw_set(key, value)?;
+    }
+    table.clear()?;
+
+    let mt = lua.create_table_with_capacity(0, 6)?;
+    mt.raw_set("__index", &backing)?;
+    let newindex = lua.create_function(move |_, (proxy, key, value): (Table, Value, Value)| {
+        let backing = backing_table(&proxy)?;
+        let old_value: Value = backing.raw_get(key.clone())?;
+        backing.raw_set(key.clone(), value.clone())?;
+        if value == old_value {
+            return Ok(());
+        }
+        f(match value {
+            Value::Nil => TableEvent::Remove { key, old_value },
+            value => TableEvent::Set {
+                key,
+                value,
+                old_value,
+            },
+        })
+    })?;
+    mt.raw_set("__newindex", newindex)?;
+    let len = lua.create_function(|_, proxy: Table| Ok(backing_table(&proxy)?.raw_len()))?;
+    mt.raw_set("__len", len)?;
+    let pairs = lua.create_function(|lua, proxy: Table| {
+        let next = lua.globals().raw_get::<_, Value>("next")?;
+        Ok((next, backing_table(&proxy)?))
+    })?;
+    #[cfg(feature = "luau")]
+    mt.raw_set("__iter", &pairs)?;
+    mt.raw_set("__pairs", pairs)?;
+    mt.raw_set("__metatable", false)?;
+    table.set_metatable(Some(mt));
+
+    Ok(backing)
+}
+
+fn backing_table<'lua>(proxy: &Table<'lua>) -> Result<Table<'lua>> {
+    match proxy.get_metatable() {
+        Some(mt) => mt.raw_get("__index"),
+        None => Err(Error::runtime("observed table has no metatable")),
+    }
+}
@@ -0,0 +1,196 @@
+use std::any::TypeId;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::private::Sealed;
+use crate::types::MaybeSend;
+use crate::userdata::{AnyUserData, MetaMethod, UserDataMethods};
+use crate::userdata_impl::UserDataRegistry;
+use crate::value::{FromLua, IntoLua, IntoLuaMulti, Value};
+
+/// A Rust collection that can be exposed to Lua using [`Lua::create_table_proxy`].
+///
+/// Implemented for [`HashMap<K, V>`] and [`Vec<T>`] with keys and values convertible from and
+/// into Lua values.
+///
+/// [`Lua::create_table_proxy`]: crate::Lua::create_table_proxy
+pub trait TableProxy: Sealed + MaybeSend + Sized + 'static {
+    #[doc(hidden)]
+    fn register(registry: &mut UserDataRegistry<'_, Self>);
+}
+
+impl<K, V, S> TableProxy for HashMap<K, V, S>
+where
+    K: Eq + Hash + Clone + for<'lua> FromLua<'lua> + for<'lua> IntoLua<'lua> + MaybeSend + 'static,
+    V: Clone + for<'lua> FromLua<'lua> + for<'lua> IntoLua<'lua> + MaybeSend + 'static,
+    S: BuildHasher + MaybeSend + 'static,
+{
+    fn register(registry: &mut UserDataRegistry<'_, Self>) {
+        registry.add_meta_method(MetaMethod::Index, |lua, this, key: Value| {
+            // Keys of other types are just missing
+            let Ok(key) = K::from_lua(key, lua) else {
+                return Ok(Value::Nil);
+            };
+            match this.get(&key) {
+                Some(value) => value.clone().into_lua(lua),
+                None => Ok(Value::Nil),
+            }
+        });
+
+        registry.add_meta_method_mut(
+            MetaMethod::NewIndex,
+            |lua, this, (key, value): (Value, Value)| {
+                let key = K::from_lua(key, lua)?;
+                match value {
+                    Value::Nil => this.remove(&key),
+                    value => this.insert(key, V::from_lua(value, lua)?),
+                };
+                Ok(())
+            },
+        );
+
+        registry.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.len()));
+
+        // Iterates over a snapshot of the keys, looking up the current values
+        add_pairs(registry, |lua, (ud, _): (AnyUserData, Value)| {
+            let keys: Vec<K> = ud.borrow::<Self>()?.keys().cloned().collect();
+            let next = Cell::new(0);
+            lua.create_function(move |lua, (ud, _): (AnyUserData, Value)| {
+                let this = ud.borrow::<Self>()?;
+                while let Some(key) = keys.get(next.get()) {
+                    next.set(next.get() + 1);
+                    if let Some(value) = this.get(key) {
+                        return Ok((key.clone().into_lua(lua)?, value.clone().into_lua(lua)?));
+                    }
+                }
+                Ok((Value::Nil, Value::Nil))
+            })
+            .map(|next| (next, ud))
+        });
+    }
+}
+
+impl<T> TableProxy for Vec<T>
+where
+    T: Clone + for<'lua> FromLua<'lua> + for<'lua> IntoLua<'lua> + MaybeSend + 'static,
+{
+    fn register(registry: &mut UserDataRegistry<'_, Self>) {
+        registry.add_meta_method(
+            MetaMethod::Index,
+            |lua, this, index: Value| match vec_index(lua, index) {
+                Some(i) if i <= this.len() => this[i - 1].clone().into_lua(lua),
+                _ => Ok(Value::Nil),
+            },
+        );
+
+        registry.add_meta_method_mut(
+            MetaMethod::NewIndex,
+            |lua, this, (index, value): (Value, Value)| {
+                let len = this.len();
+                match vec_index(lua, index) {
+                    // Assigning `nil` to the last element shrinks the vector
+                    Some(i) if i == len && value.is_nil() => {
+                        this.pop();
+                    }
+                    Some(i) if i <= len => this[i - 1] = T::from_lua(value, lua)?,
+                    Some(i) if i == len + 1 => this.push(T::from_lua(value, lua)?),
+                    _ => {
+                        let msg = format!("index out of bounds (vector has {len} elements)");
+                        return Err(Error::runtime(msg));
+                    }
+                }
+                Ok(())
+            },
+        );
+
+        registry.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.len()));
+
+        add_pairs(registry, |lua, (ud, _): (AnyUserData, Value)| {
+            let next = lua.create_function(|lua, (ud, i): (AnyUserData, usize)| {
+                match ud.borrow::<Self>()?.get(i) {
+                    Some(value) => Ok((Value::Integer((i + 1) as _), value.clone().into_lua(lua)?)),
+                    None => Ok((Value::Nil, Value::Nil)),
+                }
+            })?;
+            Ok((next, ud, 0))
+        });
+    }
+}
+
+// Registers the iteration metamethod (`__iter` in Luau), if supported by the Lua version
+fn add_pairs<'lua, T, F, R>(registry: &mut UserDataRegistry<'lua, T>, pairs: F)
+where
+    T: 'static,
+    F: Fn(&'lua Lua, (AnyUserData<'lua>, Value<'lua>)) -> Result<R> + MaybeSend + 'static,
+    R: IntoLuaMulti<'lua>,
+{
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luajit52",
+    ))]
+    registry.add_meta_function(MetaMethod::Pairs, pairs);
+    #[cfg(feature = "luau")]
+    registry.add_meta_function(MetaMethod::Iter, pairs);
+    #[cfg(any(feature = "lua51", all(feature = "luajit", not(feature = "luajit52"))))]
+    let _ = (registry, pairs);
+}
+
+// Converts a Lua value to a (1-based) vector index
+fn vec_index(lua: &Lua, index: Value) -> Option<usize> {
+    match lua.coerce_integer(index) {
+        Ok(Some(i)) if i >= 1 => usize::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+impl Lua {
+    /// Wraps a Rust collection into a table-like userdata.
+    ///
+    /// Indexing, assignment, the length operator and iteration work directly on the underlying
+    /// collection, so large host datasets can be exposed to scripts without copying them into Lua
+    /// tables. Values are converted on every access. Iteration uses `pairs` (generalized iteration
+    /// in Luau) and is not available in Lua 5.1 and LuaJIT without `luajit52`.
+    ///
+    /// For maps, assigning `nil` removes the key. For vectors, indices start at 1; assigning to
+    /// the index after the last element appends to the vector and assigning `nil` to the last
+    /// element removes it.
+    ///
+    /// The collection remains accessible from Rust using [`AnyUserData::borrow`] and
+    /// [`AnyUserData::borrow_mut`], and changes made on either side are immediately visible on the
+    /// other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let scores = HashMap::from([("alice".to_string(), 10), ("bob".to_string(), 7)]);
+    /// let proxy = lua.create_table_proxy(scores)?;
+    /// lua.globals().set("scores", &proxy)?;
+    ///
+    /// lua.load("scores.carol = scores.alice + scores.bob").exec()?;
+    /// assert_eq!(proxy.borrow::<HashMap<String, i32>>()?["carol"], 17);
+    ///
+    /// proxy.borrow_mut::<HashMap<String, i32>>()?.remove("alice");
+    /// assert_eq!(lua.load("scores.alice").eval::<Option<i32>>()?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`AnyUserData::borrow`]: crate::AnyUserData::borrow
+    /// [`AnyUserData::borrow_mut`]: crate::AnyUserData::borrow_mut
+    pub fn create_table_proxy<C: TableProxy>(&self, collection: C) -> Result<AnyUserData<'_>> {
+        // Keep the type registration made by user, if any
+        if !self.is_userdata_type_registered(TypeId::of::<C>()) {
+            self.register_userdata_type::<C>(C::register)?;
+        }
+        self.create_any_userdata(collection)
+    }
+}
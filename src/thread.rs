@@ -1,27 +1,36 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
 use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::string::String as StdString;
 
 use crate::error::{Error, Result};
-#[allow(unused)]
+use crate::function::Function;
 use crate::lua::Lua;
-use crate::types::LuaRef;
-use crate::util::{check_stack, error_traceback_thread, pop_error, StackGuard};
-use crate::value::{FromLuaMulti, IntoLuaMulti};
+use crate::quota::Quota;
+use crate::util::{
+    check_stack, error_traceback_thread, get_gc_userdata, pop_error, to_string, StackGuard,
+    WrappedFailure,
+};
+use crate::value::{FromLuaMulti, IntoLuaMulti, MultiValue};
 
 #[cfg(not(feature = "luau"))]
-use crate::{
-    hook::{Debug, HookTriggers},
-    types::MaybeSend,
-};
+use crate::hook::{Debug, HookTriggers};
+use crate::types::{AppDataRef, AppDataRefMut, LuaRef, MaybeSend};
 
 #[cfg(feature = "async")]
 use {
-    crate::value::MultiValue,
+    crate::types::{AsyncCompleteHook, AsyncResumeHook, AsyncYieldHook},
     futures_util::stream::Stream,
     std::{
         future::Future,
-        marker::PhantomData,
         pin::Pin,
         ptr::NonNull,
+        result::Result as StdResult,
+        sync::Arc,
         task::{Context, Poll, Waker},
     },
 };
@@ -41,6 +50,24 @@ pub enum ThreadStatus {
     Error,
 }
 
+/// Outcome of resuming a Lua thread (coroutine) using [`Thread::resume_outcome`].
+#[derive(Debug)]
+pub enum ResumeOutcome<R> {
+    /// The thread has called `coroutine.yield` with the given values and can be resumed again.
+    Yielded(R),
+    /// The thread has returned the given values from its main function.
+    Finished(R),
+    /// The thread has raised an error and is now dead.
+    Failed {
+        /// The error raised by the thread.
+        error: Error,
+        /// Traceback of the thread at the point of failure.
+        ///
+        /// It's `None` for memory errors, where no traceback can be captured.
+        traceback: Option<StdString>,
+    },
+}
+
 /// Handle to an internal Lua thread (coroutine).
 #[derive(Clone, Debug)]
 pub struct Thread<'lua>(pub(crate) LuaRef<'lua>, pub(crate) *mut ffi::lua_State);
@@ -69,6 +96,45 @@ impl OwnedThread {
     }
 }
 
+/// Blocking iterator over values yielded by a Lua thread (coroutine).
+///
+/// Created by [`Thread::resume_iter`].
+pub struct ThreadIter<'lua, R> {
+    thread: Thread<'lua>,
+    init_args: Option<Result<MultiValue<'lua>>>,
+    ret: PhantomData<R>,
+    done: bool,
+}
+
+/// A pool of reusable Lua threads (coroutines).
+///
+/// Recycling threads reduces allocations when running many short-lived coroutines.
+/// Threads are reused only where the backend supports resetting them (Lua 5.4 and Luau),
+/// in other Lua versions the pool transparently creates fresh threads.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Function, Lua, Result, ThreadPool};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let pool = ThreadPool::new(&lua, 16);
+/// let task: Function = lua.load("function(n) return n * 2 end").eval()?;
+///
+/// for i in 0..100 {
+///     let thread = pool.acquire(task.clone())?;
+///     assert_eq!(thread.resume::<_, i32>(i)?, i * 2);
+///     pool.release(thread);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct ThreadPool<'lua> {
+    lua: &'lua Lua,
+    threads: RefCell<Vec<Thread<'lua>>>,
+    capacity: usize,
+}
+
 /// Thread (coroutine) representation as an async [`Future`] or [`Stream`].
 ///
 /// Requires `feature = "async"`
@@ -85,6 +151,46 @@ pub struct AsyncThread<'lua, R> {
     recycle: bool,
 }
 
+/// Hooks notified about [`AsyncThread`] execution, set by [`Lua::set_async_scheduler_hooks`].
+///
+/// They allow hosts with custom schedulers (e.g. frame-based game loops or priority queues)
+/// to track coroutines driven as futures or streams. Returning an error from a hook fails the
+/// current poll with that error.
+///
+/// Requires `feature = "async"`
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use mlua::{AsyncSchedulerHooks, Lua, Result};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let yields = Arc::new(Mutex::new(Vec::new()));
+/// let yields2 = yields.clone();
+/// lua.set_async_scheduler_hooks(AsyncSchedulerHooks::new().on_yield(move |_, _, values| {
+///     yields2.lock().unwrap().push(values.len());
+///     Ok(())
+/// }));
+///
+/// let func = lua.load("coroutine.yield(1, 2); return 3").into_function()?;
+/// func.call_async::<_, i32>(()).await?;
+/// assert_eq!(*yields.lock().unwrap(), vec![2]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::set_async_scheduler_hooks`]: crate::Lua::set_async_scheduler_hooks
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Clone, Default)]
+pub struct AsyncSchedulerHooks {
+    resume: Option<AsyncResumeHook>,
+    r#yield: Option<AsyncYieldHook>,
+    complete: Option<AsyncCompleteHook>,
+}
+
 impl<'lua> Thread<'lua> {
     #[inline(always)]
     pub(crate) fn new(r#ref: LuaRef<'lua>) -> Self {
@@ -92,7 +198,7 @@ impl<'lua> Thread<'lua> {
         Thread(r#ref, state)
     }
 
-    const fn state(&self) -> *mut ffi::lua_State {
+    pub(crate) const fn state(&self) -> *mut ffi::lua_State {
         self.1
     }
 
@@ -137,6 +243,7 @@ impl<'lua> Thread<'lua> {
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn resume<A, R>(&self, args: A) -> Result<R>
     where
         A: IntoLuaMulti<'lua>,
@@ -161,22 +268,105 @@ impl<'lua> Thread<'lua> {
         }
     }
 
-    /// Resumes execution of this thread.
+    /// Resumes execution of this thread, reporting how the thread stopped.
     ///
-    /// It's similar to `resume()` but leaves `nresults` values on the thread stack.
-    unsafe fn resume_inner<A: IntoLuaMulti<'lua>>(&self, args: A) -> Result<c_int> {
+    /// Unlike [`resume()`], errors raised by the thread are not returned as `Err`, but as
+    /// [`ResumeOutcome::Failed`] together with the traceback captured from the dead thread.
+    /// This allows schedulers to tell yielded values from completion and handle coroutine
+    /// failure without inspecting error messages.
+    ///
+    /// Returns `Err` only if the thread cannot be resumed (e.g. it's not in `Resumable` state) or
+    /// the arguments and results cannot be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, ResumeOutcome, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function()
+    ///         coroutine.yield(1)
+    ///         error("boom")
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// assert!(matches!(thread.resume_outcome::<_, i32>(())?, ResumeOutcome::Yielded(1)));
+    /// match thread.resume_outcome::<_, ()>(())? {
+    ///     ResumeOutcome::Failed { error, traceback } => {
+    ///         assert!(error.to_string().contains("boom"));
+    ///         assert!(traceback.unwrap().starts_with("stack traceback:"));
+    ///     }
+    ///     _ => panic!("expected the thread to fail"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`resume()`]: #method.resume
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn resume_outcome<A, R>(&self, args: A) -> Result<ResumeOutcome<R>>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        if self.status() != ThreadStatus::Resumable {
+            return Err(Error::CoroutineInactive);
+        }
+
         let lua = self.0.lua;
         let state = lua.state();
         let thread_state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            let _thread_sg = StackGuard::with_top(thread_state, 0);
 
-        let nargs = args.push_into_stack_multi(lua)?;
-        if nargs > 0 {
-            check_stack(thread_state, nargs)?;
-            ffi::lua_xmove(state, thread_state, nargs);
+            let (ret, nresults) = self.resume_status(args)?;
+            match ret {
+                ffi::LUA_OK | ffi::LUA_YIELD => {
+                    check_stack(state, nresults + 1)?;
+                    ffi::lua_xmove(thread_state, state, nresults);
+                    let values = R::from_stack_multi(nresults, lua)?;
+                    Ok(match ret {
+                        ffi::LUA_YIELD => ResumeOutcome::Yielded(values),
+                        _ => ResumeOutcome::Finished(values),
+                    })
+                }
+                ffi::LUA_ERRMEM => Ok(ResumeOutcome::Failed {
+                    error: pop_error(thread_state, ret),
+                    traceback: None,
+                }),
+                _ => {
+                    check_stack(state, ffi::LUA_TRACEBACK_STACK + 2)?;
+                    protect_lua!(state, 0, 2, |state| {
+                        ffi::luaL_traceback(state, thread_state, ptr::null(), 0);
+                        // Convert the error object using `__tostring` metamethod if present
+                        ffi::lua_xmove(thread_state, state, 1);
+                        if get_gc_userdata::<WrappedFailure>(state, -1, ptr::null()).is_null() {
+                            ffi::luaL_tolstring(state, -1, ptr::null_mut());
+                            ffi::lua_remove(state, -2);
+                        }
+                    })?;
+                    let error = pop_error(state, ret);
+                    let traceback = to_string(state, -1);
+                    Ok(ResumeOutcome::Failed {
+                        error,
+                        traceback: Some(traceback),
+                    })
+                }
+            }
         }
+    }
 
-        let mut nresults = 0;
-        let ret = ffi::lua_resume(thread_state, state, nargs, &mut nresults as *mut c_int);
+    /// Resumes execution of this thread.
+    ///
+    /// It's similar to `resume()` but leaves `nresults` values on the thread stack.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn resume_inner<A: IntoLuaMulti<'lua>>(&self, args: A) -> Result<c_int> {
+        let state = self.0.lua.state();
+        let thread_state = self.state();
+
+        let (ret, nresults) = self.resume_status(args)?;
         if ret != ffi::LUA_OK && ret != ffi::LUA_YIELD {
             if ret == ffi::LUA_ERRMEM {
                 // Don't call error handler for memory errors
@@ -193,6 +383,213 @@ impl<'lua> Thread<'lua> {
         Ok(nresults)
     }
 
+    /// Resumes execution of this thread, giving direct access to the values it yields or returns.
+    ///
+    /// Passes `args` to the thread like [`resume()`], then calls `f` with the raw state of the
+    /// thread, the status code returned by `lua_resume` (`LUA_OK`, `LUA_YIELD` or an error code)
+    /// and the number of values on top of the thread stack: the values yielded or returned by
+    /// the thread, or the error object. The values are removed from the thread stack once `f`
+    /// returns.
+    ///
+    /// This allows hosts to implement their own resumers, for example for yields carrying host
+    /// tokens which cannot be converted to [`Value`].
+    ///
+    /// Returns `Err(CoroutineInactive)` if the thread is not in `Resumable` state.
+    ///
+    /// # Safety
+    ///
+    /// `f` must only read or replace the values on top of the thread stack, and must not resume
+    /// the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ffi, Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function(token)
+    ///         coroutine.yield(token)
+    ///         return 2
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// let token = 0x2a as *mut std::ffi::c_void;
+    /// let yielded = unsafe {
+    ///     thread.resume_raw(mlua::LightUserData(token), |state, status, nresults| {
+    ///         assert_eq!((status, nresults), (ffi::LUA_YIELD, 1));
+    ///         Ok(ffi::lua_touserdata(state, -1))
+    ///     })?
+    /// };
+    /// assert_eq!(yielded, token);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`resume()`]: #method.resume
+    /// [`Value`]: crate::Value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub unsafe fn resume_raw<A, F, R>(&self, args: A, f: F) -> Result<R>
+    where
+        A: IntoLuaMulti<'lua>,
+        F: FnOnce(*mut ffi::lua_State, c_int, c_int) -> Result<R>,
+    {
+        if self.status() != ThreadStatus::Resumable {
+            return Err(Error::CoroutineInactive);
+        }
+
+        let state = self.0.lua.state();
+        let thread_state = self.state();
+        let _sg = StackGuard::new(state);
+        let _thread_sg = StackGuard::with_top(thread_state, 0);
+
+        let (ret, nresults) = self.resume_status(args)?;
+        f(thread_state, ret, nresults)
+    }
+
+    // Passes `args` to the thread and resumes it, returning the status code and number of results
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn resume_status<A: IntoLuaMulti<'lua>>(&self, args: A) -> Result<(c_int, c_int)> {
+        let lua = self.0.lua;
+        let state = lua.state();
+        let thread_state = self.state();
+
+        // Arguments pushed by `Lua::create_thread_with` before the thread has started
+        let npushed = if is_fresh(thread_state) {
+            ffi::lua_gettop(thread_state) - 1
+        } else {
+            0
+        };
+        let nargs = args.push_into_stack_multi(lua)?;
+        if nargs > 0 {
+            check_stack(thread_state, nargs)?;
+            ffi::lua_xmove(state, thread_state, nargs);
+        }
+        let nargs = npushed + nargs;
+
+        let mut nresults = 0;
+        let ret = ffi::lua_resume(thread_state, state, nargs, &mut nresults as *mut c_int);
+        Ok((ret, nresults))
+    }
+
+    /// Sets coroutine-local data of type `T` for this thread.
+    ///
+    /// Coroutine-local data is a per-thread container (similar to [`Lua::set_app_data`]) that
+    /// can be used to pass request context (e.g. trace IDs or permissions) to Rust callbacks
+    /// running on the thread. Use [`Lua::current_thread`] to access it from a callback.
+    ///
+    /// Returns the previous value of type `T`, if any. The data is dropped when the thread is
+    /// garbage collected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let func = lua.create_function(|lua, ()| {
+    ///     let thread = lua.current_thread();
+    ///     let trace_id = thread.local::<u64>()?.map(|id| *id);
+    ///     Ok(trace_id)
+    /// })?;
+    ///
+    /// let thread = lua.create_thread(func)?;
+    /// thread.set_local(42u64)?;
+    /// assert_eq!(thread.resume::<_, Option<u64>>(())?, Some(42));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the container is currently borrowed.
+    #[track_caller]
+    pub fn set_local<T: MaybeSend + 'static>(&self, data: T) -> Result<Option<T>> {
+        let lua = self.0.lua;
+        unsafe { Ok((*lua.thread_locals(&self.0, true)?).insert(data)) }
+    }
+
+    /// Gets a reference to coroutine-local data of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data object of type `T` is currently mutably borrowed.
+    #[track_caller]
+    pub fn local<T: 'static>(&self) -> Result<Option<AppDataRef<T>>> {
+        let lua = self.0.lua;
+        unsafe {
+            let locals = lua.thread_locals(&self.0, false)?;
+            Ok(locals.as_ref().and_then(|locals| locals.borrow()))
+        }
+    }
+
+    /// Gets a mutable reference to coroutine-local data of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data object of type `T` is currently borrowed.
+    #[track_caller]
+    pub fn local_mut<T: 'static>(&self) -> Result<Option<AppDataRefMut<T>>> {
+        let lua = self.0.lua;
+        unsafe {
+            let locals = lua.thread_locals(&self.0, false)?;
+            Ok(locals.as_ref().and_then(|locals| locals.borrow_mut()))
+        }
+    }
+
+    /// Removes coroutine-local data of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the container is currently borrowed.
+    #[track_caller]
+    pub fn remove_local<T: 'static>(&self) -> Result<Option<T>> {
+        let lua = self.0.lua;
+        unsafe {
+            let locals = lua.thread_locals(&self.0, false)?;
+            Ok(locals.as_ref().and_then(|locals| locals.remove()))
+        }
+    }
+
+    /// Returns an iterator that resumes this thread on each step and yields values passed
+    /// to `coroutine.yield`, converted to `R`.
+    ///
+    /// `args` are passed to the thread on the first resume. The iteration ends when the thread
+    /// returns (the returned values are discarded) or after yielding an error raised by the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function(n)
+    ///         for i = 1, n do
+    ///             coroutine.yield(i * i)
+    ///         end
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// let squares = thread.resume_iter::<_, u32>(4).collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(squares, vec![1, 4, 9, 16]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resume_iter<A, R>(&self, args: A) -> ThreadIter<'lua, R>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        let args = args.into_lua_multi(self.0.lua);
+        ThreadIter {
+            thread: self.clone(),
+            init_args: Some(args),
+            ret: PhantomData,
+            done: false,
+        }
+    }
+
     /// Gets the status of the thread.
     pub fn status(&self) -> ThreadStatus {
         let thread_state = self.state();
@@ -228,6 +625,27 @@ impl<'lua> Thread<'lua> {
         }
     }
 
+    /// Sets a quota limiting resources consumed by Lua code running in the thread.
+    ///
+    /// This function is similar to [`Lua::set_quota()`] except that the quota is applied only to
+    /// this thread, instead of the quota set by [`Lua::set_quota()`] (if any). Calls to Rust
+    /// callbacks made from other threads are not counted.
+    ///
+    /// The quota is kept in the coroutine-local data of the thread (see [`Thread::set_local`]),
+    /// so it's released together with the thread.
+    pub fn set_quota(&self, quota: Quota) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe { lua.set_thread_quota(&self.0, quota) }
+    }
+
+    /// Removes the quota previously set by [`Thread::set_quota()`].
+    ///
+    /// The hook of the thread replaced by the quota is restored.
+    pub fn remove_quota(&self) -> Result<()> {
+        let lua = self.0.lua;
+        unsafe { lua.remove_thread_quota(&self.0) }
+    }
+
     /// Resets a thread
     ///
     /// In [Lua 5.4]: cleans its call stack and closes all pending to-be-closed variables.
@@ -244,7 +662,29 @@ impl<'lua> Thread<'lua> {
     /// [Lua 5.4]: https://www.lua.org/manual/5.4/manual.html#lua_closethread
     #[cfg(any(feature = "lua54", feature = "luau"))]
     #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "luau"))))]
-    pub fn reset(&self, func: crate::function::Function<'lua>) -> Result<()> {
+    pub fn reset(&self, func: Function<'lua>) -> Result<()> {
+        self.reset_inner()?;
+
+        let lua = self.0.lua;
+        let thread_state = self.state();
+        unsafe {
+            // Push function to the top of the thread stack
+            ffi::lua_xpush(lua.ref_thread(), thread_state, func.0.index);
+
+            #[cfg(feature = "luau")]
+            {
+                // Inherit `LUA_GLOBALSINDEX` from the main thread
+                ffi::lua_xpush(lua.main_state(), thread_state, ffi::LUA_GLOBALSINDEX);
+                ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
+            }
+
+            Ok(())
+        }
+    }
+
+    // Resets the thread state without setting a function
+    #[cfg(any(feature = "lua54", feature = "luau"))]
+    fn reset_inner(&self) -> Result<()> {
         let lua = self.0.lua;
         let thread_state = self.state();
         if thread_state == lua.state() {
@@ -261,19 +701,44 @@ impl<'lua> Thread<'lua> {
             }
             #[cfg(feature = "luau")]
             ffi::lua_resetthread(thread_state);
+        }
+        Ok(())
+    }
 
-            // Push function to the top of the thread stack
-            ffi::lua_xpush(lua.ref_thread(), thread_state, func.0.index);
-
-            #[cfg(feature = "luau")]
-            {
-                // Inherit `LUA_GLOBALSINDEX` from the main thread
-                ffi::lua_xpush(lua.main_state(), thread_state, ffi::LUA_GLOBALSINDEX);
-                ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
-            }
+    /// Closes a thread, making it dead.
+    ///
+    /// In [Lua 5.4]: cleans its call stack and closes all pending to-be-closed variables.
+    /// Returns a error in case of either the original error that stopped the thread or errors
+    /// in closing methods.
+    ///
+    /// In Luau: resets the thread, releasing its call stack.
+    ///
+    /// After closing, the thread status is `Unresumable` and it can be reused with
+    /// [`Thread::reset`].
+    ///
+    /// Requires `feature = "lua54"` OR `feature = "luau"`.
+    ///
+    /// [Lua 5.4]: https://www.lua.org/manual/5.4/manual.html#lua_closethread
+    #[cfg(any(feature = "lua54", feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "luau"))))]
+    pub fn close(&self) -> Result<()> {
+        self.reset_inner()
+    }
 
-            Ok(())
+    /// Reuses this thread to run `func`, or creates a new thread if the thread cannot be reset.
+    ///
+    /// In Lua 5.4 and Luau it resets the thread using [`Thread::reset`] and returns it back.
+    /// Other Lua versions do not support resetting threads, so a fresh thread is created instead.
+    /// It's also the case when the thread is running or resetting failed (e.g. the thread
+    /// stopped with an error).
+    ///
+    /// See [`ThreadPool`] for a pool of reusable threads.
+    pub fn reset_with(self, func: Function<'lua>) -> Result<Thread<'lua>> {
+        #[cfg(any(feature = "lua54", feature = "luau"))]
+        if self.reset(func.clone()).is_ok() {
+            return Ok(self);
         }
+        self.0.lua.create_thread(func)
     }
 
     /// Converts Thread to an AsyncThread which implements [`Future`] and [`Stream`] traits.
@@ -371,6 +836,7 @@ impl<'lua> Thread<'lua> {
     #[cfg(any(feature = "luau", docsrs))]
     #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
     #[doc(hidden)]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn sandbox(&self) -> Result<()> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -429,10 +895,96 @@ impl OwnedThread {
 
 #[cfg(feature = "async")]
 impl<'lua, R> AsyncThread<'lua, R> {
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    #[inline]
+    pub(crate) fn thread(&self) -> &Thread<'lua> {
+        &self.thread
+    }
+
     #[inline]
     pub(crate) fn set_recyclable(&mut self, recyclable: bool) {
         self.recycle = recyclable;
     }
+
+    // Resumes the thread, notifying scheduler hooks about resuming and failures
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    unsafe fn resume_scheduled(&mut self, hooks: Option<&AsyncSchedulerHooks>) -> Result<c_int> {
+        let lua = self.thread.0.lua;
+        if let Some(hook) = hooks.and_then(|hooks| hooks.resume.as_ref()) {
+            hook(lua, &self.thread)?;
+        }
+        let nresults = match self.init_args.take() {
+            Some(args) => args.and_then(|args| self.thread.resume_inner(args)),
+            None => self.thread.resume_inner(()),
+        };
+        if let (Err(err), Some(hook)) = (&nresults, hooks.and_then(|h| h.complete.as_ref())) {
+            hook(lua, &self.thread, Err(err))?;
+        }
+        nresults
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncSchedulerHooks {
+    /// Creates a new set of hooks (without any hook).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a hook called before every resume of an [`AsyncThread`].
+    ///
+    /// The thread is also resumed to poll pending Rust futures (awaited by async functions).
+    #[must_use]
+    pub fn on_resume<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Lua, &Thread) -> Result<()> + MaybeSend + 'static,
+    {
+        self.resume = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets a hook called when an [`AsyncThread`] yields values using `coroutine.yield()`.
+    ///
+    /// Waiting for a pending Rust future is not reported as yield.
+    #[must_use]
+    pub fn on_yield<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Lua, &Thread, &MultiValue) -> Result<()> + MaybeSend + 'static,
+    {
+        self.r#yield = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets a hook called when an [`AsyncThread`] finishes, with returned values or an error.
+    #[must_use]
+    pub fn on_complete<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Lua, &Thread, StdResult<&MultiValue, &Error>) -> Result<()> + MaybeSend + 'static,
+    {
+        self.complete = Some(Arc::new(hook));
+        self
+    }
+
+    // Notifies about values yielded or returned by the thread
+    fn notify_results(&self, thread: &Thread, yielded: bool, values: &MultiValue) -> Result<()> {
+        let lua = thread.0.lua;
+        match (yielded, &self.r#yield, &self.complete) {
+            (true, Some(hook), _) => hook(lua, thread, values),
+            (false, _, Some(hook)) => hook(lua, thread, Ok(values)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl fmt::Debug for AsyncSchedulerHooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncSchedulerHooks")
+            .field("on_resume", &self.resume.is_some())
+            .field("on_yield", &self.r#yield.is_some())
+            .field("on_complete", &self.complete.is_some())
+            .finish()
+    }
 }
 
 #[cfg(feature = "async")]
@@ -457,6 +1009,119 @@ impl<'lua, R> Drop for AsyncThread<'lua, R> {
     }
 }
 
+impl<'lua, R> Iterator for ThreadIter<'lua, R>
+where
+    R: FromLuaMulti<'lua>,
+{
+    type Item = Result<R>;
+
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.thread.status() != ThreadStatus::Resumable {
+            return None;
+        }
+
+        let lua = self.thread.0.lua;
+        let state = lua.state();
+        let thread_state = self.thread.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            let _thread_sg = StackGuard::with_top(thread_state, 0);
+
+            let nresults = match self.init_args.take() {
+                Some(args) => args.and_then(|args| self.thread.resume_inner(args)),
+                None => self.thread.resume_inner(()),
+            };
+            let nresults = match nresults {
+                Ok(nresults) => nresults,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+
+            // The thread has returned
+            if ffi::lua_status(thread_state) != ffi::LUA_YIELD {
+                self.done = true;
+                return None;
+            }
+
+            if let Err(err) = check_stack(state, nresults + 1) {
+                return Some(Err(err));
+            }
+            ffi::lua_xmove(thread_state, state, nresults);
+
+            Some(R::from_stack_multi(nresults, lua))
+        }
+    }
+}
+
+impl<'lua, R> FusedIterator for ThreadIter<'lua, R> where R: FromLuaMulti<'lua> {}
+
+impl<'lua> ThreadPool<'lua> {
+    /// Creates a new pool that keeps up to `capacity` idle threads.
+    pub fn new(lua: &'lua Lua, capacity: usize) -> Self {
+        ThreadPool {
+            lua,
+            threads: RefCell::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// Returns a thread ready to run `func`, reusing an idle thread if available.
+    pub fn acquire(&self, func: Function<'lua>) -> Result<Thread<'lua>> {
+        let thread = self.threads.borrow_mut().pop();
+        match thread {
+            Some(thread) => thread.reset_with(func),
+            None => self.lua.create_thread(func),
+        }
+    }
+
+    /// Returns the thread to the pool for later use.
+    ///
+    /// The thread is reset immediately to release its resources, and its coroutine-local data
+    /// (see [`Thread::set_local`]) is removed. It's dropped if the pool is full, the backend does
+    /// not support resetting threads, or resetting failed.
+    pub fn release(&self, thread: Thread<'lua>) {
+        #[cfg(any(feature = "lua54", feature = "luau"))]
+        {
+            let mut threads = self.threads.borrow_mut();
+            if threads.len() < self.capacity
+                && thread.reset_inner().is_ok()
+                && unsafe { self.lua.clear_thread_locals(&thread.0) }.is_ok()
+            {
+                threads.push(thread);
+            }
+        }
+        #[cfg(not(any(feature = "lua54", feature = "luau")))]
+        drop(thread);
+    }
+
+    /// Returns the number of idle threads in the pool.
+    pub fn len(&self) -> usize {
+        self.threads.borrow().len()
+    }
+
+    /// Returns `true` if the pool has no idle threads.
+    pub fn is_empty(&self) -> bool {
+        self.threads.borrow().is_empty()
+    }
+
+    /// Returns the maximum number of idle threads kept by the pool.
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<'lua> fmt::Debug for ThreadPool<'lua> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
 #[cfg(feature = "async")]
 impl<'lua, R> Stream for AsyncThread<'lua, R>
 where
@@ -464,6 +1129,7 @@ where
 {
     type Item = Result<R>;
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if self.thread.status() != ThreadStatus::Resumable {
             return Poll::Ready(None);
@@ -479,11 +1145,8 @@ where
 
             // This is safe as we are not moving the whole struct
             let this = self.get_unchecked_mut();
-            let nresults = if let Some(args) = this.init_args.take() {
-                this.thread.resume_inner(args?)?
-            } else {
-                this.thread.resume_inner(())?
-            };
+            let hooks = lua.async_scheduler_hooks();
+            let nresults = this.resume_scheduled(hooks.as_ref())?;
 
             if nresults == 1 && is_poll_pending(thread_state) {
                 return Poll::Pending;
@@ -493,6 +1156,12 @@ where
             ffi::lua_xmove(thread_state, state, nresults);
 
             cx.waker().wake_by_ref();
+            if let Some(hooks) = hooks {
+                let yielded = ffi::lua_status(thread_state) == ffi::LUA_YIELD;
+                let values = MultiValue::from_stack_multi(nresults, lua)?;
+                hooks.notify_results(&this.thread, yielded, &values)?;
+                return Poll::Ready(Some(R::from_lua_multi(values, lua)));
+            }
             Poll::Ready(Some(R::from_stack_multi(nresults, lua)))
         }
     }
@@ -505,6 +1174,7 @@ where
 {
     type Output = Result<R>;
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         if self.thread.status() != ThreadStatus::Resumable {
             return Poll::Ready(Err(Error::CoroutineInactive));
@@ -520,17 +1190,15 @@ where
 
             // This is safe as we are not moving the whole struct
             let this = self.get_unchecked_mut();
-            let nresults = if let Some(args) = this.init_args.take() {
-                this.thread.resume_inner(args?)?
-            } else {
-                this.thread.resume_inner(())?
-            };
+            let hooks = lua.async_scheduler_hooks();
+            let nresults = this.resume_scheduled(hooks.as_ref())?;
 
             if nresults == 1 && is_poll_pending(thread_state) {
                 return Poll::Pending;
             }
 
-            if ffi::lua_status(thread_state) == ffi::LUA_YIELD {
+            let yielded = ffi::lua_status(thread_state) == ffi::LUA_YIELD;
+            if yielded && hooks.is_none() {
                 // Ignore value returned via yield()
                 cx.waker().wake_by_ref();
                 return Poll::Pending;
@@ -539,11 +1207,32 @@ where
             check_stack(state, nresults + 1)?;
             ffi::lua_xmove(thread_state, state, nresults);
 
+            if let Some(hooks) = hooks {
+                let values = MultiValue::from_stack_multi(nresults, lua)?;
+                hooks.notify_results(&this.thread, yielded, &values)?;
+                if yielded {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                return Poll::Ready(R::from_lua_multi(values, lua));
+            }
             Poll::Ready(R::from_stack_multi(nresults, lua))
         }
     }
 }
 
+// Checks whether the thread has not been started yet (it has no active function)
+unsafe fn is_fresh(thread_state: *mut ffi::lua_State) -> bool {
+    if ffi::lua_status(thread_state) != ffi::LUA_OK || ffi::lua_gettop(thread_state) == 0 {
+        return false;
+    }
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    #[cfg(not(feature = "luau"))]
+    return ffi::lua_getstack(thread_state, 0, &mut ar) == 0;
+    #[cfg(feature = "luau")]
+    return ffi::lua_getinfo(thread_state, 0, cstr!(""), &mut ar) == 0;
+}
+
 #[cfg(feature = "async")]
 #[inline(always)]
 unsafe fn is_poll_pending(state: *mut ffi::lua_State) -> bool {
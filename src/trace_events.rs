@@ -0,0 +1,277 @@
+use std::fmt::{self, Write as _};
+use std::io;
+use std::os::raw::c_int;
+use std::process;
+use std::time::{Duration, Instant};
+
+use rustc_hash::FxHashMap;
+
+#[cfg(feature = "luau")]
+use std::os::raw::c_void;
+
+/// Function enter/exit events of Lua code in the [Chrome trace-event] format.
+///
+/// Events are recorded between [`Lua::start_trace_events`] and [`Lua::stop_trace_events`].
+/// Every Lua thread (coroutine) gets its own lane, named `main` for the main thread and
+/// `coroutine N` for the others.
+///
+/// The produced JSON can be opened in `about://tracing` or [Perfetto]. To visualize host engine
+/// events alongside Lua ones, emit them with the same process id ([`std::process::id`]) and
+/// timestamps in microseconds relative to [`TraceEvents::origin`].
+///
+/// Requires `feature = "trace_events"`
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// # #[cfg(feature = "luajit")]
+/// # lua.load("jit.off()").exec()?;
+/// lua.start_trace_events();
+/// lua.load(r#"
+///     local function fib(n) if n < 2 then return n end return fib(n - 1) + fib(n - 2) end
+///     fib(5)
+/// "#).exec()?;
+/// let events = lua.stop_trace_events().unwrap();
+///
+/// let json = events.to_json();
+/// assert!(json.contains(r#""name":"fib""#));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [Chrome trace-event]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+/// [Perfetto]: https://ui.perfetto.dev
+/// [`Lua::start_trace_events`]: crate::Lua::start_trace_events
+/// [`Lua::stop_trace_events`]: crate::Lua::stop_trace_events
+#[derive(Clone, Debug)]
+pub struct TraceEvents {
+    origin: Instant,
+    lanes: Vec<String>,
+    events: Vec<TraceEvent>,
+}
+
+#[derive(Clone, Debug)]
+struct TraceEvent {
+    lane: usize,
+    ts: Duration,
+    // `None` for exit events
+    begin: Option<(String, Option<String>)>,
+}
+
+impl TraceEvents {
+    /// Returns the instant the recording was started at.
+    ///
+    /// Timestamps of the events are relative to this instant.
+    pub fn origin(&self) -> Instant {
+        self.origin
+    }
+
+    /// Returns the number of recorded enter/exit events.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns `true` if no events were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the events as a JSON trace document.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a `String` cannot fail
+        let _ = self.write_json_fmt(&mut json);
+        json
+    }
+
+    /// Writes the events as a JSON trace document to `writer`.
+    pub fn write_json<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.to_json().as_bytes())
+    }
+
+    fn write_json_fmt(&self, out: &mut String) -> fmt::Result {
+        let pid = process::id();
+        out.push_str(r#"{"traceEvents":["#);
+        for (i, name) in self.lanes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                r#"{{"name":"thread_name","ph":"M","pid":{pid},"tid":{},"#,
+                i + 1
+            )?;
+            out.push_str(r#""args":{"name":"#);
+            write_json_str(out, name);
+            out.push_str("}}");
+        }
+        for event in &self.events {
+            out.push(',');
+            let ts = event.ts.as_nanos() as f64 / 1000.0;
+            let tid = event.lane + 1;
+            match event.begin {
+                Some((ref name, ref source)) => {
+                    out.push_str(r#"{"name":"#);
+                    write_json_str(out, name);
+                    write!(
+                        out,
+                        r#","cat":"lua","ph":"B","pid":{pid},"tid":{tid},"ts":{ts:.3}"#
+                    )?;
+                    if let Some(source) = source {
+                        out.push_str(r#","args":{"source":"#);
+                        write_json_str(out, source);
+                        out.push('}');
+                    }
+                    out.push('}');
+                }
+                None => write!(out, r#"{{"ph":"E","pid":{pid},"tid":{tid},"ts":{ts:.3}}}"#)?,
+            }
+        }
+        out.push_str(r#"],"displayTimeUnit":"ms"}"#);
+        Ok(())
+    }
+}
+
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// State of the trace events recording, kept in the Lua extra data
+pub(crate) struct TraceRecorder {
+    main_state: *mut ffi::lua_State,
+    lanes: FxHashMap<*mut ffi::lua_State, Lane>,
+    events: TraceEvents,
+    #[cfg(not(feature = "luau"))]
+    pub(crate) prev_hook: (Option<ffi::lua_Hook>, c_int, c_int),
+    #[cfg(feature = "luau")]
+    pub(crate) prev_interrupt: Option<unsafe extern "C-unwind" fn(*mut ffi::lua_State, c_int)>,
+}
+
+struct Lane {
+    index: usize,
+    depth: usize,
+    // Functions on the stack during the last interrupt
+    #[cfg(feature = "luau")]
+    stack: Vec<*const c_void>,
+}
+
+impl TraceRecorder {
+    pub(crate) fn new(main_state: *mut ffi::lua_State) -> Self {
+        let mut recorder = TraceRecorder {
+            main_state,
+            lanes: FxHashMap::default(),
+            events: TraceEvents {
+                origin: Instant::now(),
+                lanes: Vec::new(),
+                events: Vec::new(),
+            },
+            #[cfg(not(feature = "luau"))]
+            prev_hook: (None, 0, 0),
+            #[cfg(feature = "luau")]
+            prev_interrupt: None,
+        };
+        // The main thread always gets the first lane
+        recorder.lane(main_state);
+        recorder
+    }
+
+    fn lane(&mut self, state: *mut ffi::lua_State) -> &mut Lane {
+        let lanes = &mut self.events.lanes;
+        let main_state = self.main_state;
+        self.lanes.entry(state).or_insert_with(|| {
+            let name = match state == main_state {
+                true => "main".to_string(),
+                false => format!("coroutine {}", lanes.len()),
+            };
+            lanes.push(name);
+            Lane {
+                index: lanes.len() - 1,
+                depth: 0,
+                #[cfg(feature = "luau")]
+                stack: Vec::new(),
+            }
+        })
+    }
+
+    pub(crate) fn enter(
+        &mut self,
+        state: *mut ffi::lua_State,
+        name: String,
+        source: Option<String>,
+    ) {
+        let ts = self.events.origin.elapsed();
+        let lane = self.lane(state);
+        lane.depth += 1;
+        let lane = lane.index;
+        let begin = Some((name, source));
+        self.events.events.push(TraceEvent { lane, ts, begin });
+    }
+
+    pub(crate) fn exit(&mut self, state: *mut ffi::lua_State) {
+        let ts = self.events.origin.elapsed();
+        let lane = self.lane(state);
+        // Skip functions entered before the recording was started
+        if lane.depth == 0 {
+            return;
+        }
+        lane.depth -= 1;
+        let lane = lane.index;
+        self.events.events.push(TraceEvent {
+            lane,
+            ts,
+            begin: None,
+        });
+    }
+
+    // Replaces the recorded stack of `state` with `stack` (ordered from the outermost function),
+    // emitting events for the functions that were returned from or entered since then.
+    #[cfg(feature = "luau")]
+    pub(crate) fn sync_stack(
+        &mut self,
+        state: *mut ffi::lua_State,
+        stack: Vec<*const c_void>,
+        mut label: impl FnMut(usize) -> (String, Option<String>),
+    ) {
+        let prev_stack = std::mem::take(&mut self.lane(state).stack);
+        let common = (prev_stack.iter())
+            .zip(&stack)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for _ in common..prev_stack.len() {
+            self.exit(state);
+        }
+        for i in common..stack.len() {
+            let (name, source) = label(i);
+            self.enter(state, name, source);
+        }
+        self.lane(state).stack = stack;
+    }
+
+    pub(crate) fn finish(mut self) -> TraceEvents {
+        // Close functions that are still running
+        let states = self.lanes.keys().copied().collect::<Vec<_>>();
+        for state in states {
+            while self.lane(state).depth > 0 {
+                self.exit(state);
+            }
+        }
+        self.events
+    }
+}
@@ -1,8 +1,10 @@
 use std::any::{Any, TypeId};
 use std::cell::{Cell, Ref, RefCell, RefMut, UnsafeCell};
+use std::ffi::CString;
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_int, c_void};
+use std::panic::Location;
 use std::result::Result as StdResult;
 use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
@@ -10,13 +12,21 @@ use std::{fmt, mem, ptr};
 
 use rustc_hash::FxHashMap;
 
-use crate::error::Result;
+use crate::deprecation::Deprecation;
+use crate::error::{ConversionErrorInfo, Error, Result};
+use crate::lua::{ExtraData, GcEvent, Lua};
+use crate::middleware::{CallbackCtx, CallbackNext};
 #[cfg(not(feature = "luau"))]
-use crate::hook::Debug;
-use crate::lua::{ExtraData, Lua};
+use crate::{hook::Debug, lua::ExitAction};
+
+#[cfg(feature = "trace_conversions")]
+use crate::conversion_trace::ConversionTrace;
 
 #[cfg(feature = "async")]
-use {crate::value::MultiValue, futures_util::future::LocalBoxFuture};
+use {
+    crate::{thread::Thread, value::MultiValue},
+    futures_util::future::LocalBoxFuture,
+};
 
 #[cfg(feature = "unstable")]
 use {crate::lua::LuaInner, std::marker::PhantomData};
@@ -43,6 +53,55 @@ pub(crate) enum SubtypeId {
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct LightUserData(pub *mut c_void);
 
+/// A light userdata value tagged with the type of its pointee.
+///
+/// Created by [`Lua::create_light_userdata_typed`], which records the pointer and its type in a
+/// host-side registry. Converting a Lua value back to `TypedLightUserData<T>` verifies that
+/// the pointer is known and points to `T`, giving a safer alternative to raw [`LightUserData`].
+///
+/// Note that the registry does not track the pointee lifetime.
+pub struct TypedLightUserData<T>(*mut T);
+
+impl<T> TypedLightUserData<T> {
+    pub(crate) const fn new(ptr: *mut T) -> Self {
+        TypedLightUserData(ptr)
+    }
+
+    /// Returns the underlying pointer.
+    #[inline]
+    pub const fn as_ptr(&self) -> *mut T {
+        self.0
+    }
+
+    /// Returns the untyped [`LightUserData`].
+    #[inline]
+    pub const fn to_light_userdata(&self) -> LightUserData {
+        LightUserData(self.0 as *mut c_void)
+    }
+}
+
+impl<T> Clone for TypedLightUserData<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TypedLightUserData<T> {}
+
+impl<T> PartialEq for TypedLightUserData<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for TypedLightUserData<T> {}
+
+impl<T> fmt::Debug for TypedLightUserData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("TypedLightUserData").field(&self.0).finish()
+    }
+}
+
 pub(crate) type Callback<'lua, 'a> = Box<dyn Fn(&'lua Lua, c_int) -> Result<c_int> + 'a>;
 
 pub(crate) struct Upvalue<T> {
@@ -52,6 +111,30 @@ pub(crate) struct Upvalue<T> {
 
 pub(crate) type CallbackUpvalue = Upvalue<Callback<'static, 'static>>;
 
+// Registered name of a callback created by `Lua::create_named_function` or registered as a
+// userdata method, kept as its second upvalue
+pub(crate) struct CallbackName {
+    pub(crate) name: CString,
+    // Source of the callback in Lua format (`=file`), pointing to where it was created (if known)
+    pub(crate) source: Option<CString>,
+    pub(crate) line: c_int,
+}
+
+impl CallbackName {
+    pub(crate) fn new(name: &str, location: Option<&'static Location<'static>>) -> Result<Self> {
+        let cstring = |s: String| {
+            CString::new(s).map_err(|err| Error::runtime(format!("invalid name: {err}")))
+        };
+        Ok(CallbackName {
+            name: cstring(name.to_string())?,
+            source: location
+                .map(|loc| cstring(format!("={}", loc.file())))
+                .transpose()?,
+            line: location.map(|loc| loc.line() as c_int).unwrap_or(-1),
+        })
+    }
+}
+
 #[cfg(feature = "async")]
 pub(crate) type AsyncCallback<'lua, 'a> =
     Box<dyn Fn(&'lua Lua, MultiValue<'lua>) -> LocalBoxFuture<'lua, Result<c_int>> + 'a>;
@@ -88,6 +171,88 @@ pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &str, bool) -> Result<()> + Send
 #[cfg(all(not(feature = "send"), feature = "lua54"))]
 pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &str, bool) -> Result<()>>;
 
+#[cfg(feature = "send")]
+pub(crate) type GcStepCallback = Arc<dyn Fn(&Lua, bool) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type GcStepCallback = Arc<dyn Fn(&Lua, bool) -> Result<()>>;
+
+#[cfg(feature = "send")]
+pub(crate) type GcCallback = Box<dyn Fn(GcEvent) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type GcCallback = Box<dyn Fn(GcEvent)>;
+
+#[cfg(all(feature = "send", not(feature = "luau")))]
+pub(crate) type ExitCallback = Arc<dyn Fn(i32) -> ExitAction + Send>;
+
+#[cfg(all(not(feature = "send"), not(feature = "luau")))]
+pub(crate) type ExitCallback = Arc<dyn Fn(i32) -> ExitAction>;
+
+#[cfg(feature = "send")]
+pub(crate) type CallbackMiddleware =
+    Arc<dyn Fn(&Lua, CallbackCtx, CallbackNext) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type CallbackMiddleware = Arc<dyn Fn(&Lua, CallbackCtx, CallbackNext) -> Result<()>>;
+
+#[cfg(feature = "send")]
+pub(crate) type ConversionErrorHook = Arc<dyn Fn(&ConversionErrorInfo) + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type ConversionErrorHook = Arc<dyn Fn(&ConversionErrorInfo)>;
+
+#[cfg(all(feature = "trace_conversions", feature = "send"))]
+pub(crate) type ConversionTraceCallback = Arc<dyn Fn(&ConversionTrace) + Send>;
+
+#[cfg(all(feature = "trace_conversions", not(feature = "send")))]
+pub(crate) type ConversionTraceCallback = Arc<dyn Fn(&ConversionTrace)>;
+
+#[cfg(feature = "send")]
+pub(crate) type MemoryPressureCallback = Arc<dyn Fn(&Lua, usize) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type MemoryPressureCallback = Arc<dyn Fn(&Lua, usize) -> Result<()>>;
+
+#[cfg(feature = "send")]
+pub(crate) type DeprecationHandler = Arc<dyn Fn(&Lua, &Deprecation) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type DeprecationHandler = Arc<dyn Fn(&Lua, &Deprecation) -> Result<()>>;
+
+#[cfg(all(feature = "async", feature = "send"))]
+pub(crate) type AsyncResumeHook = Arc<dyn Fn(&Lua, &Thread) -> Result<()> + Send>;
+
+#[cfg(all(feature = "async", not(feature = "send")))]
+pub(crate) type AsyncResumeHook = Arc<dyn Fn(&Lua, &Thread) -> Result<()>>;
+
+#[cfg(all(feature = "async", feature = "send"))]
+pub(crate) type AsyncYieldHook = Arc<dyn Fn(&Lua, &Thread, &MultiValue) -> Result<()> + Send>;
+
+#[cfg(all(feature = "async", not(feature = "send")))]
+pub(crate) type AsyncYieldHook = Arc<dyn Fn(&Lua, &Thread, &MultiValue) -> Result<()>>;
+
+#[cfg(all(feature = "async", feature = "send"))]
+pub(crate) type AsyncCompleteHook =
+    Arc<dyn Fn(&Lua, &Thread, StdResult<&MultiValue, &Error>) -> Result<()> + Send>;
+
+#[cfg(all(feature = "async", not(feature = "send")))]
+pub(crate) type AsyncCompleteHook =
+    Arc<dyn Fn(&Lua, &Thread, StdResult<&MultiValue, &Error>) -> Result<()>>;
+
+#[cfg(feature = "send")]
+pub(crate) type SetupCallback = Arc<dyn Fn(&Lua) -> Result<()> + Send + Sync>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type SetupCallback = Arc<dyn Fn(&Lua) -> Result<()>>;
+
+// A recorded step of a Lua state initialization, replayed by `Lua::fork`
+#[derive(Clone)]
+pub(crate) enum SetupStep {
+    Chunk { name: String, bytecode: Arc<[u8]> },
+    Callback(SetupCallback),
+}
+
 #[cfg(feature = "send")]
 pub trait MaybeSend: Send {}
 #[cfg(feature = "send")]
@@ -98,6 +263,16 @@ pub trait MaybeSend {}
 #[cfg(not(feature = "send"))]
 impl<T> MaybeSend for T {}
 
+#[cfg(feature = "send")]
+pub trait MaybeSync: Sync {}
+#[cfg(feature = "send")]
+impl<T: Sync> MaybeSync for T {}
+
+#[cfg(not(feature = "send"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "send"))]
+impl<T> MaybeSync for T {}
+
 /// A Luau vector type.
 ///
 /// By default vectors are 3-dimensional, but can be 4-dimensional
@@ -297,6 +472,7 @@ impl<'lua> LuaRef<'lua> {
     #[inline]
     pub(crate) fn into_owned(self) -> LuaOwnedRef {
         assert!(self.drop, "Cannot turn non-drop reference into owned");
+        self.lua.untrack_ref(self.index);
         let owned_ref = LuaOwnedRef::new(self.lua.clone(), self.index);
         mem::forget(self);
         owned_ref
@@ -447,6 +623,21 @@ impl AppData {
             .ok()
             .map(|data| *data)
     }
+
+    pub(crate) fn type_ids(&self) -> Vec<TypeId> {
+        unsafe { &*self.container.get() }.keys().copied().collect()
+    }
+
+    // Removes all data which type is not listed in `keep`.
+    // Returns `false` if the container is borrowed.
+    pub(crate) fn retain(&self, keep: &[TypeId]) -> bool {
+        if self.borrow.get() != 0 {
+            return false;
+        }
+        // SAFETY: we checked that there are no other references to the container
+        unsafe { &mut *self.container.get() }.retain(|type_id, _| keep.contains(type_id));
+        true
+    }
 }
 
 /// A wrapper type for an immutably borrowed value from an app data container.
@@ -7,16 +7,23 @@ use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_char, c_int, c_void};
 use std::string::String as StdString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 #[cfg(feature = "async")]
-use std::future::Future;
+use {
+    crate::async_guard::{AsyncConcurrency, AsyncGuard},
+    std::future::Future,
+};
 
 #[cfg(feature = "serialize")]
 use {
+    crate::serde::UserDataToValue,
     serde::ser::{self, Serialize, Serializer},
     std::result::Result as StdResult,
 };
 
+use crate::display;
 use crate::error::{Error, Result};
 use crate::function::Function;
 use crate::lua::Lua;
@@ -315,6 +322,101 @@ pub trait UserDataMethods<'lua, T> {
         MR: Future<Output = Result<R>> + 's,
         R: IntoLuaMulti<'lua>;
 
+    /// Add an async method which accepts a `&T` as the first parameter and returns Future, with a
+    /// policy for concurrent calls on the same userdata.
+    ///
+    /// This is a version of [`add_async_method`] that can queue or reject a call while another
+    /// guarded call on the same userdata instance is running. See [`AsyncConcurrency`] for
+    /// details.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::atomic::{AtomicI64, Ordering};
+    /// # use std::time::Duration;
+    /// # use mlua::{AsyncConcurrency, UserData, UserDataMethods};
+    /// struct Counter(AtomicI64);
+    ///
+    /// impl UserData for Counter {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         // Read-modify-write across an await point
+    ///         let queue = AsyncConcurrency::Queue;
+    ///         methods.add_async_method_with("incr", queue, |_, this, ()| async move {
+    ///             let value = this.0.load(Ordering::Relaxed);
+    ///             tokio::time::sleep(Duration::from_millis(10)).await;
+    ///             this.0.store(value + 1, Ordering::Relaxed);
+    ///             Ok(value + 1)
+    ///         });
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`add_async_method`]: #method.add_async_method
+    /// [`AsyncConcurrency`]: crate::AsyncConcurrency
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    fn add_async_method_with<'s, M, A, MR, R>(
+        &mut self,
+        name: impl AsRef<str>,
+        concurrency: AsyncConcurrency,
+        method: M,
+    ) where
+        'lua: 's,
+        T: 'static,
+        M: Fn(&'lua Lua, &'s T, A) -> MR + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        MR: Future<Output = Result<R>> + 's,
+        R: IntoLuaMulti<'lua>,
+    {
+        // Custom implementations guard each method separately
+        let guard = AsyncGuard::new(Default::default(), concurrency);
+        self.add_async_method(name, move |lua, this, args| {
+            let (guard, key) = (guard.clone(), this as *const T as usize);
+            let fut = method(lua, this, args);
+            async move {
+                let _permit = match guard {
+                    Some(guard) => Some(guard.enter(key).await?),
+                    None => None,
+                };
+                fut.await
+            }
+        })
+    }
+
+    /// Add an async method which accepts a `&mut T` as the first parameter and returns Future,
+    /// with a policy for concurrent calls on the same userdata.
+    ///
+    /// This is a version of [`add_async_method_mut`] that can queue concurrent calls on the same
+    /// userdata instance, instead of failing to borrow the userdata mutably.
+    /// See [`AsyncConcurrency`] for details.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// [`add_async_method_mut`]: #method.add_async_method_mut
+    /// [`AsyncConcurrency`]: crate::AsyncConcurrency
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    fn add_async_method_mut_with<'s, M, A, MR, R>(
+        &mut self,
+        name: impl AsRef<str>,
+        concurrency: AsyncConcurrency,
+        method: M,
+    ) where
+        'lua: 's,
+        T: 'static,
+        M: Fn(&'lua Lua, &'s mut T, A) -> MR + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        MR: Future<Output = Result<R>> + 's,
+        R: IntoLuaMulti<'lua>,
+    {
+        // Custom implementations borrow the userdata before the guard is entered, so concurrent
+        // calls fail to borrow it instead of being queued
+        let _ = concurrency;
+        self.add_async_method_mut(name, method)
+    }
+
     /// Add a regular method as a function which accepts generic arguments, the first argument will
     /// be a [`AnyUserData`] of type `T` if the method is called with Lua method syntax:
     /// `my_userdata:my_method(arg1, arg2)`, or it is passed in as the first argument:
@@ -644,23 +746,88 @@ pub trait UserData: Sized {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {}
 }
 
+/// Statistics of live userdata instances of a registered type.
+///
+/// Returned by [`Lua::userdata_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UserDataStats {
+    /// Name of the Rust type.
+    pub type_name: &'static str,
+    /// Number of live (not yet destructed) instances.
+    pub live: usize,
+    /// Total number of instances created.
+    pub created: usize,
+    /// Approximate amount of memory (in bytes) used by live instances.
+    ///
+    /// Only the size of the type itself is counted, memory owned by the instances
+    /// (e.g. heap allocations) is not included.
+    pub bytes: usize,
+}
+
+// Per-type counters of userdata instances
+pub(crate) struct UserDataCounter {
+    type_name: &'static str,
+    instance_size: usize,
+    live: AtomicUsize,
+    created: AtomicUsize,
+}
+
+impl UserDataCounter {
+    pub(crate) fn new<T>() -> Self {
+        UserDataCounter {
+            type_name: type_name::<T>(),
+            instance_size: mem::size_of::<UserDataCell<T>>() + mem::size_of::<T>(),
+            live: AtomicUsize::new(0),
+            created: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> UserDataStats {
+        let live = self.live.load(Ordering::Relaxed);
+        UserDataStats {
+            type_name: self.type_name,
+            live,
+            created: self.created.load(Ordering::Relaxed),
+            bytes: live * self.instance_size,
+        }
+    }
+}
+
+// Counts a userdata instance as live until dropped
+pub(crate) struct UserDataTracker(Arc<UserDataCounter>);
+
+impl UserDataTracker {
+    pub(crate) fn new(counter: Arc<UserDataCounter>) -> Self {
+        counter.live.fetch_add(1, Ordering::Relaxed);
+        counter.created.fetch_add(1, Ordering::Relaxed);
+        UserDataTracker(counter)
+    }
+}
+
+impl Drop for UserDataTracker {
+    fn drop(&mut self) {
+        self.0.live.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 // Wraps UserData in a way to always implement `serde::Serialize` trait.
-pub(crate) struct UserDataCell<T>(RefCell<UserDataVariant<T>>);
+pub(crate) struct UserDataCell<T>(RefCell<UserDataVariant<T>>, Option<UserDataTracker>);
 
 impl<T> UserDataCell<T> {
     #[inline]
     pub(crate) fn new(data: T) -> Self {
-        UserDataCell(RefCell::new(UserDataVariant::new(data)))
+        UserDataCell(RefCell::new(UserDataVariant::new(data)), None)
     }
 
     #[inline]
     pub(crate) fn new_ref(data: &T) -> Self {
-        UserDataCell(RefCell::new(UserDataVariant::new_ref(data)))
+        UserDataCell(RefCell::new(UserDataVariant::new_ref(data)), None)
     }
 
     #[inline]
     pub(crate) fn new_ref_mut(data: &mut T) -> Self {
-        UserDataCell(RefCell::new(UserDataVariant::new_ref_mut(data)))
+        UserDataCell(RefCell::new(UserDataVariant::new_ref_mut(data)), None)
     }
 
     #[cfg(feature = "serialize")]
@@ -669,7 +836,13 @@ impl<T> UserDataCell<T> {
     where
         T: Serialize + 'static,
     {
-        UserDataCell(RefCell::new(UserDataVariant::new_ser(data)))
+        UserDataCell(RefCell::new(UserDataVariant::new_ser(data)), None)
+    }
+
+    // Attaches a tracker to count this instance in the userdata stats.
+    #[inline]
+    pub(crate) fn set_tracker(&mut self, tracker: UserDataTracker) {
+        self.1 = Some(tracker);
     }
 
     // Immutably borrows the wrapped value.
@@ -844,6 +1017,7 @@ impl<'lua> AnyUserData<'lua> {
     /// Sets the special "destructed" metatable that prevents any further operations with this userdata.
     ///
     /// Keeps associated user values unchanged (they will be collected by Lua's GC).
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn take<T: 'static>(&self) -> Result<T> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -856,7 +1030,9 @@ impl<'lua> AnyUserData<'lua> {
                 Some(type_id) if type_id == TypeId::of::<T>() => {
                     // Try to borrow userdata exclusively
                     let _ = (*get_userdata::<UserDataCell<T>>(state, -1)).try_borrow_mut()?;
-                    take_userdata::<UserDataCell<T>>(state).into_inner()
+                    let value = take_userdata::<UserDataCell<T>>(state).into_inner();
+                    lua.release_keep_alive(&self.0);
+                    value
                 }
                 _ => Err(Error::UserDataTypeMismatch),
             }
@@ -903,6 +1079,7 @@ impl<'lua> AnyUserData<'lua> {
     /// For other Lua versions this functionality is provided using a wrapping table.
     ///
     /// [`nth_user_value`]: #method.nth_user_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_nth_user_value<V: IntoLua<'lua>>(&self, n: usize, v: V) -> Result<()> {
         if n < 1 || n > u16::MAX as usize {
             return Err(Error::runtime("user value index out of bounds"));
@@ -956,6 +1133,7 @@ impl<'lua> AnyUserData<'lua> {
     /// For other Lua versions this functionality is provided using a wrapping table.
     ///
     /// [`set_nth_user_value`]: #method.set_nth_user_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn nth_user_value<V: FromLua<'lua>>(&self, n: usize) -> Result<V> {
         if n < 1 || n > u16::MAX as usize {
             return Err(Error::runtime("user value index out of bounds"));
@@ -1002,6 +1180,7 @@ impl<'lua> AnyUserData<'lua> {
     /// The value can be retrieved with [`named_user_value`].
     ///
     /// [`named_user_value`]: #method.named_user_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn set_named_user_value<V: IntoLua<'lua>>(&self, name: &str, v: V) -> Result<()> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -1037,6 +1216,7 @@ impl<'lua> AnyUserData<'lua> {
     /// Returns an associated value by name set by [`set_named_user_value`].
     ///
     /// [`set_named_user_value`]: #method.set_named_user_value
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn named_user_value<V: FromLua<'lua>>(&self, name: &str) -> Result<V> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -1075,10 +1255,12 @@ impl<'lua> AnyUserData<'lua> {
     ///
     /// [`UserDataMetatable`]: crate::UserDataMetatable
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn get_metatable(&self) -> Result<UserDataMetatable<'lua>> {
         self.get_raw_metatable().map(UserDataMetatable)
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn get_raw_metatable(&self) -> Result<Table<'lua>> {
         let lua = self.0.lua;
         let state = lua.state();
@@ -1117,6 +1299,7 @@ impl<'lua> AnyUserData<'lua> {
     }
 
     /// Returns a type name of this `UserData` (from a metatable field).
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn type_name(&self) -> Result<Option<StdString>> {
         match self.1 {
             SubtypeId::None => {}
@@ -1148,6 +1331,7 @@ impl<'lua> AnyUserData<'lua> {
         }
     }
 
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub(crate) fn equals<T: AsRef<Self>>(&self, other: T) -> Result<bool> {
         let other = other.as_ref();
         // Uses lua_rawequal() under the hood
@@ -1175,7 +1359,10 @@ impl<'lua> AnyUserData<'lua> {
         let lua = self.0.lua;
         let is_serializable = || unsafe {
             // Userdata can be unregistered or destructed
-            let _ = lua.get_userdata_ref_type_id(&self.0)?;
+            let type_id = lua.get_userdata_ref_type_id(&self.0)?;
+            if type_id.and_then(|id| lua.userdata_serializer(id)).is_some() {
+                return Result::Ok(true);
+            }
 
             let ud = &*get_userdata::<UserDataCell<()>>(lua.ref_thread(), self.0.index);
             match &*ud.0.try_borrow().map_err(|_| Error::UserDataBorrowError)? {
@@ -1186,7 +1373,18 @@ impl<'lua> AnyUserData<'lua> {
         is_serializable().unwrap_or(false)
     }
 
-    fn inspect<'a, T, F, R>(&'a self, func: F) -> Result<R>
+    /// Returns the type name and converter if this `AnyUserData` type implements
+    /// [`UserDataSerialize`] and is registered.
+    ///
+    /// [`UserDataSerialize`]: crate::UserDataSerialize
+    #[cfg(feature = "serialize")]
+    pub(crate) fn userdata_serializer(&self) -> Option<(&'static str, UserDataToValue)> {
+        let lua = self.0.lua;
+        let type_id = unsafe { lua.get_userdata_ref_type_id(&self.0) }.ok()??;
+        lua.userdata_serializer(type_id)
+    }
+
+    pub(crate) fn inspect<'a, T, F, R>(&'a self, func: F) -> Result<R>
     where
         T: 'static,
         F: FnOnce(&'a UserDataCell<T>) -> Result<R>,
@@ -1205,6 +1403,12 @@ impl<'lua> AnyUserData<'lua> {
     }
 }
 
+impl fmt::Display for AnyUserData<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        display::fmt_ref(fmt, &self.0, "userdata")
+    }
+}
+
 impl<'lua> PartialEq for AnyUserData<'lua> {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -1353,6 +1557,11 @@ impl<'lua> Serialize for AnyUserData<'lua> {
             return serializer.serialize_bytes(buf);
         }
 
+        if let Some((_, to_value)) = self.userdata_serializer() {
+            let value = to_value(self).map_err(ser::Error::custom)?;
+            return value.serialize(serializer);
+        }
+
         let data = unsafe {
             let _ = lua
                 .get_userdata_ref_type_id(&self.0)
@@ -7,6 +7,7 @@ use std::os::raw::c_int;
 use std::string::String as StdString;
 use std::sync::{Arc, Mutex, RwLock};
 
+use crate::deprecation::{deprecate_callback, DeprecationPolicy};
 use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::types::{Callback, MaybeSend};
@@ -20,7 +21,13 @@ use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Value};
 use std::rc::Rc;
 
 #[cfg(feature = "async")]
-use {crate::types::AsyncCallback, futures_util::future, std::future::Future};
+use {
+    crate::async_guard::{AsyncCalls, AsyncConcurrency, AsyncGuard},
+    crate::deprecation::deprecate_async_callback,
+    crate::types::AsyncCallback,
+    futures_util::future,
+    std::future::Future,
+};
 
 /// Handle to registry for userdata methods and metamethods.
 pub struct UserDataRegistry<'lua, T: 'static> {
@@ -38,6 +45,13 @@ pub struct UserDataRegistry<'lua, T: 'static> {
     #[cfg(feature = "async")]
     pub(crate) async_meta_methods: Vec<(String, AsyncCallback<'lua, 'static>)>,
 
+    // Number of arguments of methods (if fixed), for introspection
+    pub(crate) nargs: Vec<(String, Option<usize>)>,
+
+    // Running calls of async methods with a concurrency guard
+    #[cfg(feature = "async")]
+    async_calls: Option<Arc<AsyncCalls>>,
+
     _type: PhantomData<T>,
 }
 
@@ -54,10 +68,130 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
             meta_methods: Vec::new(),
             #[cfg(feature = "async")]
             async_meta_methods: Vec::new(),
+            nargs: Vec::new(),
+            #[cfg(feature = "async")]
+            async_calls: None,
             _type: PhantomData,
         }
     }
 
+    /// Makes the userdata directly callable from Lua, eg. `obj(...)`.
+    ///
+    /// The method receives a `&T` and the call arguments. This is a shortcut for adding the
+    /// [`MetaMethod::Call`] metamethod using [`add_meta_method`].
+    ///
+    /// [`add_meta_method`]: UserDataMethods::add_meta_method
+    pub fn add_call<M, A, R>(&mut self, method: M)
+    where
+        M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.add_meta_method(MetaMethod::Call, method);
+    }
+
+    /// Sets a handler for reading keys that don't match any registered field or method.
+    ///
+    /// Allows record-like userdata exposing keys known only at runtime (eg. a JSON document),
+    /// without registering every possible key. Unknown keys raise an error by default.
+    ///
+    /// This is a shortcut for adding the [`MetaMethod::Index`] metamethod using
+    /// [`add_meta_method`], replacing a previously added one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Record(HashMap<String, i64>);
+    ///
+    /// lua.register_userdata_type::<Record>(|reg| {
+    ///     reg.add_method("len", |_, this, ()| Ok(this.0.len()));
+    ///     reg.set_dynamic_index(|_, this, key: String| Ok(this.0.get(&key).copied()));
+    ///     reg.set_dynamic_newindex(|_, this, key: String, value: i64| {
+    ///         this.0.insert(key, value);
+    ///         Ok(())
+    ///     });
+    /// })?;
+    /// lua.globals().set("record", lua.create_any_userdata(Record(HashMap::new()))?)?;
+    /// lua.load(r#"
+    ///     record.score = 10
+    ///     assert(record.score == 10 and record.missing == nil)
+    ///     assert(record:len() == 1)
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`add_meta_method`]: UserDataMethods::add_meta_method
+    pub fn set_dynamic_index<F, K, V>(&mut self, handler: F)
+    where
+        F: Fn(&'lua Lua, &T, K) -> Result<V> + MaybeSend + 'static,
+        K: FromLua<'lua>,
+        V: IntoLua<'lua>,
+    {
+        self.add_meta_method(MetaMethod::Index, handler);
+    }
+
+    /// Sets a handler for assigning keys that don't match any registered field setter.
+    ///
+    /// This is a shortcut for adding the [`MetaMethod::NewIndex`] metamethod using
+    /// [`add_meta_method_mut`], replacing a previously added one.
+    /// See [`set_dynamic_index`] for an example.
+    ///
+    /// [`add_meta_method_mut`]: UserDataMethods::add_meta_method_mut
+    /// [`set_dynamic_index`]: UserDataRegistry::set_dynamic_index
+    pub fn set_dynamic_newindex<F, K, V>(&mut self, mut handler: F)
+    where
+        F: FnMut(&'lua Lua, &mut T, K, V) -> Result<()> + MaybeSend + 'static,
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+    {
+        self.add_meta_method_mut(
+            MetaMethod::NewIndex,
+            move |lua, this, (key, value): (K, V)| handler(lua, this, key, value),
+        );
+    }
+
+    /// Marks the previously added method (or metamethod) `name` as deprecated.
+    ///
+    /// Calls of the method notify the handler set by [`Lua::set_deprecation_handler`] with the
+    /// `message` (eg. the replacement to use), as required by the `policy`.
+    /// Has no effect if no method with this name has been added.
+    pub fn deprecate_method(
+        &mut self,
+        name: impl AsRef<str>,
+        message: impl Into<StdString>,
+        policy: DeprecationPolicy,
+    ) {
+        let name = name.as_ref();
+        let full_name = get_function_name::<T>(name);
+        let message = message.into();
+        for methods in [&mut self.methods, &mut self.meta_methods] {
+            if let Some(i) = methods.iter().rposition(|(n, _)| n == name) {
+                let (name, callback) = methods.remove(i);
+                let callback = deprecate_callback(callback, full_name, message, policy);
+                methods.insert(i, (name, callback));
+                return;
+            }
+        }
+        #[cfg(feature = "async")]
+        for methods in [&mut self.async_methods, &mut self.async_meta_methods] {
+            if let Some(i) = methods.iter().rposition(|(n, _)| n == name) {
+                let (name, callback) = methods.remove(i);
+                let callback = deprecate_async_callback(callback, full_name, message, policy);
+                methods.insert(i, (name, callback));
+                return;
+            }
+        }
+    }
+
+    fn record_nargs<A: FromLuaMulti<'lua>>(&mut self, name: &str) {
+        self.nargs.push((name.into(), A::NARGS));
+    }
+
     fn box_method<M, A, R>(name: &str, method: M) -> Callback<'lua, 'static>
     where
         M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
@@ -209,7 +343,11 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
     }
 
     #[cfg(feature = "async")]
-    fn box_async_method<'s, M, A, MR, R>(name: &str, method: M) -> AsyncCallback<'lua, 'static>
+    fn box_async_method<'s, M, A, MR, R>(
+        name: &str,
+        guard: Option<AsyncGuard>,
+        method: M,
+    ) -> AsyncCallback<'lua, 'static>
     where
         'lua: 's,
         T: 'static,
@@ -223,6 +361,7 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
 
         Box::new(move |lua, mut args| unsafe {
             let name = name.clone();
+            let guard = guard.clone();
             let method = method.clone();
             macro_rules! try_self_arg {
                 ($res:expr) => {
@@ -240,6 +379,13 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
                 let this = try_self_arg!(AnyUserData::from_lua(try_self_arg!(this), lua));
                 let args = A::from_lua_args(args, 2, Some(&name), lua);
 
+                // Wait for (or reject) a running call on the same userdata
+                let key = this.to_pointer() as usize;
+                let _permit = match &guard {
+                    Some(guard) => Some(try_self_arg!(guard.enter(key).await)),
+                    None => None,
+                };
+
                 let (ref_thread, index) = (lua.ref_thread(), this.0.index);
                 match try_self_arg!(this.type_id()) {
                     Some(id) if id == TypeId::of::<T>() => {
@@ -303,7 +449,11 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
     }
 
     #[cfg(feature = "async")]
-    fn box_async_method_mut<'s, M, A, MR, R>(name: &str, method: M) -> AsyncCallback<'lua, 'static>
+    fn box_async_method_mut<'s, M, A, MR, R>(
+        name: &str,
+        guard: Option<AsyncGuard>,
+        method: M,
+    ) -> AsyncCallback<'lua, 'static>
     where
         'lua: 's,
         T: 'static,
@@ -317,6 +467,7 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
 
         Box::new(move |lua, mut args| unsafe {
             let name = name.clone();
+            let guard = guard.clone();
             let method = method.clone();
             macro_rules! try_self_arg {
                 ($res:expr) => {
@@ -334,6 +485,13 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
                 let this = try_self_arg!(AnyUserData::from_lua(try_self_arg!(this), lua));
                 let args = A::from_lua_args(args, 2, Some(&name), lua);
 
+                // Wait for (or reject) a running call on the same userdata
+                let key = this.to_pointer() as usize;
+                let _permit = match &guard {
+                    Some(guard) => Some(try_self_arg!(guard.enter(key).await)),
+                    None => None,
+                };
+
                 let (ref_thread, index) = (lua.ref_thread(), this.0.index);
                 match try_self_arg!(this.type_id()) {
                     Some(id) if id == TypeId::of::<T>() => {
@@ -394,6 +552,13 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
         })
     }
 
+    // Guard shared by all async methods of the type with the given concurrency policy
+    #[cfg(feature = "async")]
+    fn async_guard(&mut self, concurrency: AsyncConcurrency) -> Option<AsyncGuard> {
+        let calls = self.async_calls.get_or_insert_with(Default::default);
+        AsyncGuard::new(calls.clone(), concurrency)
+    }
+
     fn box_function<F, A, R>(name: &str, function: F) -> Callback<'lua, 'static>
     where
         F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
@@ -465,7 +630,7 @@ impl<'lua, T: 'static> UserDataRegistry<'lua, T> {
 }
 
 // Returns function name for the type `T`, without the module path
-fn get_function_name<T>(name: &str) -> StdString {
+pub(crate) fn get_function_name<T>(name: &str) -> StdString {
     format!("{}.{name}", short_type_name::<T>())
 }
 
@@ -568,6 +733,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.methods
             .push((name.into(), Self::box_method(name, method)));
     }
@@ -579,6 +745,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.methods
             .push((name.into(), Self::box_method_mut(name, method)));
     }
@@ -594,8 +761,9 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.async_methods
-            .push((name.into(), Self::box_async_method(name, method)));
+            .push((name.into(), Self::box_async_method(name, None, method)));
     }
 
     #[cfg(feature = "async")]
@@ -609,8 +777,51 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
+        self.async_methods
+            .push((name.into(), Self::box_async_method_mut(name, None, method)));
+    }
+
+    #[cfg(feature = "async")]
+    fn add_async_method_with<'s, M, A, MR, R>(
+        &mut self,
+        name: impl AsRef<str>,
+        concurrency: AsyncConcurrency,
+        method: M,
+    ) where
+        'lua: 's,
+        T: 'static,
+        M: Fn(&'lua Lua, &'s T, A) -> MR + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        MR: Future<Output = Result<R>> + 's,
+        R: IntoLuaMulti<'lua>,
+    {
+        let name = name.as_ref();
+        self.record_nargs::<A>(name);
+        let guard = self.async_guard(concurrency);
+        self.async_methods
+            .push((name.into(), Self::box_async_method(name, guard, method)));
+    }
+
+    #[cfg(feature = "async")]
+    fn add_async_method_mut_with<'s, M, A, MR, R>(
+        &mut self,
+        name: impl AsRef<str>,
+        concurrency: AsyncConcurrency,
+        method: M,
+    ) where
+        'lua: 's,
+        T: 'static,
+        M: Fn(&'lua Lua, &'s mut T, A) -> MR + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        MR: Future<Output = Result<R>> + 's,
+        R: IntoLuaMulti<'lua>,
+    {
+        let name = name.as_ref();
+        self.record_nargs::<A>(name);
+        let guard = self.async_guard(concurrency);
         self.async_methods
-            .push((name.into(), Self::box_async_method_mut(name, method)));
+            .push((name.into(), Self::box_async_method_mut(name, guard, method)));
     }
 
     fn add_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
@@ -620,6 +831,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.methods
             .push((name.into(), Self::box_function(name, function)));
     }
@@ -631,6 +843,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.methods
             .push((name.into(), Self::box_function_mut(name, function)));
     }
@@ -644,6 +857,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.async_methods
             .push((name.into(), Self::box_async_function(name, function)));
     }
@@ -655,6 +869,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.meta_methods
             .push((name.into(), Self::box_method(name, method)));
     }
@@ -666,6 +881,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.meta_methods
             .push((name.into(), Self::box_method_mut(name, method)));
     }
@@ -681,8 +897,9 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.async_meta_methods
-            .push((name.into(), Self::box_async_method(name, method)));
+            .push((name.into(), Self::box_async_method(name, None, method)));
     }
 
     #[cfg(all(feature = "async", not(any(feature = "lua51", feature = "luau"))))]
@@ -696,8 +913,9 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.async_meta_methods
-            .push((name.into(), Self::box_async_method_mut(name, method)));
+            .push((name.into(), Self::box_async_method_mut(name, None, method)));
     }
 
     fn add_meta_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
@@ -707,6 +925,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.meta_methods
             .push((name.into(), Self::box_function(name, function)));
     }
@@ -718,6 +937,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.meta_methods
             .push((name.into(), Self::box_function_mut(name, function)));
     }
@@ -731,6 +951,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = name.as_ref();
+        self.record_nargs::<A>(name);
         self.async_meta_methods
             .push((name.into(), Self::box_async_function(name, function)));
     }
@@ -744,6 +965,7 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistry<'lua, T> {
         self.meta_methods.extend(other.meta_methods);
         #[cfg(feature = "async")]
         self.async_meta_methods.extend(other.async_meta_methods);
+        self.nargs.extend(other.nargs);
     }
 }
 
@@ -0,0 +1,252 @@
+use std::any::TypeId;
+use std::cell::Ref;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, RwLock, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::userdata::{AnyUserData, UserDataCell};
+
+// Longest pause between attempts to acquire a lock without timed locking support
+const MAX_BACKOFF: Duration = Duration::from_millis(1);
+
+/// A shared borrow of a userdata value returned by [`AnyUserData::try_borrow_for`].
+///
+/// Requires `feature = "send"`
+#[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+pub struct UserDataGuard<'a, T>(Box<dyn Deref<Target = T> + 'a>);
+
+/// A mutable borrow of a userdata value returned by [`AnyUserData::try_borrow_mut_for`].
+///
+/// Requires `feature = "send"`
+#[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+pub struct UserDataGuardMut<'a, T>(Box<dyn DerefMut<Target = T> + 'a>);
+
+impl<'a, T> Deref for UserDataGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'a, T> Deref for UserDataGuardMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'a, T> DerefMut for UserDataGuardMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for UserDataGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for UserDataGuardMut<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+// A lock guard together with the userdata borrow keeping the lock alive
+struct Locked<'a, L, G> {
+    // Must be dropped before the borrow
+    guard: G,
+    _borrow: Ref<'a, Arc<L>>,
+}
+
+impl<'a, L, G: Deref> Deref for Locked<'a, L, G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &G::Target {
+        &self.guard
+    }
+}
+
+impl<'a, L, G: DerefMut> DerefMut for Locked<'a, L, G> {
+    fn deref_mut(&mut self) -> &mut G::Target {
+        &mut self.guard
+    }
+}
+
+impl<'lua> AnyUserData<'lua> {
+    /// Borrows this userdata immutably, waiting at most `timeout` for the value to be available.
+    ///
+    /// Supports userdata of type `T` and values shared with other threads using `Arc<Mutex<T>>`
+    /// or `Arc<RwLock<T>>` (including `parking_lot` locks). Values of type `T` are owned by the Lua
+    /// state, so they are never waited for.
+    ///
+    /// Unlike locking the shared value directly, it never blocks indefinitely: a zero `timeout`
+    /// makes a single attempt.
+    ///
+    /// Requires `feature = "send"`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `WouldBlock` error if the value is still locked (or mutably borrowed) after
+    /// `timeout`. Returns a `UserDataTypeMismatch` if the userdata is not of a supported type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use std::time::Duration;
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let counter = Arc::new(Mutex::new(0));
+    /// let ud = lua.create_any_userdata(counter.clone())?;
+    ///
+    /// let guard = counter.lock().unwrap();
+    /// let result = ud.try_borrow_for::<i32>(Duration::from_millis(10));
+    /// assert!(matches!(result, Err(Error::WouldBlock)));
+    ///
+    /// drop(guard);
+    /// assert_eq!(*ud.try_borrow_for::<i32>(Duration::ZERO)?, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn try_borrow_for<T: 'static>(&self, timeout: Duration) -> Result<UserDataGuard<'_, T>> {
+        macro_rules! guard {
+            ($lock:expr) => {
+                $lock.map(|locked| UserDataGuard(Box::new(locked)))
+            };
+        }
+
+        let type_id = unsafe { self.0.lua.get_userdata_ref_type_id(&self.0)? };
+        match type_id {
+            Some(id) if id == TypeId::of::<T>() => self.inspect(|cell: &UserDataCell<T>| {
+                let borrow = cell.try_borrow().map_err(|_| Error::WouldBlock)?;
+                Ok(UserDataGuard(Box::new(borrow)))
+            }),
+            Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => {
+                guard!(self.lock(|lock: &Mutex<T>| retry_for(timeout, || lock.try_lock())))
+            }
+            Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => {
+                guard!(self.lock(|lock: &RwLock<T>| retry_for(timeout, || lock.try_read())))
+            }
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => {
+                guard!(self.lock(|lock: &parking_lot::Mutex<T>| {
+                    lock.try_lock_for(timeout).ok_or(Error::WouldBlock)
+                }))
+            }
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => {
+                guard!(self.lock(|lock: &parking_lot::RwLock<T>| {
+                    lock.try_read_for(timeout).ok_or(Error::WouldBlock)
+                }))
+            }
+            _ => Err(Error::UserDataTypeMismatch),
+        }
+    }
+
+    /// Borrows this userdata mutably, waiting at most `timeout` for the value to be available.
+    ///
+    /// See [`AnyUserData::try_borrow_for`] for the supported types.
+    ///
+    /// Requires `feature = "send"`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `WouldBlock` error if the value is still locked (or borrowed) after `timeout`.
+    /// Returns a `UserDataBorrowMutError` if the userdata cannot be mutably borrowed at all (e.g.
+    /// it's shared using `Arc<T>`) and `UserDataTypeMismatch` if it's not of a supported type.
+    #[cfg_attr(docsrs, doc(cfg(feature = "send")))]
+    pub fn try_borrow_mut_for<T: 'static>(
+        &self,
+        timeout: Duration,
+    ) -> Result<UserDataGuardMut<'_, T>> {
+        macro_rules! guard {
+            ($lock:expr) => {
+                $lock.map(|locked| UserDataGuardMut(Box::new(locked)))
+            };
+        }
+
+        let type_id = unsafe { self.0.lua.get_userdata_ref_type_id(&self.0)? };
+        match type_id {
+            Some(id) if id == TypeId::of::<T>() => self.inspect(|cell: &UserDataCell<T>| {
+                let borrow = cell.try_borrow_mut().map_err(|_| Error::WouldBlock)?;
+                Ok(UserDataGuardMut(Box::new(borrow)))
+            }),
+            Some(id) if id == TypeId::of::<Arc<T>>() => Err(Error::UserDataBorrowMutError),
+            Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => {
+                guard!(self.lock(|lock: &Mutex<T>| retry_for(timeout, || lock.try_lock())))
+            }
+            Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => {
+                guard!(self.lock(|lock: &RwLock<T>| retry_for(timeout, || lock.try_write())))
+            }
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => {
+                guard!(self.lock(|lock: &parking_lot::Mutex<T>| {
+                    lock.try_lock_for(timeout).ok_or(Error::WouldBlock)
+                }))
+            }
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => {
+                guard!(self.lock(|lock: &parking_lot::RwLock<T>| {
+                    lock.try_write_for(timeout).ok_or(Error::WouldBlock)
+                }))
+            }
+            _ => Err(Error::UserDataTypeMismatch),
+        }
+    }
+
+    // Borrows the shared lock of type `L` stored in this userdata and acquires it using `f`.
+    // The returned guard keeps the userdata borrowed until the lock is released.
+    fn lock<'a, L, G>(&'a self, f: impl FnOnce(&'a L) -> Result<G>) -> Result<Locked<'a, L, G>>
+    where
+        L: 'static,
+    {
+        self.inspect(|cell: &'a UserDataCell<Arc<L>>| {
+            let borrow = cell.try_borrow()?;
+            // The lock lives as long as the userdata is borrowed
+            let lock = unsafe { &*Arc::as_ptr(&borrow) };
+            let guard = f(lock)?;
+            Ok(Locked {
+                guard,
+                _borrow: borrow,
+            })
+        })
+    }
+}
+
+// Calls `try_lock` until it succeeds or `timeout` expires, backing off between attempts
+fn retry_for<G, E>(
+    timeout: Duration,
+    mut try_lock: impl FnMut() -> std::result::Result<G, TryLockError<E>>,
+) -> Result<G> {
+    // Overflowing timeouts wait without a deadline
+    let deadline = Instant::now().checked_add(timeout);
+    let mut backoff = Duration::from_micros(10);
+    loop {
+        match try_lock() {
+            Ok(guard) => return Ok(guard),
+            Err(TryLockError::Poisoned(_)) => return Err(Error::UserDataBorrowError),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        let sleep = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(Error::WouldBlock);
+                }
+                backoff.min(deadline - now)
+            }
+            None => backoff,
+        };
+        thread::sleep(sleep);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -13,8 +13,16 @@ use rustc_hash::FxHashMap;
 
 use crate::error::{Error, Result};
 use crate::memory::MemoryState;
+use crate::quota::QuotaResource;
+use crate::types::CallbackName;
 
 pub(crate) use short_names::short_type_name;
+pub(crate) use traceback::{function_name, traceback};
+
+// Blocking waits are never woken up on wasm targets without threads support (the only thread
+// would wait for itself), so they fail immediately instead
+pub(crate) const CAN_BLOCK: bool =
+    !cfg!(all(target_family = "wasm", not(target_feature = "atomics")));
 
 static METATABLE_CACHE: Lazy<FxHashMap<TypeId, u8>> = Lazy::new(|| {
     let mut map = FxHashMap::with_capacity_and_hasher(32, Default::default());
@@ -27,6 +35,7 @@ static METATABLE_CACHE: Lazy<FxHashMap<TypeId, u8>> = Lazy::new(|| {
 // Checks that Lua has enough free stack space for future stack operations. On failure, this will
 // panic with an internal error message.
 #[inline]
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
 pub unsafe fn assert_stack(state: *mut ffi::lua_State, amount: c_int) {
     // TODO: This should only be triggered when there is a logic error in `mlua`. In the future,
     // when there is a way to be confident about stack safety and test it, this could be enabled
@@ -35,18 +44,58 @@ pub unsafe fn assert_stack(state: *mut ffi::lua_State, amount: c_int) {
         ffi::lua_checkstack(state, amount) != 0,
         "out of stack space"
     );
+    #[cfg(feature = "stack_diagnostics")]
+    record_stack_usage(state, amount);
 }
 
 // Checks that Lua has enough free stack space and returns `Error::StackError` on failure.
 #[inline]
+#[cfg_attr(feature = "stack_diagnostics", track_caller)]
 pub unsafe fn check_stack(state: *mut ffi::lua_State, amount: c_int) -> Result<()> {
     if ffi::lua_checkstack(state, amount) == 0 {
         Err(Error::StackError)
     } else {
+        #[cfg(feature = "stack_diagnostics")]
+        record_stack_usage(state, amount);
         Ok(())
     }
 }
 
+// Records the deepest stack usage and the call site responsible for it (see `Lua::stack_stats`)
+#[cfg(feature = "stack_diagnostics")]
+#[track_caller]
+unsafe fn record_stack_usage(state: *mut ffi::lua_State, amount: c_int) {
+    let mem_state = MemoryState::get(state);
+    if !mem_state.is_null() {
+        let slots = ffi::lua_gettop(state) + amount;
+        (*mem_state).record_stack_usage(slots, std::panic::Location::caller());
+    }
+}
+
+// Runs `f` attributing its stack usage to `location` (see `Lua::stack_stats`), for the closures
+// run on behalf of an mlua API as they cannot propagate `#[track_caller]`
+#[cfg(feature = "stack_diagnostics")]
+pub(crate) unsafe fn with_stack_caller<R>(
+    state: *mut ffi::lua_State,
+    location: &'static std::panic::Location<'static>,
+    f: impl FnOnce() -> R,
+) -> R {
+    struct CallerGuard(*mut MemoryState);
+
+    impl Drop for CallerGuard {
+        fn drop(&mut self) {
+            unsafe { (*self.0).set_stack_caller(None) };
+        }
+    }
+
+    let mem_state = MemoryState::get(state);
+    if mem_state.is_null() || !(*mem_state).set_stack_caller(Some(location)) {
+        return f();
+    }
+    let _guard = CallerGuard(mem_state);
+    f()
+}
+
 pub struct StackGuard {
     state: *mut ffi::lua_State,
     top: c_int,
@@ -230,7 +279,13 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
                     // runtime errors, so we handle them the same way.
                     Error::RuntimeError(err_string)
                 }
-                ffi::LUA_ERRMEM => Error::MemoryError(err_string),
+                ffi::LUA_ERRMEM => match MemoryState::take_quota_reached(state) {
+                    Some(limit) => Error::QuotaExceeded {
+                        resource: QuotaResource::Memory,
+                        limit: limit as u64,
+                    },
+                    None => Error::MemoryError(err_string),
+                },
                 #[cfg(any(feature = "lua53", feature = "lua52"))]
                 ffi::LUA_ERRGCMM => Error::GarbageCollectorError(err_string),
                 _ => mlua_panic!("unrecognized lua error code"),
@@ -620,7 +675,9 @@ pub unsafe fn init_userdata_metatable(
 pub unsafe extern "C-unwind" fn userdata_destructor<T>(state: *mut ffi::lua_State) -> c_int {
     // It's probably NOT a good idea to catch Rust panics in finalizer
     // Lua 5.4 ignores it, other versions generates `LUA_ERRGCMM` without calling message handler
-    take_userdata::<T>(state);
+    let ptr = ffi::lua_touserdata(state, -1) as *const c_void;
+    let value = take_userdata::<T>(state);
+    crate::keep_alive::drop_userdata(state, ptr, value);
     0
 }
 
@@ -1070,6 +1127,46 @@ pub(crate) unsafe fn get_destructed_userdata_metatable(state: *mut ffi::lua_Stat
     ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, key);
 }
 
+// Describes the function on top of the stack in `ar` (filled by `lua_getinfo`) using its registered
+// name, if it's a named callback (see `CallbackName`). Returns `true` if it was.
+// Uses 3 stack spaces, does not call checkstack.
+pub(crate) unsafe fn describe_named_callback(
+    state: *mut ffi::lua_State,
+    ar: *mut ffi::lua_Debug,
+) -> bool {
+    if ffi::lua_iscfunction(state, -1) == 0 || ffi::lua_getupvalue(state, -1, 2).is_null() {
+        return false;
+    }
+    let callback = get_gc_userdata::<CallbackName>(state, -1, ptr::null());
+    ffi::lua_pop(state, 1);
+    let callback = match callback.as_ref() {
+        Some(callback) => callback,
+        None => return false,
+    };
+
+    (*ar).name = callback.name.as_ptr();
+    let source = match callback.source {
+        Some(ref source) => source,
+        None => return true,
+    };
+    (*ar).source = source.as_ptr();
+    (*ar).linedefined = callback.line;
+    // Skip the `=` prefix
+    let short_src = &source.as_bytes()[1..];
+    #[cfg(not(feature = "luau"))]
+    {
+        let len = short_src.len().min((*ar).short_src.len() - 1);
+        let dst = (*ar).short_src.as_mut_ptr() as *mut u8;
+        ptr::copy_nonoverlapping(short_src.as_ptr(), dst, len);
+        (*ar).short_src[len] = 0;
+    }
+    #[cfg(feature = "luau")]
+    {
+        (*ar).short_src = short_src.as_ptr() as *const c_char;
+    }
+    true
+}
+
 pub(crate) unsafe fn ptr_to_str<'a>(input: *const c_char) -> Option<&'a str> {
     if input.is_null() {
         return None;
@@ -1097,3 +1194,4 @@ static USERDATA_METATABLE_INDEX: u8 = 0;
 static USERDATA_METATABLE_NEWINDEX: u8 = 0;
 
 mod short_names;
+mod traceback;
@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::mem;
+use std::os::raw::c_int;
+use std::string::String as StdString;
+
+use super::{describe_named_callback, ptr_to_lossy_str, ptr_to_str};
+
+// Number of the first and the last frames shown in tracebacks of deep stacks
+const LEVELS1: c_int = 10;
+const LEVELS2: c_int = 11;
+
+// Builds the traceback of `state` starting at `level`, modelled after `luaL_traceback` of Lua 5.4.
+// Rust callbacks created by `Lua::create_named_function` are named by their display name.
+// Uses 6 stack spaces, does not call checkstack.
+pub(crate) unsafe fn traceback(state: *mut ffi::lua_State, mut level: c_int) -> StdString {
+    let mut output = StdString::from("stack traceback:");
+    let last = last_level(state);
+    let mut limit = match last - level > LEVELS1 + LEVELS2 {
+        true => LEVELS1,
+        false => -1,
+    };
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    while frame_info(state, level, &mut ar) {
+        level += 1;
+        if limit == 0 {
+            let skip = last - level - LEVELS2 + 1;
+            let _ = write!(output, "\n\t...\t(skipping {skip} levels)");
+            level += skip;
+            ffi::lua_pop(state, 1);
+        } else {
+            #[cfg(not(feature = "luau"))]
+            let short_src = ptr_to_lossy_str(ar.short_src.as_ptr());
+            #[cfg(feature = "luau")]
+            let short_src = ptr_to_lossy_str(ar.short_src);
+            let short_src = short_src.unwrap_or(Cow::Borrowed("?"));
+            let _ = match ar.currentline {
+                line if line > 0 => write!(output, "\n\t{short_src}:{line}: in "),
+                _ => write!(output, "\n\t{short_src}: in "),
+            };
+            write_function_name(state, &mut ar, &mut output);
+        }
+        limit -= 1;
+    }
+    output
+}
+
+// Returns the name of the function running at `level`: the display name of a named Rust
+// callback, the name it was called by or the name it has in a loaded module.
+// Uses 6 stack spaces, does not call checkstack.
+pub(crate) unsafe fn function_name(state: *mut ffi::lua_State, level: c_int) -> Option<StdString> {
+    let mut ar: ffi::lua_Debug = mem::zeroed();
+    if !frame_info(state, level, &mut ar) {
+        return None;
+    }
+    let name = match describe_named_callback(state, &mut ar) {
+        true => ptr_to_lossy_str(ar.name).map(|name| name.into_owned()),
+        false => (ptr_to_str(ar.name).and_then(valid_name).map(str::to_string))
+            .or_else(|| global_function_name(state)),
+    };
+    ffi::lua_pop(state, 1);
+    name
+}
+
+// Filters out placeholders that are not names (eg. "integer index" in Lua 5.4)
+fn valid_name(name: &str) -> Option<&str> {
+    match name.is_empty() || name == "?" || name.contains(' ') {
+        true => None,
+        false => Some(name),
+    }
+}
+
+// Fills `ar` with the `Sln` info of the function running at `level`, pushing the function
+unsafe fn frame_info(state: *mut ffi::lua_State, level: c_int, ar: &mut ffi::lua_Debug) -> bool {
+    #[cfg(not(feature = "luau"))]
+    return ffi::lua_getstack(state, level, ar) != 0
+        && ffi::lua_getinfo(state, cstr!("Slnf"), ar) != 0;
+    #[cfg(feature = "luau")]
+    return ffi::lua_getinfo(state, level, cstr!("slnf"), ar) != 0;
+}
+
+// Writes the name of the function described by `ar` and pops the function
+unsafe fn write_function_name(
+    state: *mut ffi::lua_State,
+    ar: &mut ffi::lua_Debug,
+    output: &mut StdString,
+) {
+    let _ = if describe_named_callback(state, ar) {
+        let name = ptr_to_lossy_str(ar.name).unwrap_or_default();
+        write!(output, "function '{name}'")
+    } else if let Some(name) = global_function_name(state) {
+        write!(output, "function '{name}'")
+    } else if let Some(name) = ptr_to_lossy_str(ar.name) {
+        #[cfg(not(feature = "luau"))]
+        let name_what = ptr_to_str(ar.namewhat)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("function");
+        #[cfg(feature = "luau")]
+        let name_what = "function";
+        write!(output, "{name_what} '{name}'")
+    } else {
+        match ptr_to_str(ar.what) {
+            Some("main") => write!(output, "main chunk"),
+            Some("C") | None => write!(output, "?"),
+            Some(_) => {
+                #[cfg(not(feature = "luau"))]
+                let short_src = ptr_to_lossy_str(ar.short_src.as_ptr());
+                #[cfg(feature = "luau")]
+                let short_src = ptr_to_lossy_str(ar.short_src);
+                let short_src = short_src.unwrap_or_default();
+                write!(output, "function <{short_src}:{}>", ar.linedefined)
+            }
+        }
+    };
+    ffi::lua_pop(state, 1);
+}
+
+// Searches loaded modules (globals in Luau) for the function on top of the stack, returning its
+// qualified name (without the `_G.` prefix for globals)
+unsafe fn global_function_name(state: *mut ffi::lua_State) -> Option<StdString> {
+    #[cfg(not(feature = "luau"))]
+    ffi::lua_getfield(state, ffi::LUA_REGISTRYINDEX, cstr!("_LOADED"));
+    #[cfg(feature = "luau")]
+    ffi::lua_pushvalue(state, ffi::LUA_GLOBALSINDEX);
+    let mut name = None;
+    if ffi::lua_type(state, -1) == ffi::LUA_TTABLE {
+        ffi::lua_pushnil(state);
+        while name.is_none() && ffi::lua_next(state, -2) != 0 {
+            if ffi::lua_type(state, -2) == ffi::LUA_TSTRING {
+                if ffi::lua_rawequal(state, -1, -4) != 0 {
+                    name = ptr_to_lossy_str(ffi::lua_tostring(state, -2)).map(Cow::into_owned);
+                } else if let Some(field) = field_name(state) {
+                    let module = ptr_to_lossy_str(ffi::lua_tostring(state, -2));
+                    name = match module.as_deref() {
+                        Some("_G") => Some(field),
+                        module => Some(format!("{}.{field}", module.unwrap_or_default())),
+                    };
+                }
+            }
+            ffi::lua_pop(state, 1);
+        }
+        if name.is_some() {
+            // Pop the key left by the interrupted traversal
+            ffi::lua_pop(state, 1);
+        }
+    }
+    ffi::lua_pop(state, 1);
+    name
+}
+
+// Searches the table on top of the stack for the function at index -4 (below the searched table
+// and its traversal key), returning the field name
+unsafe fn field_name(state: *mut ffi::lua_State) -> Option<StdString> {
+    if ffi::lua_type(state, -1) != ffi::LUA_TTABLE {
+        return None;
+    }
+    ffi::lua_pushnil(state);
+    while ffi::lua_next(state, -2) != 0 {
+        if ffi::lua_type(state, -2) == ffi::LUA_TSTRING && ffi::lua_rawequal(state, -1, -6) != 0 {
+            let name = ptr_to_lossy_str(ffi::lua_tostring(state, -2)).map(Cow::into_owned);
+            ffi::lua_pop(state, 2);
+            return name;
+        }
+        ffi::lua_pop(state, 1);
+    }
+    None
+}
+
+// Returns the index of the last frame on the stack of `state`
+unsafe fn last_level(state: *mut ffi::lua_State) -> c_int {
+    #[cfg(not(feature = "luau"))]
+    {
+        let mut ar: ffi::lua_Debug = mem::zeroed();
+        let (mut li, mut le) = (1, 1);
+        while ffi::lua_getstack(state, le, &mut ar) != 0 {
+            li = le;
+            le *= 2;
+        }
+        while li < le {
+            let m = (li + le) / 2;
+            match ffi::lua_getstack(state, m, &mut ar) != 0 {
+                true => li = m + 1,
+                false => le = m,
+            }
+        }
+        le - 1
+    }
+    #[cfg(feature = "luau")]
+    {
+        ffi::lua_stackdepth(state) - 1
+    }
+}
@@ -5,19 +5,21 @@ use std::iter;
 use std::ops::Index;
 use std::os::raw::{c_int, c_void};
 use std::string::String as StdString;
-use std::sync::Arc;
 use std::{fmt, mem, ptr, slice, str, vec};
 
-use num_traits::FromPrimitive;
+use num_traits::{AsPrimitive, FromPrimitive};
 
 #[cfg(feature = "serialize")]
 use {
+    crate::serde::refs::{serialize_table, SharedTables},
+    crate::serde::serialize_tagged_userdata,
     crate::table::SerializableTable,
     rustc_hash::FxHashSet,
     serde::ser::{self, Serialize, Serializer},
     std::{cell::RefCell, rc::Rc, result::Result as StdResult},
 };
 
+use crate::display::ValueDisplay;
 use crate::error::{Error, Result};
 use crate::function::Function;
 use crate::lua::Lua;
@@ -28,6 +30,9 @@ use crate::types::{Integer, LightUserData, Number, SubtypeId};
 use crate::userdata::AnyUserData;
 use crate::util::{check_stack, StackGuard};
 
+// Maximum number of characters in value previews
+const MAX_PREVIEW_LEN: usize = 40;
+
 /// A dynamically typed Lua value. The `String`, `Table`, `Function`, `Thread`, and `UserData`
 /// variants contain handle types into the internal Lua state. It is a logic error to mix handle
 /// types between separate `Lua` instances, and doing so will result in a panic.
@@ -115,6 +120,78 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Performs an arithmetic (or bitwise) operation `op` between this value and `other`,
+    /// following the Lua semantics, including string coercion and metamethods (`__add`, etc.).
+    ///
+    /// For unary operations ([`ArithOp::Unm`], `ArithOp::BNot`) `other` is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ArithOp, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let a = Value::Integer(2);
+    /// let b: Value = lua.load(r#"setmetatable({}, {__add = function(a, b) return a * 10 end})"#).eval()?;
+    /// assert_eq!(a.arith(&lua, ArithOp::Add, &b)?, Value::Integer(20));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn arith<T: AsRef<Self>>(
+        &self,
+        lua: &'lua Lua,
+        op: ArithOp,
+        other: T,
+    ) -> Result<Value<'lua>> {
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        unsafe {
+            let state = lua.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            lua.push_value_ref(self)?;
+            let nargs = match op.is_unary() {
+                true => 1,
+                false => {
+                    lua.push_value_ref(other.as_ref())?;
+                    2
+                }
+            };
+            let op = op.as_ffi();
+            protect_lua!(state, nargs, 1, |state| ffi::lua_arith(state, op))?;
+            Ok(lua.pop_value())
+        }
+
+        #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+        metamethods_fallback(lua, op.name(), self, other.as_ref())
+    }
+
+    /// Compares this value with `other` using the comparison `op`, following the Lua semantics
+    /// and invoking metamethods (`__eq`, `__lt` or `__le`) if needed.
+    ///
+    /// Ordering comparisons between values of incompatible types (without metamethods)
+    /// return an error.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
+    pub fn compare<T: AsRef<Self>>(&self, lua: &'lua Lua, op: CompareOp, other: T) -> Result<bool> {
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        unsafe {
+            let state = lua.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            lua.push_value_ref(self)?;
+            lua.push_value_ref(other.as_ref())?;
+            let op = op.as_ffi();
+            let res = protect_lua!(state, 2, 0, |state| ffi::lua_compare(state, -2, -1, op))?;
+            Ok(res != 0)
+        }
+
+        #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+        metamethods_fallback(lua, op.name(), self, other.as_ref())
+            .map(|res| res.as_boolean() == Some(true))
+    }
+
     /// Converts the value to a generic C pointer.
     ///
     /// The value can be a userdata, a table, a thread, a string, or a function; otherwise it returns NULL.
@@ -135,9 +212,37 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Returns a hashable identity of the value.
+    ///
+    /// Tables, functions, threads and userdata are identified by their pointer, while other
+    /// values (including strings) are identified by their contents. Numbers with integer values
+    /// have the same identity as integers, following Lua table key rules.
+    ///
+    /// The identity of a reference value is only stable while the object is alive (not collected).
+    pub fn identity(&self) -> ValueId {
+        ValueId(match self {
+            Value::Nil => ValueIdInner::Nil,
+            Value::Boolean(b) => ValueIdInner::Boolean(*b),
+            Value::LightUserData(ud) => ValueIdInner::LightUserData(ud.0 as usize),
+            Value::Integer(i) => ValueIdInner::Integer(*i),
+            Value::Number(n) => match num_traits::cast::<_, Integer>(*n) {
+                Some(i) if i as Number == *n => ValueIdInner::Integer(i),
+                _ => ValueIdInner::Number(n.to_bits()),
+            },
+            #[cfg(feature = "luau")]
+            Value::Vector(v) => ValueIdInner::Vector(v.0.map(f32::to_bits)),
+            Value::String(s) => ValueIdInner::String(s.as_bytes().into()),
+            Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_) => {
+                ValueIdInner::Ref(self.to_pointer() as usize)
+            }
+            Value::Error(err) => ValueIdInner::Error(err.to_string()),
+        })
+    }
+
     /// Converts the value to a string.
     ///
     /// If the value has a metatable with a `__tostring` method, then it will be called to get the result.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     pub fn to_string(&self) -> Result<StdString> {
         match self {
             Value::Nil => Ok("nil".to_string()),
@@ -167,6 +272,14 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Returns a formatter of the value, producing the same text as [`Value::to_string`] but
+    /// able to expand nested tables.
+    ///
+    /// See [`ValueDisplay`] for the available options.
+    pub fn display(&self) -> ValueDisplay<'lua> {
+        ValueDisplay::new(self.clone())
+    }
+
     /// Returns `true` if the value is a [`Nil`].
     #[inline]
     pub fn is_nil(&self) -> bool {
@@ -252,7 +365,7 @@ impl<'lua> Value<'lua> {
     /// If the value is a Lua [`Integer`], try to convert it to `i64` or return `None` otherwise.
     #[inline]
     pub fn as_i64(&self) -> Option<i64> {
-        self.as_integer().map(i64::from)
+        self.as_integer().map(|i| i.as_())
     }
 
     /// Cast the value to `u64`.
@@ -442,7 +555,7 @@ impl<'lua> Value<'lua> {
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
     #[doc(hidden)]
     pub fn to_serializable(&self) -> SerializableValue<'_, 'lua> {
-        SerializableValue::new(self, Default::default(), None)
+        SerializableValue::new(self, Default::default(), None, None)
     }
 
     // Compares two values.
@@ -485,6 +598,19 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    // Returns a short single-line representation of the value (used in error messages)
+    pub(crate) fn preview(&self) -> StdString {
+        struct Preview<'a, 'lua>(&'a Value<'lua>);
+
+        impl fmt::Display for Preview<'_, '_> {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                self.0.fmt_pretty(fmt, false, 0, &mut HashSet::new())
+            }
+        }
+
+        truncate_preview(Preview(self).to_string(), false)
+    }
+
     pub(crate) fn fmt_pretty(
         &self,
         fmt: &mut fmt::Formatter,
@@ -523,6 +649,100 @@ impl<'lua> Value<'lua> {
     }
 }
 
+// Truncates a value preview to `MAX_PREVIEW_LEN` characters
+fn truncate_preview(mut preview: StdString, truncated: bool) -> StdString {
+    if let Some((i, _)) = preview.char_indices().nth(MAX_PREVIEW_LEN) {
+        preview.truncate(i);
+        preview.push_str("...");
+    } else if truncated {
+        preview.push_str("...");
+    }
+    preview
+}
+
+// Number of string bytes kept by `ValueSnapshot`, enough for a truncated preview
+const SNAPSHOT_STRING_LEN: usize = MAX_PREVIEW_LEN * 4;
+
+// What a value looks like, taken before a conversion consumes the value.
+// It does not reference the value (or allocate), so it's cheap to make for every argument.
+pub(crate) enum ValueSnapshot {
+    Primitive(Value<'static>),
+    String {
+        bytes: [u8; SNAPSHOT_STRING_LEN],
+        len: usize,
+        truncated: bool,
+    },
+    Other(&'static str, *const c_void),
+}
+
+impl ValueSnapshot {
+    pub(crate) fn new(value: &Value) -> Self {
+        match *value {
+            Value::Nil => ValueSnapshot::Primitive(Value::Nil),
+            Value::Boolean(b) => ValueSnapshot::Primitive(Value::Boolean(b)),
+            Value::LightUserData(ud) => ValueSnapshot::Primitive(Value::LightUserData(ud)),
+            Value::Integer(i) => ValueSnapshot::Primitive(Value::Integer(i)),
+            Value::Number(n) => ValueSnapshot::Primitive(Value::Number(n)),
+            #[cfg(feature = "luau")]
+            Value::Vector(v) => ValueSnapshot::Primitive(Value::Vector(v)),
+            Value::String(ref s) => {
+                let s = s.as_bytes();
+                let len = s.len().min(SNAPSHOT_STRING_LEN);
+                let mut bytes = [0; SNAPSHOT_STRING_LEN];
+                bytes[..len].copy_from_slice(&s[..len]);
+                let truncated = len < s.len();
+                ValueSnapshot::String {
+                    bytes,
+                    len,
+                    truncated,
+                }
+            }
+            Value::Error(_) => ValueSnapshot::Other("error", ptr::null()),
+            ref value => ValueSnapshot::Other(value.type_name(), value.to_pointer()),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            ValueSnapshot::Primitive(value) => value.type_name(),
+            ValueSnapshot::String { .. } => "string",
+            ValueSnapshot::Other(type_name, _) => type_name,
+        }
+    }
+
+    fn preview(&self) -> StdString {
+        match *self {
+            ValueSnapshot::Primitive(ref value) => value.preview(),
+            ValueSnapshot::String {
+                ref bytes,
+                len,
+                truncated,
+            } => {
+                let bytes = &bytes[..len];
+                let preview = match str::from_utf8(bytes) {
+                    Ok(s) => format!("{s:?}"),
+                    // The last character may be cut off
+                    Err(err) if truncated && err.error_len().is_none() => {
+                        format!(
+                            "{:?}",
+                            StdString::from_utf8_lossy(&bytes[..err.valid_up_to()])
+                        )
+                    }
+                    Err(_) => format!("b\"{}\"", bytes.escape_ascii()),
+                };
+                truncate_preview(preview, truncated)
+            }
+            ValueSnapshot::Other("error", _) => "error".to_string(),
+            ValueSnapshot::Other(type_name, ptr) => format!("{type_name}: {ptr:?}"),
+        }
+    }
+
+    // Creates a bad argument error describing the value that failed to convert to `T`
+    pub(crate) fn bad_argument<T>(&self, to: Option<&str>, pos: usize, cause: Error) -> Error {
+        Error::bad_argument::<T>(to, pos, self.type_name(), self.preview(), cause)
+    }
+}
+
 impl fmt::Debug for Value<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         if fmt.alternate() {
@@ -575,6 +795,388 @@ impl<'lua> AsRef<Value<'lua>> for Value<'lua> {
     }
 }
 
+/// Arithmetic and bitwise operations supported by [`Value::arith`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ArithOp {
+    /// Addition (`+`).
+    Add,
+    /// Subtraction (`-`).
+    Sub,
+    /// Multiplication (`*`).
+    Mul,
+    /// Float division (`/`).
+    Div,
+    /// Modulo (`%`).
+    Mod,
+    /// Exponentiation (`^`).
+    Pow,
+    /// Floor division (`//`).
+    ///
+    /// Requires `feature = "lua54/lua53/luau"`
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "lua54", feature = "lua53", feature = "luau")))
+    )]
+    IDiv,
+    /// Unary minus (`-`).
+    Unm,
+    /// Bitwise AND (`&`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    BAnd,
+    /// Bitwise OR (`|`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    BOr,
+    /// Bitwise exclusive OR (`~`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    BXor,
+    /// Left shift (`<<`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    Shl,
+    /// Right shift (`>>`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    Shr,
+    /// Bitwise NOT (unary `~`).
+    ///
+    /// Requires `feature = "lua54/lua53"`
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "lua54", feature = "lua53"))))]
+    BNot,
+}
+
+impl ArithOp {
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    fn is_unary(self) -> bool {
+        match self {
+            ArithOp::Unm => true,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::BNot => true,
+            _ => false,
+        }
+    }
+
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    fn as_ffi(self) -> c_int {
+        match self {
+            ArithOp::Add => ffi::LUA_OPADD,
+            ArithOp::Sub => ffi::LUA_OPSUB,
+            ArithOp::Mul => ffi::LUA_OPMUL,
+            ArithOp::Div => ffi::LUA_OPDIV,
+            ArithOp::Mod => ffi::LUA_OPMOD,
+            ArithOp::Pow => ffi::LUA_OPPOW,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::IDiv => ffi::LUA_OPIDIV,
+            ArithOp::Unm => ffi::LUA_OPUNM,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::BAnd => ffi::LUA_OPBAND,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::BOr => ffi::LUA_OPBOR,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::BXor => ffi::LUA_OPBXOR,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::Shl => ffi::LUA_OPSHL,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::Shr => ffi::LUA_OPSHR,
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ArithOp::BNot => ffi::LUA_OPBNOT,
+        }
+    }
+
+    #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+    fn name(self) -> &'static str {
+        match self {
+            ArithOp::Add => "add",
+            ArithOp::Sub => "sub",
+            ArithOp::Mul => "mul",
+            ArithOp::Div => "div",
+            ArithOp::Mod => "mod",
+            ArithOp::Pow => "pow",
+            #[cfg(feature = "luau")]
+            ArithOp::IDiv => "idiv",
+            ArithOp::Unm => "unm",
+        }
+    }
+}
+
+/// Comparison operations supported by [`Value::compare`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CompareOp {
+    /// Equality (`==`).
+    Eq,
+    /// Less than (`<`).
+    Lt,
+    /// Less than or equal (`<=`).
+    Le,
+}
+
+impl CompareOp {
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    fn as_ffi(self) -> c_int {
+        match self {
+            CompareOp::Eq => ffi::LUA_OPEQ,
+            CompareOp::Lt => ffi::LUA_OPLT,
+            CompareOp::Le => ffi::LUA_OPLE,
+        }
+    }
+
+    #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+    fn name(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "eq",
+            CompareOp::Lt => "lt",
+            CompareOp::Le => "le",
+        }
+    }
+}
+
+// Lua versions without `lua_arith`/`lua_compare` evaluate the operators in Lua code
+#[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+fn metamethods_fallback<'lua>(
+    lua: &'lua Lua,
+    op: &str,
+    a: &Value<'lua>,
+    b: &Value<'lua>,
+) -> Result<Value<'lua>> {
+    #[cfg(not(feature = "luau"))]
+    const IDIV: &str = "";
+    #[cfg(feature = "luau")]
+    const IDIV: &str = r#"if op == "idiv" then return a // b end"#;
+
+    lua.load(format!(
+        r#"
+        local op, a, b = ...
+        if op == "add" then return a + b end
+        if op == "sub" then return a - b end
+        if op == "mul" then return a * b end
+        if op == "div" then return a / b end
+        if op == "mod" then return a % b end
+        if op == "pow" then return a ^ b end
+        if op == "unm" then return -a end
+        if op == "eq" then return a == b end
+        if op == "lt" then return a < b end
+        if op == "le" then return a <= b end
+        {IDIV}
+        "#
+    ))
+    .try_cache()
+    .set_name("__mlua_arith")
+    .call((op, a.clone(), b.clone()))
+}
+
+/// A Lua value borrowed from the Lua stack, received by [`Table::visit`].
+///
+/// Unlike [`Value`], it does not hold references to Lua objects, so no handles are created while
+/// scanning. Strings are exposed as byte slices and other objects (tables, functions, threads
+/// and userdata) only by their pointers. A borrowed value is valid only within the callback
+/// it was passed to.
+///
+/// [`Table::visit`]: crate::Table::visit
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum BorrowedValue<'a> {
+    /// The Lua value `nil`.
+    Nil,
+    /// The Lua value `true` or `false`.
+    Boolean(bool),
+    /// A "light userdata" object, equivalent to a raw pointer.
+    LightUserData(LightUserData),
+    /// An integer number.
+    Integer(Integer),
+    /// A floating point number.
+    Number(Number),
+    /// A Luau vector.
+    #[cfg(any(feature = "luau", doc))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    Vector(crate::types::Vector),
+    /// Contents of a Lua string.
+    String(&'a [u8]),
+    /// Pointer to a Lua table.
+    Table(*const c_void),
+    /// Pointer to a Lua function.
+    Function(*const c_void),
+    /// Pointer to a Lua thread.
+    Thread(*const c_void),
+    /// Pointer to a userdata object.
+    UserData(*const c_void),
+}
+
+impl<'a> BorrowedValue<'a> {
+    // Reads the value at `idx` without copying it to the reference thread
+    pub(crate) unsafe fn from_stack(state: *mut ffi::lua_State, idx: c_int) -> Self {
+        match ffi::lua_type(state, idx) {
+            ffi::LUA_TBOOLEAN => BorrowedValue::Boolean(ffi::lua_toboolean(state, idx) != 0),
+            ffi::LUA_TLIGHTUSERDATA => {
+                BorrowedValue::LightUserData(LightUserData(ffi::lua_touserdata(state, idx)))
+            }
+            #[cfg(any(feature = "lua54", feature = "lua53"))]
+            ffi::LUA_TNUMBER => {
+                if ffi::lua_isinteger(state, idx) != 0 {
+                    BorrowedValue::Integer(ffi::lua_tointeger(state, idx))
+                } else {
+                    BorrowedValue::Number(ffi::lua_tonumber(state, idx))
+                }
+            }
+            #[cfg(any(
+                feature = "lua52",
+                feature = "lua51",
+                feature = "luajit",
+                feature = "luau"
+            ))]
+            ffi::LUA_TNUMBER => {
+                let n = ffi::lua_tonumber(state, idx);
+                match num_traits::cast(n) {
+                    Some(i) if (n - (i as Number)).abs() < Number::EPSILON => {
+                        BorrowedValue::Integer(i)
+                    }
+                    _ => BorrowedValue::Number(n),
+                }
+            }
+            #[cfg(feature = "luau")]
+            ffi::LUA_TVECTOR => {
+                let v = ffi::lua_tovector(state, idx);
+                #[cfg(not(feature = "luau-vector4"))]
+                return BorrowedValue::Vector(crate::types::Vector([*v, *v.add(1), *v.add(2)]));
+                #[cfg(feature = "luau-vector4")]
+                return BorrowedValue::Vector(crate::types::Vector([
+                    *v,
+                    *v.add(1),
+                    *v.add(2),
+                    *v.add(3),
+                ]));
+            }
+            ffi::LUA_TSTRING => {
+                let mut size = 0;
+                let data = ffi::lua_tolstring(state, idx, &mut size);
+                BorrowedValue::String(slice::from_raw_parts(data as *const u8, size))
+            }
+            ffi::LUA_TTABLE => BorrowedValue::Table(ffi::lua_topointer(state, idx)),
+            ffi::LUA_TFUNCTION => BorrowedValue::Function(ffi::lua_topointer(state, idx)),
+            ffi::LUA_TTHREAD => BorrowedValue::Thread(ffi::lua_topointer(state, idx)),
+            ffi::LUA_TUSERDATA => BorrowedValue::UserData(ffi::lua_topointer(state, idx)),
+            #[cfg(feature = "luau")]
+            ffi::LUA_TBUFFER => BorrowedValue::UserData(ffi::lua_topointer(state, idx)),
+            #[cfg(feature = "luajit")]
+            ffi::LUA_TCDATA => BorrowedValue::UserData(ffi::lua_topointer(state, idx)),
+            _ => BorrowedValue::Nil,
+        }
+    }
+
+    /// Returns type name of this value.
+    pub const fn type_name(&self) -> &'static str {
+        match *self {
+            BorrowedValue::Nil => "nil",
+            BorrowedValue::Boolean(_) => "boolean",
+            BorrowedValue::LightUserData(_) => "lightuserdata",
+            BorrowedValue::Integer(_) => "integer",
+            BorrowedValue::Number(_) => "number",
+            #[cfg(feature = "luau")]
+            BorrowedValue::Vector(_) => "vector",
+            BorrowedValue::String(_) => "string",
+            BorrowedValue::Table(_) => "table",
+            BorrowedValue::Function(_) => "function",
+            BorrowedValue::Thread(_) => "thread",
+            BorrowedValue::UserData(_) => "userdata",
+        }
+    }
+
+    /// Returns `true` if the value is `nil`.
+    #[inline]
+    pub fn is_nil(&self) -> bool {
+        matches!(self, BorrowedValue::Nil)
+    }
+
+    /// Cast the value to boolean.
+    ///
+    /// If the value is a Boolean, returns it or `None` otherwise.
+    #[inline]
+    pub fn as_boolean(&self) -> Option<bool> {
+        match *self {
+            BorrowedValue::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Cast the value to `Integer`.
+    ///
+    /// If the value is a Lua [`Integer`], returns it or `None` otherwise.
+    #[inline]
+    pub fn as_integer(&self) -> Option<Integer> {
+        match *self {
+            BorrowedValue::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Cast the value to `Number`.
+    ///
+    /// If the value is a Lua [`Number`] or [`Integer`], returns it as `Number`, or `None`
+    /// otherwise.
+    #[inline]
+    pub fn as_number(&self) -> Option<Number> {
+        match *self {
+            BorrowedValue::Integer(i) => Some(i as Number),
+            BorrowedValue::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Cast the value to a byte slice.
+    ///
+    /// If the value is a Lua string, returns its contents or `None` otherwise.
+    #[inline]
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            BorrowedValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Cast the value to `str`.
+    ///
+    /// If the value is a Lua string with valid UTF-8 contents, returns it or `None` otherwise.
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|s| str::from_utf8(s).ok())
+    }
+}
+
+/// Hashable identity of a [`Value`], returned by [`Value::identity`].
+///
+/// It does not hold a reference to the Lua object, so it can be used as a key in Rust
+/// collections (e.g. caches and visited-sets) without going through the registry.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ValueId(ValueIdInner);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ValueIdInner {
+    Nil,
+    Boolean(bool),
+    LightUserData(usize),
+    Integer(Integer),
+    Number(u64),
+    #[cfg(feature = "luau")]
+    Vector([u32; crate::types::Vector::SIZE]),
+    String(Box<[u8]>),
+    Ref(usize),
+    Error(StdString),
+}
+
 /// A wrapped [`Value`] with customized serialization behavior.
 #[cfg(feature = "serialize")]
 #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
@@ -583,13 +1185,15 @@ pub struct SerializableValue<'a, 'lua> {
     options: crate::serde::de::Options,
     // In many cases we don't need `visited` map, so don't allocate memory by default
     visited: Option<Rc<RefCell<FxHashSet<*const c_void>>>>,
+    // Shared tables, if references are preserved
+    shared: Option<Rc<RefCell<SharedTables>>>,
 }
 
 #[cfg(feature = "serialize")]
 impl<'lua> Serialize for Value<'lua> {
     #[inline]
     fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
-        SerializableValue::new(self, Default::default(), None).serialize(serializer)
+        SerializableValue::new(self, Default::default(), None, None).serialize(serializer)
     }
 }
 
@@ -600,6 +1204,7 @@ impl<'a, 'lua> SerializableValue<'a, 'lua> {
         value: &'a Value<'lua>,
         options: crate::serde::de::Options,
         visited: Option<&Rc<RefCell<FxHashSet<*const c_void>>>>,
+        shared: Option<&Rc<RefCell<SharedTables>>>,
     ) -> Self {
         if let Value::Table(_) = value {
             return Self {
@@ -607,12 +1212,14 @@ impl<'a, 'lua> SerializableValue<'a, 'lua> {
                 options,
                 // We need to always initialize the `visited` map for Tables
                 visited: visited.cloned().or_else(|| Some(Default::default())),
+                shared: shared.cloned(),
             };
         }
         Self {
             value,
             options,
             visited: None,
+            shared: shared.cloned(),
         }
     }
 
@@ -646,6 +1253,31 @@ impl<'a, 'lua> SerializableValue<'a, 'lua> {
         self.options.sort_keys = enabled;
         self
     }
+
+    /// If true, tables referenced more than once (including recursive tables) will be serialized
+    /// only once, as `{"$id": n, "$value": table}`, and other references to them as `{"$ref": n}`.
+    /// Keys starting with `$` are escaped by doubling it.
+    ///
+    /// Default: **false**
+    #[must_use]
+    pub const fn preserve_references(mut self, enabled: bool) -> Self {
+        self.options.preserve_references = enabled;
+        self
+    }
+
+    /// If true, serializable userdata will be serialized as
+    /// `{"$userdata": type_name, "$value": value}`.
+    ///
+    /// See [`tag_userdata`] deserializer option for details.
+    ///
+    /// Default: **false**
+    ///
+    /// [`tag_userdata`]: crate::serde::de::Options::tag_userdata
+    #[must_use]
+    pub const fn tag_userdata(mut self, enabled: bool) -> Self {
+        self.options.tag_userdata = enabled;
+        self
+    }
 }
 
 #[cfg(feature = "serialize")]
@@ -654,23 +1286,36 @@ impl<'a, 'lua> Serialize for SerializableValue<'a, 'lua> {
     where
         S: Serializer,
     {
+        if self.options.preserve_references && self.shared.is_none() {
+            let shared = SharedTables::new(self.value).map_err(ser::Error::custom)?;
+            let shared = Rc::new(RefCell::new(shared));
+            let visited = self.visited.as_ref();
+            return SerializableValue::new(self.value, self.options, visited, Some(&shared))
+                .serialize(serializer);
+        }
+
         match self.value {
             Value::Nil => serializer.serialize_unit(),
             Value::Boolean(b) => serializer.serialize_bool(*b),
-            #[allow(clippy::useless_conversion)]
-            Value::Integer(i) => serializer.serialize_i64((*i).into()),
+            Value::Integer(i) => serializer.serialize_i64(i.as_()),
             Value::Number(n) => serializer.serialize_f64(*n),
             #[cfg(feature = "luau")]
             Value::Vector(v) => v.serialize(serializer),
             Value::String(s) => s.serialize(serializer),
             Value::Table(t) => {
                 let visited = self.visited.as_ref().unwrap().clone();
-                SerializableTable::new(t, self.options, visited).serialize(serializer)
+                let table = SerializableTable::new(t, self.options, visited, self.shared.clone());
+                match &self.shared {
+                    Some(shared) => serialize_table(t, &table, shared, serializer),
+                    None => table.serialize(serializer),
+                }
             }
             Value::LightUserData(ud) if ud.0.is_null() => serializer.serialize_none(),
-            Value::UserData(ud) if ud.is_serializable() || self.options.deny_unsupported_types => {
-                ud.serialize(serializer)
-            }
+            Value::UserData(ud) if ud.is_serializable() => match self.options.tag_userdata {
+                true => serialize_tagged_userdata(ud, serializer),
+                false => ud.serialize(serializer),
+            },
+            Value::UserData(ud) if self.options.deny_unsupported_types => ud.serialize(serializer),
             Value::Function(_)
             | Value::Thread(_)
             | Value::UserData(_)
@@ -690,6 +1335,7 @@ impl<'a, 'lua> Serialize for SerializableValue<'a, 'lua> {
 /// Trait for types convertible to `Value`.
 pub trait IntoLua<'lua>: Sized {
     /// Performs the conversion.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>>;
 
     /// Pushes the value into the Lua stack.
@@ -698,6 +1344,7 @@ pub trait IntoLua<'lua>: Sized {
     /// This method does not check Lua stack space.
     #[doc(hidden)]
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack(self, lua: &'lua Lua) -> Result<()> {
         lua.push_value(self.into_lua(lua)?)
     }
@@ -715,12 +1362,9 @@ pub trait FromLua<'lua>: Sized {
     #[doc(hidden)]
     #[inline]
     fn from_lua_arg(arg: Value<'lua>, i: usize, to: Option<&str>, lua: &'lua Lua) -> Result<Self> {
-        Self::from_lua(arg, lua).map_err(|err| Error::BadArgument {
-            to: to.map(|s| s.to_string()),
-            pos: i,
-            name: None,
-            cause: Arc::new(err),
-        })
+        // Remember what the value looks like to describe it if the conversion fails
+        let snapshot = ValueSnapshot::new(&arg);
+        Self::from_lua(arg, lua).map_err(|err| snapshot.bad_argument::<Self>(to, i, err))
     }
 
     /// Performs the conversion for a value in the Lua stack at index `idx`.
@@ -739,11 +1383,9 @@ pub trait FromLua<'lua>: Sized {
         to: Option<&str>,
         lua: &'lua Lua,
     ) -> Result<Self> {
-        Self::from_stack(idx, lua).map_err(|err| Error::BadArgument {
-            to: to.map(|s| s.to_string()),
-            pos: i,
-            name: None,
-            cause: Arc::new(err),
+        Self::from_stack(idx, lua).map_err(|err| {
+            let value = lua.stack_value(idx);
+            Error::bad_argument::<Self>(to, i, value.type_name(), value.preview(), err)
         })
     }
 }
@@ -921,6 +1563,7 @@ impl<'lua> MultiValue<'lua> {
 /// one. Any type that implements `IntoLua` will automatically implement this trait.
 pub trait IntoLuaMulti<'lua>: Sized {
     /// Performs the conversion.
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     fn into_lua_multi(self, lua: &'lua Lua) -> Result<MultiValue<'lua>>;
 
     /// Pushes the values into the Lua stack.
@@ -928,6 +1571,7 @@ pub trait IntoLuaMulti<'lua>: Sized {
     /// Returns number of pushed values.
     #[doc(hidden)]
     #[inline]
+    #[cfg_attr(feature = "stack_diagnostics", track_caller)]
     unsafe fn push_into_stack_multi(self, lua: &'lua Lua) -> Result<c_int> {
         let mut values = self.into_lua_multi(lua)?;
         let len: c_int = values.len().try_into().unwrap();
@@ -946,6 +1590,10 @@ pub trait IntoLuaMulti<'lua>: Sized {
 /// This is a generalization of `FromLua`, allowing an arbitrary number of Lua values to participate
 /// in the conversion. Any type that implements `FromLua` will automatically implement this trait.
 pub trait FromLuaMulti<'lua>: Sized {
+    /// Number of values taken by the conversion, or `None` if it's not fixed.
+    #[doc(hidden)]
+    const NARGS: Option<usize> = None;
+
     /// Performs the conversion.
     ///
     /// In case `values` contains more values than needed to perform the conversion, the excess
@@ -0,0 +1,173 @@
+use std::fmt;
+#[cfg(feature = "module")]
+use std::os::raw::c_int;
+
+/// Lua interpreter version (or flavour).
+///
+/// The version mlua was compiled for is available as [`LuaVersion::COMPILED`].
+/// In module mode, the interpreter that actually loaded the module can be inspected at runtime
+/// using [`LuaVersion::detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LuaVersion {
+    /// Lua 5.1
+    Lua51,
+    /// LuaJIT (Lua 5.1 ABI)
+    LuaJIT,
+    /// Lua 5.2
+    Lua52,
+    /// Lua 5.3
+    Lua53,
+    /// Lua 5.4
+    Lua54,
+    /// Roblox Luau
+    Luau,
+}
+
+impl LuaVersion {
+    /// The Lua version mlua was compiled for.
+    #[cfg(feature = "lua54")]
+    pub const COMPILED: LuaVersion = LuaVersion::Lua54;
+    /// The Lua version mlua was compiled for.
+    #[cfg(feature = "lua53")]
+    pub const COMPILED: LuaVersion = LuaVersion::Lua53;
+    /// The Lua version mlua was compiled for.
+    #[cfg(feature = "lua52")]
+    pub const COMPILED: LuaVersion = LuaVersion::Lua52;
+    /// The Lua version mlua was compiled for.
+    #[cfg(all(feature = "lua51", not(feature = "luajit")))]
+    pub const COMPILED: LuaVersion = LuaVersion::Lua51;
+    /// The Lua version mlua was compiled for.
+    #[cfg(feature = "luajit")]
+    pub const COMPILED: LuaVersion = LuaVersion::LuaJIT;
+    /// The Lua version mlua was compiled for.
+    #[cfg(feature = "luau")]
+    pub const COMPILED: LuaVersion = LuaVersion::Luau;
+
+    /// Returns the version string as reported by the `_VERSION` global.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            LuaVersion::Lua51 => "Lua 5.1",
+            LuaVersion::LuaJIT => "LuaJIT",
+            LuaVersion::Lua52 => "Lua 5.2",
+            LuaVersion::Lua53 => "Lua 5.3",
+            LuaVersion::Lua54 => "Lua 5.4",
+            LuaVersion::Luau => "Luau",
+        }
+    }
+
+    /// Returns `true` if the C ABI of `self` is compatible with the `other` version.
+    ///
+    /// LuaJIT is ABI-compatible with Lua 5.1 (but not vice versa as LuaJIT provides extra API).
+    pub const fn is_compatible_with(self, other: LuaVersion) -> bool {
+        matches!(
+            (self, other),
+            (LuaVersion::Lua51, LuaVersion::Lua51)
+                | (LuaVersion::LuaJIT, LuaVersion::LuaJIT | LuaVersion::Lua51)
+                | (LuaVersion::Lua52, LuaVersion::Lua52)
+                | (LuaVersion::Lua53, LuaVersion::Lua53)
+                | (LuaVersion::Lua54, LuaVersion::Lua54)
+                | (LuaVersion::Luau, LuaVersion::Luau)
+        )
+    }
+
+    /// Detects the Lua interpreter the current process is running, by inspecting the symbols
+    /// exported by the host.
+    ///
+    /// This does not call any Lua C API function, so it's safe to use even if the module was
+    /// compiled for a different (ABI incompatible) Lua version.
+    ///
+    /// Returns `None` if the interpreter cannot be detected (e.g. Lua symbols are not exported
+    /// globally or the platform is not supported).
+    ///
+    /// Requires `feature = "module"`
+    #[cfg(feature = "module")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "module")))]
+    pub fn detect() -> Option<LuaVersion> {
+        symbols::detect()
+    }
+}
+
+impl fmt::Display for LuaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Checks that the module is loaded by the Lua interpreter it was compiled for.
+///
+/// On mismatch, raises a Lua error with a descriptive message using only API functions
+/// that have identical ABI in all supported Lua versions.
+#[cfg(feature = "module")]
+#[doc(hidden)]
+pub unsafe fn check_runtime_version(state: *mut ffi::lua_State) -> Option<c_int> {
+    let runtime = LuaVersion::detect()?;
+    if runtime.is_compatible_with(LuaVersion::COMPILED) {
+        return None;
+    }
+    let msg = format!(
+        "module compiled for {} cannot be loaded by {runtime}\0",
+        LuaVersion::COMPILED
+    );
+    ffi::lua_pushlstring(state, msg.as_ptr() as *const _, msg.len() - 1);
+    drop(msg);
+    ffi::lua_error(state)
+}
+
+#[cfg(all(feature = "module", unix))]
+mod symbols {
+    use std::os::raw::{c_char, c_void};
+
+    use super::LuaVersion;
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))]
+    const RTLD_DEFAULT: *mut c_void = -2isize as *mut c_void;
+    #[cfg(not(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    )))]
+    const RTLD_DEFAULT: *mut c_void = std::ptr::null_mut();
+
+    extern "C" {
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    fn has_symbol(name: &str) -> bool {
+        debug_assert!(name.ends_with('\0'));
+        unsafe { !dlsym(RTLD_DEFAULT, name.as_ptr() as *const c_char).is_null() }
+    }
+
+    pub(super) fn detect() -> Option<LuaVersion> {
+        if has_symbol("luaJIT_setmode\0") {
+            Some(LuaVersion::LuaJIT)
+        } else if has_symbol("luau_load\0") {
+            Some(LuaVersion::Luau)
+        } else if has_symbol("lua_newuserdatauv\0") {
+            Some(LuaVersion::Lua54)
+        } else if has_symbol("lua_rotate\0") {
+            Some(LuaVersion::Lua53)
+        } else if has_symbol("lua_version\0") {
+            Some(LuaVersion::Lua52)
+        } else if has_symbol("lua_setfenv\0") {
+            Some(LuaVersion::Lua51)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(all(feature = "module", not(unix)))]
+mod symbols {
+    use super::LuaVersion;
+
+    pub(super) fn detect() -> Option<LuaVersion> {
+        None
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::{FromLua, IntoLua, Nil, Value};
+
+/// Mode of a weak table, controls which references are weak.
+///
+/// More information can be found in the Lua [documentation].
+///
+/// [documentation]: https://www.lua.org/manual/5.4/manual.html#2.5.4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WeakMode {
+    /// Weak keys (in Lua 5.2+ it's an ephemeron table).
+    Keys,
+    /// Weak values.
+    Values,
+    /// Both keys and values are weak.
+    Both,
+}
+
+impl WeakMode {
+    /// Returns the value of the `__mode` metatable field for this mode.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            WeakMode::Keys => "k",
+            WeakMode::Values => "v",
+            WeakMode::Both => "kv",
+        }
+    }
+}
+
+/// A typed cache backed by a Lua weak table.
+///
+/// Entries are removed by the garbage collector once their weak keys (or values) are no longer
+/// referenced elsewhere. It's useful to associate computed values with userdata (or other Lua
+/// objects) without keeping them alive.
+///
+/// Note that keys with finalizers (such as userdata) are removed from the cache only after their
+/// finalizer has run, which takes an extra garbage collection cycle.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, Table, WeakCache, WeakMode};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let cache = WeakCache::<Table, String>::new(&lua, WeakMode::Keys)?;
+///
+/// let key = lua.create_table()?;
+/// cache.insert(key.clone(), "computed".to_string())?;
+/// assert_eq!(cache.get(key.clone())?, Some("computed".to_string()));
+///
+/// drop(key);
+/// lua.gc_collect()?;
+/// assert!(cache.is_empty()?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WeakCache<'lua, K, V> {
+    table: Table<'lua>,
+    _phantom: PhantomData<fn(K) -> V>,
+}
+
+impl<'lua, K, V> WeakCache<'lua, K, V>
+where
+    K: IntoLua<'lua>,
+    V: IntoLua<'lua> + FromLua<'lua>,
+{
+    /// Creates a new empty cache with the given weak mode.
+    pub fn new(lua: &'lua Lua, mode: WeakMode) -> Result<Self> {
+        Ok(WeakCache {
+            table: lua.create_weak_table(mode)?,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the value associated with `key`, if any.
+    pub fn get(&self, key: K) -> Result<Option<V>> {
+        self.table.raw_get(key)
+    }
+
+    /// Associates `value` with `key`, replacing the previous value.
+    pub fn insert(&self, key: K, value: V) -> Result<()> {
+        self.table.raw_set(key, value)
+    }
+
+    /// Returns the value associated with `key` or computes and inserts it using `f`.
+    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> Result<V>) -> Result<V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        if let Some(value) = self.get(key.clone())? {
+            return Ok(value);
+        }
+        let value = f()?;
+        self.insert(key, value.clone())?;
+        Ok(value)
+    }
+
+    /// Removes the value associated with `key`.
+    pub fn remove(&self, key: K) -> Result<()> {
+        self.table.raw_set(key, Nil)
+    }
+
+    /// Returns the number of live entries in the cache.
+    ///
+    /// This function traverses the whole underlying table.
+    pub fn len(&self) -> Result<usize> {
+        let mut count = 0;
+        for pair in self.table.clone().pairs::<Value, Value>() {
+            pair?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns `true` if the cache has no live entries.
+    pub fn is_empty(&self) -> Result<bool> {
+        match self.table.clone().pairs::<Value, Value>().next() {
+            Some(pair) => pair.map(|_| false),
+            None => Ok(true),
+        }
+    }
+
+    /// Returns the underlying weak table.
+    pub fn table(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
+impl<'lua, K, V> Clone for WeakCache<'lua, K, V> {
+    fn clone(&self) -> Self {
+        WeakCache {
+            table: self.table.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'lua, K, V> fmt::Debug for WeakCache<'lua, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("WeakCache").field(&self.table).finish()
+    }
+}
@@ -1,13 +1,16 @@
 #![cfg(feature = "async")]
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use futures_util::future;
 use futures_util::stream::TryStreamExt;
 
+use mlua::AsyncConcurrency::{Queue, Reject};
 use mlua::{
-    AnyUserDataExt, Error, Function, Lua, LuaOptions, MultiValue, Result, StdLib, Table, TableExt,
-    UserData, UserDataMethods, Value,
+    AnyUserDataExt, AsyncSchedulerHooks, Error, Function, Lua, LuaOptions, MultiValue, Result,
+    StdLib, Table, TableExt, UserData, UserDataMethods, Value,
 };
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -483,6 +486,75 @@ async fn test_async_userdata() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_async_userdata_concurrency() -> Result<()> {
+    struct MyUserData(u64);
+
+    impl UserData for MyUserData {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_async_method_mut_with("incr", Queue, |_, data, ()| async move {
+                let n = data.0;
+                sleep_ms(10).await;
+                data.0 = n + 1;
+                Ok(data.0)
+            });
+
+            methods.add_async_method_with("get_value", Reject, |_, data, ()| async move {
+                sleep_ms(10).await;
+                Ok(data.0)
+            });
+        }
+    }
+
+    let lua = Lua::new();
+
+    // Queued calls run one at a time, without failing to borrow the userdata
+    let userdata = lua.create_userdata(MyUserData(0))?;
+    let (a, b) = future::try_join(
+        userdata.call_async_method::<_, u64>("incr", ()),
+        userdata.call_async_method::<_, u64>("incr", ()),
+    )
+    .await?;
+    assert_eq!((a, b), (1, 2));
+
+    // Rejected call fails while another call on the same userdata is running
+    let (a, b) = future::join(
+        userdata.call_async_method::<_, u64>("get_value", ()),
+        userdata.call_async_method::<_, u64>("get_value", ()),
+    )
+    .await;
+    assert_eq!(a?, 2);
+    match b {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { cause, .. } => assert!(matches!(
+                cause.as_ref(),
+                Error::RuntimeError(msg) if msg == "another async call is running on this userdata"
+            )),
+            err => panic!("expected BadArgument, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // Guarded methods of a type share the running calls
+    let res = future::join(
+        userdata.call_async_method::<_, u64>("incr", ()),
+        userdata.call_async_method::<_, u64>("get_value", ()),
+    )
+    .await;
+    assert!(matches!(res, (Ok(3), Err(_))));
+
+    // Calls on different userdata do not wait for each other
+    let userdata2 = lua.create_userdata(MyUserData(10))?;
+    let (a, b) = future::try_join(
+        userdata.call_async_method::<_, u64>("get_value", ()),
+        userdata2.call_async_method::<_, u64>("get_value", ()),
+    )
+    .await?;
+    assert_eq!((a, b), (3, 10));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_async_thread_error() -> Result<()> {
     struct MyUserData;
@@ -507,6 +579,91 @@ async fn test_async_thread_error() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_async_scheduler_hooks() -> Result<()> {
+    let lua = Lua::new();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let (events2, events3, events4) = (events.clone(), events.clone(), events.clone());
+    let hooks = AsyncSchedulerHooks::new()
+        .on_resume(move |_, _| {
+            events2.lock().unwrap().push("resume".to_string());
+            Ok(())
+        })
+        .on_yield(move |_, _, values| {
+            let values = values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Result<Vec<_>>>()?;
+            events3
+                .lock()
+                .unwrap()
+                .push(format!("yield {}", values.join(",")));
+            Ok(())
+        })
+        .on_complete(move |_, _, res| {
+            let event = match res {
+                Ok(values) => format!("complete {}", values.len()),
+                Err(_) => "error".to_string(),
+            };
+            events4.lock().unwrap().push(event);
+            Ok(())
+        });
+    lua.set_async_scheduler_hooks(hooks);
+
+    let sleep = lua.create_async_function(|_, ()| async move {
+        sleep_ms(10).await;
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+
+    // Future: yielded values are reported (and ignored)
+    let func = lua
+        .load("coroutine.yield(1, 2); sleep(); return 3")
+        .into_function()?;
+    assert_eq!(func.call_async::<_, i32>(()).await?, 3);
+    {
+        let mut events = events.lock().unwrap();
+        assert_eq!(events[..3], ["resume", "yield 1,2", "resume"]);
+        assert_eq!(events.last().unwrap(), "complete 1");
+        // Waiting for the `sleep` future triggers more resumes (and no yields)
+        assert!(events[3..events.len() - 1].iter().all(|e| e == "resume"));
+        events.clear();
+    }
+
+    // Stream: yielded values are passed through
+    let thread = lua.create_thread(lua.load("function() coroutine.yield(4) end").eval()?)?;
+    let values = thread
+        .into_async::<_, Option<i32>>(())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(values, vec![Some(4), None]);
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["resume", "yield 4", "resume", "complete 0"]
+    );
+    events.lock().unwrap().clear();
+
+    // Errors are reported on completion
+    let func = lua.load("error('boom')").into_function()?;
+    assert!(func.call_async::<_, ()>(()).await.is_err());
+    assert_eq!(*events.lock().unwrap(), ["resume", "error"]);
+    events.lock().unwrap().clear();
+
+    // Hooks can abort execution
+    lua.set_async_scheduler_hooks(
+        AsyncSchedulerHooks::new().on_yield(|_, _, _| Err(Error::runtime("aborted"))),
+    );
+    let func = lua.load("coroutine.yield()").into_function()?;
+    let err = func.call_async::<_, ()>(()).await.unwrap_err();
+    assert!(err.to_string().contains("aborted"));
+
+    lua.remove_async_scheduler_hooks();
+    func.call_async::<_, ()>(()).await?;
+
+    Ok(())
+}
+
 #[cfg(all(feature = "unstable", not(feature = "send")))]
 #[tokio::test]
 async fn test_owned_async_call() -> Result<()> {
@@ -546,3 +703,273 @@ async fn test_async_terminate() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_generator() -> Result<()> {
+    let lua = Lua::new();
+
+    let pairs = lua.create_generator(|yielder, (n, prefix): (i64, String)| async move {
+        for i in 1..=n {
+            if i == 3 {
+                // Yielded values are converted before the body is suspended
+                yielder.yield_values((i, format!("{prefix}{i}"))).await?;
+                continue;
+            }
+            yielder
+                .yield_values((i, yielder.lua().create_string(prefix.clone())?))
+                .await?;
+        }
+        Ok(())
+    })?;
+    lua.globals().set("pairs_gen", pairs)?;
+    lua.load(
+        r#"
+        local keys, values = {}, {}
+        for k, v in pairs_gen(4, "x") do
+            table.insert(keys, k)
+            table.insert(values, v)
+        end
+        assert(table.concat(keys, ",") == "1,2,3,4")
+        assert(table.concat(values, ",") == "x,x,x3,x")
+
+        -- Every call starts a new run
+        local it1, it2 = pairs_gen(2, "a"), pairs_gen(2, "b")
+        assert(select(2, it1()) == "a" and select(2, it2()) == "b")
+        assert(it1() == 2 and it1() == nil and it1() == nil)
+    "#,
+    )
+    .exec()?;
+
+    // Errors are raised in Lua and finish the iteration
+    let failing = lua.create_generator(|yielder, ()| async move {
+        yielder.yield_values(1).await?;
+        Err::<(), _>(Error::runtime("generator failed"))
+    })?;
+    let iter: Function = failing.call(())?;
+    assert_eq!(iter.call::<_, i64>(())?, 1);
+    match iter.call::<_, ()>(()) {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => assert_eq!(msg, "generator failed"),
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert_eq!(iter.call::<_, Value>(())?, Value::Nil);
+
+    // Awaiting unrelated futures is an error
+    let pending = lua.create_generator(|_, ()| async move {
+        futures_util::future::pending::<()>().await;
+        Ok(())
+    })?;
+    let iter: Function = pending.call(())?;
+    assert!(iter.call::<_, ()>(()).is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "send")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_shared_lua_lock() -> Result<()> {
+    use mlua::SharedLua;
+
+    let shared = SharedLua::new(Lua::new());
+    shared.lock_async().await.globals().set("counter", 0)?;
+
+    let mut handles = Vec::new();
+    for _ in 0..8 {
+        let shared = shared.clone();
+        handles.push(tokio::spawn(async move {
+            for _ in 0..10 {
+                let lua = shared.lock_async().await;
+                lua.load("counter = counter + 1").exec()?;
+                drop(lua);
+                tokio::task::yield_now().await;
+            }
+            Ok::<_, Error>(())
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap()?;
+    }
+
+    let lua = shared.try_lock().expect("lock is available");
+    assert_eq!(lua.globals().get::<_, i64>("counter")?, 80);
+
+    // Lock is held, synchronous callers should time out
+    let shared2 = shared.clone();
+    let timed_out =
+        std::thread::spawn(move || shared2.try_lock_for(Duration::from_millis(20)).is_none());
+    assert!(timed_out.join().unwrap());
+
+    // The async waiter acquires the lock once released
+    let shared2 = shared.clone();
+    let waiter = tokio::spawn(async move {
+        let lua = shared2.lock_async().await;
+        let counter = lua.globals().get::<_, i64>("counter");
+        counter
+    });
+    sleep_ms(10).await;
+    drop(lua);
+    assert_eq!(waiter.await.unwrap()?, 80);
+    assert!(shared.try_lock_for(Duration::from_millis(20)).is_some());
+    assert!(shared.try_lock_for(Duration::MAX).is_some());
+
+    Ok(())
+}
+
+#[cfg(feature = "send")]
+#[tokio::test]
+async fn test_lua_lock() -> Result<()> {
+    let lua = Lua::new();
+
+    // Tasks on the same thread are serialized while holding the lock
+    let task = |n: i32| {
+        let lua = &lua;
+        async move {
+            let guard = lua.lock_async().await;
+            guard.globals().set("value", n)?;
+            sleep_ms(10).await;
+            let value = guard.globals().get::<_, i32>("value")?;
+            Ok::<_, Error>(value)
+        }
+    };
+    let (a, b) = tokio::join!(task(1), task(2));
+    assert_eq!((a?, b?), (1, 2));
+
+    let guard = lua.try_lock().expect("lock is available");
+    assert!(lua.try_lock().is_none());
+    assert!(lua.try_lock_for(Duration::from_millis(10)).is_none());
+    drop(guard);
+    assert!(lua.try_lock_for(Duration::MAX).is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_scheduler() -> Result<()> {
+    use mlua::LuaScheduler;
+
+    let lua = Lua::new();
+    let mut scheduler = LuaScheduler::<i64>::new(&lua);
+
+    let counter = lua
+        .load("local n = ... for i = 1, n do coroutine.yield() end return n")
+        .into_function()?;
+    let a = scheduler.spawn(lua.create_thread(counter.clone())?.into_async(2));
+    let b = scheduler.spawn(lua.create_thread(counter)?.into_async(1));
+    let failing = lua
+        .load("coroutine.yield() error('boom')")
+        .into_function()?;
+    let c = scheduler.spawn(lua.create_thread(failing)?.into_async(()));
+    assert_eq!(scheduler.len(), 3);
+
+    // Every thread runs once per tick
+    assert!(scheduler.tick(Duration::from_secs(1)).is_empty());
+    let finished = scheduler.tick(Duration::from_secs(1));
+    assert_eq!(finished.len(), 2);
+    assert_eq!(finished[0].0, b);
+    assert_eq!(*finished[0].1.as_ref().unwrap(), 1);
+    assert_eq!(finished[1].0, c);
+    assert!(finished[1].1.is_err());
+    let finished = scheduler.tick(Duration::from_secs(1));
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].0, a);
+    assert!(scheduler.is_empty());
+    assert!(!scheduler.contains(a));
+
+    // Long running threads are preempted
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    {
+        let busy = lua.load("while true do end").into_function()?;
+        let busy = scheduler.spawn(lua.create_thread(busy)?.into_async(()));
+        for _ in 0..3 {
+            assert!(scheduler.tick(Duration::from_millis(5)).is_empty());
+            assert!(scheduler.contains(busy));
+        }
+        assert!(scheduler.cancel(busy));
+        assert!(!scheduler.cancel(busy));
+        assert!(scheduler.tick(Duration::from_millis(5)).is_empty());
+    }
+
+    // Threads waiting for Rust futures are polled when woken
+    let wait = lua.create_async_function(|_, ()| async {
+        let mut polled = false;
+        futures_util::future::poll_fn(|cx| {
+            if polled {
+                return std::task::Poll::Ready(());
+            }
+            polled = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        })
+        .await;
+        Ok(7)
+    })?;
+    let waiting = scheduler.spawn(lua.create_thread(wait)?.into_async(()));
+    assert!(scheduler.tick(Duration::from_secs(1)).is_empty());
+    let finished = scheduler.tick(Duration::from_secs(1));
+    assert_eq!(finished[0].0, waiting);
+    assert_eq!(*finished[0].1.as_ref().unwrap(), 7);
+
+    // Overflowing budgets don't panic
+    let task = lua.create_thread(lua.load("return 3").into_function()?)?;
+    let task = scheduler.spawn(task.into_async(()));
+    let finished = scheduler.tick(Duration::MAX);
+    assert_eq!(finished[0].0, task);
+    assert_eq!(*finished[0].1.as_ref().unwrap(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_scope_function() -> Result<()> {
+    struct Guard(Arc<AtomicUsize>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let lua = Lua::new();
+    let dropped2 = dropped.clone();
+    let wait = lua.create_async_scope_function(move |_, ms: u64| {
+        let guard = Guard(dropped2.clone());
+        async move {
+            sleep_ms(ms).await;
+            drop(guard);
+            Ok("done")
+        }
+    })?;
+    lua.globals().set("wait", wait)?;
+
+    // Finished calls are not cancelled
+    let res: String = lua.load("return wait(1)").call_async(()).await?;
+    assert_eq!(res, "done");
+    assert_eq!(dropped.load(Ordering::Relaxed), 1);
+
+    // Pending calls are dropped on cancellation and raise an error
+    let f = lua.load("return wait(10000)").into_function()?;
+    let mut fut = Box::pin(lua.create_thread(f)?.into_async::<_, String>(()));
+    assert!(futures::poll!(fut.as_mut()).is_pending());
+    assert_eq!(dropped.load(Ordering::Relaxed), 1);
+    assert_eq!(lua.cancel_pending_async(), 1);
+    assert_eq!(dropped.load(Ordering::Relaxed), 2);
+    match fut.await {
+        Err(err) => assert!(err
+            .to_string()
+            .contains("async function call was cancelled")),
+        r => panic!("expected error, got {r:?}"),
+    }
+    assert_eq!(lua.cancel_pending_async(), 0);
+
+    // Pending calls are dropped before the state is closed
+    let thread = lua.create_thread(lua.globals().get::<_, Function>("wait")?)?;
+    let mut fut = Box::pin(thread.into_async::<_, ()>(10000));
+    assert!(futures::poll!(fut.as_mut()).is_pending());
+    drop(fut);
+    drop(lua);
+    assert_eq!(dropped.load(Ordering::Relaxed), 3);
+
+    Ok(())
+}
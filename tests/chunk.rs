@@ -1,9 +1,11 @@
-use std::fs;
-use std::io;
+#[cfg(feature = "filesystem")]
+use std::{fs, io};
 
+#[cfg(any(feature = "filesystem", feature = "macros"))]
 use mlua::{Lua, Result};
 
 #[test]
+#[cfg(feature = "filesystem")]
 fn test_chunk_path() -> Result<()> {
     let lua = Lua::new();
 
@@ -69,3 +71,36 @@ fn test_chunk_macro() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(feature = "filesystem")]
+fn test_precompiled_prelude() -> Result<()> {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("prelude.lua");
+    fs::write(&path, "function greet(name) return 'hello ' .. name end")?;
+
+    let prelude = mlua::PreludeBuilder::new()
+        .add_source("config", "config = { level = 3 }")
+        .add_file(&path)
+        .add_source("init", "greeting = greet('world') .. config.level")
+        .strip(true)
+        .compile()?;
+
+    let lua = Lua::new();
+    lua.load_precompiled_prelude(&prelude)?;
+    assert_eq!(lua.globals().get::<_, String>("greeting")?, "hello world3");
+
+    // Syntax errors are reported when compiling
+    let res = mlua::PreludeBuilder::new()
+        .add_source("bad", "local = 1")
+        .compile();
+    assert!(matches!(res, Err(mlua::Error::SyntaxError { .. })));
+
+    // Malformed preludes are rejected
+    assert!(lua.load_precompiled_prelude(b"garbage").is_err());
+    assert!(lua
+        .load_precompiled_prelude(&prelude[..prelude.len() - 1])
+        .is_err());
+
+    Ok(())
+}
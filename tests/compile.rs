@@ -21,6 +21,9 @@ fn test_compilation() {
         t.compile_fail("tests/compile/async_userdata_method.rs");
     }
 
+    #[cfg(feature = "macros")]
+    t.compile_fail("tests/compile/lua_enum_struct.rs");
+
     #[cfg(feature = "send")]
     t.compile_fail("tests/compile/non_send.rs");
     #[cfg(not(feature = "send"))]
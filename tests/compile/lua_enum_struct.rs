@@ -0,0 +1,8 @@
+use mlua::LuaEnum;
+
+#[derive(Clone, LuaEnum)]
+struct Point {
+    x: i32,
+}
+
+fn main() {}
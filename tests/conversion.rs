@@ -5,8 +5,8 @@ use std::ffi::{CStr, CString};
 use bstr::BString;
 use maplit::{btreemap, btreeset, hashmap, hashset};
 use mlua::{
-    AnyUserData, Error, Function, IntoLua, Lua, RegistryKey, Result, Table, Thread, UserDataRef,
-    Value,
+    AnyUserData, CoercionPolicy, ConversionLimits, Error, Function, IntoLua, Lua, LuaOptions,
+    RegistryKey, Result, StdLib, Table, Thread, UserDataRef, Value,
 };
 
 #[test]
@@ -471,3 +471,180 @@ fn test_option_into_from_lua() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_enum_derive() -> Result<()> {
+    use mlua::LuaEnum;
+
+    #[derive(Clone, Copy, Debug, PartialEq, LuaEnum)]
+    enum Level {
+        Debug,
+        Info,
+        #[lua(rename = "warning")]
+        Warn = 5,
+        Error,
+    }
+
+    assert_eq!(Level::VARIANTS.len(), 4);
+    assert_eq!(Level::Error.to_integer(), 6);
+    assert_eq!(Level::Warn.name(), "warning");
+    assert_eq!(Level::from_name("Info"), Some(Level::Info));
+    assert_eq!(Level::from_integer(7), None);
+
+    let lua = Lua::new();
+    let table = lua.register_enum::<Level>("Level")?;
+    assert_eq!(table.get::<_, i64>("warning")?, 5);
+    assert_eq!(table.get::<_, String>(6)?, "Error");
+
+    lua.globals().set(
+        "level_name",
+        lua.create_function(|_, level: Level| Ok(level.name()))?,
+    )?;
+    lua.load(
+        r#"
+        assert(Level.Debug == 0 and Level.Info == 1 and Level.Error == 6)
+        assert(Level[Level.warning] == "warning")
+        assert(level_name(Level.Info) == "Info")
+        assert(level_name("warning") == "warning")
+        assert(level_name(6) == "Error")
+    "#,
+    )
+    .exec()?;
+    assert_eq!(lua.load("Level.Error").eval::<Level>()?, Level::Error);
+    assert_eq!(lua.pack(Level::Warn)?, Value::Integer(5));
+
+    // The table is read-only
+    assert!(lua.load("Level.Trace = 10").exec().is_err());
+    assert!(lua.load("Level.Debug = 10").exec().is_err());
+    assert_eq!(lua.load("Level.Debug").eval::<i64>()?, 0);
+
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    {
+        let count = lua
+            .load("local n = 0; for _ in pairs(Level) do n = n + 1 end; return n")
+            .eval::<i64>()?;
+        assert_eq!(count, 8);
+    }
+
+    // Unknown variants
+    match lua.load("level_name('Trace')").exec() {
+        Err(Error::CallbackError { cause, .. }) => {
+            let err = cause.to_string();
+            assert!(err.contains("expected one of Debug, Info, warning, Error"));
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert!(lua.load("Level[7]").eval::<Level>().is_err());
+    assert!(lua.load("true").eval::<Level>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_coercion_policy() -> Result<()> {
+    // Lua coercions by default
+    let lua = Lua::new();
+    assert_eq!(lua.unpack::<i32>(lua.pack("42")?)?, 42);
+    assert_eq!(lua.unpack::<f64>(lua.pack("1.5")?)?, 1.5);
+    assert_eq!(lua.unpack::<String>(lua.pack(42)?)?, "42");
+
+    let lua = Lua::new_with(
+        StdLib::NONE,
+        LuaOptions::new().coercion(CoercionPolicy::STRICT),
+    )?;
+    assert!(lua.unpack::<i32>(lua.pack("42")?).is_err());
+    assert!(lua.unpack::<f64>(lua.pack("1.5")?).is_err());
+    assert!(lua.unpack::<String>(lua.pack(42)?).is_err());
+    assert!(lua.unpack::<BString>(lua.pack(1.5)?).is_err());
+    assert!(lua.unpack::<mlua::String>(lua.pack(42)?).is_err());
+    // Numbers are still converted between integers and floats
+    assert_eq!(lua.unpack::<i32>(lua.pack(42.0)?)?, 42);
+    assert_eq!(lua.unpack::<f64>(lua.pack(42)?)?, 42.0);
+    // Lua semantics are unchanged
+    assert_eq!(lua.load("return 1 .. 2").eval::<String>()?, "12");
+    assert_eq!(lua.coerce_integer(lua.pack("42")?)?, Some(42));
+
+    // Single direction
+    let policy = CoercionPolicy::LUA.number_to_string(false);
+    let lua = Lua::new_with(StdLib::NONE, LuaOptions::new().coercion(policy))?;
+    assert_eq!(lua.unpack::<i32>(lua.pack("42")?)?, 42);
+    assert!(lua.unpack::<String>(lua.pack(42)?).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_conversion_limits() -> Result<()> {
+    let lua = Lua::new();
+
+    // Nesting depth is limited by default
+    assert_eq!(lua.conversion_limits().max_depth, Some(128));
+    let deep = lua
+        .load("local t = {} for i = 1, 200 do t = {t} end return t")
+        .eval::<Table>()?;
+    match deep.deep_clone() {
+        Err(Error::TooDeep { max_depth: 128 }) => {}
+        r => panic!("expected TooDeep error, got {r:?}"),
+    }
+
+    let limits = ConversionLimits::new().max_depth(Some(2));
+    assert_eq!(
+        lua.set_conversion_limits(limits),
+        ConversionLimits::default()
+    );
+    assert_eq!(lua.conversion_limits(), limits);
+
+    let value = lua.load("{{1}, {2, 3}}").eval::<Value>()?;
+    assert_eq!(
+        lua.unpack::<Vec<Vec<i32>>>(value)?,
+        vec![vec![1], vec![2, 3]]
+    );
+    let value = lua.load("{{{1}}}").eval::<Value>()?;
+    match lua.unpack::<Vec<Vec<Vec<i32>>>>(value.clone()) {
+        Err(Error::TooDeep { max_depth: 2 }) => {}
+        r => panic!("expected TooDeep error, got {r:?}"),
+    }
+    match value.as_table().unwrap().deep_clone() {
+        Err(Error::TooDeep { max_depth: 2 }) => {}
+        r => panic!("expected TooDeep error, got {r:?}"),
+    }
+    // Depth is restored after a failed conversion
+    let value2 = lua.load("{a = {b = 1}}").eval::<Value>()?;
+    let map = lua.unpack::<HashMap<String, HashMap<String, i32>>>(value2)?;
+    assert_eq!(map["a"]["b"], 1);
+
+    // Elements are counted across nested tables, for each conversion
+    lua.set_conversion_limits(ConversionLimits::new().max_elements(Some(5)));
+    let value = lua.load("{{1, 2}, {3}}").eval::<Value>()?;
+    for _ in 0..3 {
+        lua.unpack::<Vec<Vec<i32>>>(value.clone())?;
+    }
+    let value = lua.load("{{1, 2}, {3, 4}}").eval::<Value>()?;
+    match lua.unpack::<Vec<BTreeSet<i32>>>(value) {
+        Err(Error::TooLarge { max_elements: 5 }) => {}
+        r => panic!("expected TooLarge error, got {r:?}"),
+    }
+
+    // Callback arguments
+    let f = lua.create_function(|_, _: HashMap<i32, Vec<i32>>| Ok(()))?;
+    let err = f
+        .call::<_, ()>(lua.load("{{1, 2, 3, 4, 5}}").eval::<Table>()?)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("maximum number of 5 elements"),
+        "{err}"
+    );
+
+    lua.set_conversion_limits(ConversionLimits::unlimited());
+    let value = lua.load("{{{1}}}").eval::<Value>()?;
+    lua.unpack::<Vec<Vec<Vec<i32>>>>(value)?;
+    deep.deep_clone()?;
+
+    Ok(())
+}
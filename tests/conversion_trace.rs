@@ -0,0 +1,102 @@
+#![cfg(feature = "trace_conversions")]
+
+use std::any::type_name;
+use std::sync::{Arc, Mutex};
+
+use mlua::{ConversionDirection, Function, Lua, Result, Table};
+
+#[test]
+fn test_conversion_tracer() -> Result<()> {
+    let lua = Lua::new();
+    let traces = Arc::new(Mutex::new(Vec::new()));
+    let traces2 = traces.clone();
+    lua.set_conversion_tracer(move |trace| {
+        traces2
+            .lock()
+            .unwrap()
+            .push((trace.direction, trace.type_name, trace.values));
+    });
+
+    // Arguments and results of a Lua function
+    let sum: Function = lua
+        .load("function(t, n) local s = n for _, v in ipairs(t) do s = s + v end return s end")
+        .eval()?;
+    traces.lock().unwrap().clear();
+    let res: i64 = sum.call((vec![1i64, 2, 3], 4i64))?;
+    assert_eq!(res, 10);
+    assert_eq!(
+        *traces.lock().unwrap(),
+        vec![
+            (
+                ConversionDirection::IntoLua,
+                type_name::<(Vec<i64>, i64)>(),
+                2
+            ),
+            (ConversionDirection::FromLua, type_name::<i64>(), 1),
+        ]
+    );
+
+    // Table values
+    traces.lock().unwrap().clear();
+    let globals = lua.globals();
+    globals.set("items", vec!["a", "b"])?;
+    let items: Vec<String> = globals.get("items")?;
+    assert_eq!(items, vec!["a", "b"]);
+    assert_eq!(
+        *traces.lock().unwrap(),
+        vec![
+            (ConversionDirection::IntoLua, type_name::<Vec<&str>>(), 1),
+            (ConversionDirection::FromLua, type_name::<Vec<String>>(), 1),
+        ]
+    );
+
+    // Failed conversions are traced too
+    traces.lock().unwrap().clear();
+    assert!(lua.unpack::<Table>(mlua::Value::Boolean(true)).is_err());
+    assert_eq!(
+        *traces.lock().unwrap(),
+        vec![(ConversionDirection::FromLua, type_name::<Table>(), 0)]
+    );
+
+    lua.remove_conversion_tracer();
+    traces.lock().unwrap().clear();
+    globals.set("x", 1)?;
+    assert!(traces.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_conversion_tracer_callback() -> Result<()> {
+    let lua = Lua::new();
+    let traces = Arc::new(Mutex::new(Vec::new()));
+    let traces2 = traces.clone();
+    lua.set_conversion_tracer(move |trace| {
+        traces2
+            .lock()
+            .unwrap()
+            .push((trace.direction, trace.type_name, trace.memory_delta));
+    });
+
+    let make_list = lua.create_function(|_, (n, prefix): (usize, String)| {
+        Ok((0..n).map(|i| format!("{prefix}{i}")).collect::<Vec<_>>())
+    })?;
+    lua.globals().set("make_list", make_list)?;
+    traces.lock().unwrap().clear();
+    lua.load("make_list(100, 'item')").exec()?;
+
+    let traces = traces.lock().unwrap();
+    assert_eq!(traces.len(), 2);
+    assert_eq!(
+        (traces[0].0, traces[0].1),
+        (ConversionDirection::FromLua, type_name::<(usize, String)>())
+    );
+    assert_eq!(
+        (traces[1].0, traces[1].1),
+        (ConversionDirection::IntoLua, type_name::<Vec<String>>())
+    );
+    // The list of 100 strings is allocated in Lua
+    assert!(traces[1].2 > 0);
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "time")]
+
+use mlua::{DateTime, Lua, Result};
+
+#[test]
+fn test_datetime_module() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals()
+        .set("datetime", lua.create_datetime_module()?)?;
+
+    lua.load(
+        r#"
+        local dt = datetime.new{year = 2024, month = 2, day = 29, hour = 23, offset = "+02:00"}
+        assert(dt.year == 2024 and dt.month == 2 and dt.day == 29 and dt.hour == 23)
+        assert(dt.offset == 7200 and dt.weekday == 4 and dt.yearday == 60)
+        assert(tostring(dt) == "2024-02-29T23:00:00+02:00")
+        assert(dt:timestamp() == 1709240400)
+
+        local utc = dt:to_utc()
+        assert(utc == dt and utc.hour == 21 and utc.offset == 0)
+        assert(dt:to_offset(-3600).hour == 20)
+        assert(dt:to_offset("UTC") == utc)
+        assert(not pcall(dt.to_offset, dt, 100000) and not pcall(dt.to_offset, dt, 2^40))
+
+        -- Arithmetic
+        local later = dt + 3600.5
+        assert(later > dt and dt < later and dt <= dt)
+        assert(later - dt == 3600.5)
+        assert((later - 0.5).hour == 0 and (later - 0.5).month == 3)
+        assert((60 + dt):format("[hour]:[minute]") == "23:01")
+
+        -- Parsing and formatting
+        local parsed = datetime.parse("2024-03-01 10:20:30", "[year]-[month]-[day] [hour]:[minute]:[second]")
+        assert(parsed.offset == 0 and parsed.second == 30)
+        assert(datetime.parse("2024-03-01T10:20:30Z") == parsed)
+        assert(datetime.from_timestamp(parsed:timestamp()) == parsed)
+        assert(not pcall(datetime.parse, "not a date"))
+        assert(not pcall(datetime.new, {year = 2023, month = 2, day = 29}))
+
+        assert(datetime.now() > parsed)
+    "#,
+    )
+    .exec()?;
+
+    // Passing between Rust and Lua
+    let dt: DateTime = lua.load("datetime.from_timestamp(0)").eval()?;
+    assert_eq!(dt.0, time::OffsetDateTime::UNIX_EPOCH);
+    let f = lua
+        .load("function(dt) return dt:add(60) end")
+        .eval::<mlua::Function>()?;
+    let dt: DateTime = f.call(dt)?;
+    assert_eq!(dt.0.unix_timestamp(), 60);
+
+    Ok(())
+}
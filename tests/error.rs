@@ -50,3 +50,46 @@ fn test_error_context() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_error_source_location() -> Result<()> {
+    let lua = Lua::new();
+
+    // Syntax error
+    let err = lua.load("local x = 1\nlocal = 2").set_name("syntax").exec();
+    let location = err.unwrap_err().source_location().unwrap();
+    assert_eq!(location.chunk, "syntax");
+    assert_eq!(location.line, 2);
+    assert_eq!(location.column, None);
+
+    // Runtime error
+    let err = lua.load("\n\nlocal x = nil + 1").set_name("runtime").exec();
+    let location = err.unwrap_err().source_location().unwrap();
+    assert_eq!((location.chunk.as_str(), location.line), ("runtime", 3));
+    assert_eq!(location.to_string(), "runtime:3");
+
+    // Errors raised without position
+    let err = lua.load("error('no position', 0)").exec().unwrap_err();
+    assert_eq!(err.source_location(), None);
+
+    // Callback errors are located at the calling Lua code
+    let func = lua.create_function(|_, ()| Err::<(), _>(Error::runtime("rust error")))?;
+    lua.globals().set("func", func)?;
+    let err = lua
+        .load("\nfunc()")
+        .set_name("callback")
+        .exec()
+        .unwrap_err();
+    let location = err.source_location().unwrap();
+    assert_eq!((location.chunk.as_str(), location.line), ("callback", 2));
+
+    // Messages in the `chunk:line:column:` form
+    let location = Error::runtime("C:\\scripts\\main.lua:10:5: oops")
+        .source_location()
+        .unwrap();
+    assert_eq!(location.chunk, "C:\\scripts\\main.lua");
+    assert_eq!(location.line, 10);
+    assert_eq!(location.column, Some(5));
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "export")]
+
+use mlua::{lua_export, Error, Lua, Result, Table};
+
+#[lua_export]
+fn add(_: &Lua, (a, b): (i64, i64)) -> Result<i64> {
+    Ok(a + b)
+}
+
+#[lua_export(name = "utils.text.upper")]
+fn upper(_: &Lua, s: String) -> Result<String> {
+    Ok(s.to_uppercase())
+}
+
+#[lua_export(name = "utils.len")]
+fn len(_: &Lua, t: Table) -> Result<usize> {
+    Ok(t.raw_len())
+}
+
+#[test]
+fn test_register_exports() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals()
+        .set("utils", lua.create_table_from([("version", 1)])?)?;
+    lua.register_exports()?;
+
+    lua.load(
+        r#"
+        assert(add(1, 2) == 3)
+        assert(utils.text.upper("abc") == "ABC")
+        assert(utils.len({1, 2}) == 2)
+        -- Existing tables are reused
+        assert(utils.version == 1)
+    "#,
+    )
+    .exec()?;
+
+    // Exports are named after their full name
+    match lua.load("local f = utils.text.upper; f({})").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { to, .. } => assert_eq!(to.as_deref(), Some("utils.text.upper")),
+            cause => panic!("expected BadArgument, got {cause:?}"),
+        },
+        res => panic!("expected CallbackError, got {res:?}"),
+    }
+
+    Ok(())
+}
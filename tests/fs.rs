@@ -0,0 +1,107 @@
+#![cfg(feature = "fs")]
+
+use std::fs;
+
+use mlua::{FsPolicy, Lua, Result};
+
+#[test]
+fn test_fs_module() -> Result<()> {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+    let lua = Lua::new();
+    let policy = FsPolicy::new(&root).write_quota(Some(32));
+    lua.globals().set("fs", lua.create_fs_module(policy)?)?;
+
+    lua.load(
+        r#"
+        fs.mkdir("data")
+        fs.write("data/hello.txt", "hello")
+        assert(fs.read("/data/hello.txt") == "hello")
+        assert(fs.read("data/../data/./hello.txt") == "hello")
+
+        local f = fs.open("data/hello.txt", "a")
+        f:write(", world")
+        f:close()
+        assert(not pcall(f.write, f, "!"))
+
+        f = fs.open("data/hello.txt")
+        assert(f:read(5) == "hello")
+        assert(f:read() == ", world")
+        assert(f:read(1) == nil)
+        f:close()
+
+        local stat = fs.stat("data/hello.txt")
+        assert(stat.type == "file" and stat.size == 12)
+        assert(fs.stat("data").type == "directory")
+        assert(fs.stat("missing") == nil)
+
+        fs.write("data/a.txt", "")
+        local names = fs.list("data")
+        assert(#names == 2 and names[1] == "a.txt" and names[2] == "hello.txt")
+
+        fs.remove("data/a.txt")
+        assert(fs.stat("data/a.txt") == nil)
+    "#,
+    )
+    .exec()?;
+
+    // Escaping the root
+    for path in ["../secret.txt", "data/../../secret.txt"] {
+        let err = lua.load(format!("fs.read('{path}')")).exec().unwrap_err();
+        assert!(err.to_string().contains("escapes the root directory"));
+    }
+
+    // Quota
+    let err = lua
+        .load("fs.write('big.txt', string.rep('x', 32))")
+        .exec()
+        .unwrap_err();
+    assert!(err.to_string().contains("write quota exceeded"));
+    assert!(!root.join("big.txt").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fs_module_symlinks() -> Result<()> {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+    std::os::unix::fs::symlink(dir.path(), root.join("outside")).unwrap();
+    std::os::unix::fs::symlink(dir.path().join("new.txt"), root.join("dangling")).unwrap();
+    std::os::unix::fs::symlink(root.join("file.txt"), root.join("inside")).unwrap();
+    fs::write(root.join("file.txt"), "data").unwrap();
+
+    let lua = Lua::new();
+    let policy = FsPolicy::new(&root);
+    lua.globals().set("fs", lua.create_fs_module(policy)?)?;
+
+    assert!(lua.load("fs.read('outside/secret.txt')").exec().is_err());
+    assert!(lua.load("fs.write('outside/new.txt', 'x')").exec().is_err());
+    assert!(lua.load("fs.write('dangling', 'x')").exec().is_err());
+    assert!(!dir.path().join("new.txt").exists());
+    // Symlinks within the root are allowed
+    assert_eq!(lua.load("fs.read('inside')").eval::<String>()?, "data");
+    lua.load("fs.write('inside', 'data')").exec()?;
+    assert_eq!(fs::read_to_string(root.join("file.txt")).unwrap(), "data");
+    // Removing a symlink does not remove its target
+    lua.load("fs.remove('inside'); fs.remove('outside')")
+        .exec()?;
+    assert!(root.join("inside").symlink_metadata().is_err() && root.join("file.txt").exists());
+    assert!(dir.path().join("secret.txt").exists());
+
+    // Read-only mode
+    let lua = Lua::new();
+    let policy = FsPolicy::new(&root).read_only(true);
+    lua.globals().set("fs", lua.create_fs_module(policy)?)?;
+    assert!(lua.load("fs.write('file.txt', 'x')").exec().is_err());
+    assert!(lua.load("fs.open('file.txt', 'w')").exec().is_err());
+    assert_eq!(lua.load("fs.read('file.txt')").eval::<String>()?, "data");
+
+    Ok(())
+}
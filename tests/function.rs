@@ -1,4 +1,10 @@
-use mlua::{Function, Lua, Result, String, Table};
+use std::string::String as StdString;
+use std::thread;
+
+use mlua::{
+    Args, Error, Function, Lua, RegisteredFunction, Result, String, Table, UserData,
+    UserDataMethods, Value, Variadic,
+};
 
 #[test]
 fn test_function() -> Result<()> {
@@ -99,6 +105,196 @@ fn test_c_function() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_function_pcall() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua
+        .load(
+            r#"
+        function(mode)
+            if mode == "table" then error({code = 1}) end
+            if mode == "string" then error("boom", 0) end
+            return "ok", 2
+        end
+    "#,
+        )
+        .eval()?;
+
+    let (ok, res) = func.pcall::<_, (String, i64)>("none")?;
+    assert!(ok && res.is_ok());
+    let (s, n) = res.into_result().unwrap();
+    assert_eq!((s.to_str()?, n), ("ok", 2));
+
+    let (ok, res) = func.pcall::<_, ()>("table")?;
+    assert!(!ok);
+    match res.into_result() {
+        Err(Value::Table(t)) => assert_eq!(t.get::<_, i64>("code")?, 1),
+        r => panic!("unexpected result: {r:?}"),
+    }
+
+    // No position or traceback is added to the error
+    let (ok, res) = func.pcall::<_, ()>("string")?;
+    assert!(!ok);
+    match res.into_result() {
+        Err(Value::String(s)) => assert_eq!(s, "boom"),
+        r => panic!("unexpected result: {r:?}"),
+    }
+
+    // Errors raised by Rust callbacks are preserved
+    let rust_func = lua
+        .create_function(|_, ()| -> Result<()> { Err(Error::RuntimeError("rust error".into())) })?;
+    let (ok, res) = rust_func.pcall::<_, ()>(())?;
+    assert!(!ok);
+    match res.into_result() {
+        Err(Value::Error(Error::CallbackError { cause, .. })) => {
+            assert!(matches!(*cause, Error::RuntimeError(ref msg) if msg == "rust error"))
+        }
+        r => panic!("unexpected result: {r:?}"),
+    }
+
+    // Conversion errors are reported using the outer `Result`
+    assert!(func.pcall::<_, Table>("none").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_function_call_traced() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua
+        .load(
+            r#"
+        local function inner(fail)
+            if fail then
+                error("boom")
+            end
+            return "ok"
+        end
+        return function(fail)
+            return inner(fail) .. "!"
+        end
+    "#,
+        )
+        .set_name("@traced.lua")
+        .eval()?;
+
+    assert_eq!(func.call_traced::<_, String>(false).unwrap(), "ok!");
+
+    let err = func.call_traced::<_, String>(true).unwrap_err();
+    match &err.error {
+        Error::RuntimeError(msg) => assert!(msg.ends_with("boom")),
+        err => panic!("unexpected error: {err:?}"),
+    }
+    // error -> inner -> (anonymous)
+    assert!(err.frames.len() >= 3);
+    assert_eq!(err.frames[0].what, "C");
+    assert_eq!(err.frames[1].name.as_deref(), Some("inner"));
+    assert_eq!(err.frames[1].short_src.as_deref(), Some("traced.lua"));
+    assert_eq!(err.frames[1].current_line, Some(4));
+    assert_eq!(err.frames[2].current_line, Some(9));
+    assert!(err
+        .to_string()
+        .contains("traced.lua:4: in function 'inner'"));
+
+    // Rust side errors have no frames
+    let err = func.call_traced::<_, Table>(false).unwrap_err();
+    assert!(err.frames.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_function_fallible() -> Result<()> {
+    let lua = Lua::new();
+
+    let div = lua.create_function_fallible(|_, (a, b): (i64, i64)| {
+        if b == 0 {
+            return Err(Error::RuntimeError("division by zero".into()));
+        }
+        Ok(a / b)
+    })?;
+    lua.globals().set("div", div)?;
+    lua.load(
+        r#"
+        assert(div(6, 3) == 2)
+        local res, err = div(1, 0)
+        assert(res == nil and err == "runtime error: division by zero")
+        -- Bad arguments are still raised
+        assert(not pcall(div, "a", 1))
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_raw_function() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua.create_raw_function(|_, stack| {
+        assert_eq!(stack.nargs(), stack.len());
+        let s = stack.get::<String>(1)?;
+        let n = stack.get::<Option<usize>>(2)?.unwrap_or(1);
+        stack.truncate(0);
+        stack.push(s.to_str()?.repeat(n))?;
+        stack.push(n)?;
+        assert_eq!(stack.pop::<usize>()?, n);
+        Ok(1)
+    })?;
+    lua.globals().set("rep", func)?;
+
+    assert_eq!(lua.load(r#"rep("ab", 3)"#).eval::<String>()?, "ababab");
+    assert_eq!(lua.load(r#"rep("ab")"#).eval::<String>()?, "ab");
+    match lua.load(r#"rep({})"#).exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { pos: 1, .. } => {}
+            err => panic!("expected BadArgument, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // Returning more values than available
+    let func = lua.create_raw_function(|_, _| Ok(1))?;
+    assert!(func.call::<_, ()>(()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_raw_state_value_exchange() -> Result<()> {
+    let lua = Lua::new();
+
+    unsafe extern "C-unwind" fn c_function(state: *mut mlua::lua_State) -> std::os::raw::c_int {
+        let lua = Lua::init_from_ptr(state);
+        let table = lua.read_value_from(state, 1).unwrap();
+        let n = table
+            .as_table()
+            .unwrap()
+            .get::<_, mlua::Integer>("n")
+            .unwrap();
+        lua.push_value_to(state, &Value::Integer(n * 2)).unwrap();
+        assert!(lua.read_value_from(state, 10).is_err());
+
+        // Values cannot be exchanged with unrelated states
+        let other = Lua::new();
+        assert!(other.read_value_from(state, 1).is_err());
+        assert!(other.push_value_to(state, &Value::Nil).is_err());
+        1
+    }
+
+    let func = unsafe { lua.create_c_function(c_function)? };
+    lua.globals().set("c_function", func)?;
+    let res = lua
+        .load("coroutine.wrap(function() return c_function({n = 21}) end)()")
+        .eval::<i64>()?;
+    assert_eq!(res, 42);
+
+    Ok(())
+}
+
 #[cfg(not(feature = "luau"))]
 #[test]
 fn test_dump() -> Result<()> {
@@ -231,6 +427,79 @@ fn test_function_info() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_function_named() -> Result<()> {
+    let lua = Lua::new();
+
+    let line = line!() + 1;
+    let get = lua.create_named_function("http.get", |_, url: StdString| match url.as_str() {
+        "" => Err(Error::runtime("empty url")),
+        url => Ok(url.len()),
+    })?;
+
+    let info = get.info();
+    assert_eq!(info.name.as_deref(), Some("http.get"));
+    assert_eq!(info.source.as_deref(), Some(concat!("=", file!())));
+    assert_eq!(info.short_src.as_deref(), Some(file!()));
+    assert_eq!(info.line_defined, Some(line as usize));
+    assert_eq!(info.what, "C");
+    assert_eq!(get.call::<_, usize>("a.b")?, 3);
+
+    // Errors raised by the function are reported with its name
+    match get.call::<_, ()>("") {
+        Err(Error::CallbackError { ref traceback, .. }) => {
+            assert!(traceback.contains("in function 'http.get'"), "{traceback}")
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    let err = get.call::<_, ()>(lua.create_table()?).unwrap_err();
+    assert!(
+        err.to_string().contains("bad argument #1 to `http.get`"),
+        "{err}"
+    );
+
+    #[cfg(feature = "luau")]
+    {
+        lua.globals().set("get", get)?;
+        let name: StdString = lua.load("debug.info(get, 'n')").eval()?;
+        assert_eq!(name, "http.get");
+    }
+
+    // The standard `debug` library reports the name too
+    #[cfg(not(feature = "luau"))]
+    {
+        let lua = unsafe { Lua::unsafe_new() };
+        let get = lua.create_named_function("http.get", |lua, ()| {
+            let chunk = lua.load("return debug.traceback('msg', 2), debug.getinfo(2, 'nS')");
+            chunk.eval::<(StdString, Table)>()
+        })?;
+        lua.globals().set("get", get)?;
+        let (traceback, info) = lua
+            .load("local f = get; return f()")
+            .eval::<(StdString, Table)>()?;
+        assert!(
+            traceback.starts_with("msg\nstack traceback:"),
+            "{traceback}"
+        );
+        let frame = traceback.lines().nth(2).unwrap();
+        assert!(frame.contains("in function 'http.get'"), "{traceback}");
+        assert_eq!(info.get::<_, StdString>("name")?, "http.get");
+        assert_eq!(info.get::<_, StdString>("short_src")?, file!());
+        assert_eq!(info.get::<_, Value>("func")?, Value::Nil);
+
+        // Levels are not shifted by the wrapped `getinfo`
+        let line: i64 = lua
+            .load("\nreturn debug.getinfo(1, 'l').currentline")
+            .eval()?;
+        assert_eq!(line, 2);
+    }
+
+    // Names must not contain nul bytes
+    assert!(lua.create_named_function("a\0b", |_, ()| Ok(())).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_function_pointer() -> Result<()> {
     let lua = Lua::new();
@@ -338,3 +607,433 @@ fn test_owned_function_drop() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_function_args() -> Result<()> {
+    let lua = Lua::new();
+
+    let area = lua.create_named_function("area", |_, mut args: Args| {
+        assert_eq!(args.name(), Some("area"));
+        let shape: std::string::String = args.get_one_of(&["square", "rect"])?;
+        let width: f64 = args.get_in_range(0.0, 100.0)?;
+        let height = match shape.as_str() {
+            "rect" => args.get_checked(|h: &f64| match *h > 0.0 {
+                true => Ok(()),
+                false => Err("height must be positive".into()),
+            })?,
+            _ => width,
+        };
+        Ok(width * height)
+    })?;
+    lua.globals().set("area", area)?;
+
+    assert_eq!(lua.load("area('square', 3)").eval::<f64>()?, 9.0);
+    assert_eq!(lua.load("area('rect', 2, 4)").eval::<f64>()?, 8.0);
+
+    let check_err = |code: &str, expected: &str| {
+        let err = lua.load(code).exec().unwrap_err();
+        match err {
+            Error::CallbackError { ref cause, .. } => match cause.as_ref() {
+                Error::BadArgument { to, .. } => assert_eq!(to.as_deref(), Some("area")),
+                cause => panic!("expected BadArgument, got {cause:?}"),
+            },
+            ref err => panic!("expected CallbackError, got {err:?}"),
+        }
+        assert!(err.to_string().contains(expected), "{err}");
+    };
+    check_err(
+        "area('rect', 'wide')",
+        "bad argument #2 to `area`: number expected, got string",
+    );
+    check_err(
+        "area(true)",
+        "bad argument #1 to `area`: string expected, got boolean",
+    );
+    check_err(
+        "area('circle', 1)",
+        "bad argument #1 to `area`: invalid option 'circle' (expected one of: square, rect)",
+    );
+    check_err(
+        "area('square', 101)",
+        "bad argument #2 to `area`: value 101 is out of range [0, 100]",
+    );
+    check_err(
+        "area('rect', 1, 0)",
+        "bad argument #3 to `area`: height must be positive",
+    );
+
+    // Remaining arguments and userdata method names
+    struct Counter;
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("sum", |_, _, args: Args| {
+                assert_eq!(args.position(), 2);
+                Ok(args.rest::<i64>()?.into_iter().sum::<i64>())
+            });
+        }
+    }
+    lua.globals().set("counter", Counter)?;
+    assert_eq!(lua.load("counter:sum(1, 2, 3)").eval::<i64>()?, 6);
+    let err = lua.load("counter:sum(1, {})").exec().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("bad argument #3 to `Counter.sum`: number expected, got table"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_function_bad_argument_details() -> Result<()> {
+    let lua = Lua::new();
+
+    let repeat = lua.create_function(|_, (s, n): (StdString, usize)| Ok(s.repeat(n)))?;
+    lua.globals().set("repeat_str", repeat)?;
+
+    let check_err = |code: &str, pos: usize, expected: &str, received: &str, preview: &str| {
+        let err = lua.load(code).exec().unwrap_err();
+        match err {
+            Error::CallbackError { ref cause, .. } => match cause.as_ref() {
+                Error::BadArgument { pos: p, .. } => {
+                    let details = cause.argument_details().unwrap();
+                    assert_eq!(*p, pos);
+                    assert_eq!(details.expected, expected);
+                    assert_eq!(details.received, received);
+                    assert!(details.preview.starts_with(preview), "{details:?}");
+                }
+                cause => panic!("expected BadArgument, got {cause:?}"),
+            },
+            ref err => panic!("expected CallbackError, got {err:?}"),
+        }
+        let msg = format!("bad argument #{pos} to `repeat_str`");
+        assert!(err.to_string().contains(&msg), "{err}");
+        assert!(
+            err.to_string().contains(&format!("(value: {preview}")),
+            "{err}"
+        );
+    };
+
+    check_err("repeat_str('ab', 'many')", 2, "usize", "string", "\"many\"");
+    check_err("repeat_str({}, 1)", 1, "String", "table", "table: 0x");
+    check_err("repeat_str('ab')", 2, "usize", "nil", "nil");
+
+    // Long values are truncated
+    let long = "x".repeat(100);
+    let err = lua
+        .load(format!("repeat_str('ab', '{long}')"))
+        .exec()
+        .unwrap_err();
+    let details = err.argument_details().unwrap();
+    assert_eq!(details.preview, format!("\"{}...", "x".repeat(39)));
+
+    Ok(())
+}
+
+#[test]
+fn test_function_error_names() -> Result<()> {
+    let lua = Lua::new();
+
+    let add = lua.create_function(|_, (a, b): (i64, i64)| Ok(a + b))?;
+    let sub = lua.create_named_function("sub", |_, (a, b): (i64, i64)| Ok(a - b))?;
+    lua.globals().set("add", add.clone())?;
+    lua.globals().set("sub", sub)?;
+
+    let check_err = |err: Error, expected_name: &str| match err {
+        Error::CallbackError {
+            ref traceback,
+            ref cause,
+        } => {
+            match cause.as_ref() {
+                Error::BadArgument { to, pos, .. } => {
+                    assert_eq!(to.as_deref(), Some(expected_name));
+                    assert_eq!(*pos, 2);
+                }
+                cause => panic!("expected BadArgument, got {cause:?}"),
+            }
+            let frame = traceback.lines().nth(1).unwrap();
+            assert!(frame.contains(expected_name), "{traceback}");
+        }
+        err => panic!("expected CallbackError, got {err:?}"),
+    };
+
+    // Names are taken from the call site or the globals
+    check_err(lua.load("add(1, 'x')").exec().unwrap_err(), "add");
+    check_err(
+        lua.load("error(select(2, pcall(add, 1, 'x')))")
+            .exec()
+            .unwrap_err(),
+        "add",
+    );
+    check_err(add.call::<_, ()>((1, "x")).unwrap_err(), "add");
+
+    // Explicit names are preserved and used for anonymous frames
+    check_err(
+        lua.load("local f = {sub}; f[1](1, 'x')")
+            .exec()
+            .unwrap_err(),
+        "sub",
+    );
+
+    // Registered names are used for every error kind
+    let fail = lua.create_named_function("fail", |_, ()| -> Result<()> {
+        Err(Error::runtime("boom"))
+    })?;
+    lua.globals().set("t", [fail])?;
+    match lua.load("t[1]()").exec().unwrap_err() {
+        Error::CallbackError { ref traceback, .. } => {
+            let frame = traceback.lines().nth(1).unwrap();
+            assert!(frame.contains("function 'fail'"), "{traceback}");
+        }
+        err => panic!("expected CallbackError, got {err:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_module_derive() -> Result<()> {
+    use mlua::LuaModule;
+
+    #[derive(LuaModule)]
+    struct HttpApi {
+        get: fn(&Lua, std::string::String) -> Result<std::string::String>,
+        #[lua(rename = "post")]
+        post_data: fn(&Lua, (std::string::String, Table)) -> Result<usize>,
+        #[lua(module)]
+        util: HttpUtil,
+    }
+
+    #[derive(LuaModule)]
+    struct HttpUtil {
+        escape: fn(&Lua, std::string::String) -> Result<std::string::String>,
+    }
+
+    let lua = Lua::new();
+    let api = HttpApi {
+        get: |_, url| Ok(format!("GET {url}")),
+        post_data: |_, (_, data)| Ok(data.raw_len()),
+        util: HttpUtil {
+            escape: |_, s| Ok(s.replace(' ', "%20")),
+        },
+    };
+    let http = lua.register_module("http", api)?;
+    assert!(http.contains_key("post")? && !http.contains_key("post_data")?);
+
+    lua.load(
+        r#"
+        assert(http.get("/") == "GET /")
+        assert(http.post("/", {1, 2, 3}) == 3)
+        assert(http.util.escape("a b") == "a%20b")
+    "#,
+    )
+    .exec()?;
+
+    // Functions are named after their path in the module
+    match lua.load("local f = http.util.escape; f({})").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { to, .. } => assert_eq!(to.as_deref(), Some("http.util.escape")),
+            cause => panic!("expected BadArgument, got {cause:?}"),
+        },
+        res => panic!("expected CallbackError, got {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_callback_middleware() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let lua = Lua::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let calls2 = calls.clone();
+    lua.set_callback_middleware(move |_, ctx, next| {
+        let name = ctx.name().unwrap_or_default();
+        calls2
+            .lock()
+            .unwrap()
+            .push(format!("{name}/{}", ctx.nargs()));
+        if name == "forbidden" {
+            return Err(Error::runtime("access denied"));
+        }
+        next.call()
+    });
+
+    let sum = lua.create_function(|_, args: mlua::Variadic<i64>| Ok(args.iter().sum::<i64>()))?;
+    let forbidden = lua.create_function(|_, ()| -> Result<()> { panic!("must not be called") })?;
+    lua.globals().set("sum", sum)?;
+    lua.globals().set("forbidden", forbidden)?;
+
+    struct Counter;
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, _, ()| Ok(1));
+        }
+    }
+    lua.globals().set("counter", Counter)?;
+
+    assert_eq!(lua.load("return sum(1, 2, 3)").eval::<i64>()?, 6);
+    assert_eq!(lua.load("return counter:get()").eval::<i64>()?, 1);
+    let err = lua.load("forbidden()").exec().unwrap_err();
+    assert!(err.to_string().contains("access denied"), "{err}");
+
+    // Methods are named after the type and the name they are registered with
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec!["sum/3", "Counter.get/1", "forbidden/0"]
+    );
+
+    // Skipping the callback returns nothing
+    lua.set_callback_middleware(|_, _, _| Ok(()));
+    assert_eq!(lua.load("return select('#', sum(1, 2))").eval::<i64>()?, 0);
+
+    lua.remove_callback_middleware();
+    assert_eq!(lua.load("return sum(1, 2)").eval::<i64>()?, 3);
+    assert_eq!(calls.lock().unwrap().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_conversion_error_hook() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let lua = Lua::new();
+    let failures = Arc::new(Mutex::new(Vec::new()));
+
+    let failures2 = failures.clone();
+    lua.on_conversion_error(move |info| failures2.lock().unwrap().push(info.clone()));
+
+    let scale = lua.create_function(|_, (x, factor): (f64, i64)| Ok(x * factor as f64))?;
+    let config = lua.create_function(|_, table: Table| table.get::<_, i64>("size"))?;
+    let fail = lua.create_function(|_, ()| Err::<(), _>(Error::runtime("not a conversion")))?;
+    lua.globals().set("scale", scale)?;
+    lua.globals().set("config", config)?;
+    lua.globals().set("fail", fail)?;
+
+    struct Counter;
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("add", |_, _, n: i64| Ok(n));
+        }
+    }
+    lua.globals().set("counter", Counter)?;
+
+    assert!(lua.load("scale(1.5, 'x')").exec().is_err());
+    assert!(lua.load("config({ size = 'big' })").exec().is_err());
+    assert!(lua.load("counter.add()").exec().is_err());
+    assert!(lua.load("counter:add({})").exec().is_err());
+    assert!(lua.load("fail()").exec().is_err());
+    // Reported once, by the failed callback
+    assert!(lua.load("pcall(scale, 1)").exec().is_ok());
+    let nested = lua.create_function(|lua, ()| lua.load("scale(true)").exec())?;
+    assert!(nested.call::<_, ()>(()).is_err());
+
+    let failures = failures.lock().unwrap();
+    let summary = (failures.iter())
+        .map(|info| {
+            let callback = info.callback.as_deref().unwrap_or("?");
+            format!("{callback}#{:?} {}->{}", info.argument, info.from, info.to)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            "scale#Some(2) string->i64",
+            "config#None string->i64",
+            "Counter.add#Some(1) missing argument->userdata",
+            "Counter.add#Some(2) table->i64",
+            "scale#Some(2) nil->i64",
+            "scale#Some(1) boolean->f64",
+        ]
+    );
+    assert_eq!(failures[2].argument_name.as_deref(), Some("self"));
+
+    Ok(())
+}
+
+#[test]
+fn test_remote_function() -> Result<()> {
+    let lua = Lua::new();
+    let greet = lua
+        .load(
+            r#"function(name) if name == "" then error("empty name") end return "hi " .. name end"#,
+        )
+        .eval::<Function>()?
+        .into_remote()?;
+
+    let workers = ["alice", "bob", ""].map(|name| {
+        let greet = greet.clone();
+        thread::spawn(move || greet.call::<_, StdString>(name.to_string()))
+    });
+    while workers.iter().any(|worker| !worker.is_finished()) {
+        lua.run_remote_calls();
+    }
+    let results = workers.map(|worker| worker.join().unwrap());
+    assert_eq!(results[0].as_ref().unwrap(), "hi alice");
+    assert_eq!(results[1].as_ref().unwrap(), "hi bob");
+    match &results[2] {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("empty name")),
+        r => panic!("expected runtime error, got {r:?}"),
+    }
+    assert_eq!(lua.run_remote_calls(), 0);
+
+    // Calls fail once the Lua instance is dropped
+    drop(lua);
+    let err = greet.call::<_, StdString>("carol").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "runtime error: Lua instance has been dropped"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_registered_functions() -> Result<()> {
+    let lua = Lua::new();
+    // Functions created by mlua itself (eg. `os.exit`) are anonymous
+    let builtin = lua.registered_functions()?;
+    assert!(builtin.iter().all(|f| f.name.is_none()));
+    let named = |lua: &Lua| -> Result<Vec<_>> {
+        let mut functions = lua.registered_functions()?;
+        functions.retain(|f| f.name.is_some());
+        Ok(functions)
+    };
+
+    let anonymous = lua.create_function(|_, (_, _): (i64, i64)| Ok(()))?;
+    let log = lua.create_named_function("log", |_, _: Variadic<StdString>| Ok(()))?;
+    let greet = lua.create_named_function("greet", |_, _: StdString| Ok(()))?;
+    let greet2 =
+        lua.create_named_function("greet", |_, (_, _): (StdString, Option<u32>)| Ok(()))?;
+    let functions = lua.registered_functions()?;
+    assert_eq!(functions.len(), builtin.len() + 4);
+    let is_anonymous = |f: &&RegisteredFunction| f.name.is_none() && f.nargs == Some(2);
+    assert_eq!(
+        functions.iter().filter(is_anonymous).count(),
+        builtin.iter().filter(is_anonymous).count() + 1
+    );
+    let functions = named(&lua)?;
+    assert_eq!(functions.len(), 3);
+    assert_eq!(functions[0].name.as_deref(), Some("greet"));
+    assert_eq!(functions[1].name.as_deref(), Some("greet"));
+    let mut nargs = [functions[0].nargs, functions[1].nargs];
+    nargs.sort();
+    assert_eq!(nargs, [Some(1), Some(2)]);
+    assert_eq!(functions[2].name.as_deref(), Some("log"));
+    assert_eq!(functions[2].nargs, None);
+
+    // Collected functions are forgotten
+    drop((anonymous, greet, greet2));
+    lua.gc_collect()?;
+    assert_eq!(lua.registered_functions()?.len(), builtin.len() + 1);
+    let functions = named(&lua)?;
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].name.as_deref(), Some("log"));
+    drop(log);
+
+    Ok(())
+}
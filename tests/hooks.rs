@@ -4,7 +4,7 @@ use std::ops::Deref;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use mlua::{DebugEvent, Error, HookTriggers, Lua, Result, Value};
+use mlua::{DebugEvent, Error, Function, HookTriggers, Lua, Result, Value};
 
 #[test]
 fn test_hook_triggers() {
@@ -263,3 +263,200 @@ fn test_hook_threads() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_call_with_hook() -> Result<()> {
+    let lua = Lua::new();
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    let func = lua
+        .load(
+            r#"
+            function(n)
+                local co = coroutine.create(function()
+                    coroutine.yield()
+                    return n * 2
+                end)
+                coroutine.resume(co)
+                return co
+            end
+        "#,
+        )
+        .eval::<mlua::Function>()?;
+
+    let scoped = Arc::new(AtomicI64::new(0));
+    let global = Arc::new(AtomicI64::new(0));
+
+    let global2 = global.clone();
+    lua.set_hook(HookTriggers::new().every_nth_instruction(1), move |_, _| {
+        global2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+
+    let scoped2 = scoped.clone();
+    let hook = move |_: &Lua, _: mlua::Debug| {
+        scoped2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    };
+    let co: mlua::Thread =
+        func.call_with_hook(HookTriggers::new().every_nth_instruction(1), hook, 21)?;
+    let scoped_count = scoped.load(Ordering::Relaxed);
+    assert!(scoped_count > 0);
+    assert_eq!(global.load(Ordering::Relaxed), 0);
+
+    // The scoped hook is not triggered in the coroutine created during the call anymore
+    assert_eq!(co.resume::<_, i64>(())?, 42);
+    assert_eq!(scoped.load(Ordering::Relaxed), scoped_count);
+
+    // The previous hook is restored
+    lua.load("local x = 1 + 1").exec()?;
+    assert!(global.load(Ordering::Relaxed) > 0);
+
+    lua.remove_hook();
+    let global_count = global.load(Ordering::Relaxed);
+    lua.load("local x = 1 + 1").exec()?;
+    assert_eq!(global.load(Ordering::Relaxed), global_count);
+    assert_eq!(scoped.load(Ordering::Relaxed), scoped_count);
+
+    // Coroutines resumed after the call do not disturb the hook set on the main thread afterwards
+    let scoped2 = scoped.clone();
+    let hook = move |_: &Lua, _: mlua::Debug| {
+        scoped2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    };
+    let co: mlua::Thread = func.call_with_hook(HookTriggers::EVERY_LINE, hook, 1)?;
+    let scoped_count = scoped.load(Ordering::Relaxed);
+    let global2 = global.clone();
+    lua.set_hook(HookTriggers::new().every_nth_instruction(1), move |_, _| {
+        global2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+    assert_eq!(co.resume::<_, i64>(())?, 2);
+    assert_eq!(scoped.load(Ordering::Relaxed), scoped_count);
+    let global_count = global.load(Ordering::Relaxed);
+    lua.load("local x = 1 + 1").exec()?;
+    assert!(global.load(Ordering::Relaxed) > global_count);
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_named_function() -> Result<()> {
+    let lua = Lua::new();
+
+    let line = line!() as usize + 1;
+    let get = lua.create_named_function("http.get", |_, ()| Ok(()))?;
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let hook_frames = frames.clone();
+    lua.set_hook(HookTriggers::ON_CALLS, move |_, debug| {
+        let source = debug.source();
+        if source.what == "C" {
+            let name = debug.names().name.map(|name| name.into_owned());
+            let short_src = source.short_src.map(|src| src.into_owned());
+            (hook_frames.lock().unwrap()).push((name, short_src, source.line_defined));
+        }
+        Ok(())
+    });
+    lua.load("local f = ...; f()").call::<_, ()>(get.clone())?;
+    lua.remove_hook();
+
+    let frame = (Some("http.get".into()), Some(file!().into()), Some(line));
+    assert!(frames.lock().unwrap().contains(&frame));
+
+    // Function stats report the display name and the location
+    lua.enable_function_stats();
+    lua.load("local f = ...; f(); f()").call::<_, ()>(get)?;
+    let stats = lua.function_stats();
+    let get = stats.iter().find(|s| s.name == "http.get").unwrap();
+    assert_eq!(get.calls, 2);
+    assert_eq!(get.source, Some(format!("{}:{line}", file!())));
+
+    Ok(())
+}
+
+#[test]
+fn test_function_stats() -> Result<()> {
+    let lua = Lua::new();
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    // The hook set before enabling the stats is still called
+    let calls = Arc::new(AtomicI64::new(0));
+    let hook_calls = calls.clone();
+    lua.set_hook(HookTriggers::ON_CALLS, move |_, _| {
+        hook_calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+
+    lua.enable_function_stats();
+    let sleep = lua.create_function(|_, ms: u64| {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+    lua.load(
+        r#"
+        local function fast() end
+        local function slow()
+            sleep(20)
+            sleep(20)
+        end
+        local function fail()
+            fast()
+            error("boom")
+        end
+        for i = 1, 3 do fast() end
+        slow()
+        assert(not pcall(fail))
+    "#,
+    )
+    .set_name("=stats")
+    .exec()?;
+
+    let stats = lua.function_stats();
+    let get = |name: &str| stats.iter().find(|s| s.name == name).unwrap();
+    assert_eq!(stats[0].name, "sleep");
+    assert_eq!(get("sleep").calls, 2);
+    assert_eq!(get("sleep").source, None);
+    assert!(get("sleep").self_time >= std::time::Duration::from_millis(40));
+    assert!(get("slow").self_time < get("sleep").self_time);
+    assert_eq!(get("fast").calls, 4);
+    assert_eq!(get("fast").source.as_deref(), Some("stats:2"));
+    // Functions called by `pcall` have no name
+    let fail = stats
+        .iter()
+        .find(|s| s.source.as_deref() == Some("stats:7"));
+    assert_eq!(fail.unwrap().name, "function <stats:7>");
+    assert_eq!(get("main chunk").calls, 1);
+    assert!(calls.load(Ordering::Relaxed) > 0);
+
+    // Aliases of a function share the same entry
+    lua.reset_function_stats();
+    let sleep: Function = lua.globals().get("sleep")?;
+    lua.globals().set("nap", sleep)?;
+    lua.load("sleep(0) nap(0)").exec()?;
+    let stats = lua.function_stats();
+    assert_eq!(stats.iter().map(|s| s.calls).sum::<u64>(), 3);
+    assert_eq!(stats.iter().find(|s| s.name == "sleep").unwrap().calls, 2);
+
+    lua.disable_function_stats();
+    assert!(lua.function_stats().is_empty());
+    let calls_count = calls.load(Ordering::Relaxed);
+    lua.load("sleep(0)").exec()?;
+    assert!(calls.load(Ordering::Relaxed) > calls_count);
+
+    // A hook set during the collection is kept when it's disabled
+    lua.enable_function_stats();
+    let new_calls = Arc::new(AtomicI64::new(0));
+    let hook_calls = new_calls.clone();
+    lua.set_hook(HookTriggers::ON_CALLS, move |_, _| {
+        hook_calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+    lua.disable_function_stats();
+    lua.load("sleep(0)").exec()?;
+    assert!(new_calls.load(Ordering::Relaxed) > 0);
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 #![cfg(feature = "luau")]
 
 use std::fmt::Debug;
+#[cfg(feature = "filesystem")]
 use std::fs;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -22,6 +23,7 @@ fn test_version() -> Result<()> {
 }
 
 #[test]
+#[cfg(feature = "filesystem")]
 fn test_require() -> Result<()> {
     // Ensure that require() is not available if package module is not loaded
     let mut lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
@@ -87,6 +89,44 @@ fn test_require() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "filesystem")]
+fn test_module_graph() -> Result<()> {
+    if cfg!(target_arch = "wasm32") {
+        return Ok(());
+    }
+
+    let lua = Lua::new();
+    let temp_dir = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_dir.path().join("a.luau"),
+        "local b = require('b'); return require('c')",
+    )?;
+    fs::write(temp_dir.path().join("b.luau"), "return require('c')")?;
+    fs::write(temp_dir.path().join("c.luau"), "return 1")?;
+    lua.loaders()?
+        .set_path([temp_dir.path().join("?.luau").to_string_lossy()])?;
+
+    lua.load("require('a')").set_name("main").exec()?;
+    lua.load("require('c')").set_name("other").exec()?;
+
+    let graph = lua.module_graph();
+    assert_eq!(graph.dependencies("main"), ["a"]);
+    assert_eq!(graph.dependencies("a"), ["b", "c"]);
+    assert_eq!(graph.dependencies("b"), ["c"]);
+    assert!(graph.dependencies("c").is_empty());
+    assert_eq!(graph.dependents("c"), ["a", "b", "other", "main"]);
+    assert_eq!(graph.dependents("b"), ["a", "main"]);
+    assert!(graph.dependents("main").is_empty());
+
+    let path = temp_dir.path().join("b.luau");
+    assert_eq!(graph.path("b"), Some(path.as_path()));
+    assert_eq!(graph.module_at(&path), Some("b"));
+    assert_eq!(graph.module_at(temp_dir.path().join("d.luau")), None);
+
+    Ok(())
+}
+
 #[cfg(not(feature = "luau-vector4"))]
 #[test]
 fn test_vectors() -> Result<()> {
@@ -125,6 +165,44 @@ fn test_vectors() -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(feature = "luau-vector4"))]
+#[test]
+fn test_vector_library() -> Result<()> {
+    let lua = Lua::new();
+    lua.register_vector_library()?;
+
+    lua.load(
+        r#"
+        local v = vector.create(3, 4, 0)
+        assert(v == vector(3, 4, 0))
+        assert(vector.magnitude(v) == 5)
+        assert(vector.normalize(v) == vector.create(0.6, 0.8, 0))
+        assert(vector.dot(v, vector.one) == 7)
+        assert(vector.cross(vector.create(1, 0, 0), vector.create(0, 1, 0)) == vector.create(0, 0, 1))
+        assert(math.abs(vector.angle(vector.create(1, 0, 0), vector.create(0, 1, 0)) - math.pi / 2) < 1e-6)
+        assert(vector.angle(vector.create(1, 0, 0), vector.create(0, 1, 0), vector.create(0, 0, -1)) < 0)
+        assert(vector.floor(vector.create(1.5, -1.5, 0)) == vector.create(1, -2, 0))
+        assert(vector.ceil(vector.create(1.5, -1.5, 0)) == vector.create(2, -1, 0))
+        assert(vector.abs(vector.create(-1, 2, -3)) == vector.create(1, 2, 3))
+        assert(vector.sign(vector.create(-5, 0, 5)) == vector.create(-1, 0, 1))
+        assert(vector.clamp(vector.create(-5, 0.5, 5), vector.zero, vector.one) == vector.create(0, 0.5, 1))
+        assert(vector.max(vector.create(1, 5, 3), vector.create(4, 2, 6), vector.zero) == vector.create(4, 5, 6))
+        assert(vector.min(vector.create(1, 5, 3), vector.create(4, 2, 6)) == vector.create(1, 2, 3))
+    "#,
+    )
+    .exec()?;
+
+    let err = lua
+        .load("vector.clamp(vector.zero, vector.one, vector.zero)")
+        .exec()
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("max.x must be greater than or equal to min.x"));
+
+    Ok(())
+}
+
 #[cfg(feature = "luau-vector4")]
 #[test]
 fn test_vectors() -> Result<()> {
@@ -386,6 +464,58 @@ fn test_interrupts() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_memory_categories() -> Result<()> {
+    let lua = Lua::new();
+
+    let before = lua.memory_by_category();
+    assert_eq!(before.len(), 1);
+    assert!(before["main"] > 0);
+
+    lua.with_memory_category("ui", || {
+        lua.load("widgets = table.create(10000, 0)").exec()?;
+        // Nested categories are restored
+        lua.with_memory_category("game", || lua.load("entities = {}").exec())?;
+        lua.load("labels = table.create(10000, 0)").exec()
+    })?;
+    lua.load("other = {}").exec()?;
+
+    let usage = lua.memory_by_category();
+    assert!(usage["ui"] >= 2 * 10000 * 16);
+    assert!(usage["game"] > 0 && usage["game"] < 10000);
+    assert!(usage["main"] < before["main"] + 10000);
+
+    // Freed memory is deducted from the category
+    lua.load("widgets = nil; labels = nil").exec()?;
+    lua.gc_collect()?;
+    assert!(lua.memory_by_category()["ui"] < 10000);
+
+    // The number of categories is limited
+    for i in 0..253 {
+        lua.with_memory_category(&format!("cat{i}"), || Ok(()))?;
+    }
+    assert!(lua.with_memory_category("overflow", || Ok(())).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_monotonic_time() -> Result<()> {
+    let lua = Lua::new();
+
+    let t0 = lua.monotonic_time();
+    let script_time: f64 = lua.load("os.clock()").eval()?;
+    let t1 = lua.monotonic_time();
+    assert!(t0 <= script_time && script_time <= t1);
+
+    lua.load("os.clock = function() return 0 end").exec()?;
+    lua.override_os_clock()?;
+    let script_time: f64 = lua.load("os.clock()").eval()?;
+    assert!(script_time >= t1 && script_time <= lua.monotonic_time());
+
+    Ok(())
+}
+
 #[test]
 fn test_coverage() -> Result<()> {
     let lua = Lua::new();
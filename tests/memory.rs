@@ -99,6 +99,110 @@ fn test_gc_control() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_gc_step_budget() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let lua = Lua::new();
+    lua.gc_collect()?;
+
+    let steps = Arc::new(AtomicUsize::new(0));
+    let cycles = Arc::new(AtomicUsize::new(0));
+    let (steps2, cycles2) = (steps.clone(), cycles.clone());
+    lua.gc_set_incremental_callback(move |_, finished| {
+        steps2.fetch_add(1, Ordering::Relaxed);
+        if finished {
+            cycles2.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    });
+
+    struct MyUserdata(#[allow(unused)] Arc<()>);
+    impl UserData for MyUserdata {}
+
+    let rc = Arc::new(());
+    lua.globals()
+        .set("userdata", lua.create_userdata(MyUserdata(rc.clone()))?)?;
+    lua.globals().raw_remove("userdata")?;
+
+    // Garbage is collected only by budgeted steps
+    let garbage = lua
+        .load("local t = {} for i = 1, 1000 do t[i] = {} end")
+        .into_function()?;
+    for _ in 0..10 {
+        garbage.call::<_, ()>(())?;
+    }
+    assert_eq!(Arc::strong_count(&rc), 2);
+
+    let mut i = 0;
+    while Arc::strong_count(&rc) > 1 || cycles.load(Ordering::Relaxed) == 0 {
+        lua.gc_step_budget(8)?;
+        #[cfg(any(
+            feature = "lua54",
+            feature = "lua53",
+            feature = "lua52",
+            feature = "luau"
+        ))]
+        assert!(!lua.gc_is_running());
+        i += 1;
+        assert!(i < 10000, "collection cycle was not finished");
+    }
+    assert_eq!(steps.load(Ordering::Relaxed), i);
+
+    // Callback errors are propagated
+    lua.gc_set_incremental_callback(|_, _| Err(Error::runtime("budget exceeded")));
+    assert!(lua.gc_step_budget(0).is_err());
+
+    lua.gc_remove_incremental_callback();
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    assert!(lua.gc_is_running());
+    assert!(lua.gc_step_budget(0).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_callback() -> Result<()> {
+    use mlua::GcEvent;
+    use std::sync::Mutex;
+
+    let lua = Lua::new();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events2 = events.clone();
+    lua.on_gc(move |event| events2.lock().unwrap().push(event))?;
+
+    lua.load("local t = {} for i = 1, 1000 do t[i] = {} end")
+        .exec()?;
+    lua.gc_collect()?;
+    // Luau starts tracking the next cycle when Lua code is run
+    lua.load("for i = 1, 10 do end").exec()?;
+    lua.gc_collect()?;
+    {
+        let events = events.lock().unwrap();
+        assert!(events.len() >= 2);
+        match events[0] {
+            GcEvent::CycleEnd { freed_bytes, .. } => assert!(freed_bytes > 0),
+            event => panic!("expected CycleEnd event, got {event:?}"),
+        }
+        assert_eq!(events[1], GcEvent::CycleStart);
+    }
+
+    lua.remove_gc_callback();
+    lua.gc_collect()?;
+    let count = events.lock().unwrap().len();
+    lua.load("for i = 1, 10 do end").exec()?;
+    lua.gc_collect()?;
+    assert_eq!(events.lock().unwrap().len(), count);
+
+    Ok(())
+}
+
 #[cfg(any(feature = "lua53", feature = "lua52"))]
 #[test]
 fn test_gc_error() {
@@ -126,3 +230,105 @@ fn test_gc_error() {
         Ok(()) => panic!("__gc error did not result in error"),
     }
 }
+
+#[test]
+fn test_dedup_strings() -> Result<()> {
+    let lua = Lua::new();
+    let data = lua
+        .load(
+            r#"
+            local rows = {}
+            for i = 1, 1000 do
+                local row = { id = i, tags = { string.rep("tag", 20) } }
+                row.name = string.rep("x", 100) .. (i % 2)
+                row.self = row
+                rows[i] = row
+            end
+            rows.short = string.rep("s", 10)
+            return rows
+        "#,
+        )
+        .eval::<mlua::Table>()?;
+
+    lua.gc_collect()?;
+    let used_memory = lua.used_memory();
+    let saved = lua.dedup_strings(&data)?;
+    lua.gc_collect()?;
+
+    if cfg!(any(feature = "lua54", feature = "lua53", feature = "lua52")) {
+        // Two distinct names of 101 bytes and one tag of 60 bytes are kept
+        assert_eq!(saved, 998 * 101 + 999 * 60);
+        assert!(lua.used_memory() < used_memory - saved);
+    } else {
+        assert_eq!(saved, 0);
+    }
+
+    // The values are unchanged
+    lua.load(
+        r#"
+        for i, row in ipairs(...) do
+            assert(row.name == string.rep("x", 100) .. (i % 2))
+            assert(row.tags[1] == string.rep("tag", 20))
+        end
+    "#,
+    )
+    .call(data.clone())?;
+    assert_eq!(lua.dedup_strings(&data)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_memory_pressure() -> Result<()> {
+    let lua = Lua::new();
+    let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    const MB: usize = 1024 * 1024;
+    let used_memory = lua.used_memory();
+    for (name, threshold) in [("high", used_memory + 16 * MB), ("low", used_memory + MB)] {
+        let calls = calls.clone();
+        let result = lua.on_memory_pressure(threshold, move |_, used_memory| {
+            assert!(used_memory >= threshold);
+            calls.lock().unwrap().push(name);
+            Ok(())
+        });
+        if cfg!(feature = "luajit") && matches!(result, Err(Error::MemoryLimitNotAvailable)) {
+            // seems this luajit version does not support memory tracking
+            return Ok(());
+        }
+        result?;
+    }
+    lua.globals()
+        .set("noop", lua.create_function(|_, ()| Ok(()))?)?;
+    let take_calls = || std::mem::take(&mut *calls.lock().unwrap());
+
+    // Called at the next Rust function call
+    lua.load("data = string.rep('x', 2 * 1024 * 1024)").exec()?;
+    assert!(take_calls().is_empty());
+    lua.load("noop()").exec()?;
+    assert_eq!(take_calls(), ["low"]);
+
+    // Not called again until memory usage goes below the threshold
+    lua.load("noop()").exec()?;
+    assert!(take_calls().is_empty());
+
+    // Called in ascending threshold order
+    lua.load("data = nil; collectgarbage()").exec()?;
+    lua.load("data = string.rep('x', 24 * 1024 * 1024)")
+        .exec()?;
+    lua.check_memory_pressure()?;
+    assert_eq!(take_calls(), ["low", "high"]);
+
+    // Errors are propagated
+    lua.remove_memory_pressure_callbacks();
+    lua.load("data = nil; collectgarbage()").exec()?;
+    lua.on_memory_pressure(lua.used_memory() + MB, |_, _| {
+        Err(Error::runtime("out of cache"))
+    })?;
+    let result = lua
+        .load("data = string.rep('x', 2 * 1024 * 1024); noop()")
+        .exec();
+    assert!(matches!(result, Err(Error::CallbackError { .. })));
+
+    Ok(())
+}
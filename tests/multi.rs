@@ -58,3 +58,151 @@ fn test_result_conversions() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_wide_tuples() -> Result<()> {
+    let lua = Lua::new();
+
+    #[rustfmt::skip]
+    type Wide = (
+        i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32,
+        i32, i32,
+    );
+
+    let sum = lua.create_function(|_, args: Wide| {
+        let (a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p, q, r, s, t) = args;
+        let values = [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p, q, r, s, t];
+        Ok(values.iter().sum::<i32>())
+    })?;
+    let total: i32 = sum.call((
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ))?;
+    assert_eq!(total, 210);
+
+    let values: Wide = lua
+        .load("return 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20")
+        .eval()?;
+    assert_eq!((values.0, values.16, values.19), (1, 17, 20));
+
+    // Nesting in the last position extends the arity further
+    let (a, (b, rest)): (i32, (i32, mlua::Variadic<i32>)) = lua.load("return 1, 2, 3, 4").eval()?;
+    assert_eq!((a, b, rest.len()), (1, 2, 2));
+
+    Ok(())
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_multi_derive() -> Result<()> {
+    let lua = Lua::new();
+
+    #[derive(mlua::IntoLuaMulti, mlua::FromLuaMulti)]
+    struct Status {
+        ok: bool,
+        value: Option<i64>,
+        err: Option<std::string::String>,
+    }
+
+    #[derive(mlua::IntoLuaMulti, mlua::FromLuaMulti)]
+    struct Pair(i64, std::string::String);
+
+    let check = lua.create_function(|_, n: i64| {
+        Ok(match n {
+            n if n >= 0 => Status {
+                ok: true,
+                value: Some(n * 2),
+                err: None,
+            },
+            _ => Status {
+                ok: false,
+                value: None,
+                err: Some("negative".into()),
+            },
+        })
+    })?;
+    lua.globals().set("check", check)?;
+    lua.load(
+        r#"
+        local ok, value, err = check(21)
+        assert(ok == true and value == 42 and err == nil)
+        ok, value, err = check(-1)
+        assert(ok == false and value == nil and err == "negative")
+        assert(select('#', check(1)) == 3)
+    "#,
+    )
+    .exec()?;
+
+    let status: Status = lua.load("return false, nil, 'oops'").eval()?;
+    assert!(!status.ok);
+    assert_eq!(status.value, None);
+    assert_eq!(status.err.as_deref(), Some("oops"));
+
+    // Missing values are `nil`
+    let status: Status = lua.load("return true, 1").eval()?;
+    assert!(status.ok && status.value == Some(1) && status.err.is_none());
+
+    let Pair(n, s) = lua.load("return 1, 'a', 'extra'").eval()?;
+    assert_eq!((n, s.as_str()), (1, "a"));
+    let values = Pair(2, "b".into()).into_lua_multi(&lua)?;
+    assert_eq!(values.len(), 2);
+
+    // Generic types holding Lua values
+    #[derive(mlua::IntoLuaMulti, mlua::FromLuaMulti)]
+    struct Tagged<'lua, T> {
+        tag: mlua::Table<'lua>,
+        value: T,
+    }
+
+    let tagged: Tagged<i64> = lua.load("return {name = 'x'}, 5").eval()?;
+    assert_eq!(tagged.tag.get::<_, std::string::String>("name")?, "x");
+    assert_eq!(tagged.value, 5);
+    assert_eq!(tagged.into_lua_multi(&lua)?.len(), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_multi_derive_defaults() -> Result<()> {
+    let lua = Lua::new();
+
+    #[derive(mlua::FromLuaMulti)]
+    struct Reply {
+        ok: bool,
+        #[lua(default)]
+        count: i64,
+        #[lua(default = "default_label")]
+        label: std::string::String,
+        #[lua(rest)]
+        extra: mlua::Variadic<i64>,
+    }
+
+    fn default_label() -> std::string::String {
+        "none".into()
+    }
+
+    #[derive(Debug, PartialEq, mlua::FromLuaMulti)]
+    #[lua(default)]
+    struct Version(u32, u32, u32);
+
+    let reply: Reply = lua.load("return true").eval()?;
+    assert!(reply.ok);
+    assert_eq!((reply.count, reply.label.as_str()), (0, "none"));
+    assert!(reply.extra.is_empty());
+
+    let reply: Reply = lua.load("return false, 5, 'five', 1, 2, 3").eval()?;
+    assert!(!reply.ok);
+    assert_eq!((reply.count, reply.label.as_str()), (5, "five"));
+    assert_eq!(*reply.extra, vec![1, 2, 3]);
+
+    // Explicit `nil` is not a missing value
+    assert!(lua.load("return true, nil").eval::<Reply>().is_err());
+
+    assert_eq!(lua.load("return 1").eval::<Version>()?, Version(1, 0, 0));
+    assert_eq!(
+        lua.load("return 1, 2, 3, 4").eval::<Version>()?,
+        Version(1, 2, 3)
+    );
+
+    Ok(())
+}
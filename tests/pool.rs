@@ -0,0 +1,228 @@
+use mlua::{LuaPool, Result, Table};
+
+#[test]
+fn test_pool_sanitize() -> Result<()> {
+    let pool = LuaPool::new(1, |lua| {
+        lua.globals().set("counter", 0)?;
+        lua.load("function inc() counter = counter + 1; return counter end").exec()
+    })?;
+    assert_eq!(pool.idle_count(), 1);
+
+    {
+        let lua = pool.get()?;
+        assert_eq!(pool.idle_count(), 0);
+        assert!(pool.try_get().is_none());
+        lua.load(
+            r#"
+            assert(inc() == 1)
+            leaked = true
+            string.format = nil
+            setmetatable(_G, {__index = function() return 1 end})
+        "#,
+        )
+        .exec()?;
+    }
+    assert_eq!(pool.idle_count(), 1);
+
+    let lua = pool.try_get().unwrap();
+    lua.load(
+        r#"
+        assert(leaked == nil)
+        assert(counter == 0 and inc() == 1)
+        assert(type(string.format) == "function")
+        assert(getmetatable(_G) == nil)
+    "#,
+    )
+    .exec()?;
+    assert!(lua.globals().get::<_, Table>("string").is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_pool_sanitize_registry() -> Result<()> {
+    let pool = LuaPool::new(1, |lua| {
+        lua.set_named_registry_value("config", 1)?;
+        lua.set_app_data(1u8);
+        Ok(())
+    })?;
+
+    {
+        let lua = pool.get()?;
+        lua.set_named_registry_value("config", 2)?;
+        lua.set_named_registry_value("leaked", true)?;
+        lua.set_app_data(2u8);
+        lua.set_app_data("leaked");
+        #[cfg(not(feature = "luau"))]
+        lua.load("package.loaded.mymod = {}").exec()?;
+    }
+
+    let lua = pool.get()?;
+    assert_eq!(lua.named_registry_value::<i32>("config")?, 1);
+    assert_eq!(lua.named_registry_value::<Option<bool>>("leaked")?, None);
+    // Data of types set during initialization is kept as is
+    assert_eq!(*lua.app_data_ref::<u8>().unwrap(), 2);
+    assert!(lua.app_data_ref::<&str>().is_none());
+    #[cfg(not(feature = "luau"))]
+    assert!(lua.load("package.loaded.mymod == nil").eval::<bool>()?);
+
+    Ok(())
+}
+
+#[cfg(feature = "luau")]
+#[test]
+fn test_pool_reinit_error() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let inits = AtomicUsize::new(0);
+    let pool = LuaPool::new(1, move |lua| {
+        match inits.fetch_add(1, Ordering::Relaxed) {
+            0 => Ok(()),
+            _ => lua.load("error('init failed')").exec(),
+        }
+    })?;
+
+    {
+        // Read-only tables cannot be restored, so the state is replaced
+        let lua = pool.get()?;
+        lua.globals().get::<_, Table>("string")?.set_readonly(true);
+    }
+    assert_eq!(pool.idle_count(), 0);
+    match pool.get() {
+        Err(err) => assert!(err.to_string().contains("init failed")),
+        Ok(_) => panic!("expected error"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pool_init_error() {
+    match LuaPool::new(2, |lua| lua.load("error('init failed')").exec()) {
+        Err(err) => assert!(err.to_string().contains("init failed")),
+        Ok(_) => panic!("expected error"),
+    }
+}
+
+#[cfg(feature = "send")]
+#[test]
+fn test_pool_threads() -> Result<()> {
+    use std::thread;
+
+    let pool = LuaPool::new(2, |lua| lua.globals().set("base", 10))?;
+    let handles = (0..8)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || -> Result<i64> {
+                let lua = pool.get()?;
+                lua.globals().set("x", i)?;
+                lua.load("base + x").eval()
+            })
+        })
+        .collect::<Vec<_>>();
+    let sum: i64 = handles.into_iter().map(|h| h.join().unwrap().unwrap()).sum();
+    assert_eq!(sum, 8 * 10 + 28);
+    assert_eq!(pool.idle_count(), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "send")]
+#[test]
+fn test_pool_par_execute() -> Result<()> {
+    use mlua::{Error, Quota};
+
+    let pool = LuaPool::new(3, |lua| {
+        // Compiled code does not trigger hooks used to enforce quotas
+        #[cfg(feature = "luajit")]
+        lua.load("jit.off()").exec()?;
+        lua.globals().set("factor", 3)
+    })?;
+    let results = pool.par_execute::<_, _, i64>("return ... * factor", 0..1000);
+    assert_eq!(results.len(), 1000);
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result?, i as i64 * 3);
+    }
+    assert_eq!(pool.idle_count(), 3);
+
+    // Errors are reported per task
+    let results = pool.par_execute::<_, _, i64>("local x = ... assert(x ~= 2) return x", 0..4);
+    assert!(results[2].is_err());
+    assert_eq!(results[3].as_ref().ok(), Some(&3));
+
+    // Compilation errors are reported for every task
+    let results = pool.par_execute::<_, _, i64>("return +", 0..4);
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|r| matches!(r, Err(Error::SyntaxError { .. }))));
+
+    // Every task has its own budget
+    let quota = Quota::new().max_instructions(100_000);
+    let chunk = "local n = ... for i = 1, n do end return n";
+    let results =
+        pool.par_execute_with_quota::<_, _, i64>(chunk, [10, 100_000_000, 10, 10], Some(quota));
+    assert_eq!(results[0].as_ref().ok(), Some(&10));
+    let err = results[1].as_ref().unwrap_err().to_string();
+    assert!(err.contains("instructions quota of 100000 exceeded"));
+    assert_eq!(results[3].as_ref().ok(), Some(&10));
+
+    // Quotas set by the init function are restored
+    let init_quota = Quota::new();
+    let pool = LuaPool::new(1, {
+        let init_quota = init_quota.clone();
+        move |lua| {
+            lua.set_quota(init_quota.clone());
+            Ok(())
+        }
+    })?;
+    let results = pool.par_execute_with_quota::<_, _, ()>("", [()], Some(Quota::new()));
+    assert!(results[0].is_ok());
+    let lua = pool.get()?;
+    lua.create_function(|_, ()| Ok(()))?.call::<_, ()>(())?;
+    assert_eq!(init_quota.calls(), 1);
+    drop(lua);
+
+    #[cfg(feature = "serialize")]
+    {
+        let inputs = vec![vec!["a", "b"], vec!["c"]];
+        let results = pool.par_execute_serde::<_, _, usize>("return #(...)", inputs, None);
+        assert_eq!(results.into_iter().collect::<Result<Vec<_>>>()?, [2, 1]);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_pool_async() -> Result<()> {
+    let pool = LuaPool::new(1, |_| Ok(()))?;
+    let lua = pool.get_async().await?;
+
+    let waiter = async {
+        let lua = pool.get_async().await?;
+        lua.load("return 42").eval::<i32>()
+    };
+    let release = async move {
+        tokio::task::yield_now().await;
+        drop(lua);
+    };
+    let (res, _) = futures::join!(waiter, release);
+    assert_eq!(res?, 42);
+
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_pool_par_execute_async() -> Result<()> {
+    let pool = LuaPool::new(2, |lua| lua.globals().set("factor", 2))?;
+    let results = pool
+        .par_execute_async::<_, _, i64>("return ... * factor", 0..100, None)
+        .await?;
+    assert_eq!(results.len(), 100);
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result?, i as i64 * 2);
+    }
+    assert_eq!(pool.idle_count(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+#![cfg(all(feature = "process", unix))]
+
+use std::time::{Duration, Instant};
+
+use mlua::{Error, Lua, ProcessPolicy, Result, Table};
+
+#[test]
+fn test_process_run() -> Result<()> {
+    let lua = Lua::new();
+
+    let policy = ProcessPolicy::new()
+        .allow_program("/bin/sh")
+        .max_output_size(8)
+        .arg_validator(|_, args| match args.iter().any(|arg| arg.contains("rm ")) {
+            true => Err(Error::RuntimeError("forbidden argument".into())),
+            false => Ok(()),
+        });
+    lua.globals()
+        .set("process", lua.create_process_module(policy)?)?;
+
+    let res: Table = lua
+        .load(r#"process.run("/bin/sh", {"-c", "echo hello; echo oops >&2; exit 3"})"#)
+        .eval()?;
+    assert_eq!(res.get::<_, i32>("status")?, 3);
+    assert!(!res.get::<_, bool>("success")?);
+    assert_eq!(res.get::<_, String>("stdout")?, "hello\n");
+    assert_eq!(res.get::<_, String>("stderr")?, "oops\n");
+    assert!(!res.get::<_, bool>("truncated")?);
+
+    // Output is capped and input is passed
+    let res: Table = lua
+        .load(r#"process.run("/bin/sh", {"-c", "cat"}, {stdin = "0123456789"})"#)
+        .eval()?;
+    assert!(res.get::<_, bool>("success")?);
+    assert_eq!(res.get::<_, String>("stdout")?, "01234567");
+    assert!(res.get::<_, bool>("truncated")?);
+
+    // Policy violations
+    let err = lua.load(r#"process.run("/bin/ls")"#).exec().unwrap_err();
+    assert!(err.to_string().contains("program '/bin/ls' is not allowed"));
+    let err = lua
+        .load(r#"process.run("/bin/sh", {"-c", "rm -rf /tmp/x"})"#)
+        .exec()
+        .unwrap_err();
+    assert!(err.to_string().contains("forbidden argument"));
+
+    Ok(())
+}
+
+#[test]
+fn test_process_timeout() -> Result<()> {
+    let lua = Lua::new();
+
+    let policy = ProcessPolicy::new()
+        .allow_program("/bin/sh")
+        .timeout(Some(Duration::from_millis(100)));
+    lua.globals()
+        .set("process", lua.create_process_module(policy)?)?;
+
+    let start = Instant::now();
+    let res: Table = lua
+        .load(r#"process.run("/bin/sh", {"-c", "exec sleep 5"})"#)
+        .eval()?;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(res.get::<_, bool>("timed_out")?);
+    assert!(!res.get::<_, bool>("success")?);
+    assert_eq!(res.get::<_, Option<i32>>("status")?, None);
+
+    // Descendants of the program are killed too
+    let start = Instant::now();
+    let res: Table = lua
+        .load(r#"process.run("/bin/sh", {"-c", "sleep 5; echo done"})"#)
+        .eval()?;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(res.get::<_, bool>("timed_out")?);
+
+    // Output streams kept open by descendants are not waited for
+    let lua = Lua::new();
+    let policy = ProcessPolicy::new().allow_program("/bin/sh");
+    lua.globals()
+        .set("process", lua.create_process_module(policy)?)?;
+    let start = Instant::now();
+    let res: Table = lua
+        .load(r#"process.run("/bin/sh", {"-c", "echo started; sleep 5 &"}, {stdin = "input"})"#)
+        .eval()?;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(res.get::<_, bool>("success")?);
+    assert!(res.get::<_, bool>("truncated")?);
+    assert_eq!(res.get::<_, String>("stdout")?, "started\n");
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use mlua::{Error, Function, Lua, Quota, QuotaResource, Result};
+
+fn exceeded(err: &Error) -> Option<(QuotaResource, u64)> {
+    match err {
+        Error::QuotaExceeded { resource, limit } => Some((*resource, *limit)),
+        Error::CallbackError { cause, .. } => exceeded(cause),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_quota_calls() -> Result<()> {
+    let lua = Lua::new();
+    let quota = Quota::new().max_calls(3);
+    lua.set_quota(quota.clone());
+
+    let f = lua.create_function(|_, ()| Ok(()))?;
+    lua.globals().set("f", f)?;
+
+    let err = lua.load("for i = 1, 5 do f() end").exec().unwrap_err();
+    assert_eq!(exceeded(&err), Some((QuotaResource::Calls, 3)));
+    assert_eq!(quota.calls(), 4);
+
+    quota.reset();
+    lua.load("f(); f(); f()").exec()?;
+    assert_eq!(quota.calls(), 3);
+
+    lua.remove_quota();
+    lua.load("for i = 1, 5 do f() end").exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_quota_instructions() -> Result<()> {
+    let lua = Lua::new();
+    // For LuaJIT disable JIT, as compiled code does not trigger hooks
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+    let quota = Quota::new().max_instructions(10_000);
+    lua.set_quota(quota.clone());
+
+    let err = lua.load("while true do end").exec().unwrap_err();
+    assert_eq!(exceeded(&err), Some((QuotaResource::Instructions, 10_000)));
+    assert!(err
+        .to_string()
+        .contains("instructions quota of 10000 exceeded"));
+
+    lua.remove_quota();
+    lua.load("for i = 1, 100000 do end").exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_quota_memory() -> Result<()> {
+    let lua = Lua::new();
+    // For LuaJIT disable JIT, as compiled code does not trigger hooks
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+    let limit = lua.used_memory() + 100_000;
+    lua.set_quota(Quota::new().max_memory(limit));
+
+    let err = lua
+        .load("local t = {} while true do table.insert(t, tostring(#t)) end")
+        .exec()
+        .unwrap_err();
+    assert_eq!(exceeded(&err), Some((QuotaResource::Memory, limit as u64)));
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_quota() -> Result<()> {
+    let lua = Lua::new();
+    // For LuaJIT disable JIT, as compiled code does not trigger hooks
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    let func: Function = lua
+        .load("while true do coroutine.yield() end")
+        .into_function()?;
+    let thread = lua.create_thread(func)?;
+    thread.set_quota(Quota::new().max_instructions(5_000))?;
+
+    // Code outside of the thread is not limited
+    lua.load("for i = 1, 100000 do end").exec()?;
+
+    let err = lua
+        .load(
+            r#"
+            local co = ...
+            while true do
+                coroutine.resume(co)
+                assert(coroutine.status(co) ~= "dead")
+            end
+        "#,
+        )
+        .call::<_, ()>(thread.clone())
+        .unwrap_err();
+    assert!(err.to_string().contains("assertion failed"), "{err}");
+
+    let err = thread.resume::<_, ()>(()).unwrap_err();
+    assert!(matches!(err, Error::CoroutineInactive), "{err}");
+
+    // A thread without quota is not limited
+    let func: Function = lua.load("for i = 1, 100000 do end").into_function()?;
+    let thread = lua.create_thread(func)?;
+    thread.set_quota(Quota::new().max_instructions(5_000))?;
+    thread.remove_quota()?;
+    thread.resume::<_, ()>(())?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_quota_keeps_hook() -> Result<()> {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use mlua::HookTriggers;
+
+    let lua = Lua::new();
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    let calls = Arc::new(AtomicU32::new(0));
+    let calls2 = calls.clone();
+    lua.set_hook(HookTriggers::ON_CALLS, move |_, _| {
+        calls2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+    lua.set_quota(Quota::new().max_instructions(100_000));
+
+    lua.load("local function f() end for i = 1, 10 do f() end")
+        .exec()?;
+    assert!(calls.load(Ordering::Relaxed) >= 10);
+
+    // The hook is restored after removing the quota
+    lua.remove_quota();
+    calls.store(0, Ordering::Relaxed);
+    lua.load("local function f() end for i = 1, 10 do f() end")
+        .exec()?;
+    assert!(calls.load(Ordering::Relaxed) >= 10);
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "regex")]
+
+use mlua::{Lua, Result};
+
+#[test]
+fn test_regex_module() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("re", lua.create_regex_module()?)?;
+
+    lua.load(
+        r#"
+        local word = re.compile([[\w+]])
+        assert(tostring(word) == [[\w+]])
+        assert(word:is_match("  hello") and not word:is_match("  !"))
+        assert(not word:is_match("hello", 6))
+
+        -- Positions are 1-based and inclusive, like `string.find`
+        local s, e = word:find("  hello world")
+        assert(s == 3 and e == 7)
+        s, e = word:find("  hello world", 8)
+        assert(s == 9 and e == 13)
+        assert(word:find("  hello", -2) == 6)
+        assert(word:find("!!!") == nil)
+        assert(word:match("  héllo!") == "héllo")
+
+        -- Captures
+        local kv = re.compile([[(?P<key>\w+)=(?P<value>\w*)(;)?]])
+        local caps = kv:captures("a=1;b=2")
+        assert(caps[0] == "a=1;" and caps[1] == "a" and caps[2] == "1" and caps[3] == ";")
+        assert(caps.key == "a" and caps.value == "1")
+        caps = kv:captures("x=")
+        assert(caps.value == "" and caps[3] == nil)
+        assert(kv:captures("nothing") == nil)
+
+        -- Iterators
+        local words = {}
+        for w in word:gmatch("one, two,three") do
+            table.insert(words, w)
+        end
+        assert(table.concat(words, "|") == "one|two|three")
+
+        local empty = {}
+        for m in re.compile("a*"):gmatch("baab") do
+            table.insert(empty, m)
+        end
+        assert(table.concat(empty, "|") == "|aa|")
+
+        local pairs = {}
+        for c in kv:gcaptures("a=1;b=2;c=3") do
+            pairs[c.key] = tonumber(c.value)
+        end
+        assert(pairs.a == 1 and pairs.b == 2 and pairs.c == 3)
+
+        -- Replace and split
+        assert(kv:replace("a=1;b=2", "$value:$key,") == "1:a,2:b,")
+        assert(word:replace("a b c", "x", 2) == "x x c")
+        local parts = re.compile([[\s*,\s*]]):split("a , b,c")
+        assert(#parts == 3 and parts[1] == "a" and parts[3] == "c")
+        assert(#re.compile(","):split("a,b,c", 2) == 2)
+
+        -- Options and escaping
+        assert(re.compile("hello", {case_insensitive = true}):is_match("HeLLo"))
+        assert(re.compile("^b$", {multi_line = true}):is_match("a\nb\nc"))
+        assert(re.compile(re.escape("1+1=2")):is_match("1+1=2"))
+        assert(not re.compile(re.escape("1+1=2")):is_match("11=2"))
+        "#,
+    )
+    .exec()?;
+
+    // Invalid patterns
+    let err = lua.load(r#"re.compile("(unclosed")"#).exec().unwrap_err();
+    assert!(err.to_string().contains("unclosed"), "{err}");
+
+    Ok(())
+}
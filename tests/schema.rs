@@ -0,0 +1,78 @@
+use mlua::{Lua, Result, Schema, SchemaField, SchemaType, Table};
+
+fn messages(schema: &Schema, table: &Table) -> Vec<String> {
+    match schema.validate(table) {
+        Ok(()) => Vec::new(),
+        Err(violations) => violations.iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_schema_validate() -> Result<()> {
+    let lua = Lua::new();
+
+    let schema = Schema::new()
+        .field(
+            "name",
+            SchemaField::new(SchemaType::String).required().length(1, 8),
+        )
+        .field(
+            "port",
+            SchemaField::new(SchemaType::Integer).range(1.0, 65535.0),
+        )
+        .field("ratio", SchemaField::new(SchemaType::Number))
+        .field(
+            "server",
+            SchemaField::table(
+                Schema::new()
+                    .field("host", SchemaField::new(SchemaType::String).required())
+                    .deny_unknown_keys(true),
+            ),
+        )
+        .field(
+            "tags",
+            SchemaField::array(SchemaField::new(SchemaType::String)).length(0, 2),
+        );
+
+    let valid: Table = lua
+        .load(r#"{name = "app", port = 80, ratio = 0.5, server = {host = "a"}, tags = {"x"}}"#)
+        .eval()?;
+    assert!(schema.validate(&valid).is_ok());
+    assert!(schema.validate(&lua.load("{name = 'app'}").eval()?).is_ok());
+
+    let invalid: Table = lua
+        .load(r#"{port = 1.5, ratio = "x", server = {extra = true}, tags = {"a", true, "c"}}"#)
+        .eval()?;
+    assert_eq!(
+        messages(&schema, &invalid),
+        vec![
+            "name: required value is missing",
+            "port: expected integer, got number",
+            "ratio: expected number, got string",
+            "server.host: required value is missing",
+            "server.extra: unknown key",
+            "tags: length 3 is out of range [0, 2]",
+            "tags[2]: expected string, got boolean",
+        ]
+    );
+
+    let invalid: Table = lua
+        .load(r#"{name = "too long name", port = 0, server = "x"}"#)
+        .eval()?;
+    assert_eq!(
+        messages(&schema, &invalid),
+        vec![
+            "name: length 13 is out of range [1, 8]",
+            "port: value 0 is out of range [1, 65535]",
+            "server: expected table, got string",
+        ]
+    );
+
+    // Unknown keys at the top level
+    let schema = Schema::new().deny_unknown_keys(true);
+    let violations = schema.validate(&lua.load("{a = 1}").eval()?).unwrap_err();
+    assert_eq!(violations[0].path, "a");
+    assert_eq!(violations[0].message, "unknown key");
+
+    Ok(())
+}
@@ -1,10 +1,11 @@
 use std::cell::Cell;
+use std::mem;
 use std::rc::Rc;
 use std::string::String as StdString;
 use std::sync::Arc;
 
 use mlua::{
-    AnyUserData, Error, Function, Lua, MetaMethod, Result, String, UserData, UserDataFields,
+    AnyUserData, Error, Function, Lua, MetaMethod, Result, String, Table, UserData, UserDataFields,
     UserDataMethods,
 };
 
@@ -499,3 +500,51 @@ fn modify_userdata(lua: &Lua, ud: AnyUserData) -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_autorelease_scope() -> Result<()> {
+    let lua = Lua::new();
+
+    let weak = lua.create_table()?;
+    weak.set_metatable(Some(lua.load("{__mode = 'v'}").eval()?));
+
+    let value: i32 = lua.autorelease_scope(|scope_lua| {
+        let t1 = scope_lua.create_table()?;
+        t1.set("value", 42)?;
+        weak.set("t1", &t1)?;
+        mem::forget(t1);
+
+        // Handles derived from the scope handles are released too
+        let t2: Table = scope_lua.load("{inner = {}}").eval()?;
+        let inner: Table = t2.get("inner")?;
+        weak.set("inner", &inner)?;
+        mem::forget(inner);
+        drop(t2);
+
+        // Nested scope
+        scope_lua.autorelease_scope(|scope_lua| {
+            let t3 = scope_lua.create_table()?;
+            weak.set("t3", &t3)?;
+            mem::forget(t3);
+            Ok(())
+        })?;
+        scope_lua.gc_collect()?;
+        assert!(weak.contains_key("t1")?);
+        assert!(!weak.contains_key("t3")?);
+
+        // Values created through another handle are not registered
+        let t4 = lua.create_table()?;
+        weak.set("t4", &t4)?;
+        mem::forget(t4);
+
+        weak.get::<_, Table>("t1")?.get("value")
+    })?;
+    assert_eq!(value, 42);
+
+    lua.gc_collect()?;
+    assert!(!weak.contains_key("t1")?);
+    assert!(!weak.contains_key("inner")?);
+    assert!(weak.contains_key("t4")?);
+
+    Ok(())
+}
@@ -4,8 +4,8 @@ use std::collections::HashMap;
 use std::error::Error as StdError;
 
 use mlua::{
-    DeserializeOptions, Error, ExternalResult, Lua, LuaSerdeExt, Result as LuaResult,
-    SerializeOptions, UserData, Value,
+    ConversionLimits, DeserializeOptions, Error, ExternalResult, Lua, LuaSerdeExt,
+    Result as LuaResult, SerializeOptions, UserData, Value,
 };
 use serde::{Deserialize, Serialize};
 
@@ -424,6 +424,63 @@ fn test_to_value_with_options() -> Result<(), Box<dyn StdError>> {
     )
     .exec()?;
 
+    // max_depth
+    let data = serde_json::json!({"a": {"b": [{}]}});
+    let options = SerializeOptions::new().max_depth(Some(3));
+    match lua.to_value_with(&data, options) {
+        Err(Error::TooDeep { max_depth: 3 }) => {}
+        res => panic!("expected TooDeep, got {res:?}"),
+    }
+    lua.to_value_with(&data["a"], options)?;
+    let options = SerializeOptions::new().max_depth(Some(4));
+    lua.to_value_with(&data, options)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_to_value_deterministic() -> Result<(), Box<dyn StdError>> {
+    use mlua::serde::ser::FloatPolicy;
+
+    let lua = Lua::new();
+
+    // sort_keys: tables built from maps are serialized in sorted key order
+    let map = (0..50)
+        .map(|i| (format!("key{i:02}"), i))
+        .collect::<HashMap<_, _>>();
+    let options = SerializeOptions::new().sort_keys(true);
+    let value = lua.to_value_with(&map, options)?;
+    let expected = (0..50).map(|i| format!(r#""key{i:02}":{i}"#));
+    let expected = format!("{{{}}}", expected.collect::<Vec<_>>().join(","));
+    assert_eq!(serde_json::to_string(&value)?, expected);
+    let json: serde_json::Value = lua.from_value(value)?;
+    assert_eq!(json.to_string(), expected);
+    lua.globals()
+        .set("sorted", lua.to_value_with(&map, options)?)?;
+    lua.load("assert(sorted.key07 == 7 and getmetatable(sorted) == false)")
+        .exec()?;
+
+    // float_policy
+    let floats = [-0.0f64, f64::NAN, 2.0, 2.5];
+    let options = SerializeOptions::new().float_policy(FloatPolicy::Canonical);
+    lua.globals()
+        .set("v", lua.to_value_with(&floats, options)?)?;
+    lua.load(
+        r#"
+        assert(1 / v[1] == math.huge)
+        assert(v[2] ~= v[2])
+        assert(v[3] == 2 and v[4] == 2.5)
+    "#,
+    )
+    .exec()?;
+    let options = SerializeOptions::new().float_policy(FloatPolicy::Integral);
+    let v = lua.to_value_with(&floats, options)?;
+    let v = v.as_table().unwrap();
+    assert_eq!(v.get::<_, Value>(1)?, Value::Integer(0));
+    assert!(matches!(v.get::<_, Value>(2)?, Value::Number(n) if n.is_nan()));
+    assert_eq!(v.get::<_, Value>(3)?, Value::Integer(2));
+    assert_eq!(v.get::<_, Value>(4)?, Value::Number(2.5));
+
     Ok(())
 }
 
@@ -652,6 +709,269 @@ fn test_from_value_with_options() -> Result<(), Box<dyn StdError>> {
     Ok(())
 }
 
+#[test]
+fn test_preserve_references() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+
+    let value = lua
+        .load(
+            r#"
+        local shared = {name = "shared"}
+        local state = {a = shared, b = {shared, shared}}
+        state.self = state
+        return state
+    "#,
+        )
+        .eval::<Value>()?;
+
+    // Without preserving references, recursive tables cannot be serialized
+    assert!(serde_json::to_value(&value).is_err());
+
+    let json = serde_json::to_value(value.to_serializable().preserve_references(true))?;
+    assert_eq!(json["$id"], 1);
+    assert_eq!(json["$value"]["self"], serde_json::json!({"$ref": 1}));
+    let shared_count = json.to_string().matches(r#""name":"shared""#).count();
+    assert_eq!(shared_count, 1);
+
+    let options = SerializeOptions::new()
+        .resolve_references(true)
+        .detect_serde_json_arbitrary_precision(true);
+    lua.globals()
+        .set("state", lua.to_value_with(&json, options)?)?;
+    lua.load(
+        r#"
+        assert(state.self == state)
+        assert(state.a.name == "shared")
+        assert(state.b[1] == state.a and state.b[2] == state.a)
+        assert(state["$id"] == nil and state["$value"] == nil)
+    "#,
+    )
+    .exec()?;
+
+    // User keys that look like tags are escaped
+    let value = lua
+        .load(r#"{ref = {["$ref"] = 1}, id = {["$id"] = 2, ["$value"] = {}}, ["$$x"] = true}"#)
+        .eval::<Value>()?;
+    let json = serde_json::to_value(value.to_serializable().preserve_references(true))?;
+    assert_eq!(json["ref"], serde_json::json!({"$$ref": 1}));
+    assert_eq!(json["$$$x"], true);
+    lua.globals()
+        .set("value", lua.to_value_with(&json, options)?)?;
+    lua.load(
+        r#"
+        assert(value.ref["$ref"] == 1 and value.ref["$$ref"] == nil)
+        assert(value.id["$id"] == 2 and type(value.id["$value"]) == "table")
+        assert(value["$$x"] == true)
+    "#,
+    )
+    .exec()?;
+
+    // Dangling references are reported
+    let json = serde_json::json!({"a": {"$ref": 7}});
+    match lua.to_value_with(&json, options) {
+        Err(Error::SerializeError(msg)) => assert!(msg.contains("unknown table id 7")),
+        res => panic!("expected SerializeError, got {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_mixed_tables() -> Result<(), Box<dyn StdError>> {
+    use mlua::serde::de::MixedTablePolicy;
+
+    let lua = Lua::new();
+    let holes = lua.load("{1, nil, 3}").eval::<Value>()?;
+    let mixed = lua.load("{1, 2, x = 3}").eval::<Value>()?;
+
+    // Truncate (default)
+    let v: Vec<Option<i32>> = lua.from_value(holes.clone())?;
+    assert_eq!(v[0], Some(1));
+    let v: serde_json::Value = lua.from_value(mixed.clone())?;
+    assert_eq!(v, serde_json::json!([1, 2]));
+
+    // JSON object keys must be strings
+    let options = DeserializeOptions::new().coerce_numeric_keys(true);
+
+    // FillHoles
+    let options = options.mixed_tables(MixedTablePolicy::FillHoles);
+    let v: Vec<Option<i32>> = lua.from_value_with(holes.clone(), options)?;
+    assert_eq!(v, vec![Some(1), None, Some(3)]);
+    let v: serde_json::Value = lua.from_value_with(holes.clone(), options)?;
+    assert_eq!(v, serde_json::json!([1, null, 3]));
+    let v: serde_json::Value = lua.from_value_with(mixed.clone(), options)?;
+    assert_eq!(v, serde_json::json!({"1": 1, "2": 2, "x": 3}));
+    assert!(lua
+        .from_value_with::<Vec<i32>>(mixed.clone(), options)
+        .is_err());
+    // Excessively sparse tables are not filled
+    let sparse = lua.load("{[1] = 1, [1000000000] = 2}").eval::<Value>()?;
+    let v: serde_json::Value = lua.from_value_with(sparse.clone(), options)?;
+    assert_eq!(v, serde_json::json!({"1": 1, "1000000000": 2}));
+    let err = lua
+        .from_value_with::<Vec<Option<i32>>>(sparse, options)
+        .unwrap_err();
+    assert!(err.to_string().contains("too sparse"), "{err}");
+
+    // Map
+    let options = options.mixed_tables(MixedTablePolicy::Map);
+    let v: serde_json::Value = lua.from_value_with(holes.clone(), options)?;
+    assert_eq!(v, serde_json::json!({"1": 1, "3": 3}));
+    let v: serde_json::Value = lua.from_value_with(lua.load("{1, 2}").eval()?, options)?;
+    assert_eq!(v, serde_json::json!([1, 2]));
+
+    // Error
+    let options = DeserializeOptions::new().mixed_tables(MixedTablePolicy::Error);
+    for value in [holes, mixed] {
+        match lua.from_value_with::<serde_json::Value>(value, options) {
+            Err(Error::DeserializeError(msg)) => assert!(msg.contains("not a proper sequence")),
+            res => panic!("expected DeserializeError, got {res:?}"),
+        }
+    }
+    let v: serde_json::Value = lua.from_value_with(lua.load("{x = 1}").eval()?, options)?;
+    assert_eq!(v, serde_json::json!({"x": 1}));
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_numeric_keys() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    let options = DeserializeOptions::new().coerce_numeric_keys(true);
+
+    let value = lua.load("{[1] = 'a', [2] = 'b'}").eval::<Value>()?;
+    assert!(lua
+        .from_value::<HashMap<String, String>>(value.clone())
+        .is_err());
+    let map: HashMap<String, String> = lua.from_value_with(value, options)?;
+    assert_eq!(map["1"], "a");
+    assert_eq!(map["2"], "b");
+
+    let value = lua
+        .load("{['10'] = true, ['-2'] = false}")
+        .eval::<Value>()?;
+    assert!(lua.from_value::<HashMap<i32, bool>>(value.clone()).is_err());
+    let map: HashMap<i32, bool> = lua.from_value_with(value, options)?;
+    assert!(map[&10] && !map[&-2]);
+
+    // Non-numeric strings are still rejected
+    let value = lua.load("{x = true}").eval::<Value>()?;
+    assert!(lua
+        .from_value_with::<HashMap<u8, bool>>(value, options)
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_type_hints() -> Result<(), Box<dyn StdError>> {
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point { x: i32, y: i32 },
+        Circle { r: f64 },
+    }
+
+    let lua = Lua::new();
+    let options = DeserializeOptions::new().type_hint_field(Some("__type"));
+
+    let value = lua
+        .load(
+            r#"
+            local function new(ty, t) return setmetatable(t, {__type = ty}) end
+            return {new("Point", {x = 1, y = 2}), new("Circle", {r = 0.5}), {Point = {x = 0, y = 0}}}
+        "#,
+        )
+        .eval::<Value>()?;
+    let shapes: Vec<Shape> = lua.from_value_with(value.clone(), options)?;
+    assert_eq!(
+        shapes,
+        vec![
+            Shape::Point { x: 1, y: 2 },
+            Shape::Circle { r: 0.5 },
+            Shape::Point { x: 0, y: 0 }
+        ]
+    );
+    // Without the option metatables are ignored
+    assert!(lua.from_value::<Vec<Shape>>(value.clone()).is_err());
+
+    // Self-describing formats see hinted tables as externally tagged
+    let v: serde_json::Value = lua.from_value_with(value, options.sort_keys(true))?;
+    assert_eq!(v[0], serde_json::json!({"Point": {"x": 1, "y": 2}}));
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_max_depth() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    let value = lua
+        .load(
+            r#"
+            local t = {}
+            for i = 1, 100000 do t = {t} end
+            return t
+        "#,
+        )
+        .eval::<Value>()?;
+
+    lua.set_conversion_limits(ConversionLimits::new().max_depth(Some(64)));
+    match lua.from_value::<serde_json::Value>(value) {
+        Err(Error::TooDeep { max_depth: 64 }) => {}
+        res => panic!("expected TooDeep, got {res:?}"),
+    }
+
+    let value = lua.load("{{{1}}}").eval::<Value>()?;
+    lua.set_conversion_limits(ConversionLimits::new().max_depth(Some(3)));
+    let v: Vec<Vec<Vec<i32>>> = lua.from_value(value.clone())?;
+    assert_eq!(v, [[[1]]]);
+    lua.set_conversion_limits(ConversionLimits::new().max_depth(Some(2)));
+    assert!(lua.from_value::<Vec<Vec<Vec<i32>>>>(value).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_borrowed() -> Result<(), Box<dyn StdError>> {
+    use mlua::serde::de::DeserializeScope;
+    use std::borrow::Cow;
+
+    let lua = Lua::new();
+
+    #[derive(Deserialize, Debug)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        kind: Cow<'a, str>,
+        tags: Vec<&'a str>,
+        data: &'a [u8],
+    }
+
+    let value = lua
+        .load(r#"{name = "mlua", kind = "crate", tags = {"lua", "serde"}, data = "\255\0"}"#)
+        .eval()?;
+    let scope = DeserializeScope::new();
+    let borrowed: Borrowed = scope.from_value(value)?;
+    // Strings are kept alive by the scope even after a full GC cycle
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(borrowed.name, "mlua");
+    assert!(matches!(borrowed.kind, Cow::Borrowed("crate")));
+    assert_eq!(borrowed.tags, ["lua", "serde"]);
+    assert_eq!(borrowed.data, b"\xff\x00");
+
+    // Map keys are borrowed too
+    let value = lua.load(r#"{a = "1", b = "2"}"#).eval()?;
+    let map: HashMap<&str, &str> = scope.from_value(value)?;
+    assert_eq!(map["a"], "1");
+    assert_eq!(map["b"], "2");
+
+    // Non-string values cannot be borrowed
+    let value = lua.load("{1, 2}").eval()?;
+    assert!(scope.from_value::<Vec<&str>>(value).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_from_value_userdata() -> Result<(), Box<dyn StdError>> {
     let lua = Lua::new();
@@ -711,6 +1031,133 @@ fn test_from_value_userdata() -> Result<(), Box<dyn StdError>> {
     Ok(())
 }
 
+#[test]
+fn test_userdata_factories() -> Result<(), Box<dyn StdError>> {
+    use mlua::UserDataRef;
+
+    #[derive(Serialize)]
+    struct Vector {
+        x: f64,
+        y: f64,
+    }
+
+    impl UserData for Vector {}
+
+    #[derive(Serialize)]
+    struct Name(String);
+
+    impl UserData for Name {}
+
+    let lua = Lua::new();
+    let state = lua.create_table()?;
+    state.set("pos", lua.create_ser_userdata(Vector { x: 1.0, y: 2.0 })?)?;
+    state.set("name", lua.create_ser_userdata(Name("hero".into()))?)?;
+    state.set("hp", 100)?;
+    let state = Value::Table(state);
+
+    let json = serde_json::to_value(state.to_serializable().tag_userdata(true))?;
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "pos": {"$userdata": "Vector", "$value": {"x": 1.0, "y": 2.0}},
+            "name": {"$userdata": "Name", "$value": "hero"},
+            "hp": 100,
+        })
+    );
+
+    lua.register_userdata_factory("Vector", |lua, value| {
+        let t = value.as_table().unwrap();
+        lua.create_ser_userdata(Vector {
+            x: t.get("x")?,
+            y: t.get("y")?,
+        })
+    });
+    lua.register_userdata_factory("Name", |lua, value| {
+        lua.create_ser_userdata(Name(lua.unpack(value)?))
+    });
+
+    let options = SerializeOptions::new()
+        .resolve_userdata(true)
+        .detect_serde_json_arbitrary_precision(true);
+    let state = lua.to_value_with(&json, options)?;
+    let state = state.as_table().unwrap();
+    let pos: UserDataRef<Vector> = state.get("pos")?;
+    assert_eq!((pos.x, pos.y), (1.0, 2.0));
+    let name: UserDataRef<Name> = state.get("name")?;
+    assert_eq!(name.0, "hero");
+    assert_eq!(state.get::<_, i64>("hp")?, 100);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_serialize() -> Result<(), Box<dyn StdError>> {
+    use mlua::{IntoLua, UserDataRef, UserDataSerialize};
+
+    // A handle to an object stored on the host side, serialized as its id
+    struct Entity(u32);
+
+    impl UserData for Entity {}
+
+    impl UserDataSerialize for Entity {
+        fn type_name() -> &'static str {
+            "Entity"
+        }
+
+        fn to_value<'lua>(&self, lua: &'lua Lua) -> LuaResult<Value<'lua>> {
+            self.0.into_lua(lua)
+        }
+
+        fn from_value<'lua>(_: &'lua Lua, value: Value<'lua>) -> LuaResult<Self> {
+            let id = value
+                .as_u32()
+                .ok_or_else(|| Error::runtime("invalid entity id"))?;
+            Ok(Entity(id))
+        }
+    }
+
+    let lua = Lua::new();
+    let value = Value::Table(lua.create_table()?);
+    value.as_table().unwrap().set("player", Entity(7))?;
+
+    // Not registered userdata cannot be serialized
+    assert!(serde_json::to_value(&value).is_err());
+
+    lua.register_userdata_serialize::<Entity>();
+    assert_eq!(
+        serde_json::to_value(&value)?,
+        serde_json::json!({"player": 7})
+    );
+
+    #[derive(Deserialize)]
+    struct State {
+        player: u32,
+    }
+    let state: State = lua.from_value(value.clone())?;
+    assert_eq!(state.player, 7);
+
+    // Round trip with tags
+    let json = serde_json::to_value(value.to_serializable().tag_userdata(true))?;
+    assert_eq!(
+        json,
+        serde_json::json!({"player": {"$userdata": "Entity", "$value": 7}})
+    );
+    let options = SerializeOptions::new()
+        .resolve_userdata(true)
+        .detect_serde_json_arbitrary_precision(true);
+    let value = lua.to_value_with(&json, options)?;
+    let player: UserDataRef<Entity> = value.as_table().unwrap().get("player")?;
+    assert_eq!(player.0, 7);
+
+    let json = serde_json::json!({"$userdata": "Unknown", "$value": 1});
+    match lua.to_value_with(&json, options) {
+        Err(Error::SerializeError(msg)) => assert!(msg.contains("'Unknown' is not registered")),
+        res => panic!("expected SerializeError, got {res:?}"),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_from_value_sorted() -> Result<(), Box<dyn StdError>> {
     let lua = Lua::new();
@@ -791,3 +1238,212 @@ fn test_buffer_from_value() {
         .unwrap();
     assert_eq!(val, serde_value::Value::Bytes(vec![1, 2, 3, 4]));
 }
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json_module() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    lua.globals().set("json", lua.create_json_module()?)?;
+
+    lua.load(
+        r#"
+        local s = json.encode({a = 1, b = {1.5, "x", true, json.null}, c = {}}, {sort_keys = true})
+        assert(s == '{"a":1,"b":[1.5,"x",true,null],"c":{}}', s)
+        assert(json.encode(setmetatable({}, json.array_mt)) == "[]")
+        assert(json.encode({1, 2}, {pretty = true}) == "[\n  1,\n  2\n]")
+        assert(json.encode(nil) == "null" and json.encode("\"") == [["\""]])
+
+        local v = json.decode('{"n": 3, "f": 2.5, "list": [1, null, 3], "empty": [], "z": null}')
+        assert(v.n == 3 and v.f == 2.5 and v.z == json.null)
+        assert(#v.list == 3 and v.list[2] == json.null)
+        -- Decoded arrays are encoded back as arrays
+        assert(json.encode(v.empty) == "[]")
+
+        v = json.decode('{"list": [1, null], "z": null}', {null_as_nil = true})
+        assert(v.list[1] == 1 and v.list[2] == nil and v.z == nil)
+        "#,
+    )
+    .exec()?;
+
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    lua.load(
+        r#"
+        assert(math.type(json.decode("3")) == "integer")
+        assert(math.type(json.decode("3", {preserve_integers = false})) == "float")
+        assert(math.type(json.decode('[9007199254740993]')[1]) == "integer")
+        assert(json.encode(3) == "3" and json.encode(3.0) == "3.0")
+        "#,
+    )
+    .exec()?;
+
+    // Errors
+    let err = lua.load("json.decode('{')").exec().unwrap_err();
+    assert!(err.to_string().contains("EOF"), "{err}");
+    let err = lua.load("json.encode({f = print})").exec().unwrap_err();
+    assert!(
+        err.to_string().contains("cannot serialize <function>"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn test_msgpack() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+
+    let value = lua
+        .load(r#"{name = "mlua", bytes = "\xff\x00", list = {1, 2.5, true, "x"}, empty = {}}"#)
+        .eval::<Value>()?;
+    let bytes = lua.to_msgpack(&value)?;
+    let decoded = lua.from_msgpack(&bytes)?;
+    lua.globals().set("v", decoded)?;
+    lua.load(
+        r#"
+        assert(v.name == "mlua" and v.bytes == "\xff\x00")
+        assert(#v.list == 4 and v.list[2] == 2.5 and v.list[3] == true and v.list[4] == "x")
+        assert(getmetatable(v.list) ~= nil and next(v.empty) == nil)
+        "#,
+    )
+    .exec()?;
+
+    // Known encoding
+    assert_eq!(lua.to_msgpack(&Value::Boolean(true))?, [0xc3]);
+    assert_eq!(lua.to_msgpack(&Value::NULL)?, [0xc0]);
+    let seq = lua.from_msgpack(&[0x93, 0x01, 0x02, 0x03])?;
+    assert_eq!(lua.from_value::<Vec<i32>>(seq)?, [1, 2, 3]);
+    assert!(lua.from_msgpack(&[0xc1]).is_err());
+
+    // Script-facing module
+    lua.globals().set("msgpack", lua.create_msgpack_module()?)?;
+    lua.load(
+        r#"
+        local data = msgpack.encode({a = {1, 2}, b = msgpack.null})
+        local v = msgpack.decode(data)
+        assert(v.a[1] == 1 and v.a[2] == 2 and v.b == msgpack.null)
+        assert(msgpack.decode(msgpack.encode(nil)) == msgpack.null)
+        "#,
+    )
+    .exec()?;
+    let err = lua.load("msgpack.encode(print)").exec().unwrap_err();
+    assert!(
+        err.to_string().contains("cannot serialize <function>"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[cfg(all(feature = "json", feature = "msgpack"))]
+#[test]
+fn test_serialize_to_writer() -> Result<(), Box<dyn StdError>> {
+    use mlua::SerializeFormat;
+
+    let lua = Lua::new();
+    let value = lua
+        .load(
+            r#"
+        local items = {}
+        for i = 1, 1000 do
+            items[i] = {id = i, name = "item" .. i}
+        end
+        return {items = items}
+    "#,
+        )
+        .eval::<Value>()?;
+
+    let mut output = Vec::new();
+    lua.serialize_to_writer(&value, &mut output, SerializeFormat::Json)?;
+    assert_eq!(output, serde_json::to_vec(&value)?);
+
+    let mut output = Vec::new();
+    lua.serialize_to_writer(&value, &mut output, SerializeFormat::JsonPretty)?;
+    assert_eq!(output, serde_json::to_vec_pretty(&value)?);
+
+    let mut output = Vec::new();
+    lua.serialize_to_writer(&value, &mut output, SerializeFormat::MessagePack)?;
+    let decoded = lua.from_msgpack(&output)?;
+    lua.globals().set("decoded", decoded)?;
+    lua.load(r#"assert(#decoded.items == 1000 and decoded.items[500].name == "item500")"#)
+        .exec()?;
+
+    // Write errors are reported
+    struct FailingWriter;
+
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    match lua.serialize_to_writer(&value, &mut FailingWriter, SerializeFormat::Json) {
+        Err(Error::SerializeError(msg)) => assert!(msg.contains("disk full")),
+        res => panic!("expected SerializeError, got {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_conversion_limits() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    lua.set_conversion_limits(
+        ConversionLimits::new()
+            .max_depth(Some(8))
+            .max_elements(Some(100)),
+    );
+
+    let value = lua
+        .load(
+            r#"
+            local t = {}
+            for i = 1, 100000 do t = {t} end
+            return t
+        "#,
+        )
+        .eval::<Value>()?;
+    match lua.from_value::<serde_json::Value>(value.clone()) {
+        Err(Error::TooDeep { max_depth: 8 }) => {}
+        res => panic!("expected TooDeep, got {res:?}"),
+    }
+    let err = serde_json::to_string(&value).unwrap_err();
+    assert!(err.to_string().contains("maximum depth of 8"), "{err}");
+    match lua.to_value(&value.to_serializable()) {
+        Err(Error::TooDeep { max_depth: 8 }) => {}
+        res => panic!("expected TooDeep, got {res:?}"),
+    }
+
+    let value = lua.load("{a = {1, 2, 3}, b = {x = 1}}").eval::<Value>()?;
+    lua.from_value::<serde_json::Value>(value)?;
+    let value = lua
+        .load("local t = {} for i = 1, 1000 do t[i] = i end return t")
+        .eval::<Value>()?;
+    match lua.from_value::<Vec<i32>>(value.clone()) {
+        Err(Error::TooLarge { max_elements: 100 }) => {}
+        res => panic!("expected TooLarge, got {res:?}"),
+    }
+    assert!(serde_json::to_string(&value).is_err());
+    match lua.to_value(&value.to_serializable()) {
+        Err(Error::TooLarge { max_elements: 100 }) => {}
+        res => panic!("expected TooLarge, got {res:?}"),
+    }
+
+    // Serialization to Lua
+    let data = serde_json::json!({"a": {"b": {"c": {"d": {"e": {"f": {"g": {"h": {}}}}}}}}});
+    match lua.to_value(&data) {
+        Err(Error::TooDeep { max_depth: 8 }) => {}
+        res => panic!("expected TooDeep, got {res:?}"),
+    }
+    lua.to_value(&data["a"])?;
+    match lua.to_value(&vec![0; 1000]) {
+        Err(Error::TooLarge { max_elements: 100 }) => {}
+        res => panic!("expected TooLarge, got {res:?}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "signing")]
+
+use mlua::ed25519_dalek::SigningKey;
+use mlua::{CompiledChunk, Lua, Result};
+
+fn compile(lua: &Lua, source: &str) -> Result<CompiledChunk> {
+    #[cfg(not(feature = "luau"))]
+    return Ok(CompiledChunk::from_function(
+        &lua.load(source).into_function()?,
+        false,
+    ));
+    #[cfg(feature = "luau")]
+    {
+        let _ = lua;
+        Ok(CompiledChunk::new(mlua::Compiler::new().compile(source)))
+    }
+}
+
+#[test]
+fn test_load_signed() -> Result<()> {
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let other_key = SigningKey::from_bytes(&[2; 32]);
+
+    let lua = Lua::new();
+    let chunk = compile(&lua, "local a, b = ... return a * b")?;
+    let signed = chunk.sign(&key);
+    assert_eq!(&signed[64..], chunk.as_bytes());
+
+    let res: i64 = lua
+        .load_signed(&signed, &key.verifying_key())?
+        .call((6, 7))?;
+    assert_eq!(res, 42);
+
+    // Wrong key
+    let err = lua
+        .load_signed(&signed, &other_key.verifying_key())
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "runtime error: invalid chunk signature");
+
+    // Tampered bytecode
+    let mut tampered = signed.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0xff;
+    assert!(lua.load_signed(&tampered, &key.verifying_key()).is_err());
+
+    // Truncated chunk
+    let err = lua
+        .load_signed(&signed[..10], &key.verifying_key())
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "runtime error: signed chunk is too short");
+
+    // Source code must be compiled before signing
+    let source = CompiledChunk::new("return 1").sign(&key);
+    assert!(lua
+        .load_signed(&source, &key.verifying_key())?
+        .exec()
+        .is_err());
+
+    Ok(())
+}
@@ -49,8 +49,18 @@ fn test_string_views() -> Result<()> {
         &b"null bytes are valid utf-8, wh\0 knew?"[..]
     );
 
+    assert_eq!(ok.as_str_lossy(), "null bytes are valid utf-8, wh\0 knew?");
+    assert!(!ok.as_str_lossy().is_lossy());
+    assert_eq!(
+        ok.as_str_lossy().as_bytes().as_ptr(),
+        ok.as_bytes().as_ptr()
+    );
+
     assert!(err.to_str().is_err());
     assert_eq!(err.as_bytes(), &b"but \xff isn't :("[..]);
+    assert_eq!(err.as_str_lossy(), "but \u{fffd} isn't :(");
+    assert!(err.as_str_lossy().is_lossy());
+    assert_eq!(err.as_str_lossy().as_lua_string(), &err);
 
     assert_eq!(empty.to_str()?, "");
     assert_eq!(empty.as_bytes_with_nul(), &[0]);
@@ -81,6 +91,12 @@ fn test_string_hash() -> Result<()> {
     assert!(set.contains(b"321".as_ref()));
     assert!(!set.contains(b"Hello".as_ref()));
 
+    // Borrowed views can be used as `&str` keys
+    let views: HashSet<_> = set.iter().map(|s| s.as_str_lossy()).collect();
+    assert!(views.contains("hello"));
+    assert!(views.contains("321"));
+    assert!(!views.contains("Hello"));
+
     Ok(())
 }
 
@@ -130,3 +146,184 @@ fn test_owned_string() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "luajit")]
+#[test]
+fn test_string_buffer() -> Result<()> {
+    use mlua::StringBuffer;
+
+    let lua = Lua::new();
+
+    let buf = lua.create_string_buffer()?;
+    assert!(buf.is_empty()?);
+    buf.put(b"hello")?;
+    buf.put([b','; 1])?;
+    assert_eq!(buf.len()?, 6);
+
+    // Buffers are shared with Lua code
+    lua.globals().set("buf", buf.clone())?;
+    lua.load(r#"buf:put(" world"); assert(buf:tostring() == "hello, world")"#)
+        .exec()?;
+    assert_eq!(buf.to_vec()?, b"hello, world");
+
+    let buf2: StringBuffer = lua
+        .load(r#"require("string.buffer").new():put(1, "x")"#)
+        .eval()?;
+    assert_eq!(buf2.to_vec()?, b"1x");
+
+    // Other userdata is not a buffer
+    let ud = lua.create_any_userdata(0u8)?;
+    assert!(lua
+        .unpack::<StringBuffer>(mlua::Value::UserData(ud))
+        .is_err());
+
+    buf.reset()?;
+    assert!(buf.is_empty()?);
+
+    let big = vec![7u8; 100_000];
+    let buf = lua.create_string_buffer_from(&big)?;
+    assert!(unsafe { buf.with_bytes(|b| b == big.as_slice())? });
+
+    #[cfg(feature = "bytes")]
+    assert_eq!(buf.to_bytes()?, bytes::Bytes::from(big));
+
+    Ok(())
+}
+
+#[test]
+fn test_string_builder() -> Result<()> {
+    use std::fmt::Write;
+
+    let lua = Lua::new();
+
+    // Empty builder
+    let builder = lua.create_string_builder();
+    assert!(builder.is_empty());
+    assert_eq!(builder.build()?, "");
+
+    let mut builder = lua.create_string_builder();
+    builder.append("hello")?;
+    builder.append(b", ")?;
+    builder.append([b'w'; 1])?;
+    write!(builder, "orld {}", 1).unwrap();
+    assert_eq!(builder.len(), 14);
+    assert_eq!(builder.build()?, "hello, world 1");
+
+    // Growing keeps the previously appended data
+    let mut builder = lua.create_string_builder();
+    let mut expected = Vec::new();
+    for i in 0..10_000u32 {
+        builder.append(i.to_le_bytes())?;
+        expected.extend_from_slice(&i.to_le_bytes());
+    }
+    lua.gc_collect()?;
+    assert!(builder.capacity() >= expected.len());
+    assert_eq!(builder.build()?.as_bytes(), expected);
+
+    // Reserving capacity
+    let mut builder = lua.create_string_builder();
+    builder.reserve(1000)?;
+    let capacity = builder.capacity();
+    assert!(capacity >= 1000);
+    builder.append([0u8; 1000])?;
+    assert_eq!(builder.capacity(), capacity);
+
+    // Memory is accounted against the Lua memory limit
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    if lua.set_memory_limit(lua.used_memory() + 10_000).is_ok() {
+        let mut builder = lua.create_string_builder();
+        let res = builder.append(vec![0u8; 100_000]);
+        assert!(matches!(res, Err(mlua::Error::MemoryError(_))));
+        lua.set_memory_limit(0)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_string_pack() -> Result<()> {
+    let lua = Lua::new();
+
+    let cases: &[(&str, &str)] = &[
+        ("<i4 >i4 =i4", "-2, 1000, 7"),
+        ("<b B h H", "-128, 255, -32768, 65535"),
+        (">j J l L T", "-2^53, -1, 123, 456, 789"),
+        ("<i3 I5 i9 I16", "-5, 2^33, -1, 12345"),
+        ("<f d n", "1.5, -0.25, 1e100"),
+        ("c5 z s1 s", "'abcde', 'hello', 'world', ''"),
+        ("!4 b i4 x Xi8 h !2 d", "1, 2, 3, 4"),
+        ("!2 b i3 b i4", "1, -2, 3, 4"),
+    ];
+
+    for (fmt, args) in cases {
+        let values = lua.load(&format!("{{{args}}}")).eval::<mlua::Table>()?;
+        let values = values
+            .sequence_values()
+            .collect::<Result<Vec<mlua::Value>>>()?;
+        let packed = lua.string_pack(fmt, mlua::MultiValue::from_vec(values.clone()))?;
+
+        // Same as `string.pack` if available
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        {
+            let code = format!("return string.pack('{fmt}', {args})");
+            let expected = lua.load(&code).eval::<String>()?;
+            assert_eq!(packed.as_bytes(), expected.as_bytes(), "format `{fmt}`");
+        }
+
+        let unpacked = lua.string_unpack(fmt, &packed)?;
+        assert_eq!(unpacked.len(), values.len(), "format `{fmt}`");
+        for (a, b) in unpacked.iter().zip(&values) {
+            assert!(a.equals(b)?, "format `{fmt}`: {a:?} != {b:?}");
+        }
+    }
+
+    let packed = lua.string_pack(">I2 s1", (0x0102, "ab"))?;
+    assert_eq!(packed.as_bytes(), b"\x01\x02\x02ab");
+
+    // Errors
+    let err = lua.string_pack("i1", 200).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("bad argument #1: integer overflow"),
+        "{err}"
+    );
+    let err = lua.string_pack("i2 I1", (1, -1)).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("bad argument #2: unsigned overflow"),
+        "{err}"
+    );
+    let err = lua.string_pack("i", 1.5).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("number has no integer representation"),
+        "{err}"
+    );
+    let err = lua.string_pack("z", "a\0b").unwrap_err();
+    assert!(err.to_string().contains("string contains zeros"), "{err}");
+    let err = lua.string_pack("y", ()).unwrap_err();
+    assert!(
+        err.to_string().contains("invalid format option 'y'"),
+        "{err}"
+    );
+    let err = lua.string_pack("i17", 1).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("integral size (17) out of limits [1,16]"),
+        "{err}"
+    );
+    let err = lua.string_unpack("i4", b"abc").unwrap_err();
+    assert!(err.to_string().contains("data string too short"), "{err}");
+    let err = lua
+        .string_unpack("i9", b"\0\0\0\0\0\0\0\0\x01")
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("9-byte integer does not fit"),
+        "{err}"
+    );
+    let err = lua.string_unpack("z", b"abc").unwrap_err();
+    assert!(err.to_string().contains("unfinished string"), "{err}");
+
+    Ok(())
+}
@@ -1,4 +1,11 @@
-use mlua::{Error, Lua, Nil, Result, Table, TableExt, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use mlua::{
+    AnyUserData, BorrowedValue, Error, Function, Integer, Lua, MetaMethod, Nil, Result, Table,
+    TableEvent, TableExt, TypedTable, Value, WeakCache, WeakMode,
+};
 
 #[test]
 fn test_globals_set_get() -> Result<()> {
@@ -13,6 +20,44 @@ fn test_globals_set_get() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_swap_globals() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.globals().set("tenant", "alice")?;
+    let get_tenant = lua.load("return tenant").into_function()?;
+
+    let env = lua.create_table_from([("tenant", "bob")])?;
+    env.set("tostring", lua.globals().get::<_, Function>("tostring")?)?;
+    let previous = lua.swap_globals(env.clone())?;
+    assert_eq!(previous.get::<_, String>("tenant")?, "alice");
+    assert_eq!(lua.globals(), env);
+    assert_eq!(lua.load("return tostring(tenant)").eval::<String>()?, "bob");
+    assert_eq!(lua.load("return print").eval::<Value>()?, Value::Nil);
+    // Functions loaded before keep their environment
+    assert_eq!(get_tenant.call::<_, String>(())?, "alice");
+
+    let thread = lua.create_thread(lua.load("return tenant").into_function()?)?;
+    assert_eq!(thread.resume::<_, String>(())?, "bob");
+
+    // Rust callbacks see the new globals
+    let callback = lua.create_function(|lua, ()| lua.globals().get::<_, String>("tenant"))?;
+    assert_eq!(callback.call::<_, String>(())?, "bob");
+
+    let swapped = lua.swap_globals(previous)?;
+    assert_eq!(swapped, env);
+    assert_eq!(lua.load("return tenant").eval::<String>()?, "alice");
+
+    #[cfg(feature = "luau")]
+    {
+        lua.sandbox(true)?;
+        assert!(lua.swap_globals(env).is_err());
+        lua.sandbox(false)?;
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_table() -> Result<()> {
     let lua = Lua::new();
@@ -253,6 +298,21 @@ fn test_table_pairs() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_table_get_by_bytes() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(r#"setmetatable({ key = 1, ["\0\255"] = 2 }, { __index = { inherited = 3 } })"#)
+        .eval()?;
+    assert_eq!(table.get_by_bytes::<i64>(b"key")?, 1);
+    assert_eq!(table.get_by_bytes::<i64>(b"\0\xff")?, 2);
+    assert_eq!(table.get_by_bytes::<i64>(b"inherited")?, 3);
+    assert_eq!(table.get_by_bytes::<Option<i64>>(b"missing")?, None);
+
+    Ok(())
+}
+
 #[test]
 fn test_table_for_each() -> Result<()> {
     let lua = Lua::new();
@@ -335,6 +395,116 @@ fn test_metatable() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_metatable_builder() -> Result<()> {
+    let lua = Lua::new();
+
+    let writes = Arc::new(AtomicU32::new(0));
+    let writes2 = writes.clone();
+    let mt = lua.create_metatable(|mt| {
+        mt.add_meta_method(MetaMethod::Index, |_, this, key: String| {
+            Ok(format!("{}:{key}", this.raw_get::<_, String>("prefix")?))
+        });
+        mt.add_meta_function_mut(
+            MetaMethod::NewIndex,
+            move |_, (this, key, value): (Table, Value, Value)| {
+                writes2.fetch_add(1, Ordering::Relaxed);
+                this.raw_set(key, value)
+            },
+        );
+        mt.add_meta_method(MetaMethod::Call, |_, _, (a, b): (i64, i64)| Ok(a + b));
+        mt.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            this.raw_get::<_, String>("prefix")
+        });
+        mt.add_meta_field("__name", "Proxy");
+    })?;
+    assert_eq!(mt.raw_get::<_, String>("__name")?, "Proxy");
+
+    let proxy = lua.create_table()?;
+    proxy.raw_set("prefix", "p")?;
+    proxy.set_metatable(Some(mt.clone()));
+    lua.globals().set("proxy", proxy)?;
+
+    lua.load(
+        r#"
+        assert(proxy.foo == "p:foo")
+        assert(proxy(1, 2) == 3)
+        assert(tostring(proxy) == "p")
+        proxy.bar = 1
+        assert(rawget(proxy, "bar") == 1)
+    "#,
+    )
+    .exec()?;
+    assert_eq!(writes.load(Ordering::Relaxed), 1);
+
+    // Metatable can be shared between tables
+    let other = lua.create_table_from([("prefix", "o")])?;
+    other.set_metatable(Some(mt));
+    assert_eq!(other.get::<_, String>("baz")?, "o:baz");
+
+    Ok(())
+}
+
+#[test]
+fn test_weak_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let weak_keys = lua.create_weak_table(WeakMode::Keys)?;
+    let weak_values = lua.create_weak_table(WeakMode::Values)?;
+    let weak_both = lua.create_weak_table(WeakMode::Both)?;
+    assert_eq!(
+        weak_both
+            .get_metatable()
+            .unwrap()
+            .raw_get::<_, String>("__mode")?,
+        "kv"
+    );
+
+    let key = lua.create_table()?;
+    let value = lua.create_table()?;
+    weak_keys.raw_set(key.clone(), "value")?;
+    weak_values.raw_set("key", value.clone())?;
+    weak_both.raw_set(key.clone(), value.clone())?;
+    lua.gc_collect()?;
+    assert_eq!(weak_keys.clone().pairs::<Value, Value>().count(), 1);
+    assert_eq!(weak_values.clone().pairs::<Value, Value>().count(), 1);
+    assert_eq!(weak_both.clone().pairs::<Value, Value>().count(), 1);
+
+    drop((key, value));
+    lua.gc_collect()?;
+    assert_eq!(weak_keys.pairs::<Value, Value>().count(), 0);
+    assert_eq!(weak_values.pairs::<Value, Value>().count(), 0);
+    assert_eq!(weak_both.pairs::<Value, Value>().count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_weak_cache() -> Result<()> {
+    let lua = Lua::new();
+
+    let cache = WeakCache::<AnyUserData, i64>::new(&lua, WeakMode::Keys)?;
+    let ud1 = lua.create_any_userdata(1)?;
+    let ud2 = lua.create_any_userdata(2)?;
+    cache.insert(ud1.clone(), 10)?;
+    assert_eq!(cache.get(ud1.clone())?, Some(10));
+    assert_eq!(cache.get(ud2.clone())?, None);
+    assert_eq!(cache.get_or_insert_with(ud2.clone(), || Ok(20))?, 20);
+    assert_eq!(cache.get_or_insert_with(ud2.clone(), || Ok(30))?, 20);
+    assert_eq!(cache.len()?, 2);
+
+    cache.remove(ud2.clone())?;
+    assert_eq!(cache.len()?, 1);
+
+    // Userdata with finalizers are removed from weak keys only in the next cycle
+    drop(ud1);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(cache.is_empty()?);
+
+    Ok(())
+}
+
 #[test]
 fn test_table_eq() -> Result<()> {
     let lua = Lua::new();
@@ -382,6 +552,36 @@ fn test_table_pointer() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_table_deep_clone() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua
+        .load(
+            r#"
+            local shared = {1, 2}
+            local t = setmetatable({a = {b = shared}, c = shared, n = 1}, {})
+            t.self = t
+            return t
+        "#,
+        )
+        .eval::<Table>()?;
+    let copy = table.deep_clone()?;
+    lua.globals().set("t", &table)?;
+    lua.globals().set("copy", copy)?;
+    lua.load(
+        r#"
+        assert(copy ~= t and copy.a ~= t.a and copy.c ~= t.c)
+        assert(copy.a.b == copy.c and copy.self == copy)
+        assert(copy.n == 1 and copy.c[2] == 2)
+        assert(getmetatable(copy) == getmetatable(t))
+        t.c[1] = 10
+        assert(copy.c[1] == 1)
+    "#,
+    )
+    .exec()
+}
+
 #[test]
 fn test_table_error() -> Result<()> {
     let lua = Lua::new();
@@ -469,3 +669,273 @@ fn test_owned_table() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_typed_table() -> Result<()> {
+    let lua = Lua::new();
+
+    let map: TypedTable<String, i64> = lua.create_table()?.typed();
+    map.set("a".into(), 1)?;
+    map.raw_set("b".into(), 2)?;
+    assert_eq!(map.get("a".into())?, 1);
+    assert_eq!(map.raw_get("b".into())?, 2);
+    assert!(map.contains_key("b".into())?);
+    map.raw_remove("b".into())?;
+    assert!(!map.contains_key("b".into())?);
+
+    let mut sum = 0;
+    map.for_each(|_, v| {
+        sum += v;
+        Ok(())
+    })?;
+    assert_eq!(sum, 1);
+    let pairs = map.iter().collect::<Result<Vec<_>>>()?;
+    assert_eq!(pairs, vec![("a".to_string(), 1)]);
+
+    // Values of other types fail on access
+    map.as_table().set("c", "not a number")?;
+    assert!(map.get("c".into()).is_err());
+
+    // Array-like access
+    lua.globals()
+        .set("list", lua.create_sequence_from([1, 2])?)?;
+    let list: TypedTable<Integer, u8> = lua.globals().get("list")?;
+    list.push(3)?;
+    assert_eq!(list.raw_len(), 3);
+    assert_eq!(list.values().collect::<Result<Vec<_>>>()?, vec![1, 2, 3]);
+    assert_eq!(list.pop()?, 3);
+    lua.globals().set("list", list)?;
+    lua.load("assert(#list == 2)").exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_table_visit() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(
+            r#"
+            local t = { "a", "b", x = 1.5, y = true, [10] = {}, ["\255"] = print }
+            return t
+        "#,
+        )
+        .eval()?;
+
+    let mut strings = Vec::new();
+    let mut values = Vec::new();
+    table.visit(|key, value| {
+        match key {
+            BorrowedValue::Integer(1) | BorrowedValue::Integer(2) => {
+                strings.push(value.as_str().unwrap().to_string());
+            }
+            BorrowedValue::Integer(10) => assert!(matches!(value, BorrowedValue::Table(_))),
+            BorrowedValue::String(b"\xff") => {
+                assert_eq!(key.as_str(), None);
+                assert!(matches!(value, BorrowedValue::Function(_)));
+            }
+            key => values.push((key.as_str().unwrap().to_string(), value.type_name())),
+        }
+        Ok(())
+    })?;
+    strings.sort();
+    values.sort();
+    assert_eq!(strings, vec!["a", "b"]);
+    assert_eq!(
+        values,
+        vec![("x".into(), "number"), ("y".into(), "boolean")]
+    );
+
+    // Errors are propagated
+    let err = table.visit(|_, _| Err(Error::runtime("stop"))).unwrap_err();
+    assert!(matches!(err, Error::RuntimeError(msg) if msg == "stop"));
+
+    Ok(())
+}
+
+#[test]
+fn test_table_set_many() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.set_many((1..=1000).map(|i| (i, i * 2)))?;
+    assert_eq!(table.raw_len(), 1000);
+    assert_eq!(table.get::<_, i64>(500)?, 1000);
+
+    // Later values win
+    table.set_many([("a", 1), ("b", 2), ("a", 3)])?;
+    assert_eq!(table.get::<_, i64>("a")?, 3);
+    assert_eq!(table.get::<_, i64>("b")?, 2);
+
+    // Metamethods are not invoked
+    let mt = lua.create_table()?;
+    mt.set(
+        "__newindex",
+        lua.create_function(|_, ()| Err::<(), _>(Error::runtime("nope")))?,
+    )?;
+    let table = lua.create_table()?;
+    table.set_metatable(Some(mt));
+    table.set_many([("x", 1)])?;
+    assert_eq!(table.raw_get::<_, i64>("x")?, 1);
+
+    // Invalid keys
+    let err = table.set_many([(Value::Nil, 1)]).unwrap_err();
+    assert!(matches!(err, Error::RuntimeError(_)));
+
+    Ok(())
+}
+
+#[test]
+fn test_table_proxy() -> Result<()> {
+    let lua = Lua::new();
+
+    let map = HashMap::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+    let map = lua.create_table_proxy(map)?;
+    lua.globals().set("map", &map)?;
+    lua.load(
+        r#"
+        assert(map.a == 1 and map.b == 2 and map.c == nil and map[1] == nil)
+        assert(#map == 2)
+        map.c = 3
+        map.a = nil
+    "#,
+    )
+    .exec()?;
+    {
+        let mut map = map.borrow_mut::<HashMap<String, i32>>()?;
+        assert_eq!(*map, HashMap::from([("b".into(), 2), ("c".into(), 3)]));
+        map.insert("d".into(), 4);
+    }
+    assert_eq!(lua.load("map.d").eval::<i64>()?, 4);
+    assert!(lua.load("map.e = 'x'").exec().is_err());
+
+    let vec = lua.create_table_proxy(vec![10, 20, 30])?;
+    lua.globals().set("vec", &vec)?;
+    lua.load(
+        r#"
+        assert(vec[1] == 10 and vec[3] == 30 and vec[0] == nil and vec[4] == nil)
+        vec[2] = 21
+        vec[#vec + 1] = 40
+        vec[#vec] = nil
+        vec[#vec + 1] = 50
+        assert(not pcall(function() vec[10] = 1 end))
+    "#,
+    )
+    .exec()?;
+    assert_eq!(*vec.borrow::<Vec<i32>>()?, vec![10, 21, 30, 50]);
+
+    // Luau uses generalized iteration instead of `pairs`
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    {
+        let pairs = if cfg!(feature = "luau") { "" } else { "pairs" };
+        lua.load(format!(
+            r#"
+            local sum = 0
+            for k, v in {pairs}(map) do sum = sum + v end
+            assert(sum == 9)
+            local items = {{}}
+            for i, v in {pairs}(vec) do items[i] = v end
+            assert(table.concat(items, ",") == "10,21,30,50")
+        "#
+        ))
+        .exec()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_observe() -> Result<()> {
+    let lua = Lua::new();
+
+    let state = lua.create_table()?;
+    state.set("hp", 100)?;
+    lua.globals().set("state", &state)?;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events2 = events.clone();
+    let backing = state.observe(move |event| {
+        let event = match event {
+            TableEvent::Set {
+                key,
+                value,
+                old_value,
+            } => format!("set {} {old_value:?} -> {value:?}", key.to_string()?),
+            TableEvent::Remove { key, old_value } => {
+                if key == Value::Integer(0) {
+                    return Err(Error::runtime("cannot remove 0"));
+                }
+                format!("remove {} {old_value:?}", key.to_string()?)
+            }
+        };
+        events2.lock().unwrap().push(event);
+        Ok(())
+    })?;
+    assert_eq!(backing.get::<_, i64>("hp")?, 100);
+    assert_eq!(state.raw_len(), 0);
+
+    lua.load(
+        r#"
+        assert(state.hp == 100)
+        state.hp = 90
+        state.hp = 90 -- unchanged
+        state.name = "bob"
+        state[1] = "a"
+        state[2] = "b"
+        state.name = nil
+        state.missing = nil -- not present
+        "#,
+    )
+    .exec()?;
+    assert_eq!(state.len()?, 2);
+    state.set("hp", 80)?;
+    backing.set("hp", 70)?; // bypass events
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "set hp Integer(100) -> Integer(90)",
+            "set name Nil -> String(\"bob\")",
+            "set 1 Nil -> String(\"a\")",
+            "set 2 Nil -> String(\"b\")",
+            "remove name String(\"bob\")",
+            "set hp Integer(90) -> Integer(80)",
+        ]
+    );
+
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    {
+        #[cfg(not(feature = "luau"))]
+        let code = "local n = 0 for _ in pairs(state) do n = n + 1 end return n";
+        #[cfg(feature = "luau")]
+        let code = "local n = 0 for _ in state do n = n + 1 end return n";
+        assert_eq!(lua.load(code).eval::<i64>()?, 3);
+    }
+
+    // Callback errors are raised from the assignment
+    backing.set(0, true)?;
+    let err = lua.load("state[0] = nil").exec().unwrap_err();
+    assert!(err.to_string().contains("cannot remove 0"));
+
+    // Scripts cannot replace the proxy metatable
+    assert!(lua.load("setmetatable(state, {})").exec().is_err());
+
+    // Tables with metatables cannot be observed
+    let err = state.observe(|_| Ok(())).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "runtime error: cannot observe a table with a metatable"
+    );
+
+    Ok(())
+}
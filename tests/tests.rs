@@ -8,8 +8,8 @@ use std::sync::Arc;
 use std::{error, f32, f64, fmt};
 
 use mlua::{
-    ChunkMode, Error, ExternalError, Function, Lua, LuaOptions, Nil, Result, StdLib, String, Table,
-    UserData, Value, Variadic,
+    ChunkMode, Error, ExternalError, Function, GCMode, GlobalsProtection, Lua, LuaOptions, Nil,
+    Result, SandboxAudit, StdLib, String, Table, UserData, Value, Variadic,
 };
 
 #[cfg(not(feature = "luau"))]
@@ -35,6 +35,7 @@ fn test_safety() -> Result<()> {
     }
 
     let lua = Lua::new();
+    #[cfg(feature = "filesystem")]
     match lua.load(r#"package.loadlib()"#).exec() {
         Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
             Error::SafetyError(_) => {}
@@ -43,6 +44,7 @@ fn test_safety() -> Result<()> {
         Err(e) => panic!("expected CallbackError, got {:?}", e),
         Ok(_) => panic!("expected CallbackError, got no error"),
     };
+    #[cfg(feature = "filesystem")]
     match lua.load(r#"require "fake_ffi""#).exec() {
         Err(Error::RuntimeError(msg)) => assert!(msg.contains("can't load C modules in safe mode")),
         Err(e) => panic!("expected RuntimeError, got {:?}", e),
@@ -54,6 +56,7 @@ fn test_safety() -> Result<()> {
     let lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
     assert!(lua.globals().get::<_, Option<Value>>("require")?.is_none());
     lua.load_from_std_lib(StdLib::PACKAGE)?;
+    #[cfg(feature = "filesystem")]
     match lua.load(r#"package.loadlib()"#).exec() {
         Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
             Error::SafetyError(_) => {}
@@ -66,6 +69,188 @@ fn test_safety() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_safe_debug_lib() -> Result<()> {
+    #[cfg(not(feature = "luau"))]
+    {
+        let lua = Lua::new();
+        assert!(lua.globals().get::<_, Option<Value>>("debug")?.is_none());
+    }
+
+    let libs = StdLib::STRING | StdLib::PACKAGE | StdLib::DEBUG_SAFE;
+    let lua = Lua::new_with(libs, LuaOptions::default())?;
+    lua.load(
+        r#"
+        assert(debug.getmetatable == nil and debug.setupvalue == nil and debug.sethook == nil)
+        assert(require("debug") == debug)
+
+        local function inner()
+            local info = debug.getinfo(1)
+            assert(info.short_src == "[string \"test\"]" and info.currentline == 6)
+            assert(info.what == "Lua" and info.func == nil)
+            error("boom")
+        end
+        local ok, trace = xpcall(inner, debug.traceback)
+        assert(not ok)
+        assert(trace:find("boom\nstack traceback:", 1, true), trace)
+        assert(trace:find("[string \"test\"]:9: in function", 1, true), trace)
+
+        local info = debug.getinfo(inner)
+        assert(info.what == "Lua" and info.linedefined == 5)
+        assert(debug.getinfo(100) == nil)
+        assert(debug.traceback(inner) == inner)
+    "#,
+    )
+    .set_name("test")
+    .exec()?;
+
+    // The full library takes precedence
+    let lua =
+        unsafe { Lua::unsafe_new_with(StdLib::DEBUG | StdLib::DEBUG_SAFE, LuaOptions::new()) };
+    lua.load("assert(debug.getmetatable ~= nil or debug.info ~= nil)")
+        .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_unload_replace_std_lib() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("upper = string.upper").exec()?;
+
+    lua.unload_std_lib(StdLib::STRING | StdLib::MATH)?;
+    lua.load(
+        r#"
+        assert(string == nil and math == nil)
+        assert(package.loaded.string == nil and package.loaded.math == nil)
+        assert(not pcall(function() return ("abc"):upper() end))
+        -- Already stored references keep working
+        assert(upper("abc") == "ABC")
+        assert(table.concat({1, 2}) == "12")
+    "#,
+    )
+    .exec()?;
+
+    let string = lua.create_table()?;
+    string.set(
+        "upper",
+        lua.create_function(|_, s: StdString| Ok(s.to_uppercase()))?,
+    )?;
+    lua.replace_std_lib(StdLib::STRING, string)?;
+    lua.load(
+        r#"
+        assert(("abc"):upper() == "ABC")
+        assert(require("string") == string)
+        assert(string.lower == nil)
+    "#,
+    )
+    .exec()?;
+
+    // Only a single library can be replaced
+    let table = lua.create_table()?;
+    assert!(lua
+        .replace_std_lib(StdLib::ALL_SAFE, table.clone())
+        .is_err());
+    assert!(lua.replace_std_lib(StdLib::NONE, table).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_builder() -> Result<()> {
+    let lua = Lua::builder()
+        .libs(StdLib::STRING)
+        .options(LuaOptions::new().catch_rust_panics(false))
+        .stack_size(1000)
+        .ref_thread_capacity(4000)
+        .gc_mode(GCMode::Incremental)
+        .build()?;
+    assert_eq!(lua.load("string.upper('abc')").eval::<String>()?, "ABC");
+    assert!(lua.globals().get::<_, Option<Table>>("table")?.is_none());
+
+    // Hold a lot of references at the same time
+    let tables = (0..4000)
+        .map(|_| lua.create_table())
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(tables.len(), 4000);
+    drop(tables);
+
+    #[cfg(not(feature = "luau"))]
+    match Lua::builder().libs(StdLib::DEBUG).build() {
+        Err(Error::SafetyError(_)) => {}
+        Err(e) => panic!("expected SafetyError, got {:?}", e),
+        Ok(_) => panic!("expected SafetyError, got new Lua state"),
+    }
+
+    let lua = unsafe { Lua::builder().libs(StdLib::ALL).unsafe_build()? };
+    #[cfg(not(feature = "luau"))]
+    assert!(lua.load(r#"require "debug""#).exec().is_ok());
+    drop(lua);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_builder_allocator() -> Result<()> {
+    use std::os::raw::c_void;
+
+    static ALLOCATIONS: AtomicU32 = AtomicU32::new(0);
+
+    unsafe extern "C-unwind" fn alloc(
+        _ud: *mut c_void,
+        ptr: *mut c_void,
+        osize: usize,
+        nsize: usize,
+    ) -> *mut c_void {
+        use std::alloc::{self, Layout};
+
+        if nsize == 0 {
+            if !ptr.is_null() {
+                alloc::dealloc(ptr as *mut u8, Layout::from_size_align_unchecked(osize, 16));
+            }
+            return std::ptr::null_mut();
+        }
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        if ptr.is_null() {
+            return alloc::alloc(Layout::from_size_align_unchecked(nsize, 16)) as *mut c_void;
+        }
+        let layout = Layout::from_size_align_unchecked(osize, 16);
+        alloc::realloc(ptr as *mut u8, layout, nsize) as *mut c_void
+    }
+
+    let lua = unsafe {
+        Lua::builder()
+            .allocator(alloc, std::ptr::null_mut())
+            .build()?
+    };
+    assert!(ALLOCATIONS.load(Ordering::Relaxed) > 0);
+    assert_eq!(lua.load("1 + 1").eval::<i32>()?, 2);
+    assert!(matches!(
+        lua.set_memory_limit(1024),
+        Err(Error::MemoryLimitNotAvailable)
+    ));
+
+    // The state cannot be created if the allocator fails
+    unsafe extern "C-unwind" fn failing_alloc(
+        _ud: *mut c_void,
+        _ptr: *mut c_void,
+        _osize: usize,
+        _nsize: usize,
+    ) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    let res = unsafe {
+        Lua::builder()
+            .allocator(failing_alloc, std::ptr::null_mut())
+            .build()
+    };
+    assert!(matches!(res, Err(Error::MemoryError(_))));
+
+    Ok(())
+}
+
 #[test]
 fn test_load() -> Result<()> {
     let lua = Lua::new();
@@ -700,6 +885,457 @@ fn test_set_metatable_nil() -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_env_provider() -> Result<()> {
+    std::env::set_var("MLUA_TEST_SECRET", "secret");
+
+    let lua = Lua::new();
+    assert_eq!(
+        lua.load("os.getenv('MLUA_TEST_SECRET')")
+            .eval::<StdString>()?,
+        "secret"
+    );
+
+    let mut env = HashMap::new();
+    env.insert("APP_MODE".to_string(), "test".to_string());
+    lua.set_env_provider(env)?;
+    lua.load(
+        r#"
+        assert(os.getenv("APP_MODE") == "test")
+        assert(os.getenv("MLUA_TEST_SECRET") == nil)
+    "#,
+    )
+    .exec()?;
+
+    lua.set_env_provider(mlua::DenyEnv)?;
+    assert_eq!(lua.load("os.getenv('APP_MODE')").eval::<Value>()?, Nil);
+
+    // Provider applies to the `os` library loaded later
+    let lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
+    lua.set_env_provider(mlua::DenyEnv)?;
+    lua.load_from_std_lib(StdLib::OS)?;
+    assert_eq!(
+        lua.load("os.getenv('MLUA_TEST_SECRET')").eval::<Value>()?,
+        Nil
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_protect_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("setup = true").exec()?;
+    lua.protect_globals(
+        GlobalsProtection::new()
+            .lock_metatable(true)
+            .freeze_stdlib(true)
+            .error_on_new_globals(true)
+            .allow_global("config"),
+    )?;
+
+    // Existing and allowed globals can be assigned
+    lua.load("setup = false; config = {}").exec()?;
+    assert!(!lua.globals().get::<_, bool>("setup")?);
+    assert!(lua.load("counter = 1").exec().is_err());
+    assert!(lua.load("local counter = 1").exec().is_ok());
+    lua.globals().raw_set("counter", 1)?;
+    assert_eq!(lua.load("counter").eval::<i64>()?, 1);
+
+    // Stdlib tables are read-only but still usable
+    assert!(lua.load("string.format = nil").exec().is_err());
+    assert!(lua.load("string.custom = 1").exec().is_err());
+    assert!(lua.load("math.pi = 3").exec().is_err());
+    assert_eq!(
+        lua.load(r#"string.format("%d", 5) .. ("x"):rep(2)"#)
+            .eval::<String>()?,
+        "5xx"
+    );
+    assert_eq!(lua.load("math.max(1, 2)").eval::<i64>()?, 2);
+
+    // Metatables cannot be replaced
+    assert!(lua.load("setmetatable(_G, nil)").exec().is_err());
+    assert_eq!(
+        lua.load("getmetatable(_G)").eval::<Value>()?,
+        Value::Boolean(false)
+    );
+    assert!(lua.load("setmetatable(string, nil)").exec().is_err());
+
+    #[cfg(not(feature = "luau"))]
+    {
+        assert!(lua
+            .load(r#"getmetatable("").__index.format = nil"#)
+            .exec()
+            .is_err());
+
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        assert!(
+            lua.load("local n = 0; for _ in pairs(table) do n = n + 1 end; return n")
+                .eval::<i64>()?
+                > 0
+        );
+    }
+
+    // Allowed globals are created through the existing `__newindex` metamethod
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        created = {}
+        setmetatable(_G, {__newindex = function(t, k, v)
+            rawset(created, k, true)
+            rawset(t, k, v)
+        end})
+    "#,
+    )
+    .exec()?;
+    lua.protect_globals(
+        GlobalsProtection::new()
+            .error_on_new_globals(true)
+            .allow_global("config"),
+    )?;
+    lua.load("config = 1").exec()?;
+    assert!(lua.load("counter = 1").exec().is_err());
+    assert!(lua
+        .load("created.config and not created.counter")
+        .eval::<bool>()?);
+    assert_eq!(lua.globals().get::<_, i64>("config")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_run_isolated() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("limit = 10; data = {}").exec()?;
+
+    // Reading the environment and mutating passed-in tables is allowed
+    let data: Table = lua.globals().get("data")?;
+    let sum = lua.run_isolated(|lua| {
+        let f: Function = lua
+            .load("function(t) t.seen = true; return limit * 2 end")
+            .eval()?;
+        f.call::<_, i64>(data.clone())
+    })?;
+    assert_eq!(sum, 20);
+    assert!(data.get::<_, bool>("seen")?);
+
+    // Creating globals is denied
+    let err = lua
+        .run_isolated(|lua| lua.load("counter = 1").exec())
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("attempt to create global 'counter'"),
+        "{err}"
+    );
+    assert_eq!(lua.globals().get::<_, Option<i64>>("counter")?, None);
+
+    // Raw changes and changes of existing globals are reverted and reported
+    let err = lua
+        .run_isolated(|lua| lua.load("limit = 0; rawset(_G, 'counter', 1)").exec())
+        .unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("isolated execution modified globals"), "{msg}");
+    assert!(msg.contains("limit") && msg.contains("counter"), "{msg}");
+    assert_eq!(lua.globals().get::<_, i64>("limit")?, 10);
+    assert_eq!(lua.globals().get::<_, Option<i64>>("counter")?, None);
+
+    // Globals metatable is hidden and restored afterwards
+    let mt = lua.create_table()?;
+    mt.set("__index", lua.create_table_from([("fallback", 5)])?)?;
+    lua.globals().set_metatable(Some(mt.clone()));
+    lua.run_isolated(|lua| {
+        assert_eq!(lua.load("fallback").eval::<i64>()?, 5);
+        assert!(!lua.load("getmetatable(_G)").eval::<bool>()?);
+        assert!(lua.load("setmetatable(_G, nil)").exec().is_err());
+        Ok(())
+    })?;
+    assert_eq!(lua.globals().get_metatable(), Some(mt));
+
+    // Named registry writes are denied, registry keys can be created
+    let err = lua
+        .run_isolated(|lua| lua.set_named_registry_value("key", 1))
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("named registry values are read-only"),
+        "{err}"
+    );
+    lua.set_named_registry_value("key", 1)?;
+    let handle = lua.run_isolated(|lua| {
+        let key = lua.create_registry_value(1)?;
+        lua.remove_registry_value(key)?;
+        lua.global_handle::<i64>("limit")
+    })?;
+    assert_eq!(handle.get(&lua)?, 10);
+
+    // The environment is restored if `f` panics
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        lua.run_isolated(|lua| -> Result<()> {
+            lua.load("rawset(_G, 'counter', 1)").exec()?;
+            panic!("boom");
+        })
+    }));
+    assert!(res.is_err());
+    assert_eq!(lua.globals().get::<_, Option<i64>>("counter")?, None);
+    assert!(lua.load("counter = 1").exec().is_ok());
+    lua.set_named_registry_value("key", 2)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_global_handle() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("counter = 1; config = { limits = { rate = 10 } }")
+        .exec()?;
+
+    let counter = lua.global_handle::<i64>("counter")?;
+    assert_eq!(counter.path(), "counter");
+    assert_eq!(counter.get(&lua)?, 1);
+    counter.set(&lua, 2)?;
+    assert_eq!(lua.load("counter").eval::<i64>()?, 2);
+
+    let mut rate = lua.global_handle::<Option<u32>>("config.limits.rate")?;
+    assert_eq!(rate.get(&lua)?, Some(10));
+    rate.set(&lua, None)?;
+    assert_eq!(lua.load("config.limits.rate").eval::<Option<u32>>()?, None);
+
+    // Reloading the environment requires resolving the path again
+    lua.load("config = { limits = { rate = 20 } }").exec()?;
+    assert_eq!(rate.get(&lua)?, None);
+    rate.resolve(&lua)?;
+    assert_eq!(rate.get(&lua)?, Some(20));
+
+    // Intermediate tables must exist
+    assert!(lua.global_handle::<Value>("config.missing.rate").is_err());
+    assert!(lua.global_handle::<Value>("counter.value").is_err());
+    lua.load("config = nil").exec()?;
+    assert!(rate.resolve(&lua).is_err());
+
+    // Handles are tied to the Lua instance
+    let lua2 = Lua::new();
+    match counter.get(&lua2) {
+        Err(Error::MismatchedRegistryKey) => {}
+        r => panic!("expected MismatchedRegistryKey, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+#[test]
+fn test_on_exit_request() -> Result<()> {
+    let lua = Lua::new();
+    let codes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let codes2 = codes.clone();
+    lua.on_exit_request(move |code| {
+        codes2.lock().unwrap().push(code);
+        mlua::ExitAction::Error
+    })?;
+
+    match lua.load("os.exit(3)").exec() {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::ExitRequested { code: 3 } => {}
+            ref err => panic!("expected ExitRequested, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // Scripts can catch the error
+    let ok = lua.load("return pcall(os.exit, false)").eval::<bool>()?;
+    assert!(!ok);
+    lua.load("pcall(os.exit)").exec()?;
+    assert_eq!(*codes.lock().unwrap(), vec![3, 1, 0]);
+
+    // Replacing the handler
+    lua.on_exit_request(|_| mlua::ExitAction::Error)?;
+    assert!(lua.load("os.exit(true)").exec().is_err());
+    assert_eq!(codes.lock().unwrap().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_set_stdio() -> Result<()> {
+    use std::io::{self, Write};
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn take(&self) -> StdString {
+            let data = std::mem::take(&mut *self.0.lock().unwrap());
+            StdString::from_utf8(data).unwrap()
+        }
+    }
+
+    let lua = Lua::new();
+    let (stdout, stderr) = (Capture::default(), Capture::default());
+    let stdin = io::Cursor::new("first line\n42 1.5\nrest\nof input");
+    lua.set_stdio(stdout.clone(), stderr.clone(), stdin)?;
+
+    lua.load(r#"print("hello", 1, 2.5, nil, true)"#).exec()?;
+    assert_eq!(stdout.take(), "hello\t1\t2.5\tnil\ttrue\n");
+
+    #[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+    {
+        lua.load(r#"io.write("a", 1, "b"):write("c")"#).exec()?;
+        lua.load(r#"io.stdout:write("d\n")"#).exec()?;
+        assert_eq!(stdout.take(), "a1bcd\n");
+
+        lua.load(r#"io.stderr:write("error!")"#).exec()?;
+        assert_eq!(stderr.take(), "error!");
+        assert_eq!(stdout.take(), "");
+        assert!(lua.load("io.write({})").exec().is_err());
+
+        let (line, n, x) = lua
+            .load(r#"return io.read(), io.read("n"), io.stdin:read("*n")"#)
+            .eval::<(StdString, i64, f64)>()?;
+        assert_eq!((line.as_str(), n, x), ("first line", 42, 1.5));
+
+        let lines = lua
+            .load("local t = {} for l in io.lines() do t[#t + 1] = l end return t")
+            .eval::<Vec<StdString>>()?;
+        assert_eq!(lines, ["", "rest", "of input"]);
+        assert_eq!(lua.load("io.read('a')").eval::<StdString>()?, "");
+        assert_eq!(lua.load("io.read()").eval::<Option<StdString>>()?, None);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_audit_sandbox() -> Result<()> {
+    let lua = Lua::new();
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let events2 = events.clone();
+    lua.audit_sandbox(
+        SandboxAudit::sensitive().watch("string.rep"),
+        move |_, event| {
+            let event = (event.name, event.source, event.line);
+            events2.lock().unwrap().push(event);
+        },
+    )?;
+
+    // Calls are not blocked
+    lua.load(
+        r#"
+        local t = os.time()
+        assert(type(t) == "number")
+        assert(("x"):rep(2) == "xx")
+        assert(string.rep("y", 2) == "yy")
+        "#,
+    )
+    .set_name("audited")
+    .exec()?;
+
+    let events = events.lock().unwrap();
+    let events = events
+        .iter()
+        .map(|(name, source, line)| (name.as_str(), source.as_deref(), *line))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            ("os.time", Some("[string \"audited\"]"), Some(2)),
+            ("string.rep", Some("[string \"audited\"]"), Some(4)),
+            ("string.rep", Some("[string \"audited\"]"), Some(5)),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_loaders() -> Result<()> {
+    let lua = Lua::new();
+    let loaders = lua.loaders()?;
+    let count = loaders.len();
+    if cfg!(feature = "filesystem") {
+        assert!(count > 0);
+    }
+
+    loaders.set_path(["modules/?.lua", "modules/?/init.lua"])?;
+    assert_eq!(loaders.path()?, ["modules/?.lua", "modules/?/init.lua"]);
+    let path = lua.load("package.path").eval::<StdString>()?;
+    assert_eq!(path, "modules/?.lua;modules/?/init.lua");
+
+    // Empty templates are preserved
+    lua.load("package.path = 'a/?.lua;;b/?.lua'").exec()?;
+    assert_eq!(loaders.path()?, ["a/?.lua", "", "b/?.lua"]);
+    loaders.set_path(loaders.path()?)?;
+    let path = lua.load("package.path").eval::<StdString>()?;
+    assert_eq!(path, "a/?.lua;;b/?.lua");
+    loaders.set_path(Vec::<StdString>::new())?;
+    assert!(loaders.path()?.is_empty());
+
+    // Searcher resolving modules from memory
+    let searcher = lua.create_function(|lua, name: StdString| match name.as_str() {
+        "memory" => Ok(Value::Function(
+            lua.load("return 'from memory'").into_function()?,
+        )),
+        _ => Ok(Value::String(lua.create_string("\n\tno module in memory")?)),
+    })?;
+    loaders.push(searcher.clone())?;
+    assert_eq!(loaders.len(), count + 1);
+    assert_eq!(
+        lua.load("require('memory')").eval::<StdString>()?,
+        "from memory"
+    );
+
+    // Move it to the front and intercept the module names
+    loaders.move_to(count, 0)?;
+    assert_eq!(loaders.get(0)?, Some(searcher.clone()));
+    let names = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let names2 = names.clone();
+    loaders.wrap(0, move |_, searcher, name| {
+        names2.lock().unwrap().push(name.clone());
+        searcher.call::<_, Value>(name)
+    })?;
+    lua.unload("memory")?;
+    assert_eq!(
+        lua.load("require('memory')").eval::<StdString>()?,
+        "from memory"
+    );
+    let err = lua
+        .load("require('missing')")
+        .exec()
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("no module in memory"));
+    assert_eq!(*names.lock().unwrap(), ["memory", "missing"]);
+
+    // Remove all searchers
+    while !loaders.is_empty() {
+        loaders.remove(0)?;
+    }
+    assert!(loaders.searchers()?.is_empty());
+    assert!(loaders.remove(0).is_err());
+    assert!(loaders.insert(1, searcher).is_err());
+    lua.unload("memory")?;
+    assert!(lua.load("require('memory')").exec().is_err());
+
+    #[cfg(not(feature = "luau"))]
+    {
+        let lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
+        assert!(lua.loaders().is_err());
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_named_registry_value() -> Result<()> {
     let lua = Lua::new();
@@ -831,6 +1467,24 @@ fn test_mismatched_registry_key() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_mismatched_lua_state() -> Result<()> {
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+
+    let t = lua1.create_table()?;
+    let f = lua2.create_function(|_, _: Table| Ok(()))?;
+    match f.call::<_, ()>(&t) {
+        Err(Error::MismatchedLuaState { expected, found }) => {
+            assert_eq!((expected, found), (lua2.id(), lua1.id()));
+        }
+        r => panic!("wrong result type for mismatched Lua state, {:?}", r),
+    };
+    assert!(lua2.globals().set("t", Value::Table(t)).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_registry_value_reuse() -> Result<()> {
     let lua = Lua::new();
@@ -1161,6 +1815,187 @@ fn test_jit_version() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_compiled_version() -> Result<()> {
+    let lua = Lua::new();
+    let version = lua.globals().get::<_, String>("_VERSION")?;
+    let compiled = mlua::LuaVersion::COMPILED;
+    if compiled != mlua::LuaVersion::LuaJIT {
+        assert!(version.to_str()?.starts_with(compiled.as_str()));
+    }
+    assert!(compiled.is_compatible_with(compiled));
+    assert!(mlua::LuaVersion::LuaJIT.is_compatible_with(mlua::LuaVersion::Lua51));
+    assert!(!mlua::LuaVersion::Lua51.is_compatible_with(mlua::LuaVersion::LuaJIT));
+    assert!(!mlua::LuaVersion::Lua54.is_compatible_with(mlua::LuaVersion::Lua53));
+    Ok(())
+}
+
+#[test]
+fn test_no_fs_access() -> Result<()> {
+    let lua = Lua::new_with(StdLib::ALL_SAFE, LuaOptions::new().fs_access(false))?;
+    lua.load(
+        r#"
+        assert(io == nil)
+        assert(loadfile == nil and dofile == nil)
+        assert(os.execute == nil and os.exit == nil)
+        assert(os.remove == nil and os.rename == nil and os.tmpname == nil)
+        assert(type(os.time) == "function")
+        assert(package.path == nil and package.cpath == nil and package.loadlib == nil)
+
+        local ok, err = pcall(require, "missing_module")
+        assert(not ok and not string.find(err, "no file"))
+    "#,
+    )
+    .exec()?;
+    #[cfg(not(feature = "luau"))]
+    lua.load(
+        r#"
+        package.preload["mymod"] = function() return 123 end
+        assert(require("mymod") == 123)
+    "#,
+    )
+    .exec()?;
+
+    // Libraries loaded later are stripped as well
+    #[cfg(all(not(feature = "luau"), feature = "filesystem"))]
+    {
+        lua.unload_std_lib(StdLib::OS)?;
+        lua.load_from_std_lib(StdLib::OS | StdLib::IO)?;
+        lua.load("assert(io == nil and os.execute == nil and os.time ~= nil)")
+            .exec()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(not(feature = "filesystem"))]
+fn test_no_filesystem_feature() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        assert(io == nil)
+        assert(loadfile == nil and dofile == nil)
+        assert(os.remove == nil and os.rename == nil and os.tmpname == nil)
+        assert(package.path == nil and package.cpath == nil)
+    "#,
+    )
+    .exec()
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_foreign_state() -> Result<()> {
+    use mlua::{ffi, ForeignLua, StateOwnership};
+    use std::sync::atomic::AtomicBool;
+
+    // Borrowed state stays alive after `ForeignLua` is dropped
+    let state = unsafe { ffi::luaL_newstate() };
+    let foreign = unsafe { ForeignLua::new(state, StateOwnership::Borrowed, ())? };
+    let closed = Arc::new(AtomicBool::new(false));
+    let closed2 = closed.clone();
+    foreign.on_close(move || closed2.store(true, Ordering::Relaxed));
+    {
+        let lua = foreign.lock()?;
+        let f = lua.create_function(|_, x: i32| Ok(x * 2))?;
+        lua.globals().set("double", f)?;
+    }
+    match unsafe { ForeignLua::new(state, StateOwnership::Borrowed, ()) } {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("already wrapped")),
+        r => panic!("expected RuntimeError, got {:?}", r),
+    }
+    drop(foreign);
+    assert!(!closed.load(Ordering::Relaxed));
+    unsafe {
+        ffi::lua_getglobal(state, b"double\0".as_ptr() as _);
+        ffi::lua_pushinteger(state, 21);
+        assert_eq!(ffi::lua_pcall(state, 1, 1, 0), ffi::LUA_OK);
+        assert_eq!(ffi::lua_tointeger(state, -1), 42);
+        ffi::lua_close(state);
+    }
+    assert!(closed.load(Ordering::Relaxed));
+
+    // Closed state cannot be accessed
+    let state = unsafe { ffi::luaL_newstate() };
+    let foreign = unsafe { ForeignLua::new(state, StateOwnership::Borrowed, ())? };
+    unsafe { ffi::lua_close(state) };
+    assert!(foreign.is_closed());
+    assert!(foreign.lock().is_err());
+    drop(foreign);
+
+    // Borrowed instance is released once the state is closed and the handle is dropped
+    struct DropFlag(Arc<AtomicBool>);
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+    for close_first in [false, true] {
+        let state = unsafe { ffi::luaL_newstate() };
+        let foreign = unsafe { ForeignLua::new(state, StateOwnership::Borrowed, ())? };
+        let released = Arc::new(AtomicBool::new(false));
+        foreign.lock()?.set_app_data(DropFlag(released.clone()));
+        if close_first {
+            unsafe { ffi::lua_close(state) };
+            drop(foreign);
+        } else {
+            drop(foreign);
+            assert!(!released.load(Ordering::Relaxed));
+            unsafe { ffi::lua_close(state) };
+        }
+        assert!(released.load(Ordering::Relaxed));
+    }
+
+    // Owned state is closed on drop
+    let state = unsafe { ffi::luaL_newstate() };
+    let foreign = unsafe { ForeignLua::new(state, StateOwnership::Owned, ())? };
+    let closed = Arc::new(AtomicBool::new(false));
+    let closed2 = closed.clone();
+    foreign.on_close(move || closed2.store(true, Ordering::Relaxed));
+    assert_eq!(foreign.lock()?.load("1 + 2").eval::<i32>()?, 3);
+    drop(foreign);
+    assert!(closed.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[test]
+fn test_fork() -> Result<()> {
+    let lua = Lua::new();
+    lua.load("counter = 10; function inc() counter = counter + 1; return counter end")
+        .set_name("prelude")
+        .exec_recorded()?;
+    lua.record_setup(|lua| lua.globals().set("rust_value", 5))?;
+    // Not recorded
+    lua.load("unrecorded = true").exec()?;
+    assert_eq!(lua.load("inc()").eval::<i64>()?, 11);
+
+    let forked = lua.fork()?;
+    assert_eq!(forked.load("inc()").eval::<i64>()?, 11);
+    assert_eq!(forked.globals().get::<_, i64>("rust_value")?, 5);
+    assert_eq!(forked.globals().get::<_, Option<bool>>("unrecorded")?, None);
+    assert_eq!(lua.load("inc()").eval::<i64>()?, 12);
+
+    // Forked states can be forked again
+    let forked2 = forked.fork()?;
+    assert_eq!(forked2.load("inc()").eval::<i64>()?, 11);
+
+    // Failed setup is not recorded
+    assert!(lua.load("error('boom')").exec_recorded().is_err());
+    assert!(lua.record_setup(|_| Err(Error::runtime("boom"))).is_err());
+    lua.fork()?;
+
+    // Chunks with custom environment cannot be recorded
+    let env = lua.create_table()?;
+    assert!(lua
+        .load("x = 1")
+        .set_environment(env)
+        .exec_recorded()
+        .is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_load_from_function() -> Result<()> {
     let lua = Lua::new();
@@ -1332,3 +2167,138 @@ fn test_send() {
     .join()
     .unwrap();
 }
+
+#[test]
+fn test_lua_id() -> Result<()> {
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+    assert_ne!(lua1.id(), lua2.id());
+    assert!(lua1.id() < lua2.id());
+
+    let lua_id = lua1.create_function(|lua, ()| Ok(lua.id().as_u64()))?;
+    assert_eq!(lua_id.call::<_, u64>(())?, lua1.id().as_u64());
+
+    #[cfg(feature = "send")]
+    {
+        let (id1, id2) = (lua1.id(), lua2.id());
+        assert!(Lua::live_ids().contains(&id1));
+        let found = Lua::find(id1).unwrap();
+        assert_eq!(found.id(), id1);
+        found.post(|lua| lua.globals().set("x", 1).unwrap())?;
+        let worker =
+            std::thread::spawn(move || found.call(|lua| Ok(lua.globals().get::<_, i32>("x")? + 1)));
+        while !worker.is_finished() {
+            lua1.run_remote_calls();
+        }
+        assert_eq!(worker.join().unwrap()?, 2);
+
+        let found = Lua::find(id2).unwrap();
+        drop(lua2);
+        assert!(Lua::find(id2).is_none());
+        assert!(!Lua::live_ids().contains(&id2));
+        assert!(found.post(|_| {}).is_err());
+    }
+
+    // Values of another state are rejected with an error
+    let other = Lua::new();
+    let func = other.create_function(|_, ()| Ok(()))?;
+    match lua1.create_thread(func) {
+        Err(Error::MismatchedLuaState { expected, found }) => {
+            assert_eq!((expected, found), (lua1.id(), other.id()))
+        }
+        r => panic!("expected MismatchedLuaState, got {r:?}"),
+    }
+    let globals = other.create_table()?;
+    assert!(lua1.swap_globals(globals).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_stats() -> Result<()> {
+    let lua = Lua::new();
+
+    let base = lua.stack_stats();
+    let tables = (0..50)
+        .map(|_| lua.create_table())
+        .collect::<Result<Vec<_>>>()?;
+    let stats = lua.stack_stats();
+    assert_eq!(stats.ref_used, base.ref_used + 50);
+    assert!(stats.ref_peak >= stats.ref_used);
+    assert!(stats.ref_capacity >= stats.ref_used);
+
+    drop(tables);
+    let stats = lua.stack_stats();
+    assert_eq!(stats.ref_used, base.ref_used);
+    assert!(stats.ref_peak >= base.ref_used + 50);
+
+    // The deepest stack usage is tracked with `stack_diagnostics` only and attributed to the
+    // call site of the mlua API
+    let lua = Lua::new();
+    let nested = vec![vec![vec![1, 2], vec![3]]];
+    let line = line!() + 1;
+    lua.globals().set("nested", nested)?;
+    let stats = lua.stack_stats();
+    if cfg!(feature = "stack_diagnostics") {
+        let location = stats.peak_location.unwrap();
+        assert!(stats.peak > 0);
+        assert_eq!((location.file(), location.line()), (file!(), line));
+    } else {
+        assert_eq!((stats.peak, stats.peak_location), (0, None));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_export_loaded_modules() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        package.preload["point"] = function()
+            local Point = { defaults = { x = 0, y = 0 } }
+            Point.__index = Point
+            function Point.new(x, y)
+                return setmetatable({ x = x or Point.defaults.x, y = y or Point.defaults.y }, Point)
+            end
+            function Point:len2() return self.x * self.x + self.y * self.y end
+            return Point
+        end
+        package.preload["counter"] = function()
+            local count = 0
+            return { inc = function() count = count + 1 return count end }
+        end
+        package.preload["version"] = function() return "1.2.3" end
+        require("point")
+        require("counter")
+        require("version")
+        "#,
+    )
+    .exec()?;
+
+    let modules = lua.export_loaded_modules()?;
+    let names = modules.names().collect::<Vec<_>>();
+    assert!(names.contains(&"point"));
+    assert!(names.contains(&"version"));
+    // Modules with local state or Rust functions cannot be exported
+    assert!(!names.contains(&"counter"));
+    assert!(!names.contains(&"string"));
+
+    let lua2 = Lua::new();
+    lua2.load("package.preload['version'] = function() return 'old' end")
+        .exec()?;
+    lua2.import_loaded_modules(&modules)?;
+    lua2.load(
+        r#"
+        local Point = require("point")
+        local p = Point.new(3)
+        assert(p:len2() == 9 and getmetatable(p) == Point)
+        assert(require("version") == "1.2.3")
+        assert(package.loaded["counter"] == nil)
+        "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
@@ -1,6 +1,10 @@
+use std::os::raw::c_void;
 use std::panic::catch_unwind;
 
-use mlua::{Error, Function, Lua, Result, Thread, ThreadStatus};
+use mlua::{
+    ffi, Error, Function, LightUserData, Lua, Result, ResumeOutcome, Thread, ThreadPool,
+    ThreadStatus, Value,
+};
 
 #[test]
 fn test_thread() -> Result<()> {
@@ -106,6 +110,98 @@ fn test_thread() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_thread_resume_iter() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function(a, b)
+                for i = a, b do
+                    coroutine.yield(i, i * 2)
+                end
+                return "done"
+            end)
+        "#,
+        )
+        .eval()?;
+
+    let mut sum = 0;
+    for pair in thread.resume_iter::<_, (i64, i64)>((1, 3)) {
+        let (a, b) = pair?;
+        assert_eq!(b, a * 2);
+        sum += a;
+    }
+    assert_eq!(sum, 6);
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Errors end the iteration
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function()
+                coroutine.yield(1)
+                error("boom")
+            end)
+        "#,
+        )
+        .eval()?;
+    let mut iter = thread.resume_iter::<_, i64>(());
+    assert_eq!(iter.next().transpose()?, Some(1));
+    assert!(matches!(iter.next(), Some(Err(Error::RuntimeError(_)))));
+    assert!(iter.next().is_none());
+
+    // Thread that returns immediately
+    let thread = lua.create_thread(lua.create_function(|_, ()| Ok(1))?)?;
+    assert_eq!(thread.resume_iter::<_, i64>(()).count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_locals() -> Result<()> {
+    let lua = Lua::new();
+
+    #[derive(Debug, PartialEq)]
+    struct TraceId(u64);
+
+    let func = lua.create_function(|lua, ()| {
+        let thread = lua.current_thread();
+        let id = thread.local::<TraceId>()?.map(|id| id.0);
+        if let Some(mut count) = thread.local_mut::<u32>()? {
+            *count += 1;
+        }
+        Ok(id)
+    })?;
+
+    let thread1 = lua.create_thread(func.clone())?;
+    let thread2 = lua.create_thread(func.clone())?;
+    assert!(thread1.local::<TraceId>()?.is_none());
+    assert_eq!(thread1.set_local(TraceId(1))?, None);
+    assert_eq!(thread1.set_local(TraceId(2))?, Some(TraceId(1)));
+    thread1.set_local(0u32)?;
+
+    assert_eq!(thread1.resume::<_, Option<u64>>(())?, Some(2));
+    assert_eq!(thread2.resume::<_, Option<u64>>(())?, None);
+    assert_eq!(*thread1.local::<u32>()?.unwrap(), 1);
+    assert_eq!(func.call::<_, Option<u64>>(())?, None);
+
+    assert_eq!(thread1.remove_local::<TraceId>()?, Some(TraceId(2)));
+    assert!(thread1.local::<TraceId>()?.is_none());
+
+    // Data is dropped together with the thread
+    let marker = std::sync::Arc::new(());
+    let thread = lua.create_thread(func)?;
+    thread.set_local(marker.clone())?;
+    drop(thread);
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(std::sync::Arc::strong_count(&marker), 1);
+
+    Ok(())
+}
+
 #[test]
 #[cfg(any(feature = "lua54", feature = "luau"))]
 fn test_thread_reset() -> Result<()> {
@@ -177,6 +273,225 @@ fn test_thread_reset() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_thread_resume_outcome() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua
+        .load(
+            r#"
+            function(a)
+                local b = coroutine.yield(a + 1)
+                if b == "fail" then
+                    local function inner() error({code = 7}) end
+                    inner()
+                end
+                return b, "done"
+            end
+        "#,
+        )
+        .eval()?;
+    let thread = lua.create_thread(func.clone())?;
+    let outcome = thread.resume_outcome::<_, i64>(1)?;
+    assert!(matches!(outcome, ResumeOutcome::Yielded(2)), "{outcome:?}");
+    let outcome = thread.resume_outcome::<_, (String, String)>("ok")?;
+    assert!(
+        matches!(outcome, ResumeOutcome::Finished((ref b, ref s)) if b == "ok" && s == "done"),
+        "{outcome:?}"
+    );
+    assert!(matches!(
+        thread.resume_outcome::<_, ()>(()),
+        Err(Error::CoroutineInactive)
+    ));
+
+    // Errors are reported together with the traceback of the dead thread
+    let thread = lua.create_thread(func)?;
+    thread.resume_outcome::<_, ()>(1)?;
+    match thread.resume_outcome::<_, ()>("fail")? {
+        ResumeOutcome::Failed { error, traceback } => {
+            assert!(matches!(error, Error::RuntimeError(ref msg) if msg.starts_with("table:")));
+            let traceback = traceback.unwrap();
+            assert!(traceback.starts_with("stack traceback:"), "{traceback}");
+            assert!(traceback.contains("inner"), "{traceback}");
+        }
+        outcome => panic!("unexpected outcome: {outcome:?}"),
+    }
+    assert_eq!(thread.status(), ThreadStatus::Error);
+
+    // Errors from Rust callbacks are preserved
+    let thread = lua.create_thread(
+        lua.create_function(|_, ()| -> Result<()> { Err(Error::external("rust failure")) })?,
+    )?;
+    match thread.resume_outcome::<_, ()>(())? {
+        ResumeOutcome::Failed { error, .. } => {
+            assert!(error.to_string().contains("rust failure"), "{error}");
+        }
+        outcome => panic!("unexpected outcome: {outcome:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_create_with() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua
+        .load(
+            r#"
+            function(a, b, c)
+                local d = coroutine.yield(a + b + c)
+                return a, b, c, d
+            end
+            "#,
+        )
+        .eval()?;
+    let thread = unsafe {
+        lua.create_thread_with(|state| {
+            lua.push_value_to(state, &Value::Function(func))?;
+            ffi::lua_pushinteger(state, 1);
+            ffi::lua_pushinteger(state, 2);
+            Ok(())
+        })?
+    };
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    assert_eq!(thread.resume::<_, i64>(3)?, 6);
+    assert_eq!(thread.resume::<_, (i64, i64, i64, i64)>(4)?, (1, 2, 3, 4));
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // The thread stack must start with a function
+    let res = unsafe {
+        lua.create_thread_with(|state| {
+            ffi::lua_pushinteger(state, 1);
+            Ok(())
+        })
+    };
+    assert!(matches!(res, Err(Error::RuntimeError(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_resume_raw() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .load(
+            r#"
+            coroutine.create(function(token)
+                local n = coroutine.yield(token)
+                return n + 1
+            end)
+            "#,
+        )
+        .eval()?;
+
+    // Yielded host token is read from the thread stack
+    let token = 0x2a as *mut c_void;
+    let yielded = unsafe {
+        thread.resume_raw(LightUserData(token), |state, status, nresults| {
+            assert_eq!((status, nresults), (ffi::LUA_YIELD, 1));
+            Ok(ffi::lua_touserdata(state, -1))
+        })?
+    };
+    assert_eq!(yielded, token);
+
+    let res = unsafe {
+        thread.resume_raw(1, |state, status, nresults| {
+            assert_eq!((status, nresults), (ffi::LUA_OK, 1));
+            Ok(ffi::lua_tointeger(state, -1))
+        })?
+    };
+    assert_eq!(res, 2);
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Resuming finished thread fails
+    let res = unsafe { thread.resume_raw((), |_, _, _| Ok(())) };
+    assert!(matches!(res, Err(Error::CoroutineInactive)));
+
+    // Error object is left on top of the thread stack
+    let func: Function = lua.load(r#"function() error("boom", 0) end"#).eval()?;
+    let thread = lua.create_thread(func)?;
+    let msg = unsafe {
+        thread.resume_raw((), |state, status, _| {
+            assert_eq!(status, ffi::LUA_ERRRUN);
+            Ok(std::ffi::CStr::from_ptr(ffi::lua_tostring(state, -1)).to_owned())
+        })?
+    };
+    assert_eq!(msg.to_str().unwrap(), "boom");
+    assert_eq!(thread.status(), ThreadStatus::Error);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(any(feature = "lua54", feature = "luau"))]
+fn test_thread_close() -> Result<()> {
+    let lua = Lua::new();
+
+    let func: Function = lua.load("function() coroutine.yield(1) end").eval()?;
+    let thread = lua.create_thread(func.clone())?;
+    thread.resume::<_, ()>(())?;
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    thread.close()?;
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+
+    // Closed thread can be reused
+    thread.reset(func)?;
+    assert_eq!(thread.resume::<_, i32>(())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_pool() -> Result<()> {
+    let lua = Lua::new();
+
+    let pool = ThreadPool::new(&lua, 2);
+    let func: Function = lua
+        .load("function(n) coroutine.yield(n); return n * 2 end")
+        .eval()?;
+
+    let thread1 = pool.acquire(func.clone())?;
+    let thread1_ptr = thread1.to_pointer();
+    assert_eq!(thread1.resume::<_, i32>(1)?, 1);
+    thread1.set_local(7u32)?;
+    // Release a suspended thread
+    pool.release(thread1);
+
+    let thread2 = pool.acquire(func.clone())?;
+    #[cfg(any(feature = "lua54", feature = "luau"))]
+    assert_eq!(thread2.to_pointer(), thread1_ptr);
+    #[cfg(not(any(feature = "lua54", feature = "luau")))]
+    assert_ne!(thread2.to_pointer(), thread1_ptr);
+    // Coroutine-local data is not inherited from the previous use
+    assert!(thread2.local::<u32>()?.is_none());
+    assert_eq!(thread2.resume::<_, i32>(2)?, 2);
+    assert_eq!(thread2.resume::<_, i32>(())?, 4);
+    assert_eq!(thread2.status(), ThreadStatus::Unresumable);
+
+    // Pool capacity is respected
+    let threads = (0..3)
+        .map(|_| pool.acquire(func.clone()))
+        .collect::<Result<Vec<_>>>()?;
+    pool.release(thread2);
+    for thread in threads {
+        pool.release(thread);
+    }
+    #[cfg(any(feature = "lua54", feature = "luau"))]
+    assert_eq!(pool.len(), 2);
+    #[cfg(not(any(feature = "lua54", feature = "luau")))]
+    assert!(pool.is_empty());
+
+    // `reset_with` works on all backends
+    let thread = lua.create_thread(func.clone())?;
+    assert_eq!(thread.resume::<_, i32>(3)?, 3);
+    let thread = thread.reset_with(func)?;
+    assert_eq!(thread.resume::<_, i32>(5)?, 5);
+
+    Ok(())
+}
+
 #[test]
 fn test_coroutine_from_closure() -> Result<()> {
     let lua = Lua::new();
@@ -262,3 +577,83 @@ fn test_owned_thread() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+fn test_thread_run_for() -> Result<()> {
+    use std::time::Duration;
+
+    let lua = Lua::new();
+    let slice = Duration::from_millis(5);
+
+    let thread = lua.create_thread(
+        lua.load(
+            r#"
+            function()
+                counter = 0
+                while counter < 3 do
+                    local start = os.clock()
+                    -- Busy loop, never yields voluntarily
+                    while os.clock() - start < 0.02 do end
+                    counter = counter + 1
+                end
+                coroutine.yield("yielded")
+                return "done"
+            end
+            "#,
+        )
+        .eval()?,
+    )?;
+
+    let mut preempted = 0;
+    let result = loop {
+        match lua.run_for::<_, String>(slice, &thread, ())? {
+            Some(result) => break result,
+            None => {
+                assert_eq!(thread.status(), ThreadStatus::Resumable);
+                preempted += 1;
+            }
+        }
+    };
+    assert!(preempted > 0);
+    assert_eq!(result, "yielded");
+    assert_eq!(lua.globals().get::<_, i32>("counter")?, 3);
+    assert_eq!(
+        lua.run_for::<_, String>(slice, &thread, ())?.unwrap(),
+        "done"
+    );
+    assert!(matches!(
+        lua.run_for::<_, ()>(slice, &thread, ()),
+        Err(Error::CoroutineInactive)
+    ));
+
+    // Code running in non-yieldable contexts is preempted after returning to Lua
+    let thread = lua.create_thread(
+        lua.load(
+            r#"
+            function()
+                local t = {3, 2, 1}
+                table.sort(t, function(a, b)
+                    local start = os.clock()
+                    while os.clock() - start < 0.01 do end
+                    return a < b
+                end)
+                return table.concat(t, ",")
+            end
+            "#,
+        )
+        .eval()?,
+    )?;
+    let result = loop {
+        if let Some(result) = lua.run_for::<_, String>(slice, &thread, ())? {
+            break result;
+        }
+    };
+    assert_eq!(result, "1,2,3");
+
+    // Overflowing durations run without a deadline
+    let thread = lua.create_thread(lua.load("return 1 + 1").into_function()?)?;
+    assert_eq!(lua.run_for::<_, i32>(Duration::MAX, &thread, ())?, Some(2));
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "trace_events")]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use mlua::{Lua, Result};
+
+#[test]
+fn test_trace_events() -> Result<()> {
+    let lua = Lua::new();
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    assert!(lua.stop_trace_events().is_none());
+
+    lua.start_trace_events();
+    lua.load(
+        r#"
+        local function leaf(n)
+            local x = 0
+            for i = 1, n do x = x + i end
+            return x
+        end
+        function outer(n)
+            return leaf(n) + 1
+        end
+        outer(100)
+
+        local co = coroutine.wrap(function()
+            outer(10)
+            coroutine.yield()
+            outer(20)
+        end)
+        co()
+        co()
+    "#,
+    )
+    .exec()?;
+    let events = lua.stop_trace_events().unwrap();
+    assert!(!events.is_empty());
+
+    let json = events.to_json();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let events = value["traceEvents"].as_array().unwrap();
+
+    // Thread lanes
+    let lanes = (events.iter())
+        .filter(|ev| ev["ph"] == "M")
+        .map(|ev| ev["args"]["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lanes, vec!["main", "coroutine 1"]);
+
+    // Every entered function is exited in the same lane
+    for tid in 1..=2 {
+        let lane = (events.iter()).filter(|ev| ev["tid"] == tid && ev["ph"] != "M");
+        let mut depth = 0i64;
+        for ev in lane {
+            match ev["ph"].as_str().unwrap() {
+                "B" => depth += 1,
+                "E" => depth -= 1,
+                ph => panic!("unexpected phase {ph}"),
+            }
+            assert!(depth >= 0);
+        }
+        assert_eq!(depth, 0);
+    }
+
+    let count = |name: &str, tid: i64| {
+        (events.iter())
+            .filter(|ev| ev["ph"] == "B" && ev["name"] == name && ev["tid"] == tid)
+            .count()
+    };
+    assert_eq!(count("outer", 1), 1);
+    assert_eq!(count("outer", 2), 2);
+    #[cfg(not(feature = "luau"))]
+    assert_eq!(count("leaf", 2), 2);
+
+    // Recording is stopped
+    lua.load("outer(1)").exec()?;
+    assert!(lua.stop_trace_events().is_none());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_trace_events_keeps_hook() -> Result<()> {
+    let lua = Lua::new();
+    #[cfg(feature = "luajit")]
+    lua.load("jit.off()").exec()?;
+
+    let lines = Arc::new(AtomicU64::new(0));
+    let lines2 = lines.clone();
+    lua.set_hook(mlua::HookTriggers::EVERY_LINE, move |_, _| {
+        lines2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+
+    lua.start_trace_events();
+    lua.load("local x = 1\nlocal y = 2").exec()?;
+    let events = lua.stop_trace_events().unwrap();
+    assert!(!events.is_empty());
+    assert_eq!(lines.load(Ordering::Relaxed), 2);
+
+    lua.load("local z = 3").exec()?;
+    assert_eq!(lines.load(Ordering::Relaxed), 3);
+
+    Ok(())
+}
+
+#[cfg(feature = "luau")]
+#[test]
+fn test_trace_events_keeps_interrupt() -> Result<()> {
+    let lua = Lua::new();
+
+    let interrupts = Arc::new(AtomicU64::new(0));
+    let interrupts2 = interrupts.clone();
+    lua.set_interrupt(move |_| {
+        interrupts2.fetch_add(1, Ordering::Relaxed);
+        Ok(mlua::VmState::Continue)
+    });
+
+    lua.start_trace_events();
+    lua.load("local function f() end f()").exec()?;
+    let events = lua.stop_trace_events().unwrap();
+    assert!(!events.is_empty());
+    assert!(interrupts.load(Ordering::Relaxed) > 0);
+
+    Ok(())
+}
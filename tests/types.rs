@@ -1,6 +1,6 @@
 use std::os::raw::c_void;
 
-use mlua::{Function, LightUserData, Lua, Result};
+use mlua::{Error, Function, LightUserData, Lua, Result, TypedLightUserData};
 
 #[test]
 fn test_lightuserdata() -> Result<()> {
@@ -24,3 +24,42 @@ fn test_lightuserdata() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_typed_lightuserdata() -> Result<()> {
+    let lua = Lua::new();
+
+    let mut counter = 0u32;
+    let mut name = String::from("name");
+    let counter_ud = lua.create_light_userdata_typed(&mut counter as *mut u32);
+    let name_ud = lua.create_light_userdata_typed(&mut name as *mut String);
+
+    let id = lua.create_function(|_, v: mlua::Value| Ok(v))?;
+    let res = id.call::<_, TypedLightUserData<u32>>(counter_ud)?;
+    assert_eq!(res, counter_ud);
+    unsafe { *res.as_ptr() += 1 };
+    assert_eq!(counter, 1);
+
+    // Type mismatch
+    match id.call::<_, TypedLightUserData<u32>>(name_ud) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {r:?}"),
+    }
+
+    // Unknown pointer
+    match id.call::<_, TypedLightUserData<u32>>(LightUserData(42 as *mut c_void)) {
+        Err(Error::FromLuaConversionError { .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {r:?}"),
+    }
+
+    assert!(lua.remove_light_userdata_typed(name_ud.as_ptr()));
+    assert!(!lua.remove_light_userdata_typed(name_ud.as_ptr()));
+    assert!(id.call::<_, TypedLightUserData<String>>(name_ud).is_err());
+    // Untyped access still works
+    assert_eq!(
+        id.call::<_, LightUserData>(name_ud)?,
+        name_ud.to_light_userdata()
+    );
+
+    Ok(())
+}
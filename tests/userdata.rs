@@ -425,6 +425,105 @@ fn test_userdata_destroy() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_userdata_stats() -> Result<()> {
+    struct Small(#[allow(unused)] u8);
+    impl UserData for Small {}
+
+    struct Large(#[allow(unused)] [u64; 16]);
+    impl UserData for Large {}
+
+    let lua = Lua::new();
+    assert!(lua.userdata_stats().is_empty());
+
+    let small = (0..10)
+        .map(|i| lua.create_userdata(Small(i)))
+        .collect::<Result<Vec<_>>>()?;
+    let large = lua.create_userdata(Large([0; 16]))?;
+    lua.globals().set("large", large)?;
+
+    let stats = lua.userdata_stats();
+    assert_eq!(stats.len(), 2);
+    let large_stats = stats
+        .iter()
+        .find(|s| s.type_name.ends_with("Large"))
+        .unwrap();
+    assert_eq!((large_stats.live, large_stats.created), (1, 1));
+    assert!(large_stats.bytes >= 128);
+    let small_stats = stats
+        .iter()
+        .find(|s| s.type_name.ends_with("Small"))
+        .unwrap();
+    assert_eq!((small_stats.live, small_stats.created), (10, 10));
+
+    // Destructed instances are not counted as live
+    small[0].take::<Small>()?;
+    drop(small);
+    lua.globals().set("large", Nil)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+
+    for stats in lua.userdata_stats() {
+        assert_eq!(stats.live, 0);
+        assert_eq!(stats.bytes, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_iter_userdata() -> Result<()> {
+    struct MyUserData(i64);
+    impl UserData for MyUserData {}
+
+    let lua = Lua::new();
+    let ud0 = lua.create_userdata(MyUserData(0))?;
+    // The type must be tracked first
+    let result = lua.iter_userdata::<MyUserData, _>(|_, _| Ok(()));
+    assert!(matches!(result, Err(Error::RuntimeError(msg)) if msg.contains("is not tracked")));
+    lua.track_userdata::<MyUserData>()?;
+    lua.iter_userdata::<MyUserData, _>(|_, _| panic!("no instances expected"))?;
+
+    let ud1 = lua.create_userdata(MyUserData(1))?;
+    let ud2 = lua.create_userdata(MyUserData(2))?;
+    let ud3 = lua.create_userdata(MyUserData(3))?;
+    lua.create_any_userdata(4i64)?;
+
+    let mut values = Vec::new();
+    lua.iter_userdata::<MyUserData, _>(|ud, data| {
+        assert_eq!(ud.borrow::<MyUserData>()?.0, data.0);
+        values.push(data.0);
+        // Creating new instances during iteration is allowed
+        lua.create_userdata(MyUserData(0))?;
+        Ok(())
+    })?;
+    values.sort();
+    assert_eq!(values, vec![1, 2, 3]);
+
+    // Taken and collected instances are skipped
+    ud2.take::<MyUserData>()?;
+    drop((ud1, ud2));
+    lua.gc_collect()?;
+    let mut values = Vec::new();
+    lua.iter_userdata::<MyUserData, _>(|_, data| {
+        values.push(data.0);
+        Ok(())
+    })?;
+    assert_eq!(values, vec![3]);
+
+    // Mutably borrowed instances are skipped
+    let guard = ud3.borrow_mut::<MyUserData>()?;
+    lua.iter_userdata::<MyUserData, _>(|_, _| panic!("no instances expected"))?;
+    drop(guard);
+
+    // Errors are propagated
+    let result = lua.iter_userdata::<MyUserData, _>(|_, _| Err(Error::runtime("stop")));
+    assert!(matches!(result, Err(Error::RuntimeError(msg)) if msg == "stop"));
+    drop((ud0, ud3));
+
+    Ok(())
+}
+
 #[test]
 fn test_user_values() -> Result<()> {
     struct MyUserData;
@@ -967,6 +1066,336 @@ fn test_userdata_pointer() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_userdata_callable() -> Result<()> {
+    let lua = Lua::new();
+
+    struct Command {
+        name: StdString,
+        calls: usize,
+    }
+
+    impl UserData for Command {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("describe", |_, this, ()| {
+                Ok(format!("{} (called {} times)", this.name, this.calls))
+            });
+            methods.add_method_mut("record", |_, this, ()| {
+                this.calls += 1;
+                Ok(())
+            });
+        }
+    }
+
+    // Every instance has its own handler
+    let add = lua.create_callable(
+        Command {
+            name: "add".into(),
+            calls: 0,
+        },
+        |_, this, (a, b): (i64, i64)| Ok((this.name.clone(), a + b)),
+    )?;
+    let neg = lua.create_callable(
+        Command {
+            name: "neg".into(),
+            calls: 0,
+        },
+        |_, _, a: i64| Ok(-a),
+    )?;
+    lua.globals().set("add", add)?;
+    lua.globals().set("neg", neg)?;
+    lua.load(
+        r#"
+        local name, sum = add(1, 2)
+        assert(name == "add" and sum == 3)
+        assert(neg(5) == -5)
+        neg:record()
+        assert(neg:describe() == "neg (called 1 times)")
+        assert(add:describe() == "add (called 0 times)")
+    "#,
+    )
+    .exec()?;
+
+    // Regular userdata of the same type are not callable
+    let plain = lua.create_userdata(Command {
+        name: "plain".into(),
+        calls: 0,
+    })?;
+    assert!(plain.call::<_, ()>(()).is_err());
+
+    // Registry sugar
+    struct Counter(i64);
+    lua.register_userdata_type::<Counter>(|reg| {
+        reg.add_call(|_, this, n: i64| Ok(this.0 + n));
+    })?;
+    let counter = lua.create_any_userdata(Counter(10))?;
+    assert_eq!(counter.call::<_, i64>(5)?, 15);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_dynamic_index() -> Result<()> {
+    let lua = Lua::new();
+
+    struct Components {
+        name: StdString,
+        values: HashMap<StdString, f64>,
+    }
+    lua.register_userdata_type::<Components>(|reg| {
+        reg.add_field_method_get("name", |_, this| Ok(this.name.clone()));
+        reg.add_method("count", |_, this, ()| Ok(this.values.len()));
+        reg.set_dynamic_index(|_, this, key: StdString| Ok(this.values.get(&key).copied()));
+        reg.set_dynamic_newindex(|_, this, key: StdString, value: Option<f64>| {
+            match value {
+                Some(value) => this.values.insert(key, value),
+                None => this.values.remove(&key),
+            };
+            Ok(())
+        });
+    })?;
+
+    let components = Components {
+        name: "player".into(),
+        values: HashMap::from([("health".into(), 100.0)]),
+    };
+    lua.globals()
+        .set("c", lua.create_any_userdata(components)?)?;
+    lua.load(
+        r#"
+        assert(c.name == "player")
+        assert(c.health == 100 and c.speed == nil)
+        c.speed = 2.5
+        c.health = nil
+        assert(c.speed == 2.5 and c.health == nil)
+        assert(c:count() == 1)
+    "#,
+    )
+    .exec()?;
+
+    // Conversion errors of the key or value are reported
+    assert!(lua.load("c.speed = 'fast'").exec().is_err());
+    assert!(lua.load("return c[true]").exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_deprecated_methods() -> Result<()> {
+    use mlua::DeprecationPolicy;
+
+    let lua = Lua::new();
+
+    struct Player(i64);
+    lua.register_userdata_type::<Player>(|reg| {
+        reg.add_method("health", |_, this, ()| Ok(this.0));
+        reg.add_method("get_health", |_, this, ()| Ok(this.0));
+        reg.add_method("hp", |_, this, ()| Ok(this.0));
+        reg.add_method("kill", |_, _, ()| Ok(()));
+        reg.add_meta_method("__len", |_, this, ()| Ok(this.0));
+        reg.deprecate_method("get_health", "use `health`", DeprecationPolicy::WarnOnce);
+        reg.deprecate_method("hp", "use `health`", DeprecationPolicy::Log);
+        reg.deprecate_method("kill", "removed", DeprecationPolicy::Error);
+        reg.deprecate_method("__len", "use `health`", DeprecationPolicy::Log);
+        reg.deprecate_method("missing", "no effect", DeprecationPolicy::Error);
+    })?;
+    lua.globals()
+        .set("player", lua.create_any_userdata(Player(100))?)?;
+
+    // Without a handler, deprecated methods are called silently
+    lua.load("assert(player:hp() == 100)").exec()?;
+
+    let notices = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let notices2 = notices.clone();
+    lua.set_deprecation_handler(move |_, deprecation| {
+        let name = deprecation.name.clone();
+        (notices2.lock().unwrap()).push((name, deprecation.policy, deprecation.calls));
+        Ok(())
+    });
+
+    lua.load(
+        r#"
+        for _ = 1, 3 do
+            assert(player:get_health() == 100)
+            assert(player:hp() == 100)
+        end
+        assert(#player == 100)
+        assert(player:health() == 100)
+    "#,
+    )
+    .exec()?;
+    assert_eq!(
+        *notices.lock().unwrap(),
+        vec![
+            ("Player.get_health".into(), DeprecationPolicy::WarnOnce, 1),
+            ("Player.hp".into(), DeprecationPolicy::Log, 2),
+            ("Player.hp".into(), DeprecationPolicy::Log, 3),
+            ("Player.hp".into(), DeprecationPolicy::Log, 4),
+            ("Player.__len".into(), DeprecationPolicy::Log, 1),
+        ]
+    );
+
+    // Error policy
+    notices.lock().unwrap().clear();
+    match lua.load("player:kill()").exec() {
+        Err(err) => assert!(err.to_string().contains("deprecated: removed")),
+        Ok(_) => panic!("expected error"),
+    }
+    assert_eq!(
+        *notices.lock().unwrap(),
+        vec![("Player.kill".into(), DeprecationPolicy::Error, 1)]
+    );
+
+    // Handler errors are propagated
+    lua.set_deprecation_handler(|_, deprecation| {
+        Err(Error::runtime(format!("{} not allowed", deprecation.name)))
+    });
+    match lua.load("player:hp()").exec() {
+        Err(err) => assert!(err.to_string().contains("Player.hp not allowed")),
+        Ok(_) => panic!("expected error"),
+    }
+
+    lua.remove_deprecation_handler();
+    lua.load("assert(player:hp() == 100)").exec()?;
+
+    Ok(())
+}
+
+#[cfg(feature = "send")]
+#[test]
+fn test_userdata_try_borrow_for() -> Result<()> {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    let lua = Lua::new();
+
+    // Values owned by Lua are never waited for
+    let ud = lua.create_any_userdata(1i64)?;
+    {
+        let _borrow = ud.borrow_mut::<i64>()?;
+        let result = ud.try_borrow_for::<i64>(Duration::from_secs(10));
+        assert!(matches!(result, Err(Error::WouldBlock)));
+    }
+    *ud.try_borrow_mut_for::<i64>(Duration::ZERO)? += 1;
+    assert_eq!(*ud.try_borrow_for::<i64>(Duration::ZERO)?, 2);
+    assert!(matches!(
+        ud.try_borrow_for::<i32>(Duration::ZERO),
+        Err(Error::UserDataTypeMismatch)
+    ));
+
+    // Values locked by another thread
+    let shared = Arc::new(Mutex::new(StdString::from("hello")));
+    let ud = lua.create_any_userdata(shared.clone())?;
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || {
+        #[cfg(not(feature = "parking_lot"))]
+        let mut guard = shared.lock().unwrap();
+        #[cfg(feature = "parking_lot")]
+        let mut guard = shared.lock();
+        locked_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        guard.push_str(", world");
+    });
+    locked_rx.recv().unwrap();
+    let result = ud.try_borrow_mut_for::<StdString>(Duration::from_millis(10));
+    assert!(matches!(result, Err(Error::WouldBlock)));
+    release_tx.send(()).unwrap();
+    {
+        // Overflowing timeouts do not panic
+        let mut value = ud.try_borrow_mut_for::<StdString>(Duration::MAX)?;
+        assert_eq!(*value, "hello, world");
+        value.push('!');
+        // The userdata stays borrowed while the lock is held
+        assert!(ud.take::<Arc<Mutex<StdString>>>().is_err());
+    }
+    handle.join().unwrap();
+    assert_eq!(
+        *ud.try_borrow_for::<StdString>(Duration::ZERO)?,
+        "hello, world!"
+    );
+
+    // Readers of `RwLock` don't block each other
+    let ud = lua.create_any_userdata(Arc::new(RwLock::new(3i64)))?;
+    let read1 = ud.try_borrow_for::<i64>(Duration::ZERO)?;
+    let read2 = ud.try_borrow_for::<i64>(Duration::ZERO)?;
+    assert_eq!(*read1 + *read2, 6);
+    let result = ud.try_borrow_mut_for::<i64>(Duration::from_millis(1));
+    assert!(matches!(result, Err(Error::WouldBlock)));
+    drop((read1, read2));
+    *ud.try_borrow_mut_for::<i64>(Duration::ZERO)? = 4;
+    assert_eq!(*ud.try_borrow_for::<i64>(Duration::ZERO)?, 4);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "send"))]
+#[test]
+fn test_userdata_registrations() -> Result<()> {
+    use mlua::RegistrationSet;
+    use std::cell::Cell;
+
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+        }
+    }
+
+    struct Point(i64, i64);
+
+    // Registration function runs once, and callbacks are shared by all states
+    let registered = Rc::new(Cell::new(0));
+    let calls = Rc::new(Cell::new(0));
+    let registrations = RegistrationSet::new().add_with::<Point>(|registry| {
+        registered.set(registered.get() + 1);
+        let calls = calls.clone();
+        registry.add_method("sum", move |_, this, ()| {
+            calls.set(calls.get() + 1);
+            Ok(this.0 + this.1)
+        });
+    });
+    assert_eq!(registrations.len(), 1);
+
+    let lua = Lua::new();
+    lua.import_registrations(&registrations);
+    lua.globals()
+        .set("p", lua.create_any_userdata(Point(1, 2))?)?;
+    lua.globals()
+        .set("p2", lua.create_any_userdata(Point(3, 4))?)?;
+    assert_eq!(lua.load("p:sum() + p2:sum()").eval::<i64>()?, 10);
+
+    // Used `UserData` types and imported types are exported
+    lua.globals().set("c", Counter(7))?;
+    let exported = lua.export_registrations();
+    assert_eq!(exported.len(), 2);
+
+    let lua2 = Lua::new();
+    lua2.import_registrations(&exported);
+    lua2.globals()
+        .set("p", lua2.create_any_userdata(Point(5, 6))?)?;
+    lua2.globals().set("c", Counter(8))?;
+    assert_eq!(lua2.load("p:sum() + c:get()").eval::<i64>()?, 19);
+    assert_eq!(registered.get(), 1);
+    assert_eq!(calls.get(), 3);
+
+    // Types registered before importing are left untouched
+    let lua3 = Lua::new();
+    lua3.register_userdata_type::<Point>(|registry| {
+        registry.add_method("sum", |_, this, ()| Ok(this.0 - this.1));
+    })?;
+    lua3.import_registrations(&registrations);
+    lua3.globals()
+        .set("p", lua3.create_any_userdata(Point(5, 6))?)?;
+    assert_eq!(lua3.load("p:sum()").eval::<i64>()?, -1);
+    assert!(lua3.export_registrations().is_empty());
+    assert_eq!(calls.get(), 3);
+
+    Ok(())
+}
+
 #[cfg(all(feature = "unstable", not(feature = "send")))]
 #[test]
 fn test_owned_userdata() -> Result<()> {
@@ -1021,3 +1450,114 @@ fn test_userdata_derive() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_registered_types() -> Result<()> {
+    let lua = Lua::new();
+
+    struct Player;
+
+    impl UserData for Player {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field("kind", "player");
+            fields.add_field_method_get("hp", |_, _| Ok(100));
+            fields.add_field_method_set("hp", |_, _, _: i64| Ok(()));
+        }
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("move_to", |_, _, (_x, _y): (f64, f64)| Ok(()));
+            methods.add_method("say", |_, _, _: Variadic<StdString>| Ok(()));
+            methods.add_function("spawn", |_, ()| Ok(Player));
+            methods.add_meta_method(MetaMethod::ToString, |_, _, ()| Ok("player"));
+        }
+    }
+
+    struct Named;
+
+    impl UserData for Named {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_meta_field(MetaMethod::Type, "CustomName");
+        }
+    }
+
+    assert!(lua.registered_types().is_empty());
+    lua.create_userdata(Player)?;
+    lua.create_userdata(Named)?;
+
+    let types = lua.registered_types();
+    assert_eq!(types.len(), 2);
+    assert_eq!(types[0].name, "CustomName");
+    let player = &types[1];
+    assert_eq!(player.name, "Player");
+    assert_eq!(player.fields, ["kind", "hp"]);
+    let methods = (player.methods.iter())
+        .map(|m| (m.name.as_deref().unwrap(), m.nargs))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        methods,
+        [("move_to", Some(2)), ("say", None), ("spawn", Some(0))]
+    );
+    assert_eq!(player.meta_methods[0].name.as_deref(), Some("__tostring"));
+    assert_eq!(player.meta_methods[0].nargs, Some(0));
+    assert!(!player.meta_methods[0].is_async);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_keep_alive() -> Result<()> {
+    type Drops = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+    struct Resource(&'static str, Drops);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    impl UserData for Resource {}
+
+    let lua = Lua::new();
+    let drops = Drops::default();
+
+    // The connection is created last, so Lua would finalize it first
+    let stmt = lua.create_userdata(Resource("stmt", drops.clone()))?;
+    let conn = lua.create_userdata(Resource("conn", drops.clone()))?;
+    stmt.keep_alive_with(&conn)?;
+    stmt.keep_alive_with(&conn)?;
+    assert!(stmt.keep_alive_with(&stmt).is_err());
+    #[cfg(not(feature = "luau"))]
+    assert!(conn.keep_alive_with(&stmt).is_err());
+
+    lua.globals().set("stmt", stmt)?;
+    drop(conn);
+    for _ in 0..3 {
+        lua.gc_collect()?;
+    }
+    assert!(drops.lock().unwrap().is_empty());
+
+    lua.globals().set("stmt", Nil)?;
+    for _ in 0..3 {
+        lua.gc_collect()?;
+    }
+    #[cfg(not(feature = "luau"))]
+    assert_eq!(*drops.lock().unwrap(), ["stmt", "conn"]);
+    #[cfg(feature = "luau")]
+    assert_eq!(drops.lock().unwrap().len(), 2);
+
+    // Taking the value out releases the dependencies
+    drops.lock().unwrap().clear();
+    let stmt = lua.create_userdata(Resource("stmt", drops.clone()))?;
+    let conn = lua.create_userdata(Resource("conn", drops.clone()))?;
+    stmt.keep_alive_with(&conn)?;
+    drop(conn);
+    let value = stmt.take::<Resource>()?;
+    for _ in 0..3 {
+        lua.gc_collect()?;
+    }
+    assert_eq!(*drops.lock().unwrap(), ["conn"]);
+    drop(value);
+
+    Ok(())
+}
@@ -3,7 +3,10 @@ use std::os::raw::c_void;
 use std::ptr;
 use std::string::String as StdString;
 
-use mlua::{Error, LightUserData, Lua, MultiValue, Result, UserData, UserDataMethods, Value};
+use mlua::{
+    ArithOp, CompareOp, Error, Function, LightUserData, Lua, MultiValue, Result, Table, UserData,
+    UserDataMethods, Value,
+};
 
 #[test]
 fn test_value_eq() -> Result<()> {
@@ -70,6 +73,97 @@ fn test_value_eq() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_value_identity() -> Result<()> {
+    let lua = Lua::new();
+
+    let (t1, t2, f, s1, s2) = lua
+        .load(
+            r#"
+            local t = {}
+            local function f() end
+            return t, {}, f, "hello", "hel" .. "lo"
+        "#,
+        )
+        .eval::<(Value, Value, Value, Value, Value)>()?;
+
+    assert_eq!(t1.identity(), t1.clone().identity());
+    assert_ne!(t1.identity(), t2.identity());
+    assert_eq!(s1.identity(), s2.identity());
+    assert_ne!(s1.identity(), Value::Nil.identity());
+    assert_eq!(Value::Integer(1).identity(), Value::Number(1.0).identity());
+    assert_ne!(Value::Integer(1).identity(), Value::Number(1.5).identity());
+    assert_eq!(Value::NULL.identity(), Value::NULL.identity());
+
+    let mut visited = HashMap::new();
+    for v in [&t1, &t2, &f, &s1, &s2, &t1] {
+        *visited.entry(v.identity()).or_insert(0) += 1;
+    }
+    assert_eq!(visited.len(), 4);
+    assert_eq!(visited[&t1.identity()], 2);
+    assert_eq!(visited[&s1.identity()], 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_arith_compare() -> Result<()> {
+    let lua = Lua::new();
+
+    let (a, b, s) = lua
+        .load(
+            r#"
+            local mt = {}
+            mt.__add = function(a, b) return "add" end
+            mt.__unm = function(a) return "unm" end
+            mt.__lt = function(a, b) return true end
+            mt.__le = function(a, b) return false end
+            mt.__eq = function(a, b) return true end
+            return setmetatable({}, mt), setmetatable({}, mt), "10"
+        "#,
+        )
+        .eval::<(Value, Value, Value)>()?;
+
+    let (two, three) = (Value::Integer(2), Value::Number(3.0));
+    assert_eq!(two.arith(&lua, ArithOp::Add, &three)?, Value::Integer(5));
+    assert_eq!(two.arith(&lua, ArithOp::Sub, &three)?, Value::Integer(-1));
+    assert_eq!(two.arith(&lua, ArithOp::Mul, &three)?, Value::Integer(6));
+    assert_eq!(three.arith(&lua, ArithOp::Div, &two)?, Value::Number(1.5));
+    assert_eq!(three.arith(&lua, ArithOp::Mod, &two)?, Value::Integer(1));
+    assert_eq!(two.arith(&lua, ArithOp::Pow, &three)?, Value::Integer(8));
+    assert_eq!(
+        two.arith(&lua, ArithOp::Unm, Value::Nil)?,
+        Value::Integer(-2)
+    );
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+    assert_eq!(three.arith(&lua, ArithOp::IDiv, &two)?, Value::Integer(1));
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    {
+        assert_eq!(two.arith(&lua, ArithOp::Shl, &three)?, Value::Integer(16));
+        assert_eq!(
+            two.arith(&lua, ArithOp::BNot, Value::Nil)?,
+            Value::Integer(!2)
+        );
+    }
+
+    // String coercion and metamethods
+    assert_eq!(s.arith(&lua, ArithOp::Add, &two)?, Value::Integer(12));
+    assert_eq!(a.arith(&lua, ArithOp::Add, &two)?.to_string()?, "add");
+    assert_eq!(two.arith(&lua, ArithOp::Add, &a)?.to_string()?, "add");
+    assert_eq!(a.arith(&lua, ArithOp::Unm, Value::Nil)?.to_string()?, "unm");
+    assert!(Value::Nil.arith(&lua, ArithOp::Add, &two).is_err());
+
+    assert!(two.compare(&lua, CompareOp::Lt, &three)?);
+    assert!(two.compare(&lua, CompareOp::Le, &two)?);
+    assert!(!three.compare(&lua, CompareOp::Eq, &two)?);
+    assert!(a.compare(&lua, CompareOp::Lt, &b)?);
+    assert!(!a.compare(&lua, CompareOp::Le, &b)?);
+    assert!(a.compare(&lua, CompareOp::Eq, &b)?);
+    assert!(two.compare(&lua, CompareOp::Lt, &s).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_multi_value() {
     let mut multi_value = MultiValue::new();
@@ -146,6 +240,63 @@ fn test_value_to_string() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_display_format() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load("setmetatable({}, {__tostring = function() return 'test table' end})")
+        .eval()?;
+    assert_eq!(format!("{table}"), "test table");
+    #[cfg(not(feature = "luau"))]
+    {
+        let table: Table = lua.load("setmetatable({}, {__name = 'MyTable'})").eval()?;
+        assert!(format!("{table}").starts_with("MyTable: "));
+    }
+    let table: Table = lua
+        .load("setmetatable({}, {__tostring = function() error('boom') end})")
+        .eval()?;
+    assert!(format!("{table}").starts_with("table: "));
+
+    let func: Function = lua.load("function() end").eval()?;
+    assert!(format!("{func}").starts_with("function: "));
+
+    struct MyUserData;
+    impl UserData for MyUserData {}
+    let ud = lua.create_userdata(MyUserData)?;
+    assert!(format!("{ud}").starts_with("MyUserData: "));
+
+    // Expanded tables
+    let value: Value = lua
+        .load(
+            r#"
+            local t = {"a", "b", [4] = 4, x = {y = {z = true}}, ["key with spaces"] = 1.5}
+            t.self = t
+            t.obj = setmetatable({}, {__tostring = function() return "obj" end})
+            return t
+        "#,
+        )
+        .eval()?;
+    assert!(value.display().to_string().starts_with("table: "));
+    let s = value.display().depth(2).to_string();
+    assert!(s.starts_with(r#"{"a", "b", [4] = 4, ["key with spaces"] = 1.5, "#));
+    assert!(s.contains("obj = obj"));
+    assert!(s.contains("self = table: "));
+    assert!(s.contains("x = {y = table: "));
+    let s = value.display().depth(3).to_string();
+    assert!(s.contains("x = {y = {z = true}}"));
+    assert_eq!(value.display().depth(1).width(0).to_string(), "{...}");
+    assert_eq!(
+        Value::String(lua.create_string("hello")?)
+            .display()
+            .depth(1)
+            .to_string(),
+        "hello"
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_debug_format() -> Result<()> {
     let lua = Lua::new();